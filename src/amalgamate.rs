@@ -0,0 +1,263 @@
+use anyhow::{Context, Result};
+use colorize::AnsiColor;
+use std::{
+    collections::BTreeSet,
+    fs,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use crate::{backend::GENERATED_MARKER, cmake, doctest, executor::executor, glob, layout, sources};
+
+/// Directory the single header is written to
+pub const DIST_DIR: &str = "dist";
+
+/// Headers merged into one, each inlined where it's first included
+struct Amalgamation {
+    root: PathBuf,
+    include_dir: PathBuf,
+    visited: BTreeSet<PathBuf>,
+    /// The license banner of the first header, left out of the headers
+    /// repeating it
+    banner: Option<String>,
+    body: String,
+}
+
+/// Merges the headers of a header-only library into a single header in
+/// `dist/`, starting from the umbrella header named after the project or
+/// else from every public header. Project headers are inlined once where
+/// they're first included, other includes are kept.
+pub fn handle_amalgamate(
+    header: Option<String>,
+    output: Option<String>,
+    build_dir: String,
+) -> Result<()> {
+    let name = cmake::project_name()?;
+    let c = cmake::is_c_project()?;
+    let extension = if c { "h" } else { "hpp" };
+    let include_dir = layout::include_dir()?;
+    let src_dir = layout::src_dir()?;
+
+    let compiled = sources::project_sources()?
+        .into_iter()
+        .filter(|file| file.starts_with(&src_dir) && sources::is_source(file))
+        .filter(|file| file.file_stem().is_some_and(|stem| stem != "main"))
+        .collect::<Vec<_>>();
+    if let Some(source) = compiled.first() {
+        anyhow::bail!(
+            "Only header-only libraries can be amalgamated, {} is compiled",
+            source.display()
+        );
+    }
+
+    let entries = match header {
+        Some(header) => vec![PathBuf::from(header)],
+        None => {
+            let umbrella = [
+                Path::new(&include_dir)
+                    .join(&name)
+                    .join(format!("{}.{}", name, extension)),
+                Path::new(&include_dir).join(format!("{}.{}", name, extension)),
+            ]
+            .into_iter()
+            .find(|path| path.is_file());
+            match umbrella {
+                Some(umbrella) => vec![umbrella],
+                None => glob::expand(&format!("{}/**/*", include_dir))?
+                    .into_iter()
+                    .filter(|file| sources::is_header(file))
+                    .collect(),
+            }
+        }
+    };
+    if entries.is_empty() {
+        anyhow::bail!("No public headers found in '{}'", include_dir);
+    }
+
+    let root = fs::canonicalize(".").context("Failed to read current directory")?;
+    let mut amalgamation = Amalgamation {
+        include_dir: root.join(&include_dir),
+        root,
+        visited: BTreeSet::new(),
+        banner: None,
+        body: String::new(),
+    };
+    for entry in &entries {
+        amalgamation.inline(entry)?;
+    }
+
+    let mut text = amalgamation
+        .banner
+        .as_ref()
+        .map(|banner| format!("{}\n\n", banner))
+        .unwrap_or_default();
+    text.push_str(&format!(
+        "// {} single header\n// {} amalgamate from {} headers, do not edit.\n#pragma once\n\n{}",
+        name,
+        GENERATED_MARKER,
+        amalgamation.visited.len(),
+        amalgamation.body
+    ));
+
+    let output = output
+        .map(PathBuf::from)
+        .unwrap_or_else(|| Path::new(DIST_DIR).join(format!("{}.{}", name, extension)));
+    if let Some(dir) = output.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        fs::create_dir_all(dir).with_context(|| format!("Failed to create '{}'", dir.display()))?;
+    }
+    fs::write(&output, text).with_context(|| format!("Failed to write {}", output.display()))?;
+
+    check_self_contained(&output, &build_dir, c)?;
+
+    eprintln!(
+        "{}",
+        format!(
+            "Amalgamated {} {} into {}",
+            amalgamation.visited.len(),
+            if amalgamation.visited.len() == 1 {
+                "header"
+            } else {
+                "headers"
+            },
+            output.display()
+        )
+        .green()
+    );
+
+    Ok(())
+}
+
+impl Amalgamation {
+    fn inline(&mut self, header: &Path) -> Result<()> {
+        let path = fs::canonicalize(header)
+            .with_context(|| format!("Failed to read '{}'", header.display()))?;
+        if !self.visited.insert(path.clone()) {
+            return Ok(());
+        }
+
+        let text = fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read '{}'", header.display()))?;
+        let (banner, body) = split_banner(&text);
+        let relative = path.strip_prefix(&self.root).unwrap_or(&path).to_path_buf();
+
+        self.body
+            .push_str(&format!("// begin {}\n", relative.display()));
+        match &self.banner {
+            None if !banner.is_empty() => self.banner = Some(banner),
+            Some(first) if *first == banner => {}
+            _ if !banner.is_empty() => self.body.push_str(&format!("{}\n", banner)),
+            _ => {}
+        }
+        for line in body.lines() {
+            if line.trim() == "#pragma once" {
+                continue;
+            }
+            if let Some(included) = self.project_include(&path, line) {
+                self.inline(&included)?;
+                continue;
+            }
+            self.body.push_str(line);
+            self.body.push('\n');
+        }
+        self.body
+            .push_str(&format!("// end {}\n", relative.display()));
+
+        Ok(())
+    }
+
+    /// The project header an `#include` line refers to, next to the header
+    /// including it for quoted includes, or else in the include directory
+    fn project_include(&self, header: &Path, line: &str) -> Option<PathBuf> {
+        let include = line
+            .trim_start()
+            .strip_prefix('#')?
+            .trim_start()
+            .strip_prefix("include")?
+            .trim();
+        let (quoted, spelling) = match include.strip_prefix('"') {
+            Some(rest) => (true, rest.split('"').next()?),
+            None => (false, include.strip_prefix('<')?.split('>').next()?),
+        };
+
+        let mut candidates = Vec::new();
+        if quoted {
+            candidates.extend(header.parent().map(|dir| dir.join(spelling)));
+        }
+        candidates.push(self.include_dir.join(spelling));
+
+        candidates
+            .into_iter()
+            .filter_map(|candidate| fs::canonicalize(candidate).ok())
+            .find(|candidate| candidate.starts_with(&self.root) && candidate.is_file())
+    }
+}
+
+/// Splits a header into its leading comments, e.g. a license banner, and
+/// the rest
+fn split_banner(text: &str) -> (String, String) {
+    let lines = text.lines().collect::<Vec<_>>();
+    let mut in_block = false;
+    let code_start = lines
+        .iter()
+        .position(|line| {
+            let line = line.trim();
+            if in_block {
+                in_block = !line.contains("*/");
+                return false;
+            }
+            if line.starts_with("/*") {
+                in_block = !line.contains("*/");
+                return false;
+            }
+            !(line.is_empty() || line.starts_with("//"))
+        })
+        .unwrap_or(lines.len());
+
+    (
+        lines[..code_start].join("\n").trim().to_string(),
+        lines[code_start..].join("\n"),
+    )
+}
+
+/// Compiles a source including only the single header, without the
+/// project's include directories, so it doesn't rely on the other headers
+fn check_self_contained(header: &Path, build_dir: &str, c: bool) -> Result<()> {
+    let header = fs::canonicalize(header)
+        .with_context(|| format!("Failed to read '{}'", header.display()))?;
+    let unit = std::env::temp_dir().join(format!(
+        "cxx-amalgamate-{}.{}",
+        std::process::id(),
+        if c { "c" } else { "cpp" }
+    ));
+    fs::write(&unit, format!("#include \"{}\"\n", header.display()))
+        .context("Failed to write amalgamation check source")?;
+
+    let compiler = doctest::compiler(Path::new(build_dir), c);
+    let result = executor().capture(
+        Command::new(&compiler)
+            .arg(format!("-std={}", doctest::language_standard(c)))
+            .arg("-fsyntax-only")
+            .arg(&unit),
+    );
+    let _ = fs::remove_file(&unit);
+
+    match result {
+        Ok(output) if output.status.success() => Ok(()),
+        Ok(output) => anyhow::bail!(
+            "{} isn't self-contained, it doesn't compile on its own:\n{}",
+            header.display(),
+            String::from_utf8_lossy(&output.stderr).trim_end()
+        ),
+        Err(error) => {
+            eprintln!(
+                "{}",
+                format!(
+                    "Couldn't check that the single header compiles with {}: {:#}",
+                    compiler, error
+                )
+                .yellow()
+            );
+            Ok(())
+        }
+    }
+}
@@ -0,0 +1,354 @@
+use anyhow::{Context, Result};
+use colorize::AnsiColor;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use crate::{
+    amalgamate::DIST_DIR,
+    audit,
+    cmake::{self, CMAKE_LISTS, FRAGMENT_DIR},
+    executor::executor,
+    glob,
+    manifest::STATE_DIR,
+    sign,
+};
+
+/// Directory of the vendored dependencies inside the archive
+const VENDOR_DIR: &str = "vendor";
+
+/// Lists the vendored dependencies with the commit each was fetched at
+const LOCK_FILE: &str = "deps.lock";
+
+/// Initial cache script pointing FetchContent at the vendored sources
+const VENDOR_CACHE: &str = "vendor.cmake";
+
+/// A dependency fetched into the build directory, to be vendored
+struct Fetched {
+    name: String,
+    repository: String,
+    tag: String,
+    commit: Option<String>,
+    source: PathBuf,
+}
+
+/// Archives the project's sources into a zip under a directory named after
+/// the project, leaving out what git ignores, git's own files, cxx's state
+/// and build directories. With `vendor`, the dependencies FetchContent
+/// fetched into the build directory are included with a lock of their
+/// commits, so the snapshot builds offline. With `sign`, the archive is
+/// signed with cosign.
+pub fn handle_export_zip(
+    output: Option<String>,
+    vendor: bool,
+    sign: bool,
+    build_dir: String,
+) -> Result<()> {
+    let name = cmake::project_name()?;
+    let output = output
+        .map(PathBuf::from)
+        .unwrap_or_else(|| Path::new(DIST_DIR).join(format!("{}.zip", name)));
+
+    let fetched = match vendor {
+        true => fetched_dependencies(&build_dir)?,
+        false => Vec::new(),
+    };
+    if sign {
+        sign::require_cosign()?;
+    }
+    let files = source_files(&build_dir, &output)?;
+    if files.is_empty() {
+        anyhow::bail!("No source files found to archive");
+    }
+
+    let stage = std::env::temp_dir().join(format!("cxx-export-{}", std::process::id()));
+    let result = stage_and_archive(&stage, &name, &files, &fetched, &output);
+    let _ = fs::remove_dir_all(&stage);
+    result?;
+    let bundle = sign.then(|| sign::sign(&output)).transpose()?;
+
+    eprintln!(
+        "{}",
+        format!(
+            "Exported {} {}{} to {}",
+            files.len(),
+            if files.len() == 1 { "file" } else { "files" },
+            match fetched.len() {
+                0 => String::new(),
+                1 => " and 1 vendored dependency".to_string(),
+                count => format!(" and {} vendored dependencies", count),
+            },
+            output.display()
+        )
+        .green()
+    );
+    if let Some(bundle) = bundle {
+        eprintln!("{}", format!("Signed it into {}", bundle.display()).green());
+    }
+
+    Ok(())
+}
+
+fn stage_and_archive(
+    stage: &Path,
+    name: &str,
+    files: &[PathBuf],
+    fetched: &[Fetched],
+    output: &Path,
+) -> Result<()> {
+    let root = stage.join(name);
+    for file in files {
+        copy_file(file, &root.join(file))?;
+    }
+    if !fetched.is_empty() {
+        vendor(&root.join(VENDOR_DIR), fetched)?;
+    }
+
+    if let Some(dir) = output.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        fs::create_dir_all(dir).with_context(|| format!("Failed to create '{}'", dir.display()))?;
+    }
+    let _ = fs::remove_file(output);
+    let archive = std::env::current_dir()
+        .context("Failed to get current directory")?
+        .join(output);
+    // bsdtar, the tar of Windows and macOS, writes zips too
+    let mut command = match has_tool("zip") {
+        true => {
+            let mut command = Command::new("zip");
+            command
+                .current_dir(stage)
+                .arg("-qrX")
+                .arg(&archive)
+                .arg(name);
+            command
+        }
+        false => {
+            let mut command = Command::new("tar");
+            command
+                .arg("-a")
+                .arg("-cf")
+                .arg(&archive)
+                .arg("-C")
+                .arg(stage)
+                .arg(name);
+            command
+        }
+    };
+
+    executor()
+        .execute(&mut command)
+        .context("Failed to create the archive")
+}
+
+/// The files to archive, relative to the project root. In a git repository
+/// these are the tracked files and the untracked ones it doesn't ignore.
+fn source_files(build_dir: &str, output: &Path) -> Result<Vec<PathBuf>> {
+    let excluded = [STATE_DIR, DIST_DIR, build_dir.trim_start_matches("./")];
+    let files = match Path::new(".git").exists() {
+        true => git_files()?,
+        false => walk(Path::new("."), &gitignore_patterns())?,
+    };
+
+    let mut files = files
+        .into_iter()
+        .filter(|file| file.is_file() && file != output)
+        .filter(|file| !excluded.iter().any(|dir| file.starts_with(dir)))
+        .collect::<Vec<_>>();
+    files.sort();
+    files.dedup();
+
+    Ok(files)
+}
+
+fn git_files() -> Result<Vec<PathBuf>> {
+    let output = executor()
+        .capture(Command::new("git").args([
+            "ls-files",
+            "-z",
+            "--cached",
+            "--others",
+            "--exclude-standard",
+        ]))
+        .context("Failed to list the files git tracks")?;
+    if !output.status.success() {
+        anyhow::bail!(
+            "Failed to list the files git tracks: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .split('\0')
+        .filter(|file| !file.is_empty())
+        .map(PathBuf::from)
+        .collect())
+}
+
+/// The patterns of `.gitignore`, for projects outside git. Negations aren't
+/// supported.
+fn gitignore_patterns() -> Vec<String> {
+    fs::read_to_string(".gitignore")
+        .unwrap_or_default()
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with(['#', '!']))
+        .map(|line| line.trim_matches('/').to_string())
+        .collect()
+}
+
+/// The files below `dir`, without git's files, build directories and what
+/// the `.gitignore` patterns match
+fn walk(dir: &Path, ignored: &[String]) -> Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    for entry in fs::read_dir(dir).with_context(|| format!("Failed to read {}", dir.display()))? {
+        let path = entry?.path();
+        let relative = path.strip_prefix(".").unwrap_or(&path).to_path_buf();
+        let name = relative
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let relative_name = relative.display().to_string();
+        let is_ignored = ignored.iter().any(|pattern| match pattern.contains('/') {
+            true => glob::matches(pattern, &relative_name),
+            false => glob::matches(pattern, &name),
+        });
+        if name == ".git" || is_ignored {
+            continue;
+        }
+
+        if path.is_dir() {
+            if !path.join("CMakeCache.txt").exists() {
+                files.extend(walk(&path, ignored)?);
+            }
+        } else {
+            files.push(relative);
+        }
+    }
+
+    Ok(files)
+}
+
+/// The dependencies FetchContent declares, which have to be fetched into
+/// the build directory to be vendored
+fn fetched_dependencies(build_dir: &str) -> Result<Vec<Fetched>> {
+    let mut files = vec![PathBuf::from(CMAKE_LISTS)];
+    files.extend(glob::expand(&format!("{}/*.cmake", FRAGMENT_DIR))?);
+
+    let mut fetched = Vec::new();
+    let mut missing = Vec::new();
+    for file in files {
+        let Ok(text) = fs::read_to_string(&file) else {
+            continue;
+        };
+        for arguments in audit::fetch_content_declarations(&text) {
+            let Some((name, options)) = arguments.split_first() else {
+                continue;
+            };
+            let option = |key: &str| {
+                options
+                    .iter()
+                    .position(|option| option == key)
+                    .and_then(|index| options.get(index + 1))
+                    .cloned()
+                    .unwrap_or_default()
+            };
+            let source = Path::new(build_dir)
+                .join("_deps")
+                .join(format!("{}-src", name.to_ascii_lowercase()));
+            if !source.is_dir() {
+                missing.push(name.clone());
+                continue;
+            }
+            fetched.push(Fetched {
+                name: name.clone(),
+                repository: option("GIT_REPOSITORY"),
+                tag: option("GIT_TAG"),
+                commit: audit::fetched_commit(&source),
+                source,
+            });
+        }
+    }
+
+    if !missing.is_empty() {
+        anyhow::bail!(
+            "{} {} not fetched into '{}', build the project before vendoring",
+            missing.join(", "),
+            if missing.len() == 1 { "is" } else { "are" },
+            build_dir
+        );
+    }
+    if fetched.is_empty() {
+        eprintln!(
+            "{}",
+            "The project has no FetchContent dependencies to vendor".yellow()
+        );
+    }
+
+    Ok(fetched)
+}
+
+/// Copies the fetched sources, without their git history, and writes the
+/// lock and the cache script using them
+fn vendor(dir: &Path, fetched: &[Fetched]) -> Result<()> {
+    let mut lock =
+        "# Dependencies vendored by cxx export\n# name repository tag commit\n".to_string();
+    let mut cache = "# Pass to CMake with -C to build with the vendored dependencies\n".to_string();
+    for dependency in fetched {
+        copy_tree(&dependency.source, &dir.join(&dependency.name))?;
+        lock.push_str(&format!(
+            "{} {} {} {}\n",
+            dependency.name,
+            or_dash(&dependency.repository),
+            or_dash(&dependency.tag),
+            dependency.commit.as_deref().unwrap_or("-")
+        ));
+        cache.push_str(&format!(
+            "set(FETCHCONTENT_SOURCE_DIR_{} \"${{CMAKE_CURRENT_LIST_DIR}}/{}\" CACHE PATH \"\")\n",
+            dependency.name.to_ascii_uppercase(),
+            dependency.name
+        ));
+    }
+
+    fs::write(dir.join(LOCK_FILE), lock).context("Failed to write the dependency lock")?;
+    fs::write(dir.join(VENDOR_CACHE), cache).context("Failed to write the vendor cache script")
+}
+
+fn or_dash(value: &str) -> &str {
+    if value.is_empty() {
+        "-"
+    } else {
+        value
+    }
+}
+
+fn copy_tree(from: &Path, to: &Path) -> Result<()> {
+    for entry in fs::read_dir(from).with_context(|| format!("Failed to read {}", from.display()))? {
+        let entry = entry?;
+        let path = entry.path();
+        if entry.file_name() == ".git" {
+            continue;
+        }
+        if path.is_dir() {
+            copy_tree(&path, &to.join(entry.file_name()))?;
+        } else {
+            copy_file(&path, &to.join(entry.file_name()))?;
+        }
+    }
+
+    Ok(())
+}
+
+fn copy_file(from: &Path, to: &Path) -> Result<()> {
+    if let Some(dir) = to.parent() {
+        fs::create_dir_all(dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+    }
+    fs::copy(from, to).with_context(|| format!("Failed to copy {}", from.display()))?;
+
+    Ok(())
+}
+
+fn has_tool(tool: &str) -> bool {
+    executor().capture(Command::new(tool).arg("-v")).is_ok()
+}
@@ -0,0 +1,155 @@
+use anyhow::{Context, Result};
+use std::{
+    fmt::Write,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    cmake, glob,
+    guards::{wrap_header, GuardStyle},
+    manifest::AssetsConfig,
+};
+
+/// Writes the header embedding the configured assets, leaving it untouched
+/// when nothing changed so the build doesn't recompile its includers
+pub fn embed_assets(config: &AssetsConfig) -> Result<()> {
+    if config.embed.is_empty() {
+        return Ok(());
+    }
+
+    let mut files = Vec::new();
+    for pattern in &config.embed {
+        files.extend(glob::expand(pattern)?);
+    }
+
+    let style = GuardStyle::for_project()?;
+    let header = wrap_header(
+        &style,
+        &cmake::project_name()?,
+        Path::new(&config.embed_header),
+        &generate_embed_header(&files)?,
+    )?;
+    let header = format!(
+        "// Generated by cxx from the [assets] section of cpp_tools.toml, do not edit.\n\n{}",
+        header
+    );
+    if fs::read_to_string(&config.embed_header).ok().as_deref() == Some(header.as_str()) {
+        return Ok(());
+    }
+
+    if let Some(parent) = Path::new(&config.embed_header).parent() {
+        fs::create_dir_all(parent).context("Failed to create embedded assets directory")?;
+    }
+    fs::write(&config.embed_header, header).context("Failed to write embedded assets header")?;
+
+    Ok(())
+}
+
+fn generate_embed_header(files: &[PathBuf]) -> Result<String> {
+    let mut header = String::from("#include <stddef.h>\n");
+
+    for file in files {
+        let contents =
+            fs::read(file).with_context(|| format!("Failed to read asset '{}'", file.display()))?;
+        let name = asset_identifier(file);
+
+        writeln!(header, "\n// {}", file.display())?;
+        writeln!(header, "static const unsigned char {}[] = {{", name)?;
+        for chunk in contents.chunks(12) {
+            let bytes = chunk
+                .iter()
+                .map(|byte| format!("0x{:02x}", byte))
+                .collect::<Vec<_>>()
+                .join(", ");
+            writeln!(header, "  {},", bytes)?;
+        }
+        writeln!(header, "}};")?;
+        writeln!(
+            header,
+            "static const size_t {}_size = {};",
+            name,
+            contents.len()
+        )?;
+    }
+
+    Ok(header)
+}
+
+/// `data/logo.png` becomes `asset_data_logo_png`
+fn asset_identifier(file: &Path) -> String {
+    let name = file
+        .to_string_lossy()
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect::<String>();
+
+    format!("asset_{}", name)
+}
+
+/// Copies (or symlinks) the configured assets next to the executable
+pub fn install_assets(config: &AssetsConfig, runtime_dir: &str) -> Result<()> {
+    install_assets_into(config, Path::new(runtime_dir), config.symlink)
+}
+
+/// Copies the configured assets next to the executable of a release
+/// artifact, never symlinking them, as the archive is unpacked elsewhere
+pub fn stage_assets(config: &AssetsConfig, stage_dir: &Path) -> Result<()> {
+    install_assets_into(config, stage_dir, false)
+}
+
+fn install_assets_into(config: &AssetsConfig, dir: &Path, symlink: bool) -> Result<()> {
+    for (pattern, destination) in &config.files {
+        for file in glob::expand(pattern)? {
+            let target = dir
+                .join(destination)
+                .join(glob::relative_to_base(pattern, &file));
+
+            install_asset(&file, &target, symlink)
+                .with_context(|| format!("Failed to install asset '{}'", file.display()))?;
+        }
+    }
+
+    Ok(())
+}
+
+fn install_asset(source: &Path, target: &Path, symlink: bool) -> Result<()> {
+    if let Some(parent) = target.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    if symlink {
+        if target.is_symlink() {
+            return Ok(());
+        }
+        if target.exists() {
+            fs::remove_file(target)?;
+        }
+
+        let source = fs::canonicalize(source)?;
+        #[cfg(unix)]
+        std::os::unix::fs::symlink(source, target)?;
+        #[cfg(windows)]
+        std::os::windows::fs::symlink_file(source, target)?;
+
+        return Ok(());
+    }
+
+    if target.is_symlink() {
+        fs::remove_file(target)?;
+    } else if is_up_to_date(source, target) {
+        return Ok(());
+    }
+    fs::copy(source, target)?;
+
+    Ok(())
+}
+
+fn is_up_to_date(source: &Path, target: &Path) -> bool {
+    let modified = |path: &Path| fs::metadata(path).and_then(|metadata| metadata.modified());
+
+    match (modified(source), modified(target)) {
+        (Ok(source), Ok(target)) => target >= source,
+        _ => false,
+    }
+}
@@ -0,0 +1,551 @@
+use anyhow::{Context, Result};
+use colorize::AnsiColor;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use crate::{
+    cmake::{self, CMAKE_LISTS},
+    executor::executor,
+    glob,
+    manifest::{Manifest, MANIFEST_FILE, STATE_DIR},
+};
+
+/// OSV aggregates advisories from NVD, GitHub and the projects themselves
+const OSV_QUERY: &str = "https://api.osv.dev/v1/query";
+
+/// OSV responses of earlier audits, relative to the state directory
+const CACHE_FILE: &str = "audit_cache.json";
+
+const CONANFILE: &str = "conanfile.txt";
+
+/// Severities from least to most severe, as accepted by `fail_on`
+const SEVERITIES: [&str; 5] = ["none", "low", "medium", "high", "critical"];
+
+/// A dependency pinned to a version, the form OSV is queried with
+struct Dependency {
+    name: String,
+    version: String,
+    /// Where the dependency was found, e.g. CMakeLists.txt
+    origin: String,
+    query: Query,
+}
+
+/// An OSV query, by the commit a dependency was fetched at or by version
+#[derive(Serialize)]
+#[serde(untagged)]
+enum Query {
+    Commit { commit: String },
+    Version { package: Package, version: String },
+}
+
+#[derive(Serialize)]
+struct Package {
+    name: String,
+    ecosystem: &'static str,
+}
+
+#[derive(Serialize, Deserialize)]
+struct CachedQuery {
+    /// Seconds since the epoch the response was fetched at
+    fetched: u64,
+    response: String,
+}
+
+type Cache = BTreeMap<String, CachedQuery>;
+
+#[derive(Deserialize, Default)]
+struct QueryResponse {
+    #[serde(default)]
+    vulns: Vec<Vulnerability>,
+}
+
+#[derive(Deserialize)]
+struct Vulnerability {
+    id: String,
+    #[serde(default)]
+    aliases: Vec<String>,
+    #[serde(default)]
+    summary: String,
+    #[serde(default)]
+    severity: Vec<SeverityScore>,
+    #[serde(default)]
+    affected: Vec<Affected>,
+    #[serde(default)]
+    database_specific: Option<serde_json::Value>,
+}
+
+#[derive(Deserialize)]
+struct SeverityScore {
+    #[serde(rename = "type")]
+    kind: String,
+    score: String,
+}
+
+#[derive(Deserialize)]
+struct Affected {
+    #[serde(default)]
+    ranges: Vec<Range>,
+}
+
+#[derive(Deserialize)]
+struct Range {
+    #[serde(default)]
+    events: Vec<BTreeMap<String, String>>,
+}
+
+impl Vulnerability {
+    /// The CVE identifier when there is one, it is what people search for
+    fn name(&self) -> &str {
+        std::iter::once(&self.id)
+            .chain(&self.aliases)
+            .find(|id| id.starts_with("CVE-"))
+            .unwrap_or(&self.id)
+    }
+
+    /// The CVSS v3 base score when the advisory has a vector, otherwise the
+    /// severity the database rated it with
+    fn severity(&self) -> (usize, Option<f64>) {
+        let score = self
+            .severity
+            .iter()
+            .filter(|severity| severity.kind.starts_with("CVSS_V3"))
+            .find_map(|severity| cvss3_score(&severity.score));
+        if let Some(score) = score {
+            let level = match score {
+                score if score >= 9.0 => 4,
+                score if score >= 7.0 => 3,
+                score if score >= 4.0 => 2,
+                score if score > 0.0 => 1,
+                _ => 0,
+            };
+            return (level, Some(score));
+        }
+
+        let rated = self
+            .database_specific
+            .as_ref()
+            .and_then(|specific| specific.get("severity"))
+            .and_then(|severity| severity.as_str())
+            .map(|severity| severity.to_ascii_lowercase());
+        let level = match rated.as_deref() {
+            Some("moderate") => 2,
+            Some(rated) => SEVERITIES
+                .iter()
+                .position(|level| *level == rated)
+                .unwrap_or(0),
+            None => 0,
+        };
+        (level, None)
+    }
+
+    fn fixed(&self) -> Vec<String> {
+        let mut fixed = self
+            .affected
+            .iter()
+            .flat_map(|affected| &affected.ranges)
+            .flat_map(|range| &range.events)
+            .filter_map(|event| event.get("fixed"))
+            .map(|fixed| {
+                // Git ranges are fixed by commits
+                if fixed.len() == 40 && fixed.chars().all(|c| c.is_ascii_hexdigit()) {
+                    fixed[..12].to_string()
+                } else {
+                    fixed.clone()
+                }
+            })
+            .collect::<Vec<_>>();
+        fixed.sort();
+        fixed.dedup();
+        fixed
+    }
+}
+
+/// Looks up the project's pinned dependencies in the OSV database and
+/// reports their known vulnerabilities. Fails when one is at least as
+/// severe as `fail_on`, or the manifest's `[audit]` threshold.
+pub fn handle_audit(build_dir: String, offline: bool, fail_on: Option<String>) -> Result<()> {
+    let manifest = Manifest::load()?;
+    let config = &manifest.audit;
+    let threshold = fail_on.as_deref().unwrap_or(&config.fail_on);
+    let threshold = SEVERITIES
+        .iter()
+        .position(|level| level.eq_ignore_ascii_case(threshold))
+        .with_context(|| {
+            format!(
+                "Invalid severity '{}', valid severities are 'none', 'low', 'medium', 'high' and 'critical'",
+                threshold
+            )
+        })?;
+
+    let dependencies = locked_dependencies(&build_dir)?;
+    if dependencies.is_empty() {
+        eprintln!(
+            "{}",
+            format!(
+                "No pinned dependencies found in FetchContent declarations or {}",
+                CONANFILE
+            )
+            .yellow()
+        );
+        return Ok(());
+    }
+
+    let mut cache = load();
+    let mut found = 0;
+    let mut failing = 0;
+    let mut missing = Vec::new();
+    let mut oldest = u64::MAX;
+    for dependency in &dependencies {
+        let key = serde_json::to_string(&dependency.query).context("Failed to serialize query")?;
+        let response = if offline {
+            match cache.get(&key) {
+                Some(cached) => {
+                    oldest = oldest.min(cached.fetched);
+                    cached.response.clone()
+                }
+                None => {
+                    missing.push(format!("{} {}", dependency.name, dependency.version));
+                    continue;
+                }
+            }
+        } else {
+            let response = query(&key).with_context(|| {
+                format!("Failed to audit {}, retry with --offline", dependency.name)
+            })?;
+            cache.insert(
+                key,
+                CachedQuery {
+                    fetched: now(),
+                    response: response.clone(),
+                },
+            );
+            response
+        };
+        let response: QueryResponse = serde_json::from_str(&response)
+            .with_context(|| format!("Failed to parse the advisories of {}", dependency.name))?;
+
+        let mut vulnerabilities = response
+            .vulns
+            .iter()
+            .filter(|vulnerability| {
+                !config.ignore.iter().any(|ignored| {
+                    glob::matches(ignored, &vulnerability.id)
+                        || vulnerability
+                            .aliases
+                            .iter()
+                            .any(|alias| glob::matches(ignored, alias))
+                })
+            })
+            .map(|vulnerability| (vulnerability.severity(), vulnerability))
+            .collect::<Vec<_>>();
+        if vulnerabilities.is_empty() {
+            continue;
+        }
+        vulnerabilities.sort_by(|a, b| b.0 .0.cmp(&a.0 .0).then_with(|| a.1.id.cmp(&b.1.id)));
+
+        println!(
+            "{} {} ({})",
+            dependency.name.clone().bold(),
+            dependency.version,
+            dependency.origin
+        );
+        for ((level, score), vulnerability) in vulnerabilities {
+            found += 1;
+            if level >= threshold && threshold > 0 {
+                failing += 1;
+            }
+            let severity = match score {
+                Some(score) => format!("{:8} {:4.1}", SEVERITIES[level], score),
+                None => format!("{:13}", SEVERITIES[level]),
+            };
+            let severity = match level {
+                4 | 3 => severity.red(),
+                2 => severity.yellow(),
+                _ => severity,
+            };
+            println!(
+                "  {:20} {}  {}",
+                vulnerability.name(),
+                severity,
+                vulnerability.summary
+            );
+            let fixed = vulnerability.fixed();
+            if !fixed.is_empty() {
+                println!("  {:20} fixed in {}", "", fixed.join(", "));
+            }
+        }
+    }
+
+    if !offline {
+        save(&cache)?;
+    }
+    if oldest != u64::MAX {
+        eprintln!(
+            "Using advisories cached {} days ago",
+            now().saturating_sub(oldest) / (24 * 60 * 60)
+        );
+    }
+    if !missing.is_empty() {
+        eprintln!(
+            "{}",
+            format!(
+                "Not in the offline database, audit online once: {}",
+                missing.join(", ")
+            )
+            .yellow()
+        );
+    }
+
+    if failing > 0 {
+        anyhow::bail!(
+            "Found {} vulnerabilities rated {} or higher, ignore accepted ones in [audit] in {}",
+            failing,
+            SEVERITIES[threshold],
+            MANIFEST_FILE
+        );
+    }
+    eprintln!(
+        "{}",
+        format!(
+            "Audited {} dependencies, {} known vulnerabilities",
+            dependencies.len() - missing.len(),
+            found
+        )
+        .green()
+    );
+
+    Ok(())
+}
+
+/// The dependencies pinned to a tag or commit in `FetchContent_Declare`
+/// calls, resolved to the commit fetched into the build directory when
+/// there is one, and the requirements of a Conan `conanfile.txt`
+fn locked_dependencies(build_dir: &str) -> Result<Vec<Dependency>> {
+    let mut files = vec![PathBuf::from(CMAKE_LISTS)];
+    files.extend(glob::expand(&format!("{}/*.cmake", cmake::FRAGMENT_DIR))?);
+
+    let mut dependencies = Vec::new();
+    for file in files {
+        let Ok(text) = fs::read_to_string(&file) else {
+            continue;
+        };
+        for arguments in fetch_content_declarations(&text) {
+            let Some((name, options)) = arguments.split_first() else {
+                continue;
+            };
+            let option = |key: &str| {
+                options
+                    .iter()
+                    .position(|option| option == key)
+                    .and_then(|index| options.get(index + 1))
+            };
+            let (Some(repository), Some(tag)) = (option("GIT_REPOSITORY"), option("GIT_TAG"))
+            else {
+                continue;
+            };
+
+            let source = Path::new(build_dir)
+                .join("_deps")
+                .join(format!("{}-src", name.to_ascii_lowercase()));
+            let query = match fetched_commit(&source) {
+                Some(commit) => Query::Commit { commit },
+                None => Query::Version {
+                    package: Package {
+                        name: repository.trim_end_matches(".git").to_string(),
+                        ecosystem: "GIT",
+                    },
+                    version: tag.clone(),
+                },
+            };
+            dependencies.push(Dependency {
+                name: name.clone(),
+                version: tag.clone(),
+                origin: file.display().to_string(),
+                query,
+            });
+        }
+    }
+
+    if let Ok(text) = fs::read_to_string(CONANFILE) {
+        let mut requires = false;
+        for line in text.lines().map(str::trim) {
+            if line.starts_with('[') {
+                requires = line == "[requires]";
+                continue;
+            }
+            let Some((name, version)) = line.split_once('/').filter(|_| requires) else {
+                continue;
+            };
+            let version = version.split(['@', '#']).next().unwrap_or(version);
+            dependencies.push(Dependency {
+                name: name.to_string(),
+                version: version.to_string(),
+                origin: CONANFILE.to_string(),
+                query: Query::Version {
+                    package: Package {
+                        name: name.to_string(),
+                        ecosystem: "ConanCenter",
+                    },
+                    version: version.to_string(),
+                },
+            });
+        }
+    }
+
+    Ok(dependencies)
+}
+
+/// The arguments of every `FetchContent_Declare(...)` call, without comments
+pub fn fetch_content_declarations(text: &str) -> Vec<Vec<String>> {
+    let text = text
+        .lines()
+        .map(|line| line.split('#').next().unwrap_or_default())
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    let mut declarations = Vec::new();
+    let mut rest = text.as_str();
+    while let Some(start) = rest.find("FetchContent_Declare(") {
+        rest = &rest[start + "FetchContent_Declare(".len()..];
+        let end = rest.find(')').unwrap_or(rest.len());
+        declarations.push(
+            rest[..end]
+                .split_whitespace()
+                .map(|argument| argument.trim_matches('"').to_string())
+                .collect(),
+        );
+        rest = &rest[end..];
+    }
+
+    declarations
+}
+
+/// The commit checked out in a dependency's fetched sources
+pub fn fetched_commit(source: &Path) -> Option<String> {
+    if !source.join(".git").exists() {
+        return None;
+    }
+    let output = executor()
+        .capture(
+            Command::new("git")
+                .arg("-C")
+                .arg(source)
+                .args(["rev-parse", "HEAD"]),
+        )
+        .ok()?;
+    let commit = String::from_utf8_lossy(&output.stdout).trim().to_string();
+
+    (output.status.success() && commit.len() == 40).then_some(commit)
+}
+
+fn query(body: &str) -> Result<String> {
+    let output = executor()
+        .capture(Command::new("curl").args([
+            "-fsSL",
+            "-H",
+            "Content-Type: application/json",
+            "-d",
+            body,
+            OSV_QUERY,
+        ]))
+        .context("Failed to run curl")?;
+    if !output.status.success() {
+        anyhow::bail!(
+            "OSV query failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+/// The CVSS v3 base score of a vector like
+/// `CVSS:3.1/AV:N/AC:L/PR:N/UI:N/S:U/C:H/I:H/A:H`
+fn cvss3_score(vector: &str) -> Option<f64> {
+    let metrics = vector
+        .split('/')
+        .filter_map(|metric| metric.split_once(':'))
+        .collect::<BTreeMap<_, _>>();
+    let changed = *metrics.get("S")? == "C";
+
+    let attack_vector: f64 = match *metrics.get("AV")? {
+        "N" => 0.85,
+        "A" => 0.62,
+        "L" => 0.55,
+        _ => 0.2,
+    };
+    let complexity: f64 = if *metrics.get("AC")? == "L" {
+        0.77
+    } else {
+        0.44
+    };
+    let privileges: f64 = match (*metrics.get("PR")?, changed) {
+        ("N", _) => 0.85,
+        ("L", false) => 0.62,
+        ("L", true) => 0.68,
+        (_, false) => 0.27,
+        (_, true) => 0.5,
+    };
+    let interaction: f64 = if *metrics.get("UI")? == "N" {
+        0.85
+    } else {
+        0.62
+    };
+    let impact = |metric: &str| -> Option<f64> {
+        match metrics.get(metric).copied() {
+            Some("H") => Some(0.56),
+            Some("L") => Some(0.22),
+            Some(_) => Some(0.0),
+            None => None,
+        }
+    };
+
+    let base = 1.0 - (1.0 - impact("C")?) * (1.0 - impact("I")?) * (1.0 - impact("A")?);
+    let impact = if changed {
+        7.52 * (base - 0.029) - 3.25 * (base - 0.02).powi(15)
+    } else {
+        6.42 * base
+    };
+    if impact <= 0.0 {
+        return Some(0.0);
+    }
+    let exploitability = 8.22 * attack_vector * complexity * privileges * interaction;
+    let score = if changed {
+        (1.08 * (impact + exploitability)).min(10.0)
+    } else {
+        (impact + exploitability).min(10.0)
+    };
+
+    // CVSS rounds up to one decimal
+    Some((score * 10.0 - 1e-9).ceil() / 10.0)
+}
+
+fn now() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or(0)
+}
+
+fn cache_path() -> PathBuf {
+    Path::new(STATE_DIR).join(CACHE_FILE)
+}
+
+/// A cache that doesn't parse, e.g. from an older version, is started over
+fn load() -> Cache {
+    fs::read_to_string(cache_path())
+        .ok()
+        .and_then(|text| serde_json::from_str(&text).ok())
+        .unwrap_or_default()
+}
+
+fn save(cache: &Cache) -> Result<()> {
+    fs::create_dir_all(STATE_DIR).context("Failed to create state directory")?;
+    let text = serde_json::to_string(cache).context("Failed to serialize audit cache")?;
+    fs::write(cache_path(), text).context("Failed to write audit cache")
+}
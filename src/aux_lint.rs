@@ -0,0 +1,151 @@
+use anyhow::{Context, Result};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use crate::executor::executor;
+
+/// CI configurations, checked with yamllint
+const WORKFLOW_DIR: &str = ".github/workflows";
+const YAML_FILES: &[&str] = &[".gitlab-ci.yml", ".clangd"];
+
+/// Directories of Git hooks, checked with shellcheck. `.githooks` is the
+/// usual `core.hooksPath` of hooks committed to the repository.
+const HOOK_DIRS: &[&str] = &[".githooks", ".git/hooks"];
+
+/// Lints the project's auxiliary files: CI workflows with yamllint and hook
+/// and shell scripts with shellcheck, each when installed. Findings are
+/// printed like the other lints, as `file:line:column: [check] message`,
+/// and the number of them is returned.
+pub fn lint_aux() -> Result<usize> {
+    let mut yaml = Vec::new();
+    if let Ok(entries) = fs::read_dir(WORKFLOW_DIR) {
+        yaml.extend(
+            entries
+                .filter_map(|entry| entry.ok())
+                .map(|entry| entry.path())
+                .filter(|path| {
+                    path.extension()
+                        .is_some_and(|extension| extension == "yml" || extension == "yaml")
+                }),
+        );
+    }
+    yaml.extend(
+        YAML_FILES
+            .iter()
+            .map(PathBuf::from)
+            .filter(|path| path.is_file()),
+    );
+    yaml.sort();
+
+    let mut scripts = Vec::new();
+    for dir in HOOK_DIRS {
+        let Ok(entries) = fs::read_dir(dir) else {
+            continue;
+        };
+        scripts.extend(
+            entries
+                .filter_map(|entry| entry.ok())
+                .map(|entry| entry.path())
+                .filter(|path| path.is_file() && path.extension().is_none_or(|e| e != "sample")),
+        );
+    }
+    collect_scripts(Path::new("."), &mut scripts);
+    scripts.sort();
+    scripts.dedup();
+
+    let mut findings = 0;
+    if !yaml.is_empty() {
+        findings += run_linter("yamllint", &["-f", "parsable"], &yaml, parse_yamllint)?;
+    }
+    if !scripts.is_empty() {
+        findings += run_linter("shellcheck", &["-f", "gcc"], &scripts, parse_shellcheck)?;
+    }
+
+    Ok(findings)
+}
+
+fn run_linter(
+    tool: &str,
+    arguments: &[&str],
+    files: &[PathBuf],
+    parse: fn(&str) -> Option<String>,
+) -> Result<usize> {
+    if !has_tool(tool) {
+        eprintln!("{} isn't installed, skipping {} files", tool, files.len());
+        return Ok(0);
+    }
+
+    let output = executor()
+        .capture(Command::new(tool).args(arguments).args(files))
+        .with_context(|| format!("Failed to run {}", tool))?;
+    let findings = String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(parse)
+        .inspect(|finding| eprintln!("{}", finding))
+        .count();
+    if !output.status.success() && findings == 0 {
+        anyhow::bail!(
+            "{} failed: {}",
+            tool,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+
+    Ok(findings)
+}
+
+/// Reads yamllint's `file:line:column: [level] message (rule)`
+fn parse_yamllint(line: &str) -> Option<String> {
+    let (location, rest) = line.split_once(": [")?;
+    let (level, message) = rest.split_once("] ")?;
+    let (message, rule) = match message.rsplit_once(" (") {
+        Some((message, rule)) => (message, rule.trim_end_matches(')')),
+        None => (message, level),
+    };
+
+    Some(format!("{}: [{}] {}: {}", location, rule, level, message))
+}
+
+/// Reads shellcheck's `file:line:column: level: message [SC2086]`
+fn parse_shellcheck(line: &str) -> Option<String> {
+    let (message, code) = line.rsplit_once(" [")?;
+    let code = code.strip_suffix(']')?;
+    let (location, message) = ["error", "warning", "note"].iter().find_map(|level| {
+        message
+            .split_once(&format!(": {}: ", level))
+            .map(|(location, message)| (location, format!("{}: {}", level, message)))
+    })?;
+
+    Some(format!("{}: [{}] {}", location, code, message))
+}
+
+/// Shell scripts in the project, skipping hidden directories and configured
+/// build trees
+fn collect_scripts(dir: &Path, scripts: &mut Vec<PathBuf>) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+
+    for entry in entries.filter_map(|entry| entry.ok()) {
+        let path = entry.path();
+        if entry.file_name().to_string_lossy().starts_with('.') {
+            continue;
+        }
+        if path.is_dir() {
+            if !path.join("CMakeCache.txt").exists() {
+                collect_scripts(&path, scripts);
+            }
+        } else if path.extension().is_some_and(|extension| extension == "sh") {
+            scripts.push(path.strip_prefix(".").unwrap_or(&path).to_path_buf());
+        }
+    }
+}
+
+fn has_tool(tool: &str) -> bool {
+    executor()
+        .capture(Command::new(tool).arg("--version"))
+        .is_ok()
+}
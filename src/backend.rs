@@ -0,0 +1,240 @@
+use anyhow::{Context, Result};
+use colorize::AnsiColor;
+use std::{fs, path::Path, process::Command};
+
+use crate::{
+    bazel::Bazel, cmake, doctest, glob, layout::INCLUDE_DIR, manifest::Manifest, targets,
+    xmake::Xmake,
+};
+
+/// Marks files a backend generated, so hand-written ones are never replaced
+pub const GENERATED_MARKER: &str = "Generated by cxx";
+
+/// A build system the project can be described in. CMake is the native
+/// one; others are generated from the same model, e.g. to trial a migration.
+pub trait BuildBackend {
+    /// Name on the command line, e.g. `bazel`
+    fn name(&self) -> &'static str;
+
+    /// The files describing the project, relative to the project root
+    fn generate(&self, project: &Project) -> Result<Vec<(String, String)>>;
+
+    /// Command configuring the build directory with a built-in profile, for
+    /// backends cxx also builds projects with
+    fn configure_command(
+        &self,
+        root_dir: &str,
+        build_dir: &str,
+        profile: Option<&str>,
+    ) -> Result<Command> {
+        let _ = (root_dir, build_dir, profile);
+        anyhow::bail!("cxx exports to {} but can't build with it", self.name())
+    }
+
+    fn build_command(&self, jobs: Option<usize>) -> Result<Command> {
+        let _ = jobs;
+        anyhow::bail!("cxx exports to {} but can't build with it", self.name())
+    }
+
+    /// Command removing what the build produced, or only for one target
+    fn clean_command(&self, target: Option<&str>) -> Result<Command> {
+        let _ = target;
+        anyhow::bail!("cxx exports to {} but can't clean with it", self.name())
+    }
+
+    fn test_command(&self) -> Result<Command> {
+        anyhow::bail!("cxx exports to {} but can't test with it", self.name())
+    }
+
+    /// Command running an executable target from `working_dir`
+    fn run_command(&self, target: &str, working_dir: &str, args: &[String]) -> Result<Command> {
+        let _ = (target, working_dir, args);
+        anyhow::bail!("cxx exports to {} but can't run with it", self.name())
+    }
+}
+
+/// The backend a project is built with when it has no CMakeLists.txt
+pub fn native(root_dir: &Path) -> Option<Box<dyn BuildBackend>> {
+    if root_dir.join(cmake::CMAKE_LISTS).exists() {
+        return None;
+    }
+
+    root_dir
+        .join(crate::xmake::XMAKE_FILE)
+        .exists()
+        .then(|| Box::new(Xmake) as Box<dyn BuildBackend>)
+}
+
+/// What cxx knows about a project, independent of the build system
+pub struct Project {
+    pub name: String,
+    pub version: String,
+    /// Where executables are built, relative to the project root
+    pub runtime_dir: String,
+    /// Where the compilation database is written, relative to the project root
+    pub build_dir: String,
+    /// The compiler flag selecting the project's standard, e.g. `-std=c++23`
+    pub standard_flag: String,
+    pub targets: Vec<Target>,
+}
+
+pub struct Target {
+    pub name: String,
+    /// `executable`, `static`, `shared` or `interface`
+    pub kind: String,
+    pub sources: Vec<String>,
+    pub include_dirs: Vec<String>,
+    pub definitions: Vec<String>,
+    /// Targets of the project and system libraries it links
+    pub link: Vec<String>,
+    /// Overrides the project's standard flag
+    pub standard_flag: Option<String>,
+}
+
+impl Target {
+    pub fn is_library(&self) -> bool {
+        self.kind != "executable"
+    }
+}
+
+impl Project {
+    /// Builds the model from CMakeLists.txt and the manifest's targets. The
+    /// project target comes first, with any `[target.<project>]` additions.
+    pub fn load() -> Result<Project> {
+        let manifest = Manifest::load()?;
+        let name = cmake::project_name()?;
+        let c = cmake::is_c_project()?;
+
+        let main = Target {
+            name: name.clone(),
+            kind: if cmake::is_library_project()? {
+                "static"
+            } else {
+                "executable"
+            }
+            .to_string(),
+            sources: cmake::source_files()?,
+            include_dirs: if Path::new(INCLUDE_DIR).is_dir() {
+                vec![INCLUDE_DIR.to_string()]
+            } else {
+                Vec::new()
+            },
+            definitions: Vec::new(),
+            link: Vec::new(),
+            standard_flag: None,
+        };
+        let mut project_targets = vec![main];
+
+        for (target_name, config) in &manifest.target {
+            targets::check_kind(target_name, &config.kind)?;
+
+            let mut sources = Vec::new();
+            for pattern in &config.sources {
+                sources.extend(
+                    glob::expand(pattern)?
+                        .into_iter()
+                        .map(|file| file.display().to_string()),
+                );
+            }
+            let standard_flag = config
+                .standard
+                .as_deref()
+                .map(|standard| targets::standard_flag(standard, c))
+                .transpose()
+                .with_context(|| format!("Invalid standard for target '{}'", target_name))?;
+
+            let target = match project_targets
+                .iter_mut()
+                .find(|target| target.name == *target_name)
+            {
+                Some(target) => target,
+                None => {
+                    project_targets.push(Target {
+                        name: target_name.clone(),
+                        kind: config.kind.clone(),
+                        sources: Vec::new(),
+                        include_dirs: Vec::new(),
+                        definitions: Vec::new(),
+                        link: Vec::new(),
+                        standard_flag: None,
+                    });
+                    project_targets.last_mut().expect("target was just added")
+                }
+            };
+            target.sources.extend(sources);
+            target
+                .include_dirs
+                .extend(config.include_dirs.iter().cloned());
+            target
+                .definitions
+                .extend(config.definitions.iter().cloned());
+            target.link.extend(config.link.iter().cloned());
+            if standard_flag.is_some() {
+                target.standard_flag = standard_flag;
+            }
+        }
+
+        Ok(Project {
+            name,
+            version: manifest
+                .package
+                .version
+                .unwrap_or_else(|| "0.1.0".to_string()),
+            runtime_dir: cmake::runtime_output_dir()?.unwrap_or_else(|| "bin".to_string()),
+            build_dir: "build".to_string(),
+            standard_flag: format!("-std={}", doctest::language_standard(c)),
+            targets: project_targets,
+        })
+    }
+
+    /// Whether a linked name is one of the project's targets rather than a
+    /// system library
+    pub fn has_target(&self, name: &str) -> bool {
+        self.targets.iter().any(|target| target.name == name)
+    }
+}
+
+fn backend(name: &str) -> Result<Box<dyn BuildBackend>> {
+    match name.to_ascii_lowercase().as_str() {
+        "bazel" => Ok(Box::new(Bazel)),
+        "xmake" => Ok(Box::new(Xmake)),
+        _ => anyhow::bail!("Valid export formats are 'bazel' and 'xmake'"),
+    }
+}
+
+/// Writes the project's build files for another build system next to the
+/// CMake ones. Files that exist without cxx's marker are left alone.
+pub fn handle_export(format: String) -> Result<()> {
+    let backend = backend(&format)?;
+    let project = Project::load()?;
+
+    let files = backend.generate(&project)?;
+    for (path, _) in &files {
+        if fs::read_to_string(path).is_ok_and(|existing| !existing.contains(GENERATED_MARKER)) {
+            anyhow::bail!(
+                "'{}' exists and wasn't generated by cxx, move it aside to export",
+                path
+            );
+        }
+    }
+    for (path, contents) in &files {
+        fs::write(path, contents).with_context(|| format!("Failed to write {}", path))?;
+    }
+
+    eprintln!(
+        "{}",
+        format!(
+            "Exported {} targets to {} ({})",
+            project.targets.len(),
+            backend.name(),
+            files
+                .iter()
+                .map(|(path, _)| path.as_str())
+                .collect::<Vec<_>>()
+                .join(", ")
+        )
+        .green()
+    );
+
+    Ok(())
+}
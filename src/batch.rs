@@ -0,0 +1,227 @@
+use anyhow::{Context, Result};
+use clap::Parser;
+use colorize::AnsiColor;
+use std::{collections::BTreeSet, fs, path::Path, process::Command};
+
+use crate::{compdb, executor::executor, handle_new_project, Args, Commands};
+
+/// The columns a roster can have, named in its header row. Only `name` is
+/// required.
+const COLUMNS: [&str; 4] = ["name", "template", "options", "remote"];
+
+/// A project of the roster, with the `new` arguments it's created with
+struct Row {
+    line: usize,
+    name: String,
+    args: Vec<String>,
+    remote: Option<String>,
+}
+
+/// Creates a project for every row of a CSV roster, e.g. one per student of
+/// a class, each with the row's template and `new` options. Projects that
+/// already exist are skipped, so a roster can be run again after fixing a
+/// row. With a `remote`, in which `{name}` stands for the project name, or a
+/// row's own remote, the project's `origin` is set to it.
+pub fn handle_new_batch(
+    roster: String,
+    dir: String,
+    build_dir: String,
+    remote: Option<String>,
+) -> Result<()> {
+    let text = fs::read_to_string(&roster)
+        .with_context(|| format!("Failed to read roster '{}'", roster))?;
+    let rows = parse_roster(&text, &dir, &build_dir, remote.as_deref())
+        .with_context(|| format!("Invalid roster '{}'", roster))?;
+
+    let mut created = 0;
+    let mut skipped = Vec::new();
+    let mut failed = Vec::new();
+    for row in rows {
+        let project_dir = Path::new(&dir).join(&row.name);
+        if project_dir.exists() {
+            skipped.push(row.name);
+            continue;
+        }
+        match create(&row, &project_dir) {
+            Ok(()) => created += 1,
+            Err(error) => {
+                eprintln!(
+                    "{}",
+                    format!(
+                        "Failed to create '{}' from line {}: {:#}",
+                        row.name, row.line, error
+                    )
+                    .red()
+                );
+                failed.push(row.name);
+            }
+        }
+    }
+
+    if !skipped.is_empty() {
+        eprintln!(
+            "{}",
+            format!("Skipped existing projects: {}", skipped.join(", ")).yellow()
+        );
+    }
+    eprintln!(
+        "{}",
+        format!(
+            "Created {} {} from {}",
+            created,
+            if created == 1 { "project" } else { "projects" },
+            roster
+        )
+        .green()
+    );
+    if !failed.is_empty() {
+        anyhow::bail!(
+            "Failed to create {} of the projects: {}",
+            failed.len(),
+            failed.join(", ")
+        );
+    }
+
+    Ok(())
+}
+
+fn create(row: &Row, project_dir: &Path) -> Result<()> {
+    let Ok(Args {
+        command:
+            Commands::New {
+                name,
+                dir,
+                build_dir,
+                scaffold,
+                configure,
+                ..
+            },
+        ..
+    }) = Args::try_parse_from(&row.args)
+    else {
+        anyhow::bail!("Failed to parse the options of '{}'", row.name);
+    };
+    let name = name.context("Missing project name")?;
+    handle_new_project(name, dir, build_dir, scaffold, configure, false)?;
+
+    if let Some(remote) = &row.remote {
+        executor()
+            .execute(
+                Command::new("git")
+                    .args(["remote", "add", "origin", remote])
+                    .current_dir(project_dir),
+            )
+            .context("Failed to set the git remote")?;
+    }
+
+    Ok(())
+}
+
+/// Reads the rows of a roster and checks their options, so a mistake in
+/// any row is reported before a project is created
+fn parse_roster(text: &str, dir: &str, build_dir: &str, remote: Option<&str>) -> Result<Vec<Row>> {
+    let mut lines = text
+        .lines()
+        .enumerate()
+        .map(|(index, line)| (index + 1, line))
+        .filter(|(_, line)| !line.trim().is_empty() && !line.starts_with('#'));
+
+    let (_, header) = lines.next().context("The roster is empty")?;
+    let header = parse_fields(header)
+        .into_iter()
+        .map(|column| column.trim().to_ascii_lowercase())
+        .collect::<Vec<_>>();
+    if let Some(column) = header
+        .iter()
+        .find(|column| !COLUMNS.contains(&column.as_str()))
+    {
+        anyhow::bail!(
+            "Unknown column '{}', the columns are {}",
+            column,
+            COLUMNS.join(", ")
+        );
+    }
+    if !header.iter().any(|column| column == "name") {
+        anyhow::bail!("The header row has no 'name' column");
+    }
+
+    let mut names = BTreeSet::new();
+    let mut rows = Vec::new();
+    for (line, fields) in lines.map(|(line, text)| (line, parse_fields(text))) {
+        let field = |column: &str| {
+            header
+                .iter()
+                .position(|name| name == column)
+                .and_then(|index| fields.get(index))
+                .map(|field| field.trim().to_string())
+                .filter(|field| !field.is_empty())
+        };
+        let name = field("name").with_context(|| format!("Line {} has no name", line))?;
+        if !names.insert(name.clone()) {
+            anyhow::bail!("Line {} repeats the project '{}'", line, name);
+        }
+
+        let mut args = ["cxx", "new", &name, "--dir", dir, "--build-dir", build_dir]
+            .map(String::from)
+            .to_vec();
+        if let Some(template) = field("template") {
+            args.extend(["--template".to_string(), template]);
+        }
+        args.extend(compdb::split_command(&field("options").unwrap_or_default()));
+        match Args::try_parse_from(&args) {
+            Ok(Args {
+                command: Commands::New { batch: None, .. },
+                ..
+            }) => {}
+            Ok(_) => anyhow::bail!("Line {} can't use --batch", line),
+            Err(error) => anyhow::bail!(
+                "Line {} has invalid options: {}",
+                line,
+                error
+                    .to_string()
+                    .lines()
+                    .next()
+                    .unwrap_or_default()
+                    .trim_start_matches("error: ")
+            ),
+        }
+
+        let remote =
+            field("remote").or_else(|| remote.map(|remote| remote.replace("{name}", &name)));
+        rows.push(Row {
+            line,
+            name,
+            args,
+            remote,
+        });
+    }
+    if rows.is_empty() {
+        anyhow::bail!("The roster has no projects");
+    }
+
+    Ok(rows)
+}
+
+/// Splits a CSV line into its fields. Fields can be quoted to contain
+/// commas, with `""` for a quote.
+fn parse_fields(line: &str) -> Vec<String> {
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match (c, quoted) {
+            ('"', true) if chars.peek() == Some(&'"') => {
+                chars.next();
+                field.push('"');
+            }
+            ('"', _) => quoted = !quoted,
+            (',', false) => fields.push(std::mem::take(&mut field)),
+            (c, _) => field.push(c),
+        }
+    }
+    fields.push(field);
+
+    fields
+}
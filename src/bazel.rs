@@ -0,0 +1,175 @@
+use anyhow::Result;
+use std::{collections::BTreeSet, fmt::Write, path::Path};
+
+use crate::backend::{BuildBackend, Project, Target, GENERATED_MARKER};
+
+/// Version of rules_cc the generated module depends on
+const RULES_CC_VERSION: &str = "0.0.9";
+
+const HEADER_EXTENSIONS: [&str; 5] = ["h", "hh", "hpp", "hxx", "inl"];
+
+/// Generates a `MODULE.bazel` and a root `BUILD.bazel` with one rule per target
+pub struct Bazel;
+
+impl BuildBackend for Bazel {
+    fn name(&self) -> &'static str {
+        "bazel"
+    }
+
+    fn generate(&self, project: &Project) -> Result<Vec<(String, String)>> {
+        let module = format!(
+            "# {} export bazel, do not edit.
+module(
+    name = \"{}\",
+    version = \"{}\",
+)
+
+bazel_dep(name = \"rules_cc\", version = \"{}\")
+",
+            GENERATED_MARKER,
+            module_name(&project.name),
+            project.version,
+            RULES_CC_VERSION
+        );
+
+        let mut build = format!(
+            "# {} export bazel, do not edit.
+load(\"@rules_cc//cc:defs.bzl\", \"cc_binary\", \"cc_library\")
+",
+            GENERATED_MARKER
+        );
+        for target in &project.targets {
+            build.push('\n');
+            build.push_str(&rule(project, target)?);
+        }
+
+        Ok(vec![
+            ("MODULE.bazel".to_string(), module),
+            ("BUILD.bazel".to_string(), build),
+        ])
+    }
+}
+
+/// Libraries export their include directories; binaries compile against
+/// them like CMake's private include directories
+fn rule(project: &Project, target: &Target) -> Result<String> {
+    let (deps, system_libraries): (Vec<_>, Vec<_>) = target
+        .link
+        .iter()
+        .partition(|name| project.has_target(name));
+
+    // Private headers next to the sources are part of the target too
+    let source_dirs = target
+        .sources
+        .iter()
+        .filter_map(|source| Path::new(source).parent())
+        .map(|dir| dir.display().to_string())
+        .filter(|dir| !dir.is_empty())
+        .collect::<BTreeSet<_>>();
+
+    let mut copts = vec![target
+        .standard_flag
+        .clone()
+        .unwrap_or_else(|| project.standard_flag.clone())];
+    let mut rule = String::new();
+    if target.is_library() {
+        writeln!(rule, "cc_library(")?;
+        writeln!(rule, "    name = \"{}\",", target.name)?;
+        if target.kind != "interface" {
+            writeln!(
+                rule,
+                "    srcs = {} + {},",
+                list(&target.sources),
+                header_glob(source_dirs.iter())
+            )?;
+        }
+        writeln!(
+            rule,
+            "    hdrs = {},",
+            header_glob(target.include_dirs.iter())
+        )?;
+        writeln!(rule, "    includes = {},", list(&target.include_dirs))?;
+        writeln!(rule, "    defines = {},", list(&target.definitions))?;
+        if target.kind == "shared" {
+            writeln!(rule, "    linkstatic = False,")?;
+        }
+    } else {
+        copts.extend(target.include_dirs.iter().map(|dir| format!("-I{}", dir)));
+        writeln!(rule, "cc_binary(")?;
+        writeln!(rule, "    name = \"{}\",", target.name)?;
+        writeln!(
+            rule,
+            "    srcs = {} + {},",
+            list(&target.sources),
+            header_glob(source_dirs.iter().chain(&target.include_dirs))
+        )?;
+        writeln!(rule, "    local_defines = {},", list(&target.definitions))?;
+    }
+    writeln!(rule, "    copts = {},", list(&copts))?;
+    writeln!(
+        rule,
+        "    linkopts = {},",
+        list(
+            &system_libraries
+                .iter()
+                .map(|library| format!("-l{}", library))
+                .collect::<Vec<_>>()
+        )
+    )?;
+    writeln!(
+        rule,
+        "    deps = {},",
+        list(
+            &deps
+                .iter()
+                .map(|dep| format!(":{}", dep))
+                .collect::<Vec<_>>()
+        )
+    )?;
+    writeln!(rule, ")")?;
+
+    Ok(rule)
+}
+
+fn list(items: &[String]) -> String {
+    format!(
+        "[{}]",
+        items
+            .iter()
+            .map(|item| format!("\"{}\"", item.replace('\\', "\\\\").replace('"', "\\\"")))
+            .collect::<Vec<_>>()
+            .join(", ")
+    )
+}
+
+fn header_glob<'a>(dirs: impl Iterator<Item = &'a String>) -> String {
+    let patterns = dirs
+        .flat_map(|dir| {
+            HEADER_EXTENSIONS
+                .iter()
+                .map(move |extension| format!("{}/**/*.{}", dir, extension))
+        })
+        .collect::<Vec<_>>();
+
+    format!("glob({}, allow_empty = True)", list(&patterns))
+}
+
+/// Module names are lower case and start with a letter
+fn module_name(name: &str) -> String {
+    let name = name
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '.' || c == '-' {
+                c.to_ascii_lowercase()
+            } else {
+                '_'
+            }
+        })
+        .collect::<String>();
+
+    if name.starts_with(|c: char| c.is_ascii_lowercase()) {
+        name
+    } else {
+        format!("m{}", name)
+    }
+}
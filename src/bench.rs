@@ -0,0 +1,319 @@
+use anyhow::{Context, Result};
+use colorize::AnsiColor;
+use std::{fmt::Write, fs, path::Path, process::Command};
+
+use crate::{build_target, cmake, executor::executor, find, toolchain};
+
+/// Directory holding one Google Benchmark source file per benchmark
+const BENCHES_DIR: &str = "benches";
+
+/// Target that builds every benchmark
+const BENCHES_TARGET: &str = "benches";
+
+/// Benchmarks are excluded from the default build and placed in their own
+/// directory next to the project executable. An executable can't be linked
+/// against, so its sources apart from main are compiled into each benchmark.
+const BENCHES_FRAGMENT: &str = "# Generated by cxx add bench, do not edit.
+enable_language(CXX)
+
+include(FetchContent)
+set(BENCHMARK_ENABLE_TESTING OFF CACHE BOOL \"\" FORCE)
+set(BENCHMARK_ENABLE_INSTALL OFF CACHE BOOL \"\" FORCE)
+FetchContent_Declare(benchmark
+  GIT_REPOSITORY https://github.com/google/benchmark.git
+  GIT_TAG v1.9.1)
+FetchContent_MakeAvailable(benchmark)
+
+file(GLOB BENCH_SOURCES CONFIGURE_DEPENDS ${CMAKE_CURRENT_SOURCE_DIR}/benches/*.cpp)
+
+get_target_property(PROJECT_TARGET_TYPE ${PROJECT_NAME} TYPE)
+set(BENCH_PROJECT_SOURCES ${SOURCE_FILES})
+list(FILTER BENCH_PROJECT_SOURCES EXCLUDE REGEX \"(^|/)main\\\\.(c|cpp)$\")
+set(BENCH_TARGETS)
+foreach(bench_source ${BENCH_SOURCES})
+  get_filename_component(bench ${bench_source} NAME_WE)
+  add_executable(bench_${bench} EXCLUDE_FROM_ALL ${bench_source})
+  set_target_properties(bench_${bench} PROPERTIES
+    OUTPUT_NAME ${bench}
+    RUNTIME_OUTPUT_DIRECTORY ${CMAKE_RUNTIME_OUTPUT_DIRECTORY}/benches)
+  target_include_directories(bench_${bench} PRIVATE ${CMAKE_CURRENT_SOURCE_DIR})
+  target_link_libraries(bench_${bench} PRIVATE benchmark::benchmark_main)
+  if(PROJECT_TARGET_TYPE STREQUAL \"EXECUTABLE\")
+    target_sources(bench_${bench} PRIVATE ${BENCH_PROJECT_SOURCES})
+  else()
+    target_link_libraries(bench_${bench} PRIVATE ${PROJECT_NAME})
+  endif()
+  list(APPEND BENCH_TARGETS bench_${bench})
+endforeach()
+
+add_custom_target(benches DEPENDS ${BENCH_TARGETS})
+";
+
+const HEADER_EXTENSIONS: [&str; 5] = ["h", "hh", "hpp", "hxx", "h++"];
+
+/// A parameter of the benchmarked function, declared as a local the
+/// benchmark passes to it
+struct Parameter {
+    ty: String,
+    name: String,
+}
+
+/// Generates a Google Benchmark for a function declared in a project
+/// header, found with the same index as `find`. Its parameters become
+/// value-initialized locals to fill in, and its result is kept from being
+/// optimized away.
+pub fn handle_add_bench(function: String, build_dir: String) -> Result<()> {
+    let name = function
+        .rsplit("::")
+        .next()
+        .unwrap_or(&function)
+        .to_string();
+    let path = Path::new(BENCHES_DIR).join(format!("{}.cpp", name));
+    if path.exists() {
+        anyhow::bail!("Benchmark '{}' already exists", name);
+    }
+
+    let declarations = find::declarations(&function, &build_dir)?;
+    if declarations.is_empty() {
+        anyhow::bail!("No declaration of '{}' found", function);
+    }
+    let Some((header, line)) = declarations.iter().find(|(file, _)| {
+        file.extension()
+            .is_some_and(|extension| HEADER_EXTENSIONS.iter().any(|ext| extension == *ext))
+    }) else {
+        anyhow::bail!(
+            "'{}' is only declared in {}, declare it in a header to benchmark it",
+            function,
+            declarations[0].0.display()
+        );
+    };
+
+    // Headers in the include directory are included the way the project
+    // does, others relative to the project root
+    let include = match cmake::include_dir_in(Path::new(".")) {
+        Some(dir) => header.strip_prefix(dir).unwrap_or(header),
+        None => header.as_path(),
+    };
+    let text = fs::read_to_string(header)
+        .with_context(|| format!("Failed to read {}", header.display()))?;
+    let declaration = text
+        .lines()
+        .skip(line - 1)
+        .take(20)
+        .collect::<Vec<_>>()
+        .join(" ");
+    let declaration = declaration.split(['{', ';']).next().unwrap_or(&declaration);
+
+    let benchmark = format!("BM_{}", function.replace("::", "_"));
+    let mut source = format!(
+        "// Benchmark of {}, fill in realistic arguments\n#include <benchmark/benchmark.h>\n\n",
+        function
+    );
+    let include = include.display().to_string().replace('\\', "/");
+    if cmake::is_c_project()? {
+        writeln!(source, "extern \"C\" {{\n#include \"{}\"\n}}", include)?;
+    } else {
+        writeln!(source, "#include \"{}\"", include)?;
+    }
+    writeln!(
+        source,
+        "\nstatic void {}(benchmark::State& state) {{",
+        benchmark
+    )?;
+    match signature(declaration, &name) {
+        Some((returns_void, parameters)) => {
+            for parameter in &parameters {
+                writeln!(source, "  {} {}{{}};", parameter.ty, parameter.name)?;
+            }
+            if !parameters.is_empty() {
+                source.push('\n');
+            }
+            let arguments = parameters
+                .iter()
+                .map(|parameter| parameter.name.as_str())
+                .collect::<Vec<_>>()
+                .join(", ");
+            let call = format!("{}({})", function, arguments);
+            writeln!(source, "  for (auto _ : state) {{")?;
+            if returns_void {
+                writeln!(source, "    {};\n    benchmark::ClobberMemory();", call)?;
+            } else {
+                writeln!(source, "    benchmark::DoNotOptimize({});", call)?;
+            }
+        }
+        None => {
+            writeln!(source, "  for (auto _ : state) {{")?;
+            writeln!(
+                source,
+                "    // benchmark::DoNotOptimize({}(...));",
+                function
+            )?;
+        }
+    }
+    writeln!(source, "  }}\n}}\nBENCHMARK({});", benchmark)?;
+
+    fs::create_dir_all(BENCHES_DIR).context("Failed to create benches directory")?;
+    fs::write(&path, source).context("Failed to create benchmark source file")?;
+    cmake::write_fragment("benches.cmake", BENCHES_FRAGMENT)?;
+
+    eprintln!(
+        "{}",
+        format!(
+            "Added benchmark '{}' in {}, run it with 'cxx bench --filter {}'",
+            name,
+            path.display(),
+            benchmark
+        )
+        .green()
+    );
+
+    Ok(())
+}
+
+/// Builds and runs every benchmark, only the ones whose name matches the
+/// `filter` regex when given
+pub fn handle_bench(build_dir: String, runtime_dir: String, filter: Option<String>) -> Result<()> {
+    let mut names = fs::read_dir(BENCHES_DIR)
+        .map(|entries| {
+            entries
+                .filter_map(|entry| entry.ok())
+                .map(|entry| entry.path())
+                .filter(|path| path.extension().is_some_and(|extension| extension == "cpp"))
+                .filter_map(|path| Some(path.file_stem()?.to_string_lossy().to_string()))
+                .collect::<Vec<_>>()
+        })
+        .unwrap_or_default();
+    names.sort();
+    if names.is_empty() {
+        anyhow::bail!("No benchmarks found, add one with 'cxx add bench <FUNCTION>'");
+    }
+
+    let build_type = toolchain::build_config(Path::new(&build_dir))
+        .or_else(|| cmake::cache_value(Path::new(&build_dir), "CMAKE_BUILD_TYPE"))
+        .unwrap_or_default();
+    if build_type.is_empty() || build_type == "Debug" {
+        eprintln!(
+            "{}",
+            "Benchmarking a debug build, use a release profile for meaningful numbers".yellow()
+        );
+    }
+
+    build_target(&build_dir, BENCHES_TARGET)?;
+    let mut ran = 0;
+    for name in names {
+        let executable = Path::new(&runtime_dir)
+            .join(BENCHES_DIR)
+            .join(toolchain::executable(&build_dir, &name));
+        let mut command = Command::new(&executable);
+        if let Some(filter) = &filter {
+            // Google Benchmark complains when nothing matches, so executables
+            // without a matching benchmark are skipped
+            let filter = format!("--benchmark_filter={}", filter);
+            if !executor().dry_run() {
+                let output = executor()
+                    .capture(
+                        Command::new(&executable)
+                            .arg("--benchmark_list_tests=true")
+                            .arg(&filter),
+                    )
+                    .with_context(|| format!("Failed to list the benchmarks of '{}'", name))?;
+                if String::from_utf8_lossy(&output.stdout).trim().is_empty() {
+                    continue;
+                }
+            }
+            command.arg(filter);
+        }
+        executor()
+            .execute_interactive(&mut command)
+            .with_context(|| format!("Benchmark '{}' failed", name))?;
+        ran += 1;
+    }
+    if ran == 0 {
+        anyhow::bail!(
+            "No benchmark matches '{}'",
+            filter.as_deref().unwrap_or_default()
+        );
+    }
+
+    Ok(())
+}
+
+/// Whether a function declaration returns void, and its parameters. None
+/// when they can't be told apart, e.g. for variadic functions.
+fn signature(declaration: &str, name: &str) -> Option<(bool, Vec<Parameter>)> {
+    let mut search = 0;
+    let (start, open) = loop {
+        let start = search + declaration[search..].find(name)?;
+        let after = declaration[start + name.len()..].trim_start();
+        let before = declaration[..start].chars().next_back();
+        let word_start = !before.is_some_and(|c| c.is_alphanumeric() || c == '_');
+        if word_start && after.starts_with('(') {
+            break (start, declaration.len() - after.len());
+        }
+        search = start + name.len();
+    };
+    let returns_void = declaration[..start]
+        .split_whitespace()
+        .last()
+        .is_some_and(|word| word == "void");
+
+    // Splits the parameter list at the commas outside of template arguments
+    // and nested parentheses
+    let mut depth = 0;
+    let mut parts = Vec::new();
+    let mut part = String::new();
+    for c in declaration[open + 1..].chars() {
+        match c {
+            '(' | '<' | '[' => depth += 1,
+            ')' if depth == 0 => break,
+            ')' | '>' | ']' => depth -= 1,
+            ',' if depth == 0 => {
+                parts.push(std::mem::take(&mut part));
+                continue;
+            }
+            _ => {}
+        }
+        part.push(c);
+    }
+    parts.push(part);
+
+    let mut parameters = Vec::new();
+    for (index, part) in parts.iter().enumerate() {
+        let part = part.split('=').next().unwrap_or(part).trim();
+        if part.is_empty() || part == "void" {
+            continue;
+        }
+        if part.contains("...") || part.contains('[') || part.contains('(') {
+            return None;
+        }
+
+        let split = part
+            .rfind(|c: char| !(c.is_alphanumeric() || c == '_'))
+            .map_or(0, |index| index + 1);
+        let (ty, name) = part.split_at(split);
+        let (ty, name) = if ty.trim().is_empty() || is_type_keyword(name) {
+            (part, format!("arg{}", index + 1))
+        } else {
+            (ty, name.to_string())
+        };
+
+        // References and top level const are dropped to get a local the
+        // function can take
+        let ty = ty.trim().trim_end_matches('&').trim_end();
+        let ty = ty.strip_prefix("const ").unwrap_or(ty).trim();
+        parameters.push(Parameter {
+            ty: ty.to_string(),
+            name,
+        });
+    }
+
+    Some((returns_void, parameters))
+}
+
+fn is_type_keyword(word: &str) -> bool {
+    [
+        "bool", "char", "short", "int", "long", "float", "double", "signed", "unsigned", "size_t",
+        "const",
+    ]
+    .contains(&word)
+}
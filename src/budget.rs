@@ -0,0 +1,138 @@
+use anyhow::{Context, Result};
+use colorize::AnsiColor;
+use std::{collections::BTreeMap, fs, path::PathBuf};
+
+use crate::{executor::executor, manifest::STATE_DIR, stats};
+
+/// Recent compile times of every source, per build directory
+const TIMES_FILE: &str = "compile_times.json";
+
+/// How many of a source's latest compile times its baseline is taken from
+const MAX_SAMPLES: usize = 10;
+
+/// Compile times needed before a source has a baseline
+const MIN_SAMPLES: usize = 3;
+
+/// Files compiling faster than this are left out, their times are mostly
+/// noise
+const MIN_SECONDS: f64 = 0.5;
+
+type History = BTreeMap<String, BTreeMap<String, Vec<f64>>>;
+
+/// A percentage and whether exceeding it only warns
+pub struct Budget {
+    pub percent: f64,
+    pub warn: bool,
+}
+
+/// Records how long each source took to compile in the steps Ninja logged
+/// since `log_offset`. With a `budget`, a file or the whole compilation
+/// taking longer than its baseline, the median of its recent compile times,
+/// by more than the budget's percentage fails the build or warns.
+pub fn record(build_dir: &str, log_offset: u64, budget: Option<Budget>) -> Result<()> {
+    let times = stats::compile_times(build_dir, log_offset);
+    if executor().dry_run() || times.is_empty() {
+        return Ok(());
+    }
+
+    let mut history = load();
+    let samples = history.entry(build_dir.to_string()).or_default();
+    let mut over = Vec::new();
+    let mut total = 0.0;
+    let mut baseline_total = 0.0;
+    let percent = budget.as_ref().map_or(0.0, |budget| budget.percent);
+    let allowed = |baseline: f64| baseline * (1.0 + percent / 100.0);
+    for (source, seconds) in &times {
+        let Some(baseline) = samples
+            .get(source)
+            .filter(|samples| samples.len() >= MIN_SAMPLES)
+            .map(|samples| median(samples))
+        else {
+            continue;
+        };
+        total += seconds;
+        baseline_total += baseline;
+        if *seconds > MIN_SECONDS && *seconds > allowed(baseline) {
+            over.push((source.clone(), *seconds, baseline));
+        }
+    }
+    for (source, seconds) in times {
+        let source_samples = samples.entry(source).or_default();
+        source_samples.push(seconds);
+        if source_samples.len() > MAX_SAMPLES {
+            source_samples.remove(0);
+        }
+    }
+    save(&history)?;
+
+    let Some(budget) = budget else {
+        return Ok(());
+    };
+    let total_over = total - baseline_total > MIN_SECONDS && total > allowed(baseline_total);
+    if over.is_empty() && !total_over {
+        return Ok(());
+    }
+
+    over.sort_by(|a, b| (b.1 - b.2).total_cmp(&(a.1 - a.2)));
+    for (source, seconds, baseline) in &over {
+        eprintln!(
+            "  {}  {:.2}s, baseline {:.2}s ({:+.0}%)",
+            source,
+            seconds,
+            baseline,
+            (seconds / baseline - 1.0) * 100.0
+        );
+    }
+    let mut problems = Vec::new();
+    if !over.is_empty() {
+        problems.push(format!(
+            "{} {} slower than the baseline",
+            over.len(),
+            if over.len() == 1 { "file" } else { "files" }
+        ));
+    }
+    if total_over {
+        problems.push(format!(
+            "compiling took {:.1}s instead of {:.1}s ({:+.0}%)",
+            total,
+            baseline_total,
+            (total / baseline_total - 1.0) * 100.0
+        ));
+    }
+    let message = format!(
+        "Over the compile time budget of +{}%: {}",
+        budget.percent,
+        problems.join(", ")
+    );
+    if !budget.warn {
+        anyhow::bail!("{}", message);
+    }
+    eprintln!("{}", message.yellow());
+
+    Ok(())
+}
+
+fn median(samples: &[f64]) -> f64 {
+    let mut sorted = samples.to_vec();
+    sorted.sort_by(f64::total_cmp);
+
+    sorted[sorted.len() / 2]
+}
+
+fn times_path() -> PathBuf {
+    PathBuf::from(STATE_DIR).join(TIMES_FILE)
+}
+
+fn load() -> History {
+    fs::read_to_string(times_path())
+        .ok()
+        .and_then(|text| serde_json::from_str(&text).ok())
+        .unwrap_or_default()
+}
+
+fn save(history: &History) -> Result<()> {
+    fs::create_dir_all(STATE_DIR).context("Failed to create state directory")?;
+    let text = serde_json::to_string(history).context("Failed to serialize compile times")?;
+
+    fs::write(times_path(), text).context("Failed to record compile times")
+}
@@ -0,0 +1,177 @@
+use anyhow::{Context, Result};
+use std::{
+    fs,
+    path::Path,
+    process::Command,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::{
+    cmake,
+    executor::executor,
+    guards::{wrap_header, GuardStyle},
+    manifest::Manifest,
+};
+
+const TIMESTAMP_MACRO: &str = "_BUILD_TIMESTAMP";
+
+/// Regenerates the build information header when the manifest enables it.
+/// The header is left untouched when only the timestamp would change, so
+/// builds without changes don't recompile everything that includes it.
+pub fn refresh_build_info(root_dir: &Path, build_dir: &str) -> Result<()> {
+    let manifest = Manifest::load_from(root_dir)?;
+    let Some(header) = header_path(&manifest, root_dir)? else {
+        return Ok(());
+    };
+    let name = project_name(&manifest, root_dir)?;
+    let header_path = root_dir.join(&header);
+
+    let prefix = macro_prefix(&name);
+    let version = manifest.package.version.as_deref().unwrap_or("0.0.0");
+    let commit = git_output(root_dir, &["rev-parse", "HEAD"]).unwrap_or("unknown".to_string());
+    // The header itself changes with every commit, it doesn't make a checkout dirty
+    let exclude_header = format!(":(exclude){}", header);
+    let dirty = git_output(
+        root_dir,
+        &["status", "--porcelain", "--", ".", &exclude_header],
+    )
+    .is_some_and(|s| !s.is_empty());
+    let profile = build_profile(&root_dir.join(build_dir));
+
+    let definitions = format!(
+        "#define {prefix}_VERSION \"{version}\"
+#define {prefix}_GIT_COMMIT \"{commit}\"
+#define {prefix}_GIT_DIRTY {dirty}
+#define {prefix}_BUILD_PROFILE \"{profile}\"
+#define {prefix}{TIMESTAMP_MACRO} \"{timestamp}\"
+",
+        dirty = dirty as u8,
+        timestamp = build_timestamp()?,
+    );
+    let style = GuardStyle::from_config(&manifest.style)?;
+    let header = format!(
+        "// Generated by cxx from cpp_tools.toml, do not edit.\n\n{}",
+        wrap_header(&style, &name, Path::new(&header), &definitions)?
+    );
+
+    let existing = fs::read_to_string(&header_path).unwrap_or_default();
+    if without_timestamp(&existing) == without_timestamp(&header) {
+        return Ok(());
+    }
+
+    if let Some(parent) = header_path.parent() {
+        fs::create_dir_all(parent).context("Failed to create build info directory")?;
+    }
+    fs::write(&header_path, header).context("Failed to write build info header")?;
+
+    Ok(())
+}
+
+/// The path of the build information header relative to the project root,
+/// when the manifest enables it
+pub fn header_path(manifest: &Manifest, root_dir: &Path) -> Result<Option<String>> {
+    if !manifest.build_info.enabled {
+        return Ok(None);
+    }
+
+    Ok(Some(match &manifest.build_info.header {
+        Some(header) => header.clone(),
+        None => format!(
+            "include/{}/build_info.hpp",
+            project_name(manifest, root_dir)?
+        ),
+    }))
+}
+
+fn project_name(manifest: &Manifest, root_dir: &Path) -> Result<String> {
+    match &manifest.package.name {
+        Some(name) => Ok(name.clone()),
+        None => fs::canonicalize(root_dir)
+            .context("Failed to resolve project directory")?
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .context("Failed to determine project name"),
+    }
+}
+
+/// `my-app` becomes `MY_APP`
+pub fn macro_prefix(name: &str) -> String {
+    name.chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() {
+                c.to_ascii_uppercase()
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+fn without_timestamp(header: &str) -> Vec<&str> {
+    header
+        .lines()
+        .filter(|line| !line.contains(TIMESTAMP_MACRO))
+        .collect()
+}
+
+fn git_output(root_dir: &Path, args: &[&str]) -> Option<String> {
+    let output = executor()
+        .capture(Command::new("git").args(args).current_dir(root_dir))
+        .ok()?;
+
+    if !output.status.success() {
+        return None;
+    }
+
+    Some(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// The CMAKE_BUILD_TYPE the build directory was configured with
+fn build_profile(build_dir: &Path) -> String {
+    cmake::cache_value(build_dir, "CMAKE_BUILD_TYPE")
+        .filter(|profile| !profile.is_empty())
+        .unwrap_or_else(|| "default".to_string())
+}
+
+/// The current time, or SOURCE_DATE_EPOCH for reproducible builds
+pub fn build_timestamp() -> Result<String> {
+    let seconds = match std::env::var("SOURCE_DATE_EPOCH") {
+        Ok(epoch) => epoch
+            .trim()
+            .parse::<u64>()
+            .context("SOURCE_DATE_EPOCH must be a number of seconds")?,
+        Err(_) => SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .context("System clock is before the epoch")?
+            .as_secs(),
+    };
+
+    Ok(format_timestamp(seconds))
+}
+
+/// Formats seconds since the epoch as an ISO 8601 UTC timestamp
+pub fn format_timestamp(seconds: u64) -> String {
+    let days = (seconds / 86400) as i64;
+    let time = seconds % 86400;
+
+    // Howard Hinnant's days-to-civil algorithm
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + (month <= 2) as i64;
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        time / 3600,
+        time % 3600 / 60,
+        time % 60
+    )
+}
@@ -0,0 +1,259 @@
+use colorize::AnsiColor;
+use std::path::{Path, PathBuf};
+
+use crate::output::{
+    parse_compile_step, parse_diagnostic, parse_failed_step, strip_ansi, Diagnostic,
+};
+
+/// A diagnostic and the lines the compiler printed with it, e.g. the code
+/// snippet and notes
+struct Entry {
+    diagnostic: Diagnostic,
+    context: Vec<String>,
+}
+
+/// Collects the diagnostics and failed steps of a build from its output
+#[derive(Default)]
+pub struct BuildLog {
+    entries: Vec<Entry>,
+    /// Whether the lines that follow belong to the last diagnostic
+    in_diagnostic: bool,
+    /// Sources of the translation units that failed to compile
+    failed: Vec<String>,
+    /// Set when only the first failing translation unit's output is shown
+    focus: Option<Focus>,
+}
+
+/// The output of the build step being run, held back until it's known
+/// whether it failed
+#[derive(Default)]
+struct Focus {
+    block: Vec<String>,
+    failing: bool,
+    /// Whether the first failing step was shown, hiding everything after it
+    shown: bool,
+}
+
+impl Focus {
+    /// Whether a line is shown now, or `None` once the first failing step
+    /// was shown
+    fn observe(&mut self, raw: &str, line: &str) -> Option<bool> {
+        if self.shown {
+            return None;
+        }
+
+        let step = parse_compile_step(line).is_some();
+        let error = parse_diagnostic(line).is_some_and(|diagnostic| diagnostic.error);
+        // Ninja prints a failed step's output after its `FAILED:` line, Make
+        // prints it before a `*** [...] Error 1` line
+        let ninja_failed = line.starts_with("FAILED: ");
+        let make_failed = line.contains("*** [");
+
+        if make_failed {
+            self.block.push(raw.to_string());
+            self.failing = true;
+            self.end_block();
+            return Some(false);
+        }
+        if step || ninja_failed {
+            self.end_block();
+            if self.shown {
+                return None;
+            }
+        }
+        if step {
+            return Some(true);
+        }
+        self.block.push(raw.to_string());
+        self.failing |= ninja_failed || error;
+        Some(false)
+    }
+
+    fn end_block(&mut self) {
+        if self.failing && !self.shown {
+            for line in &self.block {
+                println!("{}", line);
+            }
+            self.shown = true;
+        }
+        self.block.clear();
+        self.failing = false;
+    }
+}
+
+impl BuildLog {
+    /// A log that shows progress and the output of the first translation
+    /// unit that failed to compile, hiding the rest
+    pub fn focused() -> BuildLog {
+        BuildLog {
+            focus: Some(Focus::default()),
+            ..BuildLog::default()
+        }
+    }
+
+    /// Records a line of build output and returns whether it should be shown
+    pub fn observe(&mut self, raw: &str) -> bool {
+        let line = strip_ansi(raw);
+        let show = match &mut self.focus {
+            Some(focus) => match focus.observe(raw, &line) {
+                Some(show) => show,
+                // Past the first failure, which is all that's reported
+                None => return false,
+            },
+            None => true,
+        };
+        self.record(line);
+        show
+    }
+
+    /// Shows the held back output once the build finished
+    pub fn finish(&mut self) {
+        if let Some(focus) = &mut self.focus {
+            focus.end_block();
+        }
+    }
+
+    fn record(&mut self, line: String) {
+        if let Some(diagnostic) = parse_diagnostic(&line) {
+            // Headers report the same diagnostic for every file including them
+            let duplicate = self.entries.iter().any(|entry| {
+                let seen = &entry.diagnostic;
+                seen.file == diagnostic.file
+                    && seen.line == diagnostic.line
+                    && seen.column == diagnostic.column
+                    && seen.message == diagnostic.message
+            });
+            self.in_diagnostic = !duplicate;
+            if !duplicate {
+                self.entries.push(Entry {
+                    diagnostic,
+                    context: Vec::new(),
+                });
+            }
+            return;
+        }
+
+        if let Some((_, file)) = parse_failed_step(&line) {
+            if !self.failed.contains(&file) {
+                self.failed.push(file);
+            }
+            self.in_diagnostic = false;
+            return;
+        }
+
+        let continues = line.starts_with(' ')
+            || line.contains(": note: ")
+            || line.contains(": required from ")
+            || line.contains(": in instantiation of ");
+        let ends = line.starts_with('[')
+            || line.starts_with("ninja: ")
+            || line.starts_with("make")
+            || line.starts_with("In file included from")
+            || line.contains(": In ");
+        if ends || !continues {
+            self.in_diagnostic = false;
+        } else if self.in_diagnostic {
+            if let Some(entry) = self.entries.last_mut() {
+                entry.context.push(line);
+            }
+        }
+    }
+
+    /// Prints the diagnostics grouped by file, the files with errors first,
+    /// each with its first error. `explain` prints every diagnostic with its
+    /// context instead.
+    pub fn print_summary(&self, explain: bool) {
+        let errors = self.entries.iter().filter(|e| e.diagnostic.error).count();
+        let warnings = self.entries.len() - errors;
+
+        let mut files: Vec<(&Path, Vec<&Entry>)> = Vec::new();
+        for entry in &self.entries {
+            let file = entry.diagnostic.file.as_path();
+            match files.iter_mut().find(|(seen, _)| *seen == file) {
+                Some((_, entries)) => entries.push(entry),
+                None => files.push((file, vec![entry])),
+            }
+        }
+        files.sort_by_key(|(_, entries)| !entries.iter().any(|entry| entry.diagnostic.error));
+
+        eprintln!();
+        eprintln!(
+            "{}",
+            format!(
+                "Build failed with {} and {} in {}",
+                plural(errors, "error"),
+                plural(warnings, "warning"),
+                plural(files.len(), "file")
+            )
+            .red()
+            .bold()
+        );
+
+        for (file, entries) in &files {
+            let file_errors = entries.iter().filter(|e| e.diagnostic.error).count();
+            let file_warnings = entries.len() - file_errors;
+            let mut counts = Vec::new();
+            if file_errors > 0 {
+                counts.push(plural(file_errors, "error").red());
+            }
+            if file_warnings > 0 {
+                counts.push(plural(file_warnings, "warning").yellow());
+            }
+            eprintln!("{}: {}", display(file).bold(), counts.join(", "));
+
+            let shown = if explain {
+                entries.clone()
+            } else {
+                entries
+                    .iter()
+                    .find(|entry| entry.diagnostic.error)
+                    .into_iter()
+                    .copied()
+                    .collect()
+            };
+            for entry in shown {
+                let diagnostic = &entry.diagnostic;
+                let location = match diagnostic.column {
+                    Some(column) => format!("{}:{}", diagnostic.line, column),
+                    None => diagnostic.line.to_string(),
+                };
+                let label = match diagnostic.error {
+                    true => "error".red(),
+                    false => "warning".yellow(),
+                };
+                eprintln!("  {} {}: {}", location, label, diagnostic.message);
+                if explain {
+                    for line in &entry.context {
+                        eprintln!("    {}", line.trim_end());
+                    }
+                }
+            }
+        }
+
+        if !self.failed.is_empty() {
+            eprintln!("{} {}", "Failed to compile".red(), self.failed.join(", "));
+        }
+        if !explain && !self.entries.is_empty() {
+            eprintln!(
+                "{}",
+                "Run 'cxx build --explain' to see every diagnostic with its context".grey()
+            );
+        }
+    }
+}
+
+/// Compilers report absolute paths, shown relative to the project when
+/// they are inside it
+fn display(file: &Path) -> String {
+    let relative = std::env::current_dir()
+        .ok()
+        .and_then(|current| file.strip_prefix(current).ok().map(PathBuf::from));
+    relative.as_deref().unwrap_or(file).display().to_string()
+}
+
+fn plural(count: usize, noun: &str) -> String {
+    match count {
+        1 => format!("1 {}", noun),
+        count => format!("{} {}s", count, noun),
+    }
+}
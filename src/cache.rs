@@ -0,0 +1,440 @@
+use anyhow::{Context, Result};
+use colorize::AnsiColor;
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+    process::Command,
+    time::SystemTime,
+};
+
+use crate::{
+    cmake,
+    executor::{executor, shell_quote},
+    format_size,
+    manifest::STATE_DIR,
+};
+
+/// Suffix of the files in the state directory that are caches, as opposed
+/// to history such as build statistics
+const STATE_CACHE_SUFFIX: &str = "_cache.json";
+
+/// Counters of ccache or sccache
+struct CompilerCache {
+    name: &'static str,
+    hits: u64,
+    misses: u64,
+    size: u64,
+    max_size: Option<u64>,
+}
+
+/// Something cxx can delete to free space, e.g. a fetched dependency
+struct Entry {
+    name: String,
+    paths: Vec<PathBuf>,
+    size: u64,
+    modified: SystemTime,
+}
+
+pub fn handle_cache_show(name: String, build_dir: String) -> Result<()> {
+    let (kind, value) = cache_entry(&name, &build_dir)?;
+
+    eprintln!("{}", format!("{} ({})", name, kind).grey());
+    println!("{}", value);
+
+    Ok(())
+}
+
+/// Sets the entry through CMake rather than editing the cache file, so the
+/// project is reconfigured with the new value. Existing entries keep their type.
+pub fn handle_cache_set(name: String, value: String, build_dir: String) -> Result<()> {
+    let definition = match cmake::cache_entry(Path::new(&build_dir), &name) {
+        Some((kind, _)) => format!("{}:{}={}", name, kind, value),
+        None => format!("{}={}", name, value),
+    };
+    executor()
+        .execute(
+            cmake::configure_command(&build_dir, &[])
+                .arg("-D")
+                .arg(definition),
+        )
+        .context("Failed to update CMake cache")?;
+
+    eprintln!("{}", format!("Set {} to '{}'", name, value).green());
+
+    Ok(())
+}
+
+fn cache_entry(name: &str, build_dir: &str) -> Result<(String, String)> {
+    let build_dir = Path::new(build_dir);
+    if !build_dir.join("CMakeCache.txt").exists() {
+        anyhow::bail!(
+            "'{}' isn't configured, run 'cxx init' first",
+            build_dir.display()
+        );
+    }
+
+    cmake::cache_entry(build_dir, name).with_context(|| format!("No cache entry named '{}'", name))
+}
+
+/// Prints the hit rates of ccache and sccache, the size of the dependencies
+/// fetched into the build directories and of cxx's own caches
+pub fn handle_cache_stats(build_dir: String) -> Result<()> {
+    let launcher = cmake::cache_value(Path::new(&build_dir), "CMAKE_CXX_COMPILER_LAUNCHER")
+        .or_else(|| cmake::cache_value(Path::new(&build_dir), "CMAKE_C_COMPILER_LAUNCHER"))
+        .unwrap_or_default();
+
+    println!("{}", "Compiler caches".green());
+    for (name, stats) in [("ccache", ccache_stats()), ("sccache", sccache_stats())] {
+        let Some(stats) = stats else {
+            println!("  {:8} not installed", name);
+            continue;
+        };
+        let lookups = stats.hits + stats.misses;
+        let rate = match lookups {
+            0 => "no compilations yet".to_string(),
+            _ => format!(
+                "{:.1}% hit rate ({} hits, {} misses)",
+                stats.hits as f64 * 100.0 / lookups as f64,
+                stats.hits,
+                stats.misses
+            ),
+        };
+        let size = match stats.max_size {
+            Some(max_size) => format!("{} of {}", format_size(stats.size), format_size(max_size)),
+            None => format_size(stats.size),
+        };
+        let used = if launcher.contains(stats.name) {
+            format!(", used by '{}'", build_dir)
+        } else {
+            String::new()
+        };
+        println!("  {:8} {}, {}{}", stats.name, rate, size, used);
+    }
+
+    println!("{}", "Dependency caches".green());
+    for dir in build_dirs(&build_dir) {
+        let dependencies = dependency_entries(&dir);
+        if dependencies.is_empty() {
+            continue;
+        }
+        println!(
+            "  {:24} {:>10}  ({} dependencies)",
+            dir.join("_deps").display(),
+            format_size(total(&dependencies)),
+            dependencies.len()
+        );
+    }
+
+    println!("{}", format!("State ({})", STATE_DIR).green());
+    let mut state = Vec::new();
+    if let Ok(entries) = fs::read_dir(STATE_DIR) {
+        for entry in entries.filter_map(|entry| entry.ok()) {
+            let path = entry.path();
+            state.push((
+                entry.file_name().to_string_lossy().to_string(),
+                size_of(&path),
+            ));
+        }
+    }
+    state.sort();
+    for (name, size) in &state {
+        println!("  {:24} {:>10}", name, format_size(*size));
+    }
+    println!(
+        "  {:24} {:>10}",
+        "total",
+        format_size(state.iter().map(|(_, size)| size).sum())
+    );
+
+    Ok(())
+}
+
+/// Shrinks every cache to `max_size`: sets ccache's limit and cleans it up,
+/// and deletes the least recently fetched dependencies and cxx's oldest
+/// cache files. sccache only shrinks when its server restarts.
+pub fn handle_cache_prune(max_size: String, build_dir: String) -> Result<()> {
+    let max_size = parse_size(&max_size)?;
+
+    if ccache_stats().is_some() {
+        executor()
+            .execute(
+                Command::new("ccache")
+                    .arg("--max-size")
+                    .arg(format!("{}Ki", max_size / 1024))
+                    .arg("--cleanup"),
+            )
+            .context("Failed to clean up ccache")?;
+    }
+    if let Some(stats) = sccache_stats() {
+        if stats.size > max_size {
+            eprintln!(
+                "{}",
+                format!(
+                    "sccache uses {}, restart its server with SCCACHE_CACHE_SIZE={}K to shrink it",
+                    format_size(stats.size),
+                    max_size / 1024
+                )
+                .yellow()
+            );
+        }
+    }
+
+    let mut freed = 0;
+    for dir in build_dirs(&build_dir) {
+        freed += prune(dependency_entries(&dir), max_size)?;
+    }
+    freed += prune(state_entries(), max_size)?;
+
+    eprintln!(
+        "{}",
+        format!("Pruned caches, freed {}", format_size(freed)).green()
+    );
+
+    Ok(())
+}
+
+/// Deletes the oldest entries until the rest fit `max_size`, returning the
+/// bytes freed
+fn prune(mut entries: Vec<Entry>, max_size: u64) -> Result<u64> {
+    entries.sort_by_key(|entry| entry.modified);
+
+    let mut size = total(&entries);
+    let mut freed = 0;
+    for entry in entries {
+        if size <= max_size {
+            break;
+        }
+        for path in &entry.paths {
+            if executor().dry_run() {
+                eprintln!("rm -rf {}", shell_quote(&path.display().to_string()));
+                continue;
+            }
+            let removed = if path.is_dir() {
+                fs::remove_dir_all(path)
+            } else {
+                fs::remove_file(path)
+            };
+            removed.with_context(|| format!("Failed to remove '{}'", path.display()))?;
+        }
+        eprintln!("Removed {} ({})", entry.name, format_size(entry.size));
+        size -= entry.size;
+        freed += entry.size;
+    }
+
+    Ok(freed)
+}
+
+/// The build directory and the ones `verify` derives from it, e.g.
+/// `build-release`
+pub fn build_dirs(build_dir: &str) -> Vec<PathBuf> {
+    let build_dir = Path::new(build_dir);
+    let mut dirs = vec![build_dir.to_path_buf()];
+
+    let parent = match build_dir.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    let prefix = format!(
+        "{}-",
+        build_dir.file_name().unwrap_or_default().to_string_lossy()
+    );
+    if let Ok(entries) = fs::read_dir(parent) {
+        let mut siblings = entries
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| entry.file_name().to_string_lossy().starts_with(&prefix))
+            .map(|entry| entry.path())
+            .filter(|path| path.join("CMakeCache.txt").exists())
+            .map(|path| {
+                path.strip_prefix(".")
+                    .map(Path::to_path_buf)
+                    .unwrap_or(path)
+            })
+            .collect::<Vec<_>>();
+        siblings.sort();
+        dirs.extend(siblings);
+    }
+
+    dirs
+}
+
+/// The dependencies FetchContent populated, each with its `-src`, `-build`
+/// and `-subbuild` directories
+fn dependency_entries(build_dir: &Path) -> Vec<Entry> {
+    let Ok(entries) = fs::read_dir(build_dir.join("_deps")) else {
+        return Vec::new();
+    };
+
+    let mut dependencies: BTreeMap<String, Entry> = BTreeMap::new();
+    for entry in entries.filter_map(|entry| entry.ok()) {
+        let path = entry.path();
+        let file_name = entry.file_name().to_string_lossy().to_string();
+        let name = ["-src", "-build", "-subbuild"]
+            .iter()
+            .find_map(|suffix| file_name.strip_suffix(suffix))
+            .unwrap_or(&file_name)
+            .to_string();
+        let modified = modified(&path);
+        let size = size_of(&path);
+
+        let dependency = dependencies.entry(name.clone()).or_insert_with(|| Entry {
+            name: format!("{} ({})", name, build_dir.display()),
+            paths: Vec::new(),
+            size: 0,
+            modified,
+        });
+        dependency.paths.push(path);
+        dependency.size += size;
+        dependency.modified = dependency.modified.max(modified);
+    }
+
+    dependencies.into_values().collect()
+}
+
+/// cxx's cache files, e.g. the test and lint results
+fn state_entries() -> Vec<Entry> {
+    let Ok(entries) = fs::read_dir(STATE_DIR) else {
+        return Vec::new();
+    };
+
+    entries
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| {
+            entry
+                .file_name()
+                .to_string_lossy()
+                .ends_with(STATE_CACHE_SUFFIX)
+        })
+        .map(|entry| {
+            let path = entry.path();
+            Entry {
+                name: path.display().to_string(),
+                size: size_of(&path),
+                modified: modified(&path),
+                paths: vec![path],
+            }
+        })
+        .collect()
+}
+
+fn total(entries: &[Entry]) -> u64 {
+    entries.iter().map(|entry| entry.size).sum()
+}
+
+/// The size of a file, or of everything below a directory. Symbolic links
+/// aren't followed.
+fn size_of(path: &Path) -> u64 {
+    let Ok(metadata) = fs::symlink_metadata(path) else {
+        return 0;
+    };
+    if !metadata.is_dir() {
+        return metadata.len();
+    }
+
+    fs::read_dir(path)
+        .map(|entries| {
+            entries
+                .filter_map(|entry| entry.ok())
+                .map(|entry| size_of(&entry.path()))
+                .sum()
+        })
+        .unwrap_or(0)
+}
+
+pub fn modified(path: &Path) -> SystemTime {
+    fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .unwrap_or(SystemTime::UNIX_EPOCH)
+}
+
+/// Parses sizes like `10G`, `512M`, `1.5GiB` or a number of bytes. Units
+/// are binary, so `1K` is 1024 bytes.
+fn parse_size(size: &str) -> Result<u64> {
+    let trimmed = size.trim();
+    let split = trimmed
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(trimmed.len());
+    let (number, unit) = trimmed.split_at(split);
+    let number = number.parse::<f64>().ok();
+    let unit = unit.trim().to_ascii_uppercase();
+    let multiplier = match unit.trim_end_matches('B').trim_end_matches('I') {
+        "" => Some(1u64),
+        "K" => Some(1 << 10),
+        "M" => Some(1 << 20),
+        "G" => Some(1 << 30),
+        "T" => Some(1 << 40),
+        _ => None,
+    };
+
+    match (number, multiplier) {
+        (Some(number), Some(multiplier)) => Ok((number * multiplier as f64) as u64),
+        _ => anyhow::bail!("Invalid size '{}', e.g. 10G, 512M or 100K", size),
+    }
+}
+
+/// ccache's counters from `ccache --print-stats`, `None` when it isn't
+/// installed
+fn ccache_stats() -> Option<CompilerCache> {
+    let output = executor()
+        .capture(Command::new("ccache").arg("--print-stats"))
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+
+    let counters = String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|line| {
+            let (key, value) = line.split_once('\t')?;
+            Some((key.to_string(), value.trim().parse::<u64>().ok()?))
+        })
+        .collect::<BTreeMap<_, _>>();
+    let counter = |key: &str| counters.get(key).copied().unwrap_or(0);
+
+    let max_size = executor()
+        .capture(Command::new("ccache").args(["--get-config", "max_size"]))
+        .ok()
+        .and_then(|output| parse_size(&String::from_utf8_lossy(&output.stdout)).ok())
+        .filter(|max_size| *max_size > 0);
+
+    Some(CompilerCache {
+        name: "ccache",
+        hits: counter("direct_cache_hit") + counter("preprocessed_cache_hit"),
+        misses: counter("cache_miss"),
+        size: counter("cache_size_kibibyte") * 1024,
+        max_size,
+    })
+}
+
+/// sccache's counters from its JSON statistics, `None` when it isn't
+/// installed
+fn sccache_stats() -> Option<CompilerCache> {
+    let output = executor()
+        .capture(Command::new("sccache").args(["--show-stats", "--stats-format", "json"]))
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+
+    let stats: serde_json::Value = serde_json::from_slice(&output.stdout).ok()?;
+    let counts = |key: &str| {
+        stats
+            .get("stats")
+            .and_then(|stats| stats.get(key))
+            .and_then(|counter| counter.get("counts"))
+            .and_then(|counts| counts.as_object())
+            .map(|counts| counts.values().filter_map(|count| count.as_u64()).sum())
+            .unwrap_or(0)
+    };
+
+    Some(CompilerCache {
+        name: "sccache",
+        hits: counts("cache_hits"),
+        misses: counts("cache_misses"),
+        size: stats
+            .get("cache_size")
+            .and_then(|size| size.as_u64())
+            .unwrap_or(0),
+        max_size: stats.get("max_cache_size").and_then(|size| size.as_u64()),
+    })
+}
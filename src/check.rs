@@ -0,0 +1,508 @@
+use anyhow::{Context, Result};
+use colorize::AnsiColor;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs,
+    path::{Path, PathBuf},
+    process::Command,
+    sync::Mutex,
+    thread,
+};
+
+use crate::{
+    compdb, doctest,
+    executor::executor,
+    fileapi, glob, layout, linkmap,
+    manifest::{Manifest, MANIFEST_FILE},
+    sources,
+};
+
+/// Compiles every public header on its own with each compiler and standard
+/// of the `[check.api]` matrix, so the advertised compatibility range holds
+/// and every header includes what it uses
+pub fn handle_check_api(build_dir: String) -> Result<()> {
+    let manifest = Manifest::load()?;
+    let config = &manifest.check.api;
+    if config.standards.is_empty() {
+        anyhow::bail!(
+            "{} declares no standards to check, add them to [check.api], e.g. standards = [\"c++17\", \"c++20\"]",
+            MANIFEST_FILE
+        );
+    }
+
+    let include_dir = layout::include_dir()?;
+    if !Path::new(&include_dir).is_dir() {
+        anyhow::bail!("No '{}' directory with public headers found", include_dir);
+    }
+    let headers = glob::expand(&format!("{}/**/*", include_dir))?
+        .into_iter()
+        .filter(|file| sources::is_header(file))
+        .collect::<Vec<_>>();
+    if headers.is_empty() {
+        anyhow::bail!("No public headers found in '{}'", include_dir);
+    }
+
+    let out_dir = Path::new(&build_dir).join("api_check");
+    fs::create_dir_all(&out_dir).context("Failed to create API check directory")?;
+    let units = headers
+        .iter()
+        .enumerate()
+        .map(|(index, header)| translation_unit(&out_dir, index, header, &include_dir))
+        .collect::<Result<Vec<_>>>()?;
+
+    let mut rows = Vec::new();
+    for standard in &config.standards {
+        let c = !standard.contains("++");
+        let compilers = if config.compilers.is_empty() {
+            vec![doctest::compiler(Path::new(&build_dir), c)]
+        } else {
+            config.compilers.clone()
+        };
+
+        for compiler in compilers {
+            let mut failed = 0;
+            for (header, unit) in headers.iter().zip(&units) {
+                let mut command = Command::new(&compiler);
+                command
+                    .args(["-x", if c { "c" } else { "c++" }])
+                    .arg(format!("-std={}", standard))
+                    .arg(format!("-I{}", include_dir))
+                    .args(&config.flags)
+                    .arg("-fsyntax-only")
+                    .arg(unit);
+
+                let error = match executor().capture(&mut command) {
+                    Ok(output) if output.status.success() => continue,
+                    Ok(output) => String::from_utf8_lossy(&output.stderr).to_string(),
+                    Err(error) => format!("{:#}", error),
+                };
+                failed += 1;
+                eprintln!(
+                    "{} with {} {} ... {}\n{}",
+                    header.display(),
+                    compiler,
+                    standard,
+                    "FAILED".red(),
+                    error.trim_end()
+                );
+            }
+            rows.push((compiler, standard.clone(), failed));
+        }
+    }
+
+    let width = rows
+        .iter()
+        .map(|(compiler, _, _)| compiler.len())
+        .max()
+        .unwrap_or(0);
+    for (compiler, standard, failed) in &rows {
+        let status = if *failed == 0 {
+            "ok".green()
+        } else {
+            format!("{} of {} headers failed", failed, headers.len()).red()
+        };
+        println!(
+            "{:width$}  {:8}  {}",
+            compiler,
+            standard,
+            status,
+            width = width
+        );
+    }
+
+    let failed = rows.iter().filter(|(_, _, failed)| *failed > 0).count();
+    if failed > 0 {
+        anyhow::bail!(
+            "{} of {} compiler and standard combinations failed",
+            failed,
+            rows.len()
+        );
+    }
+    eprintln!(
+        "{}",
+        format!(
+            "All {} public headers compile with every combination",
+            headers.len()
+        )
+        .green()
+    );
+
+    Ok(())
+}
+
+/// A source that only includes the header the way users do, relative to the
+/// include directory
+fn translation_unit(
+    out_dir: &Path,
+    index: usize,
+    header: &Path,
+    include_dir: &str,
+) -> Result<PathBuf> {
+    let include = header.strip_prefix(include_dir).unwrap_or(header);
+    let unit = out_dir.join(format!("header_{}.inc", index));
+
+    fs::write(&unit, format!("#include \"{}\"\n", include.display()))
+        .context("Failed to write API check source")?;
+
+    Ok(unit)
+}
+
+/// A symbol an object file defines, from `nm -P`
+struct Definition {
+    object: usize,
+    weak: bool,
+    size: u64,
+}
+
+/// Scans the object files of the build directory for symbols defined more
+/// than once in what is linked together: duplicate strong definitions, and
+/// inline definitions that differ in size, which hint at a header compiled
+/// differently or two classes sharing a name
+pub fn handle_check_odr(build_dir: String) -> Result<()> {
+    let mut objects = Vec::new();
+    collect_objects(Path::new(&build_dir), &mut objects).context("Failed to list object files")?;
+    // Objects outside of targets, e.g. CMake's compiler checks, aren't linked
+    objects.retain(|object| object_target(object).is_some());
+    objects.sort();
+    if objects.is_empty() {
+        anyhow::bail!(
+            "No object files found in '{}', build the project first",
+            build_dir
+        );
+    }
+
+    // Objects are grouped by the target CMake builds them for, and each
+    // target is checked together with the targets it links
+    let mut target_objects: BTreeMap<String, Vec<usize>> = BTreeMap::new();
+    for (index, object) in objects.iter().enumerate() {
+        target_objects
+            .entry(object_target(object).unwrap_or_default())
+            .or_default()
+            .push(index);
+    }
+    let dependencies = fileapi::dependencies(Path::new(&build_dir)).unwrap_or_default();
+
+    let mut symbols: Vec<BTreeMap<String, (bool, u64)>> = Vec::new();
+    for object in &objects {
+        symbols.push(defined_symbols(object)?);
+    }
+
+    let mut duplicates = BTreeMap::new();
+    let mut mismatched = BTreeMap::new();
+    for target in target_objects.keys() {
+        let mut linked = vec![target.clone()];
+        let mut index = 0;
+        while index < linked.len() {
+            for dependency in dependencies.get(&linked[index]).into_iter().flatten() {
+                if !linked.contains(dependency) {
+                    linked.push(dependency.clone());
+                }
+            }
+            index += 1;
+        }
+
+        let mut definitions: BTreeMap<&str, Vec<Definition>> = BTreeMap::new();
+        for object in linked
+            .iter()
+            .filter_map(|name| target_objects.get(name))
+            .flatten()
+        {
+            for (name, (weak, size)) in &symbols[*object] {
+                definitions.entry(name).or_default().push(Definition {
+                    object: *object,
+                    weak: *weak,
+                    size: *size,
+                });
+            }
+        }
+
+        for (name, definitions) in definitions {
+            let strong = definitions.iter().filter(|definition| !definition.weak);
+            if strong.clone().count() > 1 {
+                duplicates
+                    .entry(name.to_string())
+                    .or_insert_with(BTreeSet::new)
+                    .extend(strong.map(|definition| (definition.object, definition.size)));
+                continue;
+            }
+
+            let weak = definitions
+                .iter()
+                .filter(|definition| definition.weak)
+                .collect::<Vec<_>>();
+            if weak
+                .iter()
+                .any(|definition| definition.size != weak[0].size)
+            {
+                mismatched
+                    .entry(name.to_string())
+                    .or_insert_with(BTreeSet::new)
+                    .extend(
+                        weak.iter()
+                            .map(|definition| (definition.object, definition.size)),
+                    );
+            }
+        }
+    }
+
+    let names = demangle(duplicates.keys().chain(mismatched.keys()));
+    // Each symbol is followed by the objects defining it
+    let describe = |found: &BTreeMap<String, BTreeSet<(usize, u64)>>| {
+        found
+            .iter()
+            .map(|(name, definitions)| {
+                let mut item = names.get(name).unwrap_or(name).clone();
+                for (object, size) in definitions {
+                    item += &format!("\n    {} ({} bytes)", objects[*object].display(), size);
+                }
+                item
+            })
+            .collect::<Vec<_>>()
+    };
+    report("Symbols defined more than once:", &describe(&duplicates));
+    report(
+        "Inline definitions that differ in size:",
+        &describe(&mismatched),
+    );
+
+    let found = duplicates.len() + mismatched.len();
+    if found > 0 {
+        anyhow::bail!("Found {} likely ODR violations", found);
+    }
+    eprintln!(
+        "{}",
+        format!("No ODR violations found in {} object files", objects.len()).green()
+    );
+
+    Ok(())
+}
+
+/// Cross-references the compilation database, the headers each translation
+/// unit includes and the link maps of the build directory to find sources
+/// that aren't built, headers nothing includes and functions the linker
+/// discarded because nothing calls them
+pub fn handle_check_unused(build_dir: String) -> Result<()> {
+    let manifest = Manifest::load()?;
+    let ignore = &manifest.check.unused.ignore;
+    let ignored = |name: &str| ignore.iter().any(|pattern| glob::matches(pattern, name));
+
+    let commands = compdb::load(&build_dir)?;
+    let built = commands
+        .iter()
+        .filter_map(|command| fs::canonicalize(command.directory.join(&command.file)).ok())
+        .collect::<BTreeSet<_>>();
+
+    let jobs = thread::available_parallelism().map_or(1, |jobs| jobs.get());
+    let included = Mutex::new(BTreeSet::new());
+    thread::scope(|scope| {
+        for chunk in commands.chunks(commands.len().div_ceil(jobs).max(1)) {
+            let included = &included;
+            scope.spawn(move || {
+                for command in chunk {
+                    let dependencies = compdb::dependencies(command);
+                    included
+                        .lock()
+                        .expect("included headers lock")
+                        .extend(dependencies);
+                }
+            });
+        }
+    });
+    let included = included.into_inner().expect("included headers lock");
+
+    let mut unbuilt = Vec::new();
+    let mut unincluded = Vec::new();
+    for file in sources::project_sources()? {
+        let name = file.display().to_string();
+        let Ok(path) = fs::canonicalize(&file) else {
+            continue;
+        };
+        if ignored(&name) {
+            continue;
+        }
+        if sources::is_source(&file) && !built.contains(&path) {
+            unbuilt.push(name);
+        } else if sources::is_header(&file) && !included.contains(&path) {
+            unincluded.push(name);
+        }
+    }
+
+    let (functions, note) = unreferenced_functions(&build_dir)?;
+    let names = demangle(functions.iter().map(|(_, symbol)| symbol));
+    let functions = functions
+        .iter()
+        .map(|(source, symbol)| (source, names.get(symbol).unwrap_or(symbol)))
+        .filter(|(source, name)| !ignored(source) && !ignored(name))
+        .map(|(source, name)| format!("{} ({})", name, source))
+        .collect::<Vec<_>>();
+
+    report("Sources that aren't built:", &unbuilt);
+    report("Headers that nothing includes:", &unincluded);
+    report("Functions that nothing calls:", &functions);
+    if let Some(note) = note {
+        eprintln!("{}", note.yellow());
+    }
+
+    let found = unbuilt.len() + unincluded.len() + functions.len();
+    if found > 0 {
+        anyhow::bail!(
+            "Found {} unused files and functions, ignore the intended ones in [check.unused] in {}",
+            found,
+            MANIFEST_FILE
+        );
+    }
+    eprintln!("{}", "No unused files or functions found".green());
+
+    Ok(())
+}
+
+/// `(source, symbol)` pairs of functions
+type Functions = BTreeSet<(String, String)>;
+
+/// The project functions that every link map of
+/// the build directory lists as discarded, and a note when the maps can't
+/// tell which functions are unused
+/// Lists what a check found under its title, nothing when it found nothing
+fn report(title: &str, found: &[String]) {
+    if found.is_empty() {
+        return;
+    }
+    eprintln!("{}", title.to_string().red());
+    for item in found {
+        eprintln!("  {}", item);
+    }
+}
+
+fn unreferenced_functions(build_dir: &str) -> Result<(Functions, Option<String>)> {
+    let maps = fs::read_dir(build_dir)
+        .with_context(|| format!("Failed to read '{}'", build_dir))?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.extension().is_some_and(|extension| extension == "map"))
+        .collect::<Vec<_>>();
+    if maps.is_empty() {
+        return Ok((
+            BTreeSet::new(),
+            Some(
+                "Run 'cxx linkmap' in an optimized build to check for unused functions too"
+                    .to_string(),
+            ),
+        ));
+    }
+
+    let mut linked = BTreeSet::new();
+    let mut discarded = BTreeSet::new();
+    for map in &maps {
+        let text = fs::read_to_string(map)
+            .with_context(|| format!("Failed to read '{}'", map.display()))?;
+        if let Some(sections) = linkmap::function_sections(&text) {
+            linked.extend(sections.linked.into_iter().map(|(_, symbol)| symbol));
+            discarded.extend(
+                sections
+                    .discarded
+                    .into_iter()
+                    .filter_map(|(object, symbol)| {
+                        Some((object_source(Path::new(&object))?, symbol))
+                    }),
+            );
+        }
+    }
+    if discarded.is_empty() {
+        return Ok((
+            BTreeSet::new(),
+            Some("The link maps list no discarded functions, build with -ffunction-sections and -Wl,--gc-sections to check for unused functions too".to_string()),
+        ));
+    }
+
+    // A function another executable links, e.g. a test, is used
+    discarded.retain(|(_, symbol)| !linked.contains(symbol));
+    Ok((discarded, None))
+}
+
+/// The project source an object file is compiled from, e.g. `src/app.cpp`
+/// for `CMakeFiles/app.dir/src/app.cpp.o`. Objects of dependencies fetched
+/// into the build directory aren't the project's.
+fn object_source(object: &Path) -> Option<String> {
+    let object = object.to_str()?;
+    if object.contains("_deps/") {
+        return None;
+    }
+    let (_, source) = object.split_once(".dir/")?;
+    let source = source
+        .strip_suffix(".o")
+        .or_else(|| source.strip_suffix(".obj"))?;
+
+    Some(source.to_string())
+}
+
+fn collect_objects(dir: &Path, objects: &mut Vec<PathBuf>) -> std::io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            collect_objects(&path, objects)?;
+        } else if path
+            .extension()
+            .is_some_and(|extension| extension == "o" || extension == "obj")
+        {
+            objects.push(path);
+        }
+    }
+
+    Ok(())
+}
+
+/// The target an object is built for, from CMake's `CMakeFiles/<target>.dir`
+fn object_target(object: &Path) -> Option<String> {
+    object
+        .components()
+        .filter_map(|component| component.as_os_str().to_str())
+        .find_map(|component| component.strip_suffix(".dir"))
+        .map(str::to_string)
+}
+
+/// The symbols an object defines, with whether they are weak and their size
+fn defined_symbols(object: &Path) -> Result<BTreeMap<String, (bool, u64)>> {
+    let output = executor()
+        .capture(
+            Command::new("nm")
+                .args(["-P", "-S", "--defined-only"])
+                .arg(object),
+        )
+        .with_context(|| format!("Failed to read the symbols of {}", object.display()))?;
+
+    let mut symbols = BTreeMap::new();
+    for line in String::from_utf8_lossy(&output.stdout).lines() {
+        let fields = line.split_whitespace().collect::<Vec<_>>();
+        let [name, kind, _value, size, ..] = fields[..] else {
+            continue;
+        };
+        // Local symbols are lowercase and can't clash across objects
+        let weak = match kind {
+            "T" | "D" | "B" | "R" => false,
+            "W" | "V" | "u" => true,
+            _ => continue,
+        };
+        let size = u64::from_str_radix(size, 16).unwrap_or(0);
+        symbols.insert(name.to_string(), (weak, size));
+    }
+
+    Ok(symbols)
+}
+
+/// Readable names for mangled symbols, falling back to the mangled ones
+/// when c++filt isn't installed
+fn demangle<'a>(names: impl Iterator<Item = &'a String>) -> BTreeMap<String, String> {
+    let names = names.collect::<Vec<_>>();
+    let Ok(output) = executor().capture(Command::new("c++filt").args(&names)) else {
+        return BTreeMap::new();
+    };
+
+    names
+        .into_iter()
+        .cloned()
+        .zip(
+            String::from_utf8_lossy(&output.stdout)
+                .lines()
+                .map(str::to_string),
+        )
+        .collect()
+}
@@ -0,0 +1,433 @@
+use anyhow::{Context, Result};
+use colorize::AnsiColor;
+use std::{fmt::Write, fs, path::Path};
+
+use crate::{
+    backend::GENERATED_MARKER,
+    cmake::CMAKE_LISTS,
+    manifest::{Manifest, MANIFEST_FILE, STATE_DIR},
+};
+
+/// Where CI installs cxx from
+const CXX_REPOSITORY: &str = "https://github.com/dbolivar25/cpp_tools";
+
+/// Image of the Linux jobs on container based providers, with cargo
+const RUST_IMAGE: &str = "rust:1";
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Os {
+    Linux,
+    Mac,
+    Windows,
+}
+
+/// One cell of the build matrix
+pub struct Job {
+    pub os: Os,
+    pub compiler: String,
+    pub profile: String,
+}
+
+/// What every provider's pipeline does, independent of its syntax: each job
+/// installs the tools, then configures, builds and tests the project,
+/// caching the build directory and cxx's state between runs
+pub struct Pipeline {
+    pub build_dir: String,
+    pub jobs: Vec<Job>,
+}
+
+impl Job {
+    /// e.g. `linux-gcc-debug`
+    pub fn name(&self) -> String {
+        let os = match self.os {
+            Os::Linux => "linux",
+            Os::Mac => "macos",
+            Os::Windows => "windows",
+        };
+        format!("{}-{}-{}", os, self.compiler, self.profile)
+    }
+
+    /// The compilers CMake picks up, MSVC is found by the Visual Studio
+    /// generator instead
+    pub fn env(&self) -> Vec<(&'static str, &'static str)> {
+        match self.compiler.as_str() {
+            "gcc" => vec![("CC", "gcc"), ("CXX", "g++")],
+            "clang" => vec![("CC", "clang"), ("CXX", "clang++")],
+            _ => Vec::new(),
+        }
+    }
+
+    /// Installs CMake, Ninja, the compiler and cxx, assuming cargo is
+    /// available. `sudo` is needed on virtual machines but not in containers.
+    pub fn install(&self, sudo: bool) -> Vec<String> {
+        let sudo = if sudo { "sudo " } else { "" };
+        let mut commands = match self.os {
+            Os::Linux => {
+                let compiler = if self.compiler == "clang" {
+                    "clang"
+                } else {
+                    "g++"
+                };
+                vec![
+                    format!("{}apt-get update", sudo),
+                    format!(
+                        "{}apt-get install -y --no-install-recommends cmake ninja-build {}",
+                        sudo, compiler
+                    ),
+                ]
+            }
+            Os::Mac => vec!["brew install cmake ninja".to_string()],
+            Os::Windows => Vec::new(),
+        };
+        commands.push(format!(
+            "cargo install --locked --git {} cxx",
+            CXX_REPOSITORY
+        ));
+        commands
+    }
+
+    pub fn commands(&self, build_dir: &str) -> Vec<String> {
+        let generator = if self.os == Os::Windows {
+            ""
+        } else {
+            " -G Ninja"
+        };
+        vec![
+            format!("cxx init -b {}{} -p {}", build_dir, generator, self.profile),
+            format!("cxx build -b {}", build_dir),
+            format!("cxx test -b {}", build_dir),
+        ]
+    }
+}
+
+impl Pipeline {
+    /// Paths cached between runs, relative to the project root
+    pub fn cache_paths(&self) -> [&str; 2] {
+        [&self.build_dir, STATE_DIR]
+    }
+}
+
+/// A CI service the pipeline can be generated for
+trait Provider {
+    /// The pipeline file, relative to the project root
+    fn path(&self) -> &'static str;
+
+    fn supports(&self, os: Os) -> bool;
+
+    fn render(&self, pipeline: &Pipeline) -> Result<String>;
+}
+
+fn provider(name: &str) -> Result<Box<dyn Provider>> {
+    match name.to_ascii_lowercase().as_str() {
+        "github" => Ok(Box::new(GitHub)),
+        "gitlab" => Ok(Box::new(GitLab)),
+        "azure" => Ok(Box::new(Azure)),
+        "circleci" => Ok(Box::new(CircleCi)),
+        _ => anyhow::bail!("Valid CI providers are 'github', 'gitlab', 'azure' and 'circleci'"),
+    }
+}
+
+/// Expands the manifest's `[ci]` matrix, leaving out combinations that
+/// can't work
+fn pipeline(manifest: &Manifest, build_dir: String) -> Result<Pipeline> {
+    let config = &manifest.ci;
+    let mut jobs = Vec::new();
+    for os in &config.os {
+        let os = match os.to_ascii_lowercase().as_str() {
+            "linux" => Os::Linux,
+            "macos" => Os::Mac,
+            "windows" => Os::Windows,
+            _ => anyhow::bail!("Valid operating systems are 'linux', 'macos' and 'windows'"),
+        };
+        for compiler in &config.compilers {
+            let compiler = compiler.to_ascii_lowercase();
+            let supported = match compiler.as_str() {
+                "gcc" => os == Os::Linux,
+                "clang" => os != Os::Windows,
+                "msvc" => os == Os::Windows,
+                _ => anyhow::bail!("Valid compilers are 'gcc', 'clang' and 'msvc'"),
+            };
+            if !supported {
+                continue;
+            }
+            for profile in &config.profiles {
+                jobs.push(Job {
+                    os,
+                    compiler: compiler.clone(),
+                    profile: profile.clone(),
+                });
+            }
+        }
+    }
+
+    if jobs.is_empty() {
+        anyhow::bail!(
+            "The [ci] matrix in {} has no supported combination, gcc runs on Linux, clang on Linux and macOS and msvc on Windows",
+            MANIFEST_FILE
+        );
+    }
+
+    Ok(Pipeline { build_dir, jobs })
+}
+
+/// Generates a pipeline configuring, building and testing the project on
+/// every combination of the `[ci]` matrix
+pub fn handle_ci(provider_name: String, build_dir: String) -> Result<()> {
+    let provider = provider(&provider_name)?;
+    let manifest = Manifest::load()?;
+    let pipeline = pipeline(&manifest, build_dir)?;
+
+    if let Some(job) = pipeline.jobs.iter().find(|job| !provider.supports(job.os)) {
+        anyhow::bail!(
+            "{} pipelines can't run job '{}', remove its operating system from [ci] in {}",
+            provider_name,
+            job.name(),
+            MANIFEST_FILE
+        );
+    }
+
+    let path = Path::new(provider.path());
+    if fs::read_to_string(path).is_ok_and(|existing| !existing.contains(GENERATED_MARKER)) {
+        anyhow::bail!(
+            "'{}' exists and wasn't generated by cxx, move it aside to generate a pipeline",
+            path.display()
+        );
+    }
+    let contents = format!(
+        "# {} ci {}, do not edit.\n{}",
+        GENERATED_MARKER,
+        provider_name.to_ascii_lowercase(),
+        provider.render(&pipeline)?
+    );
+    if let Some(parent) = path
+        .parent()
+        .filter(|parent| !parent.as_os_str().is_empty())
+    {
+        fs::create_dir_all(parent).context("Failed to create CI configuration directory")?;
+    }
+    fs::write(path, contents).with_context(|| format!("Failed to write {}", path.display()))?;
+
+    eprintln!(
+        "{}",
+        format!(
+            "Generated {} with {} jobs",
+            path.display(),
+            pipeline.jobs.len()
+        )
+        .green()
+    );
+
+    Ok(())
+}
+
+struct GitHub;
+
+impl Provider for GitHub {
+    fn path(&self) -> &'static str {
+        ".github/workflows/ci.yml"
+    }
+
+    fn supports(&self, _os: Os) -> bool {
+        true
+    }
+
+    fn render(&self, pipeline: &Pipeline) -> Result<String> {
+        let mut yaml = "name: CI\non: [push, pull_request]\njobs:\n".to_string();
+        for job in &pipeline.jobs {
+            let runner = match job.os {
+                Os::Linux => "ubuntu-latest",
+                Os::Mac => "macos-latest",
+                Os::Windows => "windows-latest",
+            };
+            writeln!(yaml, "  {}:", job.name())?;
+            writeln!(yaml, "    runs-on: {}", runner)?;
+            write_env(&mut yaml, "    env:", "      ", job)?;
+            writeln!(yaml, "    steps:")?;
+            writeln!(yaml, "      - uses: actions/checkout@v4")?;
+            writeln!(yaml, "      - uses: actions/cache@v4")?;
+            writeln!(yaml, "        with:")?;
+            writeln!(yaml, "          path: |")?;
+            for path in pipeline.cache_paths() {
+                writeln!(yaml, "            {}", path)?;
+            }
+            writeln!(
+                yaml,
+                "          key: {}-${{{{ hashFiles('{}') }}}}",
+                job.name(),
+                CMAKE_LISTS
+            )?;
+            for command in job
+                .install(job.os == Os::Linux)
+                .iter()
+                .chain(&job.commands(&pipeline.build_dir))
+            {
+                writeln!(yaml, "      - run: {}", command)?;
+            }
+        }
+
+        Ok(yaml)
+    }
+}
+
+/// GitLab's shared runners run the jobs in a Rust container
+struct GitLab;
+
+impl Provider for GitLab {
+    fn path(&self) -> &'static str {
+        ".gitlab-ci.yml"
+    }
+
+    fn supports(&self, os: Os) -> bool {
+        os == Os::Linux
+    }
+
+    fn render(&self, pipeline: &Pipeline) -> Result<String> {
+        let mut yaml = "stages:\n  - test\n".to_string();
+        for job in &pipeline.jobs {
+            writeln!(yaml, "\n{}:", job.name())?;
+            writeln!(yaml, "  stage: test")?;
+            writeln!(yaml, "  image: {}", RUST_IMAGE)?;
+            write_env(&mut yaml, "  variables:", "    ", job)?;
+            writeln!(yaml, "  cache:")?;
+            writeln!(yaml, "    key:")?;
+            writeln!(yaml, "      files:")?;
+            writeln!(yaml, "        - {}", CMAKE_LISTS)?;
+            writeln!(yaml, "      prefix: {}", job.name())?;
+            writeln!(yaml, "    paths:")?;
+            for path in pipeline.cache_paths() {
+                writeln!(yaml, "      - {}/", path)?;
+            }
+            writeln!(yaml, "  script:")?;
+            for command in job
+                .install(false)
+                .iter()
+                .chain(&job.commands(&pipeline.build_dir))
+            {
+                writeln!(yaml, "    - {}", command)?;
+            }
+        }
+
+        Ok(yaml)
+    }
+}
+
+/// Azure's hosted agents come with Rust, and with Visual Studio on Windows
+struct Azure;
+
+impl Provider for Azure {
+    fn path(&self) -> &'static str {
+        "azure-pipelines.yml"
+    }
+
+    fn supports(&self, _os: Os) -> bool {
+        true
+    }
+
+    fn render(&self, pipeline: &Pipeline) -> Result<String> {
+        let mut yaml = "trigger:\n  - '*'\njobs:\n".to_string();
+        for job in &pipeline.jobs {
+            let image = match job.os {
+                Os::Linux => "ubuntu-latest",
+                Os::Mac => "macOS-latest",
+                Os::Windows => "windows-latest",
+            };
+            // Job names can't contain dashes
+            writeln!(yaml, "  - job: {}", job.name().replace('-', "_"))?;
+            writeln!(yaml, "    pool:")?;
+            writeln!(yaml, "      vmImage: {}", image)?;
+            write_env(&mut yaml, "    variables:", "      ", job)?;
+            writeln!(yaml, "    steps:")?;
+            for path in pipeline.cache_paths() {
+                writeln!(yaml, "      - task: Cache@2")?;
+                writeln!(yaml, "        inputs:")?;
+                writeln!(
+                    yaml,
+                    "          key: '\"{}\" | \"{}\" | {}'",
+                    job.name(),
+                    path,
+                    CMAKE_LISTS
+                )?;
+                writeln!(yaml, "          path: {}", path)?;
+            }
+            for command in job
+                .install(job.os == Os::Linux)
+                .iter()
+                .chain(&job.commands(&pipeline.build_dir))
+            {
+                writeln!(yaml, "      - script: {}", command)?;
+            }
+        }
+
+        Ok(yaml)
+    }
+}
+
+/// CircleCI runs Linux jobs in a Rust container and macOS ones on Xcode
+/// machines, which need Rust installed
+struct CircleCi;
+
+impl Provider for CircleCi {
+    fn path(&self) -> &'static str {
+        ".circleci/config.yml"
+    }
+
+    fn supports(&self, os: Os) -> bool {
+        os != Os::Windows
+    }
+
+    fn render(&self, pipeline: &Pipeline) -> Result<String> {
+        let mut yaml = "version: 2.1\njobs:\n".to_string();
+        for job in &pipeline.jobs {
+            let cache_key = format!("{}-{{{{ checksum \"{}\" }}}}", job.name(), CMAKE_LISTS);
+            writeln!(yaml, "  {}:", job.name())?;
+            let mut install = job.install(false);
+            if job.os == Os::Mac {
+                writeln!(yaml, "    macos:")?;
+                writeln!(yaml, "      xcode: 15.4.0")?;
+                install.insert(0, "brew install rust".to_string());
+            } else {
+                writeln!(yaml, "    docker:")?;
+                writeln!(yaml, "      - image: {}", RUST_IMAGE)?;
+            }
+            write_env(&mut yaml, "    environment:", "      ", job)?;
+            writeln!(yaml, "    steps:")?;
+            writeln!(yaml, "      - checkout")?;
+            writeln!(yaml, "      - restore_cache:")?;
+            writeln!(yaml, "          keys:")?;
+            writeln!(yaml, "            - {}", cache_key)?;
+            for command in install.iter().chain(&job.commands(&pipeline.build_dir)) {
+                writeln!(yaml, "      - run: {}", command)?;
+            }
+            writeln!(yaml, "      - save_cache:")?;
+            writeln!(yaml, "          key: {}", cache_key)?;
+            writeln!(yaml, "          paths:")?;
+            for path in pipeline.cache_paths() {
+                writeln!(yaml, "            - {}", path)?;
+            }
+        }
+
+        writeln!(yaml, "workflows:")?;
+        writeln!(yaml, "  ci:")?;
+        writeln!(yaml, "    jobs:")?;
+        for job in &pipeline.jobs {
+            writeln!(yaml, "      - {}", job.name())?;
+        }
+
+        Ok(yaml)
+    }
+}
+
+/// Writes the job's compiler variables under `key`, if it has any
+fn write_env(yaml: &mut String, key: &str, indent: &str, job: &Job) -> Result<()> {
+    let env = job.env();
+    if env.is_empty() {
+        return Ok(());
+    }
+
+    writeln!(yaml, "{}", key)?;
+    for (name, value) in env {
+        writeln!(yaml, "{}{}: {}", indent, name, value)?;
+    }
+
+    Ok(())
+}
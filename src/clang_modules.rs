@@ -0,0 +1,70 @@
+use anyhow::Result;
+use std::path::Path;
+
+use crate::cmake;
+
+/// Cache variable `--clang-modules` turns on
+pub const CACHE_VARIABLE: &str = "CPP_TOOLS_CLANG_MODULES";
+
+const CLANG_MODULES_FRAGMENT: &str = "clang_modules.cmake";
+
+/// Every project header becomes a submodule of one module named after the
+/// project, in a module map written to the build directory only when it
+/// changes, so reconfiguring doesn't invalidate the module cache. Compilers
+/// other than Clang build without modules.
+const FRAGMENT: &str = "# Generated by cxx, do not edit.
+# Builds project headers as Clang header modules when CPP_TOOLS_CLANG_MODULES is on
+if(NOT CPP_TOOLS_CLANG_MODULES)
+  return()
+endif()
+
+get_property(clang_modules_languages GLOBAL PROPERTY ENABLED_LANGUAGES)
+if(\"CXX\" IN_LIST clang_modules_languages)
+  set(clang_modules_compiler ${CMAKE_CXX_COMPILER_ID})
+else()
+  set(clang_modules_compiler ${CMAKE_C_COMPILER_ID})
+endif()
+if(NOT clang_modules_compiler MATCHES \"Clang\" OR MSVC)
+  message(WARNING \"Clang header modules need Clang, building without them\")
+  return()
+endif()
+
+file(GLOB_RECURSE clang_modules_headers CONFIGURE_DEPENDS
+  ${CMAKE_CURRENT_SOURCE_DIR}/include/*.h ${CMAKE_CURRENT_SOURCE_DIR}/include/*.hh
+  ${CMAKE_CURRENT_SOURCE_DIR}/include/*.hpp ${CMAKE_CURRENT_SOURCE_DIR}/include/*.hxx
+  ${CMAKE_CURRENT_SOURCE_DIR}/src/*.h ${CMAKE_CURRENT_SOURCE_DIR}/src/*.hh
+  ${CMAKE_CURRENT_SOURCE_DIR}/src/*.hpp ${CMAKE_CURRENT_SOURCE_DIR}/src/*.hxx)
+list(SORT clang_modules_headers)
+string(MAKE_C_IDENTIFIER ${PROJECT_NAME} clang_modules_name)
+set(clang_modules_map \"module ${clang_modules_name} {\\n\")
+foreach(clang_modules_header ${clang_modules_headers})
+  file(RELATIVE_PATH clang_modules_relative ${CMAKE_CURRENT_SOURCE_DIR} ${clang_modules_header})
+  string(MAKE_C_IDENTIFIER ${clang_modules_relative} clang_modules_submodule)
+  string(APPEND clang_modules_map
+    \"  module ${clang_modules_submodule} {\\n\"
+    \"    header \\\"${clang_modules_header}\\\"\\n\"
+    \"    export *\\n\"
+    \"  }\\n\")
+endforeach()
+string(APPEND clang_modules_map \"}\\n\")
+set(clang_modules_map_file ${CMAKE_CURRENT_BINARY_DIR}/module.modulemap)
+file(CONFIGURE OUTPUT ${clang_modules_map_file} CONTENT \"${clang_modules_map}\" @ONLY)
+
+set(clang_modules_options
+  -fmodules
+  -fmodule-map-file=${clang_modules_map_file}
+  -fmodules-cache-path=${CMAKE_CURRENT_BINARY_DIR}/module-cache)
+get_property(clang_modules_targets DIRECTORY PROPERTY BUILDSYSTEM_TARGETS)
+foreach(clang_modules_target ${clang_modules_targets})
+  get_target_property(clang_modules_type ${clang_modules_target} TYPE)
+  if(clang_modules_type MATCHES \"^(EXECUTABLE|STATIC_LIBRARY|SHARED_LIBRARY|MODULE_LIBRARY|OBJECT_LIBRARY)$\")
+    target_compile_options(${clang_modules_target} PRIVATE
+      \"$<$<COMPILE_LANGUAGE:C,CXX>:${clang_modules_options}>\")
+  endif()
+endforeach()
+";
+
+/// Writes the fragment that builds with Clang header modules
+pub fn write_fragment(root_dir: &Path) -> Result<()> {
+    cmake::write_fragment_in(root_dir, CLANG_MODULES_FRAGMENT, FRAGMENT)
+}
@@ -0,0 +1,149 @@
+use anyhow::{Context, Result};
+use colorize::AnsiColor;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use crate::{backend, cmake, executor::executor, handle_init_project, variants, ConfigureArgs};
+
+/// Removes what the build produced. By default the build tool cleans the
+/// whole build directory, and with `target` only that target's objects and
+/// outputs, keeping the configuration either way. `deep` deletes the build
+/// and executable directories altogether, CMake cache included, then
+/// configures the build directory again with its generator and profile.
+pub fn handle_clean(
+    build_dir: String,
+    runtime_dir: String,
+    deep: bool,
+    target: Option<String>,
+) -> Result<()> {
+    cmake::enter_project_root()?;
+    if deep {
+        return clean_deep(&build_dir, &runtime_dir);
+    }
+
+    let mut command = match backend::native(Path::new(".")) {
+        Some(backend) => backend.clean_command(target.as_deref())?,
+        None => {
+            if cmake::cache_value(Path::new(&build_dir), "CMAKE_HOME_DIRECTORY").is_none() {
+                anyhow::bail!("'{}' isn't configured, there's nothing to clean", build_dir);
+            }
+            match &target {
+                Some(target) => clean_target_command(&build_dir, target)?,
+                None => {
+                    let mut command = cmake::build_command(&build_dir, None, None);
+                    command.args(["--target", "clean"]);
+                    command
+                }
+            }
+        }
+    };
+    executor()
+        .execute(&mut command)
+        .context("Failed to clean the build")?;
+
+    let message = match target {
+        Some(target) => format!("Cleaned target '{}' in '{}'", target, build_dir),
+        None => format!("Cleaned '{}'", build_dir),
+    };
+    eprintln!("{}", message.green());
+
+    Ok(())
+}
+
+/// Deletes the build and executable directories and configures the build
+/// directory again as it was. Only the built-in profiles are recognized
+/// from the cache, builds with a manifest profile are configured without.
+fn clean_deep(build_dir: &str, runtime_dir: &str) -> Result<()> {
+    let configured = cmake::cache_value(Path::new(build_dir), "CMAKE_HOME_DIRECTORY").is_some()
+        || backend::native(Path::new(".")).is_some();
+    let configure = match configured {
+        true => variants::configure_like(build_dir),
+        false => ConfigureArgs::default(),
+    };
+    // The executable directory of a project cxx didn't generate is up to it
+    let mut dirs = vec![build_dir];
+    if !cmake::is_foreign_project() {
+        dirs.push(runtime_dir);
+    }
+
+    let dirs = dirs
+        .into_iter()
+        .filter(|dir| Path::new(dir).exists())
+        .collect::<Vec<_>>();
+    // A directory set to `.` or a parent, e.g. by `-b .` or a manifest's
+    // `dirs.runtime`, would take the sources with it
+    let root = fs::canonicalize(".").context("Failed to resolve the project root")?;
+    for dir in &dirs {
+        let path = fs::canonicalize(dir).with_context(|| format!("Failed to resolve '{}'", dir))?;
+        if root.starts_with(&path) {
+            anyhow::bail!("Refusing to remove '{}', it contains the project", dir);
+        }
+    }
+
+    for dir in dirs {
+        if executor().dry_run() {
+            eprintln!("Would remove '{}'", dir);
+            continue;
+        }
+        fs::remove_dir_all(dir).with_context(|| format!("Failed to remove '{}'", dir))?;
+        eprintln!("Removed '{}'", dir);
+    }
+    if configured {
+        handle_init_project(".", build_dir, false, &configure)?;
+    }
+
+    eprintln!(
+        "{}",
+        format!("Cleaned '{}' from scratch", build_dir).green()
+    );
+
+    Ok(())
+}
+
+/// Cleans one target with the generator's own per-target clean: Ninja's
+/// clean tool, or the clean rule the Makefile generator writes into each
+/// target's directory. Other generators only clean everything.
+fn clean_target_command(build_dir: &str, target: &str) -> Result<Command> {
+    let generator = cmake::cache_value(Path::new(build_dir), "CMAKE_GENERATOR").unwrap_or_default();
+    let mut command = cmake::build_command(build_dir, None, None);
+    if generator.starts_with("Ninja") {
+        command.args(["--", "-t", "clean", target]);
+        return Ok(command);
+    }
+    if generator != "Unix Makefiles" && generator != "MinGW Makefiles" {
+        anyhow::bail!(
+            "The {} generator can't clean a single target, clean everything without --target",
+            generator
+        );
+    }
+
+    let target_dir = target_dir(Path::new(build_dir), target)
+        .with_context(|| format!("No target '{}' in '{}'", target, build_dir))?;
+    let rule = target_dir
+        .strip_prefix(build_dir)
+        .unwrap_or(&target_dir)
+        .join("clean");
+
+    command.args(["--", "-f", "CMakeFiles/Makefile2"]).arg(rule);
+
+    Ok(command)
+}
+
+/// The `CMakeFiles/<TARGET>.dir` of a target, in the build directory of
+/// the source directory that declares it
+fn target_dir(dir: &Path, target: &str) -> Option<PathBuf> {
+    let candidate = dir.join("CMakeFiles").join(format!("{}.dir", target));
+    if candidate.is_dir() {
+        return Some(candidate);
+    }
+
+    fs::read_dir(dir)
+        .ok()?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| path.is_dir() && !path.ends_with("CMakeFiles"))
+        .find_map(|path| target_dir(&path, target))
+}
@@ -0,0 +1,346 @@
+use anyhow::{Context, Result};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use crate::manifest::STATE_DIR;
+
+/// The project's top level CMake file
+pub const CMAKE_LISTS: &str = "CMakeLists.txt";
+
+/// Directory holding the CMake fragments cxx generates
+pub const FRAGMENT_DIR: &str = "cmake";
+
+/// Writes a generated CMake fragment and makes sure CMakeLists.txt includes
+/// it. Fragments are included at the end, after the project target exists.
+pub fn write_fragment(name: &str, contents: &str) -> Result<()> {
+    write_fragment_in(Path::new("."), name, contents)
+}
+
+/// Writes a fragment into the project rooted at `root_dir`
+pub fn write_fragment_in(root_dir: &Path, name: &str, contents: &str) -> Result<()> {
+    let path = format!("{}/{}", FRAGMENT_DIR, name);
+
+    fs::create_dir_all(root_dir.join(FRAGMENT_DIR))
+        .context("Failed to create CMake fragment directory")?;
+    fs::write(root_dir.join(&path), contents)
+        .with_context(|| format!("Failed to write {}", path))?;
+
+    include_fragment(root_dir, &path)
+}
+
+/// Deletes a generated CMake fragment and its include from CMakeLists.txt
+pub fn remove_fragment(name: &str) -> Result<()> {
+    let path = format!("{}/{}", FRAGMENT_DIR, name);
+    if Path::new(&path).exists() {
+        fs::remove_file(&path).with_context(|| format!("Failed to remove {}", path))?;
+    }
+
+    let include = format!("include(${{CMAKE_CURRENT_SOURCE_DIR}}/{})", path);
+    let lists = fs::read_to_string(CMAKE_LISTS)
+        .with_context(|| format!("Failed to read {}", CMAKE_LISTS))?;
+    if !lists.lines().any(|line| line.trim() == include) {
+        return Ok(());
+    }
+    let updated = lists
+        .lines()
+        .filter(|line| line.trim() != include)
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    fs::write(CMAKE_LISTS, updated + "\n")
+        .with_context(|| format!("Failed to update {}", CMAKE_LISTS))
+}
+
+fn include_fragment(root_dir: &Path, path: &str) -> Result<()> {
+    let lists_path = root_dir.join(CMAKE_LISTS);
+    if !lists_path.exists() {
+        anyhow::bail!("No {} found, run this from the project root", CMAKE_LISTS);
+    }
+
+    let include = format!("include(${{CMAKE_CURRENT_SOURCE_DIR}}/{})", path);
+    let mut lists = fs::read_to_string(&lists_path)
+        .with_context(|| format!("Failed to read {}", CMAKE_LISTS))?;
+
+    if lists.lines().any(|line| line.trim() == include) {
+        return Ok(());
+    }
+
+    if !lists.ends_with('\n') {
+        lists.push('\n');
+    }
+    lists.push_str(&include);
+    lists.push('\n');
+
+    fs::write(&lists_path, lists).with_context(|| format!("Failed to update {}", CMAKE_LISTS))
+}
+
+/// Whether the project has a CMakeLists.txt cxx didn't generate, which
+/// doesn't list its sources in `SOURCE_FILES`
+pub fn is_foreign_project() -> bool {
+    fs::read_to_string(CMAKE_LISTS).is_ok_and(|lists| {
+        !lists
+            .lines()
+            .any(|line| line.trim().starts_with("set(SOURCE_FILES"))
+    })
+}
+
+/// Changes into the nearest directory with a CMakeLists.txt declaring a
+/// project(), so commands work from subdirectories of any CMake project
+pub fn enter_project_root() -> Result<()> {
+    let current = std::env::current_dir().context("Failed to get current directory")?;
+    if let Some(root) = project_root_of(&current).filter(|root| *root != current) {
+        std::env::set_current_dir(&root)
+            .with_context(|| format!("Failed to change into '{}'", root.display()))?;
+    }
+
+    Ok(())
+}
+
+/// The root of the CMake project `dir` is in, as `enter_project_root`
+/// finds it
+pub fn project_root_of(dir: &Path) -> Option<PathBuf> {
+    if dir.join(CMAKE_LISTS).exists() {
+        return Some(dir.to_path_buf());
+    }
+
+    dir.ancestors()
+        .skip(1)
+        .find(|dir| {
+            fs::read_to_string(dir.join(CMAKE_LISTS)).is_ok_and(|lists| {
+                lists
+                    .lines()
+                    .any(|line| line.trim().starts_with("project("))
+            })
+        })
+        .map(Path::to_path_buf)
+}
+
+/// Where `init` records a build directory outside the project, in the state
+/// directory
+const BUILD_DIR_RECORD: &str = "build_dir";
+
+/// Records the build directory when it's outside the project, e.g. on a
+/// local disk for a source tree on a network mount, so other commands find
+/// it without `--build-dir`. A build directory inside the project clears
+/// the record.
+pub fn record_build_dir(root_dir: &Path, build_dir: &str) -> Result<()> {
+    let record = root_dir.join(STATE_DIR).join(BUILD_DIR_RECORD);
+    let root = root_dir
+        .canonicalize()
+        .with_context(|| format!("Failed to resolve '{}'", root_dir.display()))?;
+    let build = root_dir
+        .join(build_dir)
+        .canonicalize()
+        .with_context(|| format!("Failed to resolve '{}'", build_dir))?;
+
+    if build.starts_with(&root) {
+        if record.exists() {
+            fs::remove_file(&record).context("Failed to remove the recorded build directory")?;
+        }
+        return Ok(());
+    }
+
+    fs::create_dir_all(root_dir.join(STATE_DIR)).context("Failed to create state directory")?;
+    fs::write(&record, format!("{}\n", build.display()))
+        .context("Failed to record the build directory")
+}
+
+/// The build directory `init` recorded for the project at `root`, read from
+/// the root only so a project nested in another doesn't get the outer one's
+pub fn recorded_build_dir(root: &Path) -> Option<String> {
+    let build_dir = fs::read_to_string(root.join(STATE_DIR).join(BUILD_DIR_RECORD)).ok()?;
+
+    Some(build_dir.trim().to_string()).filter(|build_dir| !build_dir.is_empty())
+}
+
+/// Reads the name from the project() call in CMakeLists.txt
+pub fn project_name() -> Result<String> {
+    project_name_in(Path::new("."))
+}
+
+/// The name of the project() call of another project, e.g. a workspace member
+pub fn project_name_in(root_dir: &Path) -> Result<String> {
+    let path = root_dir.join(CMAKE_LISTS);
+    let lists =
+        fs::read_to_string(&path).with_context(|| format!("Failed to read {}", path.display()))?;
+
+    lists
+        .lines()
+        .find_map(|line| line.trim().strip_prefix("project("))
+        .and_then(|rest| rest.split([' ', ')']).next())
+        .filter(|name| !name.is_empty())
+        .map(|name| name.to_string())
+        .with_context(|| format!("No project() call found in {}", path.display()))
+}
+
+/// The header directory a generated project puts on the include path, by
+/// its `include_directories()` call
+pub fn include_dir_in(root_dir: &Path) -> Option<String> {
+    let lists = fs::read_to_string(root_dir.join(CMAKE_LISTS)).ok()?;
+
+    lists.lines().find_map(|line| {
+        line.trim()
+            .strip_prefix("include_directories(")?
+            .strip_suffix(')')
+            .map(|dir| dir.trim_start_matches("./").to_string())
+    })
+}
+
+/// Whether the project() call declares C without C++
+pub fn is_c_project() -> Result<bool> {
+    let lists = fs::read_to_string(CMAKE_LISTS)
+        .with_context(|| format!("Failed to read {}", CMAKE_LISTS))?;
+
+    let languages = lists
+        .lines()
+        .find_map(|line| line.trim().strip_prefix("project("))
+        .map(|rest| {
+            rest.trim_end_matches(')')
+                .split_whitespace()
+                .skip(1)
+                .collect::<Vec<_>>()
+        })
+        .unwrap_or_default();
+
+    Ok(languages.contains(&"C") && !languages.contains(&"CXX"))
+}
+
+/// The `SOURCE_FILES` list of a generated CMakeLists.txt
+pub fn source_files() -> Result<Vec<String>> {
+    let lists = fs::read_to_string(CMAKE_LISTS)
+        .with_context(|| format!("Failed to read {}", CMAKE_LISTS))?;
+
+    Ok(lists
+        .lines()
+        .find_map(|line| line.trim().strip_prefix("set(SOURCE_FILES"))
+        .map(|rest| {
+            rest.trim_end_matches(')')
+                .split_whitespace()
+                .map(|file| file.trim_start_matches("./").to_string())
+                .collect()
+        })
+        .unwrap_or_default())
+}
+
+/// The executable directory CMakeLists.txt sets, relative to the project root
+pub fn runtime_output_dir() -> Result<Option<String>> {
+    let lists = fs::read_to_string(CMAKE_LISTS)
+        .with_context(|| format!("Failed to read {}", CMAKE_LISTS))?;
+
+    Ok(lists.lines().find_map(|line| {
+        line.trim()
+            .strip_prefix("set(CMAKE_RUNTIME_OUTPUT_DIRECTORY ${CMAKE_CURRENT_SOURCE_DIR}/")?
+            .strip_suffix(')')
+            .map(|dir| dir.to_string())
+    }))
+}
+
+/// Whether CMakeLists.txt builds the project target as a library
+pub fn is_library_project() -> Result<bool> {
+    let name = project_name()?;
+    let lists = fs::read_to_string(CMAKE_LISTS)
+        .with_context(|| format!("Failed to read {}", CMAKE_LISTS))?;
+
+    Ok(lists.lines().any(|line| {
+        line.trim()
+            .strip_prefix("add_library(")
+            .is_some_and(|rest| rest.split([' ', ')']).next() == Some(name.as_str()))
+    }))
+}
+
+/// Whether CMakeLists.txt builds the project target as an INTERFACE
+/// library, i.e. a header-only one without sources of its own
+pub fn is_interface_library() -> Result<bool> {
+    let name = project_name()?;
+    let lists = fs::read_to_string(CMAKE_LISTS)
+        .with_context(|| format!("Failed to read {}", CMAKE_LISTS))?;
+
+    Ok(lists.lines().any(|line| {
+        line.trim()
+            .strip_prefix("add_library(")
+            .is_some_and(|rest| {
+                rest.split([' ', ')'])
+                    .filter(|word| !word.is_empty())
+                    .take(2)
+                    .eq([name.as_str(), "INTERFACE"])
+            })
+    }))
+}
+
+/// Appends a source file to the `SOURCE_FILES` list of a generated
+/// CMakeLists.txt, returning false when the project doesn't have one
+pub fn add_source_file(path: &str) -> Result<bool> {
+    let lists = fs::read_to_string(CMAKE_LISTS)
+        .with_context(|| format!("Failed to read {}", CMAKE_LISTS))?;
+
+    let mut found = false;
+    let updated = lists
+        .lines()
+        .map(|line| match line.trim().strip_prefix("set(SOURCE_FILES") {
+            Some(rest) if !found && rest.ends_with(')') => {
+                found = true;
+                let sources = rest.trim_end_matches(')').trim();
+                match sources {
+                    "" => format!("set(SOURCE_FILES {})", path),
+                    _ => format!("set(SOURCE_FILES {} {})", sources, path),
+                }
+            }
+            _ => line.to_string(),
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    if found {
+        fs::write(CMAKE_LISTS, updated + "\n")
+            .with_context(|| format!("Failed to update {}", CMAKE_LISTS))?;
+    }
+
+    Ok(found)
+}
+
+/// `cmake` configuring the project in the current directory into a build
+/// directory, setting cache variables
+pub fn configure_command(build_dir: &str, variables: &[(&str, &str)]) -> Command {
+    let mut command = Command::new("cmake");
+    command.args(["-S", ".", "-B", build_dir]);
+    for (variable, value) in variables {
+        command.arg("-D").arg(format!("{}={}", variable, value));
+    }
+
+    command
+}
+
+/// `cmake --build` building a build directory, in the configuration of
+/// multi-config generators
+pub fn build_command(build_dir: &str, config: Option<&str>, jobs: Option<usize>) -> Command {
+    let mut command = Command::new("cmake");
+    command.args(["--build", build_dir]);
+    if let Some(config) = config {
+        command.args(["--config", config]);
+    }
+    if let Some(jobs) = jobs {
+        command.args(["--parallel", &jobs.to_string()]);
+    }
+
+    command
+}
+
+/// Reads an entry such as `CMAKE_CXX_COMPILER:FILEPATH=/usr/bin/c++` from the
+/// CMake cache of a configured build directory
+pub fn cache_value(build_dir: &Path, key: &str) -> Option<String> {
+    cache_entry(build_dir, key).map(|(_, value)| value)
+}
+
+/// The type and value of a cache entry
+pub fn cache_entry(build_dir: &Path, key: &str) -> Option<(String, String)> {
+    let cache = fs::read_to_string(build_dir.join("CMakeCache.txt")).ok()?;
+
+    cache.lines().find_map(|line| {
+        let (name, value) = line.split_once('=')?;
+        let (name, kind) = name.split_once(':')?;
+        (name == key).then(|| (kind.to_string(), value.to_string()))
+    })
+}
@@ -0,0 +1,319 @@
+use anyhow::{Context, Result};
+use colorize::AnsiColor;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use crate::{backend::GENERATED_MARKER, executor::executor};
+
+/// Formatters tried in order, all taking `-i` to format in place and
+/// `--check` to only report
+const FORMATTERS: &[&str] = &["gersemi", "cmake-format"];
+
+/// Commands whose arguments up to the matching end are indented a level
+const BLOCK_OPEN: &[&str] = &["if", "foreach", "while", "function", "macro", "block"];
+const BLOCK_MIDDLE: &[&str] = &["elseif", "else"];
+const BLOCK_CLOSE: &[&str] = &[
+    "endif",
+    "endforeach",
+    "endwhile",
+    "endfunction",
+    "endmacro",
+    "endblock",
+];
+
+const INDENT: &str = "  ";
+
+/// Formats the project's `CMakeLists.txt` and `.cmake` files with gersemi or
+/// cmake-format when installed, otherwise with a basic formatter that fixes
+/// indentation, command case and blank lines. `check` fails when a file
+/// isn't formatted instead of formatting it.
+pub fn handle_fmt_cmake(check: bool) -> Result<()> {
+    let files = cmake_files(false);
+    if files.is_empty() {
+        return Ok(());
+    }
+
+    match FORMATTERS.iter().find(|tool| has_tool(tool)) {
+        Some(tool) => format_with(tool, &files, check),
+        None => format_basic(&files, check),
+    }
+}
+
+fn format_with(tool: &str, files: &[PathBuf], check: bool) -> Result<()> {
+    if !check {
+        executor()
+            .execute(Command::new(tool).arg("-i").args(files))
+            .with_context(|| format!("Failed to format CMake files with {}", tool))?;
+        return Ok(());
+    }
+
+    let output = executor()
+        .capture(Command::new(tool).arg("--check").args(files))
+        .with_context(|| format!("Failed to check CMake files with {}", tool))?;
+    if !output.status.success() {
+        eprint!("{}", String::from_utf8_lossy(&output.stdout));
+        eprint!("{}", String::from_utf8_lossy(&output.stderr));
+        anyhow::bail!("CMake files aren't formatted, run 'cxx fmt-cmake'");
+    }
+
+    Ok(())
+}
+
+fn format_basic(files: &[PathBuf], check: bool) -> Result<()> {
+    let mut changed = Vec::new();
+    for file in files {
+        let source = fs::read_to_string(file)
+            .with_context(|| format!("Failed to read {}", file.display()))?;
+        let formatted = format_source(&source);
+        if formatted != source {
+            changed.push((file, formatted));
+        }
+    }
+
+    if check {
+        if changed.is_empty() {
+            return Ok(());
+        }
+        for (file, _) in &changed {
+            eprintln!("{} isn't formatted", file.display());
+        }
+        anyhow::bail!(
+            "{} CMake files aren't formatted, run 'cxx fmt-cmake'",
+            changed.len()
+        );
+    }
+
+    for (file, formatted) in &changed {
+        if executor().dry_run() {
+            eprintln!("Would format {}", file.display());
+            continue;
+        }
+        fs::write(file, formatted)
+            .with_context(|| format!("Failed to write {}", file.display()))?;
+    }
+    if !changed.is_empty() && !executor().dry_run() {
+        eprintln!(
+            "{}",
+            format!("Formatted {} CMake files", changed.len()).green()
+        );
+    }
+
+    Ok(())
+}
+
+/// Indents commands by block nesting and arguments continued on the next
+/// lines by their parentheses, lowercases command names and collapses blank
+/// lines. Multi-line strings and bracket arguments are kept as they are.
+fn format_source(source: &str) -> String {
+    let mut formatted = String::new();
+    let mut scanner = Scanner::default();
+    let mut depth = 0usize;
+    let mut statement_indent = 0usize;
+    let mut blank = false;
+
+    for line in source.lines() {
+        if scanner.in_string() {
+            formatted.push_str(line);
+            formatted.push('\n');
+            scanner.scan(line);
+            continue;
+        }
+
+        let trimmed = line.trim_start();
+        if trimmed.is_empty() {
+            blank = true;
+            continue;
+        }
+        if blank && !formatted.is_empty() {
+            formatted.push('\n');
+        }
+        blank = false;
+
+        let (indent, content) = if scanner.parens > 0 {
+            let closing = usize::from(trimmed.starts_with(')'));
+            (
+                statement_indent + scanner.parens - closing,
+                trimmed.to_string(),
+            )
+        } else {
+            match command_name(trimmed) {
+                Some((name, rest)) => {
+                    let name = name.to_ascii_lowercase();
+                    if BLOCK_CLOSE.contains(&name.as_str()) || BLOCK_MIDDLE.contains(&name.as_str())
+                    {
+                        depth = depth.saturating_sub(1);
+                    }
+                    statement_indent = depth;
+                    if BLOCK_OPEN.contains(&name.as_str()) || BLOCK_MIDDLE.contains(&name.as_str())
+                    {
+                        depth += 1;
+                    }
+                    (statement_indent, format!("{}{}", name, rest))
+                }
+                None => (depth, trimmed.to_string()),
+            }
+        };
+
+        scanner.scan(&content);
+        formatted.push_str(&INDENT.repeat(indent));
+        // Whitespace at the end of a string spanning lines is part of it
+        match scanner.in_string() {
+            true => formatted.push_str(&content),
+            false => formatted.push_str(content.trim_end()),
+        }
+        formatted.push('\n');
+    }
+
+    formatted
+}
+
+/// The command a line starts with and the rest from its opening parenthesis
+fn command_name(line: &str) -> Option<(&str, &str)> {
+    let end = line
+        .find(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+        .unwrap_or(line.len());
+    let (name, rest) = line.split_at(end);
+    let rest = rest.trim_start();
+    (!name.is_empty() && rest.starts_with('(')).then_some((name, rest))
+}
+
+/// Follows the parentheses, quoted strings and bracket arguments and
+/// comments, e.g. `[=[ ... ]=]`, across lines
+#[derive(Default)]
+struct Scanner {
+    parens: usize,
+    quoted: bool,
+    /// The number of `=` of the open bracket
+    bracket: Option<usize>,
+}
+
+impl Scanner {
+    fn in_string(&self) -> bool {
+        self.quoted || self.bracket.is_some()
+    }
+
+    fn scan(&mut self, line: &str) {
+        let mut rest = line;
+        while !rest.is_empty() {
+            if let Some(level) = self.bracket {
+                let close = format!("]{}]", "=".repeat(level));
+                match rest.find(&close) {
+                    Some(index) => {
+                        rest = &rest[index + close.len()..];
+                        self.bracket = None;
+                    }
+                    None => return,
+                }
+                continue;
+            }
+
+            let mut chars = rest.char_indices();
+            let Some((_, c)) = chars.next() else {
+                return;
+            };
+            let next = chars.next().map(|(index, _)| index).unwrap_or(rest.len());
+            if self.quoted {
+                match c {
+                    // Skip the escaped character
+                    '\\' => {
+                        let escaped = &rest[next..];
+                        rest = escaped
+                            .char_indices()
+                            .nth(1)
+                            .map_or("", |(index, _)| &escaped[index..]);
+                    }
+                    '"' => {
+                        self.quoted = false;
+                        rest = &rest[next..];
+                    }
+                    _ => rest = &rest[next..],
+                }
+                continue;
+            }
+
+            match c {
+                '#' => match bracket_level(&rest[1..]) {
+                    Some(level) => {
+                        self.bracket = Some(level);
+                        rest = &rest[level + 3..];
+                    }
+                    None => return,
+                },
+                '[' => match bracket_level(rest) {
+                    Some(level) => {
+                        self.bracket = Some(level);
+                        rest = &rest[level + 2..];
+                    }
+                    None => rest = &rest[next..],
+                },
+                '"' => {
+                    self.quoted = true;
+                    rest = &rest[next..];
+                }
+                '(' => {
+                    self.parens += 1;
+                    rest = &rest[next..];
+                }
+                ')' => {
+                    self.parens = self.parens.saturating_sub(1);
+                    rest = &rest[next..];
+                }
+                _ => rest = &rest[next..],
+            }
+        }
+    }
+}
+
+/// The number of `=` of a bracket opening the text, e.g. 1 for `[=[`
+fn bracket_level(text: &str) -> Option<usize> {
+    let rest = text.strip_prefix('[')?;
+    let level = rest.chars().take_while(|c| *c == '=').count();
+    rest[level..].starts_with('[').then_some(level)
+}
+
+/// The project's `CMakeLists.txt` and `.cmake` files, outside hidden
+/// directories and configured build trees, with the ones cxx generates when
+/// `generated` is set
+pub fn cmake_files(generated: bool) -> Vec<PathBuf> {
+    let mut files = Vec::new();
+    collect(Path::new("."), generated, &mut files);
+    files.sort();
+    files
+}
+
+fn collect(dir: &Path, include_generated: bool, files: &mut Vec<PathBuf>) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+
+    for entry in entries.filter_map(|entry| entry.ok()) {
+        let path = entry.path();
+        let name = entry.file_name().to_string_lossy().to_string();
+        if name.starts_with('.') {
+            continue;
+        }
+
+        if path.is_dir() {
+            if !path.join("CMakeCache.txt").exists() {
+                collect(&path, include_generated, files);
+            }
+            continue;
+        }
+
+        let cmake = name == "CMakeLists.txt" || name.ends_with(".cmake");
+        let generated =
+            || fs::read_to_string(&path).is_ok_and(|source| source.contains(GENERATED_MARKER));
+        if cmake && (include_generated || !generated()) {
+            files.push(path.strip_prefix(".").unwrap_or(&path).to_path_buf());
+        }
+    }
+}
+
+fn has_tool(tool: &str) -> bool {
+    executor()
+        .capture(Command::new(tool).arg("--version"))
+        .is_ok()
+}
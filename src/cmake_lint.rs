@@ -0,0 +1,363 @@
+use anyhow::{Context, Result};
+use std::{fs, path::PathBuf, process::Command};
+
+use crate::{cmake_format::cmake_files, executor::executor, manifest::CmakeLintConfig};
+
+/// Commands CMake deprecated, with what replaces them
+const DEPRECATED_COMMANDS: &[(&str, &str)] = &[
+    (
+        "build_name",
+        "the CMAKE_SYSTEM and CMAKE_CXX_COMPILER variables",
+    ),
+    ("exec_program", "execute_process()"),
+    ("export_library_dependencies", "install(EXPORT) or export()"),
+    ("install_files", "install(FILES)"),
+    ("install_programs", "install(PROGRAMS)"),
+    ("install_targets", "install(TARGETS)"),
+    ("load_command", "a function or macro"),
+    ("make_directory", "file(MAKE_DIRECTORY)"),
+    ("output_required_files", "the compiler's dependency output"),
+    ("qt_wrap_cpp", "the Qt modules' own commands"),
+    ("qt_wrap_ui", "the Qt modules' own commands"),
+    ("remove", "list(REMOVE_ITEM)"),
+    ("subdir_depends", "target dependencies"),
+    ("subdirs", "add_subdirectory()"),
+    (
+        "use_mangled_mesa",
+        "nothing, Mesa is found like other libraries",
+    ),
+    (
+        "utility_source",
+        "add_executable() and add_custom_command()",
+    ),
+    ("variable_requires", "if()"),
+    ("write_file", "file(WRITE)"),
+];
+
+/// Prefixes of variables CMake and its modules read, set without being
+/// referenced in the project
+const CONFIGURATION_PREFIXES: &[&str] = &["CMAKE_", "CPACK_", "CTEST_", "FETCHCONTENT_", "BUILD_"];
+
+/// A problem in a CMake file, printed like compiler diagnostics
+struct Finding {
+    file: PathBuf,
+    line: usize,
+    check: &'static str,
+    message: String,
+}
+
+/// A command invocation in a CMake file
+struct Invocation {
+    name: String,
+    line: usize,
+    arguments: Vec<String>,
+}
+
+/// Lints the project's CMake files, the generated ones included, with
+/// cmake-lint when installed and otherwise with built-in checks for
+/// deprecated commands, uppercase command names and unused variables.
+/// Prints what it finds and returns how many problems there are.
+pub fn lint_cmake(config: &CmakeLintConfig) -> Result<usize> {
+    let files = cmake_files(true);
+    if files.is_empty() {
+        return Ok(0);
+    }
+
+    if has_tool("cmake-lint") {
+        return cmake_lint(&files, config);
+    }
+
+    let mut parsed = Vec::new();
+    for file in files {
+        let text = fs::read_to_string(&file)
+            .with_context(|| format!("Failed to read {}", file.display()))?;
+        parsed.push((file, invocations(&text)));
+    }
+
+    let findings = check(&parsed)
+        .into_iter()
+        .filter(|finding| !config.suppress.iter().any(|check| check == finding.check))
+        .collect::<Vec<_>>();
+    for finding in &findings {
+        eprintln!(
+            "{}:{}: [{}] {}",
+            finding.file.display(),
+            finding.line,
+            finding.check,
+            finding.message
+        );
+    }
+
+    Ok(findings.len())
+}
+
+/// Runs cmake-lint, passing the suppressed checks that are its codes on
+fn cmake_lint(files: &[PathBuf], config: &CmakeLintConfig) -> Result<usize> {
+    let codes = config
+        .suppress
+        .iter()
+        .filter(|code| {
+            code.len() == 5
+                && code.starts_with(['C', 'E', 'R', 'W'])
+                && code[1..].chars().all(|c| c.is_ascii_digit())
+        })
+        .collect::<Vec<_>>();
+
+    let mut command = Command::new("cmake-lint");
+    command.arg("--suppress-decorations");
+    if !codes.is_empty() {
+        command.arg("--disabled-codes").args(&codes);
+    }
+    let output = executor()
+        .capture(command.arg("--").args(files))
+        .context("Failed to run cmake-lint")?;
+
+    let findings = String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter(|line| line.contains(": ["))
+        .inspect(|line| eprintln!("{}", line))
+        .count();
+    if !output.status.success() && findings == 0 {
+        anyhow::bail!(
+            "cmake-lint failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+
+    Ok(findings)
+}
+
+fn check(files: &[(PathBuf, Vec<Invocation>)]) -> Vec<Finding> {
+    let mut findings = Vec::new();
+
+    for (file, invocations) in files {
+        for invocation in invocations {
+            let name = invocation.name.to_ascii_lowercase();
+            if let Some((_, replacement)) = DEPRECATED_COMMANDS
+                .iter()
+                .find(|(deprecated, _)| *deprecated == name)
+            {
+                findings.push(Finding {
+                    file: file.clone(),
+                    line: invocation.line,
+                    check: "deprecated-command",
+                    message: format!("{}() is deprecated, use {}", name, replacement),
+                });
+            }
+
+            let uppercase = invocation.name.chars().any(|c| c.is_ascii_alphabetic())
+                && !invocation.name.chars().any(|c| c.is_ascii_lowercase());
+            if uppercase {
+                findings.push(Finding {
+                    file: file.clone(),
+                    line: invocation.line,
+                    check: "command-case",
+                    message: format!("Write {}() in lowercase", name),
+                });
+            }
+        }
+    }
+
+    // Variables are shared between the files, e.g. set in one fragment and
+    // read in CMakeLists.txt
+    let all = files
+        .iter()
+        .flat_map(|(file, invocations)| invocations.iter().map(move |i| (file, i)))
+        .collect::<Vec<_>>();
+    for (file, invocation) in &all {
+        let Some(variable) = set_variable(invocation) else {
+            continue;
+        };
+        let referenced = all.iter().any(|(_, other)| {
+            !std::ptr::eq(*other, *invocation)
+                && other
+                    .arguments
+                    .iter()
+                    .any(|argument| references(argument, variable))
+        });
+        if !referenced {
+            findings.push(Finding {
+                file: (*file).clone(),
+                line: invocation.line,
+                check: "unused-variable",
+                message: format!("{} is set but never used", variable),
+            });
+        }
+    }
+
+    findings.sort_by(|a, b| (&a.file, a.line).cmp(&(&b.file, b.line)));
+    findings
+}
+
+/// The variable a plain `set()` defines. Cache variables, ones set for the
+/// caller and ones CMake reads itself are left out.
+fn set_variable(invocation: &Invocation) -> Option<&str> {
+    if !invocation.name.eq_ignore_ascii_case("set") {
+        return None;
+    }
+    let (variable, values) = invocation.arguments.split_first()?;
+    let exported = values
+        .iter()
+        .any(|value| value == "CACHE" || value == "PARENT_SCOPE");
+    let configuration = CONFIGURATION_PREFIXES
+        .iter()
+        .any(|prefix| variable.starts_with(prefix));
+    let plain = variable
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || c == '_');
+
+    (!exported && !configuration && plain).then_some(variable)
+}
+
+/// Whether an argument reads a variable, by `${NAME}` or by its bare name,
+/// e.g. in `if(NAME)` or `list(APPEND NAME ...)`
+fn references(argument: &str, variable: &str) -> bool {
+    argument == variable || argument.contains(&format!("${{{}}}", variable))
+}
+
+/// The commands a CMake file invokes, with their arguments unquoted.
+/// Comments and bracket comments are skipped.
+fn invocations(text: &str) -> Vec<Invocation> {
+    let chars = text.chars().collect::<Vec<_>>();
+    let mut invocations = Vec::new();
+    let mut line = 1;
+    let mut index = 0;
+
+    // Skips a bracket starting at `index`, e.g. `[=[ ... ]=]`, returning
+    // its contents
+    let bracket = |index: &mut usize, line: &mut usize| -> Option<String> {
+        let start = *index;
+        let level = chars[start + 1..].iter().take_while(|c| **c == '=').count();
+        if chars.get(start + 1 + level) != Some(&'[') {
+            return None;
+        }
+        let close = format!("]{}]", "=".repeat(level))
+            .chars()
+            .collect::<Vec<_>>();
+        let mut end = start + level + 2;
+        while end < chars.len() && !chars[end..].starts_with(&close) {
+            end += 1;
+        }
+        let contents = chars[start + level + 2..end].iter().collect::<String>();
+        *line += contents.matches('\n').count();
+        *index = (end + close.len()).min(chars.len());
+        Some(contents)
+    };
+
+    while index < chars.len() {
+        let c = chars[index];
+        if c == '\n' {
+            line += 1;
+            index += 1;
+        } else if c == '#' {
+            index += 1;
+            if chars.get(index) == Some(&'[') && bracket(&mut index, &mut line).is_some() {
+                continue;
+            }
+            while index < chars.len() && chars[index] != '\n' {
+                index += 1;
+            }
+        } else if c.is_ascii_alphabetic() || c == '_' {
+            let start = index;
+            while index < chars.len()
+                && (chars[index].is_ascii_alphanumeric() || chars[index] == '_')
+            {
+                index += 1;
+            }
+            let name = chars[start..index].iter().collect::<String>();
+            while index < chars.len() && matches!(chars[index], ' ' | '\t') {
+                index += 1;
+            }
+            if chars.get(index) != Some(&'(') {
+                continue;
+            }
+
+            let invocation_line = line;
+            let mut arguments = Vec::new();
+            let mut current = String::new();
+            let mut depth = 0;
+            index += 1;
+            while index < chars.len() {
+                let c = chars[index];
+                match c {
+                    '"' => {
+                        index += 1;
+                        while index < chars.len() && chars[index] != '"' {
+                            if chars[index] == '\\' && index + 1 < chars.len() {
+                                current.push(chars[index]);
+                                index += 1;
+                            }
+                            if chars[index] == '\n' {
+                                line += 1;
+                            }
+                            current.push(chars[index]);
+                            index += 1;
+                        }
+                        index += 1;
+                        continue;
+                    }
+                    '[' => {
+                        if let Some(contents) = bracket(&mut index, &mut line) {
+                            current.push_str(&contents);
+                            continue;
+                        }
+                        current.push(c);
+                    }
+                    '#' => {
+                        index += 1;
+                        if chars.get(index) == Some(&'[')
+                            && bracket(&mut index, &mut line).is_some()
+                        {
+                            continue;
+                        }
+                        while index < chars.len() && chars[index] != '\n' {
+                            index += 1;
+                        }
+                        continue;
+                    }
+                    '(' => {
+                        depth += 1;
+                        current.push(c);
+                    }
+                    ')' if depth == 0 => {
+                        index += 1;
+                        break;
+                    }
+                    ')' => {
+                        depth -= 1;
+                        current.push(c);
+                    }
+                    c if c.is_whitespace() => {
+                        if c == '\n' {
+                            line += 1;
+                        }
+                        if !current.is_empty() {
+                            arguments.push(std::mem::take(&mut current));
+                        }
+                    }
+                    c => current.push(c),
+                }
+                index += 1;
+            }
+            if !current.is_empty() {
+                arguments.push(current);
+            }
+
+            invocations.push(Invocation {
+                name,
+                line: invocation_line,
+                arguments,
+            });
+        } else {
+            index += 1;
+        }
+    }
+
+    invocations
+}
+
+fn has_tool(tool: &str) -> bool {
+    executor()
+        .capture(Command::new(tool).arg("--version"))
+        .is_ok()
+}
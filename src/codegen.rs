@@ -0,0 +1,121 @@
+use anyhow::Result;
+use colorize::AnsiColor;
+use std::fs;
+
+use crate::cmake;
+
+enum Generator {
+    Protobuf,
+    Flatbuffers,
+}
+
+pub fn handle_add_codegen(generator: String, schema_dir: String) -> Result<()> {
+    let generator = match generator.to_ascii_lowercase().as_str() {
+        "protobuf" | "proto" => Generator::Protobuf,
+        "flatbuffers" | "flatc" => Generator::Flatbuffers,
+        _ => {
+            anyhow::bail!("Valid code generators are 'protobuf' and 'flatbuffers'");
+        }
+    };
+
+    if !fs::metadata(&schema_dir).is_ok_and(|metadata| metadata.is_dir()) {
+        anyhow::bail!("Schema directory '{}' does not exist", schema_dir);
+    }
+    // The generated sources are compiled into the project target
+    if cmake::is_interface_library()? {
+        anyhow::bail!(
+            "Header-only libraries have no sources to compile generated code into, add code generation to a project that builds an executable or a compiled library"
+        );
+    }
+
+    let schema_dir = schema_dir.trim_end_matches('/');
+    let (fragment, contents) = match generator {
+        Generator::Protobuf => ("codegen_protobuf.cmake", protobuf_fragment(schema_dir)),
+        Generator::Flatbuffers => (
+            "codegen_flatbuffers.cmake",
+            flatbuffers_fragment(schema_dir),
+        ),
+    };
+
+    cmake::write_fragment(fragment, &contents)?;
+
+    eprintln!(
+        "{}",
+        format!(
+            "Added code generation for schemas in '{}', re-run init to pick it up",
+            schema_dir
+        )
+        .green()
+    );
+
+    Ok(())
+}
+
+/// Generated sources live in the build tree and are regenerated whenever
+/// their schema changes, so they can never go stale
+fn protobuf_fragment(schema_dir: &str) -> String {
+    format!(
+        "# Generated by cxx add codegen, do not edit.
+find_package(Protobuf REQUIRED)
+
+set(PROTO_SCHEMA_DIR ${{CMAKE_CURRENT_SOURCE_DIR}}/{schema_dir})
+set(PROTO_GENERATED_DIR ${{CMAKE_CURRENT_BINARY_DIR}}/generated/proto)
+file(GLOB_RECURSE PROTO_SCHEMAS CONFIGURE_DEPENDS ${{PROTO_SCHEMA_DIR}}/*.proto)
+file(MAKE_DIRECTORY ${{PROTO_GENERATED_DIR}})
+
+set(PROTO_SOURCES)
+foreach(schema ${{PROTO_SCHEMAS}})
+  file(RELATIVE_PATH schema_path ${{PROTO_SCHEMA_DIR}} ${{schema}})
+  string(REGEX REPLACE \"\\\\.proto$\" \"\" schema_stem ${{schema_path}})
+  set(generated
+    ${{PROTO_GENERATED_DIR}}/${{schema_stem}}.pb.cc
+    ${{PROTO_GENERATED_DIR}}/${{schema_stem}}.pb.h)
+  add_custom_command(
+    OUTPUT ${{generated}}
+    COMMAND protobuf::protoc --cpp_out=${{PROTO_GENERATED_DIR}} -I ${{PROTO_SCHEMA_DIR}} ${{schema}}
+    DEPENDS ${{schema}} protobuf::protoc
+    COMMENT \"Generating C++ sources from ${{schema_path}}\")
+  list(APPEND PROTO_SOURCES ${{generated}})
+endforeach()
+
+target_sources(${{PROJECT_NAME}} PRIVATE ${{PROTO_SOURCES}})
+target_include_directories(${{PROJECT_NAME}} PRIVATE ${{PROTO_GENERATED_DIR}})
+target_link_libraries(${{PROJECT_NAME}} PRIVATE protobuf::libprotobuf)
+"
+    )
+}
+
+/// flatc only generates headers, so a custom target orders generation
+/// before the project's own sources compile
+fn flatbuffers_fragment(schema_dir: &str) -> String {
+    format!(
+        "# Generated by cxx add codegen, do not edit.
+find_package(flatbuffers CONFIG REQUIRED)
+find_program(FLATC_EXECUTABLE flatc REQUIRED)
+
+set(FBS_SCHEMA_DIR ${{CMAKE_CURRENT_SOURCE_DIR}}/{schema_dir})
+set(FBS_GENERATED_DIR ${{CMAKE_CURRENT_BINARY_DIR}}/generated/flatbuffers)
+file(GLOB_RECURSE FBS_SCHEMAS CONFIGURE_DEPENDS ${{FBS_SCHEMA_DIR}}/*.fbs)
+file(MAKE_DIRECTORY ${{FBS_GENERATED_DIR}})
+
+set(FBS_HEADERS)
+foreach(schema ${{FBS_SCHEMAS}})
+  file(RELATIVE_PATH schema_path ${{FBS_SCHEMA_DIR}} ${{schema}})
+  get_filename_component(schema_subdir ${{schema_path}} DIRECTORY)
+  get_filename_component(schema_name ${{schema}} NAME_WE)
+  set(generated ${{FBS_GENERATED_DIR}}/${{schema_subdir}}/${{schema_name}}_generated.h)
+  add_custom_command(
+    OUTPUT ${{generated}}
+    COMMAND ${{FLATC_EXECUTABLE}} --cpp -o ${{FBS_GENERATED_DIR}}/${{schema_subdir}} -I ${{FBS_SCHEMA_DIR}} ${{schema}}
+    DEPENDS ${{schema}}
+    COMMENT \"Generating C++ headers from ${{schema_path}}\")
+  list(APPEND FBS_HEADERS ${{generated}})
+endforeach()
+
+add_custom_target(${{PROJECT_NAME}}_flatbuffers DEPENDS ${{FBS_HEADERS}})
+add_dependencies(${{PROJECT_NAME}} ${{PROJECT_NAME}}_flatbuffers)
+target_include_directories(${{PROJECT_NAME}} PRIVATE ${{FBS_GENERATED_DIR}})
+target_link_libraries(${{PROJECT_NAME}} PRIVATE flatbuffers::flatbuffers)
+"
+    )
+}
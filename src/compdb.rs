@@ -0,0 +1,129 @@
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use crate::executor::executor;
+
+/// The compilation database CMake exports into the build directory
+pub const COMPILE_COMMANDS: &str = "compile_commands.json";
+
+#[derive(Deserialize)]
+struct Entry {
+    directory: PathBuf,
+    file: PathBuf,
+    command: Option<String>,
+    arguments: Option<Vec<String>>,
+}
+
+/// How one translation unit is compiled
+pub struct CompileCommand {
+    pub directory: PathBuf,
+    pub file: PathBuf,
+    pub arguments: Vec<String>,
+}
+
+pub fn load(build_dir: &str) -> Result<Vec<CompileCommand>> {
+    let path = Path::new(build_dir).join(COMPILE_COMMANDS);
+    let text = fs::read_to_string(&path).with_context(|| {
+        format!(
+            "Failed to read '{}', run 'cxx init' to generate it",
+            path.display()
+        )
+    })?;
+    let entries: Vec<Entry> = serde_json::from_str(&text)
+        .with_context(|| format!("Failed to parse '{}'", path.display()))?;
+
+    Ok(entries
+        .into_iter()
+        .map(|entry| CompileCommand {
+            arguments: entry
+                .arguments
+                .unwrap_or_else(|| split_command(entry.command.as_deref().unwrap_or_default())),
+            directory: entry.directory,
+            file: entry.file,
+        })
+        .collect())
+}
+
+/// The files a translation unit reads, from the compiler's `-MM` dependency
+/// output, canonicalized. Empty when the compiler fails.
+pub fn dependencies(command: &CompileCommand) -> Vec<PathBuf> {
+    let Some(mut compile) = compiler_command(command, None) else {
+        return Vec::new();
+    };
+    compile.arg("-MM");
+
+    let Ok(output) = executor().capture(&mut compile) else {
+        return Vec::new();
+    };
+    if !output.status.success() {
+        return Vec::new();
+    }
+
+    let text = String::from_utf8_lossy(&output.stdout).replace("\\\n", " ");
+    let dependencies = text
+        .split_once(": ")
+        .map(|(_, dependencies)| dependencies)
+        .unwrap_or_default();
+    dependencies
+        .split_whitespace()
+        .filter_map(|dependency| fs::canonicalize(command.directory.join(dependency)).ok())
+        .collect()
+}
+
+/// The command compiling a translation unit without writing the object or
+/// dependency files, in its directory, to run it with other outputs such as
+/// `-E`. `compiler` replaces the one in the database.
+pub fn compiler_command(command: &CompileCommand, compiler: Option<&str>) -> Option<Command> {
+    let mut arguments = command.arguments.iter();
+    let database_compiler = arguments.next()?;
+
+    let mut compile = Command::new(compiler.unwrap_or(database_compiler));
+    while let Some(argument) = arguments.next() {
+        match argument.as_str() {
+            "-c" | "-MD" | "-MMD" => {}
+            "-o" | "-MF" | "-MT" | "-MQ" => {
+                arguments.next();
+            }
+            _ => {
+                compile.arg(argument);
+            }
+        }
+    }
+    compile.current_dir(&command.directory);
+
+    Some(compile)
+}
+
+/// Splits a shell command line, honoring quotes and backslash escapes
+pub fn split_command(command: &str) -> Vec<String> {
+    let mut arguments = Vec::new();
+    let mut current: Option<String> = None;
+    let mut quote: Option<char> = None;
+    let mut chars = command.chars();
+
+    while let Some(c) = chars.next() {
+        match (c, quote) {
+            ('\\', Some('\'')) => current.get_or_insert_with(String::new).push(c),
+            ('\\', _) => {
+                if let Some(next) = chars.next() {
+                    current.get_or_insert_with(String::new).push(next);
+                }
+            }
+            ('\'' | '"', None) => {
+                quote = Some(c);
+                current.get_or_insert_with(String::new);
+            }
+            (c, Some(open)) if c == open => quote = None,
+            (c, None) if c.is_whitespace() => arguments.extend(current.take()),
+            (c, _) => current.get_or_insert_with(String::new).push(c),
+        }
+    }
+    arguments.extend(current);
+
+    arguments
+}
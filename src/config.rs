@@ -0,0 +1,648 @@
+use anyhow::{Context, Result};
+use colorize::AnsiColor;
+use serde::Deserialize;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    executor::DEFAULT_SHELL,
+    manifest::{parse_toml, Manifest, MANIFEST_FILE},
+};
+
+/// Name of the per-user configuration file
+pub const CONFIG_FILE: &str = "config.toml";
+
+/// Settings shared by all projects of a user
+#[derive(Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct GlobalConfig {
+    /// Shell that command lines are run with, called as `<shell> -c <command>`,
+    /// or `cmd /C <command>`
+    pub shell: String,
+    /// Directory the build directories of projects are kept under, one per
+    /// project and worktree, instead of `build` inside each project
+    pub build_root: Option<String>,
+}
+
+impl Default for GlobalConfig {
+    fn default() -> Self {
+        GlobalConfig {
+            shell: DEFAULT_SHELL.to_string(),
+            build_root: None,
+        }
+    }
+}
+
+impl GlobalConfig {
+    /// Loads the user's configuration, falling back to the defaults when
+    /// there is none. `CPP_TOOLS_<KEY>` environment variables take precedence
+    /// over the file.
+    pub fn load() -> Result<GlobalConfig> {
+        let mut config = GlobalConfig::load_file()?;
+
+        if let Ok(shell) = std::env::var("CPP_TOOLS_SHELL") {
+            config.shell = shell;
+        }
+        if let Ok(build_root) = std::env::var("CPP_TOOLS_BUILD_ROOT") {
+            config.build_root = Some(build_root).filter(|root| !root.is_empty());
+        }
+
+        Ok(config)
+    }
+
+    fn load_file() -> Result<GlobalConfig> {
+        let Some(path) = config_path() else {
+            return Ok(GlobalConfig::default());
+        };
+        if fs::metadata(&path).is_err() {
+            return Ok(GlobalConfig::default());
+        }
+
+        let contents = fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+
+        parse_toml(&path.display().to_string(), &contents)
+    }
+}
+
+/// `$XDG_CONFIG_HOME/cpp_tools/config.toml`, or `~/.config/cpp_tools/config.toml`
+pub fn config_path() -> Option<PathBuf> {
+    let config_home = std::env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| Path::new(&home).join(".config")))?;
+
+    Some(config_home.join("cpp_tools").join(CONFIG_FILE))
+}
+
+/// Where a configuration key is stored
+#[derive(Clone, Copy, PartialEq)]
+pub enum Scope {
+    /// The per-user configuration file
+    Global,
+    /// The project manifest
+    Project,
+}
+
+impl Scope {
+    fn name(self) -> &'static str {
+        match self {
+            Scope::Global => "global",
+            Scope::Project => "project",
+        }
+    }
+
+    fn path(self) -> Result<PathBuf> {
+        match self {
+            Scope::Global => config_path().context("Neither XDG_CONFIG_HOME nor HOME is set"),
+            Scope::Project => Ok(PathBuf::from(MANIFEST_FILE)),
+        }
+    }
+
+    /// Loads the file the way the rest of cxx does, to catch invalid edits
+    fn validate(self) -> Result<()> {
+        match self {
+            Scope::Global => GlobalConfig::load_file().map(|_| ()),
+            Scope::Project => Manifest::load().map(|_| ()),
+        }
+    }
+}
+
+enum Kind {
+    String,
+    Bool,
+    Choice(&'static [&'static str]),
+}
+
+/// A key that `config` can read and edit
+struct Key {
+    /// Dotted path of the key, `*` matching any name
+    name: &'static str,
+    scope: Scope,
+    kind: Kind,
+    default: Option<&'static str>,
+    description: &'static str,
+}
+
+const KEYS: &[Key] = &[
+    Key {
+        name: "shell",
+        scope: Scope::Global,
+        kind: Kind::String,
+        default: Some(DEFAULT_SHELL),
+        description: "Shell command lines are run with",
+    },
+    Key {
+        name: "build_root",
+        scope: Scope::Global,
+        kind: Kind::String,
+        default: None,
+        description: "Directory build directories are kept under, one per project and worktree",
+    },
+    Key {
+        name: "package.name",
+        scope: Scope::Project,
+        kind: Kind::String,
+        default: None,
+        description: "Project name, defaults to the directory name",
+    },
+    Key {
+        name: "package.version",
+        scope: Scope::Project,
+        kind: Kind::String,
+        default: None,
+        description: "Project version",
+    },
+    Key {
+        name: "package.language",
+        scope: Scope::Project,
+        kind: Kind::Choice(&["c", "cpp"]),
+        default: None,
+        description: "Project language",
+    },
+    Key {
+        name: "package.standard",
+        scope: Scope::Project,
+        kind: Kind::String,
+        default: None,
+        description: "Language standard init configures, e.g. c++23",
+    },
+    Key {
+        name: "package.layout",
+        scope: Scope::Project,
+        kind: Kind::Choice(&["flat", "canonical"]),
+        default: None,
+        description: "Project layout",
+    },
+    Key {
+        name: "dirs.build",
+        scope: Scope::Project,
+        kind: Kind::String,
+        default: Some("build"),
+        description: "Default build directory",
+    },
+    Key {
+        name: "dirs.runtime",
+        scope: Scope::Project,
+        kind: Kind::String,
+        default: Some("bin"),
+        description: "Default executable directory",
+    },
+    Key {
+        name: "dirs.src",
+        scope: Scope::Project,
+        kind: Kind::String,
+        default: Some("src"),
+        description: "Default source directory",
+    },
+    Key {
+        name: "dirs.include",
+        scope: Scope::Project,
+        kind: Kind::String,
+        default: Some("include"),
+        description: "Public header directory",
+    },
+    Key {
+        name: "build_info.enabled",
+        scope: Scope::Project,
+        kind: Kind::Bool,
+        default: Some("false"),
+        description: "Generates the build information header",
+    },
+    Key {
+        name: "build_info.header",
+        scope: Scope::Project,
+        kind: Kind::String,
+        default: None,
+        description: "Path of the build information header",
+    },
+    Key {
+        name: "assets.symlink",
+        scope: Scope::Project,
+        kind: Kind::Bool,
+        default: Some("false"),
+        description: "Symlinks assets instead of copying them",
+    },
+    Key {
+        name: "assets.embed_header",
+        scope: Scope::Project,
+        kind: Kind::String,
+        default: Some("include/assets.h"),
+        description: "Path of the embedded assets header",
+    },
+    Key {
+        name: "style.include_guards",
+        scope: Scope::Project,
+        kind: Kind::Choice(&["pragma", "macro"]),
+        default: Some("pragma"),
+        description: "Include guard style of generated headers",
+    },
+    Key {
+        name: "style.guard_pattern",
+        scope: Scope::Project,
+        kind: Kind::String,
+        default: Some("{PROJECT}_{PATH}_"),
+        description: "Name of macro include guards",
+    },
+    Key {
+        name: "env.*",
+        scope: Scope::Project,
+        kind: Kind::String,
+        default: None,
+        description: "Environment variable set when running the project",
+    },
+];
+
+impl Key {
+    fn matches(&self, name: &str) -> bool {
+        match self.name.strip_suffix('*') {
+            Some(prefix) => name
+                .strip_prefix(prefix)
+                .is_some_and(|rest| !rest.is_empty() && !rest.contains('.')),
+            None => self.name == name,
+        }
+    }
+
+    /// The TOML literal for a value given on the command line
+    fn literal(&self, name: &str, value: &str) -> Result<String> {
+        match self.kind {
+            Kind::String => Ok(toml_string(value)),
+            Kind::Bool => match value {
+                "true" | "false" => Ok(value.to_string()),
+                _ => anyhow::bail!("'{}' must be 'true' or 'false'", name),
+            },
+            Kind::Choice(choices) => {
+                if !choices.contains(&value) {
+                    anyhow::bail!(
+                        "Invalid value '{}' for '{}', valid values are {}",
+                        value,
+                        name,
+                        choices
+                            .iter()
+                            .map(|choice| format!("'{}'", choice))
+                            .collect::<Vec<_>>()
+                            .join(", ")
+                    );
+                }
+                Ok(toml_string(value))
+            }
+        }
+    }
+}
+
+/// Looks up a key, checking it belongs to the requested scope
+fn lookup(name: &str, scope: Option<Scope>) -> Result<&'static Key> {
+    let Some(key) = KEYS.iter().find(|key| key.matches(name)) else {
+        let names = KEYS.iter().map(|key| key.name).collect::<Vec<_>>();
+        match suggest(name, &names) {
+            Some(suggestion) => anyhow::bail!(
+                "Unknown configuration key '{}', did you mean '{}'?",
+                name,
+                suggestion
+            ),
+            None => anyhow::bail!(
+                "Unknown configuration key '{}', run 'cxx config list' to see all keys",
+                name
+            ),
+        }
+    };
+
+    if let Some(scope) = scope.filter(|scope| *scope != key.scope) {
+        anyhow::bail!(
+            "'{}' is a {} key, it can't be stored in the {} configuration",
+            name,
+            key.scope.name(),
+            scope.name()
+        );
+    }
+
+    Ok(key)
+}
+
+/// The candidate closest to a misspelled name, if any is close enough
+pub fn suggest<'a>(name: &str, candidates: &[&'a str]) -> Option<&'a str> {
+    candidates
+        .iter()
+        .map(|candidate| (edit_distance(name, candidate), *candidate))
+        .filter(|(distance, candidate)| *distance <= candidate.len().max(name.len()) / 2)
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, candidate)| candidate)
+}
+
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b = b.chars().collect::<Vec<_>>();
+    let mut previous = (0..=b.len()).collect::<Vec<_>>();
+
+    for (i, a) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, b) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(a != *b);
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+
+    previous[b.len()]
+}
+
+pub fn toml_string(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// Reads a dotted key from a TOML file, `None` when it isn't set
+fn read_value(path: &Path, name: &str) -> Result<Option<String>> {
+    let Ok(text) = fs::read_to_string(path) else {
+        return Ok(None);
+    };
+    let table: toml::Table =
+        toml::from_str(&text).with_context(|| format!("Failed to parse {}", path.display()))?;
+
+    let mut parts = name.split('.');
+    let mut value = parts.next().and_then(|part| table.get(part));
+    for part in parts {
+        value = value.and_then(|value| value.get(part));
+    }
+
+    Ok(value.map(|value| match value {
+        toml::Value::String(string) => string.clone(),
+        value => value.to_string(),
+    }))
+}
+
+/// Sets `key = value` in a TOML file, editing it line by line so comments
+/// and formatting survive
+pub fn write_value(text: &str, name: &str, literal: &str) -> String {
+    let (section, key) = match name.rsplit_once('.') {
+        Some((section, key)) => (section, key),
+        None => ("", name),
+    };
+    let key = if key
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+    {
+        key.to_string()
+    } else {
+        toml_string(key)
+    };
+    let assignment = format!("{} = {}", key, literal);
+
+    let mut lines = text.lines().map(str::to_string).collect::<Vec<_>>();
+    let mut current = "";
+    let mut section_end = None;
+    for (index, line) in text.lines().enumerate() {
+        let trimmed = line.trim();
+        if let Some(header) = trimmed
+            .strip_prefix('[')
+            .and_then(|rest| rest.strip_suffix(']'))
+        {
+            current = header.trim();
+            continue;
+        }
+        if current != section {
+            continue;
+        }
+
+        if let Some((_, value)) = trimmed
+            .split_once('=')
+            .filter(|(name, _)| name.trim() == key)
+        {
+            lines[index] = match trailing_comment(value) {
+                Some(comment) => assignment + comment,
+                None => assignment,
+            };
+            return lines.join("\n") + "\n";
+        }
+        if !trimmed.is_empty() {
+            section_end = Some(index + 1);
+        }
+    }
+
+    let has_section = section.is_empty()
+        || text
+            .lines()
+            .any(|line| line.trim() == format!("[{}]", section));
+    match (has_section, section_end) {
+        (true, Some(end)) => lines.insert(end, assignment),
+        (true, None) if section.is_empty() => lines.insert(0, assignment),
+        (true, None) => {
+            let header = lines
+                .iter()
+                .position(|line| line.trim() == format!("[{}]", section))
+                .unwrap_or(lines.len());
+            lines.insert((header + 1).min(lines.len()), assignment);
+        }
+        (false, _) => {
+            if lines.last().is_some_and(|line| !line.trim().is_empty()) {
+                lines.push(String::new());
+            }
+            lines.push(format!("[{}]", section));
+            lines.push(assignment);
+        }
+    }
+
+    lines.join("\n") + "\n"
+}
+
+/// The `# ...` comment after a TOML value, with the whitespace before it,
+/// skipping `#` inside strings
+fn trailing_comment(value: &str) -> Option<&str> {
+    let mut quote = None;
+    let mut escaped = false;
+    for (index, c) in value.char_indices() {
+        match quote {
+            Some('"') if escaped => escaped = false,
+            Some('"') if c == '\\' => escaped = true,
+            Some(open) if c == open => quote = None,
+            Some(_) => {}
+            None if c == '"' || c == '\'' => quote = Some(c),
+            None if c == '#' => {
+                let start = value[..index].trim_end().len();
+                return Some(&value[start..]);
+            }
+            None => {}
+        }
+    }
+    None
+}
+
+pub fn handle_config_get(name: String, scope: Option<Scope>) -> Result<()> {
+    let key = lookup(&name, scope)?;
+
+    match read_value(&key.scope.path()?, &name)? {
+        Some(value) => println!("{}", value),
+        None => match key.default {
+            Some(default) => println!("{}", default),
+            None => anyhow::bail!("'{}' is not set", name),
+        },
+    }
+
+    Ok(())
+}
+
+pub fn handle_config_set(name: String, value: String, scope: Option<Scope>) -> Result<()> {
+    let key = lookup(&name, scope)?;
+    let literal = key.literal(&name, &value)?;
+    let path = key.scope.path()?;
+
+    let original = fs::read_to_string(&path).ok();
+    if let Some(parent) = path
+        .parent()
+        .filter(|parent| !parent.as_os_str().is_empty())
+    {
+        fs::create_dir_all(parent)
+            .with_context(|| format!("Failed to create '{}'", parent.display()))?;
+    }
+    fs::write(
+        &path,
+        write_value(original.as_deref().unwrap_or_default(), &name, &literal),
+    )
+    .with_context(|| format!("Failed to write {}", path.display()))?;
+
+    if let Err(error) = key.scope.validate() {
+        match &original {
+            Some(original) => fs::write(&path, original),
+            None => fs::remove_file(&path),
+        }
+        .with_context(|| format!("Failed to restore {}", path.display()))?;
+
+        return Err(error).context(format!(
+            "Setting '{}' would make {} invalid, edit it by hand",
+            name,
+            path.display()
+        ));
+    }
+
+    eprintln!(
+        "{}",
+        format!("Set {} to {} in {}", name, literal, path.display()).green()
+    );
+
+    Ok(())
+}
+
+pub fn handle_config_list(scope: Option<Scope>) -> Result<()> {
+    for key in KEYS
+        .iter()
+        .filter(|key| scope.is_none_or(|scope| scope == key.scope))
+    {
+        let path = key.scope.path()?;
+
+        if let Some(prefix) = key.name.strip_suffix('*') {
+            let Ok(text) = fs::read_to_string(&path) else {
+                continue;
+            };
+            let table: toml::Table = toml::from_str(&text)
+                .with_context(|| format!("Failed to parse {}", path.display()))?;
+            let section = prefix.trim_end_matches('.');
+            if let Some(toml::Value::Table(entries)) = table.get(section) {
+                for name in entries.keys() {
+                    let name = format!("{}{}", prefix, name);
+                    let value = read_value(&path, &name)?.unwrap_or_default();
+                    println!("{} = {}  {}", name, value, key.scope.name().grey());
+                }
+            }
+            continue;
+        }
+
+        let value = match (read_value(&path, key.name)?, key.default) {
+            (Some(value), _) => value,
+            (None, Some(default)) => format!("{} {}", default, "(default)".grey()),
+            (None, None) => "(not set)".grey(),
+        };
+        println!(
+            "{} = {}  {}",
+            key.name,
+            value,
+            format!("{}, {}", key.scope.name(), key.description).grey()
+        );
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn replaces_a_top_level_key() {
+        assert_eq!(
+            write_value(
+                "shell = \"zsh\"\n\n[style]\nshell = 1\n",
+                "shell",
+                "\"bash\""
+            ),
+            "shell = \"bash\"\n\n[style]\nshell = 1\n"
+        );
+    }
+
+    #[test]
+    fn adds_a_top_level_key_above_the_sections() {
+        assert_eq!(
+            write_value("[style]\nx = 1\n", "build_root", "\"/tmp\""),
+            "build_root = \"/tmp\"\n[style]\nx = 1\n"
+        );
+    }
+
+    #[test]
+    fn replaces_a_key_in_its_section_only() {
+        assert_eq!(
+            write_value(
+                "# Project settings\n[dirs]\nbuild = \"a\"  # old\n\n[style]\nbuild = \"b\"\n",
+                "dirs.build",
+                "\"out\""
+            ),
+            "# Project settings\n[dirs]\nbuild = \"out\"  # old\n\n[style]\nbuild = \"b\"\n"
+        );
+        assert_eq!(
+            write_value("name = \"a#b\" # keep\n", "name", "\"c\""),
+            "name = \"c\" # keep\n"
+        );
+        assert_eq!(
+            write_value("name = 'C:\\' # keep\n", "name", "\"c\""),
+            "name = \"c\" # keep\n"
+        );
+    }
+
+    #[test]
+    fn adds_a_key_at_the_end_of_its_section() {
+        assert_eq!(
+            write_value(
+                "[dirs]\nbuild = \"out\"\n\n[style]\nx = 1\n",
+                "dirs.src",
+                "\"source\""
+            ),
+            "[dirs]\nbuild = \"out\"\nsrc = \"source\"\n\n[style]\nx = 1\n"
+        );
+        assert_eq!(
+            write_value("[dirs]\n\n[style]\n", "dirs.src", "\"source\""),
+            "[dirs]\nsrc = \"source\"\n\n[style]\n"
+        );
+    }
+
+    #[test]
+    fn adds_a_missing_section() {
+        assert_eq!(
+            write_value("name = \"app\"\n", "dirs.build", "\"out\""),
+            "name = \"app\"\n\n[dirs]\nbuild = \"out\"\n"
+        );
+        assert_eq!(
+            write_value("", "dirs.build", "\"out\""),
+            "[dirs]\nbuild = \"out\"\n"
+        );
+    }
+
+    #[test]
+    fn quotes_keys_that_need_it() {
+        assert_eq!(
+            write_value("[env]\n\"MY VAR\" = \"a\"\n", "env.MY VAR", "\"b\""),
+            "[env]\n\"MY VAR\" = \"b\"\n"
+        );
+        assert_eq!(
+            write_value("[env]\n", "env.MY VAR", "\"b\""),
+            "[env]\n\"MY VAR\" = \"b\"\n"
+        );
+    }
+}
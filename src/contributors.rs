@@ -0,0 +1,311 @@
+use anyhow::{Context, Result};
+use colorize::AnsiColor;
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+    process::Command,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
+    thread,
+};
+
+use crate::{backend::GENERATED_MARKER, executor::executor, sources::project_sources};
+
+/// Where the suggested review routing is written, next to where GitHub and
+/// GitLab look for the real one
+const CODEOWNERS_FILE: &str = "CODEOWNERS.suggested";
+
+/// Owners of a directory listed in the suggestion, each with at least
+/// `OWNER_SHARE` of its lines
+const MAX_OWNERS: usize = 3;
+const OWNER_SHARE: f64 = 0.2;
+
+/// An author as git records them, identified by email
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord)]
+struct Author {
+    email: String,
+    name: String,
+}
+
+#[derive(Default)]
+struct Ownership {
+    /// Lines last changed by each author, from `git blame`
+    lines: BTreeMap<Author, usize>,
+    /// Commits touching the directory since `since`, from `git log`
+    commits: BTreeMap<Author, usize>,
+}
+
+/// Summarizes who owns each directory of the C++ tree: the authors of its
+/// current lines by `git blame` and who committed to it recently by `git
+/// log`. `codeowners` writes a CODEOWNERS suggestion routing each
+/// directory to its main authors.
+pub fn handle_contributors(depth: usize, since: String, codeowners: bool) -> Result<()> {
+    let files = project_sources()?;
+    if files.is_empty() {
+        anyhow::bail!("No C/C++ sources found in the project");
+    }
+
+    let mut directories: BTreeMap<PathBuf, Ownership> = BTreeMap::new();
+    for (file, lines) in blame_all(&files) {
+        let ownership = directories.entry(directory(&file, depth)).or_default();
+        for (author, count) in lines {
+            *ownership.lines.entry(author).or_default() += count;
+        }
+    }
+    for (author, files) in log(&since)? {
+        let mut touched = files
+            .iter()
+            .map(|file| directory(file, depth))
+            .collect::<Vec<_>>();
+        touched.sort();
+        touched.dedup();
+        for dir in touched {
+            if let Some(ownership) = directories.get_mut(&dir) {
+                *ownership.commits.entry(author.clone()).or_default() += 1;
+            }
+        }
+    }
+    if directories
+        .values()
+        .all(|ownership| ownership.lines.is_empty())
+    {
+        anyhow::bail!("None of the project's sources are committed to git yet");
+    }
+
+    let width = directories
+        .keys()
+        .map(|dir| dir.display().to_string().len())
+        .max()
+        .unwrap_or(0)
+        .max("Directory".len());
+    println!(
+        "{}",
+        format!("{:<width$}  {:>7}  {}", "Directory", "Lines", "Owners").bold()
+    );
+    for (dir, ownership) in &directories {
+        let total = ownership.lines.values().sum::<usize>();
+        let owners = owners(ownership)
+            .iter()
+            .map(|(author, lines)| format!("{} {:.0}%", author.name, percent(*lines, total)))
+            .collect::<Vec<_>>();
+        println!(
+            "{:<width$}  {:>7}  {}",
+            dir.display(),
+            total,
+            owners.join(", ")
+        );
+
+        let mut recent = ownership.commits.iter().collect::<Vec<_>>();
+        recent.sort_by_key(|(_, commits)| std::cmp::Reverse(**commits));
+        if let Some((author, commits)) = recent.first() {
+            println!(
+                "{:<width$}  {:>7}  {}",
+                "",
+                "",
+                format!(
+                    "most active since {}: {} ({} {})",
+                    since,
+                    author.name,
+                    commits,
+                    if **commits == 1 { "commit" } else { "commits" }
+                )
+                .grey()
+            );
+        }
+    }
+
+    if codeowners {
+        write_codeowners(&directories)?;
+    }
+
+    Ok(())
+}
+
+/// Blames every file in parallel, returning the lines per author of the
+/// files git tracks
+fn blame_all(files: &[PathBuf]) -> Vec<(PathBuf, BTreeMap<Author, usize>)> {
+    let jobs = thread::available_parallelism()
+        .map(|jobs| jobs.get())
+        .unwrap_or(1);
+    let next = AtomicUsize::new(0);
+    let blamed = Mutex::new(Vec::new());
+    thread::scope(|scope| {
+        for _ in 0..jobs.min(files.len()) {
+            scope.spawn(|| loop {
+                let index = next.fetch_add(1, Ordering::Relaxed);
+                let Some(file) = files.get(index) else {
+                    break;
+                };
+                if let Some(lines) = blame(file) {
+                    blamed
+                        .lock()
+                        .expect("blame results lock")
+                        .push((file.clone(), lines));
+                }
+            });
+        }
+    });
+
+    blamed.into_inner().expect("blame results lock")
+}
+
+/// The lines of a file last changed by each author. Untracked files and
+/// uncommitted lines are left out.
+fn blame(file: &Path) -> Option<BTreeMap<Author, usize>> {
+    let output = executor()
+        .capture(
+            Command::new("git")
+                .args(["blame", "--line-porcelain", "--"])
+                .arg(file),
+        )
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+
+    let mut lines = BTreeMap::new();
+    let mut name = String::new();
+    for line in String::from_utf8_lossy(&output.stdout).lines() {
+        if let Some(author) = line.strip_prefix("author ") {
+            name = author.to_string();
+        } else if let Some(email) = line.strip_prefix("author-mail ") {
+            let email = email.trim_matches(['<', '>']).to_string();
+            if email != "not.committed.yet" {
+                let author = Author {
+                    email,
+                    name: name.clone(),
+                };
+                *lines.entry(author).or_default() += 1;
+            }
+        }
+    }
+
+    Some(lines)
+}
+
+/// The author of each commit since `since` and the files it touched in the
+/// project, relative to it
+fn log(since: &str) -> Result<Vec<(Author, Vec<PathBuf>)>> {
+    let output = executor()
+        .capture(Command::new("git").args([
+            "log",
+            &format!("--since={}", since),
+            "--format=%x00%ae%x00%an",
+            "--name-only",
+            "--relative",
+        ]))
+        .context("Failed to run git log")?;
+    if !output.status.success() {
+        anyhow::bail!(
+            "git log failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+
+    let mut commits: Vec<(Author, Vec<PathBuf>)> = Vec::new();
+    for line in String::from_utf8_lossy(&output.stdout).lines() {
+        if let Some(header) = line.strip_prefix('\0') {
+            if let Some((email, name)) = header.split_once('\0') {
+                let author = Author {
+                    email: email.to_string(),
+                    name: name.to_string(),
+                };
+                commits.push((author, Vec::new()));
+            }
+        } else if let Some((_, files)) = commits.last_mut().filter(|_| !line.is_empty()) {
+            files.push(PathBuf::from(line));
+        }
+    }
+
+    Ok(commits)
+}
+
+/// The directory a file is summarized under, its parent cut to `depth`
+/// components
+fn directory(file: &Path, depth: usize) -> PathBuf {
+    let dir = file
+        .parent()
+        .map(|parent| parent.components().take(depth).collect::<PathBuf>())
+        .unwrap_or_default();
+    if dir.as_os_str().is_empty() {
+        PathBuf::from(".")
+    } else {
+        dir
+    }
+}
+
+/// Authors by lines owned, most first, keeping those with at least
+/// `OWNER_SHARE` of the lines and always the first
+fn owners(ownership: &Ownership) -> Vec<(&Author, usize)> {
+    let total = ownership.lines.values().sum::<usize>();
+    let mut owners = ownership
+        .lines
+        .iter()
+        .map(|(author, lines)| (author, *lines))
+        .collect::<Vec<_>>();
+    owners.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+
+    owners
+        .into_iter()
+        .enumerate()
+        .filter(|(index, (_, lines))| *index == 0 || percent(*lines, total) >= OWNER_SHARE * 100.0)
+        .map(|(_, owner)| owner)
+        .take(MAX_OWNERS)
+        .collect()
+}
+
+fn percent(part: usize, total: usize) -> f64 {
+    match total {
+        0 => 0.0,
+        total => part as f64 * 100.0 / total as f64,
+    }
+}
+
+fn write_codeowners(directories: &BTreeMap<PathBuf, Ownership>) -> Result<()> {
+    let path = Path::new(CODEOWNERS_FILE);
+    if fs::read_to_string(path).is_ok_and(|existing| !existing.contains(GENERATED_MARKER)) {
+        anyhow::bail!(
+            "'{}' exists and wasn't generated by cxx, move it aside to generate it",
+            path.display()
+        );
+    }
+
+    let mut contents = format!(
+        "# {} contributors from git blame, review it and copy it to\n\
+         # .github/CODEOWNERS. Later patterns take precedence.\n",
+        GENERATED_MARKER
+    );
+    let mut routed = 0;
+    for (dir, ownership) in directories {
+        let owners = owners(ownership);
+        if owners.is_empty() {
+            continue;
+        }
+        let pattern = match dir.as_os_str() == "." {
+            true => "*".to_string(),
+            false => format!("/{}/", dir.display()),
+        };
+        let owners = owners
+            .iter()
+            .map(|(author, _)| author.email.as_str())
+            .collect::<Vec<_>>();
+        contents.push_str(&format!("{} {}\n", pattern, owners.join(" ")));
+        routed += 1;
+    }
+
+    fs::write(path, contents).with_context(|| format!("Failed to write {}", path.display()))?;
+    eprintln!(
+        "{}",
+        format!(
+            "Generated {} routing {} directories",
+            path.display(),
+            routed
+        )
+        .green()
+    );
+
+    Ok(())
+}
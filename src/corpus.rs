@@ -0,0 +1,201 @@
+use anyhow::{Context, Result};
+use colorize::AnsiColor;
+use std::{
+    collections::BTreeSet,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    cmake, default_exec_name, env,
+    executor::{executor, CommandFailed},
+    glob,
+    hash::Fnv,
+    manifest::{Manifest, RunConfig},
+    replay::{self, CASES_DIR},
+    toolchain, validate_project_name,
+};
+
+/// Directory of the fuzz corpora, one per fuzz target, in libFuzzer's
+/// layout of one input per file
+pub const CORPUS_DIR: &str = "fuzz/corpus";
+
+/// Seeds the corpus of a fuzz target with inputs the program is known to
+/// handle, so fuzzing doesn't start from nothing: the stdin of every
+/// recorded case, the files their arguments name, and the files in the
+/// `inputs` directories. With `cases`, input files no case covers yet also
+/// become recorded cases, fed on stdin or passed as the argument with
+/// `as_arg`.
+pub fn handle_corpus(
+    target: Option<String>,
+    inputs: Vec<String>,
+    cases: bool,
+    as_arg: bool,
+    build_dir: String,
+    runtime_dir: String,
+) -> Result<()> {
+    let target = match target {
+        Some(target) => target,
+        None => cmake::project_name()?,
+    };
+    validate_project_name(&target).context("Invalid fuzz target name")?;
+
+    let mut seeds = Vec::new();
+    // What the recorded cases already feed the program
+    let mut covered_files = BTreeSet::new();
+    let mut covered_stdin = BTreeSet::new();
+    for (name, case) in replay::load_all()? {
+        let stdin = fs::read(Path::new(CASES_DIR).join(&name).join("stdin")).unwrap_or_default();
+        if !stdin.is_empty() {
+            covered_stdin.insert(hash(&stdin));
+            seeds.push(stdin);
+        }
+        for arg in &case.args {
+            let path = Path::new(&case.working_dir).join(arg);
+            if let (Ok(contents), Ok(path)) = (fs::read(&path), fs::canonicalize(&path)) {
+                covered_files.insert(path);
+                seeds.push(contents);
+            }
+        }
+    }
+
+    let mut input_files = Vec::new();
+    for dir in &inputs {
+        if !Path::new(dir).is_dir() {
+            anyhow::bail!("Input directory '{}' does not exist", dir);
+        }
+        input_files.extend(
+            glob::expand(&format!("{}/**/*", dir.trim_end_matches('/')))?
+                .into_iter()
+                .filter(|file| file.is_file()),
+        );
+    }
+    for file in &input_files {
+        seeds.push(fs::read(file).with_context(|| format!("Failed to read {}", file.display()))?);
+    }
+    if seeds.is_empty() {
+        anyhow::bail!(
+            "No inputs to seed from, record a case with 'run --record <CASE>' or pass --inputs <DIR>"
+        );
+    }
+
+    let corpus = Path::new(CORPUS_DIR).join(&target);
+    fs::create_dir_all(&corpus)
+        .with_context(|| format!("Failed to create {}", corpus.display()))?;
+    let mut added = 0;
+    for seed in &seeds {
+        let path = corpus.join(hash(seed));
+        if !path.exists() {
+            fs::write(&path, seed)
+                .with_context(|| format!("Failed to write {}", path.display()))?;
+            added += 1;
+        }
+    }
+    eprintln!(
+        "{}",
+        format!(
+            "Seeded {} with {} new {}, {} already in it",
+            corpus.display(),
+            added,
+            if added == 1 { "input" } else { "inputs" },
+            seeds.len() - added
+        )
+        .green()
+    );
+
+    if cases {
+        record_cases(
+            &input_files,
+            &covered_files,
+            &covered_stdin,
+            as_arg,
+            &build_dir,
+            &runtime_dir,
+        )?;
+    }
+
+    Ok(())
+}
+
+/// Records a case for every input file the recorded cases don't feed the
+/// program yet, named after the file
+fn record_cases(
+    input_files: &[PathBuf],
+    covered_files: &BTreeSet<PathBuf>,
+    covered_stdin: &BTreeSet<String>,
+    as_arg: bool,
+    build_dir: &str,
+    runtime_dir: &str,
+) -> Result<()> {
+    let executable =
+        Path::new(runtime_dir).join(toolchain::executable(build_dir, &default_exec_name()));
+    if !executable.is_file() && !executor().dry_run() {
+        anyhow::bail!(
+            "{} doesn't exist, build the project before recording cases",
+            executable.display()
+        );
+    }
+    let env = env::project_env(&Manifest::load()?, &RunConfig::default())?;
+
+    let mut recorded = 0;
+    for file in input_files {
+        let contents =
+            fs::read(file).with_context(|| format!("Failed to read {}", file.display()))?;
+        let covered = match as_arg {
+            true => fs::canonicalize(file).is_ok_and(|file| covered_files.contains(&file)),
+            false => covered_stdin.contains(&hash(&contents)),
+        };
+        let name = format!(
+            "input-{}",
+            file.display()
+                .to_string()
+                .chars()
+                .map(|c| if c.is_ascii_alphanumeric() || c == '-' {
+                    c
+                } else {
+                    '_'
+                })
+                .collect::<String>()
+        );
+        if covered || Path::new(CASES_DIR).join(&name).exists() {
+            continue;
+        }
+
+        let case = replay::Case {
+            executable: executable.display().to_string(),
+            working_dir: ".".to_string(),
+            args: match as_arg {
+                true => vec![file.display().to_string()],
+                false => Vec::new(),
+            },
+            env: env.clone(),
+            exit_code: None,
+        };
+        let stdin = if as_arg { Vec::new() } else { contents };
+        // A failing program is recorded like any other output
+        if let Err(error) = replay::record_input(&name, case, stdin) {
+            if error.downcast_ref::<CommandFailed>().is_none() {
+                return Err(error);
+            }
+        }
+        recorded += 1;
+    }
+
+    eprintln!(
+        "{}",
+        format!(
+            "Recorded {} new {} from the inputs",
+            recorded,
+            if recorded == 1 { "case" } else { "cases" }
+        )
+        .green()
+    );
+
+    Ok(())
+}
+
+fn hash(contents: &[u8]) -> String {
+    let mut hasher = Fnv::new();
+    hasher.write(contents);
+    hasher.finish()
+}
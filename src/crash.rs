@@ -0,0 +1,435 @@
+use anyhow::{Context, Result};
+use colorize::AnsiColor;
+use std::{
+    collections::BTreeMap,
+    fs,
+    io::Read,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use crate::{cmake, executor::executor, output::TestResult, testcache};
+
+/// Directory in the build directory the crash handler is built in
+const HANDLER_DIR: &str = "crash_handler";
+
+/// The tests that crashed in the last run, for `test --crashed`
+const CRASHED_LOG: &str = "Testing/Temporary/LastTestsCrashed.log";
+
+/// Prefix of the lines the crash handler prints
+const MARKER: &str = "cxx-crash: ";
+
+/// At most this many frames of a crashed test's stack are shown
+const MAX_FRAMES: usize = 16;
+
+/// The stack of the last program `run` that crashed, in the build directory
+const RUN_CRASH_LOG: &str = "crash_handler/last_run_crash.log";
+
+/// Preloaded into the tests, it prints the stack of a crashing test for
+/// cxx to symbolize, then lets the signal kill the test as it would have.
+/// Death tests, which expect a signal, are unaffected. With `CXX_CRASH_LOG`
+/// set the stack is appended to that file instead of stderr, leaving what
+/// the program prints alone.
+const HANDLER_SOURCE: &str = r#"/* Generated by cxx, do not edit. */
+#define _GNU_SOURCE
+#include <dlfcn.h>
+#include <execinfo.h>
+#include <fcntl.h>
+#include <limits.h>
+#include <signal.h>
+#include <stdint.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <unistd.h>
+
+#define MAX_FRAMES 64
+
+/* Handlers run on a stack of their own to report stack overflows too */
+static char alternate_stack[64 * 1024];
+
+static const char *crash_log;
+static int output = STDERR_FILENO;
+
+static void print_line(const char *line, int length) {
+  if (length > 0) {
+    (void)!write(output, line, (size_t)length);
+  }
+}
+
+static void handle_crash(int signal_number) {
+  void *frames[MAX_FRAMES];
+  int count = backtrace(frames, MAX_FRAMES);
+  char line[PATH_MAX + 64];
+  if (crash_log) {
+    int log = open(crash_log, O_WRONLY | O_CREAT | O_APPEND | O_CLOEXEC, 0644);
+    if (log >= 0) {
+      output = log;
+    }
+  }
+  print_line(line, snprintf(line, sizeof line, "cxx-crash: signal %d\n", signal_number));
+
+  /* Skips the handler and the signal trampoline calling it */
+  Dl_info self;
+  int first = 0;
+  if (dladdr((void *)handle_crash, &self)) {
+    Dl_info info;
+    while (first < count && dladdr(frames[first], &info) && info.dli_fbase == self.dli_fbase) {
+      first++;
+    }
+  }
+  first++;
+
+  for (int i = first; i < count; i++) {
+    Dl_info info;
+    if (!dladdr(frames[i], &info) || !info.dli_fname) {
+      continue;
+    }
+    char path[PATH_MAX];
+    const char *file = realpath(info.dli_fname, path) ? path : info.dli_fname;
+    /* Above the crashing frame are return addresses, one past the call */
+    uintptr_t address = (uintptr_t)frames[i] - (i > first ? 1 : 0);
+    print_line(line, snprintf(line, sizeof line, "cxx-crash: frame %s 0x%lx 0x%lx\n", file,
+                              (unsigned long)address, (unsigned long)(uintptr_t)info.dli_fbase));
+  }
+
+  signal(signal_number, SIG_DFL);
+  raise(signal_number);
+}
+
+__attribute__((constructor)) static void install_crash_handler(void) {
+  /* Loads the unwinder now rather than in a crashing process */
+  void *frames[1];
+  backtrace(frames, 1);
+  crash_log = getenv("CXX_CRASH_LOG");
+
+  stack_t stack;
+  memset(&stack, 0, sizeof stack);
+  stack.ss_sp = alternate_stack;
+  stack.ss_size = sizeof alternate_stack;
+  sigaltstack(&stack, NULL);
+
+  struct sigaction action;
+  memset(&action, 0, sizeof action);
+  action.sa_handler = handle_crash;
+  action.sa_flags = SA_ONSTACK | SA_RESETHAND | SA_NODEFER;
+  sigemptyset(&action.sa_mask);
+  int signals[] = {SIGSEGV, SIGABRT, SIGBUS, SIGILL, SIGFPE};
+  for (size_t i = 0; i < sizeof signals / sizeof signals[0]; i++) {
+    sigaction(signals[i], &action, NULL);
+  }
+}
+"#;
+
+/// A frame of a crashed program's stack, as the crash handler printed it
+struct Frame {
+    file: PathBuf,
+    address: u64,
+    base: u64,
+}
+
+/// The stacks the crash handler printed, by the test they belong to.
+/// ctest prints a failed test's output below its result line.
+#[derive(Default)]
+pub struct Crashes {
+    current: Option<String>,
+    stacks: BTreeMap<String, Vec<Frame>>,
+}
+
+impl Crashes {
+    /// Collects a line of ctest's output, returning whether it's shown. The
+    /// crash handler's lines are shown symbolized after the tests instead.
+    pub fn observe(&mut self, line: &str, result: Option<&TestResult>) -> bool {
+        if let Some(result) = result {
+            self.current = result.signal.is_some().then(|| result.name.clone());
+            return true;
+        }
+        let Some(frame) = line.trim().strip_prefix(MARKER) else {
+            return true;
+        };
+        if let (Some(test), Some(frame)) = (&self.current, parse_frame(frame)) {
+            self.stacks.entry(test.clone()).or_default().push(frame);
+        }
+
+        false
+    }
+}
+
+/// Reads a `frame <FILE> <ADDRESS> <BASE>` line of the crash handler
+fn parse_frame(line: &str) -> Option<Frame> {
+    let mut fields = line.strip_prefix("frame ")?.rsplitn(3, ' ');
+    let (base, address, file) = (fields.next()?, fields.next()?, fields.next()?);
+    let hex = |value: &str| u64::from_str_radix(value.trim_start_matches("0x"), 16).ok();
+
+    Some(Frame {
+        file: PathBuf::from(file),
+        address: hex(address)?,
+        base: hex(base)?,
+    })
+}
+
+/// The environment preloading the crash handler into the tests, building
+/// it first. Only Linux preloads it; elsewhere crashes are reported without
+/// a stack.
+pub fn preload(build_dir: &str) -> Option<Vec<(String, String)>> {
+    // ctest runs the tests in their own directories
+    let library = handler_library(build_dir)?;
+    let library = fs::canonicalize(&library).unwrap_or(library);
+
+    Some(vec![(
+        "LD_PRELOAD".to_string(),
+        library.display().to_string(),
+    )])
+}
+
+/// The environment preloading the crash handler into a program `run` runs,
+/// which logs the stack into the build directory for [`report_run`]
+pub fn run_preload(build_dir: &str) -> Option<Vec<(String, String)>> {
+    let library = handler_library(build_dir)?;
+    let log = Path::new(build_dir).join(RUN_CRASH_LOG);
+    let _ = fs::remove_file(&log);
+    let log = fs::canonicalize(build_dir).ok()?.join(RUN_CRASH_LOG);
+    let library = fs::canonicalize(&library).unwrap_or(library);
+
+    Some(vec![
+        ("CXX_CRASH_LOG".to_string(), log.display().to_string()),
+        ("LD_PRELOAD".to_string(), library.display().to_string()),
+    ])
+}
+
+/// Builds the crash handler into the build directory when it's missing or
+/// out of date
+fn handler_library(build_dir: &str) -> Option<PathBuf> {
+    if !cfg!(target_os = "linux") || executor().dry_run() {
+        return None;
+    }
+
+    let dir = Path::new(build_dir).join(HANDLER_DIR);
+    let source = dir.join("crash_handler.c");
+    let library = dir.join("libcxx_crash_handler.so");
+    let current = fs::read_to_string(&source).is_ok_and(|text| text == HANDLER_SOURCE);
+    if !current || !library.exists() {
+        fs::create_dir_all(&dir).ok()?;
+        fs::write(&source, HANDLER_SOURCE).ok()?;
+        let compiler = cmake::cache_value(Path::new(build_dir), "CMAKE_C_COMPILER")
+            .unwrap_or_else(|| "cc".to_string());
+        let output = executor()
+            .capture(
+                Command::new(compiler)
+                    .args(["-shared", "-fPIC", "-O1", "-o"])
+                    .arg(&library)
+                    .arg(&source)
+                    .arg("-ldl"),
+            )
+            .ok()?;
+        if !output.status.success() {
+            return None;
+        }
+    }
+
+    fs::canonicalize(library).ok()
+}
+
+/// Prints the stack of every crashed test, symbolized with llvm-symbolizer
+/// or addr2line, and records the crashed tests for `test --crashed`
+pub fn report(build_dir: &str, results: &[TestResult], crashes: &Crashes) -> Result<()> {
+    let crashed = results
+        .iter()
+        .filter(|test| test.signal.is_some())
+        .collect::<Vec<_>>();
+    let log = Path::new(build_dir).join(CRASHED_LOG);
+    if crashed.is_empty() {
+        let _ = fs::remove_file(&log);
+        return Ok(());
+    }
+
+    let symbolizer = ["llvm-symbolizer", "addr2line"]
+        .into_iter()
+        .find(|tool| has_tool(tool));
+    for test in &crashed {
+        let heading = match test.signal.as_deref() {
+            Some("crash") | None => format!("{} crashed", test.name),
+            Some(signal) => format!("{} crashed with {}", test.name, signal),
+        };
+        eprintln!("{}", heading.red());
+
+        let frames = crashes
+            .stacks
+            .get(&test.name)
+            .map_or(&[][..], Vec::as_slice);
+        print_stack(symbolizer, frames);
+    }
+
+    let names = crashed
+        .iter()
+        .map(|test| test.name.clone())
+        .collect::<Vec<_>>();
+    if let Some(dir) = log.parent() {
+        fs::create_dir_all(dir).context("Failed to create the test log directory")?;
+    }
+    fs::write(&log, names.join("\n") + "\n").context("Failed to record the crashed tests")?;
+    eprintln!(
+        "{}",
+        "Rerun just the crashed tests with 'cxx test --crashed'".yellow()
+    );
+
+    Ok(())
+}
+
+/// Prints the stack of a program `run` ran when it crashed, from the log
+/// [`run_preload`] had the crash handler write. A program built without
+/// debug info shows its functions without their source lines, so a hint to
+/// build it with them follows.
+pub fn report_run(build_dir: &str, program: &str) {
+    let Ok(log) = fs::read_to_string(Path::new(build_dir).join(RUN_CRASH_LOG)) else {
+        return;
+    };
+    let mut signal = None;
+    let mut frames = Vec::new();
+    for line in log.lines().filter_map(|line| line.strip_prefix(MARKER)) {
+        match line.strip_prefix("signal ") {
+            // A program that crashed in several processes has the first one's stack shown
+            Some(_) if signal.is_some() => break,
+            Some(number) => signal = number.trim().parse().ok().map(signal_name),
+            None => frames.extend(parse_frame(line)),
+        }
+    }
+
+    let heading = match signal {
+        Some(signal) => format!("{} crashed with {}", program, signal),
+        None => format!("{} crashed", program),
+    };
+    eprintln!("{}", heading.red());
+    let symbolizer = ["llvm-symbolizer", "addr2line"]
+        .into_iter()
+        .find(|tool| has_tool(tool));
+    let located = print_stack(symbolizer, &frames);
+
+    let build_type = cmake::cache_value(Path::new(build_dir), "CMAKE_BUILD_TYPE")
+        .unwrap_or_default()
+        .to_ascii_lowercase();
+    if !located && !matches!(build_type.as_str(), "debug" | "relwithdebinfo") {
+        eprintln!(
+            "{}",
+            "Configure with '--profile debug' or '--profile relwithdebinfo' for the source lines of the stack"
+                .yellow()
+        );
+    }
+}
+
+/// Prints the frames of a stack down to main, symbolized when a symbolizer
+/// is installed and as offsets into their files otherwise. Returns whether
+/// any frame has a source line.
+fn print_stack(symbolizer: Option<&str>, frames: &[Frame]) -> bool {
+    let mut shown = 0;
+    let mut located = false;
+    for frame in frames {
+        let (function, location) = symbolizer
+            .and_then(|symbolizer| symbolize(symbolizer, frame))
+            .unwrap_or_default();
+        // Catch2 reports a signal from a handler of its own first
+        if function.is_empty() || function == "??" || function.starts_with("Catch::") {
+            continue;
+        }
+        let location = match location.is_empty() || location.starts_with("??") {
+            true => frame.file.display().to_string(),
+            false => {
+                located = true;
+                location
+            }
+        };
+        eprintln!("  #{} {} at {}", shown, function, location);
+        shown += 1;
+        if function == "main" || shown == MAX_FRAMES {
+            break;
+        }
+    }
+    if shown == 0 {
+        for frame in frames.iter().take(MAX_FRAMES) {
+            eprintln!(
+                "  {}+0x{:x}",
+                frame.file.display(),
+                frame.address - frame.base
+            );
+        }
+    }
+
+    located
+}
+
+/// The name of a signal the crash handler catches, by its Linux number
+fn signal_name(number: i32) -> String {
+    match number {
+        4 => "SIGILL".to_string(),
+        6 => "SIGABRT".to_string(),
+        7 => "SIGBUS".to_string(),
+        8 => "SIGFPE".to_string(),
+        11 => "SIGSEGV".to_string(),
+        _ => format!("signal {}", number),
+    }
+}
+
+/// A `ctest -R` expression matching the tests that crashed in the last run
+pub fn crashed_filter(build_dir: &str) -> Result<String> {
+    let names = fs::read_to_string(Path::new(build_dir).join(CRASHED_LOG))
+        .unwrap_or_default()
+        .lines()
+        .filter(|line| !line.is_empty())
+        .map(String::from)
+        .collect::<Vec<_>>();
+    if names.is_empty() {
+        anyhow::bail!("No tests crashed in the last run in '{}'", build_dir);
+    }
+
+    Ok(testcache::name_filter(&names))
+}
+
+/// The function and source location of a frame. Executables that aren't
+/// position independent are symbolized at the address they ran at, shared
+/// objects at the offset they were loaded at.
+fn symbolize(symbolizer: &str, frame: &Frame) -> Option<(String, String)> {
+    let address = match is_fixed_executable(&frame.file) {
+        true => frame.address,
+        false => frame.address.checked_sub(frame.base)?,
+    };
+    let address = format!("0x{:x}", address);
+    let mut command = Command::new(symbolizer);
+    match symbolizer {
+        "llvm-symbolizer" => command
+            .args(["--demangle", "--no-inlines"])
+            .arg(format!("--obj={}", frame.file.display()))
+            .arg(&address),
+        _ => command
+            .args(["-f", "-C", "-e"])
+            .arg(&frame.file)
+            .arg(&address),
+    };
+    let output = executor().capture(&mut command).ok()?;
+    let text = String::from_utf8_lossy(&output.stdout);
+    let mut lines = text.lines();
+    let function = lines.next()?.trim().to_string();
+    let location = lines.next().unwrap_or_default().trim();
+    let location = match std::env::current_dir() {
+        Ok(root) => location
+            .strip_prefix(&format!("{}/", root.display()))
+            .unwrap_or(location)
+            .to_string(),
+        Err(_) => location.to_string(),
+    };
+
+    Some((function, location))
+}
+
+/// Whether an ELF file is an executable linked at a fixed address, `ET_EXEC`
+fn is_fixed_executable(file: &Path) -> bool {
+    let mut header = [0; 18];
+    fs::File::open(file).is_ok_and(|mut file| file.read_exact(&mut header).is_ok())
+        && header[..4] == *b"\x7fELF"
+        && header[16] == 2
+}
+
+fn has_tool(tool: &str) -> bool {
+    executor()
+        .capture(Command::new(tool).arg("--version"))
+        .is_ok()
+}
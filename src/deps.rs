@@ -0,0 +1,557 @@
+use anyhow::{Context, Result};
+use colorize::AnsiColor;
+use std::{fs, iter::Peekable, path::Path, str::Chars};
+
+use crate::cmake::{self, CMAKE_LISTS, FRAGMENT_DIR};
+
+/// Fragment fetching and linking the dependencies added with `cxx add`
+const DEPS_FRAGMENT: &str = "deps.cmake";
+
+/// The project's warnings-as-errors are left out of the dependencies'
+/// sources while they're made available
+const DEPS_HEADER: &str = "# Generated by cxx add and cxx remove. Options added to a
+# FetchContent_Declare, its target_link_libraries and other calls next to
+# it, above or below without a blank line in between, are kept when
+# dependencies are added or removed.
+include(FetchContent)
+get_directory_property(DEPS_COMPILE_OPTIONS COMPILE_OPTIONS)
+set_directory_properties(PROPERTIES COMPILE_OPTIONS \"\")
+";
+
+const DEPS_FOOTER: &str =
+    "\nset_directory_properties(PROPERTIES COMPILE_OPTIONS \"${DEPS_COMPILE_OPTIONS}\")\n";
+
+/// A dependency of the fragment, as the arguments of its calls
+struct Dependency {
+    name: String,
+    /// The arguments of `FetchContent_Declare` after the name
+    options: Vec<String>,
+    /// The arguments of `target_link_libraries` after the project target
+    link: Vec<String>,
+    /// Other calls written above the declaration by hand, e.g. `set(FMT_INSTALL ON)`
+    before: Vec<String>,
+    /// Other calls written below the declaration by hand, e.g.
+    /// `set_target_properties(fmt ...)`, which need its targets
+    after: Vec<String>,
+}
+
+impl Dependency {
+    fn option(&self, key: &str) -> Option<&str> {
+        self.options
+            .iter()
+            .position(|option| option == key)
+            .and_then(|index| self.options.get(index + 1))
+            .map(|value| value.trim_matches('"'))
+    }
+
+    fn set_option(&mut self, key: &str, value: &str) {
+        let value = quote(value);
+        match self.options.iter().position(|option| option == key) {
+            Some(index) if index + 1 < self.options.len() => self.options[index + 1] = value,
+            Some(_) => self.options.push(value),
+            None => self.options.extend([key.to_string(), value]),
+        }
+    }
+}
+
+/// Fetches a dependency with FetchContent at a tag or commit and links its
+/// targets to the project, in `cmake/deps.cmake`. Adding a dependency
+/// already there changes its repository, tag or linked targets.
+pub fn handle_add_dependency(
+    name: String,
+    git: Option<String>,
+    tag: Option<String>,
+    link: Vec<String>,
+) -> Result<()> {
+    if cmake::is_foreign_project() {
+        anyhow::bail!(
+            "{} wasn't generated by cxx, add the dependency to it by hand",
+            CMAKE_LISTS
+        );
+    }
+    if name.is_empty()
+        || !name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "_-.+".contains(c))
+    {
+        anyhow::bail!(
+            "Invalid dependency name '{}', use letters, digits and _-.+",
+            name
+        );
+    }
+
+    let mut dependencies = load()?;
+    let known = dependencies
+        .iter()
+        .position(|dependency| dependency.name.eq_ignore_ascii_case(&name));
+    let message = match known {
+        Some(index) => {
+            if git.is_none() && tag.is_none() && link.is_empty() {
+                anyhow::bail!(
+                    "'{}' is already a dependency, pass --git, --tag or --link to change it",
+                    dependencies[index].name
+                );
+            }
+            let dependency = &mut dependencies[index];
+            if let Some(git) = &git {
+                dependency.set_option("GIT_REPOSITORY", git);
+            }
+            if let Some(tag) = &tag {
+                dependency.set_option("GIT_TAG", tag);
+                // Shallow clones can only check out branches and tags
+                match is_commit(tag) {
+                    true => dependency.options = without_option(&dependency.options, "GIT_SHALLOW"),
+                    false => dependency.set_option("GIT_SHALLOW", "TRUE"),
+                }
+            }
+            if !link.is_empty() {
+                let visibility = match dependency.link.first() {
+                    Some(first) if is_visibility(first) => first.clone(),
+                    _ => visibility()?.to_string(),
+                };
+                dependency.link = std::iter::once(visibility).chain(link).collect();
+            }
+            format!(
+                "Updated {} to {}",
+                dependency.name,
+                dependency.option("GIT_TAG").unwrap_or("its default branch")
+            )
+        }
+        None => {
+            let (Some(git), Some(tag)) = (git, tag) else {
+                anyhow::bail!(
+                    "Pass --git <URL> and --tag <TAG> to add '{}', e.g. cxx add fmt --git https://github.com/fmtlib/fmt --tag 10.2.1",
+                    name
+                );
+            };
+            let mut options = vec![
+                "GIT_REPOSITORY".to_string(),
+                quote(&git),
+                "GIT_TAG".to_string(),
+                quote(&tag),
+            ];
+            // Shallow clones can only check out branches and tags
+            if !is_commit(&tag) {
+                options.extend(["GIT_SHALLOW".to_string(), "TRUE".to_string()]);
+            }
+            let targets = match link.is_empty() {
+                true => vec![format!("{0}::{0}", name)],
+                false => link,
+            };
+            let message = format!("Added {} {}, linking {}", name, tag, targets.join(" "));
+            dependencies.push(Dependency {
+                name,
+                options,
+                link: std::iter::once(visibility()?.to_string())
+                    .chain(targets)
+                    .collect(),
+                before: Vec::new(),
+                after: Vec::new(),
+            });
+            message
+        }
+    };
+
+    save(&dependencies)?;
+    eprintln!("{}", message.green());
+
+    Ok(())
+}
+
+/// Removes a dependency added with `cxx add`, and `cmake/deps.cmake` along
+/// with the last one
+pub fn handle_remove_dependency(name: String) -> Result<()> {
+    let mut dependencies = load()?;
+    let Some(index) = dependencies
+        .iter()
+        .position(|dependency| dependency.name.eq_ignore_ascii_case(&name))
+    else {
+        let names = dependencies
+            .iter()
+            .map(|dependency| dependency.name.as_str())
+            .collect::<Vec<_>>();
+        match names.is_empty() {
+            true => anyhow::bail!("The project has no dependencies added with cxx add"),
+            false => anyhow::bail!(
+                "No dependency named '{}', the dependencies are {}",
+                name,
+                names.join(", ")
+            ),
+        }
+    };
+
+    let removed = dependencies.remove(index);
+    match dependencies.is_empty() {
+        true => cmake::remove_fragment(DEPS_FRAGMENT)?,
+        false => save(&dependencies)?,
+    }
+    eprintln!("{}", format!("Removed {}", removed.name).green());
+
+    Ok(())
+}
+
+/// Linking to a header-only library has to be INTERFACE, as its target
+/// has no sources of its own
+fn visibility() -> Result<&'static str> {
+    Ok(match cmake::is_interface_library()? {
+        true => "INTERFACE",
+        false => "PRIVATE",
+    })
+}
+
+fn is_visibility(argument: &str) -> bool {
+    ["PRIVATE", "PUBLIC", "INTERFACE"].contains(&argument)
+}
+
+fn is_commit(tag: &str) -> bool {
+    tag.len() == 40 && tag.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+fn without_option(options: &[String], key: &str) -> Vec<String> {
+    match options.iter().position(|option| option == key) {
+        Some(index) => options
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| *i != index && *i != index + 1)
+            .map(|(_, option)| option.clone())
+            .collect(),
+        None => options.to_vec(),
+    }
+}
+
+fn quote(value: &str) -> String {
+    match value.is_empty() || value.contains(|c: char| c.is_whitespace() || c == ';') {
+        true => format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\"")),
+        false => value.to_string(),
+    }
+}
+
+fn fragment_path() -> String {
+    format!("{}/{}", FRAGMENT_DIR, DEPS_FRAGMENT)
+}
+
+/// Reads the dependencies back from the fragment
+fn load() -> Result<Vec<Dependency>> {
+    let path = fragment_path();
+    if !Path::new(&path).exists() {
+        return Ok(Vec::new());
+    }
+    let text = fs::read_to_string(&path).with_context(|| format!("Failed to read {}", path))?;
+
+    Ok(parse(&text))
+}
+
+fn save(dependencies: &[Dependency]) -> Result<()> {
+    cmake::write_fragment(DEPS_FRAGMENT, &render(dependencies))
+}
+
+/// The dependencies of a fragment. A `target_link_libraries` belongs to the
+/// dependency declared above it. The calls cxx doesn't generate belong to
+/// the dependency right above them, or after a blank line, to the one
+/// declared below them.
+fn parse(text: &str) -> Vec<Dependency> {
+    let mut dependencies: Vec<Dependency> = Vec::new();
+    let mut pending = Vec::new();
+    // Whether a blank line separates the calls from the last dependency
+    let mut separated = true;
+    for Call {
+        command,
+        arguments,
+        after_blank_line,
+    } in calls(text)
+    {
+        separated |= after_blank_line;
+        match command.to_ascii_lowercase().as_str() {
+            "fetchcontent_declare" => {
+                let Some((name, options)) = arguments.split_first() else {
+                    continue;
+                };
+                dependencies.push(Dependency {
+                    name: name.trim_matches('"').to_string(),
+                    options: options.to_vec(),
+                    link: Vec::new(),
+                    before: std::mem::take(&mut pending),
+                    after: Vec::new(),
+                });
+                separated = false;
+            }
+            "target_link_libraries"
+                if arguments.first().map(String::as_str) == Some("${PROJECT_NAME}") =>
+            {
+                if let Some(dependency) = dependencies.last_mut() {
+                    dependency.link.extend(arguments.into_iter().skip(1));
+                }
+            }
+            "fetchcontent_makeavailable" => {}
+            "include" if arguments == ["FetchContent"] => {}
+            "get_directory_property" | "set_directory_properties"
+                if arguments
+                    .iter()
+                    .any(|argument| argument == "COMPILE_OPTIONS") => {}
+            _ => {
+                let call = format!("{}({})", command, arguments.join(" "));
+                match dependencies.last_mut().filter(|_| !separated) {
+                    Some(dependency) => dependency.after.push(call),
+                    None => pending.push(call),
+                }
+            }
+        }
+    }
+    // Calls below the last declaration stay below it
+    if let Some(dependency) = dependencies.last_mut() {
+        dependency.after.extend(pending);
+    }
+
+    dependencies
+}
+
+fn render(dependencies: &[Dependency]) -> String {
+    let mut fragment = DEPS_HEADER.to_string();
+    for dependency in dependencies {
+        fragment.push('\n');
+        for call in &dependency.before {
+            fragment.push_str(call);
+            fragment.push('\n');
+        }
+        fragment.push_str(&format!("FetchContent_Declare({}", dependency.name));
+        // One option per line, with its values
+        for (index, option) in dependency.options.iter().enumerate() {
+            let starts_option = option.chars().all(|c| c.is_ascii_uppercase() || c == '_')
+                && index > 0
+                && !dependency.options[index - 1]
+                    .chars()
+                    .all(|c| c.is_ascii_uppercase() || c == '_');
+            match index == 0 || starts_option {
+                true => fragment.push_str("\n  "),
+                false => fragment.push(' '),
+            }
+            fragment.push_str(option);
+        }
+        fragment.push_str(&format!(
+            ")\nFetchContent_MakeAvailable({})\n",
+            dependency.name
+        ));
+        if !dependency.link.is_empty() {
+            fragment.push_str(&format!(
+                "target_link_libraries(${{PROJECT_NAME}} {})\n",
+                dependency.link.join(" ")
+            ));
+        }
+        for call in &dependency.after {
+            fragment.push_str(call);
+            fragment.push('\n');
+        }
+    }
+    fragment.push_str(DEPS_FOOTER);
+
+    fragment
+}
+
+/// A command call of a CMake file
+struct Call {
+    command: String,
+    /// The arguments as written, quotes included, without comments
+    arguments: Vec<String>,
+    /// Whether a blank line separates the call from the one above it
+    after_blank_line: bool,
+}
+
+/// The command calls of a CMake file
+fn calls(text: &str) -> Vec<Call> {
+    let mut calls = Vec::new();
+    let mut chars = text.chars().peekable();
+    let mut word = String::new();
+    let mut blank_line = false;
+    let mut line_empty = true;
+    while let Some(c) = chars.next() {
+        match c {
+            '#' => {
+                skip_line(&mut chars);
+                word.clear();
+                line_empty = true;
+            }
+            '(' if !word.is_empty() => {
+                calls.push(Call {
+                    command: std::mem::take(&mut word),
+                    arguments: arguments(&mut chars),
+                    after_blank_line: std::mem::take(&mut blank_line),
+                });
+                line_empty = false;
+            }
+            '\n' => {
+                blank_line |= line_empty;
+                line_empty = true;
+                word.clear();
+            }
+            c if c.is_ascii_alphanumeric() || c == '_' => {
+                word.push(c);
+                line_empty = false;
+            }
+            c => {
+                word.clear();
+                line_empty &= c.is_whitespace();
+            }
+        }
+    }
+
+    calls
+}
+
+fn arguments(chars: &mut Peekable<Chars>) -> Vec<String> {
+    let mut arguments = Vec::new();
+    let mut argument = String::new();
+    let mut depth = 0;
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => {
+                argument.push(c);
+                while let Some(c) = chars.next() {
+                    argument.push(c);
+                    match c {
+                        '\\' => argument.extend(chars.next()),
+                        '"' => break,
+                        _ => {}
+                    }
+                }
+            }
+            '#' => skip_line(chars),
+            '(' => {
+                depth += 1;
+                argument.push(c);
+            }
+            ')' if depth == 0 => break,
+            ')' => {
+                depth -= 1;
+                argument.push(c);
+            }
+            c if c.is_whitespace() => {
+                if !argument.is_empty() {
+                    arguments.push(std::mem::take(&mut argument));
+                }
+            }
+            c => argument.push(c),
+        }
+    }
+    if !argument.is_empty() {
+        arguments.push(argument);
+    }
+
+    arguments
+}
+
+fn skip_line(chars: &mut Peekable<Chars>) {
+    for c in chars.by_ref() {
+        if c == '\n' {
+            break;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn arguments_of(text: &str) -> Vec<String> {
+        let mut calls = calls(text);
+        assert_eq!(calls.len(), 1);
+        calls.remove(0).arguments
+    }
+
+    #[test]
+    fn arguments_keep_quotes_and_skip_comments() {
+        assert_eq!(
+            arguments_of("set(A \"x y\" # a comment)\n  \"a ) \\\"b\\\"\" B)"),
+            ["A", "\"x y\"", "\"a ) \\\"b\\\"\"", "B"]
+        );
+    }
+
+    #[test]
+    fn arguments_keep_nested_parentheses() {
+        assert_eq!(
+            arguments_of("if((A AND B) OR C)"),
+            ["(A", "AND", "B)", "OR", "C"]
+        );
+    }
+
+    #[test]
+    fn calls_skip_commented_out_calls() {
+        let calls = calls("# set(A 1)\nset(B 2) # set(C 3)\n\n  message(done)\n");
+
+        let commands = calls
+            .iter()
+            .map(|call| (call.command.as_str(), call.after_blank_line))
+            .collect::<Vec<_>>();
+        assert_eq!(commands, [("set", false), ("message", true)]);
+    }
+
+    #[test]
+    fn rendered_dependencies_round_trip() {
+        let fragment = render(&[Dependency {
+            name: "fmt".to_string(),
+            options: [
+                "GIT_REPOSITORY",
+                "https://github.com/fmtlib/fmt",
+                "GIT_TAG",
+                "10.2.1",
+            ]
+            .map(String::from)
+            .to_vec(),
+            link: ["PRIVATE", "fmt::fmt"].map(String::from).to_vec(),
+            before: vec!["set(FMT_INSTALL ON)".to_string()],
+            after: vec!["target_compile_options(fmt PRIVATE -w)".to_string()],
+        }]);
+
+        assert_eq!(render(&parse(&fragment)), fragment);
+    }
+
+    #[test]
+    fn hand_written_calls_stay_next_to_their_dependency() {
+        let fragment = format!(
+            "{}
+FetchContent_Declare(fmt
+  GIT_REPOSITORY https://github.com/fmtlib/fmt
+  GIT_TAG \"10.2.1\") # pinned
+FetchContent_MakeAvailable(fmt)
+target_link_libraries(${{PROJECT_NAME}} PRIVATE fmt::fmt)
+set_target_properties(fmt PROPERTIES CXX_CLANG_TIDY \"\")
+
+set(SPDLOG_FMT_EXTERNAL ON)
+FetchContent_Declare(spdlog
+  GIT_REPOSITORY https://github.com/gabime/spdlog
+  GIT_TAG v1.14.1)
+FetchContent_MakeAvailable(spdlog)
+target_link_libraries(${{PROJECT_NAME}} PRIVATE spdlog::spdlog)
+{}
+target_compile_options(spdlog PRIVATE -w)
+",
+            DEPS_HEADER, DEPS_FOOTER
+        );
+
+        let mut dependencies = parse(&fragment);
+        let calls = dependencies
+            .iter()
+            .map(|dependency| (dependency.before.clone(), dependency.after.clone()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            calls,
+            [
+                (
+                    vec![],
+                    vec!["set_target_properties(fmt PROPERTIES CXX_CLANG_TIDY \"\")".to_string()]
+                ),
+                (
+                    vec!["set(SPDLOG_FMT_EXTERNAL ON)".to_string()],
+                    vec!["target_compile_options(spdlog PRIVATE -w)".to_string()]
+                ),
+            ]
+        );
+        assert_eq!(dependencies[0].option("GIT_TAG"), Some("10.2.1"));
+
+        dependencies.remove(1);
+        let rendered = render(&dependencies);
+        assert!(rendered.contains(
+            "target_link_libraries(${PROJECT_NAME} PRIVATE fmt::fmt)\nset_target_properties(fmt"
+        ));
+        assert!(!rendered.contains("spdlog"));
+    }
+}
@@ -0,0 +1,398 @@
+use anyhow::{Context, Result};
+use colorize::AnsiColor;
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use crate::{
+    cmake,
+    executor::{executor, CommandFailed},
+    handle_init_project,
+    manifest::STATE_DIR,
+    stats, ConfigureArgs,
+};
+
+/// Directory in the state directory holding a source tree and build per
+/// commit, and the listings compared last
+const DISASM_DIR: &str = "disasm";
+
+/// A commit's sources exported for building, with its build directory
+struct Checkout {
+    commit: String,
+    /// The commit as it's shown, e.g. `main (1a2b3c4d)`
+    label: String,
+    root: PathBuf,
+}
+
+impl Checkout {
+    /// Each profile is built in a directory of its own
+    fn build_dir(&self, profile: &str) -> PathBuf {
+        self.root.join(format!("build-{}", profile))
+    }
+}
+
+/// The disassembly of the selected functions, by function
+type Listing = BTreeMap<String, Vec<String>>;
+
+/// Builds the project at two git refs and diffs the assembly generated for a
+/// function, for the functions of a source file, or for a function in a
+/// source file. Each commit is exported into `.cpp_tools/disasm/<COMMIT>/`
+/// and built there with `profile`, so neither the working tree nor its
+/// build directories are touched, and comparing the same commits again only
+/// disassembles. Addresses are left out of the listings, so code that only
+/// moved doesn't show up as changed.
+pub fn handle_disasm_diff(
+    base: String,
+    head: String,
+    function: Option<String>,
+    file: Option<String>,
+    profile: String,
+    jobs: Option<usize>,
+) -> Result<()> {
+    cmake::enter_project_root()?;
+    let objdump = ["objdump", "llvm-objdump"]
+        .into_iter()
+        .find(|tool| has_tool(tool))
+        .context("Disassembling requires objdump or llvm-objdump")?;
+    let file = file.map(|file| file.trim_start_matches("./").to_string());
+    let selection = match (&function, &file) {
+        (Some(function), Some(file)) => format!("'{}' in {}", function, file),
+        (Some(function), None) => format!("'{}'", function),
+        (None, Some(file)) => file.clone(),
+        (None, None) => anyhow::bail!("Select the code to compare with --function or --file"),
+    };
+
+    let checkouts = [checkout(&base)?, checkout(&head)?];
+    for checkout in &checkouts {
+        build(checkout, &profile, jobs)
+            .with_context(|| format!("Failed to build {}", checkout.label))?;
+    }
+    if executor().dry_run() {
+        return Ok(());
+    }
+
+    let mut listings = Vec::new();
+    for checkout in &checkouts {
+        let listing = disassemble(
+            objdump,
+            &checkout.build_dir(&profile),
+            function.as_deref(),
+            file.as_deref(),
+        )?;
+        listings.push(listing);
+    }
+    if listings.iter().all(|listing| listing.is_empty()) {
+        anyhow::bail!(
+            "Neither {} nor {} compiles {}",
+            checkouts[0].label,
+            checkouts[1].label,
+            selection
+        );
+    }
+
+    let (before, after) = (&listings[0], &listings[1]);
+    if before == after {
+        eprintln!(
+            "{}",
+            format!(
+                "No codegen changes in {} between {} and {}",
+                selection, checkouts[0].label, checkouts[1].label
+            )
+            .green()
+        );
+        return Ok(());
+    }
+
+    let dir = Path::new(STATE_DIR).join(DISASM_DIR).join("diff");
+    fs::create_dir_all(&dir).context("Failed to create the listing directory")?;
+    let mut paths = Vec::new();
+    for (checkout, listing) in checkouts.iter().zip(&listings) {
+        let path = dir.join(format!("{}.s", &checkout.commit[..10]));
+        fs::write(&path, render(listing))
+            .with_context(|| format!("Failed to write {}", path.display()))?;
+        paths.push(path.display().to_string());
+    }
+    // git diff exits with 1 when the files differ, which they do here
+    if let Err(error) = executor().execute_interactive(
+        Command::new("git")
+            .args(["diff", "--no-index", "--"])
+            .args(&paths),
+    ) {
+        if error.downcast_ref::<CommandFailed>().is_none() {
+            return Err(error);
+        }
+    }
+
+    eprintln!(
+        "{}",
+        format!(
+            "Instructions of {} from {} to {}:",
+            selection, checkouts[0].label, checkouts[1].label
+        )
+        .bold()
+    );
+    let mut names = before.keys().chain(after.keys()).collect::<Vec<_>>();
+    names.sort();
+    names.dedup();
+    for name in names {
+        let line = match (before.get(name), after.get(name)) {
+            (Some(before), Some(after)) if before == after => continue,
+            (Some(before), Some(after)) => {
+                format!("  {}: {} -> {}", name, before.len(), after.len())
+            }
+            (Some(before), None) => format!("  {}: {} -> removed", name, before.len()),
+            (None, Some(after)) => format!("  {}: added -> {}", name, after.len()),
+            (None, None) => continue,
+        };
+        eprintln!("{}", line);
+    }
+
+    Ok(())
+}
+
+/// Exports the sources of the project at `reference` unless a previous
+/// comparison already did. Only committed files are exported, so a project
+/// in a subdirectory of its repository is exported from that subdirectory.
+fn checkout(reference: &str) -> Result<Checkout> {
+    let commit = git(&[
+        "rev-parse",
+        "--verify",
+        &format!("{}^{{commit}}", reference),
+    ])
+    .with_context(|| format!("'{}' isn't a commit", reference))?;
+    let prefix = git(&["rev-parse", "--show-prefix"]).unwrap_or_default();
+    let root = std::env::current_dir()
+        .context("Failed to get current directory")?
+        .join(STATE_DIR)
+        .join(DISASM_DIR)
+        .join(&commit);
+
+    if !root.exists() || executor().dry_run() {
+        if !executor().dry_run() {
+            fs::create_dir_all(&root)
+                .with_context(|| format!("Failed to create {}", root.display()))?;
+        }
+        let tree = match prefix.is_empty() {
+            true => commit.clone(),
+            false => format!("{}:{}", commit, prefix.trim_end_matches('/')),
+        };
+        // Through a file rather than a pipe, which would need a shell
+        let tarball = root.with_extension("tar");
+        let result = executor()
+            .execute(
+                Command::new("git")
+                    .args(["archive", "--format=tar", "-o"])
+                    .arg(&tarball)
+                    .arg(&tree),
+            )
+            .and_then(|_| {
+                executor().execute(
+                    Command::new("tar")
+                        .arg("-xf")
+                        .arg(&tarball)
+                        .arg("-C")
+                        .arg(&root),
+                )
+            });
+        let _ = fs::remove_file(&tarball);
+        if result.is_err() || (!executor().dry_run() && is_empty(&root)) {
+            let _ = fs::remove_dir_all(&root);
+            anyhow::bail!("Failed to export the sources of '{}'", reference);
+        }
+    }
+
+    Ok(Checkout {
+        label: format!("{} ({})", reference, &commit[..10]),
+        commit,
+        root,
+    })
+}
+
+/// Configures the exported sources for the profile the first time, then
+/// builds them
+fn build(checkout: &Checkout, profile: &str, jobs: Option<usize>) -> Result<()> {
+    let build_dir = checkout.build_dir(profile);
+    if cmake::cache_value(&build_dir, "CMAKE_HOME_DIRECTORY").is_none() {
+        let configure = ConfigureArgs {
+            profile: Some(profile.to_string()),
+            ..Default::default()
+        };
+        handle_init_project(
+            &checkout.root.display().to_string(),
+            &format!("build-{}", profile),
+            false,
+            &configure,
+        )?;
+    }
+
+    eprintln!("{}", format!("Building {}", checkout.label).green());
+
+    executor().execute_observed(
+        &mut cmake::build_command(&build_dir.display().to_string(), None, jobs),
+        &mut |_, _| true,
+    )
+}
+
+/// Disassembles the project's objects compiled from `file`, or all of them,
+/// keeping the functions named `function`, or all of them. Objects of
+/// fetched dependencies are left out.
+fn disassemble(
+    objdump: &str,
+    build_dir: &Path,
+    function: Option<&str>,
+    file: Option<&str>,
+) -> Result<Listing> {
+    let mut objects = Vec::new();
+    find_objects(build_dir, &mut objects);
+    objects.sort();
+
+    let mut listing = Listing::new();
+    for object in objects {
+        let relative = object.strip_prefix(build_dir).unwrap_or(&object);
+        let Some(source) = stats::object_source(&relative.display().to_string()) else {
+            continue;
+        };
+        if file.is_some_and(|file| source != file && !source.ends_with(&format!("/{}", file))) {
+            continue;
+        }
+
+        let output = executor()
+            .capture(
+                Command::new(objdump)
+                    .args(["-d", "-C", "--no-show-raw-insn"])
+                    .arg(&object),
+            )
+            .with_context(|| format!("Failed to disassemble {}", object.display()))?;
+        let text = String::from_utf8_lossy(&output.stdout);
+        for (name, instructions) in functions(&text) {
+            if function.is_some_and(|function| !is_named(&name, function)) {
+                continue;
+            }
+            listing.entry(name).or_default().extend(instructions);
+        }
+    }
+
+    Ok(listing)
+}
+
+/// The functions of objdump's output with their instructions, without the
+/// addresses and padding that change whenever code before them does. Jump
+/// and call targets keep the symbol they point into.
+fn functions(text: &str) -> Vec<(String, Vec<String>)> {
+    let mut functions: Vec<(String, Vec<String>)> = Vec::new();
+    for line in text.lines() {
+        // e.g. `0000000000000040 <parse(char const*)>:`
+        if let Some(name) = line
+            .split_once(" <")
+            .filter(|(address, _)| address.chars().all(|c| c.is_ascii_hexdigit()))
+            .and_then(|(_, rest)| rest.strip_suffix(">:"))
+        {
+            functions.push((name.to_string(), Vec::new()));
+            continue;
+        }
+
+        // e.g. `  4a:	call   50 <helper(int)+0x4>`
+        let Some((address, instruction)) = line.split_once(":\t") else {
+            continue;
+        };
+        let address = address.trim();
+        if address.is_empty() || !address.chars().all(|c| c.is_ascii_hexdigit()) {
+            continue;
+        }
+        let Some((_, instructions)) = functions.last_mut() else {
+            continue;
+        };
+        let instruction = instruction.split_whitespace().collect::<Vec<_>>().join(" ");
+        let instruction = match instruction.split_once(" <") {
+            Some((operation, target)) => {
+                let operation = operation
+                    .rsplit_once(' ')
+                    .filter(|(_, address)| address.chars().all(|c| c.is_ascii_hexdigit()))
+                    .map_or(operation, |(operation, _)| operation);
+                format!("{} <{}", operation, target)
+            }
+            None => instruction,
+        };
+        if !instruction.is_empty() && !is_padding(&instruction) {
+            instructions.push(instruction);
+        }
+    }
+
+    functions
+}
+
+/// Whether an instruction only aligns the code after it, e.g. a loop head
+/// or the next function, which shifts with any change before it
+fn is_padding(instruction: &str) -> bool {
+    let operation = instruction
+        .split_whitespace()
+        .find(|token| !matches!(*token, "data16" | "cs" | "ds"))
+        .unwrap_or_default();
+
+    operation.starts_with("nop") || instruction == "xchg %ax,%ax" || instruction == "int3"
+}
+
+/// Whether a demangled function is the one asked for, with or without its
+/// namespace and parameters, e.g. `app::parse(char const*)` for `parse`
+fn is_named(demangled: &str, function: &str) -> bool {
+    let name = demangled.split('(').next().unwrap_or(demangled);
+
+    demangled == function || name == function || name.ends_with(&format!("::{}", function))
+}
+
+fn render(listing: &Listing) -> String {
+    let mut text = String::new();
+    for (name, instructions) in listing {
+        text.push_str(&format!("{}:\n", name));
+        for instruction in instructions {
+            text.push_str(&format!("\t{}\n", instruction));
+        }
+        text.push('\n');
+    }
+
+    text
+}
+
+fn find_objects(dir: &Path, objects: &mut Vec<PathBuf>) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+
+    for path in entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+    {
+        if path.is_dir() {
+            if !path.ends_with("_deps") {
+                find_objects(&path, objects);
+            }
+        } else if path
+            .extension()
+            .is_some_and(|extension| extension == "o" || extension == "obj")
+        {
+            objects.push(path);
+        }
+    }
+}
+
+fn is_empty(dir: &Path) -> bool {
+    fs::read_dir(dir).map_or(true, |mut entries| entries.next().is_none())
+}
+
+fn git(args: &[&str]) -> Option<String> {
+    let output = executor().capture(Command::new("git").args(args)).ok()?;
+
+    output
+        .status
+        .success()
+        .then(|| String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+fn has_tool(tool: &str) -> bool {
+    executor()
+        .capture(Command::new(tool).arg("--version"))
+        .is_ok()
+}
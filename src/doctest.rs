@@ -0,0 +1,254 @@
+use anyhow::{Context, Result};
+use colorize::AnsiColor;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use crate::{cmake, executor::executor, layout, manifest::Manifest, sources};
+
+const README: &str = "README.md";
+
+/// A fenced code block from the README or a header comment
+struct DocTest {
+    file: PathBuf,
+    line: usize,
+    c: bool,
+    /// Compile the block but never run it
+    no_run: bool,
+    code: String,
+}
+
+/// Compile-checks every C/C++ code block in the README and in header
+/// comments, running the ones that build into a program when asked to
+pub fn handle_doc_tests(build_dir: String, run: bool) -> Result<()> {
+    let mut tests = Vec::new();
+
+    if let Ok(readme) = fs::read_to_string(README) {
+        tests.extend(extract(
+            Path::new(README),
+            readme.lines().map(Some).enumerate(),
+        ));
+    }
+    for header in sources::project_sources()?
+        .into_iter()
+        .filter(|file| sources::is_header(file))
+    {
+        let text = fs::read_to_string(&header)
+            .with_context(|| format!("Failed to read '{}'", header.display()))?;
+        tests.extend(extract(&header, text.lines().map(comment_text).enumerate()));
+    }
+
+    if tests.is_empty() {
+        eprintln!("{}", "No documentation code blocks found".yellow());
+        return Ok(());
+    }
+
+    let out_dir = Path::new(&build_dir).join("doctests");
+    fs::create_dir_all(&out_dir).context("Failed to create doc test directory")?;
+
+    let mut failed = 0;
+    for (index, test) in tests.iter().enumerate() {
+        let location = format!("{}:{}", test.file.display(), test.line);
+
+        let output = out_dir.join(format!("doctest_{}", index));
+        match run_doc_test(test, Path::new(&build_dir), &output, run) {
+            Ok(()) => eprintln!("{} ... {}", location, "ok".green()),
+            Err(error) => {
+                failed += 1;
+                eprintln!("{} ... {}\n{:#}", location, "FAILED".red(), error);
+            }
+        }
+    }
+
+    if failed > 0 {
+        anyhow::bail!("{} of {} doc tests failed", failed, tests.len());
+    }
+    eprintln!(
+        "{}",
+        format!("All {} doc tests passed", tests.len()).green()
+    );
+
+    Ok(())
+}
+
+/// The text of a comment line with its markers removed, `None` for code
+fn comment_text(line: &str) -> Option<&str> {
+    let line = line.trim_start();
+    let text = ["///", "//!", "//", "*"]
+        .into_iter()
+        .find_map(|marker| line.strip_prefix(marker))?;
+
+    Some(text.strip_prefix(' ').unwrap_or(text))
+}
+
+/// Collects the C/C++ fences from numbered lines. A `None` line, i.e. code
+/// between doc comments, ends any open block.
+fn extract<'a>(file: &Path, lines: impl Iterator<Item = (usize, Option<&'a str>)>) -> Vec<DocTest> {
+    let mut tests = Vec::new();
+    let mut open: Option<(DocTest, bool)> = None;
+
+    for (index, line) in lines {
+        let Some(line) = line else {
+            open = None;
+            continue;
+        };
+
+        let trimmed = line.trim();
+        match open.take() {
+            Some((test, keep)) if trimmed.starts_with("```") => {
+                if keep {
+                    tests.push(test);
+                }
+            }
+            Some((mut test, keep)) => {
+                test.code.push_str(line);
+                test.code.push('\n');
+                open = Some((test, keep));
+            }
+            None => {
+                let Some(info) = trimmed.strip_prefix("```") else {
+                    continue;
+                };
+                let mut attributes = info.split([',', ' ']).filter(|part| !part.is_empty());
+                let (c, tested) = match attributes.next() {
+                    Some("c") => (true, true),
+                    Some("cpp" | "c++" | "cxx") => (false, true),
+                    _ => (false, false),
+                };
+                let attributes = attributes.collect::<Vec<_>>();
+
+                let test = DocTest {
+                    file: file.to_path_buf(),
+                    line: index + 1,
+                    c,
+                    no_run: attributes.contains(&"no_run"),
+                    code: String::new(),
+                };
+                open = Some((test, tested && !attributes.contains(&"ignore")));
+            }
+        }
+    }
+
+    tests
+}
+
+/// Wraps a block into a translation unit. Blocks without a `main` become
+/// its body, and blocks from headers include the header they document.
+fn translation_unit(test: &DocTest) -> Result<String> {
+    let mut includes = String::new();
+    if sources::is_header(&test.file) {
+        let header = fs::canonicalize(&test.file)
+            .with_context(|| format!("Failed to resolve '{}'", test.file.display()))?;
+        includes.push_str(&format!("#include \"{}\"\n", header.display()));
+    }
+
+    if test.code.contains("main(") {
+        return Ok(format!("{}{}", includes, test.code));
+    }
+
+    let mut body = String::new();
+    for line in test.code.lines() {
+        if line.trim_start().starts_with('#') {
+            includes.push_str(line);
+            includes.push('\n');
+        } else {
+            body.push_str(&format!("  {}\n", line));
+        }
+    }
+
+    Ok(format!(
+        "{}\nint main() {{\n{}  return 0;\n}}\n",
+        includes, body
+    ))
+}
+
+fn run_doc_test(test: &DocTest, build_dir: &Path, output: &Path, run: bool) -> Result<()> {
+    let source = output.with_extension(if test.c { "c" } else { "cpp" });
+    fs::write(&source, translation_unit(test)?).context("Failed to write doc test source")?;
+
+    let link = run && !test.no_run;
+    let compiler = compiler(build_dir, test.c);
+    let mut command = Command::new(&compiler);
+    command
+        .arg(format!("-std={}", language_standard(test.c)))
+        .arg(format!("-I{}", layout::include_dir()?))
+        .arg(format!("-I{}", layout::src_dir()?))
+        .arg(&source);
+    if link {
+        command.arg("-o").arg(output);
+    } else {
+        command.arg("-fsyntax-only");
+    }
+
+    let result = executor().capture(&mut command)?;
+    if !result.status.success() {
+        anyhow::bail!("{}", String::from_utf8_lossy(&result.stderr));
+    }
+
+    if link {
+        let result = executor()
+            .capture(&mut Command::new(output))
+            .context("Failed to run doc test")?;
+        if !result.status.success() {
+            anyhow::bail!(
+                "Exited with {}\n{}",
+                result.status,
+                String::from_utf8_lossy(&result.stderr)
+            );
+        }
+    }
+
+    Ok(())
+}
+
+/// The compiler CMake picked for the build directory, so doc tests see the
+/// same toolchain as the project
+pub fn compiler(build_dir: &Path, c: bool) -> String {
+    let (key, fallback) = if c {
+        ("CMAKE_C_COMPILER", "cc")
+    } else {
+        ("CMAKE_CXX_COMPILER", "c++")
+    };
+
+    cmake::cache_value(build_dir, key)
+        .filter(|compiler| !compiler.is_empty())
+        .unwrap_or_else(|| fallback.to_string())
+}
+
+/// The language standard of the manifest or else the one set in
+/// CMakeLists.txt, e.g. `c++23`
+pub fn language_standard(c: bool) -> String {
+    if let Some(standard) = Manifest::load()
+        .ok()
+        .filter(|manifest| {
+            manifest
+                .standard_variable()
+                .is_some_and(|(variable, _)| (variable == "CMAKE_C_STANDARD") == c)
+        })
+        .and_then(|manifest| manifest.package.standard)
+    {
+        return standard.to_ascii_lowercase();
+    }
+
+    let (variable, prefix, default) = if c {
+        ("CMAKE_C_STANDARD", "c", "17")
+    } else {
+        ("CMAKE_CXX_STANDARD", "c++", "23")
+    };
+    let lists = fs::read_to_string(cmake::CMAKE_LISTS).unwrap_or_default();
+
+    let version = lists
+        .lines()
+        .find_map(|line| {
+            line.trim()
+                .strip_prefix(&format!("set({} ", variable))?
+                .strip_suffix(')')
+                .map(str::trim)
+                .map(str::to_string)
+        })
+        .unwrap_or_else(|| default.to_string());
+
+    format!("{}{}", prefix, version)
+}
@@ -0,0 +1,169 @@
+use anyhow::{Context, Result};
+use colorize::AnsiColor;
+use std::{collections::BTreeMap, fs};
+
+use crate::manifest::{Manifest, RunConfig};
+
+/// Local, usually uncommitted, environment overrides in the project root
+pub const DOTENV_FILE: &str = ".env";
+
+/// Resolves the variables cpp_tools sets for the executable on top of the
+/// inherited environment. Later sources win: the manifest's `[env]` table,
+/// then the `.env` file, then the selected run configuration.
+pub fn project_env(
+    manifest: &Manifest,
+    run_config: &RunConfig,
+) -> Result<BTreeMap<String, String>> {
+    let mut env = manifest.env.clone();
+
+    env.extend(load_dotenv()?);
+    env.extend(run_config.env.clone());
+
+    Ok(env)
+}
+
+fn load_dotenv() -> Result<BTreeMap<String, String>> {
+    if fs::metadata(DOTENV_FILE).is_err() {
+        return Ok(BTreeMap::new());
+    }
+
+    let contents = fs::read_to_string(DOTENV_FILE)
+        .with_context(|| format!("Failed to read {}", DOTENV_FILE))?;
+
+    parse_dotenv(&contents)
+}
+
+fn parse_dotenv(contents: &str) -> Result<BTreeMap<String, String>> {
+    let mut env = BTreeMap::new();
+
+    for (number, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let line = line.strip_prefix("export ").unwrap_or(line);
+        let (key, value) = line.split_once('=').with_context(|| {
+            format!(
+                "Invalid line {} in {}, expected KEY=VALUE",
+                number + 1,
+                DOTENV_FILE
+            )
+        })?;
+        let key = key.trim();
+        if key.is_empty() || key.contains(char::is_whitespace) {
+            anyhow::bail!(
+                "Invalid variable name '{}' on line {} in {}",
+                key,
+                number + 1,
+                DOTENV_FILE
+            );
+        }
+
+        env.insert(key.to_string(), unquote(value.trim()));
+    }
+
+    Ok(env)
+}
+
+fn unquote(value: &str) -> String {
+    for quote in ['"', '\''] {
+        let Some((quoted, rest)) = value
+            .strip_prefix(quote)
+            .and_then(|value| value.split_once(quote))
+        else {
+            continue;
+        };
+        // A quoted value may be followed by a comment
+        let rest = rest.trim_start();
+        if rest.is_empty() || rest.starts_with('#') {
+            return quoted.to_string();
+        }
+    }
+
+    // Unquoted values may carry a trailing comment
+    match value.find(" #") {
+        Some(index) => value[..index].trim_end().to_string(),
+        None => value.to_string(),
+    }
+}
+
+pub fn handle_env_project(config: Option<String>, all: bool) -> Result<()> {
+    let manifest = Manifest::load()?;
+    let run_config = match config {
+        Some(name) => manifest.run_config(&name)?.clone(),
+        None => RunConfig::default(),
+    };
+    let overrides = project_env(&manifest, &run_config)?;
+
+    if all {
+        let mut env = std::env::vars().collect::<BTreeMap<_, _>>();
+        env.extend(overrides);
+        for (key, value) in env {
+            println!("{}={}", key, value);
+        }
+    } else {
+        for (key, value) in overrides {
+            println!("{}={}", key, value);
+        }
+        eprintln!(
+            "{}",
+            "Set for the executable by run and gdbserver, and for the tests by test".grey()
+        );
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_quoted_and_exported_values() {
+        let env = parse_dotenv(
+            "export A=1\nB=\"two words\"\nC='single'\n  D = spaced  \nE=\"\"\nF=a=b\n",
+        )
+        .unwrap();
+
+        assert_eq!(
+            env.into_iter().collect::<Vec<_>>(),
+            [
+                ("A", "1"),
+                ("B", "two words"),
+                ("C", "single"),
+                ("D", "spaced"),
+                ("E", ""),
+                ("F", "a=b"),
+            ]
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+        );
+    }
+
+    #[test]
+    fn skips_comments() {
+        let env = parse_dotenv(
+            "# Local settings\n\nA=1 # note\nB=\"quoted\" # note\nC='a # b'\nD=\"a\"#note\nE=a#b\n",
+        )
+        .unwrap();
+
+        assert_eq!(env["A"], "1");
+        assert_eq!(env["B"], "quoted");
+        assert_eq!(env["C"], "a # b");
+        assert_eq!(env["D"], "a");
+        assert_eq!(env["E"], "a#b");
+        assert_eq!(env.len(), 5);
+    }
+
+    #[test]
+    fn rejects_malformed_lines() {
+        let error = parse_dotenv("A=1\nNOT_AN_ASSIGNMENT\n").unwrap_err();
+        assert!(error.to_string().contains("line 2"), "{}", error);
+
+        let error = parse_dotenv("=value\n").unwrap_err();
+        assert!(error.to_string().contains("line 1"), "{}", error);
+
+        let error = parse_dotenv("MY VAR=1\n").unwrap_err();
+        assert!(error.to_string().contains("'MY VAR'"), "{}", error);
+    }
+}
@@ -0,0 +1,139 @@
+use anyhow::{Context, Result};
+use serde::Serialize;
+use std::{
+    fs::OpenOptions,
+    io::Write,
+    net::TcpStream,
+    sync::{Mutex, OnceLock},
+};
+
+use crate::output::{
+    parse_compile_step, parse_diagnostic, parse_test_result, strip_ansi, CompileStep, Diagnostic,
+    TestResult,
+};
+
+/// What happened in a running command, sent to the event stream as a
+/// JSON-RPC notification named after the variant, e.g. `compile-file`
+#[derive(Serialize)]
+#[serde(tag = "method", content = "params", rename_all = "kebab-case")]
+pub enum Event {
+    ConfigureStarted {
+        build_dir: String,
+    },
+    ConfigureFinished {
+        build_dir: String,
+        success: bool,
+        seconds: f64,
+    },
+    BuildStarted {
+        build_dir: String,
+    },
+    CompileFile(CompileStep),
+    Diagnostic(Diagnostic),
+    BuildFinished {
+        build_dir: String,
+        success: bool,
+        seconds: f64,
+    },
+    TestsStarted {
+        build_dir: String,
+    },
+    TestCaseFinished(TestResult),
+    TestsFinished {
+        build_dir: String,
+        success: bool,
+        seconds: f64,
+    },
+}
+
+#[derive(Serialize)]
+struct Notification<'a> {
+    jsonrpc: &'static str,
+    #[serde(flatten)]
+    event: &'a Event,
+}
+
+static STREAM: OnceLock<Mutex<Box<dyn Write + Send>>> = OnceLock::new();
+
+/// Opens the event stream: `fd:<N>` writes to a file descriptor the caller
+/// left open, `unix:<PATH>` and `tcp:<HOST>:<PORT>` connect to a socket
+/// an IDE plugin or dashboard listens on
+pub fn install(target: &str) -> Result<()> {
+    let stream: Box<dyn Write + Send> = if let Some(fd) = target.strip_prefix("fd:") {
+        let fd = fd
+            .parse::<u32>()
+            .with_context(|| format!("Invalid file descriptor '{}'", fd))?;
+        let file = OpenOptions::new()
+            .write(true)
+            .open(format!("/dev/fd/{}", fd))
+            .with_context(|| format!("File descriptor {} isn't open for writing", fd))?;
+        Box::new(file)
+    } else if let Some(path) = target.strip_prefix("unix:") {
+        unix_socket(path)?
+    } else if let Some(address) = target.strip_prefix("tcp:") {
+        let socket = TcpStream::connect(address)
+            .with_context(|| format!("Failed to connect to the event stream at {}", address))?;
+        Box::new(socket)
+    } else {
+        anyhow::bail!(
+            "Invalid event stream '{}', expected fd:<N>, unix:<PATH> or tcp:<HOST>:<PORT>",
+            target
+        );
+    };
+
+    let _ = STREAM.set(Mutex::new(stream));
+    Ok(())
+}
+
+#[cfg(unix)]
+fn unix_socket(path: &str) -> Result<Box<dyn Write + Send>> {
+    let socket = std::os::unix::net::UnixStream::connect(path)
+        .with_context(|| format!("Failed to connect to the event stream at {}", path))?;
+    Ok(Box::new(socket))
+}
+
+#[cfg(not(unix))]
+fn unix_socket(_path: &str) -> Result<Box<dyn Write + Send>> {
+    anyhow::bail!("Unix sockets aren't supported on this platform, use tcp:<HOST>:<PORT>")
+}
+
+/// Whether an event stream is open, so output is worth reading
+pub fn enabled() -> bool {
+    STREAM.get().is_some()
+}
+
+/// Sends an event as one line of JSON. A reader that went away doesn't fail
+/// the command.
+pub fn emit(event: Event) {
+    let Some(stream) = STREAM.get() else {
+        return;
+    };
+    let notification = Notification {
+        jsonrpc: "2.0",
+        event: &event,
+    };
+    let Ok(line) = serde_json::to_string(&notification) else {
+        return;
+    };
+
+    let mut stream = stream
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    let _ = writeln!(stream, "{}", line).and_then(|_| stream.flush());
+}
+
+/// Emits the events a line of build or test output describes
+pub fn observe(line: &str) {
+    if !enabled() {
+        return;
+    }
+
+    let line = strip_ansi(line);
+    if let Some(step) = parse_compile_step(&line) {
+        emit(Event::CompileFile(step));
+    } else if let Some(diagnostic) = parse_diagnostic(&line) {
+        emit(Event::Diagnostic(diagnostic));
+    } else if let Some(test) = parse_test_result(&line) {
+        emit(Event::TestCaseFinished(test));
+    }
+}
@@ -0,0 +1,134 @@
+use anyhow::{Context, Result};
+use colorize::AnsiColor;
+use std::{fs, path::Path};
+
+use crate::{cmake, import_std, layout, validate_project_name, FileExtension};
+
+/// Directory holding one source file per example
+pub const EXAMPLES_DIR: &str = "examples";
+
+/// Target that builds every example
+pub const EXAMPLES_TARGET: &str = "examples";
+
+/// The example a new library project starts out with, using the library
+pub const CONSUMER_EXAMPLE: &str = "basic";
+
+/// Examples are excluded from the default build and placed in their own
+/// directory next to the project executable
+const EXAMPLES_FRAGMENT: &str = "# Generated by cxx add example, do not edit.
+file(GLOB EXAMPLE_SOURCES CONFIGURE_DEPENDS
+  ${CMAKE_CURRENT_SOURCE_DIR}/examples/*.c
+  ${CMAKE_CURRENT_SOURCE_DIR}/examples/*.cpp)
+
+get_target_property(PROJECT_TARGET_TYPE ${PROJECT_NAME} TYPE)
+set(EXAMPLE_TARGETS)
+foreach(example_source ${EXAMPLE_SOURCES})
+  get_filename_component(example ${example_source} NAME_WE)
+  add_executable(example_${example} EXCLUDE_FROM_ALL ${example_source})
+  set_target_properties(example_${example} PROPERTIES
+    OUTPUT_NAME ${example}
+    RUNTIME_OUTPUT_DIRECTORY ${CMAKE_RUNTIME_OUTPUT_DIRECTORY}/examples)
+  # Examples of library projects use the library
+  if(NOT PROJECT_TARGET_TYPE STREQUAL \"EXECUTABLE\")
+    target_link_libraries(example_${example} PRIVATE ${PROJECT_NAME})
+  endif()
+  list(APPEND EXAMPLE_TARGETS example_${example})
+endforeach()
+
+add_custom_target(examples DEPENDS ${EXAMPLE_TARGETS})
+";
+
+/// The CMake target of a single example
+pub fn example_target(name: &str) -> String {
+    format!("example_{}", name)
+}
+
+pub fn handle_add_example(name: String) -> Result<()> {
+    validate_project_name(&name).context("Invalid example name")?;
+
+    let c_project = cmake::is_c_project()?;
+    let extension = if c_project { "c" } else { "cpp" };
+    let path = Path::new(EXAMPLES_DIR).join(format!("{}.{}", name, extension));
+    if path.exists() {
+        anyhow::bail!("Example '{}' already exists", name);
+    }
+
+    let contents = if c_project {
+        "#include <stdio.h>
+
+int main(void) {
+  printf(\"Hello from an example!\\n\");
+  return 0;
+}
+"
+    } else if import_std::uses_import_std() {
+        "import std;
+
+int main() {
+  std::cout << \"Hello from an example!\" << std::endl;
+  return 0;
+}
+"
+    } else {
+        "#include <iostream>
+
+int main() {
+  std::cout << \"Hello from an example!\" << std::endl;
+  return 0;
+}
+"
+    };
+
+    fs::create_dir_all(EXAMPLES_DIR).context("Failed to create examples directory")?;
+    fs::write(&path, contents).context("Failed to create example source file")?;
+    cmake::write_fragment("examples.cmake", EXAMPLES_FRAGMENT)?;
+
+    eprintln!(
+        "{}",
+        format!(
+            "Added example '{}', run it with 'cxx run --example {}'",
+            name, name
+        )
+        .green()
+    );
+
+    Ok(())
+}
+
+/// Writes the example of a new library project, which prints the greeting
+/// of the library's public header
+pub fn write_consumer(name: &str, project_dir: &str, file_ext: &FileExtension) -> Result<()> {
+    let namespace = layout::identifier(name);
+    let contents = match file_ext {
+        FileExtension::Cpp => format!(
+            "#include <iostream>
+#include <{name}/{name}.hpp>
+
+int main() {{
+  std::cout << {namespace}::greeting() << std::endl;
+  return 0;
+}}
+"
+        ),
+        FileExtension::C => format!(
+            "#include <stdio.h>
+#include <{name}/{name}.h>
+
+int main(void) {{
+  printf(\"%s\\n\", {namespace}_greeting());
+  return 0;
+}}
+"
+        ),
+    };
+
+    let dir = Path::new(project_dir).join(EXAMPLES_DIR);
+    fs::create_dir_all(&dir).context("Failed to create examples directory")?;
+    fs::write(
+        dir.join(format!("{}.{}", CONSUMER_EXAMPLE, file_ext)),
+        contents,
+    )
+    .context("Failed to create example source file")?;
+
+    cmake::write_fragment_in(Path::new(project_dir), "examples.cmake", EXAMPLES_FRAGMENT)
+}
@@ -0,0 +1,432 @@
+use anyhow::{Context, Result};
+use std::{
+    fmt,
+    io::{BufRead, BufReader, Read, Write},
+    path::Path,
+    process::{Command, ExitStatus, Output, Stdio},
+    sync::{mpsc, OnceLock},
+    thread,
+};
+
+/// Shell that command lines are run with unless configured otherwise
+pub const DEFAULT_SHELL: &str = if cfg!(windows) { "cmd" } else { "bash" };
+
+/// Runs the external commands cxx invokes, so they can be printed instead
+/// of executed or recorded in tests. Programs such as `cmake`, `git` and the
+/// project's executables are run directly with their arguments; command
+/// lines only when they need a shell, e.g. for a pipe or a user's wrapper.
+pub trait Executor: Send + Sync {
+    /// Runs a command line through the shell with the terminal attached,
+    /// e.g. an editor or the user's own command. Fails with
+    /// [`CommandFailed`] when the command does.
+    fn run(&self, command: &str) -> Result<()>;
+
+    /// Runs a program without a shell, so its arguments reach it as they
+    /// are, in the working directory and with the environment set on
+    /// `command` and the terminal attached. Fails with [`CommandFailed`]
+    /// when the program does.
+    fn execute(&self, command: &mut Command) -> Result<()>;
+
+    /// Runs a program like `execute`, with the terminal attached even when
+    /// tools are quiet, for what the user asked to see, e.g. the project's
+    /// executable
+    fn execute_interactive(&self, command: &mut Command) -> Result<()> {
+        self.execute(command)
+    }
+
+    /// Runs a program like `execute`, passing every line it prints to
+    /// `observe` with whether it went to stderr. `observe` returns whether
+    /// the line is shown on the terminal.
+    fn execute_observed(
+        &self,
+        command: &mut Command,
+        observe: &mut dyn FnMut(&str, bool) -> bool,
+    ) -> Result<()> {
+        let _ = observe;
+        self.execute(command)
+    }
+
+    /// The command running a command line with the shell, for what only a
+    /// shell can run, e.g. a build backend's command line
+    fn shell(&self, command: &str) -> Command {
+        shell_command(DEFAULT_SHELL, command)
+    }
+
+    /// Runs a program that only inspects the project, e.g. `git rev-parse`,
+    /// and captures its output
+    fn capture(&self, command: &mut Command) -> Result<Output>;
+
+    /// Whether commands are only printed, so nothing should be recorded
+    fn dry_run(&self) -> bool {
+        false
+    }
+}
+
+/// Executes programs, and command lines with the configured shell. Quiet,
+/// the output of programs run as tools is only shown when they fail.
+pub struct ProcessExecutor {
+    shell: String,
+    scripted: bool,
+    quiet: bool,
+}
+
+impl ProcessExecutor {
+    pub fn new(shell: String) -> ProcessExecutor {
+        ProcessExecutor {
+            shell,
+            scripted: false,
+            quiet: false,
+        }
+    }
+
+    /// Executes commands for a script reading cxx's stdout: what they print
+    /// goes to stderr, and they can't prompt, since stdin is empty and git
+    /// doesn't ask for credentials
+    pub fn scripted(shell: String) -> ProcessExecutor {
+        ProcessExecutor {
+            shell,
+            scripted: true,
+            quiet: false,
+        }
+    }
+
+    /// Holds back what tools print unless they fail
+    pub fn quiet(self, quiet: bool) -> ProcessExecutor {
+        ProcessExecutor { quiet, ..self }
+    }
+
+    fn prepare<'a>(&self, command: &'a mut Command) -> &'a mut Command {
+        if self.scripted {
+            command
+                .stdin(Stdio::null())
+                .stdout(std::io::stderr())
+                .env("GIT_TERMINAL_PROMPT", "0");
+        }
+
+        command
+    }
+}
+
+impl Executor for ProcessExecutor {
+    fn run(&self, command: &str) -> Result<()> {
+        let status = self
+            .prepare(&mut self.shell(command))
+            .status()
+            .context("Failed to run command")?;
+        if !status.success() {
+            return Err(CommandFailed(status).into());
+        }
+
+        Ok(())
+    }
+
+    fn execute(&self, command: &mut Command) -> Result<()> {
+        if !self.quiet {
+            return self.execute_interactive(command);
+        }
+
+        let output = self
+            .prepare(command)
+            .stdin(Stdio::null())
+            .output()
+            .with_context(|| format!("Failed to run {:?}", command.get_program()))?;
+        if !output.status.success() {
+            let mut stderr = std::io::stderr();
+            let _ = stderr.write_all(&output.stdout);
+            let _ = stderr.write_all(&output.stderr);
+            return Err(CommandFailed(output.status).into());
+        }
+
+        Ok(())
+    }
+
+    fn execute_interactive(&self, command: &mut Command) -> Result<()> {
+        let status = self
+            .prepare(command)
+            .status()
+            .with_context(|| format!("Failed to run {:?}", command.get_program()))?;
+        if !status.success() {
+            return Err(CommandFailed(status).into());
+        }
+
+        Ok(())
+    }
+
+    fn execute_observed(
+        &self,
+        command: &mut Command,
+        observe: &mut dyn FnMut(&str, bool) -> bool,
+    ) -> Result<()> {
+        // Ninja and CMake's color diagnostics keep their colors when piped
+        let mut child = self
+            .prepare(command)
+            .env("CLICOLOR_FORCE", "1")
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .with_context(|| format!("Failed to run {:?}", command.get_program()))?;
+
+        let (sender, receiver) = mpsc::channel();
+        let stdout = child.stdout.take().map(|stdout| {
+            let sender = sender.clone();
+            thread::spawn(move || forward(stdout, false, sender))
+        });
+        let stderr = child
+            .stderr
+            .take()
+            .map(|stderr| thread::spawn(move || forward(stderr, true, sender)));
+
+        let show = |line: &str, error: bool| match error || self.scripted {
+            true => eprintln!("{}", line),
+            false => println!("{}", line),
+        };
+        let mut held = Vec::new();
+        for (line, error) in receiver {
+            if !observe(&line, error) {
+                continue;
+            }
+            match self.quiet {
+                true => held.push((line, error)),
+                false => show(&line, error),
+            }
+        }
+        for reader in [stdout, stderr].into_iter().flatten() {
+            let _ = reader.join();
+        }
+
+        let status = child.wait().context("Failed to wait on command")?;
+        if !status.success() {
+            for (line, error) in held {
+                show(&line, error);
+            }
+            return Err(CommandFailed(status).into());
+        }
+
+        Ok(())
+    }
+
+    fn shell(&self, command: &str) -> Command {
+        shell_command(&self.shell, command)
+    }
+
+    fn capture(&self, command: &mut Command) -> Result<Output> {
+        command
+            .output()
+            .with_context(|| format!("Failed to run {:?}", command.get_program()))
+    }
+}
+
+/// A command that ran and exited unsuccessfully, e.g. to tell its exit code
+/// apart from failing to start it
+#[derive(Debug)]
+pub struct CommandFailed(pub ExitStatus);
+
+impl fmt::Display for CommandFailed {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Command failed ({})", self.0)
+    }
+}
+
+impl std::error::Error for CommandFailed {}
+
+impl CommandFailed {
+    /// The exit code cxx passes on for the command: its own, or 128 plus
+    /// the signal that killed it, like shells report
+    pub fn exit_code(&self) -> u8 {
+        #[cfg(unix)]
+        if let Some(signal) = std::os::unix::process::ExitStatusExt::signal(&self.0) {
+            return u8::try_from(128 + signal).unwrap_or(1);
+        }
+
+        self.0
+            .code()
+            .and_then(|code| u8::try_from(code).ok())
+            .filter(|code| *code != 0)
+            .unwrap_or(1)
+    }
+}
+
+/// Sends a command's output on line by line to be observed, with whether it
+/// came from stderr
+fn forward(reader: impl Read, error: bool, sender: mpsc::Sender<(String, bool)>) {
+    for line in BufReader::new(reader).lines().map_while(|line| line.ok()) {
+        let _ = sender.send((line, error));
+    }
+}
+
+/// Prints command lines instead of running them. Inspecting commands still
+/// run, since what would be executed can depend on their output.
+pub struct DryRunExecutor;
+
+impl Executor for DryRunExecutor {
+    fn run(&self, command: &str) -> Result<()> {
+        println!("{}", command);
+
+        Ok(())
+    }
+
+    fn execute(&self, command: &mut Command) -> Result<()> {
+        println!("{}", display(command));
+
+        Ok(())
+    }
+
+    fn dry_run(&self) -> bool {
+        true
+    }
+
+    fn capture(&self, command: &mut Command) -> Result<Output> {
+        command
+            .output()
+            .with_context(|| format!("Failed to run {:?}", command.get_program()))
+    }
+}
+
+/// Records the programs and arguments of commands instead of running them,
+/// for tests of the commands cxx builds. Clones share what was recorded.
+/// Inspecting commands still run, like in a dry run.
+#[cfg(test)]
+#[derive(Clone, Default)]
+pub struct RecordingExecutor {
+    commands: std::sync::Arc<std::sync::Mutex<Vec<Vec<String>>>>,
+}
+
+#[cfg(test)]
+impl RecordingExecutor {
+    /// Takes the commands recorded so far, each as its program followed by
+    /// its arguments
+    pub fn take(&self) -> Vec<Vec<String>> {
+        std::mem::take(
+            &mut self
+                .commands
+                .lock()
+                .unwrap_or_else(|error| error.into_inner()),
+        )
+    }
+
+    fn record(&self, command: &Command) {
+        let argv = std::iter::once(command.get_program())
+            .chain(command.get_args())
+            .map(|arg| arg.to_string_lossy().to_string())
+            .collect();
+        self.commands
+            .lock()
+            .unwrap_or_else(|error| error.into_inner())
+            .push(argv);
+    }
+}
+
+#[cfg(test)]
+impl Executor for RecordingExecutor {
+    fn run(&self, command: &str) -> Result<()> {
+        self.record(&self.shell(command));
+
+        Ok(())
+    }
+
+    fn execute(&self, command: &mut Command) -> Result<()> {
+        self.record(command);
+
+        Ok(())
+    }
+
+    fn dry_run(&self) -> bool {
+        true
+    }
+
+    fn capture(&self, command: &mut Command) -> Result<Output> {
+        command
+            .output()
+            .with_context(|| format!("Failed to run {:?}", command.get_program()))
+    }
+}
+
+static EXECUTOR: OnceLock<Box<dyn Executor>> = OnceLock::new();
+
+/// Sets the executor for the rest of the process, ignored once one is in use
+pub fn install(executor: Box<dyn Executor>) {
+    let _ = EXECUTOR.set(executor);
+}
+
+/// The installed executor, a [`ProcessExecutor`] with the default shell
+/// unless set otherwise
+pub fn executor() -> &'static dyn Executor {
+    EXECUTOR
+        .get_or_init(|| Box::new(ProcessExecutor::new(DEFAULT_SHELL.to_string())))
+        .as_ref()
+}
+
+/// The command line a command is shown as, e.g. in a dry run: the program
+/// and its arguments quoted where a shell would need it, after the
+/// directory it runs in and its environment. Command lines run with the
+/// shell are shown as they are.
+pub fn display(command: &Command) -> String {
+    let args = command
+        .get_args()
+        .map(|arg| arg.to_string_lossy())
+        .collect::<Vec<_>>();
+    let line = match args.as_slice() {
+        [flag, line] if flag == "-c" || flag == "/C" => line.to_string(),
+        _ => std::iter::once(command.get_program().to_string_lossy())
+            .chain(args)
+            .map(|arg| quote(&arg))
+            .collect::<Vec<_>>()
+            .join(" "),
+    };
+
+    let mut shown = String::new();
+    if let Some(dir) = command.get_current_dir() {
+        shown.push_str(&format!("cd {} && ", quote(&dir.to_string_lossy())));
+    }
+    for (key, value) in command.get_envs() {
+        if let Some(value) = value {
+            shown.push_str(&format!(
+                "{}={} ",
+                key.to_string_lossy(),
+                quote(&value.to_string_lossy())
+            ));
+        }
+    }
+    shown.push_str(&line);
+
+    shown
+}
+
+/// Quotes a value so a POSIX shell passes it through as a single word, for
+/// command lines run by a remote or device shell and files read by bash
+pub fn shell_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "'\\''"))
+}
+
+/// Quotes an argument for the shell unless it's a single plain word
+fn quote(arg: &str) -> String {
+    let plain = !arg.is_empty()
+        && arg
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-_./=:,+@%".contains(c));
+
+    match plain {
+        true => arg.to_string(),
+        false => shell_quote(arg),
+    }
+}
+
+/// The command running a command line with a shell, which is `cmd` on
+/// Windows
+fn shell_command(shell: &str, command: &str) -> Command {
+    let mut shell_command = Command::new(shell);
+    let flag = match is_cmd(shell) {
+        true => "/C",
+        false => "-c",
+    };
+    shell_command.arg(flag).arg(command);
+
+    shell_command
+}
+
+/// Whether `shell` is Windows' `cmd`, which takes `/C` instead of `-c`
+pub fn is_cmd(shell: &str) -> bool {
+    Path::new(shell)
+        .file_stem()
+        .is_some_and(|name| name.eq_ignore_ascii_case("cmd"))
+}
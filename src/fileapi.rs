@@ -0,0 +1,288 @@
+use anyhow::{Context, Result};
+use colorize::AnsiColor;
+use serde::{de::DeserializeOwned, Deserialize};
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use crate::{cmake, executor::executor, toolchain};
+
+/// Stateless query asking CMake to describe the project's targets on every
+/// configure
+const CODEMODEL_QUERY: &str = ".cmake/api/v1/query/codemodel-v2";
+
+const REPLY_DIR: &str = ".cmake/api/v1/reply";
+
+#[derive(Deserialize)]
+struct Index {
+    reply: Reply,
+}
+
+#[derive(Deserialize)]
+struct Reply {
+    #[serde(rename = "codemodel-v2")]
+    codemodel: Option<ReplyFile>,
+}
+
+#[derive(Deserialize)]
+struct ReplyFile {
+    #[serde(rename = "jsonFile")]
+    json_file: String,
+}
+
+#[derive(Deserialize)]
+struct Codemodel {
+    configurations: Vec<Configuration>,
+}
+
+#[derive(Deserialize)]
+struct Configuration {
+    name: String,
+    targets: Vec<ReplyTarget>,
+}
+
+#[derive(Deserialize)]
+struct ReplyTarget {
+    #[serde(rename = "jsonFile")]
+    json_file: String,
+}
+
+#[derive(Deserialize)]
+struct TargetReply {
+    name: String,
+    #[serde(rename = "type")]
+    kind: String,
+    #[serde(default)]
+    artifacts: Vec<Artifact>,
+    #[serde(default)]
+    sources: Vec<Source>,
+    #[serde(default)]
+    dependencies: Vec<Dependency>,
+}
+
+#[derive(Deserialize)]
+struct Dependency {
+    /// e.g. `core::@6890427a1f51a3e7e1df`
+    id: String,
+}
+
+#[derive(Deserialize)]
+struct Artifact {
+    path: PathBuf,
+}
+
+#[derive(Deserialize)]
+struct Source {
+    path: PathBuf,
+}
+
+/// An executable target of a configured build directory
+pub struct Executable {
+    pub name: String,
+    /// Relative to the build directory unless CMake builds it outside of it
+    pub path: PathBuf,
+    /// Relative to the project root unless they are outside of it
+    pub sources: Vec<PathBuf>,
+}
+
+/// Asks CMake to describe the targets the next time the build directory is
+/// configured
+pub fn write_query(build_dir: &Path) -> Result<()> {
+    let query = build_dir.join(CODEMODEL_QUERY);
+    fs::create_dir_all(query.parent().expect("query path has a parent"))
+        .context("Failed to create CMake File API query directory")?;
+    fs::write(&query, "").context("Failed to write CMake File API query")
+}
+
+/// The targets of the build type the directory builds by default. If CMake
+/// hasn't answered the query yet, the directory is reconfigured once when
+/// `reconfigure` is set, or no targets are returned.
+fn targets(build_dir: &Path, reconfigure: bool) -> Result<Vec<TargetReply>> {
+    let codemodel = match read_codemodel(build_dir)? {
+        Some(codemodel) => codemodel,
+        None if !reconfigure => return Ok(Vec::new()),
+        None => {
+            write_query(build_dir)?;
+            executor()
+                .execute(&mut cmake::configure_command(
+                    &build_dir.display().to_string(),
+                    &[],
+                ))
+                .context("Failed to reconfigure project")?;
+            read_codemodel(build_dir)?.with_context(|| {
+                format!(
+                    "CMake didn't describe the targets of '{}', it needs CMake 3.14 or newer",
+                    build_dir.display()
+                )
+            })?
+        }
+    };
+
+    let config = toolchain::build_config(build_dir)
+        .or_else(|| cmake::cache_value(build_dir, "CMAKE_BUILD_TYPE"))
+        .unwrap_or_default();
+    let configuration = codemodel
+        .configurations
+        .iter()
+        .find(|configuration| configuration.name == config)
+        .or_else(|| codemodel.configurations.first())
+        .context("CMake reported no build configurations")?;
+
+    configuration
+        .targets
+        .iter()
+        .map(|target| read_reply(build_dir, &target.json_file))
+        .collect()
+}
+
+pub fn executables(build_dir: &Path) -> Result<Vec<Executable>> {
+    let mut executables = Vec::new();
+    for target in targets(build_dir, true)? {
+        if target.kind != "EXECUTABLE" {
+            continue;
+        }
+        if let Some(artifact) = target.artifacts.into_iter().next() {
+            executables.push(Executable {
+                name: target.name,
+                path: artifact.path,
+                sources: target
+                    .sources
+                    .into_iter()
+                    .map(|source| source.path)
+                    .collect(),
+            });
+        }
+    }
+
+    Ok(executables)
+}
+
+/// The executables and libraries the project's targets build, by target,
+/// as far as the last configure described them. Fetched dependencies,
+/// built in `_deps/`, are left out.
+pub fn artifacts(build_dir: &Path) -> Result<Vec<(String, PathBuf)>> {
+    let mut artifacts = Vec::new();
+    for target in targets(build_dir, false)? {
+        if !matches!(
+            target.kind.as_str(),
+            "EXECUTABLE" | "STATIC_LIBRARY" | "SHARED_LIBRARY" | "MODULE_LIBRARY"
+        ) {
+            continue;
+        }
+        for artifact in target.artifacts {
+            if !artifact.path.starts_with("_deps") {
+                artifacts.push((target.name.clone(), artifact.path));
+            }
+        }
+    }
+
+    Ok(artifacts)
+}
+
+/// The names of the targets each target depends on directly, as far as the
+/// last configure described them
+pub fn dependencies(build_dir: &Path) -> Result<BTreeMap<String, Vec<String>>> {
+    Ok(targets(build_dir, false)?
+        .into_iter()
+        .map(|target| {
+            let names = target
+                .dependencies
+                .iter()
+                .filter_map(|dependency| dependency.id.split_once("::"))
+                .map(|(name, _)| name.to_string())
+                .collect();
+            (target.name, names)
+        })
+        .collect())
+}
+
+/// The executable named `name`, or the project's only one
+pub fn executable(build_dir: &Path, name: Option<&str>) -> Result<Executable> {
+    let executables = executables(build_dir)?;
+    let names = executables
+        .iter()
+        .map(|executable| executable.name.clone())
+        .collect::<Vec<_>>()
+        .join(", ");
+
+    match name {
+        Some(name) => executables
+            .into_iter()
+            .find(|executable| executable.name == name)
+            .with_context(|| format!("No executable target '{}', found {}", name, names)),
+        None if executables.len() == 1 => Ok(executables.into_iter().next().expect("one target")),
+        None if executables.is_empty() => anyhow::bail!("The project has no executable targets"),
+        None => anyhow::bail!(
+            "The project has several executable targets, pick one with --exec-name: {}",
+            names
+        ),
+    }
+}
+
+/// A directory of the project configured for it, e.g. by an IDE, used when
+/// `build_dir` isn't configured yet
+pub fn existing_build_dir(build_dir: &str) -> Result<String> {
+    if cmake::cache_value(Path::new(build_dir), "CMAKE_HOME_DIRECTORY").is_some() {
+        return Ok(build_dir.to_string());
+    }
+
+    let root = fs::canonicalize(".").context("Failed to resolve project directory")?;
+    let mut candidates = Vec::new();
+    for parent in [Path::new("."), Path::new("out/build")] {
+        let Ok(entries) = fs::read_dir(parent) else {
+            continue;
+        };
+        candidates.extend(entries.flatten().map(|entry| entry.path()));
+    }
+    candidates.sort();
+
+    let existing = candidates.into_iter().find(|dir| {
+        cmake::cache_value(dir, "CMAKE_HOME_DIRECTORY")
+            .and_then(|home| fs::canonicalize(home).ok())
+            .is_some_and(|home| home == root)
+    });
+    match existing {
+        Some(dir) => {
+            let dir = dir.strip_prefix(".").unwrap_or(&dir).display().to_string();
+            eprintln!(
+                "{}",
+                format!("Using the existing build directory '{}'", dir).green()
+            );
+            Ok(dir)
+        }
+        None => Ok(build_dir.to_string()),
+    }
+}
+
+fn read_codemodel(build_dir: &Path) -> Result<Option<Codemodel>> {
+    let reply_dir = build_dir.join(REPLY_DIR);
+    let Ok(entries) = fs::read_dir(&reply_dir) else {
+        return Ok(None);
+    };
+    // Index files are named after the time they were written
+    let index = entries
+        .flatten()
+        .map(|entry| entry.file_name().to_string_lossy().to_string())
+        .filter(|name| name.starts_with("index-") && name.ends_with(".json"))
+        .max();
+    let Some(index) = index else {
+        return Ok(None);
+    };
+
+    let index: Index = read_reply(build_dir, &index)?;
+    index
+        .reply
+        .codemodel
+        .map(|file| read_reply(build_dir, &file.json_file))
+        .transpose()
+}
+
+fn read_reply<T: DeserializeOwned>(build_dir: &Path, file: &str) -> Result<T> {
+    let path = build_dir.join(REPLY_DIR).join(file);
+    let text = fs::read_to_string(&path)
+        .with_context(|| format!("Failed to read '{}'", path.display()))?;
+
+    serde_json::from_str(&text).with_context(|| format!("Failed to parse '{}'", path.display()))
+}
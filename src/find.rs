@@ -0,0 +1,260 @@
+use anyhow::{Context, Result};
+use colorize::AnsiColor;
+use std::{
+    collections::BTreeSet,
+    fs,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use crate::{compdb, executor::executor, sources};
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Kind {
+    Definition,
+    Declaration,
+    Reference,
+}
+
+impl Kind {
+    fn label(self) -> String {
+        match self {
+            Kind::Definition => "definition".green(),
+            Kind::Declaration => "declaration".cyan(),
+            Kind::Reference => "reference".grey(),
+        }
+    }
+}
+
+type Location = (Kind, PathBuf, usize);
+
+/// Prints where a symbol is defined, declared and referenced. Declarations
+/// come from clang's AST for every entry in the compilation database, or
+/// from ctags when clang or the database are unavailable; references are
+/// the remaining whole-word occurrences in the project sources.
+pub fn handle_find(symbol: String, build_dir: String, declarations_only: bool) -> Result<()> {
+    let name = symbol.rsplit("::").next().unwrap_or(&symbol);
+    let mut locations = index(&symbol, &build_dir)?;
+
+    if !declarations_only {
+        let declared = locations
+            .iter()
+            .map(|(_, file, line)| (file.clone(), *line))
+            .collect::<BTreeSet<_>>();
+
+        for file in sources::project_sources()? {
+            let Ok(text) = fs::read_to_string(&file) else {
+                continue;
+            };
+            for (index, line) in text.lines().enumerate() {
+                if sources::contains_word(line, name)
+                    && !declared.contains(&(file.clone(), index + 1))
+                {
+                    locations.insert((Kind::Reference, file.clone(), index + 1));
+                }
+            }
+        }
+    }
+
+    if locations.is_empty() {
+        anyhow::bail!("No occurrences of '{}' found", symbol);
+    }
+
+    for (kind, file, line) in &locations {
+        let text = fs::read_to_string(file)
+            .ok()
+            .and_then(|text| {
+                text.lines()
+                    .nth(line - 1)
+                    .map(|line| line.trim().to_string())
+            })
+            .unwrap_or_default();
+
+        println!("{}:{}: {}  {}", file.display(), line, kind.label(), text);
+    }
+
+    Ok(())
+}
+
+/// Where a symbol is defined or declared, definitions first, e.g. to find
+/// the header declaring a function
+pub fn declarations(symbol: &str, build_dir: &str) -> Result<Vec<(PathBuf, usize)>> {
+    Ok(index(symbol, build_dir)?
+        .into_iter()
+        .map(|(_, file, line)| (file, line))
+        .collect())
+}
+
+/// Looks the symbol's declarations up with clang's AST, or ctags when clang
+/// or the compilation database are unavailable
+fn index(symbol: &str, build_dir: &str) -> Result<BTreeSet<Location>> {
+    let name = symbol.rsplit("::").next().unwrap_or(symbol);
+    let project_dir = std::env::current_dir().context("Failed to read current directory")?;
+
+    let database = Path::new(build_dir).join(compdb::COMPILE_COMMANDS);
+    if database.exists() && has_tool("clang") {
+        clang_declarations(symbol, build_dir, &project_dir)
+    } else if has_tool("ctags") {
+        ctags_declarations(name)
+    } else {
+        anyhow::bail!(
+            "Finding symbols requires clang and {} or ctags",
+            database.display()
+        );
+    }
+}
+
+fn has_tool(tool: &str) -> bool {
+    executor()
+        .capture(Command::new(tool).arg("--version"))
+        .is_ok()
+}
+
+/// Dumps the declarations matching the symbol from every translation unit
+fn clang_declarations(
+    symbol: &str,
+    build_dir: &str,
+    project_dir: &Path,
+) -> Result<BTreeSet<Location>> {
+    let mut locations = BTreeSet::new();
+
+    for command in compdb::load(build_dir)? {
+        let c = command
+            .file
+            .extension()
+            .is_some_and(|extension| extension == "c");
+        let mut arguments = command.arguments.iter().skip(1);
+        let mut clang = Command::new(if c { "clang" } else { "clang++" });
+
+        while let Some(argument) = arguments.next() {
+            match argument.as_str() {
+                "-c" => {}
+                "-o" => {
+                    arguments.next();
+                }
+                _ => {
+                    clang.arg(argument);
+                }
+            }
+        }
+
+        clang
+            .args(["-fsyntax-only", "-fno-color-diagnostics"])
+            .args(["-Xclang", "-ast-dump", "-Xclang", "-ast-dump-filter"])
+            .args(["-Xclang", symbol])
+            .current_dir(&command.directory);
+        let output = executor().capture(&mut clang)?;
+
+        let dump = String::from_utf8_lossy(&output.stdout);
+        for (kind, file, line) in parse_ast_dump(&dump, symbol) {
+            let file = command.directory.join(file);
+            if let Ok(file) = fs::canonicalize(&file) {
+                if let Ok(relative) = file.strip_prefix(project_dir) {
+                    locations.insert((kind, relative.to_path_buf(), line));
+                }
+            }
+        }
+    }
+
+    Ok(locations)
+}
+
+/// Reads the `Dumping <name>:` blocks of `-ast-dump-filter` output. Each
+/// block starts with a fresh dumper, so its first location has a file name.
+fn parse_ast_dump(dump: &str, symbol: &str) -> Vec<(Kind, PathBuf, usize)> {
+    let mut locations = Vec::new();
+    let mut lines = dump.lines().peekable();
+    let qualified_suffix = format!("::{}", symbol);
+
+    while let Some(line) = lines.next() {
+        let Some(name) = line
+            .strip_prefix("Dumping ")
+            .and_then(|rest| rest.strip_suffix(':'))
+        else {
+            continue;
+        };
+
+        let mut block = Vec::new();
+        while let Some(line) = lines.next_if(|line| !line.starts_with("Dumping ")) {
+            block.push(line);
+        }
+
+        if name != symbol && !name.ends_with(&qualified_suffix) {
+            continue;
+        }
+        let Some((file, line)) = block.first().and_then(|header| decl_location(header)) else {
+            continue;
+        };
+
+        let defines = block[0].contains(" definition")
+            || block[1..].iter().any(|line| line.contains("CompoundStmt"));
+        let kind = if defines {
+            Kind::Definition
+        } else {
+            Kind::Declaration
+        };
+        locations.push((kind, file, line));
+    }
+
+    locations
+}
+
+/// The location of a declaration in a line such as
+/// `CXXRecordDecl 0x1 <./foo.hpp:3:1, line:6:1> line:3:7 class Foo definition`
+fn decl_location(header: &str) -> Option<(PathBuf, usize)> {
+    let (_, range) = header.split_once(" <")?;
+    let (range, rest) = range.split_once('>')?;
+    let start = range.split(',').next()?;
+    if start.starts_with('<') {
+        return None;
+    }
+
+    let mut parts = start.rsplitn(3, ':');
+    let (_, start_line, file) = (parts.next()?, parts.next()?, parts.next()?);
+
+    // The declaration's own location only names what changed since the start
+    let location = rest.split_whitespace().next()?;
+    let (file, line) = if location.starts_with("col:") {
+        (file, start_line)
+    } else if let Some(location) = location.strip_prefix("line:") {
+        (file, location.split(':').next()?)
+    } else {
+        let mut parts = location.rsplitn(3, ':');
+        let (_, line, file) = (parts.next()?, parts.next()?, parts.next()?);
+        (file, line)
+    };
+
+    Some((PathBuf::from(file), line.parse().ok()?))
+}
+
+/// Tags definitions, plus prototypes and extern variables as declarations
+fn ctags_declarations(name: &str) -> Result<BTreeSet<Location>> {
+    let files = sources::project_sources()?;
+    let output = executor().capture(
+        Command::new("ctags")
+            .args(["-x", "--c-kinds=+px", "--c++-kinds=+px"])
+            .args(&files),
+    )?;
+
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            let (tag, kind, line, file) = (
+                fields.next()?,
+                fields.next()?,
+                fields.next()?,
+                fields.next()?,
+            );
+            if tag != name {
+                return None;
+            }
+
+            let kind = match kind {
+                "prototype" | "externvar" => Kind::Declaration,
+                _ => Kind::Definition,
+            };
+            Some((kind, PathBuf::from(file), line.parse().ok()?))
+        })
+        .collect())
+}
@@ -0,0 +1,206 @@
+use anyhow::{Context, Result};
+use colorize::AnsiColor;
+use std::{fs, path::Path};
+
+use crate::{
+    compdb::{self, CompileCommand},
+    executor::shell_quote,
+};
+
+/// Options that take their value as the next argument
+const SEPARATE_VALUE: [&str; 8] = [
+    "-D",
+    "-U",
+    "-I",
+    "-isystem",
+    "-iquote",
+    "-idirafter",
+    "-include",
+    "-Xclang",
+];
+
+/// Dependency file options only the build system cares about
+const DEPENDENCY_OPTIONS: [&str; 3] = ["-MF", "-MT", "-MQ"];
+
+/// The flags one translation unit is compiled with, grouped the way they
+/// affect compilation
+struct Flags {
+    compiler: String,
+    defines: Vec<String>,
+    includes: Vec<String>,
+    other: Vec<String>,
+}
+
+impl Flags {
+    /// Splits the compile command into its groups, pairing options with
+    /// separate values and leaving out the input, the output and
+    /// dependency files
+    fn from_command(command: &CompileCommand) -> Flags {
+        let mut arguments = command.arguments.iter();
+        let mut flags = Flags {
+            compiler: arguments.next().cloned().unwrap_or_default(),
+            defines: Vec::new(),
+            includes: Vec::new(),
+            other: Vec::new(),
+        };
+        let file = command.file.display().to_string();
+
+        while let Some(argument) = arguments.next() {
+            let argument = argument.as_str();
+            if argument == "-c" || argument == "-MD" || argument == "-MMD" || argument == file {
+                continue;
+            }
+            if argument == "-o" || DEPENDENCY_OPTIONS.contains(&argument) {
+                arguments.next();
+                continue;
+            }
+
+            let argument = if SEPARATE_VALUE.contains(&argument) {
+                let value = arguments.next().map(String::as_str).unwrap_or_default();
+                match argument {
+                    "-D" | "-U" | "-I" => format!("{}{}", argument, value),
+                    _ => format!("{} {}", argument, value),
+                }
+            } else {
+                argument.to_string()
+            };
+
+            if argument.starts_with("-D") || argument.starts_with("-U") {
+                flags.defines.push(argument);
+            } else if argument.starts_with("-I")
+                || argument.starts_with("-isystem")
+                || argument.starts_with("-iquote")
+                || argument.starts_with("-idirafter")
+            {
+                flags.includes.push(argument);
+            } else if !argument.starts_with("-o") {
+                flags.other.push(argument);
+            }
+        }
+
+        flags
+    }
+
+    fn all(&self) -> impl Iterator<Item = &String> {
+        std::iter::once(&self.compiler)
+            .chain(&self.defines)
+            .chain(&self.includes)
+            .chain(&self.other)
+    }
+}
+
+/// Prints the compile command of a source file from the compilation
+/// database, or how it differs from another file's
+pub fn handle_flags(file: String, diff: Option<String>, build_dir: String) -> Result<()> {
+    let commands = compdb::load(&build_dir)?;
+    let command = find(&commands, &file)?;
+
+    let Some(other_file) = diff else {
+        println!(
+            "{}\n",
+            command
+                .arguments
+                .iter()
+                .map(|argument| quote(argument))
+                .collect::<Vec<_>>()
+                .join(" ")
+        );
+        let flags = Flags::from_command(command);
+        println!(
+            "{} {}",
+            format!("{:10}", "Compiler:").green(),
+            flags.compiler
+        );
+        print_group("Defines:", &flags.defines);
+        print_group("Includes:", &flags.includes);
+        print_group("Flags:", &flags.other);
+        println!(
+            "{} {}",
+            format!("{:10}", "Directory:").green(),
+            command.directory.display()
+        );
+        return Ok(());
+    };
+
+    let other = find(&commands, &other_file)?;
+    let flags = Flags::from_command(command);
+    let other_flags = Flags::from_command(other);
+    let only_in = |a: &Flags, b: &Flags| {
+        a.all()
+            .filter(|flag| !b.all().any(|other| other == *flag))
+            .cloned()
+            .collect::<Vec<_>>()
+    };
+    let removed = only_in(&flags, &other_flags);
+    let added = only_in(&other_flags, &flags);
+
+    if removed.is_empty() && added.is_empty() {
+        if command.directory != other.directory {
+            println!(
+                "Same flags, compiled in '{}' and '{}'",
+                command.directory.display(),
+                other.directory.display()
+            );
+        } else {
+            println!("{} and {} compile with the same flags", file, other_file);
+        }
+        return Ok(());
+    }
+
+    println!("--- {}", file);
+    println!("+++ {}", other_file);
+    for flag in &removed {
+        println!("{}", format!("- {}", flag).red());
+    }
+    for flag in &added {
+        println!("{}", format!("+ {}", flag).green());
+    }
+
+    Ok(())
+}
+
+fn print_group(label: &str, flags: &[String]) {
+    let mut flags = flags.iter();
+    let Some(first) = flags.next() else {
+        return;
+    };
+
+    println!("{} {}", format!("{:10}", label).green(), first);
+    for flag in flags {
+        println!("{:10} {}", "", flag);
+    }
+}
+
+/// The command compiling `file`, which may be given relative to the current
+/// directory or as the compilation database lists it
+fn find<'a>(commands: &'a [CompileCommand], file: &str) -> Result<&'a CompileCommand> {
+    let path = fs::canonicalize(file).ok();
+
+    commands
+        .iter()
+        .find(|command| {
+            let listed = command.directory.join(&command.file);
+            match (&path, fs::canonicalize(&listed)) {
+                (Some(path), Ok(listed)) => *path == listed,
+                _ => Path::new(file) == command.file,
+            }
+        })
+        .with_context(|| {
+            format!(
+                "'{}' isn't in the compilation database, which only lists translation units",
+                file
+            )
+        })
+}
+
+/// Quotes arguments the shell would split or expand
+fn quote(argument: &str) -> String {
+    if argument
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || "-_=+./:,@%".contains(c))
+    {
+        argument.to_string()
+    } else {
+        shell_quote(argument)
+    }
+}
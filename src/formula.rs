@@ -0,0 +1,321 @@
+use anyhow::{Context, Result};
+use colorize::AnsiColor;
+use std::{fmt::Write, fs, path::Path, process::Command};
+
+use crate::{
+    backend::GENERATED_MARKER,
+    cmake, default_exec_name,
+    executor::{executor, shell_quote},
+    manifest::Manifest,
+};
+
+/// Where the generated package definitions are written, to be copied into a
+/// Homebrew tap and an AUR repository
+const FORMULA_DIR: &str = "packaging/homebrew";
+const PKGBUILD_FILE: &str = "packaging/aur/PKGBUILD";
+
+/// A release archive `publish github` built for one platform
+struct Artifact {
+    /// `linux` or `macos`
+    os: String,
+    /// `x86_64` or `aarch64`
+    arch: String,
+    url: String,
+    sha256: String,
+    /// The directory the archive unpacks to
+    dir: String,
+}
+
+/// Generates a Homebrew formula, and with `aur` an AUR PKGBUILD, installing
+/// the executable from the archives of a GitHub release made by `publish
+/// github`, the latest one unless `tag` is given. The description, homepage
+/// and license come from the manifest's `[package]`.
+pub fn handle_publish_formula(tag: Option<String>, aur: bool) -> Result<()> {
+    if !has_tool("gh") {
+        anyhow::bail!("Reading the GitHub release requires the GitHub CLI, gh");
+    }
+    if cmake::is_library_project()? {
+        anyhow::bail!("Formulas install executables, use 'publish local' to share libraries");
+    }
+
+    let manifest = Manifest::load()?;
+    let Some(description) = manifest.package.description.clone() else {
+        anyhow::bail!("Package managers need a description, set package.description");
+    };
+    let repository = gh(&[
+        "repo",
+        "view",
+        "--json",
+        "nameWithOwner",
+        "-q",
+        ".nameWithOwner",
+    ])?;
+    let tag = match tag {
+        Some(tag) => tag,
+        None => gh(&["release", "view", "--json", "tagName", "-q", ".tagName"])
+            .context("No release found, create one with 'publish github' first")?,
+    };
+    let version = tag.trim_start_matches('v').to_string();
+    let name = default_exec_name();
+    let homepage = manifest
+        .package
+        .homepage
+        .clone()
+        .unwrap_or_else(|| format!("https://github.com/{}", repository));
+
+    let checksums = gh(&[
+        "release",
+        "download",
+        &tag,
+        "--pattern",
+        "SHA256SUMS",
+        "--output",
+        "-",
+    ])
+    .with_context(|| {
+        format!(
+            "Release {} has no SHA256SUMS, was it made by 'publish github'?",
+            tag
+        )
+    })?;
+    let prefix = format!("{}-{}-", name, version);
+    let artifacts = checksums
+        .lines()
+        .filter_map(|line| {
+            let (sha256, file) = line.split_once("  ")?;
+            let dir = file.strip_suffix(".tar.gz")?;
+            let (arch, os) = dir.strip_prefix(&prefix)?.split_once('-')?;
+            Some(Artifact {
+                os: os.to_string(),
+                arch: arch.to_string(),
+                url: format!(
+                    "https://github.com/{}/releases/download/{}/{}",
+                    repository, tag, file
+                ),
+                sha256: sha256.to_string(),
+                dir: dir.to_string(),
+            })
+        })
+        .filter(|artifact| artifact.os == "linux" || artifact.os == "macos")
+        .collect::<Vec<_>>();
+    if artifacts.is_empty() {
+        anyhow::bail!(
+            "Release {} has no Linux or macOS archives, add 'native' to [publish] targets and publish from those platforms",
+            tag
+        );
+    }
+
+    let license = manifest.package.license.as_deref();
+    let formula_path = Path::new(FORMULA_DIR).join(format!("{}.rb", name));
+    write_generated(
+        &formula_path,
+        &formula(
+            &name,
+            &description,
+            &homepage,
+            license,
+            &version,
+            &artifacts,
+        )?,
+    )?;
+    eprintln!(
+        "{}",
+        format!("Generated {} for {}", formula_path.display(), tag).green()
+    );
+
+    if aur {
+        let linux = artifacts
+            .iter()
+            .filter(|artifact| artifact.os == "linux")
+            .collect::<Vec<_>>();
+        if linux.is_empty() {
+            anyhow::bail!("Release {} has no Linux archives for the AUR", tag);
+        }
+        write_generated(
+            Path::new(PKGBUILD_FILE),
+            &pkgbuild(&name, &description, &homepage, license, &version, &linux)?,
+        )?;
+        eprintln!(
+            "{}",
+            format!("Generated {} for {}", PKGBUILD_FILE, tag).green()
+        );
+    }
+
+    Ok(())
+}
+
+fn formula(
+    name: &str,
+    description: &str,
+    homepage: &str,
+    license: Option<&str>,
+    version: &str,
+    artifacts: &[Artifact],
+) -> Result<String> {
+    let mut formula = format!(
+        "# {} publish formula, copy it into a tap\nclass {} < Formula\n",
+        GENERATED_MARKER,
+        class_name(name)
+    );
+    writeln!(formula, "  desc {}", ruby_string(description))?;
+    writeln!(formula, "  homepage {}", ruby_string(homepage))?;
+    writeln!(formula, "  version {}", ruby_string(version))?;
+    if let Some(license) = license {
+        writeln!(formula, "  license {}", ruby_string(license))?;
+    }
+
+    for (os, block) in [("macos", "on_macos"), ("linux", "on_linux")] {
+        let archives = artifacts
+            .iter()
+            .filter(|artifact| artifact.os == os)
+            .collect::<Vec<_>>();
+        if archives.is_empty() {
+            continue;
+        }
+        writeln!(formula, "\n  {} do", block)?;
+        for artifact in archives {
+            let cpu = match artifact.arch.as_str() {
+                "aarch64" | "arm64" => "on_arm",
+                _ => "on_intel",
+            };
+            writeln!(formula, "    {} do", cpu)?;
+            writeln!(formula, "      url {}", ruby_string(&artifact.url))?;
+            writeln!(formula, "      sha256 {}", ruby_string(&artifact.sha256))?;
+            writeln!(formula, "    end")?;
+        }
+        writeln!(formula, "  end")?;
+    }
+
+    // Homebrew changes into the single directory an archive unpacks to
+    write!(
+        formula,
+        "
+  def install
+    bin.install {name}
+  end
+
+  test do
+    assert_predicate bin/{name}, :executable?
+  end
+end
+",
+        name = ruby_string(name)
+    )?;
+
+    Ok(formula)
+}
+
+/// A `-bin` package, the AUR's convention for packages of prebuilt binaries
+fn pkgbuild(
+    name: &str,
+    description: &str,
+    homepage: &str,
+    license: Option<&str>,
+    version: &str,
+    artifacts: &[&Artifact],
+) -> Result<String> {
+    let mut pkgbuild = format!("# {} publish formula\n", GENERATED_MARKER);
+    writeln!(pkgbuild, "pkgname={}-bin", name)?;
+    writeln!(pkgbuild, "pkgver={}", version.replace('-', "_"))?;
+    writeln!(pkgbuild, "pkgrel=1")?;
+    writeln!(pkgbuild, "pkgdesc={}", shell_quote(description))?;
+    let arches = artifacts
+        .iter()
+        .map(|artifact| shell_quote(&artifact.arch))
+        .collect::<Vec<_>>();
+    writeln!(pkgbuild, "arch=({})", arches.join(" "))?;
+    writeln!(pkgbuild, "url={}", shell_quote(homepage))?;
+    writeln!(
+        pkgbuild,
+        "license=({})",
+        shell_quote(license.unwrap_or("custom"))
+    )?;
+    writeln!(pkgbuild, "provides=({})", shell_quote(name))?;
+    writeln!(pkgbuild, "conflicts=({})", shell_quote(name))?;
+    for artifact in artifacts {
+        writeln!(
+            pkgbuild,
+            "source_{}=({})",
+            artifact.arch,
+            shell_quote(&artifact.url)
+        )?;
+        writeln!(
+            pkgbuild,
+            "sha256sums_{}=({})",
+            artifact.arch,
+            shell_quote(&artifact.sha256)
+        )?;
+    }
+
+    let dir = artifacts[0].dir.replace(&artifacts[0].arch, "${CARCH}");
+    write!(
+        pkgbuild,
+        "
+package() {{
+  install -Dm755 \"${{srcdir}}/{dir}/{name}\" \"${{pkgdir}}/usr/bin/{name}\"
+}}
+"
+    )?;
+
+    Ok(pkgbuild)
+}
+
+/// Homebrew's class name for a formula, e.g. `MyTool` for `my-tool`
+fn class_name(name: &str) -> String {
+    name.split(['-', '_'])
+        .map(|part| {
+            let mut chars = part.chars();
+            match chars.next() {
+                Some(first) => first.to_ascii_uppercase().to_string() + chars.as_str(),
+                None => String::new(),
+            }
+        })
+        .collect()
+}
+
+fn ruby_string(value: &str) -> String {
+    format!(
+        "\"{}\"",
+        value
+            .replace('\\', "\\\\")
+            .replace('"', "\\\"")
+            .replace('#', "\\#")
+    )
+}
+
+fn write_generated(path: &Path, contents: &str) -> Result<()> {
+    if fs::read_to_string(path).is_ok_and(|existing| !existing.contains(GENERATED_MARKER)) {
+        anyhow::bail!(
+            "'{}' exists and wasn't generated by cxx, move it aside to generate it",
+            path.display()
+        );
+    }
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)
+            .with_context(|| format!("Failed to create {}", parent.display()))?;
+    }
+
+    fs::write(path, contents).with_context(|| format!("Failed to write {}", path.display()))
+}
+
+/// Runs a gh command, returning its trimmed output
+fn gh(args: &[&str]) -> Result<String> {
+    let output = executor()
+        .capture(Command::new("gh").args(args))
+        .context("Failed to run gh")?;
+    if !output.status.success() {
+        anyhow::bail!(
+            "gh {} failed: {}",
+            args[..2].join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+fn has_tool(tool: &str) -> bool {
+    executor()
+        .capture(Command::new(tool).arg("--version"))
+        .is_ok()
+}
@@ -0,0 +1,698 @@
+use anyhow::{Context, Result};
+use colorize::AnsiColor;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    cmake,
+    compdb::{self, CompileCommand},
+    executor::executor,
+    layout, sources,
+};
+
+/// What a header declares at namespace scope
+#[derive(Default)]
+struct Declarations {
+    /// Classes, structs and unions that can be forward declared, by name,
+    /// with their namespace and keyword
+    classes: BTreeMap<String, (String, String)>,
+    /// Everything else a header using it needs the definition of: macros,
+    /// functions, variables, aliases, enums and templates
+    others: BTreeSet<String>,
+}
+
+/// An include of a project header by another header that only uses its
+/// classes through pointers and references
+struct Candidate {
+    header: PathBuf,
+    include: String,
+    included: PathBuf,
+    /// `class Name;` declarations by namespace
+    forward: BTreeMap<String, Vec<String>>,
+    /// Project sources including the header, which get the include instead
+    sources: Vec<PathBuf>,
+}
+
+/// Finds project headers included by other headers only to declare
+/// pointers and references to their classes, and replaces those includes
+/// with forward declarations, moving the include to the sources including
+/// the header. Each replacement is checked by compiling the translation
+/// units reading the header and undone when one fails.
+pub fn handle_fix_forward_decls(build_dir: String, dry_run: bool) -> Result<()> {
+    let files = sources::project_sources()?;
+    let candidates = candidates(&files)?;
+
+    if dry_run {
+        eprintln!(
+            "{}",
+            format!(
+                "Would replace {} {} with forward declarations",
+                candidates.len(),
+                if candidates.len() == 1 {
+                    "include"
+                } else {
+                    "includes"
+                }
+            )
+            .green()
+        );
+        for candidate in &candidates {
+            print_candidate(candidate);
+        }
+        return Ok(());
+    }
+    if candidates.is_empty() {
+        eprintln!(
+            "{}",
+            "No include can be replaced by forward declarations".green()
+        );
+        return Ok(());
+    }
+
+    // The translation units reading each header, from before any change
+    let commands = compdb::load(&build_dir)?;
+    let dependencies = commands
+        .iter()
+        .map(|command| (command, compdb::dependencies(command)))
+        .collect::<Vec<_>>();
+
+    let mut replaced = Vec::new();
+    let mut kept = Vec::new();
+    for candidate in candidates {
+        let header = fs::canonicalize(&candidate.header)
+            .with_context(|| format!("Failed to read '{}'", candidate.header.display()))?;
+        let units = dependencies
+            .iter()
+            .filter(|(_, dependencies)| dependencies.contains(&header))
+            .map(|(command, _)| *command)
+            .collect::<Vec<_>>();
+
+        let originals = apply(&candidate)?;
+        if let Some(unit) = units.iter().find(|command| !compiles(command)) {
+            for (path, text) in originals {
+                fs::write(&path, text)
+                    .with_context(|| format!("Failed to restore '{}'", path.display()))?;
+            }
+            kept.push((candidate, unit.file.clone()));
+        } else {
+            replaced.push(candidate);
+        }
+    }
+
+    eprintln!(
+        "{}",
+        format!(
+            "Replaced {} {} with forward declarations",
+            replaced.len(),
+            if replaced.len() == 1 {
+                "include"
+            } else {
+                "includes"
+            }
+        )
+        .green()
+    );
+    for candidate in &replaced {
+        print_candidate(candidate);
+    }
+    for (candidate, unit) in &kept {
+        eprintln!(
+            "{}",
+            format!(
+                "Kept #include {} in {}, {} doesn't compile without it",
+                candidate.include,
+                candidate.header.display(),
+                unit.display()
+            )
+            .yellow()
+        );
+    }
+
+    Ok(())
+}
+
+fn print_candidate(candidate: &Candidate) {
+    let classes = candidate
+        .forward
+        .iter()
+        .flat_map(|(namespace, classes)| {
+            classes.iter().map(move |class| match namespace.is_empty() {
+                true => class.clone(),
+                false => {
+                    let (keyword, name) = class.split_once(' ').unwrap_or(("", class));
+                    format!("{} {}::{}", keyword, namespace, name)
+                }
+            })
+        })
+        .collect::<Vec<_>>();
+    eprintln!(
+        "  {}: {} instead of #include {}",
+        candidate.header.display(),
+        classes.join(", "),
+        candidate.include
+    );
+    if !candidate.sources.is_empty() {
+        eprintln!(
+            "    including it in {}",
+            candidate
+                .sources
+                .iter()
+                .map(|source| source.display().to_string())
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+    }
+}
+
+fn candidates(files: &[PathBuf]) -> Result<Vec<Candidate>> {
+    let include_dir = cmake::include_dir_in(Path::new("."));
+    let src_dir = layout::src_dir()?;
+    let mut declarations = BTreeMap::<PathBuf, Declarations>::new();
+    let mut candidates = Vec::new();
+
+    for header in files.iter().filter(|file| sources::is_header(file)) {
+        let text = fs::read_to_string(header)
+            .with_context(|| format!("Failed to read '{}'", header.display()))?;
+        let header_code = code(&text);
+
+        'includes: for (include, spelling) in includes(&text) {
+            let Some(included) =
+                resolve(files, include_dir.as_deref(), &src_dir, header, &spelling)
+                    .filter(|included| included != header)
+            else {
+                continue;
+            };
+            if !declarations.contains_key(&included) {
+                let included_text = fs::read_to_string(&included)
+                    .with_context(|| format!("Failed to read '{}'", included.display()))?;
+                declarations.insert(included.clone(), parse_declarations(&included_text));
+            }
+            let declared = &declarations[&included];
+            if declared
+                .others
+                .iter()
+                .any(|name| sources::contains_word(&header_code, name))
+            {
+                continue;
+            }
+
+            let mut forward = BTreeMap::<String, Vec<String>>::new();
+            for (name, (namespace, keyword)) in &declared.classes {
+                if !sources::contains_word(&header_code, name) {
+                    continue;
+                }
+                if !only_pointers_and_references(&header_code, name) {
+                    continue 'includes;
+                }
+                forward
+                    .entry(namespace.clone())
+                    .or_default()
+                    .push(format!("{} {}", keyword, name));
+            }
+            if forward.is_empty() {
+                continue;
+            }
+
+            let mut including = Vec::new();
+            for source in files.iter().filter(|file| sources::is_source(file)) {
+                let source_text = fs::read_to_string(source)
+                    .with_context(|| format!("Failed to read '{}'", source.display()))?;
+                let resolved = includes(&source_text)
+                    .into_iter()
+                    .filter_map(|(_, spelling)| {
+                        resolve(files, include_dir.as_deref(), &src_dir, source, &spelling)
+                    })
+                    .collect::<Vec<_>>();
+                if resolved.contains(header) && !resolved.contains(&included) {
+                    including.push(source.clone());
+                }
+            }
+
+            candidates.push(Candidate {
+                header: header.clone(),
+                include,
+                included,
+                forward,
+                sources: including,
+            });
+        }
+    }
+
+    Ok(candidates)
+}
+
+/// Replaces the include with the forward declarations and includes the
+/// header in the sources after the header including it. Returns the files
+/// changed with their previous contents.
+fn apply(candidate: &Candidate) -> Result<Vec<(PathBuf, String)>> {
+    let include_dir = cmake::include_dir_in(Path::new("."));
+    let src_dir = layout::src_dir()?;
+    let mut originals = Vec::new();
+
+    let text = fs::read_to_string(&candidate.header)
+        .with_context(|| format!("Failed to read '{}'", candidate.header.display()))?;
+    let mut declarations = String::new();
+    for (namespace, classes) in &candidate.forward {
+        let classes = classes
+            .iter()
+            .map(|class| format!("{};\n", class))
+            .collect::<String>();
+        if namespace.is_empty() {
+            declarations.push_str(&classes);
+        } else {
+            declarations.push_str(&format!(
+                "namespace {} {{\n{}}}  // namespace {}\n",
+                namespace, classes, namespace
+            ));
+        }
+    }
+    let updated = replace_include(&text, &candidate.include, declarations.trim_end());
+    fs::write(&candidate.header, updated)
+        .with_context(|| format!("Failed to update '{}'", candidate.header.display()))?;
+    originals.push((candidate.header.clone(), text));
+
+    // Sources include the header relative to the include directory when
+    // it's in there, as the header did, or else relative to themselves
+    for source in &candidate.sources {
+        let text = fs::read_to_string(source)
+            .with_context(|| format!("Failed to read '{}'", source.display()))?;
+        let spelling = match include_dir
+            .as_deref()
+            .and_then(|dir| candidate.included.strip_prefix(dir).ok())
+        {
+            Some(relative) if candidate.include.starts_with('<') => {
+                format!("<{}>", relative.display().to_string().replace('\\', "/"))
+            }
+            Some(relative) => format!("\"{}\"", relative.display().to_string().replace('\\', "/")),
+            None => format!(
+                "\"{}\"",
+                relative_to(
+                    &candidate.included,
+                    source.parent().unwrap_or(Path::new(""))
+                )
+            ),
+        };
+        let header_include = includes(&text)
+            .into_iter()
+            .find(|(_, included)| {
+                resolve(
+                    std::slice::from_ref(&candidate.header),
+                    include_dir.as_deref(),
+                    &src_dir,
+                    source,
+                    included,
+                )
+                .is_some()
+            })
+            .map(|(include, _)| include);
+        let Some(header_include) = header_include else {
+            continue;
+        };
+        let updated = replace_include(
+            &text,
+            &header_include,
+            &format!("#include {}\n#include {}", header_include, spelling),
+        );
+        fs::write(source, updated)
+            .with_context(|| format!("Failed to update '{}'", source.display()))?;
+        originals.push((source.clone(), text));
+    }
+
+    Ok(originals)
+}
+
+/// Whether a translation unit compiles, checking its syntax only
+fn compiles(command: &CompileCommand) -> bool {
+    let Some(mut compile) = compdb::compiler_command(command, None) else {
+        return false;
+    };
+    compile.arg("-fsyntax-only");
+
+    executor()
+        .capture(&mut compile)
+        .is_ok_and(|output| output.status.success())
+}
+
+/// The `#include` directives of a file, as written after `#include`, e.g.
+/// `"widget.hpp"`, with the file name between the quotes or brackets
+fn includes(text: &str) -> Vec<(String, String)> {
+    text.lines()
+        .filter_map(|line| {
+            let directive = line.trim_start().strip_prefix('#')?.trim_start();
+            let include = directive.strip_prefix("include")?.trim();
+            let include = include.split("//").next().unwrap_or(include).trim();
+            let spelling = include
+                .strip_prefix('"')
+                .and_then(|rest| rest.strip_suffix('"'))
+                .or_else(|| include.strip_prefix('<')?.strip_suffix('>'))?;
+
+            Some((include.to_string(), spelling.to_string()))
+        })
+        .collect()
+}
+
+/// The project header an include in `file` refers to, searched next to the
+/// file, in the include directory, in the project root and in the source
+/// directory
+fn resolve(
+    files: &[PathBuf],
+    include_dir: Option<&str>,
+    src_dir: &str,
+    file: &Path,
+    spelling: &str,
+) -> Option<PathBuf> {
+    let mut candidates = vec![file.parent().unwrap_or(Path::new("")).join(spelling)];
+    candidates.extend(include_dir.map(|dir| Path::new(dir).join(spelling)));
+    candidates.push(PathBuf::from(spelling));
+    candidates.push(Path::new(src_dir).join(spelling));
+
+    candidates.into_iter().find_map(|candidate| {
+        let candidate = fs::canonicalize(candidate).ok()?;
+        files
+            .iter()
+            .find(|file| fs::canonicalize(file).is_ok_and(|file| file == candidate))
+            .cloned()
+    })
+}
+
+/// A project path relative to a directory of the project
+fn relative_to(path: &Path, dir: &Path) -> String {
+    let mut dir = dir.components().collect::<Vec<_>>();
+    let mut path = path.components().collect::<Vec<_>>();
+    while !dir.is_empty() && !path.is_empty() && dir[0] == path[0] {
+        dir.remove(0);
+        path.remove(0);
+    }
+
+    let mut relative = vec!["..".to_string(); dir.len()];
+    relative.extend(
+        path.iter()
+            .map(|component| component.as_os_str().to_string_lossy().to_string()),
+    );
+    relative.join("/")
+}
+
+/// Replaces the first `#include` line with `include` after the directive
+fn replace_include(text: &str, include: &str, replacement: &str) -> String {
+    let mut replaced = false;
+    let mut lines = text
+        .lines()
+        .map(|line| {
+            let directive = line.trim_start().strip_prefix('#').map(str::trim_start);
+            let matches = directive
+                .and_then(|directive| directive.strip_prefix("include"))
+                .is_some_and(|rest| rest.trim().starts_with(include));
+            if matches && !replaced {
+                replaced = true;
+                replacement.to_string()
+            } else {
+                line.to_string()
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+    if text.ends_with('\n') {
+        lines.push('\n');
+    }
+
+    lines
+}
+
+/// Whether every use of a class in the code of a header is a pointer or a
+/// reference, possibly const, or an elaborated declaration such as
+/// `class Name;`
+fn only_pointers_and_references(code: &str, name: &str) -> bool {
+    code.match_indices(name).all(|(index, _)| {
+        let before = code[..index].chars().next_back();
+        let after = code[index + name.len()..].chars().next();
+        if before.is_some_and(is_identifier) || after.is_some_and(is_identifier) {
+            return true;
+        }
+
+        let rest = code[index + name.len()..].trim_start();
+        let rest = rest
+            .strip_prefix("const")
+            .filter(|rest| !rest.starts_with(is_identifier))
+            .map_or(rest, str::trim_start);
+        let elaborated = ["class", "struct", "union"].iter().any(|keyword| {
+            code[..index]
+                .trim_end()
+                .strip_suffix(keyword)
+                .is_some_and(|before| !before.ends_with(is_identifier))
+        });
+
+        rest.starts_with('*') || rest.starts_with('&') || (elaborated && rest.starts_with(';'))
+    })
+}
+
+/// The names a header declares outside of class and function bodies
+fn parse_declarations(text: &str) -> Declarations {
+    let mut declarations = Declarations::default();
+    for line in text.lines() {
+        let define = line
+            .trim_start()
+            .strip_prefix('#')
+            .and_then(|directive| directive.trim_start().strip_prefix("define"));
+        if let Some(name) = define.and_then(|define| identifiers(define).into_iter().next()) {
+            declarations.others.insert(name);
+        }
+    }
+
+    // Namespaces and `extern "C"` blocks are Some, other scopes None
+    let mut scopes: Vec<Option<String>> = Vec::new();
+    let mut statement = String::new();
+    for c in code(text).chars() {
+        let namespace_scope = scopes.iter().all(Option::is_some);
+        match c {
+            '{' if namespace_scope => {
+                let trimmed = statement.trim();
+                let trimmed = trimmed.strip_prefix("inline ").unwrap_or(trimmed);
+                if let Some(name) = trimmed.strip_prefix("namespace") {
+                    scopes.push(Some(name.trim().to_string()));
+                } else if trimmed.starts_with("extern") {
+                    scopes.push(Some(String::new()));
+                } else {
+                    declare(&statement, true, &scopes, &mut declarations);
+                    scopes.push(None);
+                }
+                statement.clear();
+            }
+            '{' => scopes.push(None),
+            '}' => {
+                scopes.pop();
+                statement.clear();
+            }
+            ';' if namespace_scope => {
+                declare(&statement, false, &scopes, &mut declarations);
+                statement.clear();
+            }
+            _ if namespace_scope => statement.push(c),
+            _ => {}
+        }
+    }
+
+    declarations
+}
+
+/// Records the name a namespace scope statement declares, `definition`
+/// when a body follows it
+fn declare(
+    statement: &str,
+    definition: bool,
+    scopes: &[Option<String>],
+    declarations: &mut Declarations,
+) {
+    let tokens = tokens(statement);
+    // Export macros such as `APP_API` may precede a declaration
+    let mut tokens = tokens
+        .iter()
+        .map(String::as_str)
+        .skip_while(|token| token.starts_with(is_identifier) && *token == token.to_uppercase())
+        .collect::<Vec<_>>();
+    if tokens.is_empty() {
+        return;
+    }
+
+    let template = tokens[0] == "template";
+    if template {
+        let mut depth = 0;
+        let end = tokens.iter().position(|token| {
+            match *token {
+                "<" => depth += 1,
+                ">" => depth -= 1,
+                _ => {}
+            }
+            depth == 0 && *token == ">"
+        });
+        tokens.drain(..end.map_or(tokens.len(), |end| end + 1));
+    }
+
+    let name = |tokens: &[&str]| {
+        tokens
+            .iter()
+            .find(|token| token.starts_with(is_identifier) && !is_keyword(token))
+            .map(|token| token.to_string())
+    };
+    match tokens.first().copied() {
+        None => {}
+        Some(keyword @ ("class" | "struct" | "union")) => {
+            let Some(class) = name(&tokens[1..]) else {
+                return;
+            };
+            let position = tokens.iter().position(|token| *token == class);
+            let after = position
+                .and_then(|position| tokens.get(position + 1))
+                .copied();
+            let class_declaration =
+                definition || after.is_none() || matches!(after, Some("final" | ":"));
+            if template || !class_declaration || after == Some("::") {
+                declarations.others.insert(class);
+            } else {
+                let namespace = scopes
+                    .iter()
+                    .flatten()
+                    .filter(|name| !name.is_empty())
+                    .cloned()
+                    .collect::<Vec<_>>()
+                    .join("::");
+                declarations
+                    .classes
+                    .insert(class, (namespace, keyword.to_string()));
+            }
+        }
+        Some("enum") => declarations.others.extend(name(&tokens[1..])),
+        Some("using") if tokens.get(1) == Some(&"namespace") => {}
+        Some("using") => match tokens.iter().position(|token| *token == "=") {
+            Some(equals) => declarations.others.extend(name(&tokens[1..equals])),
+            None => declarations.others.extend(last_identifier(&tokens)),
+        },
+        Some("typedef") => declarations.others.extend(last_identifier(&tokens)),
+        Some(_) => {
+            // A function's name precedes its parameters, a variable's its
+            // initializer
+            let end = tokens
+                .iter()
+                .position(|token| ["(", "=", "["].contains(token))
+                .unwrap_or(tokens.len());
+            declarations.others.extend(last_identifier(&tokens[..end]));
+        }
+    }
+}
+
+fn last_identifier(tokens: &[&str]) -> Option<String> {
+    tokens
+        .iter()
+        .rev()
+        .find(|token| token.starts_with(is_identifier) && !is_keyword(token))
+        .map(|token| token.to_string())
+}
+
+/// Splits code into identifiers and the other characters, `::` kept whole
+fn tokens(code: &str) -> Vec<String> {
+    let mut tokens = Vec::new();
+    let mut chars = code.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c.is_whitespace() {
+            continue;
+        }
+        let mut token = c.to_string();
+        if is_identifier(c) {
+            while let Some(next) = chars.next_if(|next| is_identifier(*next)) {
+                token.push(next);
+            }
+        } else if c == ':' && chars.next_if_eq(&':').is_some() {
+            token.push(':');
+        }
+        tokens.push(token);
+    }
+
+    tokens
+}
+
+fn identifiers(code: &str) -> Vec<String> {
+    tokens(code)
+        .into_iter()
+        .filter(|token| token.starts_with(is_identifier))
+        .collect()
+}
+
+fn is_keyword(word: &str) -> bool {
+    [
+        "alignas",
+        "const",
+        "constexpr",
+        "consteval",
+        "constinit",
+        "extern",
+        "final",
+        "inline",
+        "static",
+        "thread_local",
+        "volatile",
+        "class",
+        "struct",
+        "union",
+        "enum",
+        "typename",
+        "unsigned",
+        "signed",
+    ]
+    .contains(&word)
+}
+
+fn is_identifier(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_'
+}
+
+/// The code of a file with comments, string and character literals and
+/// preprocessor directives blanked out
+fn code(text: &str) -> String {
+    let mut code = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    let mut line_start = true;
+    while let Some(c) = chars.next() {
+        match c {
+            '#' if line_start => {
+                // Up to the end of the line, continued by backslashes
+                let mut previous = c;
+                while let Some(next) = chars.next_if(|next| *next != '\n' || previous == '\\') {
+                    previous = next;
+                }
+            }
+            '/' if chars.next_if_eq(&'/').is_some() => {
+                while chars.next_if(|next| *next != '\n').is_some() {}
+            }
+            '/' if chars.next_if_eq(&'*').is_some() => {
+                let mut previous = ' ';
+                for next in chars.by_ref() {
+                    if previous == '*' && next == '/' {
+                        break;
+                    }
+                    previous = next;
+                }
+                code.push(' ');
+            }
+            '"' | '\'' => {
+                while let Some(next) = chars.next() {
+                    if next == '\\' {
+                        chars.next();
+                    } else if next == c || next == '\n' {
+                        break;
+                    }
+                }
+                code.push(' ');
+            }
+            _ => code.push(c),
+        }
+        if c == '\n' {
+            line_start = true;
+        } else if !c.is_whitespace() {
+            line_start = false;
+        }
+    }
+
+    code
+}
@@ -0,0 +1,154 @@
+use anyhow::{Context, Result};
+use colorize::AnsiColor;
+use std::{
+    cmp::Reverse,
+    fs,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use crate::{
+    build_info::format_timestamp,
+    cache::{build_dirs, modified},
+    executor::executor,
+    format_size, variants,
+};
+
+/// Intermediate files the compiler writes and the build recreates: objects,
+/// precompiled headers and coverage counters
+const INTERMEDIATE_EXTENSIONS: &[&str] = &["o", "obj", "gch", "pch", "gcda", "gcno"];
+
+/// Deletes the object files of the build directories used least recently,
+/// keeping the `keep_last` most recently used untouched. Their CMake cache
+/// and built executables and libraries stay, so a build only recompiles.
+/// With `prune_variants`, the variants of `build_dir` no branch builds in
+/// anymore are deleted first.
+pub fn handle_gc(keep_last: usize, prune_variants: bool, build_dir: String) -> Result<()> {
+    let mut freed = 0;
+    if prune_variants {
+        freed += variants::prune(&build_dir)?;
+    }
+
+    let mut dirs = build_dirs(&build_dir)
+        .into_iter()
+        .filter(|dir| dir.join("CMakeCache.txt").exists())
+        .map(|dir| (last_used(&dir), dir))
+        .collect::<Vec<_>>();
+    if dirs.is_empty() {
+        anyhow::bail!(
+            "No configured build directories found next to '{}'",
+            build_dir
+        );
+    }
+    dirs.sort_by_key(|(used, _)| Reverse(*used));
+
+    for (index, (used, dir)) in dirs.iter().enumerate() {
+        let used = used
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map(|duration| duration.as_secs())
+            .unwrap_or(0);
+        let last_used = format_timestamp(used);
+        if index < keep_last {
+            eprintln!("Kept {} (last used {})", dir.display(), last_used);
+            continue;
+        }
+
+        let mut files = Vec::new();
+        intermediates(dir, &mut files);
+        let size = files.iter().map(|(_, size)| size).sum::<u64>();
+        if executor().dry_run() {
+            eprintln!(
+                "Would remove {} object files from {} (last used {}), {}",
+                files.len(),
+                dir.display(),
+                last_used,
+                format_size(size)
+            );
+            freed += size;
+            continue;
+        }
+
+        for (file, _) in &files {
+            fs::remove_file(file)
+                .with_context(|| format!("Failed to remove '{}'", file.display()))?;
+        }
+        eprintln!(
+            "Removed {} object files from {} (last used {}), {}",
+            files.len(),
+            dir.display(),
+            last_used,
+            format_size(size)
+        );
+        freed += size;
+    }
+
+    let verb = if executor().dry_run() {
+        "Would free"
+    } else {
+        "Freed"
+    };
+    eprintln!("{}", format!("{} {}", verb, format_size(freed)).green());
+
+    Ok(())
+}
+
+/// When the build directory was last built in. Ninja appends to its log on
+/// every build, other generators only leave newer files behind.
+fn last_used(dir: &Path) -> SystemTime {
+    let ninja_log = dir.join(".ninja_log");
+    if ninja_log.exists() {
+        return modified(&ninja_log);
+    }
+
+    fn newest(dir: &Path) -> SystemTime {
+        let Ok(entries) = fs::read_dir(dir) else {
+            return SystemTime::UNIX_EPOCH;
+        };
+        entries
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| entry.file_name() != "_deps")
+            .map(|entry| match entry.file_type() {
+                Ok(file_type) if file_type.is_dir() => newest(&entry.path()),
+                _ => modified(&entry.path()),
+            })
+            .max()
+            .unwrap_or(SystemTime::UNIX_EPOCH)
+    }
+
+    newest(dir).max(modified(&dir.join("CMakeCache.txt")))
+}
+
+/// The intermediate files below a build directory and their sizes. The
+/// sources of fetched dependencies and CMake's compiler checks, e.g.
+/// `CMakeFiles/3.28.3/`, are left alone.
+fn intermediates(dir: &Path, files: &mut Vec<(PathBuf, u64)>) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+
+    for entry in entries.filter_map(|entry| entry.ok()) {
+        let path = entry.path();
+        let name = entry.file_name().to_string_lossy().to_string();
+        let Ok(file_type) = entry.file_type() else {
+            continue;
+        };
+
+        if file_type.is_dir() {
+            let checks = name.starts_with(|c: char| c.is_ascii_digit())
+                && dir.file_name().is_some_and(|parent| parent == "CMakeFiles");
+            if !checks && !name.ends_with("-src") {
+                intermediates(&path, files);
+            }
+            continue;
+        }
+
+        let intermediate = path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .is_some_and(|extension| INTERMEDIATE_EXTENSIONS.contains(&extension));
+        if file_type.is_file() && intermediate {
+            let size = entry.metadata().map(|metadata| metadata.len()).unwrap_or(0);
+            files.push((path, size));
+        }
+    }
+}
@@ -0,0 +1,274 @@
+use anyhow::{Context, Result};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// Expands a glob pattern relative to the current directory into the files
+/// it matches. Supports `*` and `?` within a path component and `**` for any
+/// number of directories.
+pub fn expand(pattern: &str) -> Result<Vec<PathBuf>> {
+    let components = pattern.split('/').collect::<Vec<_>>();
+    let literal = components
+        .iter()
+        .take_while(|component| !is_pattern(component))
+        .count();
+
+    let root = components[..literal].join("/");
+    let root = if root.is_empty() {
+        ".".to_string()
+    } else {
+        root
+    };
+    let root = PathBuf::from(root);
+
+    if literal == components.len() {
+        return Ok(if root.is_file() {
+            vec![root]
+        } else {
+            Vec::new()
+        });
+    }
+
+    let mut matches = Vec::new();
+    walk(&root, &components[literal..], &mut matches)
+        .with_context(|| format!("Failed to expand pattern '{}'", pattern))?;
+    matches.sort();
+
+    Ok(matches)
+}
+
+/// The part of a matched path below the pattern's literal prefix, used to
+/// mirror directory structure when copying matches elsewhere
+pub fn relative_to_base<'a>(pattern: &str, path: &'a Path) -> &'a Path {
+    let base = pattern
+        .split('/')
+        .take_while(|component| !is_pattern(component))
+        .collect::<Vec<_>>()
+        .join("/");
+
+    path.strip_prefix(&base).unwrap_or(path)
+}
+
+/// Whether a relative path matches a glob pattern, with the same syntax as
+/// `expand` but without touching the filesystem
+pub fn matches(pattern: &str, path: &str) -> bool {
+    let pattern = pattern.split('/').collect::<Vec<_>>();
+    let path = path.split('/').collect::<Vec<_>>();
+
+    matches_components(&pattern, &path)
+}
+
+fn matches_components(pattern: &[&str], path: &[&str]) -> bool {
+    match (pattern.split_first(), path.split_first()) {
+        (None, None) => true,
+        (Some((&"**", rest)), _) => {
+            matches_components(rest, path)
+                || (!path.is_empty() && matches_components(pattern, &path[1..]))
+        }
+        (Some((first, rest)), Some((name, path))) => {
+            matches_component(first, name) && matches_components(rest, path)
+        }
+        _ => false,
+    }
+}
+
+fn is_pattern(component: &str) -> bool {
+    component.contains(['*', '?'])
+}
+
+fn walk(dir: &Path, pattern: &[&str], matches: &mut Vec<PathBuf>) -> Result<()> {
+    let Some((first, rest)) = pattern.split_first() else {
+        return Ok(());
+    };
+
+    if *first == "**" {
+        walk(dir, rest, matches)?;
+    }
+
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let path = entry.path();
+        let name = entry.file_name().to_string_lossy().to_string();
+
+        if *first == "**" {
+            if path.is_dir() {
+                if is_link_to_ancestor(&entry, dir) {
+                    continue;
+                }
+                walk(&path, pattern, matches)?;
+            } else if rest.is_empty() && path.is_file() {
+                // A trailing `**` matches every file below it
+                matches.push(strip_current_dir(path));
+            }
+        } else if matches_component(first, &name) {
+            if rest.is_empty() {
+                if path.is_file() {
+                    matches.push(strip_current_dir(path));
+                }
+            } else if path.is_dir() {
+                walk(&path, rest, matches)?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Whether a symlinked directory points at `dir` or above it, where `**`
+/// would follow it forever. Other symlinked directories are walked.
+fn is_link_to_ancestor(entry: &fs::DirEntry, dir: &Path) -> bool {
+    if !entry
+        .file_type()
+        .is_ok_and(|file_type| file_type.is_symlink())
+    {
+        return false;
+    }
+
+    match (fs::canonicalize(entry.path()), fs::canonicalize(dir)) {
+        (Ok(target), Ok(dir)) => dir.starts_with(target),
+        _ => true,
+    }
+}
+
+fn strip_current_dir(path: PathBuf) -> PathBuf {
+    match path.strip_prefix(".") {
+        Ok(stripped) => stripped.to_path_buf(),
+        Err(_) => path,
+    }
+}
+
+fn matches_component(pattern: &str, name: &str) -> bool {
+    let pattern = pattern.chars().collect::<Vec<_>>();
+    let name = name.chars().collect::<Vec<_>>();
+
+    let (mut p, mut n) = (0, 0);
+    let mut backtrack = None;
+
+    while n < name.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p, n));
+                p += 1;
+            }
+            Some('?') => {
+                p += 1;
+                n += 1;
+            }
+            Some(c) if *c == name[n] => {
+                p += 1;
+                n += 1;
+            }
+            _ => match backtrack {
+                Some((star, matched)) => {
+                    p = star + 1;
+                    n = matched + 1;
+                    backtrack = Some((star, matched + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A directory with `src/main.cpp`, `src/net/socket.cpp`,
+    /// `src/net/tls/session.cpp` and `README.md`
+    fn tree(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("cxx-glob-{}-{}", std::process::id(), name));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("src/net/tls")).unwrap();
+        for file in [
+            "src/main.cpp",
+            "src/net/socket.cpp",
+            "src/net/tls/session.cpp",
+            "README.md",
+        ] {
+            fs::write(dir.join(file), "").unwrap();
+        }
+        dir
+    }
+
+    fn expand_in(dir: &Path, pattern: &str) -> Vec<String> {
+        expand(&format!("{}/{}", dir.display(), pattern))
+            .unwrap()
+            .into_iter()
+            .map(|path| {
+                let path = path.strip_prefix(dir).unwrap().display().to_string();
+                path.replace('\\', "/")
+            })
+            .collect()
+    }
+
+    #[test]
+    fn double_star_matches_any_number_of_directories() {
+        let dir = tree("double-star");
+
+        assert_eq!(
+            expand_in(&dir, "src/**/*.cpp"),
+            [
+                "src/main.cpp",
+                "src/net/socket.cpp",
+                "src/net/tls/session.cpp"
+            ]
+        );
+        assert_eq!(
+            expand_in(&dir, "**/s*.cpp"),
+            ["src/net/socket.cpp", "src/net/tls/session.cpp"]
+        );
+        assert_eq!(
+            expand_in(&dir, "src/net/**"),
+            ["src/net/socket.cpp", "src/net/tls/session.cpp"]
+        );
+        assert_eq!(expand_in(&dir, "*.md"), ["README.md"]);
+        assert!(expand_in(&dir, "src/**/*.hpp").is_empty());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn double_star_follows_symlinked_directories_once() {
+        let dir = tree("symlinks");
+        let vendor = std::env::temp_dir().join(format!("cxx-glob-{}-vendor", std::process::id()));
+        let _ = fs::remove_dir_all(&vendor);
+        fs::create_dir_all(&vendor).unwrap();
+        fs::write(vendor.join("lib.cpp"), "").unwrap();
+        std::os::unix::fs::symlink(&vendor, dir.join("src/vendor")).unwrap();
+        // Links back up the tree would make `**` walk forever
+        std::os::unix::fs::symlink(&dir, dir.join("src/net/root")).unwrap();
+        std::os::unix::fs::symlink(dir.join("src"), dir.join("src/net/tls/src")).unwrap();
+
+        assert_eq!(
+            expand_in(&dir, "src/**/*.cpp"),
+            [
+                "src/main.cpp",
+                "src/net/socket.cpp",
+                "src/net/tls/session.cpp",
+                "src/vendor/lib.cpp",
+            ]
+        );
+        assert_eq!(
+            expand_in(&dir, "src/net/root/src/*.cpp"),
+            ["src/net/root/src/main.cpp"]
+        );
+
+        fs::remove_dir_all(dir).unwrap();
+        fs::remove_dir_all(vendor).unwrap();
+    }
+
+    #[test]
+    fn matches_double_star_without_the_filesystem() {
+        assert!(matches("src/**/*.cpp", "src/main.cpp"));
+        assert!(matches("src/**/*.cpp", "src/net/tls/session.cpp"));
+        assert!(matches("**", "a/b/c"));
+        assert!(matches("**/test_?.cpp", "tests/test_a.cpp"));
+        assert!(!matches("src/**/*.cpp", "include/app.hpp"));
+        assert!(!matches("src/*.cpp", "src/net/socket.cpp"));
+    }
+}
@@ -0,0 +1,244 @@
+use anyhow::{Context, Result};
+use colorize::AnsiColor;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    build_info::macro_prefix,
+    layout,
+    manifest::{Manifest, StyleConfig},
+    sources,
+};
+
+/// How headers protect against multiple inclusion
+pub enum GuardStyle {
+    PragmaOnce,
+    /// `#ifndef`/`#define`/`#endif` guards named by a pattern
+    Macro(String),
+}
+
+impl GuardStyle {
+    pub fn from_config(style: &StyleConfig) -> Result<GuardStyle> {
+        match style.include_guards.as_str() {
+            "pragma" | "pragma_once" => Ok(GuardStyle::PragmaOnce),
+            "macro" | "ifndef" => Ok(GuardStyle::Macro(style.guard_pattern.clone())),
+            other => anyhow::bail!(
+                "Invalid include guard style '{}', valid styles are 'pragma' and 'macro'",
+                other
+            ),
+        }
+    }
+
+    /// The style configured for the project in the current directory
+    pub fn for_project() -> Result<GuardStyle> {
+        GuardStyle::from_config(&Manifest::load()?.style)
+    }
+}
+
+/// Wraps a header body in the configured include guard. `header` is the
+/// path of the header relative to the project root and names the guard.
+pub fn wrap_header(
+    style: &GuardStyle,
+    project_name: &str,
+    header: &Path,
+    body: &str,
+) -> Result<String> {
+    let body = body.trim_matches('\n');
+    let body = if body.is_empty() {
+        String::new()
+    } else {
+        format!("\n{}\n", body)
+    };
+
+    Ok(match style {
+        GuardStyle::PragmaOnce => format!("#pragma once\n{}", body),
+        GuardStyle::Macro(pattern) => {
+            let guard = guard_name(pattern, project_name, header)?;
+            format!("#ifndef {guard}\n#define {guard}\n{body}\n#endif  // {guard}\n")
+        }
+    })
+}
+
+/// Expands `{PROJECT}`, `{PATH}` and `{FILE}` in a guard pattern, e.g.
+/// `{PROJECT}_{PATH}_` gives `APP_NET_SOCKET_HPP_` for
+/// `include/app/net/socket.hpp` in project `app`
+pub fn guard_name(pattern: &str, project_name: &str, header: &Path) -> Result<String> {
+    Ok(guard_name_in(
+        pattern,
+        project_name,
+        header,
+        &layout::include_dir()?,
+        &layout::src_dir()?,
+    ))
+}
+
+/// Like [`guard_name`], with `{PATH}` relative to the given header or
+/// source directory
+fn guard_name_in(
+    pattern: &str,
+    project_name: &str,
+    header: &Path,
+    include_dir: &str,
+    src_dir: &str,
+) -> String {
+    let relative = header
+        .strip_prefix(include_dir)
+        .or_else(|_| header.strip_prefix(src_dir))
+        .unwrap_or(header);
+    let file = header
+        .file_name()
+        .map(|file| file.to_string_lossy().to_string())
+        .unwrap_or_default();
+
+    pattern
+        .replace("{PROJECT}", &macro_prefix(project_name))
+        .replace("{PATH}", &macro_prefix(&relative.display().to_string()))
+        .replace("{FILE}", &macro_prefix(&file))
+}
+
+/// Splits a header into its leading `//` or `/* */` comments, e.g. a license
+/// banner, and its body with any existing include guard removed
+fn strip_guard(text: &str) -> (String, String) {
+    let lines = text.lines().collect::<Vec<_>>();
+    let mut in_comment = false;
+    let code_start = lines
+        .iter()
+        .position(|line| {
+            let line = line.trim();
+            if in_comment {
+                in_comment = !line.contains("*/");
+                return false;
+            }
+            if let Some(comment) = line.strip_prefix("/*") {
+                in_comment = !comment.contains("*/");
+                return false;
+            }
+            !(line.is_empty() || line.starts_with("//"))
+        })
+        .unwrap_or(lines.len());
+
+    let preamble = lines[..code_start].join("\n");
+    let mut body = lines[code_start..].to_vec();
+
+    if body
+        .first()
+        .is_some_and(|line| line.trim() == "#pragma once")
+    {
+        body.remove(0);
+    } else if let Some(guard) = body
+        .first()
+        .and_then(|line| line.trim().strip_prefix("#ifndef "))
+        .map(str::trim)
+    {
+        let defines_guard = body
+            .get(1)
+            .and_then(|line| line.trim().strip_prefix("#define "))
+            .is_some_and(|define| define.trim() == guard);
+        let last = body.iter().rposition(|line| !line.trim().is_empty());
+        let ends_guard = last.is_some_and(|last| body[last].trim().starts_with("#endif"));
+
+        if let (true, true, Some(last)) = (defines_guard, ends_guard, last) {
+            body.truncate(last);
+            body.drain(..2);
+        }
+    }
+
+    (preamble, body.join("\n"))
+}
+
+pub fn handle_fix_include_guards(dry_run: bool) -> Result<()> {
+    let style = GuardStyle::for_project()?;
+    let project_name = crate::cmake::project_name()?;
+
+    let mut changed: Vec<PathBuf> = Vec::new();
+    for header in sources::project_sources()?
+        .into_iter()
+        .filter(|file| sources::is_header(file))
+    {
+        let text = fs::read_to_string(&header)
+            .with_context(|| format!("Failed to read '{}'", header.display()))?;
+
+        let (preamble, body) = strip_guard(&text);
+        let wrapped = wrap_header(&style, &project_name, &header, &body)?;
+        let updated = if preamble.trim().is_empty() {
+            wrapped
+        } else {
+            format!("{}\n\n{}", preamble.trim_end(), wrapped)
+        };
+
+        if updated != text {
+            if !dry_run {
+                fs::write(&header, updated)
+                    .with_context(|| format!("Failed to update '{}'", header.display()))?;
+            }
+            changed.push(header);
+        }
+    }
+
+    let verb = if dry_run { "Would update" } else { "Updated" };
+    eprintln!(
+        "{}",
+        format!("{} include guards in {} headers", verb, changed.len()).green()
+    );
+    for header in changed {
+        eprintln!("  {}", header.display());
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn guard_names_are_relative_to_the_header_directories() {
+        let pattern = "{PROJECT}_{PATH}_";
+        let name = |header: &str| guard_name_in(pattern, "app", Path::new(header), "inc", "src");
+
+        assert_eq!(name("inc/app/net/socket.hpp"), "APP_APP_NET_SOCKET_HPP_");
+        assert_eq!(name("src/detail/pool.hpp"), "APP_DETAIL_POOL_HPP_");
+        assert_eq!(
+            name("include/app/config.hpp"),
+            "APP_INCLUDE_APP_CONFIG_HPP_"
+        );
+        assert_eq!(
+            guard_name_in("{FILE}", "app", Path::new("inc/my-lib.hpp"), "inc", "src"),
+            "MY_LIB_HPP"
+        );
+    }
+
+    #[test]
+    fn strips_pragma_and_macro_guards() {
+        assert_eq!(
+            strip_guard("#pragma once\n\nint f();\n"),
+            (String::new(), "\nint f();".to_string())
+        );
+        assert_eq!(
+            strip_guard("#ifndef A_HPP\n#define A_HPP\nint f();\n#endif  // A_HPP\n\n"),
+            (String::new(), "int f();".to_string())
+        );
+        // A guard whose #define doesn't match is part of the body
+        let text = "#ifndef A_HPP\n#define B_HPP\nint f();\n#endif";
+        assert_eq!(strip_guard(text), (String::new(), text.to_string()));
+    }
+
+    #[test]
+    fn keeps_line_and_block_comments_as_preamble() {
+        let (preamble, body) =
+            strip_guard("// SPDX-License-Identifier: MIT\n\n#pragma once\nint f();\n");
+        assert_eq!(preamble, "// SPDX-License-Identifier: MIT\n");
+        assert_eq!(body, "int f();");
+
+        let (preamble, body) =
+            strip_guard("/*\n * Copyright\n * #pragma once\n */\n#pragma once\nint f();\n");
+        assert_eq!(preamble, "/*\n * Copyright\n * #pragma once\n */");
+        assert_eq!(body, "int f();");
+
+        let (preamble, body) = strip_guard("/* one line */\n#ifndef A\n#define A\n#endif\n");
+        assert_eq!(preamble, "/* one line */");
+        assert_eq!(body, "");
+    }
+}
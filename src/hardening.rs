@@ -0,0 +1,256 @@
+use anyhow::{Context, Result};
+use colorize::AnsiColor;
+use std::{path::Path, process::Command};
+
+use crate::{cmake, executor::executor, fileapi};
+
+/// Cache variable hardened profiles turn on
+pub const CACHE_VARIABLE: &str = "CPP_TOOLS_HARDENED";
+
+const HARDENING_FRAGMENT: &str = "hardening.cmake";
+
+/// Each mitigation is only added when the compiler or linker accepts it, so
+/// the same profile works with GCC, Clang and MSVC on every architecture.
+/// The fragment is included after the targets exist, so it sets their
+/// options instead of the directory's.
+const FRAGMENT: &str = "# Generated by cxx, do not edit.
+# Enables the exploit mitigations the toolchain supports in hardened profiles
+if(NOT CPP_TOOLS_HARDENED)
+  return()
+endif()
+
+include(CheckCompilerFlag)
+include(CheckLinkerFlag)
+include(CheckPIESupported)
+get_property(hardening_languages GLOBAL PROPERTY ENABLED_LANGUAGES)
+if(\"CXX\" IN_LIST hardening_languages)
+  set(hardening_language CXX)
+else()
+  set(hardening_language C)
+endif()
+
+set(hardening_compile_options)
+set(hardening_link_options)
+if(MSVC)
+  list(APPEND hardening_compile_options /GS /guard:cf)
+  list(APPEND hardening_link_options /guard:cf /DYNAMICBASE /NXCOMPAT)
+else()
+  foreach(flag -fstack-protector-strong -fstack-clash-protection -fcf-protection=full -mbranch-protection=standard)
+    string(MAKE_C_IDENTIFIER \"HARDENING${flag}\" supported)
+    check_compiler_flag(${hardening_language} ${flag} ${supported})
+    if(${supported})
+      list(APPEND hardening_compile_options ${flag})
+    endif()
+  endforeach()
+  # Fortification needs optimization and warns without it
+  list(APPEND hardening_compile_options
+    \"$<$<NOT:$<CONFIG:Debug>>:-U_FORTIFY_SOURCE>\"
+    \"$<$<NOT:$<CONFIG:Debug>>:-D_FORTIFY_SOURCE=2>\")
+  check_linker_flag(${hardening_language} \"LINKER:-z,relro,-z,now\" HARDENING_RELRO)
+  if(HARDENING_RELRO)
+    list(APPEND hardening_link_options \"LINKER:-z,relro,-z,now\")
+  endif()
+  check_linker_flag(${hardening_language} \"LINKER:-z,noexecstack\" HARDENING_NOEXECSTACK)
+  if(HARDENING_NOEXECSTACK)
+    list(APPEND hardening_link_options \"LINKER:-z,noexecstack\")
+  endif()
+endif()
+check_pie_supported(LANGUAGES ${hardening_language})
+
+get_property(hardening_targets DIRECTORY PROPERTY BUILDSYSTEM_TARGETS)
+foreach(hardening_target ${hardening_targets})
+  get_target_property(hardening_type ${hardening_target} TYPE)
+  if(hardening_type STREQUAL \"INTERFACE_LIBRARY\")
+    continue()
+  endif()
+  set_target_properties(${hardening_target} PROPERTIES POSITION_INDEPENDENT_CODE ON)
+  target_compile_options(${hardening_target} PRIVATE ${hardening_compile_options})
+  if(hardening_type MATCHES \"^(EXECUTABLE|SHARED_LIBRARY|MODULE_LIBRARY)$\")
+    target_link_options(${hardening_target} PRIVATE ${hardening_link_options})
+  endif()
+endforeach()
+";
+
+/// Writes the fragment that applies hardened profiles
+pub fn write_fragment(root_dir: &Path) -> Result<()> {
+    cmake::write_fragment_in(root_dir, HARDENING_FRAGMENT, FRAGMENT)
+}
+
+/// The mitigations found in one binary. `None` means the check doesn't
+/// apply, e.g. fortification when no fortifiable function is called.
+struct Mitigations {
+    pie: bool,
+    relro: &'static str,
+    canary: bool,
+    nx: bool,
+    fortify: Option<bool>,
+    cet: Option<bool>,
+}
+
+/// Inspects the project's executables like checksec and reports which
+/// exploit mitigations are active in each
+pub fn handle_check_hardening(build_dir: String) -> Result<()> {
+    let executables = fileapi::executables(Path::new(&build_dir))?;
+    let executables = executables
+        .iter()
+        .map(|executable| (executable, Path::new(&build_dir).join(&executable.path)))
+        .filter(|(_, path)| path.exists())
+        .collect::<Vec<_>>();
+    if executables.is_empty() {
+        anyhow::bail!(
+            "No executables found in '{}', build the project first",
+            build_dir
+        );
+    }
+
+    let width = executables
+        .iter()
+        .map(|(executable, _)| executable.name.len())
+        .max()
+        .unwrap_or(0)
+        .max(6);
+    println!(
+        "{:width$}  {:5}  {:7}  {:6}  {:5}  {:7}  {:5}",
+        "Binary",
+        "PIE",
+        "RELRO",
+        "Canary",
+        "NX",
+        "Fortify",
+        "CFI",
+        width = width
+    );
+
+    let mut weak = 0;
+    for (executable, path) in &executables {
+        let mitigations = inspect(path)?;
+        let flag = |active: bool, width: usize| match active {
+            true => format!("{:width$}", "yes", width = width).green(),
+            false => format!("{:width$}", "no", width = width).red(),
+        };
+        let optional = |active: Option<bool>, width: usize| match active {
+            Some(active) => flag(active, width),
+            None => format!("{:width$}", "n/a", width = width),
+        };
+        let relro = match mitigations.relro {
+            "full" => format!("{:7}", "full").green(),
+            "partial" => format!("{:7}", "partial").yellow(),
+            _ => format!("{:7}", "none").red(),
+        };
+        println!(
+            "{:width$}  {}  {}  {}  {}  {}  {}",
+            executable.name,
+            flag(mitigations.pie, 5),
+            relro,
+            flag(mitigations.canary, 6),
+            flag(mitigations.nx, 5),
+            optional(mitigations.fortify, 7),
+            optional(mitigations.cet, 5),
+            width = width
+        );
+
+        let complete = mitigations.pie
+            && mitigations.relro == "full"
+            && mitigations.canary
+            && mitigations.nx
+            && mitigations.fortify != Some(false);
+        if !complete {
+            weak += 1;
+        }
+    }
+
+    if weak > 0 {
+        anyhow::bail!(
+            "{} of {} executables lack mitigations, build them with a hardened profile, e.g. 'cxx build -p release --hardened'",
+            weak,
+            executables.len()
+        );
+    }
+    eprintln!(
+        "{}",
+        format!("All {} executables are hardened", executables.len()).green()
+    );
+
+    Ok(())
+}
+
+/// Reads the ELF headers, dynamic section, symbols and notes of a binary
+/// with readelf
+fn inspect(path: &Path) -> Result<Mitigations> {
+    let output = executor()
+        .capture(
+            Command::new("readelf")
+                .args(["-W", "-h", "-l", "-d", "-s", "-n"])
+                .arg(path),
+        )
+        .with_context(|| {
+            format!(
+                "Failed to inspect {}, is readelf installed?",
+                path.display()
+            )
+        })?;
+    if !output.status.success() {
+        anyhow::bail!(
+            "{} isn't an ELF binary, only Linux executables can be checked",
+            path.display()
+        );
+    }
+    let text = String::from_utf8_lossy(&output.stdout);
+
+    let pie = text
+        .lines()
+        .any(|line| line.trim_start().starts_with("Type:") && line.contains("DYN"));
+    let relro = if !text.contains("GNU_RELRO") {
+        "none"
+    } else if text.contains("BIND_NOW") || text.contains("Flags: NOW") {
+        "full"
+    } else {
+        "partial"
+    };
+    let nx = text
+        .lines()
+        .find(|line| line.trim_start().starts_with("GNU_STACK"))
+        .is_some_and(|line| !line.contains("RWE"));
+
+    let symbols = text
+        .lines()
+        .filter_map(|line| line.split_whitespace().nth(7))
+        .map(|symbol| symbol.split('@').next().unwrap_or(symbol))
+        .collect::<Vec<_>>();
+    let canary = symbols
+        .iter()
+        .any(|symbol| symbol.starts_with("__stack_chk_fail"));
+    let fortified = symbols.iter().any(|symbol| {
+        symbol.starts_with("__") && symbol.ends_with("_chk") && !symbol.starts_with("__stack_chk")
+    });
+    let fortifiable = [
+        "memcpy", "memmove", "memset", "strcpy", "strcat", "sprintf", "snprintf", "printf",
+        "fprintf", "read",
+    ]
+    .iter()
+    .any(|function| symbols.contains(function));
+    let fortify = (fortified || fortifiable).then_some(fortified);
+
+    // x86 CET and Arm BTI are recorded as GNU properties, other
+    // architectures have no equivalent
+    let machine = text
+        .lines()
+        .find(|line| line.trim_start().starts_with("Machine:"))
+        .unwrap_or_default();
+    let cet = if machine.contains("X86-64") || machine.contains("80386") {
+        Some(text.contains("IBT") && text.contains("SHSTK"))
+    } else if machine.contains("AArch64") {
+        Some(text.contains("BTI"))
+    } else {
+        None
+    };
+
+    Ok(Mitigations {
+        pie,
+        relro,
+        canary,
+        nx,
+        fortify,
+        cet,
+    })
+}
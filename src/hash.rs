@@ -0,0 +1,40 @@
+use std::{fs, path::Path};
+
+/// FNV-1a, stable across Rust versions unlike the standard library's hasher,
+/// for cache keys kept between runs
+pub struct Fnv(u64);
+
+impl Fnv {
+    pub fn new() -> Fnv {
+        Fnv(0xcbf29ce484222325)
+    }
+
+    pub fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 ^= u64::from(*byte);
+            self.0 = self.0.wrapping_mul(0x100000001b3);
+        }
+        // Separates consecutive writes, so "ab" + "c" differs from "a" + "bc"
+        self.0 = (self.0 ^ 0xff).wrapping_mul(0x100000001b3);
+    }
+
+    /// Hashes a file's contents, with missing files hashing differently
+    /// from empty ones
+    pub fn write_file(&mut self, path: &Path) {
+        match fs::read(path) {
+            Ok(contents) => self.write(&contents),
+            Err(_) => self.write(&[0xff]),
+        }
+    }
+
+    pub fn finish(&self) -> String {
+        format!("{:016x}", self.0)
+    }
+}
+
+/// The hash of a single file's contents
+pub fn file_hash(path: &Path) -> String {
+    let mut hasher = Fnv::new();
+    hasher.write_file(path);
+    hasher.finish()
+}
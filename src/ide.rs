@@ -0,0 +1,181 @@
+use anyhow::{Context, Result};
+use colorize::AnsiColor;
+use std::{fs, path::Path};
+
+use crate::{
+    backend::GENERATED_MARKER,
+    manifest::{Manifest, MANIFEST_FILE},
+};
+
+const CLANGD_CONFIG: &str = ".clangd";
+
+/// The clang-tidy configuration the editor's checks are taken from
+const TIDY_CONFIG: &str = ".clang-tidy";
+
+/// Warnings `--strict` adds on top of the manifest's, too noisy to fail
+/// builds with but worth seeing while editing
+const STRICT_WARNINGS: &[&str] = &[
+    "-Wall",
+    "-Wextra",
+    "-Wpedantic",
+    "-Wshadow",
+    "-Wconversion",
+    "-Wsign-conversion",
+    "-Wnon-virtual-dtor",
+    "-Woverloaded-virtual",
+    "-Wold-style-cast",
+    "-Wcast-align",
+    "-Wnull-dereference",
+    "-Wdouble-promotion",
+    "-Wformat=2",
+    "-Wimplicit-fallthrough",
+];
+
+/// Generates `.clangd` from the manifest's `[ide.clangd]` table, pointing
+/// clangd at the build directory's compilation database and mirroring the
+/// checks of `.clang-tidy`. `--strict` adds extra warnings and reports
+/// unused and missing includes.
+pub fn handle_ide_clangd(strict: bool, build_dir: String) -> Result<()> {
+    let manifest = Manifest::load()?;
+    let config = &manifest.ide.clangd;
+
+    let header_insertion = match config.header_insertion.to_ascii_lowercase().as_str() {
+        "iwyu" => "IWYU",
+        "never" => "Never",
+        other => anyhow::bail!(
+            "Invalid header_insertion '{}' in [ide.clangd] of {}, valid values are 'iwyu' and 'never'",
+            other,
+            MANIFEST_FILE
+        ),
+    };
+
+    let path = Path::new(CLANGD_CONFIG);
+    if fs::read_to_string(path).is_ok_and(|existing| !existing.contains(GENERATED_MARKER)) {
+        anyhow::bail!(
+            "'{}' exists and wasn't generated by cxx, move it aside to generate it",
+            path.display()
+        );
+    }
+
+    let mut warnings = Vec::new();
+    if strict {
+        warnings.extend(STRICT_WARNINGS.iter().map(|flag| flag.to_string()));
+    }
+    for warning in &config.warnings {
+        if !warnings.contains(warning) {
+            warnings.push(warning.clone());
+        }
+    }
+    let (enabled, disabled) = tidy_checks();
+
+    let mut contents = format!(
+        "# {} ide clangd, do not edit.\nCompileFlags:\n  CompilationDatabase: {}\n",
+        GENERATED_MARKER,
+        yaml_string(&build_dir)
+    );
+    if !warnings.is_empty() {
+        contents.push_str(&format!("  Add: {}\n", yaml_list(&warnings)));
+    }
+    if !config.remove.is_empty() {
+        contents.push_str(&format!("  Remove: {}\n", yaml_list(&config.remove)));
+    }
+    let mut diagnostics = String::new();
+    if !enabled.is_empty() || !disabled.is_empty() {
+        diagnostics.push_str("  ClangTidy:\n");
+        if !enabled.is_empty() {
+            diagnostics.push_str(&format!("    Add: {}\n", yaml_list(&enabled)));
+        }
+        if !disabled.is_empty() {
+            diagnostics.push_str(&format!("    Remove: {}\n", yaml_list(&disabled)));
+        }
+    }
+    if !config.suppress.is_empty() {
+        diagnostics.push_str(&format!("  Suppress: {}\n", yaml_list(&config.suppress)));
+    }
+    if strict {
+        diagnostics.push_str("  UnusedIncludes: Strict\n  MissingIncludes: Strict\n");
+    }
+    if !diagnostics.is_empty() {
+        contents.push_str(&format!("Diagnostics:\n{}", diagnostics));
+    }
+    contents.push_str(&format!(
+        "Completion:\n  HeaderInsertion: {}\nIndex:\n  Background: {}\n",
+        header_insertion,
+        if config.background_index {
+            "Build"
+        } else {
+            "Skip"
+        }
+    ));
+
+    fs::write(path, contents).with_context(|| format!("Failed to write {}", path.display()))?;
+
+    eprintln!(
+        "{}",
+        format!(
+            "Generated {} with {} extra warnings and {} clang-tidy checks",
+            path.display(),
+            warnings.len(),
+            enabled.len() + disabled.len()
+        )
+        .green()
+    );
+
+    Ok(())
+}
+
+/// The enabled and disabled check globs of `.clang-tidy`'s `Checks`, which
+/// is either a single string or a block spanning the indented lines below
+fn tidy_checks() -> (Vec<String>, Vec<String>) {
+    let Ok(config) = fs::read_to_string(TIDY_CONFIG) else {
+        return (Vec::new(), Vec::new());
+    };
+
+    let mut lines = config
+        .lines()
+        .skip_while(|line| !line.starts_with("Checks:"));
+    let Some(first) = lines.next() else {
+        return (Vec::new(), Vec::new());
+    };
+    let mut value = first["Checks:".len()..].trim().to_string();
+    if matches!(value.as_str(), "" | ">" | "|" | ">-" | "|-") {
+        value = lines
+            .take_while(|line| line.starts_with([' ', '\t']) || line.trim().is_empty())
+            .collect::<Vec<_>>()
+            .join(",");
+    } else if value.starts_with(['\'', '"']) && value.len() > 1 && !value.ends_with(&value[..1]) {
+        // A quoted string continued on the next lines
+        let quote = value[..1].to_string();
+        for line in lines {
+            value.push_str(line);
+            if line.trim_end().ends_with(&quote) {
+                break;
+            }
+        }
+    }
+
+    let (disabled, enabled) = value
+        .split([',', '\n'])
+        .map(|check| check.trim().trim_matches(['\'', '"']).trim())
+        .filter(|check| !check.is_empty())
+        .map(|check| check.to_string())
+        .partition::<Vec<_>, _>(|check| check.starts_with('-'));
+    let disabled = disabled
+        .into_iter()
+        .map(|check| check[1..].to_string())
+        .collect();
+
+    (enabled, disabled)
+}
+
+fn yaml_list(items: &[String]) -> String {
+    let items = items
+        .iter()
+        .map(|item| yaml_string(item))
+        .collect::<Vec<_>>();
+    format!("[{}]", items.join(", "))
+}
+
+fn yaml_string(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
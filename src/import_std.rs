@@ -0,0 +1,189 @@
+use anyhow::Result;
+use std::{fs, path::Path, process::Command};
+
+use crate::{cmake::CMAKE_LISTS, executor::executor};
+
+/// CMake only honors `CMAKE_CXX_MODULE_STD` while this variable holds the
+/// gate of its release, which changes with every release while `import std`
+/// is experimental
+const EXPERIMENTAL_VARIABLE: &str = "CMAKE_EXPERIMENTAL_CXX_IMPORT_STD";
+
+/// The gates of the CMake releases supporting `import std`, from their
+/// Help/dev/experimental.rst
+const GATES: [((u32, u32), &str); 4] = [
+    ((3, 30), "0e5b6991-d74f-4b3d-a41c-cf096e0b2508"),
+    ((3, 31), "0e5b6991-d74f-4b3d-a41c-cf096e0b2508"),
+    ((4, 0), "a9e1cf81-9932-4810-974b-6eccaf14e457"),
+    ((4, 1), "d0edc3af-4c50-42ea-a356-e2862fe7a444"),
+];
+
+/// Ninja is the only generator besides Visual Studio that builds modules,
+/// since this release
+const NINJA_VERSION: (u32, u32) = (1, 11);
+
+/// The standard library providing the std module
+pub enum StdLibrary {
+    /// LLVM's libc++, which Clang only uses by default on Apple platforms
+    LibCxx,
+    MsvcStl,
+}
+
+/// What a project using `import std` needs from the toolchain found
+pub struct Support {
+    gate: &'static str,
+    library: StdLibrary,
+}
+
+impl Support {
+    /// Lines of CMakeLists.txt that have to come before `project()`
+    pub fn before_project(&self) -> String {
+        let mut lines = format!(
+            "# import std is experimental, CMake checks this gate of its release
+set({} \"{}\")
+",
+            EXPERIMENTAL_VARIABLE, self.gate
+        );
+        if matches!(self.library, StdLibrary::LibCxx) && !cfg!(target_os = "macos") {
+            lines.push_str("set(CMAKE_CXX_FLAGS \"-stdlib=libc++\" CACHE STRING \"\")\n");
+        }
+
+        lines
+    }
+
+    /// Lines of CMakeLists.txt that come after `project()`
+    pub fn after_project(&self) -> &'static str {
+        "set(CMAKE_CXX_MODULE_STD ON)\n"
+    }
+}
+
+/// Checks that CMake, the generator and the standard library can build
+/// `import std;`, listing everything that's missing otherwise. Without a
+/// `generator` the project is built with Ninja.
+pub fn check(generator: Option<&str>) -> Result<Support> {
+    let mut problems = Vec::new();
+
+    let gate = match version("cmake") {
+        None => {
+            problems.push("CMake wasn't found".to_string());
+            None
+        }
+        Some(cmake) if cmake < GATES[0].0 => {
+            problems.push(format!(
+                "CMake {}.{} or newer is needed, found {}.{}",
+                GATES[0].0 .0, GATES[0].0 .1, cmake.0, cmake.1
+            ));
+            None
+        }
+        Some(cmake) => {
+            let gate = GATES
+                .iter()
+                .find(|(release, _)| *release == cmake)
+                .map(|(_, gate)| *gate);
+            if gate.is_none() {
+                let (newest, _) = GATES[GATES.len() - 1];
+                problems.push(format!(
+                    "CMake {}.{} isn't supported yet, its import std gate is unknown; use CMake {}.{} or set {} from the release's Help/dev/experimental.rst",
+                    cmake.0, cmake.1, newest.0, newest.1, EXPERIMENTAL_VARIABLE
+                ));
+            }
+            gate
+        }
+    };
+
+    match generator {
+        Some(generator) if generator.starts_with("Visual Studio") => {}
+        Some(generator) if !generator.starts_with("Ninja") => problems.push(format!(
+            "The {} generator can't build modules, use Ninja or Visual Studio",
+            generator
+        )),
+        _ => match version("ninja") {
+            Some(ninja) if ninja >= NINJA_VERSION => {}
+            Some(ninja) => problems.push(format!(
+                "Ninja {}.{} or newer is needed, found {}.{}",
+                NINJA_VERSION.0, NINJA_VERSION.1, ninja.0, ninja.1
+            )),
+            None => problems.push("Ninja wasn't found".to_string()),
+        },
+    }
+
+    let library = match std_library() {
+        Ok(library) => Some(library),
+        Err(problem) => {
+            problems.push(problem);
+            None
+        }
+    };
+
+    match (gate, library) {
+        (Some(gate), Some(library)) if problems.is_empty() => Ok(Support { gate, library }),
+        _ => anyhow::bail!(
+            "The toolchain can't build 'import std;':\n  - {}",
+            problems.join("\n  - ")
+        ),
+    }
+}
+
+/// Whether the project in the current directory imports the std module
+pub fn uses_import_std() -> bool {
+    fs::read_to_string(CMAKE_LISTS).is_ok_and(|lists| {
+        lists
+            .lines()
+            .any(|line| line.trim() == "set(CMAKE_CXX_MODULE_STD ON)")
+    })
+}
+
+/// The standard library of the compiler CMake picks up, which has to ship
+/// the std module: MSVC's from a developer prompt, or Clang's libc++
+fn std_library() -> std::result::Result<StdLibrary, String> {
+    if cfg!(windows) {
+        if let Ok(tools) = std::env::var("VCToolsInstallDir") {
+            return match Path::new(&tools).join("modules").join("std.ixx").exists() {
+                true => Ok(StdLibrary::MsvcStl),
+                false => Err(
+                    "MSVC doesn't ship the std module, install Visual Studio 2022 17.5 or newer"
+                        .to_string(),
+                ),
+            };
+        }
+    }
+
+    let compiler = std::env::var("CXX").unwrap_or_else(|_| "c++".to_string());
+    let Ok(output) = executor().capture(Command::new(&compiler).arg("--version")) else {
+        return Err(format!("The C++ compiler {} wasn't found", compiler));
+    };
+    let version = String::from_utf8_lossy(&output.stdout);
+    if !version.contains("clang") {
+        return Err(format!(
+            "{} isn't Clang, import std needs Clang with libc++ or MSVC (set CXX=clang++)",
+            compiler
+        ));
+    }
+
+    // Clang echoes the name back when it doesn't find the file
+    let modules = executor()
+        .capture(
+            Command::new(&compiler)
+                .args(["-stdlib=libc++", "-print-file-name=libc++.modules.json"]),
+        )
+        .map(|output| String::from_utf8_lossy(&output.stdout).trim().to_string())
+        .unwrap_or_default();
+    if Path::new(&modules).is_absolute() && Path::new(&modules).exists() {
+        Ok(StdLibrary::LibCxx)
+    } else {
+        Err("libc++ doesn't ship the std module, install libc++ 18 or newer".to_string())
+    }
+}
+
+/// The major and minor version a tool prints with `--version`
+fn version(tool: &str) -> Option<(u32, u32)> {
+    let output = executor()
+        .capture(Command::new(tool).arg("--version"))
+        .ok()?;
+    let text = String::from_utf8_lossy(&output.stdout);
+    let version = text
+        .split_whitespace()
+        .find(|word| word.starts_with(|c: char| c.is_ascii_digit()))?;
+    let mut parts = version.split('.').map(|part| part.parse::<u32>().ok());
+
+    Some((parts.next()??, parts.next()??))
+}
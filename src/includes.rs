@@ -0,0 +1,281 @@
+use anyhow::{Context, Result};
+use colorize::AnsiColor;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use crate::{
+    compdb::{self, CompileCommand},
+    executor::executor,
+    sources,
+};
+
+/// A header is worth precompiling when at least this share of the
+/// translation units include it
+const PRECOMPILE_SHARE: f64 = 0.5;
+
+/// What a header costs the translation units including it
+#[derive(Default)]
+struct Cost {
+    /// Milliseconds spent parsing it with clang, or preprocessed lines
+    total: f64,
+    /// Translation units including it, directly or not
+    units: usize,
+}
+
+/// Ranks the headers by what they cost across every translation unit of
+/// the compilation database: the time clang's `-ftime-trace` attributes to
+/// them, or without clang the lines they add to the preprocessed output.
+/// Each is suggested for a precompiled header, forward declarations or
+/// removal.
+pub fn handle_includes_cost(build_dir: String, top: usize) -> Result<()> {
+    let commands = compdb::load(&build_dir)?;
+    if commands.is_empty() {
+        anyhow::bail!("The compilation database has no translation units");
+    }
+
+    let time_trace = has_tool("clang");
+    let trace_dir = std::env::temp_dir().join(format!("cxx-time-trace-{}", std::process::id()));
+    if time_trace {
+        fs::create_dir_all(&trace_dir).context("Failed to create the time trace directory")?;
+    }
+    let mut costs = BTreeMap::<PathBuf, Cost>::new();
+    for (index, command) in commands.iter().enumerate() {
+        eprint!(
+            "\rMeasuring {}/{} {}\x1b[K",
+            index + 1,
+            commands.len(),
+            command.file.display()
+        );
+        let unit_costs = match time_trace {
+            true => traced_costs(command, &trace_dir.join(format!("{}.json", index)))?,
+            false => preprocessed_costs(command)?,
+        };
+        for (header, total) in unit_costs {
+            let cost = costs.entry(header).or_default();
+            cost.total += total;
+            cost.units += 1;
+        }
+    }
+    eprint!("\r\x1b[K");
+    if time_trace {
+        let _ = fs::remove_dir_all(&trace_dir);
+    }
+    if costs.is_empty() {
+        anyhow::bail!("No header costs found, does the project compile?");
+    }
+
+    let root = fs::canonicalize(".").context("Failed to read current directory")?;
+    let build = fs::canonicalize(&build_dir).unwrap_or_else(|_| root.join(&build_dir));
+    let project_headers = sources::project_sources()?
+        .into_iter()
+        .filter(|path| sources::is_header(path))
+        .collect::<Vec<_>>();
+
+    let mut ranked = costs.into_iter().collect::<Vec<_>>();
+    ranked.sort_by(|a, b| b.1.total.total_cmp(&a.1.total));
+    let unit = if time_trace { "ms" } else { "lines" };
+    eprintln!(
+        "{}",
+        format!(
+            "Headers by {} across {} translation units",
+            if time_trace {
+                "parse time"
+            } else {
+                "preprocessed lines"
+            },
+            commands.len()
+        )
+        .bold()
+    );
+    for (header, cost) in ranked.iter().take(top) {
+        let in_project = header.starts_with(&root) && !header.starts_with(&build);
+        let name = match in_project {
+            true => header.strip_prefix(&root).unwrap_or(header),
+            false => header.as_path(),
+        };
+        let share = cost.units as f64 / commands.len() as f64;
+        let suggestion = if !in_project && share >= PRECOMPILE_SHARE && commands.len() > 1 {
+            "precompile it".green()
+        } else if in_project {
+            let includers = includers(&project_headers, name);
+            if includers.is_empty() {
+                "include it only where it's used".yellow()
+            } else {
+                format!(
+                    "forward declare its types in {}",
+                    includers
+                        .iter()
+                        .map(|path| path.display().to_string())
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                )
+                .cyan()
+            }
+        } else {
+            "include it only where it's used".yellow()
+        };
+        println!(
+            "{:>10.0} {:<5} {:>4} TUs  {}  {}",
+            cost.total,
+            unit,
+            cost.units,
+            name.display(),
+            suggestion
+        );
+    }
+
+    Ok(())
+}
+
+/// Parses a translation unit with clang's `-ftime-trace` and sums the time
+/// spent in each header, including what it includes
+fn traced_costs(command: &CompileCommand, trace: &Path) -> Result<BTreeMap<PathBuf, f64>> {
+    let c = command
+        .file
+        .extension()
+        .is_some_and(|extension| extension == "c");
+    let Some(mut clang) =
+        compdb::compiler_command(command, Some(if c { "clang" } else { "clang++" }))
+    else {
+        return Ok(BTreeMap::new());
+    };
+    clang
+        .args(["-fsyntax-only", "-w", "-Wno-unknown-warning-option"])
+        .arg(format!("-ftime-trace={}", trace.display()))
+        .arg("-ftime-trace-granularity=0");
+    let output = executor().capture(&mut clang)?;
+    if !output.status.success() {
+        return Ok(BTreeMap::new());
+    }
+
+    let text =
+        fs::read_to_string(trace).with_context(|| format!("Failed to read {}", trace.display()))?;
+    let json: serde_json::Value = serde_json::from_str(&text)
+        .with_context(|| format!("Failed to parse {}", trace.display()))?;
+    let mut costs = BTreeMap::new();
+    for event in json
+        .get("traceEvents")
+        .and_then(|events| events.as_array())
+        .into_iter()
+        .flatten()
+    {
+        if event.get("name").and_then(|name| name.as_str()) != Some("Source") {
+            continue;
+        }
+        let (Some(header), Some(duration)) = (
+            event
+                .get("args")
+                .and_then(|args| args.get("detail"))
+                .and_then(|detail| detail.as_str()),
+            event.get("dur").and_then(|duration| duration.as_f64()),
+        ) else {
+            continue;
+        };
+        if let Ok(header) = fs::canonicalize(command.directory.join(header)) {
+            *costs.entry(header).or_insert(0.0) += duration / 1000.0;
+        }
+    }
+
+    Ok(costs)
+}
+
+/// Preprocesses a translation unit and counts the lines each header adds,
+/// including what it includes, following the preprocessor's line markers
+fn preprocessed_costs(command: &CompileCommand) -> Result<BTreeMap<PathBuf, f64>> {
+    let Some(mut compile) = compdb::compiler_command(command, None) else {
+        return Ok(BTreeMap::new());
+    };
+    let output = executor().capture(compile.arg("-E"))?;
+    if !output.status.success() {
+        return Ok(BTreeMap::new());
+    }
+
+    let mut costs = BTreeMap::<String, f64>::new();
+    let mut stack = Vec::<String>::new();
+    // Lines of the compiler's `<built-in>` and `<command-line>` pseudo files
+    // belong to no header
+    let mut pseudo = false;
+    for line in String::from_utf8_lossy(&output.stdout).lines() {
+        if let Some((file, flags)) = line_marker(line) {
+            pseudo = file.starts_with('<');
+            if pseudo {
+                continue;
+            }
+            match stack.iter().position(|open| *open == file) {
+                // Returning to a file, or a marker within it
+                Some(position) if !flags.contains(&1) => stack.truncate(position + 1),
+                _ if flags.contains(&1) || stack.is_empty() => stack.push(file),
+                _ => *stack.last_mut().expect("stack isn't empty") = file,
+            }
+            continue;
+        }
+        if pseudo || line.trim().is_empty() {
+            continue;
+        }
+        // The translation unit itself is at the bottom of the stack
+        for header in stack.iter().skip(1).collect::<BTreeSet<_>>() {
+            *costs.entry(header.clone()).or_insert(0.0) += 1.0;
+        }
+    }
+
+    Ok(costs
+        .into_iter()
+        .filter_map(|(header, lines)| {
+            Some((
+                fs::canonicalize(command.directory.join(header)).ok()?,
+                lines,
+            ))
+        })
+        .collect())
+}
+
+/// The file and flags of a line marker such as `# 1 "/usr/include/stdio.h" 1 3 4`
+fn line_marker(line: &str) -> Option<(String, Vec<u32>)> {
+    let rest = line.strip_prefix("# ")?;
+    let (number, rest) = rest.split_once(' ')?;
+    number.parse::<u32>().ok()?;
+    let rest = rest.strip_prefix('"')?;
+    let (file, flags) = rest.split_once('"')?;
+    let flags = flags
+        .split_whitespace()
+        .filter_map(|flag| flag.parse().ok())
+        .collect();
+
+    Some((file.replace("\\\\", "\\"), flags))
+}
+
+/// The project headers including a header, whose includes could become
+/// forward declarations
+fn includers(project_headers: &[PathBuf], header: &Path) -> Vec<PathBuf> {
+    let Some(file_name) = header.file_name().map(|name| name.to_string_lossy()) else {
+        return Vec::new();
+    };
+
+    project_headers
+        .iter()
+        .filter(|path| {
+            path.as_path() != header && path.strip_prefix("./").unwrap_or(path) != header
+        })
+        .filter(|path| {
+            fs::read_to_string(path).is_ok_and(|text| {
+                text.lines().any(|line| {
+                    let line = line.trim_start();
+                    line.starts_with("#include")
+                        && (line.contains(&format!("/{}\"", file_name))
+                            || line.contains(&format!("\"{}\"", file_name)))
+                })
+            })
+        })
+        .map(|path| path.strip_prefix("./").unwrap_or(path).to_path_buf())
+        .collect()
+}
+
+fn has_tool(tool: &str) -> bool {
+    executor()
+        .capture(Command::new(tool).arg("--version"))
+        .is_ok()
+}
@@ -0,0 +1,239 @@
+use anyhow::{Context, Result};
+use std::{
+    fs,
+    path::{Component, Path, PathBuf},
+};
+
+use crate::{cmake, manifest::Manifest, FileExtension};
+
+/// Default directories for public headers and sources
+pub const INCLUDE_DIR: &str = "include";
+pub const SRC_DIR: &str = "src";
+
+/// The public header directory of the project in the current directory:
+/// the one its CMakeLists.txt puts on the include path, else the manifest's
+/// `dirs.include`
+pub fn include_dir() -> Result<String> {
+    Ok(cmake::include_dir_in(Path::new("."))
+        .or(Manifest::load()?.dirs.include)
+        .unwrap_or(INCLUDE_DIR.to_string()))
+}
+
+/// The source directory of the project in the current directory, the
+/// manifest's `dirs.src`
+pub fn src_dir() -> Result<String> {
+    Ok(Manifest::load()?.dirs.src.unwrap_or(SRC_DIR.to_string()))
+}
+
+/// How headers and sources are organized in a project
+#[derive(Clone, Copy, PartialEq)]
+pub enum Layout {
+    /// Headers directly in `include/`, sources in `src/`
+    Flat,
+    /// Public headers in `include/<project>/`, private sources in `src/`,
+    /// with namespaces matching the directory structure
+    Canonical,
+}
+
+pub fn parse_layout(layout: &str) -> Result<Layout> {
+    match layout.to_ascii_lowercase().as_str() {
+        "flat" => Ok(Layout::Flat),
+        "canonical" => Ok(Layout::Canonical),
+        _ => {
+            anyhow::bail!("Valid layouts are 'flat' and 'canonical'");
+        }
+    }
+}
+
+/// A project uses the canonical layout when its headers live in a folder
+/// named after it
+pub fn detect_layout(project_name: &str) -> Result<Layout> {
+    Ok(if Path::new(&include_dir()?).join(project_name).is_dir() {
+        Layout::Canonical
+    } else {
+        Layout::Flat
+    })
+}
+
+/// Turns a project or directory name into a C/C++ identifier
+pub fn identifier(name: &str) -> String {
+    name.chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect()
+}
+
+/// Where a scaffolded header and source go, and the namespace they share
+#[derive(Default)]
+pub struct Placement {
+    /// Path of the header, relative to the include directory
+    pub header_dir: PathBuf,
+    /// Directory of the source, relative to the source directory
+    pub source_dir: PathBuf,
+    pub namespace: Vec<String>,
+}
+
+/// Infers the placement of new files from a target directory inside either
+/// the include or the source directory, e.g. `include/app/net` or `src/net`
+/// both give `app::net` in a canonical project named `app`
+pub fn placement(project_name: &str, layout: Layout, dir: Option<&str>) -> Result<Placement> {
+    let mut sub = Vec::new();
+
+    if let Some(dir) = dir {
+        let (include_dir, src_dir) = (include_dir()?, src_dir()?);
+        let dir = Path::new(dir);
+        let relative = dir
+            .strip_prefix(&include_dir)
+            .or_else(|_| dir.strip_prefix(&src_dir))
+            .with_context(|| {
+                format!(
+                    "'{}' must be inside '{}' or '{}'",
+                    dir.display(),
+                    include_dir,
+                    src_dir
+                )
+            })?;
+
+        for component in relative.components() {
+            match component {
+                Component::Normal(part) => sub.push(part.to_string_lossy().to_string()),
+                _ => anyhow::bail!("'{}' must be a plain relative path", dir.display()),
+            }
+        }
+    }
+
+    if layout == Layout::Canonical && sub.first().map(String::as_str) == Some(project_name) {
+        sub.remove(0);
+    }
+
+    let source_dir = sub.iter().collect::<PathBuf>();
+    let (header_dir, namespace) = match layout {
+        Layout::Flat => (
+            source_dir.clone(),
+            sub.iter().map(|s| identifier(s)).collect(),
+        ),
+        Layout::Canonical => (
+            Path::new(project_name).join(&source_dir),
+            std::iter::once(project_name)
+                .chain(sub.iter().map(String::as_str))
+                .map(identifier)
+                .collect(),
+        ),
+    };
+
+    Ok(Placement {
+        header_dir,
+        source_dir,
+        namespace,
+    })
+}
+
+/// Writes the public header and its implementation that a canonical project
+/// starts out with, returning the implementation's path for the source list
+pub fn create_canonical_files(
+    name: &str,
+    project_dir: &str,
+    src_dir: &str,
+    include_dir: &str,
+    file_ext: &FileExtension,
+) -> Result<String> {
+    let header_dir = format!("{}/{}/{}", project_dir, include_dir, name);
+    let namespace = identifier(name);
+
+    fs::create_dir_all(&header_dir).context("Failed to create public header directory")?;
+
+    let (header, header_ext, source) = match file_ext {
+        FileExtension::Cpp => (
+            format!(
+                "#pragma once
+
+namespace {namespace} {{
+
+// Returns the greeting printed by the executable
+const char* greeting();
+
+}}  // namespace {namespace}
+"
+            ),
+            "hpp",
+            format!(
+                "#include <{name}/{name}.hpp>
+
+namespace {namespace} {{
+
+const char* greeting() {{ return \"Hello, world!\"; }}
+
+}}  // namespace {namespace}
+"
+            ),
+        ),
+        FileExtension::C => (
+            format!(
+                "#pragma once
+
+// Returns the greeting printed by the executable
+const char* {namespace}_greeting(void);
+"
+            ),
+            "h",
+            format!(
+                "#include <{name}/{name}.h>
+
+const char* {namespace}_greeting(void) {{ return \"Hello, world!\"; }}
+"
+            ),
+        ),
+    };
+
+    fs::write(format!("{}/{}.{}", header_dir, name, header_ext), header)
+        .context("Failed to create public header")?;
+
+    let source_path = format!("{}/{}.{}", src_dir, name, file_ext);
+    fs::write(format!("{}/{}", project_dir, source_path), source)
+        .context("Failed to create library source file")?;
+
+    Ok(source_path)
+}
+
+/// Writes the public header a header-only library starts out with, which
+/// defines what it declares inline
+pub fn create_header_only_files(
+    name: &str,
+    project_dir: &str,
+    include_dir: &str,
+    file_ext: &FileExtension,
+) -> Result<()> {
+    let header_dir = format!("{}/{}/{}", project_dir, include_dir, name);
+    let namespace = identifier(name);
+
+    fs::create_dir_all(&header_dir).context("Failed to create public header directory")?;
+
+    let (header, header_ext) = match file_ext {
+        FileExtension::Cpp => (
+            format!(
+                "#pragma once
+
+namespace {namespace} {{
+
+// Returns the greeting printed by the examples
+inline const char* greeting() {{ return \"Hello, world!\"; }}
+
+}}  // namespace {namespace}
+"
+            ),
+            "hpp",
+        ),
+        FileExtension::C => (
+            format!(
+                "#pragma once
+
+// Returns the greeting printed by the examples
+static inline const char* {namespace}_greeting(void) {{ return \"Hello, world!\"; }}
+"
+            ),
+            "h",
+        ),
+    };
+
+    fs::write(format!("{}/{}.{}", header_dir, name, header_ext), header)
+        .context("Failed to create public header")
+}
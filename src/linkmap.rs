@@ -0,0 +1,447 @@
+use anyhow::{Context, Result};
+use colorize::AnsiColor;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs,
+    path::Path,
+};
+
+use crate::{cmake, format_size, handle_build_project, toolchain, FailureArgs};
+
+/// Fragment making the linker write a map next to the build files
+const LINKMAP_FRAGMENT: &str = "linkmap.cmake";
+
+/// Maps are only written for optimized builds, the ones whose size matters.
+/// The fragment is included after the targets exist, so it sets their link
+/// options instead of the directory's.
+const FRAGMENT: &str = "# Generated by cxx linkmap, do not edit.
+# Writes a link map of every linked target to <build dir>/<target>.map in optimized builds
+get_property(LINKMAP_TARGETS DIRECTORY PROPERTY BUILDSYSTEM_TARGETS)
+foreach(linkmap_target ${LINKMAP_TARGETS})
+  get_target_property(linkmap_type ${linkmap_target} TYPE)
+  if(NOT linkmap_type MATCHES \"^(EXECUTABLE|SHARED_LIBRARY|MODULE_LIBRARY)$\")
+    continue()
+  endif()
+  set(linkmap_path \"${CMAKE_BINARY_DIR}/${linkmap_target}.map\")
+  if(MSVC)
+    set(linkmap_option \"/MAP:${linkmap_path}\")
+  elseif(APPLE)
+    set(linkmap_option \"LINKER:-map,${linkmap_path}\")
+  else()
+    set(linkmap_option \"LINKER:-Map=${linkmap_path}\")
+  endif()
+  target_link_options(${linkmap_target} PRIVATE \"$<$<NOT:$<CONFIG:Debug>>:${linkmap_option}>\")
+endforeach()
+";
+
+/// Where the GNU ld map lists what was linked, and what wasn't
+const MEMORY_MAP: &str = "Linker script and memory map";
+const DISCARDED_SECTIONS: &str = "Discarded input sections";
+
+/// `(object file, symbol)` pairs of functions with their own section
+pub struct FunctionSections {
+    pub linked: BTreeSet<(String, String)>,
+    pub discarded: BTreeSet<(String, String)>,
+}
+
+/// Bytes an input file contributes to each output section
+#[derive(Default)]
+struct Contributions {
+    sections: BTreeMap<String, u64>,
+    files: BTreeMap<String, BTreeMap<String, u64>>,
+}
+
+impl Contributions {
+    fn add(&mut self, section: &str, file: &str, size: u64) {
+        if size == 0 || !is_allocated(section) {
+            return;
+        }
+        *self.sections.entry(section.to_string()).or_default() += size;
+        *self
+            .files
+            .entry(file.to_string())
+            .or_default()
+            .entry(section.to_string())
+            .or_default() += size;
+    }
+}
+
+/// Shows what the sections of a linked executable are made of, per object
+/// file and per library, from the map the linker writes in release builds
+pub fn handle_linkmap(
+    target: Option<String>,
+    build_dir: String,
+    runtime_dir: String,
+    top: usize,
+) -> Result<()> {
+    let fragment_path = Path::new(cmake::FRAGMENT_DIR).join(LINKMAP_FRAGMENT);
+    let fresh = fs::read_to_string(&fragment_path).ok().as_deref() != Some(FRAGMENT);
+    if fresh {
+        cmake::write_fragment(LINKMAP_FRAGMENT, FRAGMENT)?;
+    }
+
+    let build_type = toolchain::build_config(Path::new(&build_dir))
+        .or_else(|| cmake::cache_value(Path::new(&build_dir), "CMAKE_BUILD_TYPE"))
+        .unwrap_or_default();
+    if build_type.is_empty() || build_type.eq_ignore_ascii_case("debug") {
+        anyhow::bail!(
+            "Link maps are written in optimized builds only, configure one with 'cxx build -p release' or 'cxx build -p minsizerel'"
+        );
+    }
+
+    let target = target.unwrap_or_else(crate::default_exec_name);
+    let map_path = Path::new(&build_dir).join(format!("{}.map", target));
+    if fresh || !map_path.exists() {
+        // CMake picks up the new fragment while building
+        handle_build_project(
+            build_dir.clone(),
+            &runtime_dir,
+            None,
+            &FailureArgs::default(),
+        )?;
+    }
+
+    let map = fs::read_to_string(&map_path).with_context(|| {
+        format!(
+            "Failed to read '{}', is '{}' a target that links?",
+            map_path.display(),
+            target
+        )
+    })?;
+    let contributions = if map.trim_start().starts_with("VMA") {
+        parse_lld(&map)
+    } else {
+        parse_gnu(&map)
+    };
+    if contributions.sections.is_empty() {
+        anyhow::bail!(
+            "Found no sections in '{}', the map format isn't supported",
+            map_path.display()
+        );
+    }
+
+    let total = contributions.sections.values().sum::<u64>();
+    println!(
+        "{}",
+        format!("{} ({} total)", target, format_size(total)).green()
+    );
+    for (section, bytes) in by_size(&contributions.sections) {
+        println!("  {:24} {:>10}", section, format_size(bytes));
+    }
+
+    let mut files = BTreeMap::new();
+    let mut libraries = BTreeMap::new();
+    for (file, sections) in &contributions.files {
+        let bytes = sections.values().sum::<u64>();
+        files.insert(file.clone(), bytes);
+        let library = match file.split_once('(') {
+            Some((archive, _)) => archive.to_string(),
+            None => "(objects)".to_string(),
+        };
+        *libraries.entry(library).or_default() += bytes;
+    }
+
+    println!("\n{}", "By library".green());
+    for (library, bytes) in by_size(&libraries).into_iter().take(top) {
+        println!("  {:>10}  {}", format_size(bytes), library);
+    }
+
+    println!("\n{}", "By object".green());
+    for (file, bytes) in by_size(&files).into_iter().take(top) {
+        let sections = by_size(&contributions.files[&file])
+            .into_iter()
+            .map(|(section, bytes)| format!("{} {}", section, format_size(bytes)))
+            .collect::<Vec<_>>()
+            .join(", ");
+        println!("  {:>10}  {}  ({})", format_size(bytes), file, sections);
+    }
+
+    Ok(())
+}
+
+/// An input section the GNU ld map lists, with the output section it went to
+struct GnuInput<'a> {
+    output: String,
+    input: &'a str,
+    file: &'a str,
+    size: u64,
+}
+
+fn parse_gnu(map: &str) -> Contributions {
+    let mut contributions = Contributions::default();
+    let Some((_, memory_map)) = map.split_once(MEMORY_MAP) else {
+        return contributions;
+    };
+
+    for input in gnu_inputs(memory_map) {
+        contributions.add(&input.output, input.file, input.size);
+    }
+
+    contributions
+}
+
+/// GNU ld lists output sections unindented and the input sections of each
+/// object indented by one space, wrapping long section names onto their own
+/// line, e.g. ` .text  0x0000000000001040  0x1a5 CMakeFiles/app.dir/main.cpp.o`
+fn gnu_inputs(text: &str) -> Vec<GnuInput<'_>> {
+    let mut inputs = Vec::new();
+
+    let mut output = String::new();
+    let mut pending = None;
+    for line in text.lines() {
+        let fields = line.split_whitespace().collect::<Vec<_>>();
+        if fields.is_empty() {
+            continue;
+        }
+
+        if !line.starts_with(' ') {
+            if fields[0].starts_with('.') {
+                output = fields[0].to_string();
+            }
+            pending = None;
+            continue;
+        }
+
+        let (input, values) = if line.starts_with(" .") || line.starts_with(" COMMON") {
+            if fields.len() == 1 {
+                pending = Some(fields[0]);
+                continue;
+            }
+            (fields[0], &fields[1..])
+        } else if let Some(input) = pending.take() {
+            (input, &fields[..])
+        } else {
+            continue;
+        };
+        if let [address, size, file, ..] = values {
+            if address.starts_with("0x") {
+                if let Some(size) = parse_hex(size) {
+                    inputs.push(GnuInput {
+                        output: output.clone(),
+                        input,
+                        file,
+                        size,
+                    });
+                }
+            }
+        }
+    }
+
+    inputs
+}
+
+/// The functions compiled into their own sections, e.g. with
+/// `-ffunction-sections`, per object file: the ones linked in and the ones
+/// `--gc-sections` discarded. `None` unless the map is a GNU ld map listing
+/// discarded sections, as lld maps don't.
+pub fn function_sections(map: &str) -> Option<FunctionSections> {
+    let (_, rest) = map.split_once(DISCARDED_SECTIONS)?;
+    let (discarded, rest) = rest.split_once("Memory Configuration")?;
+    let (_, memory_map) = rest.split_once(MEMORY_MAP)?;
+
+    let functions = |text: &str| {
+        gnu_inputs(text)
+            .into_iter()
+            .filter_map(|input| {
+                let function = input.input.strip_prefix(".text.")?;
+                // GCC moves some functions to e.g. `.text.startup.main`
+                let function = ["startup.", "unlikely.", "hot.", "exit."]
+                    .iter()
+                    .find_map(|prefix| function.strip_prefix(prefix))
+                    .unwrap_or(function);
+                Some((input.file.to_string(), function.to_string()))
+            })
+            .collect::<BTreeSet<_>>()
+    };
+
+    Some(FunctionSections {
+        linked: functions(memory_map),
+        discarded: functions(discarded),
+    })
+}
+
+/// lld prints `VMA LMA Size Align Out In Symbol` columns, with input
+/// sections like `main.cpp.o:(.text)` indented below their output section
+fn parse_lld(map: &str) -> Contributions {
+    let mut contributions = Contributions::default();
+
+    let mut output_section = String::new();
+    for line in map.lines().skip(1) {
+        let fields = line.split_whitespace().collect::<Vec<_>>();
+        let [_, _, bytes, _, name, ..] = fields[..] else {
+            continue;
+        };
+        let Ok(bytes) = u64::from_str_radix(bytes, 16) else {
+            continue;
+        };
+
+        if name.starts_with('.') && fields.len() == 5 {
+            output_section = name.to_string();
+        } else if let Some((file, _)) = name.rsplit_once(":(") {
+            contributions.add(&output_section, file, bytes);
+        }
+    }
+
+    contributions
+}
+
+/// Sections that take space in the binary, as opposed to debug information
+fn is_allocated(section: &str) -> bool {
+    ![
+        ".debug",
+        ".comment",
+        ".note",
+        ".stab",
+        ".gnu.attributes",
+        ".symtab",
+        ".strtab",
+        ".shstrtab",
+    ]
+    .iter()
+    .any(|prefix| section.starts_with(prefix))
+        && !section.is_empty()
+}
+
+fn parse_hex(value: &str) -> Option<u64> {
+    u64::from_str_radix(value.trim_start_matches("0x"), 16).ok()
+}
+
+fn by_size(map: &BTreeMap<String, u64>) -> Vec<(String, u64)> {
+    let mut entries = map
+        .iter()
+        .map(|(name, bytes)| (name.clone(), *bytes))
+        .collect::<Vec<_>>();
+    entries.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    entries
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GNU_MAP: &str = "Archive member included to satisfy reference by file (symbol)
+
+libfoo.a(foo.o)               CMakeFiles/app.dir/main.cpp.o (_Z3foov)
+
+Discarded input sections
+
+ .text.unused   0x0000000000000000       0x12 CMakeFiles/app.dir/main.cpp.o
+ .text._Z3barv  0x0000000000000000        0x8 libfoo.a(foo.o)
+
+Memory Configuration
+
+Name             Origin             Length             Attributes
+*default*        0x0000000000000000 0xffffffffffffffff
+
+Linker script and memory map
+
+LOAD /usr/lib/crt1.o
+LOAD CMakeFiles/app.dir/main.cpp.o
+
+.text           0x0000000000001040       0x96
+ *(.text .text.*)
+ .text          0x0000000000001040       0x26 /usr/lib/crt1.o
+ .text.main
+                0x0000000000001070       0x40 CMakeFiles/app.dir/main.cpp.o
+                0x0000000000001070                main
+ .text.startup.init
+                0x00000000000010b0       0x20 CMakeFiles/app.dir/main.cpp.o
+ .text._Z3foov  0x00000000000010d0       0x10 libfoo.a(foo.o)
+                0x00000000000010d0                _Z3foov
+
+.data           0x0000000000004000       0x10
+ .data          0x0000000000004000        0x8 CMakeFiles/app.dir/main.cpp.o
+ .data          0x0000000000004008        0x8 libfoo.a(foo.o)
+
+.bss            0x0000000000004010        0x4
+ .bss           0x0000000000004010        0x0 CMakeFiles/app.dir/main.cpp.o
+ COMMON         0x0000000000004010        0x4 libfoo.a(foo.o)
+
+.debug_info     0x0000000000000000      0x100
+ .debug_info    0x0000000000000000      0x100 CMakeFiles/app.dir/main.cpp.o
+";
+
+    const LLD_MAP: &str = "             VMA              LMA     Size Align Out     In      Symbol
+          2002a0           2002a0       10     1 .rodata
+          2002a0           2002a0       10     1         CMakeFiles/app.dir/main.cpp.o:(.rodata.str1.1)
+          2012f0           2012f0       76    16 .text
+          2012f0           2012f0       26    16         /usr/lib/crt1.o:(.text)
+          201320           201320       40    16         CMakeFiles/app.dir/main.cpp.o:(.text.main)
+          201320           201320        0     1                 main
+          201360           201360       10    16         libfoo.a(foo.o):(.text._Z3foov)
+          201360           201360        0     1                 _Z3foov
+               0                0       80     1 .debug_info
+               0                0       80     1         CMakeFiles/app.dir/main.cpp.o:(.debug_info)
+";
+
+    fn sizes<const N: usize>(entries: [(&str, u64); N]) -> BTreeMap<String, u64> {
+        entries
+            .into_iter()
+            .map(|(name, bytes)| (name.to_string(), bytes))
+            .collect()
+    }
+
+    #[test]
+    fn parses_gnu_ld_maps() {
+        let contributions = parse_gnu(GNU_MAP);
+
+        assert_eq!(
+            contributions.sections,
+            sizes([(".text", 0x96), (".data", 0x10), (".bss", 0x4)])
+        );
+        assert_eq!(
+            contributions.files["CMakeFiles/app.dir/main.cpp.o"],
+            sizes([(".text", 0x60), (".data", 0x8)])
+        );
+        assert_eq!(
+            contributions.files["libfoo.a(foo.o)"],
+            sizes([(".text", 0x10), (".data", 0x8), (".bss", 0x4)])
+        );
+        assert_eq!(
+            contributions.files["/usr/lib/crt1.o"],
+            sizes([(".text", 0x26)])
+        );
+    }
+
+    #[test]
+    fn parses_lld_maps() {
+        let contributions = parse_lld(LLD_MAP);
+
+        assert_eq!(
+            contributions.sections,
+            sizes([(".rodata", 0x10), (".text", 0x76)])
+        );
+        assert_eq!(
+            contributions.files["CMakeFiles/app.dir/main.cpp.o"],
+            sizes([(".rodata", 0x10), (".text", 0x40)])
+        );
+        assert_eq!(
+            contributions.files["libfoo.a(foo.o)"],
+            sizes([(".text", 0x10)])
+        );
+        assert!(parse_gnu(LLD_MAP).sections.is_empty());
+    }
+
+    #[test]
+    fn lists_linked_and_discarded_function_sections() {
+        let pair = |file: &str, function: &str| (file.to_string(), function.to_string());
+        let functions = function_sections(GNU_MAP).unwrap();
+
+        assert_eq!(
+            functions.linked,
+            BTreeSet::from([
+                pair("CMakeFiles/app.dir/main.cpp.o", "init"),
+                pair("CMakeFiles/app.dir/main.cpp.o", "main"),
+                pair("libfoo.a(foo.o)", "_Z3foov"),
+            ])
+        );
+        assert_eq!(
+            functions.discarded,
+            BTreeSet::from([
+                pair("CMakeFiles/app.dir/main.cpp.o", "unused"),
+                pair("libfoo.a(foo.o)", "_Z3barv"),
+            ])
+        );
+        assert!(function_sections(LLD_MAP).is_none());
+    }
+}
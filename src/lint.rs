@@ -0,0 +1,246 @@
+use anyhow::{Context, Result};
+use colorize::AnsiColor;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+    process::Command,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
+    thread,
+};
+
+use crate::{
+    aux_lint::lint_aux,
+    cmake_lint::lint_cmake,
+    compdb::{self, CompileCommand},
+    executor::executor,
+    hash::{file_hash, Fnv},
+    manifest::{Manifest, STATE_DIR},
+};
+
+/// clang-tidy results of unchanged files, relative to the state directory
+const CACHE_FILE: &str = "lint_cache.json";
+
+/// The clang-tidy configuration, part of every file's key
+const TIDY_CONFIG: &str = ".clang-tidy";
+
+/// The result of linting one file and what it depended on
+#[derive(Serialize, Deserialize, Clone)]
+struct CachedResult {
+    /// Hash of the file, its compile flags and the clang-tidy configuration
+    key: String,
+    /// Hashes of the project headers the file included
+    headers: BTreeMap<PathBuf, String>,
+    /// What clang-tidy reported, empty when the file is clean
+    output: String,
+}
+
+type Cache = BTreeMap<PathBuf, CachedResult>;
+
+/// Runs clang-tidy on the project's translation units, then lints the CMake
+/// files. Files are only rechecked when they, their flags, the headers they
+/// include or the clang-tidy configuration changed, unless `no_cache` is set.
+/// `aux` also lints the CI workflows and shell scripts.
+pub fn handle_lint(
+    build_dir: String,
+    jobs: Option<usize>,
+    no_cache: bool,
+    aux: bool,
+) -> Result<()> {
+    let manifest = Manifest::load()?;
+    let root = fs::canonicalize(".").context("Failed to resolve project directory")?;
+    let build_root = fs::canonicalize(&build_dir).ok();
+    let commands = compdb::load(&build_dir)?
+        .into_iter()
+        .filter_map(|command| {
+            let file = fs::canonicalize(command.directory.join(&command.file)).ok()?;
+            let relative = file.strip_prefix(&root).ok()?.to_path_buf();
+            // Sources CMake generates into the build directory aren't the project's
+            let generated = build_root
+                .as_ref()
+                .is_some_and(|build_root| file.starts_with(build_root));
+            (!generated).then_some((relative, command))
+        })
+        .collect::<Vec<_>>();
+    if commands.is_empty() {
+        anyhow::bail!("The compilation database lists no project sources to lint");
+    }
+
+    if executor().dry_run() {
+        for (file, _) in &commands {
+            executor().execute(
+                Command::new("clang-tidy")
+                    .args(["--quiet", "-p", &build_dir])
+                    .arg(file),
+            )?;
+        }
+        return Ok(());
+    }
+
+    let config_hash = file_hash(Path::new(TIDY_CONFIG));
+    let cached = if no_cache { Cache::new() } else { load() };
+    let mut results = Cache::new();
+    let mut stale = Vec::new();
+    for (file, command) in &commands {
+        let key = key(file, command, &config_hash);
+        match cached.get(file) {
+            Some(result) if result.key == key && headers_unchanged(result) => {
+                results.insert(file.clone(), result.clone());
+            }
+            _ => stale.push((file, command, key)),
+        }
+    }
+
+    let reused = results.len();
+    if !stale.is_empty() {
+        eprintln!("Linting {} files, {} unchanged", stale.len(), reused);
+    }
+
+    let jobs = jobs
+        .or_else(|| thread::available_parallelism().ok().map(|jobs| jobs.get()))
+        .unwrap_or(1)
+        .max(1);
+    let next = AtomicUsize::new(0);
+    let checked = Mutex::new(Vec::new());
+    thread::scope(|scope| {
+        for _ in 0..jobs.min(stale.len()) {
+            scope.spawn(|| loop {
+                let index = next.fetch_add(1, Ordering::Relaxed);
+                let Some((file, command, key)) = stale.get(index) else {
+                    break;
+                };
+                let result = tidy(&build_dir, file, command).map(|output| CachedResult {
+                    key: key.clone(),
+                    headers: included_headers(command, file, &root),
+                    output,
+                });
+                checked
+                    .lock()
+                    .expect("lint results lock")
+                    .push(((*file).clone(), result));
+            });
+        }
+    });
+    for (file, result) in checked.into_inner().expect("lint results lock") {
+        results.insert(file, result?);
+    }
+
+    save(&results)?;
+
+    let mut failed = 0;
+    for (file, result) in &results {
+        if !result.output.trim().is_empty() {
+            failed += 1;
+            eprintln!(
+                "{}\n{}",
+                file.display().to_string().red(),
+                result.output.trim_end()
+            );
+        }
+    }
+    let cmake_problems = lint_cmake(&manifest.lint.cmake)?;
+    let aux_problems = if aux { lint_aux()? } else { 0 };
+    if failed > 0 {
+        anyhow::bail!(
+            "clang-tidy reported problems in {} of {} files",
+            failed,
+            results.len()
+        );
+    }
+    if cmake_problems > 0 {
+        anyhow::bail!("Found {} problems in the CMake files", cmake_problems);
+    }
+    if aux_problems > 0 {
+        anyhow::bail!(
+            "Found {} problems in the CI workflows and scripts",
+            aux_problems
+        );
+    }
+
+    eprintln!(
+        "{}",
+        format!("Linted {} files, {} from the cache", results.len(), reused).green()
+    );
+
+    Ok(())
+}
+
+fn key(file: &Path, command: &CompileCommand, config_hash: &str) -> String {
+    let mut hasher = Fnv::new();
+    hasher.write(config_hash.as_bytes());
+    for argument in &command.arguments {
+        hasher.write(argument.as_bytes());
+    }
+    hasher.write_file(file);
+    hasher.finish()
+}
+
+fn headers_unchanged(result: &CachedResult) -> bool {
+    result
+        .headers
+        .iter()
+        .all(|(header, hash)| file_hash(header) == *hash)
+}
+
+/// Runs clang-tidy on one file, returning its diagnostics
+fn tidy(build_dir: &str, file: &Path, command: &CompileCommand) -> Result<String> {
+    let output = executor()
+        .capture(
+            Command::new("clang-tidy")
+                .arg("--quiet")
+                .arg("-p")
+                .arg(build_dir)
+                .arg(command.directory.join(&command.file)),
+        )
+        .with_context(|| format!("Failed to lint {}", file.display()))?;
+
+    let mut report = String::from_utf8_lossy(&output.stdout).to_string();
+    if !output.status.success() {
+        report.push_str(&String::from_utf8_lossy(&output.stderr));
+        if report.trim().is_empty() {
+            report = format!("clang-tidy failed with {}", output.status);
+        }
+    }
+
+    Ok(report)
+}
+
+/// The project headers a translation unit includes, with their hashes.
+/// System headers are left out.
+fn included_headers(
+    command: &CompileCommand,
+    file: &Path,
+    root: &Path,
+) -> BTreeMap<PathBuf, String> {
+    compdb::dependencies(command)
+        .into_iter()
+        .filter_map(|path| path.strip_prefix(root).ok().map(Path::to_path_buf))
+        .filter(|path| path != file)
+        .map(|path| {
+            let hash = file_hash(&path);
+            (path, hash)
+        })
+        .collect()
+}
+
+fn cache_path() -> PathBuf {
+    Path::new(STATE_DIR).join(CACHE_FILE)
+}
+
+/// A cache that doesn't parse, e.g. from an older version, is started over
+fn load() -> Cache {
+    fs::read_to_string(cache_path())
+        .ok()
+        .and_then(|text| serde_json::from_str(&text).ok())
+        .unwrap_or_default()
+}
+
+fn save(cache: &Cache) -> Result<()> {
+    fs::create_dir_all(STATE_DIR).context("Failed to create state directory")?;
+    let text = serde_json::to_string(cache).context("Failed to serialize lint cache")?;
+    fs::write(cache_path(), text).context("Failed to write lint cache")
+}
@@ -1,7 +1,112 @@
 use anyhow::{Context, Result};
-use clap::{Parser, Subcommand};
+use clap::{CommandFactory, FromArgMatches, Parser, Subcommand};
 use colorize::AnsiColor;
-use std::{fmt::Display, fs, process::Command};
+use std::{
+    ffi::{OsStr, OsString},
+    fmt::Display,
+    fs,
+    io::Write,
+    path::{Path, PathBuf},
+    process::{Command, ExitCode},
+    time::Instant,
+};
+
+mod amalgamate;
+mod archive;
+mod assets;
+mod audit;
+mod aux_lint;
+mod backend;
+mod batch;
+mod bazel;
+mod bench;
+mod budget;
+mod build_info;
+mod build_summary;
+mod cache;
+mod check;
+mod ci;
+mod clang_modules;
+mod clean;
+mod cmake;
+mod cmake_format;
+mod cmake_lint;
+mod codegen;
+mod compdb;
+mod config;
+mod contributors;
+mod corpus;
+mod crash;
+mod deps;
+mod disasm;
+mod doctest;
+mod env;
+mod events;
+mod examples;
+mod executor;
+mod fileapi;
+mod find;
+mod flags;
+mod formula;
+mod forward_decls;
+mod gc;
+mod glob;
+mod guards;
+mod hardening;
+mod hash;
+mod ide;
+mod import_std;
+mod includes;
+mod layout;
+mod linkmap;
+mod lint;
+mod manifest;
+mod memcheck;
+mod mv;
+mod new_report;
+mod ninja_report;
+mod open;
+mod output;
+mod package;
+mod package_manager;
+mod pgo;
+mod profiles;
+mod provenance;
+mod release;
+mod remote;
+mod rename;
+mod replay;
+mod runs;
+mod scaffold;
+mod serve;
+mod sign;
+mod sources;
+mod stats;
+mod tags;
+mod targets;
+mod templates;
+mod test_framework;
+mod testcache;
+mod toolchain;
+mod tui;
+mod upgrade;
+mod variants;
+mod verify;
+mod watch;
+mod workspace;
+mod worktree;
+mod xmake;
+
+use build_summary::BuildLog;
+use config::GlobalConfig;
+use events::Event;
+use executor::{DryRunExecutor, ProcessExecutor};
+use layout::Layout;
+use manifest::{Manifest, RunConfig};
+use new_report::NewReport;
+use remote::{RemoteDebugOptions, RemoteTarget};
+use templates::Template;
+use toolchain::Platform;
 
 /// A simple C/C++ project manager
 #[derive(Parser)]
@@ -9,6 +114,22 @@ use std::{fmt::Display, fs, process::Command};
 struct Args {
     #[clap(subcommand)]
     command: Commands,
+
+    /// Prints the commands that would run instead of running them, and for
+    /// `fix` the files that would change. Files cxx writes itself, e.g. when
+    /// scaffolding a project, are still written.
+    #[clap(long, global = true, env = "CPP_TOOLS_DRY_RUN")]
+    dry_run: bool,
+
+    /// Hides what CMake, the build tool, the test runner and other tools
+    /// print unless they fail, keeping the output of the executables run
+    #[clap(short, long, global = true, env = "CPP_TOOLS_QUIET")]
+    quiet: bool,
+
+    /// Sends JSON-RPC notifications about configuring, compiling and testing
+    /// to fd:<N>, unix:<PATH> or tcp:<HOST>:<PORT>
+    #[clap(long, global = true, env = "CPP_TOOLS_EVENT_STREAM")]
+    event_stream: Option<String>,
 }
 
 /// Supported commands
@@ -17,27 +138,41 @@ enum Commands {
     /// Creates a new C/C++ project
     New {
         /// Sets the name of the project
-        name: String,
-
-        /// Sets the file extension for the project
-        #[clap(short, long, default_value = "cpp")]
-        file_ext: String,
-
-        /// Sets the source directory
-        #[clap(short, long, default_value = "src")]
-        src_dir: String,
+        #[clap(required_unless_present = "batch")]
+        name: Option<String>,
 
-        /// Sets the include directory
-        #[clap(short, long, default_value = "include")]
-        include_dir: String,
+        /// Sets the directory the project is created in
+        #[clap(short, long, default_value = ".")]
+        dir: String,
 
         /// Sets the build directory
-        #[clap(short, long, default_value = "build")]
+        #[clap(short, long, default_value = "build", env = "CPP_TOOLS_BUILD_DIR")]
         build_dir: String,
 
-        /// Sets the executable directory
-        #[clap(short, long, default_value = "bin")]
-        exec_dir: String,
+        #[clap(flatten)]
+        scaffold: ScaffoldArgs,
+
+        #[clap(flatten)]
+        configure: ConfigureArgs,
+
+        /// Prints the created files, options and next steps as JSON on stdout,
+        /// with the tools' output on stderr and no prompts
+        #[clap(long)]
+        json: bool,
+
+        /// Creates a project for every row of a CSV roster with the columns
+        /// name, template, options and remote, instead of one
+        #[clap(
+            long,
+            value_name = "CSV",
+            conflicts_with_all = ["name", "json", "ScaffoldArgs", "ConfigureArgs"]
+        )]
+        batch: Option<String>,
+
+        /// Sets the git remote of every project of the roster, with `{name}`
+        /// standing for the project name
+        #[clap(long, value_name = "URL", requires = "batch")]
+        remote: Option<String>,
     },
     /// Initializes and runs set up for the C/C++ project
     Init {
@@ -46,306 +181,3538 @@ enum Commands {
         root_dir: String,
 
         /// Sets the build directory
-        #[clap(short, long, default_value = "build")]
+        #[clap(short, long, default_value = "build", env = "CPP_TOOLS_BUILD_DIR")]
         build_dir: String,
+
+        /// Scaffolds a new project in the root directory instead of a subdirectory
+        #[clap(short, long)]
+        new: bool,
+
+        /// Removes the CMake cache before configuring
+        #[clap(long, conflicts_with = "new")]
+        fresh: bool,
+
+        #[clap(flatten)]
+        scaffold: ScaffoldArgs,
+
+        #[clap(flatten)]
+        configure: ConfigureArgs,
     },
     /// Builds the C/C++ project
+    #[clap(args_conflicts_with_subcommands = true)]
     Build {
         /// Sets the build directory
-        #[clap(short, long, default_value = "build")]
+        #[clap(short, long, default_value = "build", env = "CPP_TOOLS_BUILD_DIR")]
         build_dir: String,
+
+        /// Sets the executable directory assets are installed into
+        #[clap(short, long, default_value = "bin", env = "CPP_TOOLS_RUNTIME_DIR")]
+        runtime_dir: String,
+
+        /// Also builds all examples
+        #[clap(long)]
+        examples: bool,
+
+        /// Reconfigures from a fresh CMake cache before building
+        #[clap(long)]
+        fresh: bool,
+
+        /// Sets the number of parallel build jobs
+        #[clap(short, long, env = "CPP_TOOLS_BUILD_JOBS")]
+        jobs: Option<usize>,
+
+        #[clap(flatten)]
+        configure: ConfigureArgs,
+
+        #[clap(flatten)]
+        failures: FailureArgs,
+
+        #[clap(subcommand)]
+        action: Option<BuildCommands>,
     },
     /// Runs the built C/C++ project
     Run {
         /// Specifies the build directory
-        #[clap(short, long, default_value = "build")]
+        #[clap(short, long, default_value = "build", env = "CPP_TOOLS_BUILD_DIR")]
         build_dir: String,
 
         /// Specifies the executable directory
-        #[clap(short, long, default_value = "bin")]
+        #[clap(short, long, default_value = "bin", env = "CPP_TOOLS_RUNTIME_DIR")]
         runtime_dir: String,
 
         /// Specifies the executable name
         #[clap(short, long, default_value = None)]
         exec_name: Option<String>,
 
+        /// Specifies a run configuration from the manifest
+        #[clap(short, long, default_value = None)]
+        config: Option<String>,
+
+        /// Specifies an example to run instead of the project executable
+        #[clap(long, conflicts_with = "exec_name")]
+        example: Option<String>,
+
+        #[clap(flatten)]
+        platform: PlatformArgs,
+
+        #[clap(flatten)]
+        output: OutputArgs,
+
+        /// Sets the number of parallel build jobs
+        #[clap(short, long, env = "CPP_TOOLS_BUILD_JOBS")]
+        jobs: Option<usize>,
+
         /// Specifies the executable arguments
         #[clap(last = true)]
         args: Vec<String>,
     },
-    /// Formats the C/C++ project
-    Format {
-        /// Specifies the source directory
-        #[clap(short, long, default_value = "src")]
-        src_dir: String,
+    /// Deploys the built executable to a remote target and debugs it via gdbserver
+    Gdbserver {
+        /// Specifies the ssh host to deploy to (e.g. user@board)
+        #[clap(
+            long,
+            conflicts_with = "container",
+            required_unless_present = "container"
+        )]
+        host: Option<String>,
+
+        /// Specifies the container to deploy to instead of an ssh host
+        #[clap(long)]
+        container: Option<String>,
+
+        /// Specifies the port gdbserver listens on
+        #[clap(short, long, default_value = "2345")]
+        port: u16,
+
+        /// Specifies the directory on the remote target to deploy to
+        #[clap(long, default_value = "/tmp")]
+        remote_dir: String,
+
+        /// Specifies the sysroot for the local debugger
+        #[clap(long)]
+        sysroot: Option<String>,
+
+        /// Specifies the local debugger (gdb, gdb-multiarch, lldb)
+        #[clap(short, long, default_value = "gdb")]
+        debugger: String,
+
+        /// Launches the local debugger instead of printing the connect command
+        #[clap(short, long)]
+        launch: bool,
+
+        /// Specifies the build directory
+        #[clap(short, long, default_value = "build", env = "CPP_TOOLS_BUILD_DIR")]
+        build_dir: String,
+
+        /// Specifies the executable directory
+        #[clap(short, long, default_value = "bin", env = "CPP_TOOLS_RUNTIME_DIR")]
+        runtime_dir: String,
+
+        /// Specifies the executable name
+        #[clap(short, long, default_value = None)]
+        exec_name: Option<String>,
+
+        /// Specifies the executable arguments
+        #[clap(last = true)]
+        args: Vec<String>,
     },
-}
+    /// Prints the environment the executable and the tests run with
+    Env {
+        /// Specifies a run configuration from the manifest
+        #[clap(short, long, default_value = None)]
+        config: Option<String>,
 
-enum FileExtension {
-    Cpp,
-    C,
-}
+        /// Prints the full inherited environment, not just the project's variables
+        #[clap(short, long)]
+        all: bool,
+    },
+    /// Renames the C/C++ project
+    Rename {
+        /// Sets the new name of the project
+        new_name: String,
+    },
+    /// Moves a file and updates the includes and build files referencing it
+    Mv {
+        /// Specifies the file to move
+        source: String,
 
-impl Display for FileExtension {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
-            FileExtension::Cpp => write!(f, "cpp"),
-            FileExtension::C => write!(f, "c"),
-        }
-    }
-}
+        /// Specifies where to move the file to
+        destination: String,
+    },
+    /// Adds a component or a dependency to the C/C++ project
+    #[clap(args_conflicts_with_subcommands = true)]
+    Add {
+        /// Depends on a sibling library member of the workspace, linking it
+        /// and building it first
+        #[clap(long, value_name = "MEMBER", conflicts_with = "dependency")]
+        member: Option<String>,
 
-fn main() -> Result<()> {
-    let Args { command } = Args::parse();
+        /// Specifies a dependency to fetch with FetchContent and link, e.g. fmt
+        dependency: Option<String>,
 
-    match command {
-        Commands::New {
-            name,
-            file_ext,
-            src_dir,
-            include_dir,
-            build_dir,
-            exec_dir,
-        } => handle_new_project(name, file_ext, src_dir, include_dir, build_dir, exec_dir),
-        Commands::Init {
-            root_dir,
-            build_dir,
-        } => handle_init_project(&root_dir, &build_dir),
-        Commands::Build { build_dir } => handle_build_project(build_dir),
-        Commands::Run {
-            build_dir,
-            runtime_dir,
-            exec_name,
-            args,
-        } => handle_run_project(build_dir, runtime_dir, exec_name, args),
-        Commands::Format { src_dir } => handle_format_project(src_dir),
-    }
-}
+        /// Specifies the git repository of the dependency
+        #[clap(long, value_name = "URL", requires = "dependency")]
+        git: Option<String>,
 
-fn handle_new_project(
-    name: String,
-    file_ext: String,
-    src_dir: String,
-    include_dir: String,
-    build_dir: String,
-    exec_dir: String,
-) -> Result<()> {
-    if fs::metadata(&name).is_ok() {
-        anyhow::bail!("Project '{}' already exists", name);
-    }
+        /// Specifies the tag or commit of the dependency
+        #[clap(long, requires = "dependency")]
+        tag: Option<String>,
 
-    let file_ext = match file_ext.to_ascii_lowercase().as_str() {
-        "cpp" => FileExtension::Cpp,
-        "c" => FileExtension::C,
-        _ => {
-            anyhow::bail!("Valid file extensions are 'cpp' and 'c'");
-        }
-    };
+        /// Specifies a target of the dependency to link, e.g. fmt::fmt,
+        /// defaults to <DEPENDENCY>::<DEPENDENCY>
+        #[clap(long, value_name = "TARGET", requires = "dependency")]
+        link: Vec<String>,
 
-    create_directories(&name, &src_dir, &include_dir, &build_dir, &exec_dir)?;
-    create_project_files(
-        &name,
-        &src_dir,
-        &include_dir,
-        &build_dir,
-        &exec_dir,
-        &file_ext,
-    )?;
-    handle_init_project(&name, &build_dir)?;
-    initialize_version_control(&name)?;
+        #[clap(subcommand)]
+        component: Option<AddCommands>,
+    },
+    /// Removes a dependency added with add
+    Remove {
+        /// Specifies the dependency, e.g. fmt
+        dependency: String,
+    },
+    /// Applies automatic fixes to the C/C++ project
+    Fix {
+        /// Converts header include guards to the style configured in the manifest
+        #[clap(long)]
+        include_guards: bool,
 
-    eprintln!("{}", format!("Created new project '{}'", name).green());
+        /// Replaces includes of project headers only needed for pointers and
+        /// references with forward declarations, moving them to the sources
+        #[clap(long)]
+        forward_decls: bool,
 
-    Ok(())
-}
+        /// Sets the build directory, whose compilation database checks the
+        /// forward declarations
+        #[clap(short, long, default_value = "build", env = "CPP_TOOLS_BUILD_DIR")]
+        build_dir: String,
+    },
+    /// Runs static checks on the C/C++ project
+    Check {
+        /// Compiles every public header with the compilers and standards in the manifest
+        #[clap(long)]
+        api: bool,
 
-fn create_directories(
-    name: &str,
-    src_dir: &str,
-    include_dir: &str,
-    build_dir: &str,
-    exec_dir: &str,
-) -> Result<()> {
-    fs::create_dir_all(format!("{}/{}", name, src_dir))
-        .context("Failed to create source directory")?;
-    fs::create_dir_all(format!("{}/{}", name, include_dir))
-        .context("Failed to create include directory")?;
-    fs::create_dir_all(format!("{}/{}", name, build_dir))
-        .context("Failed to create build directory")?;
-    fs::create_dir_all(format!("{}/{}", name, exec_dir))
-        .context("Failed to create executable directory")?;
+        /// Finds symbols defined more than once in the object files linked together
+        #[clap(long)]
+        odr: bool,
 
-    Ok(())
-}
+        /// Finds sources that aren't built, headers nothing includes and functions nothing calls
+        #[clap(long)]
+        unused: bool,
 
-fn create_project_files(
-    name: &str,
-    src_dir: &str,
-    include_dir: &str,
-    build_dir: &str,
-    exec_dir: &str,
-    file_ext: &FileExtension,
-) -> Result<()> {
-    let project_lang = match file_ext {
-        FileExtension::Cpp => "CXX",
-        FileExtension::C => "C",
-    };
+        /// Reports the exploit mitigations active in the project's executables
+        #[clap(long)]
+        hardening: bool,
 
-    let project_type = match file_ext {
-        FileExtension::Cpp => "CXX_",
-        FileExtension::C => "C_",
-    };
+        /// Sets the build directory
+        #[clap(short, long, default_value = "build", env = "CPP_TOOLS_BUILD_DIR")]
+        build_dir: String,
+    },
+    /// Generates the targets declared in the manifest into CMake
+    Sync,
+    /// Generates build files for another build system (bazel, xmake), or
+    /// archives the sources with --zip
+    Export {
+        /// Specifies the build system, e.g. bazel
+        #[clap(required_unless_present = "zip")]
+        format: Option<String>,
 
-    let version = match file_ext {
-        FileExtension::Cpp => "23",
-        FileExtension::C => "17",
-    };
+        /// Archives the project's sources into a zip instead, without what
+        /// git ignores, build directories and caches
+        #[clap(long, conflicts_with = "format")]
+        zip: bool,
 
-    fs::write(
-        format!("{}/.gitignore", name),
-        format!(
-            "
-.*
+        /// Includes the fetched dependencies and a lock of their commits
+        #[clap(long, requires = "zip")]
+        vendor: bool,
 
-# Build and executable directories
-{}
-{}
-",
-            build_dir, exec_dir
-        ),
-    )
-    .context("Failed to create .gitignore file")?;
+        /// Sets the archive's path [default: dist/<name>.zip]
+        #[clap(short, long, requires = "zip")]
+        output: Option<String>,
 
-    fs::write(
-        format!("{}/CMakeLists.txt", name),
-        format!(
-            "cmake_minimum_required(VERSION 3.24)
-project({name} {project_lang})
+        /// Signs the archive keylessly with cosign
+        #[clap(long, requires = "zip")]
+        sign: bool,
 
-# Set compiler flags
-set(CMAKE_{project_type}STANDARD {version})
-set(CMAKE_{project_type}STANDARD_REQUIRED ON)
-set(CMAKE_{project_type}EXTENSIONS OFF)
-set(CMAKE_{project_type}FLAGS \"${{CMAKE_{project_type}FLAGS}} -Wall -Werror -Wextra -pedantic -pedantic-errors -g\")
+        /// Sets the build directory, left out of the archive and where
+        /// --vendor finds the dependencies
+        #[clap(short, long, default_value = "build", env = "CPP_TOOLS_BUILD_DIR")]
+        build_dir: String,
+    },
+    /// Reports known vulnerabilities of the pinned dependencies from the OSV database
+    Audit {
+        /// Uses the advisories cached by the last online audit
+        #[clap(long)]
+        offline: bool,
 
-# Include project headers
-include_directories(./{include_dir})
-# Define the source files and dependencies for the executable
-set(SOURCE_FILES {src_dir}/main.{file_ext})
+        /// Sets the lowest severity that fails (none, low, medium, high, critical)
+        /// [default: the manifest's, high]
+        #[clap(long)]
+        fail_on: Option<String>,
 
-# Make the project root directory the working directory when we run
-set(CMAKE_RUNTIME_OUTPUT_DIRECTORY ${{CMAKE_CURRENT_SOURCE_DIR}}/{exec_dir})
-set(CMAKE_EXPORT_COMPILE_COMMANDS TRUE)
-add_executable({name} ${{SOURCE_FILES}})
-",
-        ),
-    ).context("Failed to create CMakeLists.txt file")?;
+        /// Sets the build directory dependencies are fetched into
+        #[clap(short, long, default_value = "build", env = "CPP_TOOLS_BUILD_DIR")]
+        build_dir: String,
+    },
+    /// Generates a CI pipeline testing the manifest's build matrix (github, gitlab, azure, circleci)
+    Ci {
+        /// Specifies the CI provider, e.g. gitlab
+        provider: String,
 
-    fs::write(
-        format!("{}/{}/main.{}", name, src_dir, file_ext),
-        format!(
-            "// Copyright (c) 2024 Author. All Rights Reserved.
-{}
+        /// Sets the build directory
+        #[clap(short, long, default_value = "build", env = "CPP_TOOLS_BUILD_DIR")]
+        build_dir: String,
+    },
+    /// Opens the main source, a class or the compilation database in $EDITOR
+    Open {
+        /// Specifies what to open, e.g. a class name like FooBar or
+        /// compile_commands [default: the main source]
+        target: Option<String>,
 
-int main() {{
-  {}
-  return 0;
-}}
-",
-            match file_ext {
-                FileExtension::Cpp => "#include <iostream>",
-                FileExtension::C => "#include <stdio.h>",
-            },
-            match file_ext {
-                FileExtension::Cpp => "std::cout << \"Hello, world!\" << std::endl;",
-                FileExtension::C => "printf(\"Hello, world!\\n\");",
-            },
-        ),
-    )
-    .context("Failed to create main source file")?;
+        /// Sets the build directory
+        #[clap(short, long, default_value = "build", env = "CPP_TOOLS_BUILD_DIR")]
+        build_dir: String,
+    },
+    /// Generates editor configuration from the manifest
+    Ide {
+        #[clap(subcommand)]
+        tool: IdeCommands,
+    },
+    /// Prints the compiler command line of a source file from the compilation database
+    Flags {
+        /// Specifies the source file
+        file: String,
 
-    Ok(())
-}
+        /// Shows how the flags differ from another source file's
+        #[clap(long)]
+        diff: Option<String>,
 
-fn initialize_version_control(name: &str) -> Result<()> {
-    let command = format!(
-        "cd {} && git init && git add . && git commit -m \"Initial commit\"",
-        name
-    );
+        /// Sets the build directory
+        #[clap(short, long, default_value = "build", env = "CPP_TOOLS_BUILD_DIR")]
+        build_dir: String,
+    },
+    /// Generates tags for editors without a language server (ctags, etags, global)
+    Tags {
+        /// Specifies the tag format, e.g. ctags
+        #[clap(default_value = "ctags")]
+        format: String,
 
-    run_command(&command).context("Failed to initialize version control")?;
+        /// Sets the build directory dependencies are fetched into
+        #[clap(short, long, default_value = "build", env = "CPP_TOOLS_BUILD_DIR")]
+        build_dir: String,
+    },
+    /// Shows what takes up space in a release binary, from its linker map
+    Linkmap {
+        /// Specifies the target [default: the project executable]
+        target: Option<String>,
 
-    Ok(())
-}
+        /// Sets how many objects and libraries to list
+        #[clap(long, default_value_t = 20)]
+        top: usize,
 
-fn handle_init_project(root_dir: &str, build_dir: &str) -> Result<()> {
-    let command = format!("cmake -S ./{}/ -B ./{}/{}/", root_dir, root_dir, build_dir);
+        /// Sets the build directory
+        #[clap(short, long, default_value = "build", env = "CPP_TOOLS_BUILD_DIR")]
+        build_dir: String,
 
-    run_command(&command).context("Failed to initialize project")?;
+        /// Specifies the executable directory
+        #[clap(short, long, default_value = "bin", env = "CPP_TOOLS_RUNTIME_DIR")]
+        runtime_dir: String,
+    },
+    /// Finds the definitions, declarations and references of a symbol
+    Find {
+        /// Specifies the symbol, optionally qualified, e.g. app::FooBar
+        symbol: String,
 
-    eprintln!(
-        "{}",
-        format!("Initialized project in '{}'", build_dir).green()
-    );
+        /// Sets the build directory holding compile_commands.json
+        #[clap(short, long, default_value = "build", env = "CPP_TOOLS_BUILD_DIR")]
+        build_dir: String,
 
-    Ok(())
-}
+        /// Only lists definitions and declarations
+        #[clap(short, long)]
+        declarations: bool,
+    },
+    /// Runs the project tests
+    Test {
+        /// Sets the build directory
+        #[clap(short, long, default_value = "build", env = "CPP_TOOLS_BUILD_DIR")]
+        build_dir: String,
 
-fn handle_build_project(build_dir: String) -> Result<()> {
-    let command = format!("cmake --build ./{}/", build_dir);
+        /// Sets the executable directory assets are installed into
+        #[clap(short, long, default_value = "bin", env = "CPP_TOOLS_RUNTIME_DIR")]
+        runtime_dir: String,
 
-    run_command(&command).context("Failed to run build command")?;
+        /// Compile-checks the C/C++ code blocks in the README and header comments
+        #[clap(long)]
+        doc: bool,
 
-    eprintln!("{}", "Build successful".green());
+        /// Also runs documentation code blocks that build into a program
+        #[clap(long, requires = "doc")]
+        run: bool,
 
-    Ok(())
-}
+        /// Runs every test, including unchanged ones that passed before
+        #[clap(long)]
+        no_cache: bool,
 
-fn handle_run_project(
-    build_dir: String,
-    runtime_dir: String,
-    exec_name: Option<String>,
-    args: Vec<String>,
-) -> Result<()> {
-    let exec_name = exec_name.unwrap_or_else(|| {
-        let output = Command::new("pwd")
-            .output()
-            .expect("Failed to execute command");
-        let pwd = String::from_utf8_lossy(&output.stdout).trim().to_string();
-        let name = pwd.split('/').last().unwrap();
-        name.to_string()
-    });
-    let args = args.join(" ");
-    let command = format!("cd {} && ./{} {}", runtime_dir, exec_name, args);
+        /// Replays the cases recorded with `run --record` and fails when
+        /// their output drifted
+        #[clap(long, conflicts_with = "doc")]
+        replay: bool,
 
-    handle_build_project(build_dir.clone()).context("Failed to build project")?;
-    run_command(&command).context("Failed to run executable")?;
+        /// Only runs the tests whose name matches the regex
+        #[clap(long, value_name = "REGEX", conflicts_with_all = ["doc", "replay"])]
+        filter: Option<String>,
 
-    Ok(())
-}
+        /// Only reruns the tests that crashed in the last run
+        #[clap(long, conflicts_with_all = ["doc", "replay", "filter"])]
+        crashed: bool,
 
-fn handle_format_project(src_dir: String) -> Result<()> {
-    let command = format!("clang-format -i -style=file ./{}/{}", src_dir, "*");
+        /// Builds and tests optimized, in `<BUILD_DIR>-release`
+        #[clap(long, conflicts_with = "doc")]
+        release: bool,
 
-    run_command(&command).context("Failed to format project")?;
+        /// Runs the tests under valgrind and fails when any leaks or has
+        /// memory errors
+        #[clap(long, conflicts_with_all = ["doc", "replay", "asan"])]
+        valgrind: bool,
 
-    Ok(())
-}
+        /// Builds with AddressSanitizer, in `<BUILD_DIR>-asan`, and fails
+        /// when any test leaks or has memory errors
+        #[clap(long, conflicts_with_all = ["doc", "replay", "release"])]
+        asan: bool,
 
-fn run_command(command: &str) -> Result<()> {
-    let output = Command::new("bash")
-        .arg("-c")
-        .arg(command)
-        .spawn()
-        .context("Failed to spawn command")?
-        .wait_with_output()
-        .context("Failed to wait on command")?;
-
-    if output.status.success() {
-        println!("{}", String::from_utf8_lossy(&output.stdout));
-    } else {
-        eprintln!("{}", String::from_utf8_lossy(&output.stderr));
-    }
+        /// Specifies a file suppressing known leaks and errors, in the
+        /// format of valgrind or LeakSanitizer
+        #[clap(long, value_name = "FILE")]
+        suppressions: Option<String>,
 
-    Ok(())
+        /// Sets the number of parallel build jobs
+        #[clap(short, long, env = "CPP_TOOLS_BUILD_JOBS")]
+        jobs: Option<usize>,
+    },
+    /// Seeds a fuzz corpus in fuzz/corpus/ from the recorded cases and example inputs
+    Corpus {
+        /// Specifies the fuzz target whose corpus is seeded [default: the project name]
+        #[clap(long)]
+        target: Option<String>,
+
+        /// Adds every file in these directories as a seed
+        #[clap(short, long, value_name = "DIR")]
+        inputs: Vec<String>,
+
+        /// Also records a replay case for every input file no case covers yet
+        #[clap(long)]
+        cases: bool,
+
+        /// Passes the input file as the argument instead of on stdin
+        #[clap(long, requires = "cases")]
+        as_arg: bool,
+
+        /// Sets the build directory
+        #[clap(short, long, default_value = "build", env = "CPP_TOOLS_BUILD_DIR")]
+        build_dir: String,
+
+        /// Specifies the executable directory
+        #[clap(short, long, default_value = "bin", env = "CPP_TOOLS_RUNTIME_DIR")]
+        runtime_dir: String,
+    },
+    /// Builds the project optimized with profiles of a training run
+    Pgo {
+        /// Sets the build directory, the builds go into `<BUILD_DIR>-pgo-instrumented`
+        /// and `<BUILD_DIR>-pgo`
+        #[clap(short, long, default_value = "build", env = "CPP_TOOLS_BUILD_DIR")]
+        build_dir: String,
+
+        /// Specifies the command line to train with, the tests by default
+        #[clap(long, value_name = "COMMAND")]
+        train: Option<String>,
+
+        /// Sets the executable directory
+        #[clap(short, long, default_value = "bin", env = "CPP_TOOLS_RUNTIME_DIR")]
+        runtime_dir: String,
+
+        /// Trains again even when the commit's profiles exist
+        #[clap(long)]
+        retrain: bool,
+
+        /// Also rewrites the executable with BOLT after sampling the training
+        /// run with perf, on Linux
+        #[clap(long, requires = "train")]
+        bolt: bool,
+
+        /// Sets the number of parallel build jobs
+        #[clap(short, long, env = "CPP_TOOLS_BUILD_JOBS")]
+        jobs: Option<usize>,
+    },
+    /// Builds and runs the benchmarks added with `add bench`
+    Bench {
+        /// Sets the build directory
+        #[clap(short, long, default_value = "build", env = "CPP_TOOLS_BUILD_DIR")]
+        build_dir: String,
+
+        /// Sets the executable directory
+        #[clap(short, long, default_value = "bin", env = "CPP_TOOLS_RUNTIME_DIR")]
+        runtime_dir: String,
+
+        /// Only runs the benchmarks whose name matches the regex
+        #[clap(long, value_name = "REGEX")]
+        filter: Option<String>,
+    },
+    /// Runs clang-tidy on the files that changed since the last lint and
+    /// lints the CMake files
+    Lint {
+        /// Sets the build directory
+        #[clap(short, long, default_value = "build", env = "CPP_TOOLS_BUILD_DIR")]
+        build_dir: String,
+
+        /// Sets the number of files linted in parallel
+        #[clap(short, long, env = "CPP_TOOLS_BUILD_JOBS")]
+        jobs: Option<usize>,
+
+        /// Lints every file, including unchanged ones
+        #[clap(long)]
+        no_cache: bool,
+
+        /// Also lints the CI workflows with yamllint and the hook and shell
+        /// scripts with shellcheck
+        #[clap(long)]
+        aux: bool,
+    },
+    /// Summarizes who owns each directory of the C++ tree from git blame and
+    /// log, optionally suggesting a CODEOWNERS file
+    Contributors {
+        /// Number of directory levels summarized separately
+        #[clap(long, default_value_t = 2)]
+        depth: usize,
+
+        /// How far back recent activity is counted, in git's date syntax
+        #[clap(long, default_value = "6 months ago")]
+        since: String,
+
+        /// Writes CODEOWNERS.suggested routing each directory to its owners
+        #[clap(long)]
+        codeowners: bool,
+    },
+    /// Shows the output of runs captured with `run --capture`
+    Runs {
+        #[clap(subcommand)]
+        action: RunsCommands,
+    },
+    /// Runs tasks across the members of a workspace
+    Workspace {
+        #[clap(subcommand)]
+        task: WorkspaceCommands,
+    },
+    /// Publishes the project's libraries
+    Publish {
+        #[clap(subcommand)]
+        destination: PublishCommands,
+    },
+    /// Checks an artifact's cosign signature and who signed it
+    VerifyArtifact {
+        /// Specifies the artifact, e.g. a downloaded release archive
+        artifact: String,
+
+        /// Sets the signature bundle [default: <ARTIFACT>.sigstore.json]
+        #[clap(long)]
+        bundle: Option<String>,
+
+        /// Specifies the signer: an email address, or the workflow URI for CI
+        #[clap(long)]
+        identity: String,
+
+        /// Specifies the OpenID Connect issuer that vouched for the signer,
+        /// e.g. https://token.actions.githubusercontent.com
+        #[clap(long)]
+        issuer: String,
+    },
+    /// Runs the quality gate configured in the manifest before pushing
+    Verify {
+        /// Sets the build directory
+        #[clap(short, long, default_value = "build", env = "CPP_TOOLS_BUILD_DIR")]
+        build_dir: String,
+
+        /// Sets the executable directory assets are installed into
+        #[clap(short, long, default_value = "bin", env = "CPP_TOOLS_RUNTIME_DIR")]
+        runtime_dir: String,
+
+        /// Sets the number of parallel build jobs
+        #[clap(short, long, env = "CPP_TOOLS_BUILD_JOBS")]
+        jobs: Option<usize>,
+
+        /// Runs the remaining steps after one fails
+        #[clap(short, long)]
+        keep_going: bool,
+    },
+    /// Shows statistics recorded by previous commands
+    Stats {
+        #[clap(subcommand)]
+        kind: StatsCommands,
+    },
+    /// Merges the headers of a header-only library into one in dist/
+    Amalgamate {
+        /// Specifies the header to start from [default: the umbrella header
+        /// named after the project, or else every public header]
+        #[clap(long)]
+        header: Option<String>,
+
+        /// Sets the single header's path [default: dist/<name>.hpp]
+        #[clap(short, long)]
+        output: Option<String>,
+
+        /// Sets the build directory, whose compiler checks the single header
+        #[clap(short, long, default_value = "build", env = "CPP_TOOLS_BUILD_DIR")]
+        build_dir: String,
+    },
+    /// Measures what the included headers cost to compile
+    Includes {
+        #[clap(subcommand)]
+        action: IncludesCommands,
+    },
+    /// Inspects and edits the CMake cache, and reports and prunes the build caches
+    Cache {
+        #[clap(subcommand)]
+        action: CacheCommands,
+    },
+    /// Builds the project at two git refs and diffs the assembly of a function or file
+    DisasmDiff {
+        /// Specifies the ref to compare from, e.g. main
+        base: String,
+
+        /// Specifies the ref to compare to
+        #[clap(default_value = "HEAD")]
+        head: String,
+
+        /// Compares the functions with this name, with or without namespaces
+        #[clap(long, required_unless_present = "file")]
+        function: Option<String>,
+
+        /// Compares the functions compiled from this source file
+        #[clap(long)]
+        file: Option<String>,
+
+        /// Sets the build profile both refs are built with
+        #[clap(short, long, default_value = "release")]
+        profile: String,
+
+        /// Sets the number of parallel build jobs
+        #[clap(short, long)]
+        jobs: Option<usize>,
+    },
+    /// Removes what the build produced, keeping the configuration unless --deep
+    Clean {
+        /// Also deletes the CMake cache and the executable directory, then
+        /// configures the build directory again
+        #[clap(long, conflicts_with = "target")]
+        deep: bool,
+
+        /// Cleans only this target
+        #[clap(long)]
+        target: Option<String>,
+
+        /// Sets the build directory
+        #[clap(short, long, default_value = "build", env = "CPP_TOOLS_BUILD_DIR")]
+        build_dir: String,
+
+        /// Sets the executable directory
+        #[clap(short, long, default_value = "bin", env = "CPP_TOOLS_RUNTIME_DIR")]
+        runtime_dir: String,
+    },
+    /// Removes the object files of build directories that weren't used recently
+    Gc {
+        /// Sets how many of the most recently used build directories to keep intact
+        #[clap(long, default_value_t = 1)]
+        keep_last: usize,
+
+        /// Also deletes the build directory variants of configurations no
+        /// local branch has anymore
+        #[clap(long)]
+        prune_variants: bool,
+
+        /// Sets the build directory, whose `<BUILD_DIR>-*` siblings are collected too
+        #[clap(short, long, default_value = "build", env = "CPP_TOOLS_BUILD_DIR")]
+        build_dir: String,
+    },
+    /// Reruns a cxx command whenever a project file changes, e.g. watch "test"
+    Watch {
+        /// Specifies the command and its options, e.g. "build -p release"
+        command: String,
+
+        /// Watches files matching a glob, replacing the manifest's [watch] include
+        #[clap(long)]
+        include: Vec<String>,
+
+        /// Ignores files matching a glob, replacing the manifest's [watch] exclude
+        #[clap(long)]
+        exclude: Vec<String>,
+
+        /// Keeps the output of previous runs on screen
+        #[clap(long)]
+        no_clear: bool,
+    },
+    /// Opens a terminal dashboard with the build output, diagnostics and test results
+    Tui {
+        /// Sets the build directory
+        #[clap(short, long, default_value = "build", env = "CPP_TOOLS_BUILD_DIR")]
+        build_dir: String,
+
+        /// Specifies the executable directory
+        #[clap(short, long, default_value = "bin", env = "CPP_TOOLS_RUNTIME_DIR")]
+        runtime_dir: String,
+    },
+    /// Serves the Doxygen docs and coverage report locally, reloading them when regenerated
+    Serve {
+        /// Sets the port to listen on
+        #[clap(long, default_value_t = 8000)]
+        port: u16,
+
+        /// Specifies the Doxygen HTML directory [default: from the Doxyfile]
+        #[clap(long)]
+        docs: Option<String>,
+
+        /// Specifies the coverage report directory [default: <BUILD_DIR>-coverage/coverage]
+        #[clap(long)]
+        coverage: Option<String>,
+
+        /// Sets the build directory
+        #[clap(short, long, default_value = "build", env = "CPP_TOOLS_BUILD_DIR")]
+        build_dir: String,
+    },
+    /// Reads and edits configuration keys
+    Config {
+        #[clap(subcommand)]
+        action: ConfigCommands,
+    },
+    /// Updates cxx to the latest release
+    Upgrade {
+        /// Only checks for a newer version, failing if there is one
+        #[clap(long)]
+        check: bool,
+    },
+    /// Formats the C/C++ project and its CMake files
+    Format {
+        /// Specifies the source directory
+        #[clap(short, long, default_value = "src", env = "CPP_TOOLS_SRC_DIR")]
+        src_dir: String,
+
+        /// Fails when a file isn't formatted instead of formatting it
+        #[clap(long)]
+        check: bool,
+    },
+    /// Formats CMakeLists.txt and .cmake files with gersemi or cmake-format,
+    /// or a basic built-in formatter
+    FmtCmake {
+        /// Fails when a file isn't formatted instead of formatting it
+        #[clap(long)]
+        check: bool,
+    },
+}
+
+impl Commands {
+    /// The canonical root of the project the command works on, the one the
+    /// current directory is in or the root directory `init` was pointed at.
+    /// `new` creates a project that doesn't exist yet.
+    fn project_root(&self) -> Option<PathBuf> {
+        let root = match self {
+            Commands::New { .. } => return None,
+            Commands::Init { root_dir, .. } => PathBuf::from(root_dir),
+            _ => cmake::project_root_of(&std::env::current_dir().ok()?)?,
+        };
+
+        fs::canonicalize(root).ok()
+    }
+
+    /// The build directory the command works in, if it takes one. `gc`'s is
+    /// the one whose variants it prunes, not a variant itself.
+    fn build_dir_mut(&mut self) -> Option<&mut String> {
+        match self {
+            Commands::New { .. } | Commands::Init { .. } | Commands::Gc { .. } => None,
+            Commands::Build {
+                action: Some(BuildCommands::Report { build_dir, .. }),
+                ..
+            }
+            | Commands::Build { build_dir, .. }
+            | Commands::Run { build_dir, .. }
+            | Commands::Gdbserver { build_dir, .. }
+            | Commands::Fix { build_dir, .. }
+            | Commands::Check { build_dir, .. }
+            | Commands::Export { build_dir, .. }
+            | Commands::Audit { build_dir, .. }
+            | Commands::Ci { build_dir, .. }
+            | Commands::Open { build_dir, .. }
+            | Commands::Flags { build_dir, .. }
+            | Commands::Tags { build_dir, .. }
+            | Commands::Linkmap { build_dir, .. }
+            | Commands::Find { build_dir, .. }
+            | Commands::Test { build_dir, .. }
+            | Commands::Corpus { build_dir, .. }
+            | Commands::Pgo { build_dir, .. }
+            | Commands::Bench { build_dir, .. }
+            | Commands::Lint { build_dir, .. }
+            | Commands::Verify { build_dir, .. }
+            | Commands::Amalgamate { build_dir, .. }
+            | Commands::Clean { build_dir, .. }
+            | Commands::Tui { build_dir, .. }
+            | Commands::Serve { build_dir, .. }
+            | Commands::Includes {
+                action: IncludesCommands::Cost { build_dir, .. },
+            }
+            | Commands::Ide {
+                tool: IdeCommands::Clangd { build_dir, .. },
+            }
+            | Commands::Cache {
+                action:
+                    CacheCommands::Show { build_dir, .. }
+                    | CacheCommands::Set { build_dir, .. }
+                    | CacheCommands::Stats { build_dir }
+                    | CacheCommands::Prune { build_dir, .. },
+            }
+            | Commands::Add {
+                component: Some(AddCommands::Bench { build_dir, .. }),
+                ..
+            }
+            | Commands::Publish {
+                destination:
+                    PublishCommands::Local { build_dir, .. } | PublishCommands::Github { build_dir, .. },
+            } => Some(build_dir),
+            _ => None,
+        }
+    }
+
+    /// Whether the command builds, configuring its build directory first
+    /// when it has to
+    fn builds(&self) -> bool {
+        matches!(
+            self,
+            Commands::Build { action: None, .. }
+                | Commands::Run { .. }
+                | Commands::Test { .. }
+                | Commands::Pgo { .. }
+                | Commands::Bench { .. }
+        )
+    }
+}
+
+#[derive(Subcommand)]
+enum StatsCommands {
+    /// Charts build times and lists the slowest files to compile
+    Build {
+        /// Sets how many of the most recent builds are shown
+        #[clap(short, long, default_value = "50")]
+        last: usize,
+    },
+}
+
+#[derive(Subcommand)]
+enum IncludesCommands {
+    /// Ranks headers by their compile time across all translation units,
+    /// suggesting which to precompile, forward declare or remove
+    Cost {
+        /// Sets how many headers are listed
+        #[clap(long, default_value_t = 20)]
+        top: usize,
+
+        /// Sets the build directory
+        #[clap(short, long, default_value = "build", env = "CPP_TOOLS_BUILD_DIR")]
+        build_dir: String,
+    },
+}
+
+#[derive(Subcommand)]
+enum ConfigCommands {
+    /// Prints the value of a key
+    Get {
+        /// Specifies the key, e.g. style.include_guards
+        key: String,
+
+        #[clap(flatten)]
+        scope: ScopeArgs,
+    },
+    /// Sets a key
+    Set {
+        /// Specifies the key, e.g. style.include_guards
+        key: String,
+
+        /// Specifies the new value
+        value: String,
+
+        #[clap(flatten)]
+        scope: ScopeArgs,
+    },
+    /// Lists all keys with their values
+    List {
+        #[clap(flatten)]
+        scope: ScopeArgs,
+    },
+}
+
+/// Which configuration file a `config` command works on
+#[derive(clap::Args)]
+struct ScopeArgs {
+    /// Uses the per-user configuration
+    #[clap(long, conflicts_with = "project")]
+    global: bool,
+
+    /// Uses the project manifest
+    #[clap(long)]
+    project: bool,
+}
+
+impl ScopeArgs {
+    fn scope(&self) -> Option<config::Scope> {
+        match (self.global, self.project) {
+            (true, _) => Some(config::Scope::Global),
+            (_, true) => Some(config::Scope::Project),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Subcommand)]
+enum IdeCommands {
+    /// Generates .clangd with IDE-only warnings, include handling and the .clang-tidy checks
+    Clangd {
+        /// Adds extra warnings and reports unused and missing includes
+        #[clap(long)]
+        strict: bool,
+
+        /// Sets the build directory holding compile_commands.json
+        #[clap(short, long, default_value = "build", env = "CPP_TOOLS_BUILD_DIR")]
+        build_dir: String,
+    },
+}
+
+#[derive(Subcommand)]
+enum CacheCommands {
+    /// Prints the value of a cache entry
+    Show {
+        /// Specifies the cache variable, e.g. CMAKE_BUILD_TYPE
+        name: String,
+
+        /// Sets the build directory
+        #[clap(short, long, default_value = "build", env = "CPP_TOOLS_BUILD_DIR")]
+        build_dir: String,
+    },
+    /// Sets a cache entry and reconfigures the project
+    Set {
+        /// Specifies the cache variable, e.g. CMAKE_BUILD_TYPE
+        name: String,
+
+        /// Specifies the new value
+        value: String,
+
+        /// Sets the build directory
+        #[clap(short, long, default_value = "build", env = "CPP_TOOLS_BUILD_DIR")]
+        build_dir: String,
+    },
+    /// Shows compiler cache hit rates and the size of every cache cxx uses
+    Stats {
+        /// Sets the build directory
+        #[clap(short, long, default_value = "build", env = "CPP_TOOLS_BUILD_DIR")]
+        build_dir: String,
+    },
+    /// Removes the least recently used cache entries until every cache fits a size
+    Prune {
+        /// Specifies the size each cache is limited to, e.g. 10G or 512M
+        #[clap(long)]
+        max_size: String,
+
+        /// Sets the build directory
+        #[clap(short, long, default_value = "build", env = "CPP_TOOLS_BUILD_DIR")]
+        build_dir: String,
+    },
+}
+
+/// CMake configuration options shared by `new`, `init` and `build`
+#[derive(clap::Args, Default)]
+struct ConfigureArgs {
+    /// Sets the CMake generator, e.g. Ninja
+    #[clap(short = 'G', long, env = "CPP_TOOLS_GENERATOR")]
+    generator: Option<String>,
+
+    /// Sets the build profile, built-in (debug, release, relwithdebinfo,
+    /// minsizerel) or defined in the manifest
+    #[clap(short, long, env = "CPP_TOOLS_PROFILE")]
+    profile: Option<String>,
+
+    /// Enables exploit mitigations on top of the profile: fortification,
+    /// stack protectors, PIE, full RELRO and control-flow protection
+    #[clap(long, requires = "profile")]
+    hardened: bool,
+
+    /// Builds project headers as Clang header modules, with a generated
+    /// module map and a module cache in the build directory (experimental)
+    #[clap(long)]
+    clang_modules: bool,
+
+    /// Sets the architecture Visual Studio generators build for (x64, arm64)
+    #[clap(long)]
+    arch: Option<String>,
+
+    /// Builds universal arm64 and x86_64 binaries on macOS
+    #[clap(long, conflicts_with = "target")]
+    universal: bool,
+
+    #[clap(flatten)]
+    platform: PlatformArgs,
+
+    /// Sanitizers added to the profile, for builds cxx configures itself
+    #[clap(skip)]
+    sanitizers: Vec<String>,
+}
+
+impl ConfigureArgs {
+    fn is_set(&self) -> bool {
+        self.generator.is_some()
+            || self.profile.is_some()
+            || self.hardened
+            || self.clang_modules
+            || self.arch.is_some()
+            || self.universal
+            || self.platform.target.is_some()
+    }
+}
+
+/// How `run` invokes the program
+struct Invocation {
+    /// A run configuration from the manifest
+    config: Option<String>,
+    args: Vec<String>,
+    output: OutputArgs,
+}
+
+/// Where `run` keeps what the program prints
+#[derive(clap::Args, Default)]
+struct OutputArgs {
+    /// Tees the program's stdout and stderr to a file, each line timestamped
+    #[clap(long, value_name = "FILE")]
+    log: Option<String>,
+
+    /// Stores the program's output and exit code for `runs show`
+    #[clap(long)]
+    capture: bool,
+
+    /// Records the arguments, environment, piped stdin and stdout as a case
+    /// `test --replay` checks
+    #[clap(long, value_name = "CASE", conflicts_with_all = ["log", "capture"])]
+    record: Option<String>,
+}
+
+/// When `build` fails and how it reports a failed build
+#[derive(clap::Args, Default)]
+struct FailureArgs {
+    /// Lists every diagnostic with its code snippet and notes in the summary
+    /// of a failed build, instead of the first error per file
+    #[clap(long)]
+    explain: bool,
+
+    /// Stops at the first translation unit that fails to compile and shows
+    /// only its diagnostics
+    #[clap(long)]
+    stop_on_first_error: bool,
+
+    /// Keeps building what doesn't depend on failed steps, to report as many
+    /// independent failures as possible in one build
+    #[clap(long, conflicts_with = "stop_on_first_error")]
+    keep_going: bool,
+
+    /// Stops a `--keep-going` build after this many failed steps (Ninja only)
+    #[clap(long, value_name = "N", requires = "keep_going")]
+    max_errors: Option<usize>,
+
+    /// Fails the build when a file, or compiling as a whole, takes more than
+    /// PERCENT longer than its recent median, the manifest's
+    /// `time_budget.percent` by default (Ninja only)
+    #[clap(long, value_name = "PERCENT", num_args = 0..=1)]
+    time_budget: Option<Option<f64>>,
+}
+
+/// The platform to cross compile for, shared by the configure options and `run`
+#[derive(clap::Args, Default, Clone)]
+struct PlatformArgs {
+    /// Cross compiles for another platform (windows, windows-msvc, android).
+    /// `run` runs Windows executables under Wine and Android ones on the
+    /// device connected with adb.
+    #[clap(long)]
+    target: Option<String>,
+
+    /// Sets the Android ABI (arm64-v8a, armeabi-v7a, x86, x86_64)
+    #[clap(long, requires = "target")]
+    abi: Option<String>,
+
+    /// Sets the minimum Android API level [default: 24]
+    #[clap(long, requires = "target")]
+    api: Option<u32>,
+}
+
+impl PlatformArgs {
+    fn platform(&self) -> Result<Option<Platform>> {
+        self.target
+            .as_deref()
+            .map(|target| toolchain::parse_platform(target, self.abi.as_deref(), self.api))
+            .transpose()
+    }
+}
+
+/// Project scaffolding options shared by `new` and `init --new`
+#[derive(clap::Args)]
+struct ScaffoldArgs {
+    /// Sets the file extension for the project
+    #[clap(short, long, default_value = "cpp")]
+    file_ext: String,
+
+    /// Sets the source directory
+    #[clap(short, long, default_value = "src")]
+    src_dir: String,
+
+    /// Sets the include directory
+    #[clap(short, long, default_value = "include")]
+    include_dir: String,
+
+    /// Sets the executable directory
+    #[clap(short, long, default_value = "bin")]
+    exec_dir: String,
+
+    /// Sets the project layout (flat, canonical)
+    #[clap(short, long, default_value = "flat")]
+    layout: String,
+
+    /// Sets what the project builds (bin, static, shared, header-only)
+    #[clap(long, default_value = "bin")]
+    kind: String,
+
+    /// Sets the build system (cmake, xmake)
+    #[clap(long, default_value = "cmake")]
+    build_system: String,
+
+    /// Imports the C++23 std module instead of including standard headers,
+    /// once the toolchain is checked for it
+    #[clap(long)]
+    import_std: bool,
+
+    /// Starts from a sample project instead of hello world (async), or
+    /// from a template directory
+    #[clap(long, conflicts_with = "import_std")]
+    template: Option<String>,
+
+    /// Runs the post-generate hook of a template directory without asking
+    #[clap(long, requires = "template")]
+    trust_template: bool,
+
+    /// Sets the package manager dependencies are installed with (conan)
+    #[clap(long)]
+    package_manager: Option<String>,
+}
+
+impl ScaffoldArgs {
+    fn uses_xmake(&self) -> Result<bool> {
+        match self.build_system.to_ascii_lowercase().as_str() {
+            "cmake" => Ok(false),
+            "xmake" => Ok(true),
+            _ => anyhow::bail!("Valid build systems are 'cmake' and 'xmake'"),
+        }
+    }
+
+    fn package_manager(&self) -> Result<Option<Box<dyn package_manager::PackageManager>>> {
+        let Some(name) = &self.package_manager else {
+            return Ok(None);
+        };
+        let manager = package_manager::parse(name)?;
+        if self.uses_xmake()? {
+            anyhow::bail!("Package managers require a project built with CMake");
+        }
+
+        Ok(Some(manager))
+    }
+
+    fn template(&self, file_ext: &FileExtension) -> Result<Option<Template>> {
+        let Some(template) = &self.template else {
+            return Ok(None);
+        };
+        let template = templates::parse_template(template)?;
+        if matches!(template, Template::Async)
+            && (matches!(file_ext, FileExtension::C) || self.uses_xmake()?)
+        {
+            anyhow::bail!("Templates require a C++ project built with CMake");
+        }
+        templates::check_hooks(&template, self.trust_template)?;
+
+        Ok(Some(template))
+    }
+
+    /// What `--import-std` needs from the toolchain. It's checked before
+    /// anything is created, and makes Ninja the default generator.
+    fn import_std_support(
+        &self,
+        file_ext: &FileExtension,
+        configure: &mut ConfigureArgs,
+    ) -> Result<Option<import_std::Support>> {
+        if !self.import_std {
+            return Ok(None);
+        }
+        if matches!(file_ext, FileExtension::C) || self.uses_xmake()? {
+            anyhow::bail!("--import-std requires a C++ project built with CMake");
+        }
+
+        let support = import_std::check(configure.generator.as_deref())?;
+        configure
+            .generator
+            .get_or_insert_with(|| "Ninja".to_string());
+
+        Ok(Some(support))
+    }
+
+    /// What the project builds. Libraries use the canonical layout, so their
+    /// headers install into a directory named after them.
+    fn kind(&mut self) -> Result<ProjectKind> {
+        let kind = match self.kind.to_ascii_lowercase().as_str() {
+            "bin" => return Ok(ProjectKind::Bin),
+            "static" => ProjectKind::Static,
+            "shared" => ProjectKind::Shared,
+            "header-only" => ProjectKind::HeaderOnly,
+            _ => anyhow::bail!("Valid kinds are 'bin', 'static', 'shared' and 'header-only'"),
+        };
+        if self.uses_xmake()? {
+            anyhow::bail!("Library projects require CMake");
+        }
+        if self.template.is_some() || self.import_std {
+            anyhow::bail!("--template and --import-std require a bin project");
+        }
+        self.layout = "canonical".to_string();
+
+        Ok(kind)
+    }
+}
+
+#[derive(Subcommand)]
+enum BuildCommands {
+    /// Analyzes the last Ninja build: its critical path, most expensive
+    /// steps and how many cores were busy over time
+    Report {
+        /// Sets how many of the most expensive steps are listed
+        #[clap(long, default_value_t = 10)]
+        top: usize,
+
+        /// Sets the build directory
+        #[clap(short, long, default_value = "build", env = "CPP_TOOLS_BUILD_DIR")]
+        build_dir: String,
+    },
+}
+
+/// Components that can be added to a project
+#[derive(Subcommand)]
+enum AddCommands {
+    /// Adds a class with a header and source file
+    Class {
+        /// Sets the name of the class
+        name: String,
+
+        /// Sets the directory, inside include/ or src/, the namespace is inferred from
+        #[clap(short, long, default_value = None)]
+        dir: Option<String>,
+
+        /// Only generates the header
+        #[clap(long)]
+        header_only: bool,
+    },
+    /// Adds an example executable in examples/
+    Example {
+        /// Sets the name of the example
+        name: String,
+    },
+    /// Adds a header file
+    Header {
+        /// Sets the name of the header
+        name: String,
+
+        /// Sets the directory, inside include/ or src/, the namespace is inferred from
+        #[clap(short, long, default_value = None)]
+        dir: Option<String>,
+    },
+    /// Wires a schema compiler into the build
+    Codegen {
+        /// Specifies the code generator (protobuf, flatbuffers)
+        generator: String,
+
+        /// Specifies the directory containing the schemas
+        #[clap(short, long, default_value = "proto")]
+        schema: String,
+    },
+    /// Installs the libraries with the files find_package() needs to find them
+    Package,
+    /// Adds a Google Benchmark of a function declared in a project header
+    Bench {
+        /// Specifies the function, qualified with its namespace if needed
+        function: String,
+
+        /// Specifies the build directory holding the compilation database
+        #[clap(short, long, default_value = "build", env = "CPP_TOOLS_BUILD_DIR")]
+        build_dir: String,
+    },
+}
+
+#[derive(Subcommand)]
+enum RunsCommands {
+    /// Lists the captured runs
+    List,
+    /// Prints the output of a captured run
+    Show {
+        /// Specifies the run, the latest by default
+        run: Option<String>,
+    },
+    /// Compares the output, exit code and duration of two captured runs
+    Diff {
+        /// Specifies the earlier run, the one before the latest by default
+        before: Option<String>,
+        /// Specifies the later run, the latest by default
+        after: Option<String>,
+    },
+}
+
+#[derive(Subcommand)]
+enum PublishCommands {
+    /// Installs a release build into the per-user package registry
+    Local {
+        /// Sets the build directory, the release build goes into `<BUILD_DIR>-publish`
+        #[clap(short, long, default_value = "build", env = "CPP_TOOLS_BUILD_DIR")]
+        build_dir: String,
+
+        /// Sets the number of parallel build jobs
+        #[clap(short, long)]
+        jobs: Option<usize>,
+    },
+    /// Creates a GitHub release with artifacts for the [publish] targets
+    Github {
+        /// Sets the release tag, e.g. v1.2.0, matching package.version
+        #[clap(long)]
+        tag: String,
+
+        /// Uploads the files of a directory instead of building, e.g. CI's artifacts
+        #[clap(long, value_name = "DIR")]
+        artifacts: Option<String>,
+
+        /// Creates the release as a draft to review before publishing
+        #[clap(long)]
+        draft: bool,
+
+        /// Signs every uploaded file keylessly with cosign
+        #[clap(long)]
+        sign: bool,
+
+        /// Sets the build directory, each target builds in `<BUILD_DIR>-release-<TARGET>`
+        #[clap(short, long, default_value = "build", env = "CPP_TOOLS_BUILD_DIR")]
+        build_dir: String,
+
+        /// Sets the number of parallel build jobs
+        #[clap(short, long)]
+        jobs: Option<usize>,
+    },
+    /// Generates a Homebrew formula installing the executable of a GitHub release
+    Formula {
+        /// Sets the release tag, the latest release by default
+        #[clap(long)]
+        tag: Option<String>,
+
+        /// Also generates an AUR PKGBUILD
+        #[clap(long)]
+        aur: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum WorkspaceCommands {
+    /// Builds every member after the members it depends on
+    Build {
+        #[clap(flatten)]
+        options: WorkspaceArgs,
+    },
+    /// Tests every member after the members it depends on
+    Test {
+        #[clap(flatten)]
+        options: WorkspaceArgs,
+    },
+}
+
+/// How a workspace task picks and schedules members
+#[derive(clap::Args)]
+struct WorkspaceArgs {
+    /// Sets the number of members run in parallel
+    #[clap(short, long, env = "CPP_TOOLS_BUILD_JOBS")]
+    jobs: Option<usize>,
+
+    /// Only runs the members changed since `--since` and those depending on them
+    #[clap(long)]
+    affected: bool,
+
+    /// The git ref `--affected` compares against
+    #[clap(
+        long,
+        value_name = "REF",
+        default_value = "origin/main",
+        requires = "affected"
+    )]
+    since: String,
+
+    /// Runs members whose files and dependencies are unchanged too
+    #[clap(long)]
+    no_cache: bool,
+}
+
+enum FileExtension {
+    Cpp,
+    C,
+}
+
+impl FileExtension {
+    /// The version of the standard new projects use
+    fn standard_version(&self) -> &'static str {
+        match self {
+            FileExtension::Cpp => "23",
+            FileExtension::C => "17",
+        }
+    }
+
+    /// The standard new projects use, e.g. `c++23`
+    fn standard(&self) -> String {
+        match self {
+            FileExtension::Cpp => format!("c++{}", self.standard_version()),
+            FileExtension::C => format!("c{}", self.standard_version()),
+        }
+    }
+}
+
+/// What a new project builds
+#[derive(Clone, Copy, PartialEq)]
+enum ProjectKind {
+    Bin,
+    Static,
+    Shared,
+    HeaderOnly,
+}
+
+impl ProjectKind {
+    /// The type `add_library()` declares, none for executables
+    fn library_type(&self) -> Option<&'static str> {
+        match self {
+            ProjectKind::Bin => None,
+            ProjectKind::Static => Some("STATIC"),
+            ProjectKind::Shared => Some("SHARED"),
+            ProjectKind::HeaderOnly => Some("INTERFACE"),
+        }
+    }
+}
+
+impl Display for FileExtension {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FileExtension::Cpp => write!(f, "cpp"),
+            FileExtension::C => write!(f, "c"),
+        }
+    }
+}
+
+/// Exits with the exit code of a command that failed, e.g. the program
+/// `run` ran, and with 1 for other errors
+fn main() -> ExitCode {
+    let Err(error) = try_main() else {
+        return ExitCode::SUCCESS;
+    };
+    eprintln!("Error: {:?}", error);
+
+    let failed = error
+        .chain()
+        .find_map(|cause| cause.downcast_ref::<executor::CommandFailed>());
+    ExitCode::from(failed.map_or(1, |failed| failed.exit_code()))
+}
+
+/// A build directory `init` placed outside the project is the default of
+/// every command working on it, unless the environment or a flag chooses
+/// another. Then comes the project's directory under the configured build
+/// root, and the manifest's directories. `init` resolves them for its root
+/// directory, so it configures the directory the other commands build in.
+/// Returns them by the environment variable of the options they're the
+/// defaults of.
+fn project_defaults(command: &Commands) -> Vec<(&'static str, String)> {
+    let root = command.project_root();
+    let dirs = match (&root, std::env::current_dir()) {
+        (Some(root), _) => manifest::dirs_of(root),
+        (None, Ok(current)) => manifest::dirs_of(&current),
+        (None, Err(_)) => Default::default(),
+    };
+    let build_dir = root.as_deref().and_then(|root| {
+        cmake::recorded_build_dir(root)
+            .filter(|build_dir| !worktree::is_foreign(root, build_dir))
+            .or_else(|| worktree::build_dir(root))
+    });
+
+    [
+        ("CPP_TOOLS_BUILD_DIR", build_dir.or(dirs.build)),
+        ("CPP_TOOLS_RUNTIME_DIR", dirs.runtime),
+        ("CPP_TOOLS_SRC_DIR", dirs.src),
+    ]
+    .into_iter()
+    .filter_map(|(variable, value)| Some((variable, value?)))
+    .collect()
+}
+
+/// Sets the default of every option of `command` and its subcommands that
+/// reads one of the environment variables
+fn with_defaults(mut command: clap::Command, defaults: &[(&str, String)]) -> clap::Command {
+    let options = command
+        .get_arguments()
+        .filter_map(|arg| {
+            let variable = arg.get_env()?;
+            let (_, value) = defaults
+                .iter()
+                .find(|(name, _)| variable == OsStr::new(name))?;
+            Some((arg.get_id().clone(), value.clone()))
+        })
+        .collect::<Vec<_>>();
+    for (id, value) in options {
+        command = command.mut_arg(id, |arg| arg.default_value(value));
+    }
+
+    let subcommands = command
+        .get_subcommands()
+        .map(|subcommand| subcommand.get_name().to_string())
+        .collect::<Vec<_>>();
+    for name in subcommands {
+        command = command.mut_subcommand(name, |subcommand| with_defaults(subcommand, defaults));
+    }
+
+    command
+}
+
+/// Parses the arguments, again with the project's defaults once the command
+/// tells which project it works on
+fn parse_args(args: Vec<OsString>) -> Args {
+    let parsed = Args::parse_from(&args);
+    if matches!(parsed.command, Commands::New { .. }) {
+        return parsed;
+    }
+    let defaults = project_defaults(&parsed.command);
+    if defaults.is_empty() {
+        return parsed;
+    }
+
+    let matches = with_defaults(Args::command(), &defaults).get_matches_from(args);
+    Args::from_arg_matches(&matches).unwrap_or_else(|error| error.exit())
+}
+
+fn try_main() -> Result<()> {
+    worktree::share_compiler_cache();
+
+    let Args {
+        mut command,
+        dry_run,
+        quiet,
+        event_stream,
+    } = parse_args(std::env::args_os().collect());
+
+    if dry_run {
+        executor::install(Box::new(DryRunExecutor));
+    } else {
+        let shell = GlobalConfig::load()?.shell;
+        match command {
+            Commands::New { json: true, .. } => {
+                executor::install(Box::new(ProcessExecutor::scripted(shell).quiet(quiet)))
+            }
+            _ => executor::install(Box::new(ProcessExecutor::new(shell).quiet(quiet))),
+        }
+    }
+    if let Some(target) = &event_stream {
+        events::install(target)?;
+    }
+    select_build_dir(&mut command)?;
+
+    match command {
+        Commands::New {
+            name,
+            dir,
+            build_dir,
+            scaffold,
+            configure,
+            json,
+            batch,
+            remote,
+        } => match batch {
+            Some(roster) => batch::handle_new_batch(roster, dir, build_dir, remote),
+            None => {
+                let name = name.context("Missing project name")?;
+                handle_new_project(name, dir, build_dir, scaffold, configure, json)
+            }
+        },
+        Commands::Init {
+            root_dir,
+            build_dir,
+            new,
+            fresh,
+            scaffold,
+            configure,
+        } => {
+            if new {
+                handle_init_new_project(root_dir, build_dir, scaffold, configure)
+            } else {
+                handle_init_project(&root_dir, &build_dir, fresh, &configure)
+            }
+        }
+        Commands::Build {
+            build_dir,
+            runtime_dir,
+            examples,
+            fresh,
+            jobs,
+            configure,
+            failures,
+            action: None,
+        } => {
+            if fresh || configure.is_set() {
+                handle_init_project(".", &build_dir, fresh, &configure)?;
+            }
+            handle_build_project(build_dir.clone(), &runtime_dir, jobs, &failures)?;
+            if examples {
+                build_target(&build_dir, examples::EXAMPLES_TARGET)?;
+            }
+
+            if !cmake::is_foreign_project() && cmake::is_library_project()? {
+                eprintln!("{}", library_run_hint()?);
+                return Ok(());
+            }
+
+            let executable = Path::new(&runtime_dir)
+                .join(toolchain::executable(&build_dir, &default_exec_name()));
+            if configure.universal && !executor::executor().dry_run() && executable.exists() {
+                toolchain::verify_universal(&executable)?;
+            }
+
+            Ok(())
+        }
+        Commands::Build {
+            action: Some(BuildCommands::Report { top, build_dir }),
+            ..
+        } => ninja_report::handle_build_report(build_dir, top),
+        Commands::Run {
+            build_dir,
+            runtime_dir,
+            exec_name,
+            config,
+            example,
+            platform: platform_args,
+            output,
+            jobs,
+            args,
+        } => {
+            let platform = platform_args.platform()?;
+            if let Some(platform) = platform {
+                if !toolchain::is_configured_for(Path::new(&build_dir), platform) {
+                    let configure = ConfigureArgs {
+                        platform: platform_args,
+                        ..Default::default()
+                    };
+                    handle_init_project(".", &build_dir, false, &configure)?;
+                }
+            }
+
+            let invocation = Invocation {
+                config,
+                args,
+                output,
+            };
+            match example {
+                Some(example) => {
+                    build_target(&build_dir, &examples::example_target(&example))?;
+                    handle_run_project(
+                        build_dir,
+                        format!("{}/{}", runtime_dir, examples::EXAMPLES_DIR),
+                        Some(example),
+                        platform,
+                        invocation,
+                        jobs,
+                    )
+                }
+                None => handle_run_project(
+                    build_dir,
+                    runtime_dir,
+                    exec_name,
+                    platform,
+                    invocation,
+                    jobs,
+                ),
+            }
+        }
+        Commands::Gdbserver {
+            host,
+            container,
+            port,
+            remote_dir,
+            sysroot,
+            debugger,
+            launch,
+            build_dir,
+            runtime_dir,
+            exec_name,
+            args,
+        } => {
+            let target = match container {
+                Some(container) => RemoteTarget::Container(container),
+                None => RemoteTarget::Ssh(host.expect("--host is required without --container")),
+            };
+            let options = RemoteDebugOptions {
+                target,
+                port,
+                remote_dir,
+                sysroot,
+                debugger,
+                launch,
+            };
+
+            remote::handle_gdbserver_project(build_dir, runtime_dir, exec_name, options, args)
+        }
+        Commands::Env { config, all } => env::handle_env_project(config, all),
+        Commands::Rename { new_name } => rename::handle_rename_project(new_name),
+        Commands::Mv {
+            source,
+            destination,
+        } => mv::handle_move_file(source, destination),
+        Commands::Add {
+            member,
+            dependency,
+            git,
+            tag,
+            link,
+            component,
+        } => match (member, dependency, component) {
+            (Some(member), _, _) => workspace::handle_add_member(member),
+            (None, Some(dependency), _) => deps::handle_add_dependency(dependency, git, tag, link),
+            (None, None, None) => {
+                anyhow::bail!("Nothing to add, pass a component, a dependency or --member")
+            }
+            (None, None, Some(component)) => match component {
+                AddCommands::Class {
+                    name,
+                    dir,
+                    header_only,
+                } => scaffold::handle_add_class(name, dir, header_only),
+                AddCommands::Header { name, dir } => scaffold::handle_add_header(name, dir),
+                AddCommands::Example { name } => examples::handle_add_example(name),
+                AddCommands::Codegen { generator, schema } => {
+                    codegen::handle_add_codegen(generator, schema)
+                }
+                AddCommands::Package => package::handle_add_package(),
+                AddCommands::Bench {
+                    function,
+                    build_dir,
+                } => bench::handle_add_bench(function, build_dir),
+            },
+        },
+        Commands::Remove { dependency } => deps::handle_remove_dependency(dependency),
+        Commands::Fix {
+            include_guards,
+            forward_decls,
+            build_dir,
+        } => {
+            if !include_guards && !forward_decls {
+                anyhow::bail!("Nothing to fix, pass --include-guards or --forward-decls");
+            }
+
+            if include_guards {
+                guards::handle_fix_include_guards(dry_run)?;
+            }
+            if forward_decls {
+                forward_decls::handle_fix_forward_decls(build_dir, dry_run)?;
+            }
+            Ok(())
+        }
+        Commands::Check {
+            api,
+            odr,
+            unused,
+            hardening,
+            build_dir,
+        } => {
+            if !api && !odr && !unused && !hardening {
+                anyhow::bail!("Nothing to check, pass --api, --odr, --unused or --hardening");
+            }
+
+            if api {
+                check::handle_check_api(build_dir.clone())?;
+            }
+            if odr {
+                check::handle_check_odr(build_dir.clone())?;
+            }
+            if unused {
+                check::handle_check_unused(build_dir.clone())?;
+            }
+            if hardening {
+                hardening::handle_check_hardening(build_dir)?;
+            }
+
+            Ok(())
+        }
+        Commands::Sync => targets::handle_sync(),
+        Commands::Export {
+            format,
+            zip,
+            vendor,
+            output,
+            sign,
+            build_dir,
+        } => match (zip, format) {
+            (true, _) => archive::handle_export_zip(output, vendor, sign, build_dir),
+            (false, format) => {
+                backend::handle_export(format.context("Missing build system to export to")?)
+            }
+        },
+        Commands::Audit {
+            offline,
+            fail_on,
+            build_dir,
+        } => audit::handle_audit(build_dir, offline, fail_on),
+        Commands::Ci {
+            provider,
+            build_dir,
+        } => ci::handle_ci(provider, build_dir),
+        Commands::Open { target, build_dir } => open::handle_open(target, build_dir),
+        Commands::Ide { tool } => match tool {
+            IdeCommands::Clangd { strict, build_dir } => ide::handle_ide_clangd(strict, build_dir),
+        },
+        Commands::Flags {
+            file,
+            diff,
+            build_dir,
+        } => flags::handle_flags(file, diff, build_dir),
+        Commands::Tags { format, build_dir } => tags::handle_tags(format, build_dir),
+        Commands::Linkmap {
+            target,
+            top,
+            build_dir,
+            runtime_dir,
+        } => linkmap::handle_linkmap(target, build_dir, runtime_dir, top),
+        Commands::Find {
+            symbol,
+            build_dir,
+            declarations,
+        } => find::handle_find(symbol, build_dir, declarations),
+        Commands::Test {
+            mut build_dir,
+            runtime_dir,
+            doc,
+            run,
+            no_cache,
+            replay,
+            filter,
+            crashed,
+            release,
+            valgrind,
+            asan,
+            suppressions,
+            jobs,
+        } => {
+            if doc {
+                return doctest::handle_doc_tests(build_dir, run);
+            }
+            let memcheck = match (valgrind, asan) {
+                (true, _) => Some(memcheck::Memcheck::new(
+                    memcheck::Tool::Valgrind,
+                    suppressions,
+                )?),
+                (_, true) => Some(memcheck::Memcheck::new(memcheck::Tool::Asan, suppressions)?),
+                _ if suppressions.is_some() => {
+                    anyhow::bail!("--suppressions only applies with --valgrind or --asan")
+                }
+                _ => None,
+            };
+
+            if !replay && backend::native(Path::new(".")).is_none() {
+                test_framework::scaffold_tests()?;
+            }
+            if release {
+                build_dir = format!("{}-release", build_dir);
+                if cmake::cache_value(Path::new(&build_dir), "CMAKE_HOME_DIRECTORY").is_none() {
+                    let configure = ConfigureArgs {
+                        profile: Some("release".to_string()),
+                        ..Default::default()
+                    };
+                    handle_init_project(".", &build_dir, false, &configure)?;
+                }
+            }
+            if let Some(memcheck) = &memcheck {
+                build_dir = memcheck.prepare(build_dir)?;
+            }
+            let filter = match crashed {
+                true => Some(crash::crashed_filter(&build_dir)?),
+                false => filter,
+            };
+            if replay {
+                handle_build_project(build_dir, &runtime_dir, jobs, &FailureArgs::default())?;
+                replay::handle_replay()
+            } else {
+                handle_test_project(
+                    build_dir,
+                    &runtime_dir,
+                    jobs,
+                    no_cache,
+                    filter.as_deref(),
+                    memcheck.as_ref(),
+                )
+            }
+        }
+        Commands::Corpus {
+            target,
+            inputs,
+            cases,
+            as_arg,
+            build_dir,
+            runtime_dir,
+        } => corpus::handle_corpus(target, inputs, cases, as_arg, build_dir, runtime_dir),
+        Commands::Pgo {
+            build_dir,
+            runtime_dir,
+            train,
+            retrain,
+            bolt,
+            jobs,
+        } => pgo::handle_pgo(build_dir, runtime_dir, train, retrain, bolt, jobs),
+        Commands::Bench {
+            build_dir,
+            runtime_dir,
+            filter,
+        } => bench::handle_bench(build_dir, runtime_dir, filter),
+        Commands::Lint {
+            build_dir,
+            jobs,
+            no_cache,
+            aux,
+        } => lint::handle_lint(build_dir, jobs, no_cache, aux),
+        Commands::Runs { action } => match action {
+            RunsCommands::List => runs::handle_runs_list(),
+            RunsCommands::Show { run } => runs::handle_runs_show(run),
+            RunsCommands::Diff { before, after } => runs::handle_runs_diff(before, after),
+        },
+        Commands::Publish { destination } => match destination {
+            PublishCommands::Local { build_dir, jobs } => {
+                package::handle_publish_local(build_dir, jobs)
+            }
+            PublishCommands::Github {
+                tag,
+                artifacts,
+                draft,
+                sign,
+                build_dir,
+                jobs,
+            } => release::handle_publish_github(tag, artifacts, draft, sign, build_dir, jobs),
+            PublishCommands::Formula { tag, aur } => formula::handle_publish_formula(tag, aur),
+        },
+        Commands::VerifyArtifact {
+            artifact,
+            bundle,
+            identity,
+            issuer,
+        } => sign::handle_verify_artifact(artifact, bundle, identity, issuer),
+        Commands::Workspace { task } => {
+            let (task, options) = match task {
+                WorkspaceCommands::Build { options } => (workspace::Task::Build, options),
+                WorkspaceCommands::Test { options } => (workspace::Task::Test, options),
+            };
+            workspace::handle_workspace(
+                task,
+                options.jobs,
+                options.affected.then_some(options.since),
+                options.no_cache,
+            )
+        }
+        Commands::Contributors {
+            depth,
+            since,
+            codeowners,
+        } => contributors::handle_contributors(depth, since, codeowners),
+        Commands::Verify {
+            build_dir,
+            runtime_dir,
+            jobs,
+            keep_going,
+        } => verify::handle_verify(build_dir, runtime_dir, jobs, keep_going),
+        Commands::Stats { kind } => match kind {
+            StatsCommands::Build { last } => stats::handle_build_stats(last),
+        },
+        Commands::Amalgamate {
+            header,
+            output,
+            build_dir,
+        } => amalgamate::handle_amalgamate(header, output, build_dir),
+        Commands::Includes { action } => match action {
+            IncludesCommands::Cost { top, build_dir } => {
+                includes::handle_includes_cost(build_dir, top)
+            }
+        },
+        Commands::Cache { action } => match action {
+            CacheCommands::Show { name, build_dir } => cache::handle_cache_show(name, build_dir),
+            CacheCommands::Set {
+                name,
+                value,
+                build_dir,
+            } => cache::handle_cache_set(name, value, build_dir),
+            CacheCommands::Stats { build_dir } => cache::handle_cache_stats(build_dir),
+            CacheCommands::Prune {
+                max_size,
+                build_dir,
+            } => cache::handle_cache_prune(max_size, build_dir),
+        },
+        Commands::DisasmDiff {
+            base,
+            head,
+            function,
+            file,
+            profile,
+            jobs,
+        } => disasm::handle_disasm_diff(base, head, function, file, profile, jobs),
+        Commands::Clean {
+            deep,
+            target,
+            build_dir,
+            runtime_dir,
+        } => clean::handle_clean(build_dir, runtime_dir, deep, target),
+        Commands::Gc {
+            keep_last,
+            prune_variants,
+            build_dir,
+        } => gc::handle_gc(keep_last, prune_variants, build_dir),
+        Commands::Watch {
+            command,
+            include,
+            exclude,
+            no_clear,
+        } => watch::handle_watch(command, include, exclude, no_clear),
+        Commands::Tui {
+            build_dir,
+            runtime_dir,
+        } => tui::handle_tui(build_dir, runtime_dir),
+        Commands::Serve {
+            port,
+            docs,
+            coverage,
+            build_dir,
+        } => serve::handle_serve(port, docs, coverage, build_dir),
+        Commands::Config { action } => match action {
+            ConfigCommands::Get { key, scope } => config::handle_config_get(key, scope.scope()),
+            ConfigCommands::Set { key, value, scope } => {
+                config::handle_config_set(key, value, scope.scope())
+            }
+            ConfigCommands::List { scope } => config::handle_config_list(scope.scope()),
+        },
+        Commands::Upgrade { check } => upgrade::handle_upgrade(check),
+        Commands::Format { src_dir, check } => handle_format_project(src_dir, check),
+        Commands::FmtCmake { check } => cmake_format::handle_fmt_cmake(check),
+    }
+}
+
+fn handle_new_project(
+    name: String,
+    dir: String,
+    build_dir: String,
+    mut scaffold: ScaffoldArgs,
+    mut configure: ConfigureArgs,
+    json: bool,
+) -> Result<()> {
+    validate_project_name(&name)?;
+
+    let project_dir = Path::new(&dir).join(&name).display().to_string();
+    if fs::metadata(&project_dir).is_ok() {
+        anyhow::bail!("Project '{}' already exists", project_dir);
+    }
+
+    let file_ext = parse_file_ext(&scaffold.file_ext)?;
+    layout::parse_layout(&scaffold.layout)?;
+    scaffold.uses_xmake()?;
+    scaffold.template(&file_ext)?;
+    scaffold.package_manager()?;
+    let kind = scaffold.kind()?;
+    let import_std = scaffold.import_std_support(&file_ext, &mut configure)?;
+
+    create_directories(
+        &project_dir,
+        &scaffold.src_dir,
+        &scaffold.include_dir,
+        &build_dir,
+        &scaffold.exec_dir,
+    )?;
+    create_project_files(
+        &name,
+        &project_dir,
+        &build_dir,
+        &scaffold,
+        &file_ext,
+        kind,
+        import_std.as_ref(),
+    )?;
+    if let Some(template) = scaffold.template(&file_ext)? {
+        templates::generate(&template, &name, &project_dir, scaffold.trust_template)?;
+    }
+    handle_init_project(&project_dir, &build_dir, false, &configure)?;
+    initialize_version_control(&project_dir)?;
+
+    eprintln!("{}", format!("Created new project '{}'", name).green());
+    if json {
+        NewReport::new(&name, &project_dir, &build_dir, &scaffold, &configure)?.print()?;
+    }
+
+    Ok(())
+}
+
+/// Scaffolds a project into an existing directory, such as a freshly cloned
+/// empty repository, instead of creating a new one
+fn handle_init_new_project(
+    root_dir: String,
+    build_dir: String,
+    mut scaffold: ScaffoldArgs,
+    mut configure: ConfigureArgs,
+) -> Result<()> {
+    let name = fs::canonicalize(&root_dir)
+        .with_context(|| format!("Directory '{}' does not exist", root_dir))?
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .context("Failed to determine project name")?;
+    validate_project_name(&name)?;
+
+    let file_ext = parse_file_ext(&scaffold.file_ext)?;
+    layout::parse_layout(&scaffold.layout)?;
+    scaffold.uses_xmake()?;
+    scaffold.template(&file_ext)?;
+    scaffold.package_manager()?;
+    let kind = scaffold.kind()?;
+    let import_std = scaffold.import_std_support(&file_ext, &mut configure)?;
+
+    let conflicts = [
+        "CMakeLists.txt".to_string(),
+        xmake::XMAKE_FILE.to_string(),
+        format!("{}/main.{}", scaffold.src_dir, file_ext),
+    ]
+    .into_iter()
+    .filter(|file| Path::new(&root_dir).join(file).exists())
+    .collect::<Vec<_>>();
+    if !conflicts.is_empty() {
+        anyhow::bail!(
+            "Refusing to initialize a project in '{}', it already contains {}",
+            root_dir,
+            conflicts.join(", ")
+        );
+    }
+
+    let has_version_control = Path::new(&root_dir).join(".git").exists();
+
+    create_directories(
+        &root_dir,
+        &scaffold.src_dir,
+        &scaffold.include_dir,
+        &build_dir,
+        &scaffold.exec_dir,
+    )?;
+    create_project_files(
+        &name,
+        &root_dir,
+        &build_dir,
+        &scaffold,
+        &file_ext,
+        kind,
+        import_std.as_ref(),
+    )?;
+    if let Some(template) = scaffold.template(&file_ext)? {
+        templates::generate(&template, &name, &root_dir, scaffold.trust_template)?;
+    }
+    handle_init_project(&root_dir, &build_dir, false, &configure)?;
+    if !has_version_control {
+        initialize_version_control(&root_dir)?;
+    }
+
+    eprintln!(
+        "{}",
+        format!("Created new project '{}' in '{}'", name, root_dir).green()
+    );
+
+    Ok(())
+}
+
+fn parse_file_ext(file_ext: &str) -> Result<FileExtension> {
+    match file_ext.to_ascii_lowercase().as_str() {
+        "cpp" => Ok(FileExtension::Cpp),
+        "c" => Ok(FileExtension::C),
+        _ => {
+            anyhow::bail!("Valid file extensions are 'cpp' and 'c'");
+        }
+    }
+}
+
+/// Project names become directory names and the CMake project() and
+/// executable names, so they have to be valid as all three
+fn validate_project_name(name: &str) -> Result<()> {
+    if name.is_empty() {
+        anyhow::bail!("Project name must not be empty");
+    }
+
+    if name.contains(['/', '\\']) {
+        anyhow::bail!(
+            "Project name '{}' must not contain path separators, use --dir to create it elsewhere",
+            name
+        );
+    }
+
+    if name.starts_with(|c: char| c.is_ascii_digit()) {
+        anyhow::bail!("Project name '{}' must not start with a digit", name);
+    }
+
+    if let Some(invalid) = name
+        .chars()
+        .find(|c| !(c.is_ascii_alphanumeric() || *c == '_' || *c == '-'))
+    {
+        anyhow::bail!(
+            "Project name '{}' contains '{}', only letters, digits, '_' and '-' are allowed",
+            name,
+            invalid
+        );
+    }
+
+    Ok(())
+}
+
+fn create_directories(
+    project_dir: &str,
+    src_dir: &str,
+    include_dir: &str,
+    build_dir: &str,
+    exec_dir: &str,
+) -> Result<()> {
+    fs::create_dir_all(format!("{}/{}", project_dir, src_dir))
+        .context("Failed to create source directory")?;
+    fs::create_dir_all(format!("{}/{}", project_dir, include_dir))
+        .context("Failed to create include directory")?;
+    fs::create_dir_all(format!("{}/{}", project_dir, build_dir))
+        .context("Failed to create build directory")?;
+    fs::create_dir_all(format!("{}/{}", project_dir, exec_dir))
+        .context("Failed to create executable directory")?;
+
+    Ok(())
+}
+
+fn create_project_files(
+    name: &str,
+    project_dir: &str,
+    build_dir: &str,
+    scaffold: &ScaffoldArgs,
+    file_ext: &FileExtension,
+    kind: ProjectKind,
+    import_std: Option<&import_std::Support>,
+) -> Result<()> {
+    let ScaffoldArgs {
+        src_dir,
+        include_dir,
+        exec_dir,
+        ..
+    } = scaffold;
+    let layout = layout::parse_layout(&scaffold.layout)?;
+
+    let mut source_files = Vec::new();
+    if kind == ProjectKind::Bin {
+        source_files.push(format!("{}/main.{}", src_dir, file_ext));
+    }
+    if kind == ProjectKind::HeaderOnly {
+        layout::create_header_only_files(name, project_dir, include_dir, file_ext)?;
+    } else if layout == Layout::Canonical {
+        source_files.push(layout::create_canonical_files(
+            name,
+            project_dir,
+            src_dir,
+            include_dir,
+            file_ext,
+        )?);
+    }
+    let source_files = source_files.join(" ");
+
+    // Appends so an existing .gitignore, e.g. from a cloned repository, is kept
+    fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(format!("{}/.gitignore", project_dir))
+        .and_then(|mut gitignore| {
+            gitignore.write_all(
+                format!(
+                    "
+.*
+
+# Build and executable directories
+{}
+{}
+",
+                    build_dir, exec_dir
+                )
+                .as_bytes(),
+            )
+        })
+        .context("Failed to create .gitignore file")?;
+
+    write_manifest(name, project_dir, build_dir, scaffold, file_ext)?;
+    if scaffold.uses_xmake()? {
+        write_xmake_project(name, project_dir, build_dir, scaffold, file_ext)?;
+    } else {
+        write_cmake_project(
+            name,
+            project_dir,
+            scaffold,
+            file_ext,
+            kind,
+            &source_files,
+            import_std,
+        )?;
+    }
+    if let Some(manager) = scaffold.package_manager()? {
+        package_manager::scaffold(manager.as_ref(), name, project_dir)?;
+    }
+
+    // Libraries have no main, an example shows how they're used instead
+    if kind != ProjectKind::Bin {
+        examples::write_consumer(name, project_dir, file_ext)?;
+        return package::write_new_package(name, project_dir, include_dir);
+    }
+
+    let (includes, greeting) = match (layout, file_ext) {
+        (Layout::Flat, FileExtension::Cpp) if import_std.is_some() => (
+            "import std;".to_string(),
+            "std::cout << \"Hello, world!\" << std::endl;".to_string(),
+        ),
+        // Headers are included before importing modules
+        (Layout::Canonical, FileExtension::Cpp) if import_std.is_some() => (
+            format!("#include <{}/{}.hpp>\n\nimport std;", name, name),
+            format!(
+                "std::cout << {}::greeting() << std::endl;",
+                layout::identifier(name)
+            ),
+        ),
+        (Layout::Flat, FileExtension::Cpp) => (
+            "#include <iostream>".to_string(),
+            "std::cout << \"Hello, world!\" << std::endl;".to_string(),
+        ),
+        (Layout::Flat, FileExtension::C) => (
+            "#include <stdio.h>".to_string(),
+            "printf(\"Hello, world!\\n\");".to_string(),
+        ),
+        (Layout::Canonical, FileExtension::Cpp) => (
+            format!("#include <iostream>\n#include <{}/{}.hpp>", name, name),
+            format!(
+                "std::cout << {}::greeting() << std::endl;",
+                layout::identifier(name)
+            ),
+        ),
+        (Layout::Canonical, FileExtension::C) => (
+            format!("#include <stdio.h>\n#include <{}/{}.h>", name, name),
+            format!(
+                "printf(\"%s\\n\", {}_greeting());",
+                layout::identifier(name)
+            ),
+        ),
+    };
+
+    fs::write(
+        format!("{}/{}/main.{}", project_dir, src_dir, file_ext),
+        format!(
+            "// Copyright (c) 2024 Author. All Rights Reserved.
+{}
+
+int main() {{
+  {}
+  return 0;
+}}
+",
+            includes, greeting,
+        ),
+    )
+    .context("Failed to create main source file")?;
+
+    if let Some(template) = scaffold.template(file_ext)? {
+        templates::write_template(&template, project_dir, src_dir)?;
+    }
+
+    Ok(())
+}
+
+fn write_cmake_project(
+    name: &str,
+    project_dir: &str,
+    scaffold: &ScaffoldArgs,
+    file_ext: &FileExtension,
+    kind: ProjectKind,
+    source_files: &str,
+    import_std: Option<&import_std::Support>,
+) -> Result<()> {
+    let ScaffoldArgs {
+        include_dir,
+        exec_dir,
+        ..
+    } = scaffold;
+
+    let project_lang = match file_ext {
+        FileExtension::Cpp => "CXX",
+        FileExtension::C => "C",
+    };
+
+    let project_type = match file_ext {
+        FileExtension::Cpp => "CXX_",
+        FileExtension::C => "C_",
+    };
+
+    let version = file_ext.standard_version();
+
+    // Libraries pass their include directory on to what links them
+    let (target_kind, target) = match kind.library_type() {
+        None => (
+            "executable",
+            format!("add_executable({name} ${{SOURCE_FILES}})\n"),
+        ),
+        Some("INTERFACE") => (
+            "library",
+            format!(
+                "add_library({name} INTERFACE)
+target_include_directories({name} INTERFACE
+  \"$<BUILD_INTERFACE:${{CMAKE_CURRENT_SOURCE_DIR}}/{include_dir}>\")
+"
+            ),
+        ),
+        Some(library_type) => (
+            "library",
+            format!(
+                "add_library({name} {library_type} ${{SOURCE_FILES}})
+target_include_directories({name} PUBLIC
+  \"$<BUILD_INTERFACE:${{CMAKE_CURRENT_SOURCE_DIR}}/{include_dir}>\")
+"
+            ),
+        ),
+    };
+    let source_files = match source_files {
+        "" => String::new(),
+        files => format!(" {}", files),
+    };
+
+    let (minimum_version, before_project, after_project) = match import_std {
+        Some(support) => ("3.30", support.before_project(), support.after_project()),
+        None => ("3.24", String::new(), ""),
+    };
+
+    fs::write(
+        format!("{}/CMakeLists.txt", project_dir),
+        format!(
+            "cmake_minimum_required(VERSION {minimum_version})
+{before_project}project({name} {project_lang})
+
+# Set compiler flags, the standard unless cpp_tools.toml sets another
+if(NOT DEFINED CMAKE_{project_type}STANDARD)
+  set(CMAKE_{project_type}STANDARD {version})
+endif()
+set(CMAKE_{project_type}STANDARD_REQUIRED ON)
+set(CMAKE_{project_type}EXTENSIONS OFF)
+{after_project}if(MSVC)
+  add_compile_options(/W4 /WX /permissive-)
+else()
+  add_compile_options(-Wall -Werror -Wextra -pedantic -pedantic-errors -g)
+endif()
+
+# Include project headers
+include_directories(./{include_dir})
+# Define the source files and dependencies for the {target_kind}
+set(SOURCE_FILES{source_files})
+
+# Make the project root directory the working directory when we run
+set(CMAKE_RUNTIME_OUTPUT_DIRECTORY ${{CMAKE_CURRENT_SOURCE_DIR}}/{exec_dir})
+set(CMAKE_EXPORT_COMPILE_COMMANDS TRUE)
+{target}",
+        ),
+    )
+    .context("Failed to create CMakeLists.txt file")?;
+
+    Ok(())
+}
+
+/// Records how the project was created in a new manifest, so the other
+/// commands default to its directories and `init` to its standard. A
+/// manifest the directory already has is kept.
+fn write_manifest(
+    name: &str,
+    project_dir: &str,
+    build_dir: &str,
+    scaffold: &ScaffoldArgs,
+    file_ext: &FileExtension,
+) -> Result<()> {
+    let path = Path::new(project_dir).join(manifest::MANIFEST_FILE);
+    if path.exists() {
+        return Ok(());
+    }
+
+    // A build directory outside the project is recorded by init instead,
+    // as it's specific to this machine
+    let build = match Path::new(build_dir).is_absolute() {
+        true => String::new(),
+        false => format!("build = {}\n", config::toml_string(build_dir)),
+    };
+    fs::write(
+        &path,
+        format!(
+            "[package]
+name = {}
+version = \"0.1.0\"
+language = \"{}\"
+standard = \"{}\"
+layout = {}
+
+[dirs]
+{}runtime = {}
+src = {}
+include = {}
+
+[build]
+flags = []
+link_flags = []
+",
+            config::toml_string(name),
+            file_ext,
+            file_ext.standard(),
+            config::toml_string(&scaffold.layout.to_ascii_lowercase()),
+            build,
+            config::toml_string(&scaffold.exec_dir),
+            config::toml_string(&scaffold.src_dir),
+            config::toml_string(&scaffold.include_dir),
+        ),
+    )
+    .with_context(|| format!("Failed to create {}", manifest::MANIFEST_FILE))
+}
+
+/// Writes an xmake.lua that globs the sources, so classes added later are
+/// picked up without editing it
+fn write_xmake_project(
+    name: &str,
+    project_dir: &str,
+    build_dir: &str,
+    scaffold: &ScaffoldArgs,
+    file_ext: &FileExtension,
+) -> Result<()> {
+    let standard_flag = format!("-std={}", file_ext.standard());
+    let project = backend::Project {
+        name: name.to_string(),
+        version: "0.1.0".to_string(),
+        runtime_dir: scaffold.exec_dir.clone(),
+        build_dir: build_dir.to_string(),
+        standard_flag,
+        targets: vec![backend::Target {
+            name: name.to_string(),
+            kind: "executable".to_string(),
+            sources: vec![format!("{}/**.{}", scaffold.src_dir, file_ext)],
+            include_dirs: vec![scaffold.include_dir.clone()],
+            definitions: Vec::new(),
+            link: Vec::new(),
+            standard_flag: None,
+        }],
+    };
+
+    fs::write(
+        format!("{}/{}", project_dir, xmake::XMAKE_FILE),
+        xmake::project_file(&project)?,
+    )
+    .context("Failed to create xmake.lua file")
+}
+
+fn initialize_version_control(project_dir: &str) -> Result<()> {
+    for args in [
+        &["init"][..],
+        &["add", "."],
+        &["commit", "-m", "Initial commit"],
+    ] {
+        executor::executor()
+            .execute(Command::new("git").args(args).current_dir(project_dir))
+            .context("Failed to initialize version control")?;
+    }
+
+    Ok(())
+}
+
+fn handle_init_project(
+    root_dir: &str,
+    build_dir: &str,
+    fresh: bool,
+    configure: &ConfigureArgs,
+) -> Result<()> {
+    if let Some(backend) = backend::native(Path::new(root_dir)) {
+        if configure.generator.is_some()
+            || configure.hardened
+            || configure.arch.is_some()
+            || configure.universal
+            || configure.platform.target.is_some()
+        {
+            anyhow::bail!(
+                "{} projects only support the --profile option",
+                backend.name()
+            );
+        }
+        executor::executor()
+            .execute(&mut backend.configure_command(
+                root_dir,
+                build_dir,
+                configure.profile.as_deref(),
+            )?)
+            .context("Failed to initialize project")?;
+
+        eprintln!(
+            "{}",
+            format!("Initialized project in '{}'", build_dir).green()
+        );
+        return Ok(());
+    }
+
+    let mut command = Command::new("cmake");
+    command
+        .arg("-S")
+        .arg(root_dir)
+        .arg("-B")
+        .arg(Path::new(root_dir).join(build_dir));
+    if fresh {
+        command.arg("--fresh");
+    }
+    if let Some(generator) = &configure.generator {
+        command.args(["-G", generator]);
+    }
+    if let Some(arch) = &configure.arch {
+        command.args(["-A", toolchain::visual_studio_arch(arch)?]);
+    }
+    let platform = configure.platform.platform()?;
+    if platform.is_some() && package_manager::detect(Path::new(root_dir)).is_some() {
+        anyhow::bail!(
+            "Dependencies are installed for the host, --target can't be combined with a package manager"
+        );
+    }
+    let mut variables = Vec::new();
+    if let Some(platform) = platform {
+        variables.extend(platform.cache_variables(Path::new(root_dir))?);
+    }
+    if configure.universal {
+        variables.push(toolchain::universal_cache_variable()?);
+    }
+    if configure.clang_modules {
+        clang_modules::write_fragment(Path::new(root_dir))?;
+        variables.push((clang_modules::CACHE_VARIABLE.to_string(), "ON".to_string()));
+    }
+    for (variable, value) in variables {
+        command.arg("-D").arg(format!("{}={}", variable, value));
+    }
+    let msvc = platform.is_some_and(|platform| platform.msvc_flags())
+        || toolchain::uses_msvc_flags(
+            &Path::new(root_dir).join(build_dir),
+            configure.generator.as_deref(),
+        );
+    let manifest = Manifest::load_from(Path::new(root_dir))?;
+    let mut variables = Vec::from_iter(manifest.standard_variable());
+    let build_path = Path::new(root_dir).join(build_dir);
+    let mut build_type = cmake::cache_value(&build_path, "CMAKE_BUILD_TYPE");
+    if let Some(profile) = &configure.profile {
+        let mut profile = profiles::resolve(&manifest, profile)?;
+        build_type = Some(profile.build_type.clone());
+        profile.hardened |= configure.hardened;
+        profile
+            .sanitizers
+            .extend(configure.sanitizers.iter().cloned());
+        if profile.hardened {
+            hardening::write_fragment(Path::new(root_dir))?;
+        }
+        variables.extend(profile.cache_variables(msvc));
+    } else if !manifest.build.flags.is_empty() || !manifest.build.link_flags.is_empty() {
+        for variable in ["CMAKE_C_FLAGS", "CMAKE_CXX_FLAGS"] {
+            variables.push((variable.to_string(), manifest.build.flags.join(" ")));
+        }
+        for variable in ["CMAKE_EXE_LINKER_FLAGS", "CMAKE_SHARED_LINKER_FLAGS"] {
+            variables.push((variable.to_string(), manifest.build.link_flags.join(" ")));
+        }
+    }
+    variables.extend(package_manager::install(
+        Path::new(root_dir),
+        &build_path,
+        build_type.as_deref(),
+    )?);
+    for (variable, value) in variables {
+        command.arg("-D").arg(format!("{}={}", variable, value));
+    }
+    profiles::write_presets(Path::new(root_dir), &manifest, msvc)?;
+
+    build_info::refresh_build_info(Path::new(root_dir), build_dir)?;
+    fileapi::write_query(&Path::new(root_dir).join(build_dir))?;
+    events::emit(Event::ConfigureStarted {
+        build_dir: build_dir.to_string(),
+    });
+    let start = Instant::now();
+    let result = executor::executor()
+        .execute(&mut command)
+        .context("Failed to initialize project");
+    events::emit(Event::ConfigureFinished {
+        build_dir: build_dir.to_string(),
+        success: result.is_ok(),
+        seconds: start.elapsed().as_secs_f64(),
+    });
+    result?;
+    stats::record_configure(Path::new(root_dir), start.elapsed())?;
+    if !executor::executor().dry_run() {
+        cmake::record_build_dir(Path::new(root_dir), build_dir)?;
+    }
+
+    eprintln!(
+        "{}",
+        format!("Initialized project in '{}'", build_dir).green()
+    );
+
+    Ok(())
+}
+
+fn handle_build_project(
+    build_dir: String,
+    runtime_dir: &str,
+    jobs: Option<usize>,
+    failures: &FailureArgs,
+) -> Result<()> {
+    let manifest = Manifest::load()?;
+    let mut command = match backend::native(Path::new(".")) {
+        Some(backend) => backend.build_command(jobs)?,
+        None => {
+            package_manager::refresh(Path::new(&build_dir))?;
+            let config = toolchain::build_config(Path::new(&build_dir));
+            let mut command = cmake::build_command(&build_dir, config.as_deref(), jobs);
+            if let Some(args) = generator_failure_args(Path::new(&build_dir), failures) {
+                command.arg("--").args(args.split_whitespace());
+            }
+            command
+        }
+    };
+
+    build_info::refresh_build_info(Path::new("."), &build_dir)?;
+    assets::embed_assets(&manifest.assets)?;
+    let log_offset = stats::ninja_log_offset(&build_dir);
+    events::emit(Event::BuildStarted {
+        build_dir: build_dir.clone(),
+    });
+    let start = Instant::now();
+    let mut log = match failures.stop_on_first_error {
+        true => BuildLog::focused(),
+        false => BuildLog::default(),
+    };
+    let result = executor::executor()
+        .execute_observed(&mut command, &mut |line, _| {
+            events::observe(line);
+            log.observe(line)
+        })
+        .context("Failed to run build command");
+    log.finish();
+    events::emit(Event::BuildFinished {
+        build_dir: build_dir.clone(),
+        success: result.is_ok(),
+        seconds: start.elapsed().as_secs_f64(),
+    });
+    if result.is_err() {
+        log.print_summary(failures.explain);
+    }
+    result?;
+    stats::record_build(start.elapsed(), &build_dir, log_offset)?;
+    let budget = failures.time_budget.map(|percent| budget::Budget {
+        percent: percent.unwrap_or(manifest.time_budget.percent),
+        warn: manifest.time_budget.warn,
+    });
+    budget::record(&build_dir, log_offset, budget)?;
+    assets::install_assets(&manifest.assets, runtime_dir)?;
+    tags::refresh_tags(&build_dir)?;
+    provenance::record(Path::new(&build_dir))?;
+
+    eprintln!("{}", "Build successful".green());
+
+    Ok(())
+}
+
+/// The native build tool's arguments for how far a failing build goes.
+/// Ninja takes the number of failures to stop at, so stopping at the first is
+/// `-k 1` and `-k 0` never stops; Make stops unless `-k` came in through
+/// `MAKEFLAGS`, which `-S` overrides.
+fn generator_failure_args(build_dir: &Path, failures: &FailureArgs) -> Option<String> {
+    if !failures.stop_on_first_error && !failures.keep_going {
+        return None;
+    }
+
+    let generator = cmake::cache_value(build_dir, "CMAKE_GENERATOR")?;
+    let ninja = generator.starts_with("Ninja");
+    let make = generator == "Unix Makefiles" || generator == "MinGW Makefiles";
+    if failures.keep_going && failures.max_errors.is_some() && !ninja {
+        eprintln!(
+            "{}",
+            format!(
+                "--max-errors isn't supported by the {} generator, building everything possible",
+                generator
+            )
+            .yellow()
+        );
+    }
+
+    let args = match (failures.keep_going, ninja, make) {
+        (false, true, _) => "-k 1".to_string(),
+        (false, _, true) => "-S".to_string(),
+        (true, true, _) => format!("-k {}", failures.max_errors.unwrap_or(0)),
+        (true, _, true) => "-k".to_string(),
+        (false, ..) if generator.starts_with("Visual Studio") => {
+            "-p:StopOnFirstFailure=true".to_string()
+        }
+        _ => return None,
+    };
+
+    Some(args)
+}
+
+/// Builds a single CMake target, e.g. one that isn't part of the default build
+fn build_target(build_dir: &str, target: &str) -> Result<()> {
+    let config = toolchain::build_config(Path::new(build_dir));
+
+    executor::executor()
+        .execute(
+            cmake::build_command(build_dir, config.as_deref(), None).args(["--target", target]),
+        )
+        .with_context(|| format!("Failed to build target '{}'", target))
+}
+
+/// Points the command at the variant of its build directory for the checked
+/// out branch, see [`variants::select`]. Commands that build configure the
+/// variant when it's new, the others read the one the last build used.
+fn select_build_dir(command: &mut Commands) -> Result<()> {
+    let builds = command.builds();
+    let Some(build_dir) = command.build_dir_mut() else {
+        return Ok(());
+    };
+
+    *build_dir = match builds {
+        true => detect_project(build_dir.clone())?,
+        false => variants::active(build_dir.clone())?,
+    };
+
+    Ok(())
+}
+
+/// Makes the commands that build work in CMake projects cxx didn't
+/// generate: they run from the project root, in a build directory that is
+/// already configured for it or configured now
+fn detect_project(build_dir: String) -> Result<String> {
+    cmake::enter_project_root()?;
+    if !cmake::is_foreign_project() {
+        return variants::select(build_dir);
+    }
+
+    let build_dir = fileapi::existing_build_dir(&build_dir)?;
+    if cmake::cache_value(Path::new(&build_dir), "CMAKE_HOME_DIRECTORY").is_none() {
+        handle_init_project(".", &build_dir, false, &ConfigureArgs::default())?;
+    }
+
+    Ok(build_dir)
+}
+
+fn handle_run_project(
+    build_dir: String,
+    mut runtime_dir: String,
+    exec_name: Option<String>,
+    platform: Option<Platform>,
+    invocation: Invocation,
+    jobs: Option<usize>,
+) -> Result<()> {
+    let Invocation {
+        config,
+        args,
+        output,
+    } = invocation;
+    if exec_name.is_none() && !cmake::is_foreign_project() && cmake::is_library_project()? {
+        anyhow::bail!("{}", library_run_hint()?);
+    }
+    let (target_name, mut exec_name) = if platform.is_none() && cmake::is_foreign_project() {
+        // Runs from the project root, as the executable's location is up to
+        // the project
+        let executable = fileapi::executable(Path::new(&build_dir), exec_name.as_deref())?;
+        runtime_dir = ".".to_string();
+        let path = Path::new(&build_dir).join(&executable.path);
+        (executable.name, path.display().to_string())
+    } else {
+        let name = exec_name.unwrap_or_else(default_exec_name);
+        let exec_name = toolchain::executable(&build_dir, &name);
+        (name, exec_name)
+    };
+    if let Some(platform) = platform {
+        exec_name.push_str(platform.executable_suffix());
+        if let Some(output_dir) = platform.output_dir() {
+            exec_name = format!("{}/{}", output_dir, exec_name);
+        }
+    }
+    let manifest = Manifest::load()?;
+    let run_config = match config {
+        Some(name) => manifest.run_config(&name)?.clone(),
+        None => RunConfig::default(),
+    };
+
+    // Where a relative program is looked up from differs between platforms
+    // once it runs in another directory
+    let working_dir = run_config
+        .cwd
+        .clone()
+        .unwrap_or_else(|| runtime_dir.clone());
+    let exec_path = std::env::current_dir()
+        .context("Failed to get current directory")?
+        .join(&runtime_dir)
+        .join(&exec_name);
+    if let Some(case) = output.record {
+        if platform.is_some()
+            || backend::native(Path::new(".")).is_some()
+            || run_config.wrapper.is_some()
+        {
+            anyhow::bail!(
+                "Only CMake projects run on the host without a run config wrapper can be recorded"
+            );
+        }
+        handle_build_project(build_dir, &runtime_dir, jobs, &FailureArgs::default())
+            .context("Failed to build project")?;
+        let executable = Path::new(&runtime_dir).join(&exec_name);
+        return replay::record(
+            &case,
+            replay::Case {
+                executable: executable.display().to_string(),
+                working_dir,
+                env: env::project_env(&manifest, &run_config)?,
+                args: run_config.args.into_iter().chain(args).collect(),
+                exit_code: None,
+            },
+        );
+    }
+
+    let env = env::project_env(&manifest, &run_config)?;
+    let mut wrapper = run_config
+        .wrapper
+        .iter()
+        .flat_map(|wrapper| wrapper.split_whitespace())
+        .map(str::to_string)
+        .collect::<Vec<_>>();
+    let args = run_config.args.into_iter().chain(args).collect::<Vec<_>>();
+    let native = backend::native(Path::new("."));
+    // The crash handler is left out of wrappers, which handle crashes themselves
+    let crash_handler = match platform.is_none() && native.is_none() && wrapper.is_empty() {
+        true => crash::run_preload(&build_dir),
+        false => None,
+    };
+    // Copies the executable to a device once it's built
+    let mut push = None;
+    let mut command = match platform {
+        _ if native.is_some() => {
+            let backend = native.expect("native backend was checked");
+            if platform.is_some() || !wrapper.is_empty() {
+                anyhow::bail!(
+                    "{} projects can't run with --target or a run config wrapper",
+                    backend.name()
+                );
+            }
+            let mut command = backend.run_command(&target_name, &working_dir, &args)?;
+            command.envs(&env);
+            command
+        }
+        Some(platform @ Platform::Android { .. }) => {
+            let local_path = Path::new(&runtime_dir).join(&exec_name);
+            let (device_push, run) = platform.device_commands(&local_path, &env, &args)?;
+            push = Some(device_push);
+            run
+        }
+        _ => {
+            if let Some(runner) = platform.map(Platform::wrapper).transpose()?.flatten() {
+                wrapper.push(runner);
+            }
+            let mut program = wrapper
+                .into_iter()
+                .map(|word| word.into())
+                .chain([exec_path.into_os_string()]);
+            let mut command = Command::new(program.next().expect("the executable is a word"));
+            command
+                .args(program)
+                .args(&args)
+                .current_dir(&working_dir)
+                .envs(&env)
+                .envs(crash_handler.iter().flatten().cloned());
+            command
+        }
+    };
+
+    let crash_build_dir = build_dir.clone();
+    handle_build_project(build_dir, &runtime_dir, jobs, &FailureArgs::default())
+        .context("Failed to build project")?;
+    if let Some(mut push) = push {
+        executor::executor()
+            .execute(&mut push)
+            .context("Failed to copy the executable to the device")?;
+    }
+    let description = std::iter::once(target_name.as_str())
+        .chain(args.iter().map(String::as_str))
+        .collect::<Vec<_>>()
+        .join(" ");
+    let result = runs::run_program(
+        &mut command,
+        &description,
+        output.log.as_deref(),
+        output.capture,
+    );
+    if crash_handler.is_some() {
+        crash::report_run(&crash_build_dir, &target_name);
+    }
+    result.context("Failed to run executable")?;
+
+    Ok(())
+}
+
+/// Formats a size in bytes with binary units, e.g. 1.5 KiB
+fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KiB", "MiB", "GiB", "TiB"];
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit + 1 < UNITS.len() {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", size, UNITS[unit])
+}
+
+/// Tells that a library project has no executable to run, and which of its
+/// examples can be run instead
+fn library_run_hint() -> Result<String> {
+    let mut examples = glob::expand(&format!("{}/*", examples::EXAMPLES_DIR))
+        .unwrap_or_default()
+        .into_iter()
+        .filter_map(|path| Some(path.file_stem()?.to_string_lossy().to_string()))
+        .collect::<Vec<_>>();
+    examples.sort();
+    examples.dedup();
+
+    let name = cmake::project_name()?;
+    Ok(match examples.first() {
+        Some(example) => format!(
+            "'{}' is a library and has no executable to run, run an example with 'cxx run --example {}' ({})",
+            name,
+            example,
+            examples.join(", ")
+        ),
+        None => format!(
+            "'{}' is a library and has no executable to run, add an example using it with 'cxx add example <NAME>'",
+            name
+        ),
+    })
+}
+
+/// The executable is named after the project, which is named after its
+/// directory unless the manifest says otherwise
+fn default_exec_name() -> String {
+    if let Some(name) = Manifest::load()
+        .ok()
+        .and_then(|manifest| manifest.package.name)
+    {
+        return name;
+    }
+
+    let pwd = std::env::current_dir().expect("Failed to read current directory");
+    let name = pwd.to_string_lossy();
+    name.split('/').next_back().unwrap().to_string()
+}
+
+/// Unless `no_cache` is set, tests that passed before and whose inputs are
+/// unchanged are skipped
+fn handle_test_project(
+    build_dir: String,
+    runtime_dir: &str,
+    jobs: Option<usize>,
+    no_cache: bool,
+    filter: Option<&str>,
+    memcheck: Option<&memcheck::Memcheck>,
+) -> Result<()> {
+    handle_build_project(
+        build_dir.clone(),
+        runtime_dir,
+        jobs,
+        &FailureArgs::default(),
+    )?;
+
+    // The tests run with the project's environment like the executable
+    let project_env = env::project_env(&Manifest::load()?, &RunConfig::default())?;
+    let mut plan = None;
+    let mut command = match backend::native(Path::new(".")) {
+        Some(backend) if memcheck.is_some() => anyhow::bail!(
+            "{} projects can't be tested with --valgrind or --asan",
+            backend.name()
+        ),
+        Some(backend) => {
+            let mut command = backend.test_command()?;
+            command.envs(&project_env);
+            command
+        }
+        None => {
+            let config = toolchain::build_config(Path::new(&build_dir));
+            let mut command = Command::new("ctest");
+            command
+                .envs(&project_env)
+                .args(["--test-dir", &build_dir])
+                .arg("--output-on-failure");
+            if let Some(config) = &config {
+                command.args(["-C", config]);
+            }
+
+            // A subset of the tests says nothing about the others, so
+            // filtered runs don't use the test cache, and neither do memory
+            // checks, which tests passing before can fail
+            if let Some(filter) = filter {
+                command.args(["-R", filter]);
+            }
+            // The sanitizer runtime has to be loaded first, so crashes under
+            // memory checks are reported without the crash handler's stack
+            if let Some(memcheck) = memcheck {
+                memcheck.ctest_command(&mut command)?;
+            } else if let Some(preload) = crash::preload(&build_dir) {
+                command.envs(preload);
+            }
+            if memcheck.is_none()
+                && filter.is_none()
+                && !no_cache
+                && !executor::executor().dry_run()
+            {
+                let test_plan = testcache::plan(&build_dir, config.as_deref())?;
+                if test_plan.run.is_empty() && test_plan.skipped > 0 {
+                    eprintln!(
+                        "{}",
+                        format!(
+                            "All {} tests passed before and are unchanged, pass --no-cache to rerun them",
+                            test_plan.skipped
+                        )
+                        .green()
+                    );
+                    return Ok(());
+                }
+                if test_plan.skipped > 0 {
+                    eprintln!("Skipping {} unchanged tests", test_plan.skipped);
+                    command.args(["-R", &test_plan.filter()]);
+                }
+                test_plan.clear_results();
+                plan = Some(test_plan);
+            }
+            command
+        }
+    };
+
+    events::emit(Event::TestsStarted {
+        build_dir: build_dir.clone(),
+    });
+    let start = Instant::now();
+    let mut results = Vec::new();
+    let mut defects = Vec::new();
+    let mut crashes = crash::Crashes::default();
+    let result = run_reported(&mut command, &mut results, &mut defects, &mut crashes)
+        .context("Failed to run tests");
+    events::emit(Event::TestsFinished {
+        build_dir: build_dir.clone(),
+        success: result.is_ok(),
+        seconds: start.elapsed().as_secs_f64(),
+    });
+    if let Some(plan) = plan {
+        plan.record()?;
+    }
+    print_test_summary(&results);
+    crash::report(&build_dir, &results, &crashes)?;
+    result?;
+    memcheck::report(&build_dir, &defects)?;
+    stats::record_test(start.elapsed())
+}
+
+fn print_test_summary(results: &[output::TestResult]) {
+    if results.is_empty() {
+        return;
+    }
+
+    let failed = results
+        .iter()
+        .filter(|test| !test.passed)
+        .map(|test| match &test.signal {
+            Some(signal) => format!("{} ({})", test.name, signal),
+            None => test.name.clone(),
+        })
+        .collect::<Vec<_>>();
+    let tests = if results.len() == 1 { "test" } else { "tests" };
+    if failed.is_empty() {
+        eprintln!("{}", format!("{} {} passed", results.len(), tests).green());
+    } else {
+        eprintln!(
+            "{}",
+            format!(
+                "{} of {} {} failed: {}",
+                failed.len(),
+                results.len(),
+                tests,
+                failed.join(", ")
+            )
+            .red()
+        );
+    }
+}
+
+fn handle_format_project(src_dir: String, check: bool) -> Result<()> {
+    let files = glob::expand(&format!("{}/*", src_dir)).unwrap_or_default();
+    // Without files clang-format would format stdin
+    if files.is_empty() && !executor::executor().dry_run() {
+        return cmake_format::handle_fmt_cmake(check);
+    }
+
+    if check {
+        executor::executor()
+            .execute_observed(
+                Command::new("clang-format")
+                    .args(["--dry-run", "-Werror", "-style=file"])
+                    .args(&files),
+                &mut |_, _| true,
+            )
+            .context("Sources aren't formatted, run 'cxx format'")?;
+    } else {
+        executor::executor()
+            .execute(
+                Command::new("clang-format")
+                    .args(["-i", "-style=file"])
+                    .args(&files),
+            )
+            .context("Failed to format project")?;
+    }
+
+    cmake_format::handle_fmt_cmake(check)
+}
+
+fn run_command(command: &str) -> Result<()> {
+    executor::executor().run(command)
+}
+
+/// Runs a test command, collecting the test results for the summary and
+/// reporting them as events when an event stream is open
+fn run_reported(
+    command: &mut Command,
+    results: &mut Vec<output::TestResult>,
+    defects: &mut Vec<memcheck::Defects>,
+    crashes: &mut crash::Crashes,
+) -> Result<()> {
+    executor::executor().execute_observed(command, &mut |line, _| {
+        events::observe(line);
+        let line = output::strip_ansi(line);
+        let result = output::parse_test_result(&line);
+        let shown = crashes.observe(&line, result.as_ref());
+        if let Some(test) = result {
+            results.push(test);
+        } else if let Some(test) = memcheck::parse_defects(&line) {
+            defects.push(test);
+        }
+        shown
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use executor::RecordingExecutor;
+    use std::{
+        path::PathBuf,
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Mutex, MutexGuard, OnceLock,
+        },
+    };
+
+    /// The executor every test records with, as only one can be installed
+    fn recorder() -> &'static RecordingExecutor {
+        static RECORDER: OnceLock<RecordingExecutor> = OnceLock::new();
+
+        RECORDER.get_or_init(|| {
+            let recorder = RecordingExecutor::default();
+            executor::install(Box::new(recorder.clone()));
+            recorder
+        })
+    }
+
+    /// A scratch CMake project that's the current directory until the test
+    /// finishes. Tests using one run one at a time, as the directory, the
+    /// environment and the executor belong to the process.
+    struct Project {
+        dir: PathBuf,
+        scratch: PathBuf,
+        _lock: MutexGuard<'static, ()>,
+    }
+
+    impl Project {
+        fn new() -> Project {
+            static LOCK: Mutex<()> = Mutex::new(());
+            static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+            let lock = LOCK.lock().unwrap_or_else(|error| error.into_inner());
+            let scratch = std::env::temp_dir().join(format!(
+                "cxx-unit-{}-{}",
+                std::process::id(),
+                COUNTER.fetch_add(1, Ordering::SeqCst)
+            ));
+            let dir = scratch.join("app");
+            fs::create_dir_all(dir.join("src")).unwrap();
+            fs::write(
+                dir.join("CMakeLists.txt"),
+                "cmake_minimum_required(VERSION 3.24)\nproject(app CXX)\nset(SOURCE_FILES src/main.cpp)\nadd_executable(app ${SOURCE_FILES})\n",
+            )
+            .unwrap();
+            std::env::set_var("XDG_CONFIG_HOME", &scratch);
+            for variable in [
+                "CPP_TOOLS_BUILD_DIR",
+                "CPP_TOOLS_RUNTIME_DIR",
+                "CPP_TOOLS_SRC_DIR",
+                "CPP_TOOLS_BUILD_ROOT",
+            ] {
+                std::env::remove_var(variable);
+            }
+            std::env::set_current_dir(&dir).unwrap();
+            recorder().take();
+
+            Project {
+                dir,
+                scratch,
+                _lock: lock,
+            }
+        }
+    }
+
+    impl Drop for Project {
+        fn drop(&mut self) {
+            let _ = std::env::set_current_dir(std::env::temp_dir());
+            let _ = fs::remove_dir_all(&self.scratch);
+        }
+    }
+
+    fn argv(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn init_configures_with_cmake() {
+        let _project = Project::new();
+
+        handle_init_project(".", "build", true, &ConfigureArgs::default()).unwrap();
+
+        assert_eq!(
+            recorder().take(),
+            [argv(&["cmake", "-S", ".", "-B", "./build", "--fresh"])]
+        );
+    }
+
+    #[test]
+    fn init_new_configures_and_commits() {
+        let project = Project::new();
+        fs::remove_file(project.dir.join("CMakeLists.txt")).unwrap();
+        let root_dir = project.dir.display().to_string();
+
+        let Commands::Init {
+            root_dir,
+            build_dir,
+            scaffold,
+            configure,
+            ..
+        } = Args::parse_from(["cxx", "init", "--new", "-r", &root_dir]).command
+        else {
+            unreachable!("init was parsed");
+        };
+        handle_init_new_project(root_dir.clone(), build_dir, scaffold, configure).unwrap();
+
+        assert_eq!(
+            recorder().take(),
+            [
+                argv(&[
+                    "cmake",
+                    "-S",
+                    &root_dir,
+                    "-B",
+                    &format!("{}/build", root_dir),
+                    "-D",
+                    "CMAKE_CXX_STANDARD=23",
+                ]),
+                argv(&["git", "init"]),
+                argv(&["git", "add", "."]),
+                argv(&["git", "commit", "-m", "Initial commit"]),
+            ]
+        );
+    }
+
+    /// Runs `init` then `build` the way `main` does, defaults included
+    fn init_then_build(root_dir: &str) {
+        let parse = |args: &[&str]| parse_args(args.iter().map(OsString::from).collect()).command;
+
+        let Commands::Init {
+            root_dir,
+            build_dir,
+            fresh,
+            configure,
+            ..
+        } = parse(&["cxx", "init", "-r", root_dir])
+        else {
+            unreachable!("init was parsed");
+        };
+        handle_init_project(&root_dir, &build_dir, fresh, &configure).unwrap();
+
+        let Commands::Build {
+            build_dir,
+            runtime_dir,
+            jobs,
+            failures,
+            ..
+        } = parse(&["cxx", "build"])
+        else {
+            unreachable!("build was parsed");
+        };
+        handle_build_project(build_dir, &runtime_dir, jobs, &failures).unwrap();
+    }
+
+    #[test]
+    fn init_and_build_use_the_manifest_build_dir() {
+        let project = Project::new();
+        fs::write(
+            project.dir.join(manifest::MANIFEST_FILE),
+            "[dirs]\nbuild = \"out\"\n",
+        )
+        .unwrap();
+        let root_dir = fs::canonicalize(&project.dir)
+            .unwrap()
+            .display()
+            .to_string();
+
+        init_then_build(&root_dir);
+
+        assert_eq!(
+            recorder().take(),
+            [
+                argv(&["cmake", "-S", &root_dir, "-B", &format!("{}/out", root_dir)]),
+                argv(&["cmake", "--build", "out"]),
+            ]
+        );
+    }
+
+    #[test]
+    fn init_and_build_use_the_build_root() {
+        let project = Project::new();
+        let build_root = project.scratch.join("builds");
+        std::env::set_var("CPP_TOOLS_BUILD_ROOT", &build_root);
+        let root = fs::canonicalize(&project.dir).unwrap();
+        let root_dir = root.display().to_string();
+
+        init_then_build(&root_dir);
+
+        let build_dir = worktree::build_dir(&root).unwrap();
+        assert!(build_dir.starts_with(&build_root.display().to_string()));
+        assert_eq!(
+            recorder().take(),
+            [
+                argv(&["cmake", "-S", &root_dir, "-B", &build_dir]),
+                argv(&["cmake", "--build", &build_dir]),
+            ]
+        );
+    }
+
+    #[test]
+    fn rename_keeps_the_sources_of_a_canonical_project() {
+        let project = Project::new();
+        fs::write(
+            project.dir.join("CMakeLists.txt"),
+            "cmake_minimum_required(VERSION 3.24)\nproject(app CXX)\ninclude_directories(./include)\nset(SOURCE_FILES src/main.cpp src/app.cpp)\nadd_executable(app ${SOURCE_FILES})\n",
+        )
+        .unwrap();
+        fs::write(
+            project.dir.join(manifest::MANIFEST_FILE),
+            "[package]\nname = \"app\"\n\n[env]\nname = \"app\"\n",
+        )
+        .unwrap();
+        fs::create_dir_all(project.dir.join("include/app")).unwrap();
+        fs::write(project.dir.join("include/app/app.hpp"), "#pragma once\n").unwrap();
+        fs::write(project.dir.join("src/app.cpp"), "#include <app/app.hpp>\n").unwrap();
+        fs::write(project.dir.join("src/main.cpp"), "#include <app/app.hpp>\n").unwrap();
+
+        rename::handle_rename_project("bar".to_string()).unwrap();
+
+        let lists = fs::read_to_string(project.dir.join("CMakeLists.txt")).unwrap();
+        assert!(lists.contains("project(bar CXX)"));
+        assert!(lists.contains("add_executable(bar ${SOURCE_FILES})"));
+        assert!(lists.contains("set(SOURCE_FILES src/main.cpp src/app.cpp)"));
+        for source in cmake::source_files().unwrap() {
+            assert!(project.dir.join(&source).exists(), "{} is missing", source);
+        }
+        assert!(project.dir.join("include/bar/app.hpp").exists());
+        assert_eq!(
+            fs::read_to_string(project.dir.join("src/app.cpp")).unwrap(),
+            "#include <bar/app.hpp>\n"
+        );
+        assert_eq!(
+            fs::read_to_string(project.dir.join(manifest::MANIFEST_FILE)).unwrap(),
+            "[package]\nname = \"bar\"\n\n[env]\nname = \"app\"\n"
+        );
+    }
+
+    #[test]
+    fn build_runs_cmake_build() {
+        let _project = Project::new();
+
+        handle_build_project("build".to_string(), "bin", Some(4), &FailureArgs::default()).unwrap();
+
+        assert_eq!(
+            recorder().take(),
+            [argv(&["cmake", "--build", "build", "--parallel", "4"])]
+        );
+    }
+
+    #[test]
+    fn test_builds_then_runs_ctest() {
+        let _project = Project::new();
+
+        handle_test_project("build".to_string(), "bin", None, false, Some("a b"), None).unwrap();
+
+        assert_eq!(
+            recorder().take(),
+            [
+                argv(&["cmake", "--build", "build"]),
+                argv(&[
+                    "ctest",
+                    "--test-dir",
+                    "build",
+                    "--output-on-failure",
+                    "-R",
+                    "a b"
+                ]),
+            ]
+        );
+    }
+
+    #[test]
+    fn run_builds_then_runs_the_executable() {
+        let project = Project::new();
+
+        let invocation = Invocation {
+            config: None,
+            args: argv(&["--name", "a b"]),
+            output: OutputArgs::default(),
+        };
+        handle_run_project(
+            "build".to_string(),
+            "bin".to_string(),
+            None,
+            None,
+            invocation,
+            None,
+        )
+        .unwrap();
+
+        let executable = project.dir.join("bin/app").display().to_string();
+        assert_eq!(
+            recorder().take(),
+            [
+                argv(&["cmake", "--build", "build"]),
+                argv(&[&executable, "--name", "a b"]),
+            ]
+        );
+    }
 }
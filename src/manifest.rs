@@ -0,0 +1,706 @@
+use anyhow::{Context, Result};
+use serde::{de::DeserializeOwned, Deserialize};
+use std::{collections::BTreeMap, fs, path::Path};
+
+use crate::config::suggest;
+
+/// The project manifest, read from the project root
+pub const MANIFEST_FILE: &str = "cpp_tools.toml";
+
+/// Directory in the project root where cxx keeps local state, e.g. build statistics
+pub const STATE_DIR: &str = ".cpp_tools";
+
+/// Settings stored in the project manifest
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct Manifest {
+    /// Project metadata
+    pub package: PackageConfig,
+    /// Generated build information header
+    pub build_info: BuildInfoConfig,
+    /// Environment variables set for every run of the executable
+    pub env: BTreeMap<String, String>,
+    /// Named run configurations, selected with `run --config <name>`
+    pub run: BTreeMap<String, RunConfig>,
+    /// Resource files shipped with the executable
+    pub assets: AssetsConfig,
+    /// Conventions applied to generated files
+    pub style: StyleConfig,
+    /// Custom build profiles, selected with `--profile <name>`
+    pub profile: BTreeMap<String, ProfileConfig>,
+    /// Build targets generated into CMake by `sync`
+    pub target: BTreeMap<String, TargetConfig>,
+    /// Settings of the `check` command
+    pub check: CheckConfig,
+    /// The build matrix of pipelines generated by `ci`
+    pub ci: CiConfig,
+    /// The quality gate `verify` runs
+    pub verify: VerifyConfig,
+    /// What `audit` fails on
+    pub audit: AuditConfig,
+    /// The files `watch` reruns its command for
+    pub watch: WatchConfig,
+    /// Editor configuration generated by `ide`
+    pub ide: IdeConfig,
+    /// Checks `lint` runs besides clang-tidy
+    pub lint: LintConfig,
+    /// Members of a workspace, or the members a member depends on
+    pub workspace: WorkspaceConfig,
+    /// Release artifacts and where their notes come from
+    pub publish: PublishConfig,
+    /// How much slower compiling may get before `build --time-budget` objects
+    pub time_budget: TimeBudgetConfig,
+    /// Project directories, the defaults of the commands' directory options
+    pub dirs: DirsConfig,
+    /// Compiler and linker flags of every build
+    pub build: BuildConfig,
+}
+
+/// Project metadata
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct PackageConfig {
+    /// Project name, defaults to the name of the project directory
+    pub name: Option<String>,
+    /// Project version
+    pub version: Option<String>,
+    /// One line describing the project, for package managers
+    pub description: Option<String>,
+    /// The project's website, defaults to its GitHub repository
+    pub homepage: Option<String>,
+    /// SPDX license identifier, e.g. MIT
+    pub license: Option<String>,
+    /// `c` or `cpp`
+    pub language: Option<String>,
+    /// Language standard, e.g. `c++23` or `c17`
+    pub standard: Option<String>,
+    /// `flat` or `canonical`, see `new --layout`
+    pub layout: Option<String>,
+}
+
+/// Where the project keeps its files, relative to the project root. Flags
+/// and `CPP_TOOLS_*` environment variables take precedence.
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct DirsConfig {
+    /// Build directory, the default of `--build-dir`
+    pub build: Option<String>,
+    /// Executable directory, the default of `--runtime-dir`
+    pub runtime: Option<String>,
+    /// Source directory, the default of `format --src-dir`
+    pub src: Option<String>,
+    /// Public header directory
+    pub include: Option<String>,
+}
+
+/// Flags added to every profile, and to builds without one
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct BuildConfig {
+    /// Extra compiler flags, e.g. `-march=native`
+    pub flags: Vec<String>,
+    /// Extra linker flags
+    pub link_flags: Vec<String>,
+}
+
+/// A header describing the build, regenerated on `init` and `build`
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct BuildInfoConfig {
+    /// Generates the header
+    pub enabled: bool,
+    /// Path of the header, defaults to `include/<name>/build_info.hpp`
+    pub header: Option<String>,
+}
+
+/// A named scenario for running the project executable
+#[derive(Deserialize, Default, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct RunConfig {
+    /// Arguments passed to the executable before any given on the command line
+    pub args: Vec<String>,
+    /// Extra environment variables for the executable
+    pub env: BTreeMap<String, String>,
+    /// Working directory, relative to the project root
+    pub cwd: Option<String>,
+    /// Command the executable is run under (e.g. `valgrind`)
+    pub wrapper: Option<String>,
+}
+
+/// Resource files installed next to the executable or compiled into it
+#[derive(Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AssetsConfig {
+    /// Globs of asset files mapped to a directory relative to the executable
+    pub files: BTreeMap<String, String>,
+    /// Symlinks assets instead of copying them
+    pub symlink: bool,
+    /// Globs of small files compiled into a generated header
+    pub embed: Vec<String>,
+    /// Path of the generated header
+    pub embed_header: String,
+}
+
+impl Default for AssetsConfig {
+    fn default() -> Self {
+        AssetsConfig {
+            files: BTreeMap::new(),
+            symlink: false,
+            embed: Vec::new(),
+            embed_header: "include/assets.h".to_string(),
+        }
+    }
+}
+
+/// A build profile. Custom profiles inherit from another one, built-in or
+/// custom, overriding its build type and adding to its flags.
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct ProfileConfig {
+    /// Profile this one is based on, required unless it overrides a built-in one
+    pub inherits: Option<String>,
+    /// CMake build type, e.g. `debug` or `RelWithDebInfo`
+    pub build_type: Option<String>,
+    /// Sanitizers to enable, e.g. `address` or `undefined`
+    pub sanitizers: Vec<String>,
+    /// Extra compiler flags
+    pub flags: Vec<String>,
+    /// Extra linker flags
+    pub link_flags: Vec<String>,
+    /// Extra CMake cache variables
+    pub cache: BTreeMap<String, String>,
+    /// Enables the exploit mitigations the toolchain supports, e.g. stack
+    /// protectors, fortification, PIE and full RELRO
+    pub hardened: bool,
+}
+
+/// A build target declared in the manifest. A target named after the
+/// project adds to the project target instead of creating a new one.
+#[derive(Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TargetConfig {
+    /// `executable`, `static`, `shared` or `interface`
+    pub kind: String,
+    /// Globs of source files, relative to the project root
+    pub sources: Vec<String>,
+    /// Include directories, relative to the project root
+    pub include_dirs: Vec<String>,
+    /// Compile definitions, e.g. `USE_SIMD` or `LEVEL=2`
+    pub definitions: Vec<String>,
+    /// Targets and libraries to link, e.g. another target or `pthread`
+    pub link: Vec<String>,
+    /// Language standard, e.g. `c++20` or `c11`
+    pub standard: Option<String>,
+}
+
+impl Default for TargetConfig {
+    fn default() -> Self {
+        TargetConfig {
+            kind: "executable".to_string(),
+            sources: Vec::new(),
+            include_dirs: Vec::new(),
+            definitions: Vec::new(),
+            link: Vec::new(),
+            standard: None,
+        }
+    }
+}
+
+/// Settings of the `check` command
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct CheckConfig {
+    /// The compatibility range public headers are checked against
+    pub api: ApiCheckConfig,
+    /// What `check --unused` doesn't report
+    pub unused: UnusedCheckConfig,
+}
+
+/// The standards and compilers every public header must compile with
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct ApiCheckConfig {
+    /// Language standards, e.g. `c++17` or `c11`
+    pub standards: Vec<String>,
+    /// Compilers, defaults to the one CMake picked for the build directory
+    pub compilers: Vec<String>,
+    /// Extra compiler flags, e.g. `-Wall` and `-Werror`
+    pub flags: Vec<String>,
+}
+
+/// Files and functions that are unused on purpose, e.g. platform specific
+/// sources or functions only called from a debugger
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct UnusedCheckConfig {
+    /// Globs of files, e.g. `src/platform/win32/**`, or of function names,
+    /// e.g. `debug::*`
+    pub ignore: Vec<String>,
+}
+
+/// Every combination of operating system, compiler and profile becomes a
+/// CI job, except ones that can't work such as MSVC on Linux
+#[derive(Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CiConfig {
+    /// `linux`, `macos` or `windows`
+    pub os: Vec<String>,
+    /// `gcc`, `clang` or `msvc`
+    pub compilers: Vec<String>,
+    /// Built-in or manifest profiles
+    pub profiles: Vec<String>,
+}
+
+impl Default for CiConfig {
+    fn default() -> Self {
+        CiConfig {
+            os: vec!["linux".to_string()],
+            compilers: vec!["gcc".to_string(), "clang".to_string()],
+            profiles: vec!["debug".to_string(), "release".to_string()],
+        }
+    }
+}
+
+/// The steps contributors run before pushing
+#[derive(Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct VerifyConfig {
+    /// Steps in the order they run, of `format`, `build`, `lint`, `test` and
+    /// `coverage`
+    pub steps: Vec<String>,
+    /// Profiles `build` builds, each in its own directory. Tests and lints
+    /// run against the first.
+    pub profiles: Vec<String>,
+    /// Minimum line coverage in percent the `coverage` step accepts
+    pub coverage: f64,
+}
+
+impl Default for VerifyConfig {
+    fn default() -> Self {
+        VerifyConfig {
+            steps: vec![
+                "format".to_string(),
+                "build".to_string(),
+                "test".to_string(),
+            ],
+            profiles: vec!["debug".to_string()],
+            coverage: 80.0,
+        }
+    }
+}
+
+/// Which known vulnerabilities of dependencies fail `audit`
+#[derive(Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AuditConfig {
+    /// Lowest severity that fails, `low`, `medium`, `high`, `critical` or
+    /// `none` to only report
+    pub fail_on: String,
+    /// Accepted advisories, e.g. `CVE-2023-1234` or `GHSA-*`
+    pub ignore: Vec<String>,
+}
+
+impl Default for AuditConfig {
+    fn default() -> Self {
+        AuditConfig {
+            fail_on: "high".to_string(),
+            ignore: Vec::new(),
+        }
+    }
+}
+
+/// Globs are relative to the project root. Hidden directories and
+/// configured build directories are never watched.
+#[derive(Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WatchConfig {
+    /// Files whose changes rerun the command
+    pub include: Vec<String>,
+    /// Files and directories to ignore even when included
+    pub exclude: Vec<String>,
+    /// Clears the terminal before every run
+    pub clear: bool,
+}
+
+impl Default for WatchConfig {
+    fn default() -> Self {
+        WatchConfig {
+            include: [
+                "src/**",
+                "include/**",
+                "tests/**",
+                "examples/**",
+                "cmake/**",
+                "CMakeLists.txt",
+                "cpp_tools.toml",
+            ]
+            .iter()
+            .map(|glob| glob.to_string())
+            .collect(),
+            exclude: Vec::new(),
+            clear: true,
+        }
+    }
+}
+
+/// Editor configuration generated by `ide`
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct IdeConfig {
+    /// The `.clangd` file
+    pub clangd: ClangdConfig,
+}
+
+/// Settings only clangd sees, the build is unaffected
+#[derive(Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ClangdConfig {
+    /// Warning flags added to every file in the editor
+    pub warnings: Vec<String>,
+    /// Flags removed from compile commands, e.g. GCC options clang rejects
+    pub remove: Vec<String>,
+    /// Diagnostics hidden in the editor, by name or clang-tidy check
+    pub suppress: Vec<String>,
+    /// How completion inserts includes, `iwyu` or `never`
+    pub header_insertion: String,
+    /// Indexes the whole project in the background, turn off for very
+    /// large repositories to only index open files
+    pub background_index: bool,
+}
+
+impl Default for ClangdConfig {
+    fn default() -> Self {
+        ClangdConfig {
+            warnings: Vec::new(),
+            remove: Vec::new(),
+            suppress: Vec::new(),
+            header_insertion: "iwyu".to_string(),
+            background_index: true,
+        }
+    }
+}
+
+/// Checks `lint` runs besides clang-tidy
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct LintConfig {
+    /// Linting of the CMake files
+    pub cmake: CmakeLintConfig,
+}
+
+/// Linting of the CMake files, with cmake-lint when installed
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct CmakeLintConfig {
+    /// Checks that aren't reported, by cmake-lint code, e.g. `C0103`, or
+    /// built-in check name, e.g. `unused-variable`
+    pub suppress: Vec<String>,
+}
+
+/// A workspace of projects built together. The root manifest lists the
+/// members, each member's manifest the siblings it depends on.
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct WorkspaceConfig {
+    /// Directories of the member projects, relative to the workspace root
+    pub members: Vec<String>,
+    /// Names of the sibling members this member depends on
+    pub depends: Vec<String>,
+}
+
+/// What `publish github` releases
+#[derive(Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PublishConfig {
+    /// Platforms release artifacts are built for, `native` or a `--target`
+    /// such as `windows` or `android`
+    pub targets: Vec<String>,
+    /// The changelog release notes are taken from
+    pub changelog: String,
+}
+
+impl Default for PublishConfig {
+    fn default() -> Self {
+        PublishConfig {
+            targets: vec!["native".to_string()],
+            changelog: "CHANGELOG.md".to_string(),
+        }
+    }
+}
+
+/// The compile time budget `build --time-budget` checks
+#[derive(Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TimeBudgetConfig {
+    /// How many percent a file or the build may take longer than its baseline
+    pub percent: f64,
+    /// Only warns instead of failing the build
+    pub warn: bool,
+}
+
+impl Default for TimeBudgetConfig {
+    fn default() -> Self {
+        TimeBudgetConfig {
+            percent: 25.0,
+            warn: false,
+        }
+    }
+}
+
+/// Conventions applied to generated files
+#[derive(Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct StyleConfig {
+    /// Include guard style, `pragma` for `#pragma once` or `macro` for
+    /// `#ifndef` guards
+    pub include_guards: String,
+    /// Name of `#ifndef` guards, expanding `{PROJECT}`, `{PATH}` and `{FILE}`
+    pub guard_pattern: String,
+}
+
+impl Default for StyleConfig {
+    fn default() -> Self {
+        StyleConfig {
+            include_guards: "pragma".to_string(),
+            guard_pattern: "{PROJECT}_{PATH}_".to_string(),
+        }
+    }
+}
+
+impl Manifest {
+    /// Loads the manifest from the current directory, falling back to the
+    /// defaults when the project doesn't have one
+    pub fn load() -> Result<Manifest> {
+        Manifest::load_from(Path::new("."))
+    }
+
+    /// Loads the manifest of the project rooted at `root_dir`
+    pub fn load_from(root_dir: &Path) -> Result<Manifest> {
+        let path = root_dir.join(MANIFEST_FILE);
+        if fs::metadata(&path).is_err() {
+            return Ok(Manifest::default());
+        }
+
+        let contents = fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read {}", MANIFEST_FILE))?;
+
+        let manifest: Manifest = parse_toml(MANIFEST_FILE, &contents)?;
+        manifest.validate(&contents)?;
+
+        Ok(manifest)
+    }
+
+    /// Checks what the types alone can't, e.g. options that contradict each other
+    fn validate(&self, contents: &str) -> Result<()> {
+        let guard_styles = ["pragma", "pragma_once", "macro", "ifndef"];
+        if !guard_styles.contains(&self.style.include_guards.as_str()) {
+            let message = format!(
+                "invalid include guard style '{}', expected 'pragma' or 'macro'",
+                self.style.include_guards
+            );
+            anyhow::bail!(located(
+                MANIFEST_FILE,
+                contents,
+                key_offset(contents, "style", "include_guards"),
+                &message
+            ));
+        }
+
+        let checks = [
+            ("language", &self.package.language, &["c", "cpp"][..]),
+            ("layout", &self.package.layout, &["flat", "canonical"][..]),
+        ];
+        for (key, value, valid) in checks {
+            if let Some(value) = value
+                .as_ref()
+                .filter(|value| !valid.contains(&value.as_str()))
+            {
+                let message = format!(
+                    "invalid {} '{}', expected '{}'",
+                    key,
+                    value,
+                    valid.join("' or '")
+                );
+                anyhow::bail!(located(
+                    MANIFEST_FILE,
+                    contents,
+                    key_offset(contents, "package", key),
+                    &message
+                ));
+            }
+        }
+        if let Some(standard) = &self.package.standard {
+            if self.standard_variable().is_none() {
+                let message = format!(
+                    "invalid standard '{}', expected e.g. 'c++23' or 'c17'{}",
+                    standard,
+                    match self.package.language.as_deref() {
+                        Some(language) => format!(" for language '{}'", language),
+                        None => String::new(),
+                    }
+                );
+                anyhow::bail!(located(
+                    MANIFEST_FILE,
+                    contents,
+                    key_offset(contents, "package", "standard"),
+                    &message
+                ));
+            }
+        }
+
+        if self.build_info.header.is_some() && !self.build_info.enabled {
+            anyhow::bail!(located(
+                MANIFEST_FILE,
+                contents,
+                key_offset(contents, "build_info", "header"),
+                "build_info.header has no effect unless build_info.enabled = true"
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// The CMake variable and value selecting the manifest's language
+    /// standard, e.g. `CMAKE_CXX_STANDARD` and `23` for `c++23`
+    pub fn standard_variable(&self) -> Option<(String, String)> {
+        let standard = self.package.standard.as_deref()?.to_ascii_lowercase();
+        let (language, version) = match standard.strip_prefix("c++") {
+            Some(version) => ("cpp", version.to_string()),
+            None => ("c", standard.strip_prefix('c')?.to_string()),
+        };
+        if self
+            .package
+            .language
+            .as_deref()
+            .is_some_and(|declared| declared != language)
+            || version.is_empty()
+            || !version.chars().all(|c| c.is_ascii_digit())
+        {
+            return None;
+        }
+
+        let variable = match language {
+            "cpp" => "CMAKE_CXX_STANDARD",
+            _ => "CMAKE_C_STANDARD",
+        };
+        Some((variable.to_string(), version))
+    }
+
+    pub fn run_config(&self, name: &str) -> Result<&RunConfig> {
+        self.run.get(name).with_context(|| {
+            let available = self.run.keys().cloned().collect::<Vec<_>>().join(", ");
+            if available.is_empty() {
+                format!(
+                    "Run configuration '{}' not found, {} defines no [run.<name>] tables",
+                    name, MANIFEST_FILE
+                )
+            } else {
+                format!(
+                    "Run configuration '{}' not found, available configurations: {}",
+                    name, available
+                )
+            }
+        })
+    }
+}
+
+/// The directories of the manifest of the project `dir` is in, e.g. the
+/// root directory `init` was pointed at. A manifest that doesn't parse is
+/// reported by the command loading it.
+pub fn dirs_of(dir: &Path) -> DirsConfig {
+    dir.ancestors()
+        .find(|dir| dir.join(MANIFEST_FILE).is_file())
+        .and_then(|root| Manifest::load_from(root).ok())
+        .map(|manifest| manifest.dirs)
+        .unwrap_or_default()
+}
+
+/// Deserializes a TOML file, reporting errors at their line and column with
+/// the offending line and a suggestion for misspelled keys
+pub fn parse_toml<T: DeserializeOwned>(file: &str, contents: &str) -> Result<T> {
+    toml::from_str(contents).map_err(|error| {
+        let message = describe_error(error.message());
+        let offset = error.span().map(|span| span.start);
+
+        anyhow::anyhow!(located(file, contents, offset, &message))
+    })
+}
+
+/// Adds "did you mean" to serde's unknown field errors, which look like
+/// "unknown field `nmae`, expected `name` or `version`"
+fn describe_error(message: &str) -> String {
+    let Some(rest) = message.strip_prefix("unknown field ") else {
+        return message.to_string();
+    };
+
+    let names = rest.split('`').skip(1).step_by(2).collect::<Vec<_>>();
+    let Some((field, expected)) = names.split_first() else {
+        return message.to_string();
+    };
+
+    match suggest(field, expected) {
+        Some(suggestion) => format!("unknown key `{}`, did you mean `{}`?", field, suggestion),
+        None if expected.is_empty() => format!("unknown key `{}`", field),
+        None => format!(
+            "unknown key `{}`, expected one of {}",
+            field,
+            expected
+                .iter()
+                .map(|name| format!("`{}`", name))
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+    }
+}
+
+/// Formats an error as `file:line:column: message` followed by the line
+/// it points at, when the location is known
+fn located(file: &str, contents: &str, offset: Option<usize>, message: &str) -> String {
+    let Some(offset) = offset.filter(|offset| *offset <= contents.len()) else {
+        return format!("{}: {}", file, message);
+    };
+
+    let before = &contents[..offset];
+    let line = before.matches('\n').count() + 1;
+    let column = before.len() - before.rfind('\n').map_or(0, |index| index + 1) + 1;
+    let text = contents.lines().nth(line - 1).unwrap_or_default();
+    let gutter = " ".repeat(line.to_string().len());
+
+    format!(
+        "{}:{}:{}: {}\n{} |\n{} | {}\n{} | {}^",
+        file,
+        line,
+        column,
+        message,
+        gutter,
+        line,
+        text,
+        gutter,
+        " ".repeat(column - 1)
+    )
+}
+
+/// The byte offset of `key` inside the `[section]` table
+fn key_offset(contents: &str, section: &str, key: &str) -> Option<usize> {
+    let mut current = "";
+    let mut offset = 0;
+
+    for line in contents.split_inclusive('\n') {
+        let trimmed = line.trim();
+        if let Some(header) = trimmed
+            .strip_prefix('[')
+            .and_then(|rest| rest.strip_suffix(']'))
+        {
+            current = header.trim();
+        } else if current == section
+            && trimmed
+                .split_once('=')
+                .is_some_and(|(name, _)| name.trim() == key)
+        {
+            return Some(offset + line.len() - line.trim_start().len());
+        }
+        offset += line.len();
+    }
+
+    None
+}
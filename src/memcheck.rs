@@ -0,0 +1,261 @@
+use anyhow::{Context, Result};
+use colorize::AnsiColor;
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use crate::{cmake, handle_init_project, manifest::Manifest, ConfigureArgs};
+
+/// Valgrind's memcheck options: full leak checks without the memory still
+/// reachable at exit, which is rarely a bug
+const VALGRIND_OPTIONS: &str =
+    "-q --tool=memcheck --leak-check=full --show-leak-kinds=definite,indirect,possible --num-callers=50";
+
+/// The directory CTest writes each test's memory checker log into
+const LOG_DIR: &str = "Testing/Temporary";
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Tool {
+    Valgrind,
+    Asan,
+}
+
+/// How `test` checks the tests for leaks and memory errors, with CTest's
+/// memcheck mode
+pub struct Memcheck {
+    tool: Tool,
+    suppressions: Option<PathBuf>,
+}
+
+/// What CTest's memcheck found in one test, from its
+/// `1/2 MemCheck: #1: parser ......   Defects: 2` line
+pub struct Defects {
+    index: usize,
+    name: String,
+    count: usize,
+}
+
+impl Memcheck {
+    pub fn new(tool: Tool, suppressions: Option<String>) -> Result<Memcheck> {
+        let suppressions = suppressions
+            .map(|file| {
+                fs::canonicalize(&file)
+                    .with_context(|| format!("Suppression file '{}' doesn't exist", file))
+            })
+            .transpose()?;
+
+        Ok(Memcheck { tool, suppressions })
+    }
+
+    /// The build directory to test in. ASan needs an instrumented build,
+    /// which is kept apart from the regular one in `<BUILD_DIR>-asan`, a
+    /// debug build with AddressSanitizer or the manifest's `asan` profile
+    /// when it defines one.
+    pub fn prepare(&self, build_dir: String) -> Result<String> {
+        if self.tool == Tool::Valgrind {
+            return Ok(build_dir);
+        }
+
+        let build_dir = format!("{}-asan", build_dir);
+        if cmake::cache_value(Path::new(&build_dir), "CMAKE_HOME_DIRECTORY").is_none() {
+            let configure = match Manifest::load()?.profile.contains_key("asan") {
+                true => ConfigureArgs {
+                    profile: Some("asan".to_string()),
+                    ..Default::default()
+                },
+                false => ConfigureArgs {
+                    profile: Some("debug".to_string()),
+                    sanitizers: vec!["address".to_string()],
+                    ..Default::default()
+                },
+            };
+            handle_init_project(".", &build_dir, false, &configure)?;
+        }
+
+        Ok(build_dir)
+    }
+
+    /// Adds the environment and ctest arguments running the tests under the
+    /// tool
+    pub fn ctest_command(&self, command: &mut Command) -> Result<()> {
+        command.args(["-T", "memcheck"]);
+        match self.tool {
+            Tool::Valgrind => {
+                let valgrind = find_tool("valgrind")
+                    .context("valgrind isn't installed, install it or test with --asan")?;
+                command
+                    .arg("--overwrite")
+                    .arg(format!("MemoryCheckCommand={}", valgrind.display()))
+                    .arg("--overwrite")
+                    .arg(format!("MemoryCheckCommandOptions={}", VALGRIND_OPTIONS));
+            }
+            Tool::Asan => {
+                command.args(["--overwrite", "MemoryCheckType=AddressSanitizer"]);
+                // Leaks are suppressed through LeakSanitizer's own options
+                if let Some(suppressions) = &self.suppressions {
+                    command.env(
+                        "LSAN_OPTIONS",
+                        format!("suppressions={}", suppressions.display()),
+                    );
+                }
+            }
+        }
+        if let Some(suppressions) = &self.suppressions {
+            command.arg("--overwrite").arg(format!(
+                "MemoryCheckSuppressionFile={}",
+                suppressions.display()
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+/// Reads a test's defect count from the summary memcheck prints after the tests
+pub fn parse_defects(line: &str) -> Option<Defects> {
+    let (_, rest) = line.split_once(" MemCheck: #")?;
+    let (index, rest) = rest.split_once(": ")?;
+    let name = rest.split([' ', '.']).next()?.to_string();
+    let count = rest.rsplit_once("Defects: ")?.1.trim().parse().ok()?;
+
+    Some(Defects {
+        index: index.trim().parse().ok()?,
+        name,
+        count,
+    })
+}
+
+/// Lists the leaks and memory errors of every test that has any, by kind,
+/// with the log holding their stack traces. Fails when there are any.
+pub fn report(build_dir: &str, defects: &[Defects]) -> Result<()> {
+    let defective = defects
+        .iter()
+        .filter(|test| test.count > 0)
+        .collect::<Vec<_>>();
+    if defective.is_empty() {
+        if !defects.is_empty() {
+            eprintln!("{}", "No leaks or memory errors found".green());
+        }
+        return Ok(());
+    }
+
+    for test in &defective {
+        let logs = logs(build_dir, test.index);
+        let mut kinds = BTreeMap::<&str, usize>::new();
+        for log in &logs {
+            for line in fs::read_to_string(log).unwrap_or_default().lines() {
+                if let Some(kind) = defect_kind(line) {
+                    *kinds.entry(kind).or_default() += 1;
+                }
+            }
+        }
+        let summary = match kinds.is_empty() {
+            true => plural(test.count, "defect"),
+            false => kinds
+                .iter()
+                .map(|(kind, count)| plural(*count, kind))
+                .collect::<Vec<_>>()
+                .join(", "),
+        };
+        eprintln!("{}", format!("{}: {}", test.name, summary).red());
+        for log in &logs {
+            eprintln!("  {}", log.display());
+        }
+    }
+
+    let tests = if defects.len() == 1 { "test" } else { "tests" };
+    anyhow::bail!(
+        "{} of {} {} leaked or had memory errors, suppress false positives with --suppressions",
+        defective.len(),
+        defects.len(),
+        tests
+    )
+}
+
+/// The kind of defect a valgrind or sanitizer report starts with
+fn defect_kind(line: &str) -> Option<&'static str> {
+    // Valgrind prefixes its lines with the process id, e.g. `==1234== `
+    let line = line
+        .strip_prefix("==")
+        .and_then(|rest| rest.split_once("== "))
+        .map_or(line, |(_, rest)| rest);
+
+    if line.contains(" are definitely lost")
+        || line.contains(" are indirectly lost")
+        || line.contains(" are possibly lost")
+        || line.starts_with("Direct leak of ")
+        || line.starts_with("Indirect leak of ")
+    {
+        Some("leak")
+    } else if line.starts_with("Invalid read") {
+        Some("invalid read")
+    } else if line.starts_with("Invalid write") {
+        Some("invalid write")
+    } else if line.starts_with("Invalid free") || line.starts_with("Mismatched free") {
+        Some("invalid free")
+    } else if line.starts_with("Conditional jump or move depends on uninitialised")
+        || line.starts_with("Use of uninitialised value")
+    {
+        Some("use of uninitialised memory")
+    } else if let Some(error) = line.split_once("ERROR: AddressSanitizer: ") {
+        Some(match error.1.split_whitespace().next() {
+            Some("heap-use-after-free") => "use after free",
+            Some("heap-buffer-overflow")
+            | Some("stack-buffer-overflow")
+            | Some("global-buffer-overflow") => "buffer overflow",
+            Some("attempting") => "invalid free",
+            _ => "memory error",
+        })
+    } else {
+        None
+    }
+}
+
+/// The memory checker logs of a test, one per process the sanitizers
+/// write into when the test starts others
+fn logs(build_dir: &str, index: usize) -> Vec<PathBuf> {
+    let dir = Path::new(build_dir).join(LOG_DIR);
+    let prefix = format!("MemoryChecker.{}.log", index);
+    let Ok(entries) = fs::read_dir(&dir) else {
+        return Vec::new();
+    };
+
+    let mut logs = entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| {
+            path.file_name()
+                .and_then(|name| name.to_str())
+                .is_some_and(|name| {
+                    name == prefix
+                        || name
+                            .strip_prefix(&prefix)
+                            .is_some_and(|rest| rest.starts_with('.'))
+                })
+        })
+        .filter(|path| fs::metadata(path).is_ok_and(|metadata| metadata.len() > 0))
+        .collect::<Vec<_>>();
+    logs.sort();
+
+    logs
+}
+
+fn plural(count: usize, noun: &str) -> String {
+    if count == 1 {
+        return format!("1 {}", noun);
+    }
+
+    match noun.strip_prefix("use ") {
+        Some(rest) => format!("{} uses {}", count, rest),
+        None => format!("{} {}s", count, noun),
+    }
+}
+
+fn find_tool(tool: &str) -> Option<PathBuf> {
+    std::env::split_paths(&std::env::var_os("PATH")?)
+        .map(|dir| dir.join(tool))
+        .find(|path| path.is_file())
+}
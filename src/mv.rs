@@ -0,0 +1,233 @@
+use anyhow::{Context, Result};
+use colorize::AnsiColor;
+use std::{
+    fs,
+    path::{Component, Path, PathBuf},
+};
+
+use crate::{cmake, layout, manifest::MANIFEST_FILE, sources};
+
+pub fn handle_move_file(source: String, destination: String) -> Result<()> {
+    let source = normalize(Path::new(&source));
+    let destination = normalize(Path::new(&destination));
+
+    if !source.is_file() {
+        anyhow::bail!("'{}' is not a file", source.display());
+    }
+    if destination.exists() {
+        anyhow::bail!("'{}' already exists", destination.display());
+    }
+
+    // Directories `#include <...>` paths are resolved against, besides the
+    // directory of the including file
+    let roots = [layout::include_dir()?, layout::src_dir()?, ".".to_string()];
+
+    // Work out every edit before touching anything, so a failure leaves the
+    // project as it was
+    let mut edits = Vec::new();
+    for file in sources::project_sources()? {
+        let text = fs::read_to_string(&file)
+            .with_context(|| format!("Failed to read '{}'", file.display()))?;
+        let updated = rewrite_includes(&text, &file, &source, &destination, &roots);
+        if updated != text {
+            edits.push((file, text, updated));
+        }
+    }
+
+    let source_str = source.display().to_string();
+    let destination_str = destination.display().to_string();
+    for build_file in [cmake::CMAKE_LISTS, MANIFEST_FILE] {
+        let Ok(text) = fs::read_to_string(build_file) else {
+            continue;
+        };
+        let updated = replace_path(&text, &source_str, &destination_str);
+        if updated != text {
+            edits.push((PathBuf::from(build_file), text, updated));
+        }
+    }
+
+    apply(&edits)?;
+
+    if let Err(error) = move_file(&source, &destination) {
+        restore(&edits);
+        return Err(error);
+    }
+
+    eprintln!(
+        "{}",
+        format!("Moved '{}' to '{}'", source_str, destination_str).green()
+    );
+    for (file, _, _) in &edits {
+        eprintln!("  updated {}", file.display());
+    }
+
+    Ok(())
+}
+
+fn apply(edits: &[(PathBuf, String, String)]) -> Result<()> {
+    for (index, (file, _, updated)) in edits.iter().enumerate() {
+        if let Err(error) = fs::write(file, updated) {
+            restore(&edits[..index]);
+            return Err(error).with_context(|| format!("Failed to update '{}'", file.display()));
+        }
+    }
+
+    Ok(())
+}
+
+fn restore(edits: &[(PathBuf, String, String)]) {
+    for (file, original, _) in edits {
+        let _ = fs::write(file, original);
+    }
+}
+
+fn move_file(source: &Path, destination: &Path) -> Result<()> {
+    if let Some(parent) = destination.parent() {
+        fs::create_dir_all(parent).context("Failed to create destination directory")?;
+    }
+
+    fs::rename(source, destination)
+        .with_context(|| format!("Failed to move '{}'", source.display()))
+}
+
+/// Rewrites the includes in `file` that refer to `source` so they refer to
+/// `destination`. When `file` is the one being moved, its relative includes
+/// are also adjusted to its new location.
+fn rewrite_includes(
+    text: &str,
+    file: &Path,
+    source: &Path,
+    destination: &Path,
+    roots: &[String],
+) -> String {
+    let file = normalize(file);
+    let old_dir = file.parent().unwrap_or(Path::new("")).to_path_buf();
+    let new_dir = if file == source {
+        destination.parent().unwrap_or(Path::new("")).to_path_buf()
+    } else {
+        old_dir.clone()
+    };
+
+    let mut result = text
+        .lines()
+        .map(|line| {
+            let Some((spelling, quoted)) = parse_include(line) else {
+                return line.to_string();
+            };
+
+            let relative = normalize(&old_dir.join(spelling));
+            let new_spelling = if quoted && relative.exists() {
+                let target = if relative == source {
+                    destination.to_path_buf()
+                } else {
+                    relative
+                };
+                if target == normalize(&new_dir.join(spelling)) {
+                    return line.to_string();
+                }
+                relative_path(&new_dir, &target)
+            } else if normalize(&Path::new(resolve_root(roots, spelling)).join(spelling)) == source
+            {
+                let root = resolve_root(roots, spelling);
+                match destination.strip_prefix(normalize(Path::new(root))) {
+                    Ok(path) if root != "." => path.to_path_buf(),
+                    _ => relative_path(&new_dir, destination),
+                }
+            } else {
+                return line.to_string();
+            };
+
+            line.replacen(spelling, &new_spelling.display().to_string(), 1)
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    if text.ends_with('\n') {
+        result.push('\n');
+    }
+
+    result
+}
+
+/// The include root a `<...>` or unresolved `"..."` include is found in
+fn resolve_root<'a>(roots: &'a [String], spelling: &str) -> &'a str {
+    roots
+        .iter()
+        .map(String::as_str)
+        .find(|root| Path::new(root).join(spelling).exists())
+        .unwrap_or(".")
+}
+
+/// Splits `#include "foo.hpp"` into its path and whether it was quoted
+fn parse_include(line: &str) -> Option<(&str, bool)> {
+    let rest = line.trim_start().strip_prefix('#')?.trim_start();
+    let rest = rest.strip_prefix("include")?.trim_start();
+
+    if let Some(rest) = rest.strip_prefix('"') {
+        return rest.split_once('"').map(|(path, _)| (path, true));
+    }
+
+    rest.strip_prefix('<')?
+        .split_once('>')
+        .map(|(path, _)| (path, false))
+}
+
+/// Replaces a path where it appears as a whole token, e.g. in a CMake
+/// source list or a manifest string
+fn replace_path(text: &str, old: &str, new: &str) -> String {
+    let is_path = |c: char| c.is_ascii_alphanumeric() || "_-./".contains(c);
+    let mut result = String::with_capacity(text.len());
+    let mut offset = 0;
+
+    while let Some(index) = text[offset..].find(old).map(|index| offset + index) {
+        let before = text[..index].chars().next_back();
+        let after = text[index + old.len()..].chars().next();
+
+        result.push_str(&text[offset..index]);
+        if before.is_some_and(is_path) || after.is_some_and(is_path) {
+            result.push_str(old);
+        } else {
+            result.push_str(new);
+        }
+        offset = index + old.len();
+    }
+    result.push_str(&text[offset..]);
+
+    result
+}
+
+/// Lexically resolves `.` and `..` components
+fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                if !normalized.pop() {
+                    normalized.push("..");
+                }
+            }
+            component => normalized.push(component),
+        }
+    }
+
+    normalized
+}
+
+/// The path to `target` as seen from `from_dir`
+fn relative_path(from_dir: &Path, target: &Path) -> PathBuf {
+    let from = from_dir.components().collect::<Vec<_>>();
+    let to = target.components().collect::<Vec<_>>();
+    let common = from.iter().zip(&to).take_while(|(a, b)| a == b).count();
+
+    let mut path = PathBuf::new();
+    for _ in common..from.len() {
+        path.push("..");
+    }
+    for component in &to[common..] {
+        path.push(component);
+    }
+
+    path
+}
@@ -0,0 +1,107 @@
+use anyhow::{Context, Result};
+use serde::Serialize;
+use std::path::Path;
+
+use crate::{examples::CONSUMER_EXAMPLE, executor::shell_quote, glob, ConfigureArgs, ScaffoldArgs};
+
+/// What `new --json` prints on stdout, so provisioning scripts learn where
+/// the project is and how to carry on without parsing messages
+#[derive(Serialize)]
+pub struct NewReport {
+    name: String,
+    project_dir: String,
+    build_dir: String,
+    /// The files created, relative to the project directory, without the
+    /// build directory and git's
+    files: Vec<String>,
+    options: Options,
+    next_steps: Vec<String>,
+}
+
+/// The options the project was created with, defaults included
+#[derive(Serialize)]
+struct Options {
+    language: String,
+    layout: String,
+    kind: String,
+    build_system: String,
+    src_dir: String,
+    include_dir: String,
+    exec_dir: String,
+    template: Option<String>,
+    import_std: bool,
+    package_manager: Option<String>,
+    generator: Option<String>,
+    profile: Option<String>,
+    target: Option<String>,
+}
+
+impl NewReport {
+    pub fn new(
+        name: &str,
+        project_dir: &str,
+        build_dir: &str,
+        scaffold: &ScaffoldArgs,
+        configure: &ConfigureArgs,
+    ) -> Result<NewReport> {
+        let build_path = Path::new(project_dir).join(build_dir);
+        let files = glob::expand(&format!("{}/**/*", project_dir))?
+            .into_iter()
+            .filter(|file| !file.starts_with(&build_path))
+            .filter_map(|file| {
+                let relative = file.strip_prefix(project_dir).ok()?.to_path_buf();
+                (!relative.starts_with(".git")).then(|| relative.display().to_string())
+            })
+            .collect();
+
+        // A build directory outside the project is its recorded default
+        let build_arg = match build_dir {
+            "build" => String::new(),
+            _ if Path::new(build_dir).is_absolute() => String::new(),
+            _ => format!(" -b {}", shell_quote(build_dir)),
+        };
+
+        Ok(NewReport {
+            name: name.to_string(),
+            project_dir: project_dir.to_string(),
+            build_dir: build_path.display().to_string(),
+            files,
+            options: Options {
+                language: scaffold.file_ext.to_ascii_lowercase(),
+                layout: scaffold.layout.to_ascii_lowercase(),
+                kind: scaffold.kind.to_ascii_lowercase(),
+                build_system: scaffold.build_system.to_ascii_lowercase(),
+                src_dir: scaffold.src_dir.clone(),
+                include_dir: scaffold.include_dir.clone(),
+                exec_dir: scaffold.exec_dir.clone(),
+                template: scaffold
+                    .template
+                    .as_ref()
+                    .map(|template| template.to_ascii_lowercase()),
+                import_std: scaffold.import_std,
+                package_manager: scaffold
+                    .package_manager
+                    .as_ref()
+                    .map(|manager| manager.to_ascii_lowercase()),
+                generator: configure.generator.clone(),
+                profile: configure.profile.clone(),
+                target: configure.platform.target.clone(),
+            },
+            next_steps: vec![
+                format!("cd {}", shell_quote(project_dir)),
+                format!("cxx build{}", build_arg),
+                match scaffold.kind.eq_ignore_ascii_case("bin") {
+                    true => format!("cxx run{}", build_arg),
+                    false => format!("cxx run{} --example {}", build_arg, CONSUMER_EXAMPLE),
+                },
+            ],
+        })
+    }
+
+    pub fn print(&self) -> Result<()> {
+        let json = serde_json::to_string_pretty(self).context("Failed to serialize the report")?;
+        println!("{}", json);
+
+        Ok(())
+    }
+}
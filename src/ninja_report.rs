@@ -0,0 +1,362 @@
+use anyhow::{Context, Result};
+use colorize::AnsiColor;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs,
+    path::Path,
+    process::Command,
+};
+
+use crate::{executor::executor, stats};
+
+/// Width of the bars in the parallelism chart
+const CHART_WIDTH: usize = 40;
+
+/// Number of time slices the parallelism chart has
+const CHART_ROWS: usize = 20;
+
+/// A build step of the last build, as Ninja logged it
+struct Step {
+    output: String,
+    /// Milliseconds since the build started
+    start: u64,
+    end: u64,
+}
+
+impl Step {
+    fn seconds(&self) -> f64 {
+        self.end.saturating_sub(self.start) as f64 / 1000.0
+    }
+
+    /// The source of a compile step, or the output of any other
+    fn name(&self) -> String {
+        stats::object_source(&self.output).unwrap_or_else(|| self.output.clone())
+    }
+}
+
+/// Analyzes the last Ninja build of `build_dir`: the critical path of steps
+/// that kept it from finishing sooner, the most expensive steps, and how
+/// many of the cores were busy over time. A build whose critical path is
+/// about as long as the build gains nothing from more cores, only from
+/// splitting the steps on it.
+pub fn handle_build_report(build_dir: String, top: usize) -> Result<()> {
+    let log_path = Path::new(&build_dir).join(".ninja_log");
+    let log = fs::read_to_string(&log_path).with_context(|| {
+        format!(
+            "No Ninja log in '{}', build with the Ninja generator first, e.g. 'cxx build -G Ninja'",
+            build_dir
+        )
+    })?;
+    let steps = last_build(&log);
+    if steps.is_empty() {
+        anyhow::bail!("{} has no build steps yet", log_path.display());
+    }
+
+    let wall = steps.iter().map(|step| step.end).max().unwrap_or(0)
+        - steps.iter().map(|step| step.start).min().unwrap_or(0);
+    let wall = wall.max(1) as f64 / 1000.0;
+    let busy = steps.iter().map(Step::seconds).sum::<f64>();
+    let cores = std::thread::available_parallelism().map_or(1, |cores| cores.get());
+    let cores_label = format!("{} {}", cores, if cores == 1 { "core" } else { "cores" });
+    println!(
+        "{}",
+        format!(
+            "Last build: {} {} in {:.1}s, {:.1}s of work, {:.1} of {} busy on average",
+            steps.len(),
+            if steps.len() == 1 { "step" } else { "steps" },
+            wall,
+            busy,
+            busy / wall,
+            cores_label
+        )
+        .bold()
+    );
+
+    let path = critical_path(&steps, &dependencies(&build_dir));
+    let path_seconds = path.iter().map(|step| step.seconds()).sum::<f64>();
+    println!(
+        "\nCritical path, {:.1}s ({:.0}% of the build):",
+        path_seconds,
+        path_seconds / wall * 100.0
+    );
+    for step in &path {
+        println!("  {:>7.2}s  {}", step.seconds(), step.name());
+    }
+
+    let mut expensive = steps.iter().collect::<Vec<_>>();
+    expensive.sort_by(|a, b| b.seconds().total_cmp(&a.seconds()));
+    println!("\nMost expensive steps:");
+    for step in expensive.iter().take(top) {
+        println!(
+            "  {:>7.2}s  {:>4.1}%  {}",
+            step.seconds(),
+            step.seconds() / busy.max(f64::EPSILON) * 100.0,
+            step.name()
+        );
+    }
+
+    println!("\nParallelism over time ({}):", cores_label);
+    let slice = wall / CHART_ROWS as f64;
+    let first = steps.iter().map(|step| step.start).min().unwrap_or(0) as f64 / 1000.0;
+    let mut idle = 0;
+    for row in 0..CHART_ROWS {
+        let from = first + row as f64 * slice;
+        let to = from + slice;
+        // The average number of steps running during the slice
+        let running = steps
+            .iter()
+            .map(|step| {
+                let start = step.start as f64 / 1000.0;
+                let end = step.end as f64 / 1000.0;
+                (end.min(to) - start.max(from)).max(0.0)
+            })
+            .sum::<f64>()
+            / slice;
+        if running < cores as f64 / 2.0 {
+            idle += 1;
+        }
+        let width = ((running / cores as f64) * CHART_WIDTH as f64).round() as usize;
+        println!(
+            "  {:>6.1}s  {:>5.1}  {}",
+            from - first,
+            running,
+            "#".repeat(width.clamp(1, CHART_WIDTH)).cyan()
+        );
+    }
+
+    println!();
+    if path_seconds >= wall * 0.8 {
+        println!(
+            "The critical path takes most of the build, more cores won't help. Split the slowest steps on it, e.g. large sources or a target everything waits for."
+        );
+    } else if idle > CHART_ROWS / 2 {
+        println!(
+            "Fewer than half the cores were busy most of the time. Targets that depend on each other serialize the build, finer targets or fewer dependencies between them would help."
+        );
+    } else {
+        println!("The build keeps the cores busy, more cores would speed it up.");
+    }
+
+    Ok(())
+}
+
+/// The steps of the last build in the log. Ninja appends every build's steps
+/// as they finish, timed from the start of that build, so a step ending
+/// before the one logged above it starts a new build. Steps of the same
+/// output logged twice keep the later one.
+fn last_build(log: &str) -> Vec<Step> {
+    let mut builds = vec![Vec::new()];
+    let mut last_end = 0;
+    for line in log.lines().filter(|line| !line.starts_with('#')) {
+        let fields = line.split('\t').collect::<Vec<_>>();
+        let (Some(start), Some(end), Some(output)) = (
+            fields.first().and_then(|start| start.parse::<u64>().ok()),
+            fields.get(1).and_then(|end| end.parse::<u64>().ok()),
+            fields.get(3),
+        ) else {
+            continue;
+        };
+        if end < last_end {
+            builds.push(Vec::new());
+        }
+        last_end = end;
+        if let Some(build) = builds.last_mut() {
+            build.push(Step {
+                output: output.to_string(),
+                start,
+                end,
+            });
+        }
+    }
+
+    let mut steps: BTreeMap<String, Step> = BTreeMap::new();
+    for step in builds.pop().unwrap_or_default() {
+        steps.insert(step.output.clone(), step);
+    }
+    let mut steps = steps.into_values().collect::<Vec<_>>();
+    steps.sort_by_key(|step| step.start);
+
+    steps
+}
+
+/// The inputs of every output in the build graph, from `ninja -t graph`,
+/// whose build edges with several inputs or outputs are nodes of their own
+fn dependencies(build_dir: &str) -> BTreeMap<String, Vec<String>> {
+    let mut dependencies: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    let Ok(output) =
+        executor().capture(Command::new("ninja").args(["-C", build_dir, "-t", "graph"]))
+    else {
+        return dependencies;
+    };
+
+    let mut labels = BTreeMap::new();
+    let mut edges = BTreeSet::new();
+    let mut arrows = Vec::new();
+    let graph = String::from_utf8_lossy(&output.stdout);
+    for line in graph.lines() {
+        let line = line.trim();
+        if let Some((from, rest)) = line.split_once(" -> ") {
+            let to = rest.split(' ').next().unwrap_or_default();
+            arrows.push((from.trim_matches('"'), to.trim_matches('"')));
+        } else if let Some((id, attributes)) = line.split_once(" [") {
+            if attributes.contains("shape=ellipse") {
+                edges.insert(id.trim_matches('"'));
+            } else if let Some(label) = attributes
+                .split_once("label=\"")
+                .and_then(|(_, rest)| rest.split_once('"'))
+                .map(|(label, _)| label)
+            {
+                labels.insert(id.trim_matches('"'), label.to_string());
+            }
+        }
+    }
+
+    let mut edge_inputs: BTreeMap<&str, Vec<String>> = BTreeMap::new();
+    let mut edge_outputs: BTreeMap<&str, Vec<String>> = BTreeMap::new();
+    for (from, to) in arrows {
+        match (edges.contains(from), edges.contains(to)) {
+            (false, true) => edge_inputs
+                .entry(to)
+                .or_default()
+                .extend(labels.get(from).cloned()),
+            (true, false) => edge_outputs
+                .entry(from)
+                .or_default()
+                .extend(labels.get(to).cloned()),
+            _ => {
+                if let (Some(from), Some(to)) = (labels.get(from), labels.get(to)) {
+                    dependencies
+                        .entry(to.clone())
+                        .or_default()
+                        .push(from.clone());
+                }
+            }
+        }
+    }
+    for (edge, outputs) in edge_outputs {
+        let inputs = edge_inputs.get(edge).cloned().unwrap_or_default();
+        for output in outputs {
+            dependencies
+                .entry(output)
+                .or_default()
+                .extend(inputs.iter().cloned());
+        }
+    }
+
+    dependencies
+}
+
+/// Walks back from the step that finished last, each time to the input
+/// step that finished last, the one it waited for. Inputs that weren't
+/// built, e.g. sources and phony targets, are looked through.
+fn critical_path<'a>(
+    steps: &'a [Step],
+    dependencies: &BTreeMap<String, Vec<String>>,
+) -> Vec<&'a Step> {
+    let by_output = steps
+        .iter()
+        .map(|step| (step.output.as_str(), step))
+        .collect::<BTreeMap<_, _>>();
+
+    let mut path = Vec::new();
+    let mut current = steps.iter().max_by_key(|step| step.end);
+    while let Some(step) = current {
+        path.push(step);
+
+        let mut waited_for: Option<&Step> = None;
+        let mut pending = dependencies.get(&step.output).cloned().unwrap_or_default();
+        let mut seen = BTreeSet::new();
+        while let Some(input) = pending.pop() {
+            if !seen.insert(input.clone()) {
+                continue;
+            }
+            match by_output.get(input.as_str()) {
+                Some(input) if input.end <= step.start => {
+                    if waited_for.is_none_or(|waited_for| input.end > waited_for.end) {
+                        waited_for = Some(input);
+                    }
+                }
+                Some(_) => {}
+                None => pending.extend(dependencies.get(&input).cloned().unwrap_or_default()),
+            }
+        }
+        current = waited_for;
+    }
+    path.reverse();
+
+    path
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn steps(log: &str) -> Vec<(String, u64, u64)> {
+        last_build(log)
+            .into_iter()
+            .map(|step| (step.output, step.start, step.end))
+            .collect()
+    }
+
+    fn step(output: &str, start: u64, end: u64) -> (String, u64, u64) {
+        (output.to_string(), start, end)
+    }
+
+    #[test]
+    fn reads_the_last_build_of_a_v5_log() {
+        let log = "# ninja log v5
+0\t100\t1700000000000000000\tCMakeFiles/app.dir/src/a.cpp.o\t1f2e3d
+0\t250\t1700000000000000000\tCMakeFiles/app.dir/src/b.cpp.o\t4c5b6a
+250\t300\t1700000000000000000\tapp\t7d8e9f
+0\t120\t1700000100000000000\tCMakeFiles/app.dir/src/a.cpp.o\t1f2e3e
+120\t200\t1700000100000000000\tapp\t7d8ea0
+";
+
+        assert_eq!(
+            steps(log),
+            [
+                step("CMakeFiles/app.dir/src/a.cpp.o", 0, 120),
+                step("app", 120, 200)
+            ]
+        );
+    }
+
+    #[test]
+    fn restarts_at_every_build_of_a_v6_log() {
+        let log = "# ninja log v6
+0\t40\t1700000000000000000\ta.o\t1
+0\t90\t1700000000000000000\tb.o\t2
+0\t30\t1700000100000000000\ta.o\t3
+0\t10\t1700000200000000000\tb.o\t4
+10\t60\t1700000200000000000\tapp\t5
+";
+
+        assert_eq!(steps(log), [step("b.o", 0, 10), step("app", 10, 60)]);
+    }
+
+    #[test]
+    fn keeps_the_later_of_an_output_logged_twice() {
+        let log = "# ninja log v6
+0\t50\t0\tx.o\t1
+10\t80\t0\ty.o\t2
+60\t90\t0\tx.o\t3
+";
+
+        assert_eq!(steps(log), [step("y.o", 10, 80), step("x.o", 60, 90)]);
+    }
+
+    #[test]
+    fn skips_lines_that_are_not_steps() {
+        let log = "# ninja log v5
+0\t50\t0\ta.o\t1
+garbage
+x\t60\t0\tb.o\t2
+50\t70\t0
+50\t80\t0\tapp\t3
+";
+
+        assert_eq!(steps(log), [step("a.o", 0, 50), step("app", 50, 80)]);
+        assert!(steps("").is_empty());
+        assert!(steps("# ninja log v6\n").is_empty());
+    }
+}
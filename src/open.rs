@@ -0,0 +1,146 @@
+use anyhow::{Context, Result};
+use colorize::AnsiColor;
+use std::{
+    collections::BTreeSet,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use crate::{executor::executor, layout::SRC_DIR, scaffold::file_stem, sources};
+
+/// Opens project files in `$VISUAL` or `$EDITOR`. Without a target this is
+/// the main source, `compile_commands` opens the compilation database and
+/// anything else is matched against the project's file names, so `FooBar`
+/// opens both `foo_bar.hpp` and `foo_bar.cpp`.
+pub fn handle_open(target: Option<String>, build_dir: String) -> Result<()> {
+    let files = match target.as_deref() {
+        None => vec![main_source()?],
+        Some("compile_commands" | "compile_commands.json") => {
+            let database = Path::new(&build_dir).join("compile_commands.json");
+            if !database.exists() {
+                anyhow::bail!(
+                    "'{}' doesn't exist, run 'cxx init' to generate it",
+                    database.display()
+                );
+            }
+            vec![database]
+        }
+        Some(target) if Path::new(target).is_file() => vec![PathBuf::from(target)],
+        Some(target) => resolve(target)?,
+    };
+
+    let mut command = editor();
+    command.args(&files);
+
+    eprintln!(
+        "{}",
+        format!(
+            "Opening {}",
+            files
+                .iter()
+                .map(|file| file.display().to_string())
+                .collect::<Vec<_>>()
+                .join(", ")
+        )
+        .green()
+    );
+
+    executor()
+        .execute_interactive(&mut command)
+        .context("Failed to open editor")
+}
+
+/// Opens a file at a line, e.g. where the compiler reported an error
+pub fn open_at(file: &Path, line: usize) -> Result<()> {
+    let mut command = editor();
+    // VS Code and its forks take the line as part of the path
+    if ["code", "codium", "cursor"]
+        .iter()
+        .any(|name| command.get_program() == *name)
+    {
+        command
+            .arg("--goto")
+            .arg(format!("{}:{}", file.display(), line));
+    } else {
+        command.arg(format!("+{}", line)).arg(file);
+    }
+
+    executor()
+        .execute_interactive(&mut command)
+        .context("Failed to open editor")
+}
+
+/// The editor, whose variable may hold arguments too, e.g. `code --wait`
+fn editor() -> Command {
+    let editor = std::env::var("VISUAL")
+        .or_else(|_| std::env::var("EDITOR"))
+        .unwrap_or_else(|_| "vi".to_string());
+    let mut words = editor.split_whitespace();
+    let mut command = Command::new(words.next().unwrap_or("vi"));
+    command.args(words);
+
+    command
+}
+
+fn main_source() -> Result<PathBuf> {
+    ["main.cpp", "main.c"]
+        .into_iter()
+        .map(|main| Path::new(SRC_DIR).join(main))
+        .find(|main| main.exists())
+        .with_context(|| format!("No main source found in '{}'", SRC_DIR))
+}
+
+/// Finds the files whose stem matches a name, ignoring case and separators.
+/// An exact match wins over files that merely contain the name.
+fn resolve(name: &str) -> Result<Vec<PathBuf>> {
+    let wanted = simplify(&file_stem(name));
+    let candidates = sources::project_sources()?
+        .into_iter()
+        .map(|file| (simplify(&stem(&file)), file))
+        .collect::<Vec<_>>();
+
+    let exact = candidates
+        .iter()
+        .filter(|(stem, _)| *stem == wanted)
+        .map(|(_, file)| file.clone())
+        .collect::<Vec<_>>();
+    if !exact.is_empty() {
+        return Ok(exact);
+    }
+
+    let partial = candidates
+        .into_iter()
+        .filter(|(stem, _)| stem.contains(&wanted))
+        .collect::<Vec<_>>();
+    let stems = partial
+        .iter()
+        .map(|(stem, _)| stem.as_str())
+        .collect::<BTreeSet<_>>();
+
+    match stems.len() {
+        0 => anyhow::bail!("No project file matches '{}'", name),
+        1 => Ok(partial.into_iter().map(|(_, file)| file).collect()),
+        _ => {
+            let matches = partial
+                .iter()
+                .map(|(_, file)| format!("  {}", file.display()))
+                .collect::<Vec<_>>()
+                .join("\n");
+            anyhow::bail!("'{}' is ambiguous, it matches:\n{}", name, matches)
+        }
+    }
+}
+
+fn stem(file: &Path) -> String {
+    file.file_stem()
+        .map(|stem| stem.to_string_lossy().to_string())
+        .unwrap_or_default()
+}
+
+/// `foo_bar`, `foo-bar` and `FooBar` all become `foobar`
+fn simplify(name: &str) -> String {
+    name.chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .map(|c| c.to_ascii_lowercase())
+        .collect()
+}
@@ -0,0 +1,200 @@
+use serde::Serialize;
+use std::path::PathBuf;
+
+/// A compiler error or warning
+#[derive(Serialize, Clone)]
+pub struct Diagnostic {
+    pub file: PathBuf,
+    pub line: usize,
+    pub column: Option<usize>,
+    pub error: bool,
+    pub message: String,
+}
+
+/// A test ctest finished
+#[derive(Serialize, Clone)]
+pub struct TestResult {
+    pub name: String,
+    pub passed: bool,
+    pub seconds: Option<f64>,
+    /// The signal a crashed test died of, e.g. `SIGSEGV`, or `crash` when
+    /// ctest doesn't tell
+    pub signal: Option<String>,
+}
+
+/// An object file the build tool started compiling
+#[derive(Serialize, Clone)]
+pub struct CompileStep {
+    pub target: String,
+    /// The source, relative to the project root for sources inside it
+    pub file: String,
+    /// How many build steps started and how many there are, from Ninja's
+    /// `[3/10]`, or the percentage of Makefiles' `[ 30%]` out of 100
+    pub done: usize,
+    pub total: usize,
+}
+
+/// Removes the escape sequences tools color their output with
+pub fn strip_ansi(line: &str) -> String {
+    let mut stripped = String::with_capacity(line.len());
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        if c == '\x1b' {
+            // Skip to the final byte of the escape sequence
+            for c in chars.by_ref() {
+                if c.is_ascii_alphabetic() {
+                    break;
+                }
+            }
+        } else {
+            stripped.push(c);
+        }
+    }
+
+    stripped
+}
+
+/// Reads GCC and Clang diagnostics, `src/main.cpp:12:5: error: ...`, and
+/// MSVC ones, `src\main.cpp(12,5): error C2065: ...`. Notes are left out.
+pub fn parse_diagnostic(line: &str) -> Option<Diagnostic> {
+    let (location, error, message) = [
+        (": error: ", true),
+        (": fatal error: ", true),
+        (": warning: ", false),
+    ]
+    .iter()
+    .find_map(|(marker, error)| {
+        let (location, message) = line.split_once(marker)?;
+        Some((location, *error, message))
+    })
+    .or_else(|| {
+        let (location, rest) = line.split_once("): ")?;
+        let (kind, message) = rest.split_once(' ')?;
+        let error = match kind {
+            "error" | "fatal" => true,
+            "warning" => false,
+            _ => return None,
+        };
+        Some((location, error, message.trim_start()))
+    })?;
+
+    let (file, line, column) = match location.split_once('(') {
+        Some((file, position)) => {
+            let mut position = position.split(',');
+            (file, position.next()?, position.next())
+        }
+        None => {
+            let mut parts = location.rsplitn(3, ':');
+            let last = parts.next()?;
+            let second = parts.next()?;
+            match parts.next() {
+                Some(file) => (file, second, Some(last)),
+                None => (second, last, None),
+            }
+        }
+    };
+
+    Some(Diagnostic {
+        file: PathBuf::from(file.trim()),
+        line: line.trim().parse().ok()?,
+        column: column.and_then(|column| column.trim().parse().ok()),
+        error,
+        message: message.to_string(),
+    })
+}
+
+/// Reads ctest's result lines, e.g.
+/// `1/3 Test #1: parser ...........   Passed    0.01 sec`, or
+/// `1/3 MemCheck #1: parser ...` when testing under a memory checker
+pub fn parse_test_result(line: &str) -> Option<TestResult> {
+    let (_, rest) = line
+        .split_once(" Test ")
+        .or_else(|| line.split_once(" MemCheck "))?;
+    let (_, rest) = rest.trim_start().strip_prefix('#')?.split_once(": ")?;
+    let name = rest.split([' ', '.']).next()?.to_string();
+    let seconds = rest
+        .strip_suffix(" sec")
+        .and_then(|rest| rest.split_whitespace().next_back())
+        .and_then(|seconds| seconds.parse().ok());
+
+    // ctest reports a test killed by a signal as an exception, e.g.
+    // `***Exception: SegFault` or `Subprocess aborted***Exception:`
+    let signal = rest.contains("***Exception").then(|| {
+        let outcome = &rest[name.len()..];
+        [
+            ("SegFault", "SIGSEGV"),
+            ("aborted", "SIGABRT"),
+            ("Bus error", "SIGBUS"),
+            ("Illegal", "SIGILL"),
+            ("Numerical", "SIGFPE"),
+            ("Interrupt", "SIGINT"),
+        ]
+        .iter()
+        .find(|(exception, _)| outcome.contains(exception))
+        .map_or("crash", |(_, signal)| signal)
+        .to_string()
+    });
+
+    Some(TestResult {
+        name,
+        passed: rest.contains(" Passed "),
+        seconds,
+        signal,
+    })
+}
+
+/// Reads the lines CMake's generators print per object file, e.g.
+/// `[3/10] Building CXX object CMakeFiles/app.dir/src/main.cpp.o` from Ninja
+/// or `[ 30%] Building CXX object ...` from Makefiles
+pub fn parse_compile_step(line: &str) -> Option<CompileStep> {
+    let (progress, rest) = line.trim_start().strip_prefix('[')?.split_once("] ")?;
+    let (done, total) = match progress.split_once('/') {
+        Some((done, total)) => (done.trim().parse().ok()?, total.trim().parse().ok()?),
+        None => (progress.trim().strip_suffix('%')?.trim().parse().ok()?, 100),
+    };
+    let rest = rest.strip_prefix("Building ")?;
+    let (_, object) = rest.split_once(" object ")?;
+    let (target, file) = object_source(object.trim())?;
+
+    Some(CompileStep {
+        target,
+        file,
+        done,
+        total,
+    })
+}
+
+/// Reads the object file a failed build step produces, from Ninja's
+/// `FAILED: CMakeFiles/app.dir/src/main.cpp.o` or Make's
+/// `make[2]: *** [CMakeFiles/app.dir/build.make:76: CMakeFiles/app.dir/src/main.cpp.o] Error 1`,
+/// and returns its target and source
+pub fn parse_failed_step(line: &str) -> Option<(String, String)> {
+    let object = if let Some(rest) = line.strip_prefix("FAILED: ") {
+        rest.split_whitespace()
+            .find(|word| word.contains(".dir/"))?
+    } else {
+        let (_, rest) = line.split_once("*** [")?;
+        let (rule, _) = rest.split_once(']')?;
+        rule.rsplit(": ").next()?
+    };
+
+    object_source(object)
+}
+
+/// The target and source of an object file in a CMake build tree. Objects
+/// are named after their source below the target's directory, with `__`
+/// standing in for `..`.
+fn object_source(object: &str) -> Option<(String, String)> {
+    let (dir, source) = object.split_once(".dir/")?;
+    let target = dir.rsplit('/').next()?.to_string();
+    let source = source
+        .strip_suffix(".o")
+        .or_else(|| source.strip_suffix(".obj"))?;
+    let file = source
+        .split('/')
+        .map(|component| if component == "__" { ".." } else { component })
+        .collect::<Vec<_>>()
+        .join("/");
+
+    Some((target, file))
+}
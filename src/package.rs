@@ -0,0 +1,290 @@
+use anyhow::{Context, Result};
+use colorize::AnsiColor;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use crate::{
+    backend::GENERATED_MARKER,
+    cmake::{self, FRAGMENT_DIR},
+    executor::executor,
+    manifest::Manifest,
+};
+
+/// Fragment installing the project's libraries with their package files
+pub const PACKAGE_FRAGMENT: &str = "package.cmake";
+
+/// A library package as consumers find it
+struct Package {
+    /// The name passed to `find_package()`, which is also the namespace of
+    /// the exported targets
+    name: String,
+    version: String,
+    targets: Vec<String>,
+}
+
+/// Generates what `find_package(<Name>)` needs to find the project's
+/// libraries once installed: a fragment installing them with an export set,
+/// the `<Name>Config.cmake` template and a version file
+pub fn handle_add_package() -> Result<()> {
+    let package = write_package()?;
+
+    eprintln!(
+        "{}",
+        format!(
+            "Generated {}/{} exporting {} as {}::{}",
+            FRAGMENT_DIR,
+            PACKAGE_FRAGMENT,
+            package.targets.join(", "),
+            package.name,
+            package.targets[0]
+        )
+        .green()
+    );
+
+    Ok(())
+}
+
+/// Builds the project's libraries in release and installs them into the
+/// per-user package directory, registering the installation in CMake's user
+/// package registry so other projects on the machine find it with a plain
+/// `find_package()`
+pub fn handle_publish_local(build_dir: String, jobs: Option<usize>) -> Result<()> {
+    let package = write_package()?;
+    let prefix = packages_dir()?.join(&package.name).join(&package.version);
+
+    let dir = format!("{}-publish", build_dir);
+    // The package directory is registered, so it has to be the same on
+    // every distribution, some of which install to lib64 by default
+    executor()
+        .execute(&mut cmake::configure_command(
+            &dir,
+            &[
+                ("CMAKE_BUILD_TYPE", "Release"),
+                ("CMAKE_INSTALL_LIBDIR", "lib"),
+            ],
+        ))
+        .context("Failed to configure the release build")?;
+    executor()
+        .execute(&mut cmake::build_command(&dir, Some("Release"), jobs))
+        .context("Failed to build the release build")?;
+    executor()
+        .execute(
+            Command::new("cmake")
+                .args(["--install", &dir, "--config", "Release", "--prefix"])
+                .arg(&prefix),
+        )
+        .context("Failed to install the package")?;
+
+    let config_dir = prefix.join("lib").join("cmake").join(&package.name);
+    register(&package, &config_dir)?;
+
+    eprintln!(
+        "{}",
+        format!(
+            "Published {} {} to {}",
+            package.name,
+            package.version,
+            prefix.display()
+        )
+        .green()
+    );
+    eprintln!(
+        "Use it with find_package({} {} REQUIRED) and target_link_libraries(<target> PRIVATE {}::{})",
+        package.name, package.version, package.name, package.targets[0]
+    );
+
+    Ok(())
+}
+
+/// Writes the package fragment and config template, returning the package
+fn write_package() -> Result<Package> {
+    let manifest = Manifest::load()?;
+    let name = cmake::project_name()?;
+    let mut targets = Vec::new();
+    if cmake::is_library_project()? {
+        targets.push(name.clone());
+    }
+    targets.extend(
+        manifest
+            .target
+            .iter()
+            .filter(|(target, config)| **target != name && config.kind != "executable")
+            .map(|(target, _)| target.clone()),
+    );
+    if targets.is_empty() {
+        anyhow::bail!(
+            "The project builds no libraries, packages export the library targets of \
+             CMakeLists.txt and the [target.<name>] tables"
+        );
+    }
+
+    // CMake versions are numeric, so pre-release and build suffixes such as
+    // `1.2.0-rc.1` are left out
+    let version = manifest
+        .package
+        .version
+        .as_deref()
+        .unwrap_or("0.0.0")
+        .split(['-', '+'])
+        .next()
+        .unwrap_or_default()
+        .to_string();
+    if version.is_empty()
+        || !version
+            .split('.')
+            .all(|part| !part.is_empty() && part.chars().all(|c| c.is_ascii_digit()))
+    {
+        anyhow::bail!(
+            "package.version '{}' isn't a version CMake understands, e.g. 1.2.0",
+            version
+        );
+    }
+
+    let package = Package {
+        name,
+        version,
+        targets,
+    };
+    let include_dir =
+        cmake::include_dir_in(Path::new(".")).unwrap_or_else(|| "include".to_string());
+    write_config_template(Path::new("."), &package)?;
+    cmake::write_fragment(PACKAGE_FRAGMENT, &fragment(&package, &include_dir))?;
+
+    Ok(package)
+}
+
+/// Writes the package files of a new library project, exporting its
+/// library at the version it starts out with
+pub fn write_new_package(name: &str, project_dir: &str, include_dir: &str) -> Result<()> {
+    let package = Package {
+        name: name.to_string(),
+        version: "0.1.0".to_string(),
+        targets: vec![name.to_string()],
+    };
+    let root_dir = Path::new(project_dir);
+    write_config_template(root_dir, &package)?;
+
+    cmake::write_fragment_in(
+        root_dir,
+        PACKAGE_FRAGMENT,
+        &fragment(&package, include_dir.trim_start_matches("./")),
+    )
+}
+
+/// The fragment installing the libraries, their headers and the package
+/// files. Targets declared after the fragment is included, e.g. by
+/// `targets.cmake`, don't exist yet when it runs, so the installation is
+/// deferred to the end of CMakeLists.txt.
+fn fragment(package: &Package, include_dir: &str) -> String {
+    let Package {
+        name,
+        version,
+        targets,
+    } = package;
+
+    format!(
+        "# {GENERATED_MARKER} add package, installs the libraries for find_package({name})
+
+include(GNUInstallDirs)
+include(CMakePackageConfigHelpers)
+
+function(cxx_install_package)
+  install(TARGETS {targets}
+    EXPORT {name}Targets
+    ARCHIVE DESTINATION ${{CMAKE_INSTALL_LIBDIR}}
+    LIBRARY DESTINATION ${{CMAKE_INSTALL_LIBDIR}}
+    RUNTIME DESTINATION ${{CMAKE_INSTALL_BINDIR}}
+    INCLUDES DESTINATION ${{CMAKE_INSTALL_INCLUDEDIR}})
+  install(DIRECTORY ${{CMAKE_CURRENT_SOURCE_DIR}}/{include_dir}/
+    DESTINATION ${{CMAKE_INSTALL_INCLUDEDIR}})
+  install(EXPORT {name}Targets
+    NAMESPACE {name}::
+    DESTINATION ${{CMAKE_INSTALL_LIBDIR}}/cmake/{name})
+
+  configure_package_config_file(
+    ${{CMAKE_CURRENT_SOURCE_DIR}}/{FRAGMENT_DIR}/{name}Config.cmake.in
+    ${{CMAKE_CURRENT_BINARY_DIR}}/{name}Config.cmake
+    INSTALL_DESTINATION ${{CMAKE_INSTALL_LIBDIR}}/cmake/{name})
+  write_basic_package_version_file(
+    ${{CMAKE_CURRENT_BINARY_DIR}}/{name}ConfigVersion.cmake
+    VERSION {version}
+    COMPATIBILITY SameMajorVersion)
+  install(FILES
+    ${{CMAKE_CURRENT_BINARY_DIR}}/{name}Config.cmake
+    ${{CMAKE_CURRENT_BINARY_DIR}}/{name}ConfigVersion.cmake
+    DESTINATION ${{CMAKE_INSTALL_LIBDIR}}/cmake/{name})
+endfunction()
+
+cmake_language(DEFER CALL cxx_install_package)
+",
+        targets = targets.join(" "),
+    )
+}
+
+/// Writes `<Name>Config.cmake.in`, which is meant to be edited, e.g. to find
+/// the packages the libraries link, so one that was changed is kept
+fn write_config_template(root_dir: &Path, package: &Package) -> Result<()> {
+    let path = root_dir
+        .join(FRAGMENT_DIR)
+        .join(format!("{}Config.cmake.in", package.name));
+    match fs::read_to_string(&path) {
+        Ok(existing) if !existing.contains(GENERATED_MARKER) => return Ok(()),
+        _ => {}
+    }
+
+    let name = &package.name;
+    fs::create_dir_all(root_dir.join(FRAGMENT_DIR))
+        .context("Failed to create CMake fragment directory")?;
+    fs::write(
+        &path,
+        format!(
+            "# {GENERATED_MARKER} add package, remove this line to keep changes
+@PACKAGE_INIT@
+
+include(CMakeFindDependencyMacro)
+# Find the packages the libraries link here, e.g. find_dependency(fmt)
+
+include(${{CMAKE_CURRENT_LIST_DIR}}/{name}Targets.cmake)
+check_required_components({name})
+"
+        ),
+    )
+    .with_context(|| format!("Failed to write {}", path.display()))
+}
+
+/// Where `publish local` installs packages,
+/// `$XDG_DATA_HOME/cpp_tools/packages` or `~/.local/share/cpp_tools/packages`
+fn packages_dir() -> Result<PathBuf> {
+    std::env::var_os("XDG_DATA_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| Path::new(&home).join(".local/share")))
+        .map(|dir| dir.join("cpp_tools").join("packages"))
+        .context("Neither XDG_DATA_HOME nor HOME is set")
+}
+
+/// Adds the installation to CMake's user package registry, the files in
+/// `~/.cmake/packages/<Name>` naming directories with package files. Each
+/// version gets its own entry so projects can ask for an older one.
+fn register(package: &Package, config_dir: &Path) -> Result<()> {
+    let registry = std::env::var_os("HOME")
+        .map(|home| Path::new(&home).join(".cmake/packages").join(&package.name))
+        .context("HOME isn't set, can't register the package")?;
+    let entry = registry.join(format!("{}-{}", package.name, package.version));
+    if executor().dry_run() {
+        eprintln!(
+            "Would register {} in {}",
+            config_dir.display(),
+            entry.display()
+        );
+        return Ok(());
+    }
+
+    fs::create_dir_all(&registry)
+        .with_context(|| format!("Failed to create {}", registry.display()))?;
+    fs::write(&entry, format!("{}\n", config_dir.display()))
+        .with_context(|| format!("Failed to register the package in {}", entry.display()))
+}
@@ -0,0 +1,264 @@
+use anyhow::{Context, Result};
+use colorize::AnsiColor;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use crate::{
+    cmake::{self, CMAKE_LISTS},
+    executor::executor,
+    manifest::Manifest,
+};
+
+/// Build type dependencies are installed for when the build directory has none
+const DEFAULT_BUILD_TYPE: &str = "Release";
+
+/// A package manager installing the project's dependencies before CMake is
+/// configured, leaving a toolchain file that lets `find_package` find them
+pub trait PackageManager {
+    /// Name on the command line, e.g. `conan`
+    fn name(&self) -> &'static str;
+
+    /// The file listing the dependencies, relative to the project root
+    fn manifest(&self) -> &'static str;
+
+    /// The files a new project starts with, relative to the project root
+    fn scaffold(&self, name: &str) -> Vec<(String, String)>;
+
+    /// Commands installing the dependencies into the build directory for a
+    /// CMake build type, building those without a binary package
+    fn install_commands(
+        &self,
+        root_dir: &Path,
+        build_dir: &Path,
+        build_type: &str,
+        cxx_standard: Option<&str>,
+    ) -> Vec<Command>;
+
+    /// The toolchain file the install leaves in the build directory
+    fn toolchain_file(&self, build_dir: &Path) -> PathBuf;
+}
+
+/// Conan 2, with the CMakeDeps and CMakeToolchain generators writing into
+/// `<BUILD_DIR>/conan/`
+struct Conan {
+    manifest: &'static str,
+}
+
+impl PackageManager for Conan {
+    fn name(&self) -> &'static str {
+        "conan"
+    }
+
+    fn manifest(&self) -> &'static str {
+        self.manifest
+    }
+
+    fn scaffold(&self, name: &str) -> Vec<(String, String)> {
+        let conanfile = format!(
+            "[requires]
+# Dependencies of {name} from ConanCenter, e.g. fmt/11.0.2, found in
+# CMakeLists.txt with find_package(fmt) and linked as fmt::fmt
+
+[generators]
+CMakeDeps
+CMakeToolchain
+"
+        );
+
+        vec![(self.manifest.to_string(), conanfile)]
+    }
+
+    fn install_commands(
+        &self,
+        root_dir: &Path,
+        build_dir: &Path,
+        build_type: &str,
+        cxx_standard: Option<&str>,
+    ) -> Vec<Command> {
+        let mut commands = Vec::new();
+        // A first install detects the default profile from the compiler
+        let has_profile = executor()
+            .capture(Command::new("conan").args(["profile", "path", "default"]))
+            .is_ok_and(|output| output.status.success());
+        if !has_profile {
+            let mut detect = Command::new("conan");
+            detect.args(["profile", "detect"]);
+            commands.push(detect);
+        }
+
+        let mut install = Command::new("conan");
+        install
+            .arg("install")
+            .arg(root_dir)
+            .arg(format!(
+                "--output-folder={}",
+                build_dir.join("conan").display()
+            ))
+            .arg("--build=missing")
+            .args(["-s", &format!("build_type={}", build_type)]);
+        if let Some(standard) = cxx_standard {
+            install.args(["-s", &format!("compiler.cppstd={}", standard)]);
+        }
+        commands.push(install);
+
+        commands
+    }
+
+    fn toolchain_file(&self, build_dir: &Path) -> PathBuf {
+        build_dir.join("conan").join("conan_toolchain.cmake")
+    }
+}
+
+/// The package manager named on the command line
+pub fn parse(name: &str) -> Result<Box<dyn PackageManager>> {
+    match name.to_ascii_lowercase().as_str() {
+        "conan" => Ok(Box::new(Conan {
+            manifest: "conanfile.txt",
+        })),
+        _ => anyhow::bail!("Valid package managers are 'conan'"),
+    }
+}
+
+/// The package manager of the project, by the file listing its dependencies
+pub fn detect(root_dir: &Path) -> Option<Box<dyn PackageManager>> {
+    ["conanfile.py", "conanfile.txt"]
+        .into_iter()
+        .find(|manifest| root_dir.join(manifest).exists())
+        .map(|manifest| Box::new(Conan { manifest }) as Box<dyn PackageManager>)
+}
+
+/// Writes the package manager's files into a new project
+pub fn scaffold(manager: &dyn PackageManager, name: &str, project_dir: &str) -> Result<()> {
+    for (path, contents) in manager.scaffold(name) {
+        let path = Path::new(project_dir).join(path);
+        fs::write(&path, contents)
+            .with_context(|| format!("Failed to write {}", path.display()))?;
+    }
+
+    Ok(())
+}
+
+/// Installs the project's dependencies for the build type about to be
+/// configured, returning the cache variables that make CMake find them.
+/// Without a build type the dependencies are installed for Release, which
+/// the build directory is configured with too, as the imported targets are
+/// empty in a build type they weren't installed for.
+pub fn install(
+    root_dir: &Path,
+    build_dir: &Path,
+    build_type: Option<&str>,
+) -> Result<Vec<(String, String)>> {
+    let Some(manager) = detect(root_dir) else {
+        return Ok(Vec::new());
+    };
+
+    let mut variables = Vec::new();
+    let build_type = match build_type.filter(|build_type| !build_type.is_empty()) {
+        Some(build_type) => build_type,
+        None => {
+            variables.push((
+                "CMAKE_BUILD_TYPE".to_string(),
+                DEFAULT_BUILD_TYPE.to_string(),
+            ));
+            DEFAULT_BUILD_TYPE
+        }
+    };
+    let commands = manager.install_commands(
+        root_dir,
+        build_dir,
+        build_type,
+        cxx_standard(root_dir).as_deref(),
+    );
+    for mut command in commands {
+        executor().execute(&mut command).with_context(|| {
+            format!(
+                "Failed to install the dependencies in {} with {}",
+                manager.manifest(),
+                manager.name()
+            )
+        })?;
+    }
+
+    let toolchain = manager.toolchain_file(build_dir);
+    let toolchain = fs::canonicalize(&toolchain).unwrap_or(toolchain);
+    variables.push((
+        "CMAKE_TOOLCHAIN_FILE".to_string(),
+        toolchain.display().to_string(),
+    ));
+
+    Ok(variables)
+}
+
+/// Reinstalls the dependencies of a configured build directory when their
+/// list changed, before `build`. CMake reconfigures by itself once the files
+/// it found them through change. A build directory configured before the
+/// project had dependencies has no toolchain file, which only a fresh
+/// configure sets.
+pub fn refresh(build_dir: &Path) -> Result<()> {
+    let root_dir = Path::new(".");
+    if cmake::cache_value(build_dir, "CMAKE_HOME_DIRECTORY").is_none()
+        || !needs_install(root_dir, build_dir)
+    {
+        return Ok(());
+    }
+
+    let build_type = cmake::cache_value(build_dir, "CMAKE_BUILD_TYPE");
+    let variables = install(root_dir, build_dir, build_type.as_deref())?;
+    let toolchain = cmake::cache_value(build_dir, "CMAKE_TOOLCHAIN_FILE");
+    let configured = variables.iter().all(|(variable, value)| {
+        variable != "CMAKE_TOOLCHAIN_FILE" || Some(value) == toolchain.as_ref()
+    });
+    if !configured && !executor().dry_run() {
+        eprintln!(
+            "{}",
+            format!(
+                "'{}' isn't configured with the dependencies' toolchain, reconfigure it with 'cxx build --fresh'",
+                build_dir.display()
+            )
+            .yellow()
+        );
+    }
+
+    Ok(())
+}
+
+/// Whether the dependencies were never installed into the build directory
+/// or their list changed since
+fn needs_install(root_dir: &Path, build_dir: &Path) -> bool {
+    let Some(manager) = detect(root_dir) else {
+        return false;
+    };
+    let modified = |path: &Path| fs::metadata(path).and_then(|metadata| metadata.modified());
+
+    match (
+        modified(&root_dir.join(manager.manifest())),
+        modified(&manager.toolchain_file(build_dir)),
+    ) {
+        (Ok(listed), Ok(installed)) => listed > installed,
+        _ => true,
+    }
+}
+
+/// The C++ standard of the manifest or else the one set in CMakeLists.txt,
+/// which dependencies are built with rather than the Conan profile's
+fn cxx_standard(root_dir: &Path) -> Option<String> {
+    if let Some((variable, version)) = Manifest::load_from(root_dir)
+        .ok()
+        .and_then(|manifest| manifest.standard_variable())
+    {
+        return (variable == "CMAKE_CXX_STANDARD").then_some(version);
+    }
+
+    fs::read_to_string(root_dir.join(CMAKE_LISTS))
+        .ok()?
+        .lines()
+        .find_map(|line| {
+            line.trim()
+                .strip_prefix("set(CMAKE_CXX_STANDARD ")?
+                .strip_suffix(')')
+                .map(|version| version.trim().to_string())
+        })
+}
@@ -0,0 +1,394 @@
+use anyhow::{Context, Result};
+use colorize::AnsiColor;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use crate::{
+    cmake, default_exec_name, executor::executor, manifest::STATE_DIR, run_command, toolchain,
+};
+
+/// Directory of the training profiles, relative to the state directory,
+/// with one directory per commit and compiler
+const PGO_DIR: &str = "pgo";
+
+/// The merged profile clang optimizes with
+const PROFDATA_FILE: &str = "merged.profdata";
+
+/// The branch profile BOLT lays the executable out with
+const BOLT_PROFILE_FILE: &str = "bolt.fdata";
+
+#[derive(Clone, Copy, PartialEq)]
+enum Compiler {
+    Clang,
+    Gcc,
+}
+
+impl Compiler {
+    fn name(self) -> &'static str {
+        match self {
+            Compiler::Clang => "clang",
+            Compiler::Gcc => "gcc",
+        }
+    }
+
+    /// Flags making the build in `build_dir` write its profiles into
+    /// `raw_dir`
+    fn generate_flags(self, raw_dir: &Path, build_dir: &Path) -> String {
+        format!(
+            "-fprofile-generate={}{}",
+            raw_dir.display(),
+            self.prefix_flag(build_dir)
+        )
+    }
+
+    /// Flags optimizing the build in `build_dir` with the profiles of
+    /// `profile_dir`. Functions the training didn't reach, or that changed
+    /// since, aren't worth a warning each.
+    fn use_flags(self, profile_dir: &Path, build_dir: &Path) -> String {
+        match self {
+            Compiler::Clang => format!(
+                "-fprofile-use={} -Wno-profile-instr-unprofiled -Wno-profile-instr-out-of-date",
+                profile_dir.join(PROFDATA_FILE).display()
+            ),
+            Compiler::Gcc => format!(
+                "-fprofile-use={} -fprofile-correction -Wno-missing-profile{}",
+                profile_dir.join("raw").display(),
+                self.prefix_flag(build_dir)
+            ),
+        }
+    }
+
+    /// GCC names the profiles after the object files, which are in another
+    /// build directory when optimizing, so the directory is left out
+    fn prefix_flag(self, build_dir: &Path) -> String {
+        match self {
+            Compiler::Clang => String::new(),
+            Compiler::Gcc => format!(" -fprofile-prefix-path={}", build_dir.display()),
+        }
+    }
+}
+
+/// Builds the project with profile-guided optimization. An instrumented
+/// release build in `<BUILD_DIR>-pgo-instrumented` runs `train`, or the
+/// tests when it's not given, its profiles are merged, and the release
+/// build in `<BUILD_DIR>-pgo` is optimized with them. The profiles are kept
+/// per commit, so later runs on the same commit only rebuild unless
+/// `retrain` is set. With `bolt` the optimized executable is then sampled
+/// with perf while `train` runs again and rewritten by BOLT, on Linux.
+pub fn handle_pgo(
+    build_dir: String,
+    runtime_dir: String,
+    train: Option<String>,
+    retrain: bool,
+    bolt: bool,
+    jobs: Option<usize>,
+) -> Result<()> {
+    if bolt {
+        if cmake::is_library_project()? {
+            anyhow::bail!(
+                "BOLT rewrites executables, libraries can only use profile-guided optimization"
+            );
+        }
+        let problems = bolt_problems();
+        if !problems.is_empty() {
+            anyhow::bail!(
+                "BOLT can't run here:\n{}",
+                problems
+                    .iter()
+                    .map(|problem| format!("  - {}", problem))
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            );
+        }
+    }
+
+    let compiler = compiler()?;
+    let (commit, label) = match head_commit() {
+        Some(commit) => (commit.clone(), commit[..commit.len().min(10)].to_string()),
+        None => ("uncommitted".to_string(), "the working tree".to_string()),
+    };
+    let root = std::env::current_dir().context("Failed to get current directory")?;
+    let profile_dir = root
+        .join(STATE_DIR)
+        .join(PGO_DIR)
+        .join(&commit)
+        .join(compiler.name());
+
+    let trained = retrain || !is_trained(compiler, &profile_dir);
+    if trained {
+        let dir = format!("{}-pgo-instrumented", build_dir);
+        let raw_dir = profile_dir.join("raw");
+        if raw_dir.exists() && !executor().dry_run() {
+            fs::remove_dir_all(&raw_dir)
+                .with_context(|| format!("Failed to clear {}", raw_dir.display()))?;
+        }
+        let flags = compiler.generate_flags(&raw_dir, &root.join(&dir));
+        build(&dir, &flags, &flags, jobs).context("Failed to build the instrumented build")?;
+
+        // Profiles are written wherever the instrumented build runs from,
+        // so the training can run the project's executable or its tests
+        match &train {
+            Some(train) => run_command(train).context("Training command failed")?,
+            None => executor()
+                .execute(
+                    Command::new("ctest")
+                        .args(["--test-dir", &dir])
+                        .arg("--output-on-failure"),
+                )
+                .context("Tests failed in the instrumented build")?,
+        }
+        if !executor().dry_run() && profiles(&raw_dir).is_empty() {
+            anyhow::bail!(
+                "Training wrote no profiles to {}, does the training command run the instrumented build?",
+                raw_dir.display()
+            );
+        }
+
+        if compiler == Compiler::Clang {
+            let Some(profdata) = profdata() else {
+                anyhow::bail!("Merging clang profiles requires llvm-profdata");
+            };
+            executor()
+                .execute(
+                    Command::new(profdata)
+                        .arg("merge")
+                        .arg(format!(
+                            "-output={}",
+                            profile_dir.join(PROFDATA_FILE).display()
+                        ))
+                        .args(profiles(&raw_dir)),
+                )
+                .context("Failed to merge the profiles")?;
+        }
+        eprintln!("{}", format!("Trained profiles for {}", label).green());
+    } else {
+        eprintln!(
+            "Reusing the profiles of {}, pass --retrain to train again",
+            label
+        );
+    }
+
+    let dir = format!("{}-pgo", build_dir);
+    let flags = compiler.use_flags(&profile_dir, &root.join(&dir));
+    // BOLT needs the relocations to move code around
+    let link_flags = match bolt {
+        true => format!("{} -Wl,--emit-relocs", flags),
+        false => flags.clone(),
+    };
+    build(&dir, &flags, &link_flags, jobs).context("Failed to build with the profiles")?;
+    eprintln!(
+        "{}",
+        format!("Built {} optimized with the profiles", dir).green()
+    );
+
+    if let Some(train) = train.filter(|_| bolt) {
+        let executable =
+            Path::new(&runtime_dir).join(toolchain::executable(&dir, &default_exec_name()));
+        // A retrained build lays its code out differently, so the branch
+        // profile of the previous one doesn't apply anymore
+        optimize_layout(&executable, &train, &profile_dir, trained)?;
+    }
+
+    Ok(())
+}
+
+/// Samples the branches the executable takes while `train` runs, with the
+/// CPU's last branch records, and rewrites it with BOLT so the hot code is
+/// packed together
+fn optimize_layout(
+    executable: &Path,
+    train: &str,
+    profile_dir: &Path,
+    retrain: bool,
+) -> Result<()> {
+    let executable = executable.display().to_string();
+    let fdata = profile_dir.join(BOLT_PROFILE_FILE);
+    if retrain || !fdata.is_file() {
+        let perf_data = profile_dir.join("perf.data");
+        // The training command is a command line, and perf only runs on Linux
+        executor()
+            .execute(
+                Command::new("perf")
+                    .args(["record", "-e", "cycles:u", "-j", "any,u", "-o"])
+                    .arg(&perf_data)
+                    .args(["--", "sh", "-c", train]),
+            )
+            .context("Training command failed under perf")?;
+        executor()
+            .execute(
+                Command::new("perf2bolt")
+                    .arg("-p")
+                    .arg(&perf_data)
+                    .arg("-o")
+                    .arg(&fdata)
+                    .arg(&executable),
+            )
+            .context("Failed to convert the perf profile for BOLT")?;
+    } else {
+        eprintln!("Reusing the BOLT profile, pass --retrain to sample again");
+    }
+
+    let output = format!("{}.bolt", executable);
+    executor()
+        .execute(
+            Command::new("llvm-bolt")
+                .arg(&executable)
+                .args(["-o", &output])
+                .arg(format!("-data={}", fdata.display()))
+                .args([
+                    "-reorder-blocks=ext-tsp",
+                    "-reorder-functions=hfsort",
+                    "-split-functions",
+                    "-split-all-cold",
+                    "-split-eh",
+                    "-dyno-stats",
+                ]),
+        )
+        .context("BOLT failed to rewrite the executable")?;
+    if !executor().dry_run() {
+        fs::rename(&output, &executable)
+            .with_context(|| format!("Failed to replace {}", executable))?;
+    }
+    eprintln!("{}", format!("Rewrote {} with BOLT", executable).green());
+
+    Ok(())
+}
+
+/// What keeps BOLT from running on this machine, empty when nothing does
+fn bolt_problems() -> Vec<String> {
+    if !cfg!(target_os = "linux") {
+        return vec!["BOLT only runs on Linux".to_string()];
+    }
+
+    let mut problems = Vec::new();
+    for tool in ["perf", "perf2bolt", "llvm-bolt"] {
+        if !has_tool(tool) {
+            problems.push(format!("{} isn't installed", tool));
+        }
+    }
+    if !Path::new("/sys/bus/event_source/devices/cpu/caps/branches").exists() {
+        problems.push("The CPU doesn't provide last branch records (LBR) to perf".to_string());
+    }
+    let paranoid = fs::read_to_string("/proc/sys/kernel/perf_event_paranoid")
+        .ok()
+        .and_then(|value| value.trim().parse::<i32>().ok());
+    if let Some(paranoid) = paranoid.filter(|paranoid| *paranoid > 2) {
+        problems.push(format!(
+            "kernel.perf_event_paranoid is {}, set it to 2 or lower to let perf sample",
+            paranoid
+        ));
+    }
+
+    problems
+}
+
+/// Configures and builds a release build with extra compile and link flags
+fn build(dir: &str, flags: &str, link_flags: &str, jobs: Option<usize>) -> Result<()> {
+    executor().execute(&mut cmake::configure_command(
+        dir,
+        &[
+            ("CMAKE_BUILD_TYPE", "Release"),
+            ("CMAKE_C_FLAGS", flags),
+            ("CMAKE_CXX_FLAGS", flags),
+            ("CMAKE_EXE_LINKER_FLAGS", link_flags),
+            ("CMAKE_SHARED_LINKER_FLAGS", link_flags),
+        ],
+    ))?;
+
+    executor().execute(&mut cmake::build_command(dir, Some("Release"), jobs))
+}
+
+/// Whether the profiles of a commit were trained and merged before
+fn is_trained(compiler: Compiler, profile_dir: &Path) -> bool {
+    match compiler {
+        Compiler::Clang => profile_dir.join(PROFDATA_FILE).is_file(),
+        Compiler::Gcc => !profiles(&profile_dir.join("raw")).is_empty(),
+    }
+}
+
+/// The raw profiles a training run wrote, `.profraw` files for clang and
+/// `.gcda` files for GCC
+fn profiles(raw_dir: &Path) -> Vec<PathBuf> {
+    let mut profiles = Vec::new();
+    let mut dirs = vec![raw_dir.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        let Ok(entries) = fs::read_dir(&dir) else {
+            continue;
+        };
+        for path in entries
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+        {
+            if path.is_dir() {
+                dirs.push(path);
+            } else if path
+                .extension()
+                .is_some_and(|extension| extension == "profraw" || extension == "gcda")
+            {
+                profiles.push(path);
+            }
+        }
+    }
+
+    profiles
+}
+
+/// The compiler CMake picks up, as `CXX` or `CC` name it or the system's
+/// default otherwise
+fn compiler() -> Result<Compiler> {
+    let (variable, default) = if cmake::is_c_project()? {
+        ("CC", "cc")
+    } else {
+        ("CXX", "c++")
+    };
+    let compiler = std::env::var(variable).unwrap_or_else(|_| default.to_string());
+    let output = executor()
+        .capture(Command::new(&compiler).arg("--version"))
+        .with_context(|| format!("Failed to run {}", compiler))?;
+    let version = String::from_utf8_lossy(&output.stdout);
+    if version.contains("clang") {
+        Ok(Compiler::Clang)
+    } else if version.contains("GCC") || version.contains("Free Software Foundation") {
+        Ok(Compiler::Gcc)
+    } else {
+        anyhow::bail!(
+            "Profile-guided optimization requires clang or GCC, {} is neither",
+            compiler
+        )
+    }
+}
+
+/// llvm-profdata, which Xcode only provides through xcrun
+fn profdata() -> Option<String> {
+    if has_tool("llvm-profdata") {
+        Some("llvm-profdata".to_string())
+    } else if cfg!(target_os = "macos")
+        && executor()
+            .capture(Command::new("xcrun").args(["--find", "llvm-profdata"]))
+            .is_ok_and(|output| output.status.success())
+    {
+        Some("xcrun llvm-profdata".to_string())
+    } else {
+        None
+    }
+}
+
+fn head_commit() -> Option<String> {
+    let output = executor()
+        .capture(Command::new("git").args(["rev-parse", "HEAD"]))
+        .ok()?;
+
+    output
+        .status
+        .success()
+        .then(|| String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+fn has_tool(tool: &str) -> bool {
+    executor()
+        .capture(Command::new(tool).arg("--version"))
+        .is_ok()
+}
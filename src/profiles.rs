@@ -0,0 +1,270 @@
+use anyhow::{Context, Result};
+use colorize::AnsiColor;
+use serde::Serialize;
+use std::{collections::BTreeMap, fs, path::Path};
+
+use crate::{config::suggest, hardening, manifest::Manifest};
+
+/// The profiles every project has, with the CMake build types they select
+const BUILTIN_PROFILES: [(&str, &str); 4] = [
+    ("debug", "Debug"),
+    ("release", "Release"),
+    ("relwithdebinfo", "RelWithDebInfo"),
+    ("minsizerel", "MinSizeRel"),
+];
+
+/// CMake presets generated from the profiles, for IDEs and plain `cmake --preset`
+pub const PRESETS_FILE: &str = "CMakePresets.json";
+
+/// A profile with its inheritance chain applied
+pub struct Profile {
+    pub name: String,
+    pub build_type: String,
+    pub sanitizers: Vec<String>,
+    pub flags: Vec<String>,
+    pub link_flags: Vec<String>,
+    pub cache: BTreeMap<String, String>,
+    pub hardened: bool,
+}
+
+impl Profile {
+    fn builtin(name: &str) -> Option<Profile> {
+        BUILTIN_PROFILES
+            .iter()
+            .find(|(builtin, _)| builtin.eq_ignore_ascii_case(name))
+            .map(|(name, build_type)| Profile {
+                name: name.to_string(),
+                build_type: build_type.to_string(),
+                sanitizers: Vec::new(),
+                flags: Vec::new(),
+                link_flags: Vec::new(),
+                cache: BTreeMap::new(),
+                hardened: false,
+            })
+    }
+
+    /// The cache variables that configure a build directory for this
+    /// profile. Flags are always set, so switching to a profile without any
+    /// clears those of the previous one, and so is hardening. `msvc` spells sanitizers the way cl
+    /// and clang-cl expect them.
+    pub fn cache_variables(&self, msvc: bool) -> BTreeMap<String, String> {
+        let mut flags = self.flags.clone();
+        let mut link_flags = self.link_flags.clone();
+        if !self.sanitizers.is_empty() && msvc {
+            // The MSVC linker picks up the sanitizer runtime by itself
+            flags.push(format!("/fsanitize={}", self.sanitizers.join(",")));
+        } else if !self.sanitizers.is_empty() {
+            let sanitize = format!("-fsanitize={}", self.sanitizers.join(","));
+            flags.push(sanitize.clone());
+            flags.push("-fno-omit-frame-pointer".to_string());
+            link_flags.push(sanitize);
+        }
+
+        let mut variables = self.cache.clone();
+        variables.insert("CMAKE_BUILD_TYPE".to_string(), self.build_type.clone());
+        variables.insert(
+            hardening::CACHE_VARIABLE.to_string(),
+            if self.hardened { "ON" } else { "OFF" }.to_string(),
+        );
+        for variable in ["CMAKE_C_FLAGS", "CMAKE_CXX_FLAGS"] {
+            variables.insert(variable.to_string(), flags.join(" "));
+        }
+        for variable in ["CMAKE_EXE_LINKER_FLAGS", "CMAKE_SHARED_LINKER_FLAGS"] {
+            variables.insert(variable.to_string(), link_flags.join(" "));
+        }
+
+        variables
+    }
+}
+
+/// Resolves a built-in or manifest profile
+pub fn resolve(manifest: &Manifest, name: &str) -> Result<Profile> {
+    let mut profile = resolve_chain(manifest, name, &mut Vec::new())?;
+    // The manifest's flags come first, so a profile's flags can override them
+    profile
+        .flags
+        .splice(0..0, manifest.build.flags.iter().cloned());
+    profile
+        .link_flags
+        .splice(0..0, manifest.build.link_flags.iter().cloned());
+
+    Ok(profile)
+}
+
+fn resolve_chain(manifest: &Manifest, name: &str, chain: &mut Vec<String>) -> Result<Profile> {
+    if chain.iter().any(|seen| seen == name) {
+        chain.push(name.to_string());
+        anyhow::bail!("Profile inheritance forms a cycle: {}", chain.join(" -> "));
+    }
+    chain.push(name.to_string());
+
+    let Some(config) = manifest.profile.get(name) else {
+        return Profile::builtin(name).ok_or_else(|| unknown_profile(manifest, name));
+    };
+
+    let mut profile = match &config.inherits {
+        Some(parent) => resolve_chain(manifest, parent, chain)?,
+        None => Profile::builtin(name).ok_or_else(|| {
+            anyhow::anyhow!(
+                "Profile '{}' must set 'inherits', e.g. inherits = \"debug\"",
+                name
+            )
+        })?,
+    };
+
+    profile.name = name.to_string();
+    if let Some(build_type) = &config.build_type {
+        profile.build_type = Profile::builtin(build_type)
+            .map(|builtin| builtin.build_type)
+            .ok_or_else(|| {
+                anyhow::anyhow!(
+                    "Invalid build type '{}' in profile '{}', valid build types are 'debug', 'release', 'relwithdebinfo' and 'minsizerel'",
+                    build_type,
+                    name
+                )
+            })?;
+    }
+    profile.hardened |= config.hardened;
+    profile.sanitizers.extend(config.sanitizers.iter().cloned());
+    profile.flags.extend(config.flags.iter().cloned());
+    profile.link_flags.extend(config.link_flags.iter().cloned());
+    profile.cache.extend(
+        config
+            .cache
+            .iter()
+            .map(|(key, value)| (key.clone(), value.clone())),
+    );
+
+    Ok(profile)
+}
+
+fn unknown_profile(manifest: &Manifest, name: &str) -> anyhow::Error {
+    let names = profile_names(manifest);
+    let candidates = names.iter().map(String::as_str).collect::<Vec<_>>();
+
+    match suggest(name, &candidates) {
+        Some(suggestion) => {
+            anyhow::anyhow!("Unknown profile '{}', did you mean '{}'?", name, suggestion)
+        }
+        None => anyhow::anyhow!(
+            "Unknown profile '{}', available profiles: {}",
+            name,
+            names.join(", ")
+        ),
+    }
+}
+
+/// Built-in profiles followed by the ones the manifest adds
+fn profile_names(manifest: &Manifest) -> Vec<String> {
+    let mut names = BUILTIN_PROFILES
+        .iter()
+        .map(|(name, _)| name.to_string())
+        .collect::<Vec<_>>();
+    for name in manifest.profile.keys() {
+        if !names.contains(name) {
+            names.push(name.clone());
+        }
+    }
+
+    names
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Presets {
+    version: u32,
+    cmake_minimum_required: CMakeVersion,
+    configure_presets: Vec<ConfigurePreset>,
+    build_presets: Vec<BuildPreset>,
+    vendor: BTreeMap<String, BTreeMap<String, bool>>,
+}
+
+#[derive(Serialize)]
+struct CMakeVersion {
+    major: u32,
+    minor: u32,
+    patch: u32,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ConfigurePreset {
+    name: String,
+    display_name: String,
+    binary_dir: String,
+    cache_variables: BTreeMap<String, String>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct BuildPreset {
+    name: String,
+    configure_preset: String,
+}
+
+/// Writes a configure and build preset for every profile when the manifest
+/// defines custom ones. A presets file that cxx didn't generate is left alone.
+pub fn write_presets(root_dir: &Path, manifest: &Manifest, msvc: bool) -> Result<()> {
+    if manifest.profile.is_empty() {
+        return Ok(());
+    }
+
+    let path = root_dir.join(PRESETS_FILE);
+    let existing = fs::read_to_string(&path).ok();
+    if existing
+        .as_deref()
+        .is_some_and(|existing| !existing.contains("\"cpp_tools\""))
+    {
+        eprintln!(
+            "{}",
+            format!(
+                "{} wasn't generated by cxx, not updating it with the manifest profiles",
+                PRESETS_FILE
+            )
+            .yellow()
+        );
+        return Ok(());
+    }
+
+    let mut configure_presets = Vec::new();
+    let mut build_presets = Vec::new();
+    for name in profile_names(manifest) {
+        let profile = resolve(manifest, &name)?;
+        if profile.hardened {
+            hardening::write_fragment(root_dir)?;
+        }
+        configure_presets.push(ConfigurePreset {
+            display_name: format!("{} ({})", profile.name, profile.build_type),
+            binary_dir: format!("${{sourceDir}}/build/{}", profile.name),
+            cache_variables: profile.cache_variables(msvc),
+            name: profile.name.clone(),
+        });
+        build_presets.push(BuildPreset {
+            configure_preset: profile.name.clone(),
+            name: profile.name,
+        });
+    }
+
+    let presets = Presets {
+        version: 3,
+        cmake_minimum_required: CMakeVersion {
+            major: 3,
+            minor: 21,
+            patch: 0,
+        },
+        configure_presets,
+        build_presets,
+        vendor: BTreeMap::from([(
+            "cpp_tools".to_string(),
+            BTreeMap::from([("generated".to_string(), true)]),
+        )]),
+    };
+    let contents =
+        serde_json::to_string_pretty(&presets).context("Failed to serialize presets")? + "\n";
+
+    if existing.as_deref() != Some(contents.as_str()) {
+        fs::write(&path, contents).with_context(|| format!("Failed to write {}", PRESETS_FILE))?;
+    }
+
+    Ok(())
+}
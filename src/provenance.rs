@@ -0,0 +1,180 @@
+use anyhow::{Context, Result};
+use serde::Serialize;
+use std::{collections::BTreeMap, fs, path::Path, process::Command};
+
+use crate::{
+    build_info::build_timestamp, cmake, executor::executor, fileapi, manifest::Manifest,
+    upgrade::sha256,
+};
+
+/// The provenance manifest of the last release build, in its build directory
+pub const PROVENANCE_FILE: &str = "provenance.json";
+
+/// The build types whose builds get a provenance manifest
+const RELEASE_BUILD_TYPES: [&str; 3] = ["Release", "RelWithDebInfo", "MinSizeRel"];
+
+/// What a release build produced and what produced it, for supply-chain
+/// attestations and for telling whether two builds are the same
+#[derive(Serialize)]
+pub struct Provenance {
+    project: String,
+    version: Option<String>,
+    /// The commit built, and whether the working tree had changes on top
+    commit: Option<String>,
+    dirty: bool,
+    build_type: String,
+    generator: Option<String>,
+    /// Honors SOURCE_DATE_EPOCH, like the build info header
+    built_at: String,
+    /// The compilers and CMake, by `c`, `cxx` and `cmake`
+    toolchain: BTreeMap<String, Tool>,
+    /// The flags of the build type, by `c`, `cxx`, `exe_linker` and
+    /// `shared_linker`
+    flags: BTreeMap<String, String>,
+    artifacts: Vec<Checksum>,
+}
+
+#[derive(Serialize)]
+struct Tool {
+    path: String,
+    /// The first line of `--version`
+    version: String,
+}
+
+#[derive(Serialize)]
+struct Checksum {
+    /// Relative to the project root unless it's outside of it
+    path: String,
+    target: String,
+    sha256: String,
+    size: u64,
+}
+
+impl Provenance {
+    pub fn write(&self, build_dir: &Path) -> Result<()> {
+        let json =
+            serde_json::to_string_pretty(self).context("Failed to serialize the provenance")?;
+        let path = build_dir.join(PROVENANCE_FILE);
+
+        fs::write(&path, json + "\n").with_context(|| format!("Failed to write {}", path.display()))
+    }
+}
+
+/// Whether the build directory is configured for one of the release build
+/// types
+fn is_release(build_dir: &Path) -> bool {
+    cmake::cache_value(build_dir, "CMAKE_BUILD_TYPE").is_some_and(|build_type| {
+        RELEASE_BUILD_TYPES
+            .iter()
+            .any(|release| release.eq_ignore_ascii_case(&build_type))
+    })
+}
+
+/// Describes the artifacts a configured build directory built, with their
+/// checksums, the toolchain and the flags they were built with
+pub fn collect(build_dir: &Path) -> Result<Provenance> {
+    let manifest = Manifest::load()?;
+    let build_type = cmake::cache_value(build_dir, "CMAKE_BUILD_TYPE").unwrap_or_default();
+    let cache =
+        |key: &str| cmake::cache_value(build_dir, key).filter(|value| !value.trim().is_empty());
+
+    let mut toolchain = BTreeMap::new();
+    for (name, key) in [
+        ("c", "CMAKE_C_COMPILER"),
+        ("cxx", "CMAKE_CXX_COMPILER"),
+        ("cmake", "CMAKE_COMMAND"),
+    ] {
+        if let Some(path) = cache(key) {
+            toolchain.insert(
+                name.to_string(),
+                Tool {
+                    version: version(&path).unwrap_or_default(),
+                    path,
+                },
+            );
+        }
+    }
+
+    // The flags of every build type are followed by those of this one
+    let suffix = build_type.to_ascii_uppercase();
+    let mut flags = BTreeMap::new();
+    for (name, key) in [
+        ("c", "CMAKE_C_FLAGS"),
+        ("cxx", "CMAKE_CXX_FLAGS"),
+        ("exe_linker", "CMAKE_EXE_LINKER_FLAGS"),
+        ("shared_linker", "CMAKE_SHARED_LINKER_FLAGS"),
+    ] {
+        let value = [cache(key), cache(&format!("{}_{}", key, suffix))]
+            .into_iter()
+            .flatten()
+            .map(|value| value.trim().to_string())
+            .collect::<Vec<_>>()
+            .join(" ");
+        flags.insert(name.to_string(), value);
+    }
+
+    let root = std::env::current_dir().context("Failed to get current directory")?;
+    let mut artifacts = Vec::new();
+    for (target, path) in fileapi::artifacts(build_dir)? {
+        let path = build_dir.join(path);
+        let Ok(metadata) = fs::metadata(&path) else {
+            // Targets left out of the default build, e.g. examples
+            continue;
+        };
+        let shown = fs::canonicalize(&path)
+            .ok()
+            .and_then(|path| path.strip_prefix(&root).ok().map(Path::to_path_buf))
+            .unwrap_or_else(|| path.clone());
+        artifacts.push(Checksum {
+            path: shown.display().to_string(),
+            target,
+            sha256: sha256(&path)?,
+            size: metadata.len(),
+        });
+    }
+    artifacts.sort_by(|a, b| a.path.cmp(&b.path));
+
+    Ok(Provenance {
+        project: cmake::project_name()?,
+        version: manifest.package.version,
+        commit: git(&["rev-parse", "HEAD"]),
+        dirty: git(&["status", "--porcelain"]).is_some_and(|status| !status.is_empty()),
+        build_type,
+        generator: cache("CMAKE_GENERATOR"),
+        built_at: build_timestamp()?,
+        toolchain,
+        flags,
+        artifacts,
+    })
+}
+
+/// Writes the provenance manifest of a release build into its build
+/// directory, after every build in it
+pub fn record(build_dir: &Path) -> Result<()> {
+    if !is_release(build_dir) || executor().dry_run() {
+        return Ok(());
+    }
+
+    collect(build_dir)?.write(build_dir)
+}
+
+fn version(tool: &str) -> Option<String> {
+    let output = executor()
+        .capture(Command::new(tool).arg("--version"))
+        .ok()?;
+    let text = String::from_utf8_lossy(&output.stdout);
+
+    text.lines()
+        .next()
+        .map(|line| line.trim().to_string())
+        .filter(|line| !line.is_empty())
+}
+
+fn git(args: &[&str]) -> Option<String> {
+    let output = executor().capture(Command::new("git").args(args)).ok()?;
+
+    output
+        .status
+        .success()
+        .then(|| String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
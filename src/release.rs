@@ -0,0 +1,327 @@
+use anyhow::{Context, Result};
+use colorize::AnsiColor;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use crate::{
+    assets, cmake, default_exec_name,
+    executor::executor,
+    fileapi,
+    manifest::{AssetsConfig, Manifest},
+    provenance, sign, toolchain,
+    upgrade::sha256,
+};
+
+/// The checksums uploaded along with the artifacts, in `sha256sum` format
+const CHECKSUMS_FILE: &str = "SHA256SUMS";
+
+/// Creates the GitHub release `tag` with the project's artifacts: built in
+/// release for every platform of the manifest's `[publish] targets`, or the
+/// files of `artifacts` when they were built elsewhere, e.g. by CI. The
+/// artifacts are uploaded with their checksums, and the release notes are
+/// the tag's section of the changelog. Each build's provenance manifest is
+/// uploaded next to its archive. With `sign`, every file is signed with
+/// cosign and its signature bundle uploaded too.
+pub fn handle_publish_github(
+    tag: String,
+    artifacts: Option<String>,
+    draft: bool,
+    sign: bool,
+    build_dir: String,
+    jobs: Option<usize>,
+) -> Result<()> {
+    if !has_tool("gh") {
+        anyhow::bail!("Publishing to GitHub requires the GitHub CLI, gh");
+    }
+    if sign {
+        sign::require_cosign()?;
+    }
+
+    let manifest = Manifest::load()?;
+    let version = tag.trim_start_matches('v');
+    if let Some(expected) = &manifest.package.version {
+        if expected != version {
+            anyhow::bail!(
+                "Tag '{}' doesn't match package.version {}, update the manifest first",
+                tag,
+                expected
+            );
+        }
+    }
+
+    let dist_dir = PathBuf::from(format!("{}-dist", build_dir)).join(&tag);
+    if !executor().dry_run() {
+        if dist_dir.exists() {
+            fs::remove_dir_all(&dist_dir)
+                .with_context(|| format!("Failed to clear {}", dist_dir.display()))?;
+        }
+        fs::create_dir_all(&dist_dir)
+            .with_context(|| format!("Failed to create {}", dist_dir.display()))?;
+    }
+
+    let mut files = match artifacts {
+        Some(dir) => collect_artifacts(Path::new(&dir))?,
+        None => {
+            let mut files = Vec::new();
+            for target in &manifest.publish.targets {
+                files.extend(build_artifact(
+                    target,
+                    version,
+                    &manifest.assets,
+                    &build_dir,
+                    &dist_dir,
+                    jobs,
+                )?);
+            }
+            files
+        }
+    };
+    if executor().dry_run() {
+        return Ok(());
+    }
+    let count = files
+        .iter()
+        .filter(|file| !is_provenance(file) && !sign::is_bundle(file))
+        .count();
+    files.push(write_checksums(&files, &dist_dir)?);
+    if sign {
+        // Bundles that came with prebuilt artifacts are uploaded as they are
+        let unsigned = files
+            .iter()
+            .filter(|file| !sign::is_bundle(file) && !files.contains(&sign::bundle_path(file)))
+            .cloned()
+            .collect::<Vec<_>>();
+        for file in unsigned {
+            files.push(sign::sign(&file)?);
+        }
+    }
+
+    let notes = release_notes(&manifest.publish.changelog, version);
+    let exists = executor()
+        .capture(Command::new("gh").args(["release", "view", &tag]))
+        .is_ok_and(|output| output.status.success());
+    if exists {
+        executor()
+            .execute(
+                Command::new("gh")
+                    .args(["release", "upload", &tag])
+                    .args(&files)
+                    .arg("--clobber"),
+            )
+            .context("Failed to upload the artifacts")?;
+    } else {
+        let mut command = Command::new("gh");
+        command
+            .args(["release", "create", &tag])
+            .args(&files)
+            .args(["--title", &tag]);
+        if let Some(commit) = head_commit() {
+            command.args(["--target", &commit]);
+        }
+        match notes {
+            Some(notes) => {
+                let notes_file = dist_dir.join("NOTES.md");
+                fs::write(&notes_file, notes).context("Failed to write the release notes")?;
+                command.arg("--notes-file").arg(notes_file);
+            }
+            None => {
+                command.arg("--generate-notes");
+            }
+        }
+        if draft {
+            command.arg("--draft");
+        }
+        executor()
+            .execute(&mut command)
+            .context("Failed to create the release")?;
+    }
+
+    eprintln!(
+        "{}",
+        format!(
+            "Published {} with {} {}",
+            tag,
+            count,
+            if count == 1 { "artifact" } else { "artifacts" }
+        )
+        .green()
+    );
+
+    Ok(())
+}
+
+/// Builds the project in release for a platform and archives what it
+/// produces: the executable with the manifest's assets, or for libraries
+/// what `cmake --install` installs, which `add package` sets up. The
+/// archive comes with the build's provenance manifest, named after it.
+fn build_artifact(
+    target: &str,
+    version: &str,
+    assets: &AssetsConfig,
+    build_dir: &str,
+    dist_dir: &Path,
+    jobs: Option<usize>,
+) -> Result<Vec<PathBuf>> {
+    let platform = match target {
+        "native" => None,
+        target => Some(
+            toolchain::parse_platform(target, None, None)
+                .with_context(|| format!("Invalid [publish] target '{}'", target))?,
+        ),
+    };
+    let target_name = match platform {
+        Some(_) => target.to_string(),
+        None => format!("{}-{}", std::env::consts::ARCH, std::env::consts::OS),
+    };
+    let name = default_exec_name();
+
+    let dir = format!("{}-release-{}", build_dir, target);
+    let mut configure = cmake::configure_command(&dir, &[("CMAKE_BUILD_TYPE", "Release")]);
+    if let Some(platform) = platform {
+        for (variable, value) in platform.cache_variables(Path::new("."))? {
+            configure.arg("-D").arg(format!("{}={}", variable, value));
+        }
+    }
+    if !executor().dry_run() {
+        fileapi::write_query(Path::new(&dir))?;
+    }
+    executor()
+        .execute(&mut configure)
+        .with_context(|| format!("Failed to configure the {} release build", target))?;
+    executor()
+        .execute(&mut cmake::build_command(&dir, Some("Release"), jobs))
+        .with_context(|| format!("Failed to build the {} release", target))?;
+
+    let stage_name = format!("{}-{}-{}", name, version, target_name);
+    let stage = dist_dir.join(&stage_name);
+    if !executor().dry_run() {
+        fs::create_dir_all(&stage)
+            .with_context(|| format!("Failed to create {}", stage.display()))?;
+    }
+    if cmake::is_library_project()? {
+        executor()
+            .execute(
+                Command::new("cmake")
+                    .args(["--install", &dir, "--config", "Release", "--prefix"])
+                    .arg(&stage),
+            )
+            .with_context(|| format!("Failed to install the {} release", target))?;
+    } else {
+        // Every build writes the executable to the same runtime directory,
+        // so it's copied out before the next platform is built
+        let runtime_dir = cmake::runtime_output_dir()?.unwrap_or_else(|| "bin".to_string());
+        let mut executable = toolchain::executable(&dir, &name);
+        if let Some(platform) = platform {
+            executable.push_str(platform.executable_suffix());
+            if let Some(output_dir) = platform.output_dir() {
+                executable = format!("{}/{}", output_dir, executable);
+            }
+        }
+        let source = Path::new(&runtime_dir).join(&executable);
+        let file_name = source.file_name().context("Executable has no file name")?;
+        if !executor().dry_run() {
+            fs::copy(&source, stage.join(file_name))
+                .with_context(|| format!("Failed to copy {}", source.display()))?;
+            assets::stage_assets(assets, &stage)?;
+        }
+    }
+
+    let archive = dist_dir.join(format!("{}.tar.gz", stage_name));
+    executor()
+        .execute(
+            Command::new("tar")
+                .arg("-czf")
+                .arg(&archive)
+                .arg("-C")
+                .arg(dist_dir)
+                .arg(&stage_name),
+        )
+        .with_context(|| format!("Failed to archive the {} release", target))?;
+    if executor().dry_run() {
+        return Ok(vec![archive]);
+    }
+
+    let build = provenance::collect(Path::new(&dir))?;
+    build.write(Path::new(&dir))?;
+    let manifest = dist_dir.join(format!("{}.{}", stage_name, provenance::PROVENANCE_FILE));
+    fs::copy(Path::new(&dir).join(provenance::PROVENANCE_FILE), &manifest)
+        .with_context(|| format!("Failed to copy the {} provenance", target))?;
+
+    Ok(vec![archive, manifest])
+}
+
+/// The files of a directory of prebuilt artifacts, without subdirectories
+fn collect_artifacts(dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut files = fs::read_dir(dir)
+        .with_context(|| format!("Failed to read {}", dir.display()))?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| {
+            path.is_file() && path.file_name().is_some_and(|name| name != CHECKSUMS_FILE)
+        })
+        .collect::<Vec<_>>();
+    files.sort();
+    if files.is_empty() {
+        anyhow::bail!("No artifacts found in '{}'", dir.display());
+    }
+
+    Ok(files)
+}
+
+fn is_provenance(file: &Path) -> bool {
+    file.to_string_lossy()
+        .ends_with(&format!(".{}", provenance::PROVENANCE_FILE))
+}
+
+fn write_checksums(files: &[PathBuf], dist_dir: &Path) -> Result<PathBuf> {
+    let mut checksums = String::new();
+    for file in files {
+        let name = file.file_name().context("Artifact has no file name")?;
+        checksums.push_str(&format!("{}  {}\n", sha256(file)?, name.to_string_lossy()));
+    }
+
+    let path = dist_dir.join(CHECKSUMS_FILE);
+    fs::write(&path, checksums).with_context(|| format!("Failed to write {}", path.display()))?;
+
+    Ok(path)
+}
+
+/// The changelog section of a version, headed e.g. `## [1.2.0] - 2024-05-01`
+/// or `## v1.2.0`, up to the next version's heading
+fn release_notes(changelog: &str, version: &str) -> Option<String> {
+    let text = fs::read_to_string(changelog).ok()?;
+    let mut lines = text.lines();
+    lines.find(|line| {
+        line.starts_with("## ")
+            && line
+                .split(|c: char| c.is_whitespace() || "[]()v".contains(c))
+                .any(|word| word == version)
+    })?;
+
+    let notes = lines
+        .take_while(|line| !line.starts_with("## ") && !line.starts_with("# "))
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    Some(notes.trim().to_string()).filter(|notes| !notes.is_empty())
+}
+
+fn head_commit() -> Option<String> {
+    let output = executor()
+        .capture(Command::new("git").args(["rev-parse", "HEAD"]))
+        .ok()?;
+
+    output
+        .status
+        .success()
+        .then(|| String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+fn has_tool(tool: &str) -> bool {
+    executor()
+        .capture(Command::new(tool).arg("--version"))
+        .is_ok()
+}
@@ -0,0 +1,185 @@
+use anyhow::{Context, Result};
+use colorize::AnsiColor;
+use std::{collections::BTreeMap, process::Command};
+
+use crate::{
+    default_exec_name, env,
+    executor::{self, executor, shell_quote},
+    handle_build_project,
+    manifest::{Manifest, RunConfig},
+    toolchain, FailureArgs,
+};
+
+/// Where the binary under debug is deployed to
+pub enum RemoteTarget {
+    /// A board or machine reachable over ssh, e.g. `user@board`
+    Ssh(String),
+    /// A running container, addressed by name or id
+    Container(String),
+}
+
+/// Options for a remote debugging session
+pub struct RemoteDebugOptions {
+    pub target: RemoteTarget,
+    pub port: u16,
+    pub remote_dir: String,
+    pub sysroot: Option<String>,
+    pub debugger: String,
+    pub launch: bool,
+}
+
+pub fn handle_gdbserver_project(
+    build_dir: String,
+    runtime_dir: String,
+    exec_name: Option<String>,
+    options: RemoteDebugOptions,
+    args: Vec<String>,
+) -> Result<()> {
+    let exec_name = exec_name.unwrap_or_else(default_exec_name);
+    let local_path = format!(
+        "./{}/{}",
+        runtime_dir,
+        toolchain::executable(&build_dir, &exec_name)
+    );
+    let remote_path = format!("{}/{}", options.remote_dir.trim_end_matches('/'), exec_name);
+
+    handle_build_project(build_dir, &runtime_dir, None, &FailureArgs::default())
+        .context("Failed to build project")?;
+
+    let env = env::project_env(&Manifest::load()?, &RunConfig::default())?;
+
+    deploy_binary(&options.target, &local_path, &remote_path)?;
+    start_gdbserver(&options.target, options.port, &remote_path, &env, &args)?;
+
+    let mut connect = debugger_connect_command(&options, &local_path);
+    if options.launch {
+        executor()
+            .execute_interactive(&mut connect)
+            .context("Failed to launch debugger")?;
+    } else {
+        eprintln!(
+            "{}",
+            format!(
+                "gdbserver listening on {}:{}",
+                target_host(&options.target),
+                options.port
+            )
+            .green()
+        );
+        eprintln!("Connect with:\n  {}", executor::display(&connect));
+    }
+
+    Ok(())
+}
+
+fn deploy_binary(target: &RemoteTarget, local_path: &str, remote_path: &str) -> Result<()> {
+    let (mut command, host) = match target {
+        RemoteTarget::Ssh(host) => (Command::new("scp"), host),
+        RemoteTarget::Container(container) => {
+            let mut command = Command::new("docker");
+            command.arg("cp");
+            (command, container)
+        }
+    };
+    command
+        .arg(local_path)
+        .arg(format!("{}:{}", host, remote_path));
+
+    executor()
+        .execute(&mut command)
+        .context("Failed to deploy executable to remote target")?;
+
+    Ok(())
+}
+
+fn start_gdbserver(
+    target: &RemoteTarget,
+    port: u16,
+    remote_path: &str,
+    env: &BTreeMap<String, String>,
+    args: &[String],
+) -> Result<()> {
+    let mut command = match target {
+        // ssh runs the command line with the remote user's shell
+        RemoteTarget::Ssh(host) => {
+            let env = env
+                .iter()
+                .map(|(key, value)| format!("{}={} ", key, shell_quote(value)))
+                .collect::<String>();
+            let args = args
+                .iter()
+                .map(|arg| format!(" {}", shell_quote(arg)))
+                .collect::<String>();
+            let mut command = Command::new("ssh");
+            command.arg("-f").arg(host).arg(format!(
+                "{}gdbserver :{} {}{}",
+                env,
+                port,
+                shell_quote(remote_path),
+                args
+            ));
+            command
+        }
+        RemoteTarget::Container(container) => {
+            let mut command = Command::new("docker");
+            command.args(["exec", "-d"]);
+            for (key, value) in env {
+                command.arg("-e").arg(format!("{}={}", key, value));
+            }
+            command
+                .arg(container)
+                .arg("gdbserver")
+                .arg(format!(":{}", port))
+                .arg(remote_path)
+                .args(args);
+            command
+        }
+    };
+
+    executor()
+        .execute(&mut command)
+        .context("Failed to start gdbserver on remote target")?;
+
+    Ok(())
+}
+
+fn target_host(target: &RemoteTarget) -> String {
+    match target {
+        RemoteTarget::Ssh(host) => host.rsplit('@').next().unwrap_or(host).to_string(),
+        // Containers are expected to publish the gdbserver port on the host
+        RemoteTarget::Container(_) => "localhost".to_string(),
+    }
+}
+
+/// Quotes an argument of an lldb command, which lldb splits at spaces like
+/// a shell, e.g. a sysroot path with spaces. gdb's `set sysroot` takes the
+/// rest of the line as it is.
+fn lldb_quote(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+fn debugger_connect_command(options: &RemoteDebugOptions, local_path: &str) -> Command {
+    let address = format!("{}:{}", target_host(&options.target), options.port);
+    let mut command = Command::new(&options.debugger);
+
+    match options.debugger.as_str() {
+        "lldb" => {
+            if let Some(sysroot) = &options.sysroot {
+                command.arg("-o").arg(format!(
+                    "platform select remote-linux --sysroot {}",
+                    lldb_quote(sysroot)
+                ));
+            }
+            command.arg("-o").arg(format!("gdb-remote {}", address));
+        }
+        _ => {
+            if let Some(sysroot) = &options.sysroot {
+                command.arg("-ex").arg(format!("set sysroot {}", sysroot));
+            }
+            command.arg("-ex").arg(format!("target remote {}", address));
+        }
+    }
+    command.arg(local_path);
+
+    command
+}
@@ -0,0 +1,128 @@
+use anyhow::{Context, Result};
+use colorize::AnsiColor;
+use std::{fs, path::Path};
+
+use crate::{
+    build_info::macro_prefix,
+    cmake,
+    config::{toml_string, write_value},
+    layout,
+    manifest::MANIFEST_FILE,
+    sources::{self, replace_identifier_prefix, replace_word},
+    validate_project_name,
+};
+
+pub fn handle_rename_project(new_name: String) -> Result<()> {
+    validate_project_name(&new_name)?;
+
+    let old_name = cmake::project_name()?;
+    if old_name == new_name {
+        anyhow::bail!("Project is already named '{}'", new_name);
+    }
+
+    let mut changed = Vec::new();
+
+    rename_in_manifest(&new_name, &mut changed)?;
+    rewrite_file(Path::new(cmake::CMAKE_LISTS), &mut changed, |text| {
+        replace_argument(text, &old_name, &new_name)
+    })?;
+
+    let include_dir = layout::include_dir()?;
+    let old_include = Path::new(&include_dir).join(&old_name);
+    let new_include = Path::new(&include_dir).join(&new_name);
+    if old_include.is_dir() {
+        if new_include.exists() {
+            anyhow::bail!("'{}' already exists", new_include.display());
+        }
+        fs::rename(&old_include, &new_include)
+            .with_context(|| format!("Failed to rename '{}'", old_include.display()))?;
+        changed.push(format!(
+            "{} -> {}",
+            old_include.display(),
+            new_include.display()
+        ));
+    }
+
+    // Include paths and guards or macros derived from the name, e.g.
+    // `#include <old/foo.hpp>` and `OLD_FOO_HPP`
+    let old_prefix = format!("{}_", macro_prefix(&old_name));
+    let new_prefix = format!("{}_", macro_prefix(&new_name));
+    for file in sources::project_sources()? {
+        rewrite_file(&file, &mut changed, |text| {
+            let text = text
+                .replace(&format!("\"{}/", old_name), &format!("\"{}/", new_name))
+                .replace(&format!("<{}/", old_name), &format!("<{}/", new_name));
+            replace_identifier_prefix(&text, &old_prefix, &new_prefix)
+        })?;
+    }
+
+    rewrite_file(Path::new("README.md"), &mut changed, |text| {
+        replace_word(text, &old_name, &new_name)
+    })?;
+
+    eprintln!(
+        "{}",
+        format!("Renamed project '{}' to '{}'", old_name, new_name).green()
+    );
+    for change in changed {
+        eprintln!("  {}", change);
+    }
+    eprintln!("Re-run init to reconfigure the build directory");
+
+    Ok(())
+}
+
+/// Records the new name in the manifest's `[package]`, which is where the
+/// executable name is taken from once it no longer matches the project
+/// directory
+fn rename_in_manifest(new_name: &str, changed: &mut Vec<String>) -> Result<()> {
+    let manifest = fs::read_to_string(MANIFEST_FILE).unwrap_or_default();
+    let updated = write_value(&manifest, "package.name", &toml_string(new_name));
+
+    fs::write(MANIFEST_FILE, updated)
+        .with_context(|| format!("Failed to update {}", MANIFEST_FILE))?;
+    changed.push(MANIFEST_FILE.to_string());
+
+    Ok(())
+}
+
+/// Renames the project where it's a whole argument of a CMake call, e.g.
+/// `project(old CXX)` or `add_executable(old ...)`, but not in paths such as
+/// `src/old.cpp`, whose files keep their names
+fn replace_argument(text: &str, old: &str, new: &str) -> String {
+    let is_separator = |c: Option<char>| c.is_none_or(|c| c.is_whitespace() || "()".contains(c));
+    let mut result = String::with_capacity(text.len());
+    let mut offset = 0;
+
+    for (index, _) in text.match_indices(old) {
+        let before = text[..index].chars().next_back();
+        let after = text[index + old.len()..].chars().next();
+        if is_separator(before) && is_separator(after) {
+            result.push_str(&text[offset..index]);
+            result.push_str(new);
+            offset = index + old.len();
+        }
+    }
+    result.push_str(&text[offset..]);
+
+    result
+}
+
+fn rewrite_file(
+    path: &Path,
+    changed: &mut Vec<String>,
+    rewrite: impl Fn(&str) -> String,
+) -> Result<()> {
+    let Ok(text) = fs::read_to_string(path) else {
+        return Ok(());
+    };
+
+    let updated = rewrite(&text);
+    if updated != text {
+        fs::write(path, updated)
+            .with_context(|| format!("Failed to update '{}'", path.display()))?;
+        changed.push(path.display().to_string());
+    }
+
+    Ok(())
+}
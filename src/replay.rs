@@ -0,0 +1,229 @@
+use anyhow::{Context, Result};
+use colorize::AnsiColor;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    fs,
+    io::{IsTerminal, Read},
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+};
+
+use crate::{
+    executor::{executor, CommandFailed},
+    runs,
+};
+
+/// Directory of the recorded cases, committed with the project so they
+/// run wherever its tests do
+pub const CASES_DIR: &str = "tests/replay";
+
+/// How the program was run when a case was recorded, and what it printed
+/// is stored next to it in `stdout`, with its input in `stdin`
+#[derive(Serialize, Deserialize)]
+pub struct Case {
+    /// The executable, relative to the project root
+    pub executable: String,
+    /// The directory it ran in, relative to the project root
+    pub working_dir: String,
+    pub args: Vec<String>,
+    pub env: BTreeMap<String, String>,
+    /// None when the program was killed by a signal
+    pub exit_code: Option<i32>,
+}
+
+/// Runs the program like `run` and records it as the case `name`: its
+/// arguments, environment and piped stdin, and the stdout and exit code
+/// `test --replay` expects from then on. Recording a case again replaces
+/// it.
+pub fn record(name: &str, case: Case) -> Result<()> {
+    // Input typed at a terminal can't be replayed, so only piped input is
+    // recorded and the program otherwise reads nothing
+    let mut stdin = Vec::new();
+    if !executor().dry_run() && !std::io::stdin().is_terminal() {
+        std::io::stdin()
+            .read_to_end(&mut stdin)
+            .context("Failed to read stdin")?;
+    }
+
+    record_input(name, case, stdin)
+}
+
+/// Records the case `name` with `stdin` as the program's input
+pub fn record_input(name: &str, mut case: Case, stdin: Vec<u8>) -> Result<()> {
+    if name.is_empty()
+        || !name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+    {
+        anyhow::bail!(
+            "Case name '{}' may only contain letters, digits, '_' and '-'",
+            name
+        );
+    }
+
+    let dir = Path::new(CASES_DIR).join(name);
+    if executor().dry_run() {
+        return executor().execute(&mut command(&case, &dir)?);
+    }
+
+    fs::create_dir_all(&dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+    fs::write(dir.join("stdin"), stdin).context("Failed to write the case's stdin")?;
+
+    let (stdout, result) = execute(&case, &dir, true)?;
+    case.exit_code = exit_code(&result);
+    fs::write(dir.join("stdout"), stdout).context("Failed to write the case's stdout")?;
+    fs::write(
+        dir.join("case.json"),
+        serde_json::to_string_pretty(&case).context("Failed to serialize the case")?,
+    )
+    .context("Failed to write the case")?;
+    eprintln!(
+        "{}",
+        format!("Recorded case {}, replay it with 'test --replay'", name).green()
+    );
+
+    // Failing is recorded like any output, but `run` still reports it
+    result
+}
+
+/// Runs every recorded case against the current build and compares what it
+/// prints to stdout and its exit code with the recording, showing the lines
+/// that drifted
+pub fn handle_replay() -> Result<()> {
+    let cases = load_all()?;
+    if cases.is_empty() {
+        anyhow::bail!("No recorded cases, record one with 'run --record <CASE>'");
+    }
+
+    let mut drifted = 0;
+    for (name, case) in &cases {
+        let dir = Path::new(CASES_DIR).join(name);
+        if executor().dry_run() {
+            executor().execute(&mut command(case, &dir)?)?;
+            continue;
+        }
+
+        let expected = fs::read_to_string(dir.join("stdout")).unwrap_or_default();
+        let (stdout, result) = execute(case, &dir, false)?;
+        let code = exit_code(&result);
+        if stdout == expected && code == case.exit_code {
+            eprintln!("{} {}", "ok".green(), name);
+            continue;
+        }
+
+        drifted += 1;
+        eprintln!("{} {}", "drifted".red(), name);
+        if code != case.exit_code {
+            let status = |code: Option<i32>| {
+                code.map_or("killed".to_string(), |code| format!("exit {}", code))
+            };
+            println!(
+                "{}",
+                format!("status:    {} -> {}", status(case.exit_code), status(code)).red()
+            );
+        }
+        runs::print_diff("stdout", &expected, &stdout);
+    }
+
+    if drifted > 0 {
+        anyhow::bail!(
+            "{} of {} recorded cases drifted, record them again if the change is intended",
+            drifted,
+            cases.len()
+        );
+    }
+    eprintln!(
+        "{}",
+        format!(
+            "All {} recorded {}",
+            cases.len(),
+            if cases.len() == 1 {
+                "case matches"
+            } else {
+                "cases match"
+            }
+        )
+        .green()
+    );
+
+    Ok(())
+}
+
+/// Runs a case's program, returning its stdout and whether it failed.
+/// Only failing to start the program is an error.
+fn execute(case: &Case, dir: &Path, show: bool) -> Result<(String, Result<()>)> {
+    let mut stdout = String::new();
+    let result = executor().execute_observed(&mut command(case, dir)?, &mut |line, error| {
+        if !error {
+            stdout.push_str(line);
+            stdout.push('\n');
+        }
+        show
+    });
+
+    match result {
+        Err(error) if error.downcast_ref::<CommandFailed>().is_none() => Err(error),
+        result => Ok((stdout, result)),
+    }
+}
+
+fn exit_code(result: &Result<()>) -> Option<i32> {
+    match result {
+        Ok(()) => Some(0),
+        Err(error) => error
+            .downcast_ref::<CommandFailed>()
+            .and_then(|CommandFailed(status)| status.code()),
+    }
+}
+
+/// The command running a case from the project root, with the recorded
+/// input on stdin
+fn command(case: &Case, dir: &Path) -> Result<Command> {
+    let root = std::env::current_dir().context("Failed to get current directory")?;
+    let mut command = Command::new(root.join(&case.executable));
+    command
+        .current_dir(root.join(&case.working_dir))
+        .envs(&case.env)
+        .args(&case.args);
+
+    // A dry run doesn't write the input
+    let stdin = root.join(dir).join("stdin");
+    match stdin.is_file() {
+        true => command.stdin(
+            fs::File::open(&stdin)
+                .with_context(|| format!("Failed to read {}", stdin.display()))?,
+        ),
+        false => command.stdin(Stdio::null()),
+    };
+
+    Ok(command)
+}
+
+/// The recorded cases by name, in order
+pub fn load_all() -> Result<Vec<(String, Case)>> {
+    let Ok(entries) = fs::read_dir(CASES_DIR) else {
+        return Ok(Vec::new());
+    };
+    let mut dirs = entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| path.join("case.json").is_file())
+        .collect::<Vec<PathBuf>>();
+    dirs.sort();
+
+    dirs.into_iter()
+        .map(|dir| {
+            let name = dir
+                .file_name()
+                .context("Case has no name")?
+                .to_string_lossy()
+                .to_string();
+            let text = fs::read_to_string(dir.join("case.json"))
+                .with_context(|| format!("Failed to read case {}", name))?;
+            let case = serde_json::from_str(&text)
+                .with_context(|| format!("Failed to parse case {}", name))?;
+            Ok((name, case))
+        })
+        .collect()
+}
@@ -0,0 +1,405 @@
+use anyhow::{Context, Result};
+use colorize::AnsiColor;
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::{self, File},
+    io::Write,
+    path::{Path, PathBuf},
+    process::Command,
+    time::{Instant, SystemTime, UNIX_EPOCH},
+};
+
+use crate::{
+    build_info::format_timestamp,
+    executor::{executor, CommandFailed},
+    manifest::STATE_DIR,
+};
+
+/// Directory of the captured runs, relative to the state directory
+const RUNS_DIR: &str = "runs";
+
+/// How many captured runs are kept, the oldest are removed first
+const MAX_RUNS: usize = 20;
+
+/// What a captured run did, stored next to its output
+#[derive(Serialize, Deserialize)]
+pub struct Run {
+    pub id: usize,
+    /// Seconds since the epoch when it started
+    pub timestamp: u64,
+    /// The executable and its arguments
+    pub command: String,
+    /// None when the program was killed by a signal
+    pub exit_code: Option<i32>,
+    pub seconds: f64,
+}
+
+/// Runs the program, with `log` teeing what it prints to a
+/// file, each line stamped with the time since the start, and with
+/// `capture` storing its output in the state directory for `runs show`.
+/// `description` is the executable and its arguments.
+pub fn run_program(
+    command: &mut Command,
+    description: &str,
+    log: Option<&str>,
+    capture: bool,
+) -> Result<()> {
+    if (log.is_none() && !capture) || executor().dry_run() {
+        return executor().execute_interactive(command);
+    }
+
+    let mut log = log
+        .map(|path| File::create(path).with_context(|| format!("Failed to create {}", path)))
+        .transpose()?;
+    if let Some(log) = &mut log {
+        writeln!(log, "# {} started {}", description, format_timestamp(now()))
+            .context("Failed to write the log")?;
+    }
+
+    let timestamp = now();
+    let start = Instant::now();
+    let mut stdout = String::new();
+    let mut stderr = String::new();
+    let result = executor().execute_observed(command, &mut |line, error| {
+        if let Some(log) = &mut log {
+            let stream = if error { "stderr" } else { "stdout" };
+            let _ = writeln!(
+                log,
+                "[+{:.3}s {}] {}",
+                start.elapsed().as_secs_f64(),
+                stream,
+                line
+            );
+        }
+        let output = if error { &mut stderr } else { &mut stdout };
+        output.push_str(line);
+        output.push('\n');
+        // Shown here rather than by the executor, which holds back what
+        // tools print when quiet, while this is the program's own output
+        match error {
+            true => eprintln!("{}", line),
+            false => println!("{}", line),
+        }
+        false
+    });
+    let seconds = start.elapsed().as_secs_f64();
+
+    // A program failing is part of its output, failing to start it isn't
+    let exit_code = match &result {
+        Ok(()) => Some(0),
+        Err(error) => match error.downcast_ref::<CommandFailed>() {
+            Some(CommandFailed(status)) => status.code(),
+            None => return result,
+        },
+    };
+    if let Some(log) = &mut log {
+        let status = exit_code.map_or("a signal".to_string(), |code| format!("code {}", code));
+        writeln!(log, "# exited with {} after {:.3}s", status, seconds)
+            .context("Failed to write the log")?;
+    }
+    if capture {
+        let run = Run {
+            id: next_id(),
+            timestamp,
+            command: description.to_string(),
+            exit_code,
+            seconds,
+        };
+        save(&run, &stdout, &stderr)?;
+        eprintln!("{}", format!("Captured run {}", run.id).green());
+    }
+
+    result
+}
+
+/// Prints the output of a captured run, the latest one by default, to the
+/// streams it went to
+pub fn handle_runs_show(run: Option<String>) -> Result<()> {
+    let run = find(run.as_deref())?;
+    let dir = run_dir(run.id);
+    let stdout = fs::read_to_string(dir.join("stdout")).unwrap_or_default();
+    let stderr = fs::read_to_string(dir.join("stderr")).unwrap_or_default();
+
+    eprintln!("{}", summary(&run).bold());
+    print!("{}", stdout);
+    eprint!("{}", stderr);
+
+    Ok(())
+}
+
+/// Lists the captured runs, the latest last
+pub fn handle_runs_list() -> Result<()> {
+    let runs = load_all();
+    if runs.is_empty() {
+        eprintln!("No captured runs, run with --capture to capture one");
+    }
+    for run in runs {
+        println!("{}", summary(&run));
+    }
+
+    Ok(())
+}
+
+/// Compares two captured runs, by default the latest with the one before:
+/// their exit codes, durations and output, as hunks of changed lines.
+/// A run that started failing, or got much slower, is highlighted.
+pub fn handle_runs_diff(before: Option<String>, after: Option<String>) -> Result<()> {
+    let runs = load_all();
+    let (before, after) = match (before, after) {
+        (Some(before), Some(after)) => (find(Some(&before))?, find(Some(&after))?),
+        (Some(before), None) => (find(Some(&before))?, find(None)?),
+        _ => {
+            if runs.len() < 2 {
+                anyhow::bail!("Diffing needs two captured runs, run with --capture again");
+            }
+            let mut runs = runs.into_iter().rev();
+            let after = runs.next().context("No captured runs")?;
+            (runs.next().context("No captured runs")?, after)
+        }
+    };
+
+    println!(
+        "{}",
+        format!("Run {} -> run {}", before.id, after.id).bold()
+    );
+    if before.command != after.command {
+        println!("command:   {} -> {}", before.command, after.command);
+    }
+
+    let status = |run: &Run| match run.exit_code {
+        Some(code) => format!("exit {}", code),
+        None => "killed".to_string(),
+    };
+    if before.exit_code == after.exit_code {
+        println!("status:    {}", status(&after));
+    } else {
+        let change = format!("status:    {} -> {}", status(&before), status(&after));
+        match after.exit_code == Some(0) {
+            true => println!("{}", change.green()),
+            false => println!("{}", change.red()),
+        }
+    }
+
+    let change = (after.seconds - before.seconds) / before.seconds.max(0.001) * 100.0;
+    let duration = format!(
+        "duration:  {:.3}s -> {:.3}s ({:+.0}%)",
+        before.seconds, after.seconds, change
+    );
+    if change > SLOWER_PERCENT && after.seconds - before.seconds > SLOWER_SECONDS {
+        println!("{}", duration.yellow());
+    } else {
+        println!("{}", duration);
+    }
+
+    for stream in ["stdout", "stderr"] {
+        let read = |run: &Run| fs::read_to_string(run_dir(run.id).join(stream)).unwrap_or_default();
+        let (old, new) = (read(&before), read(&after));
+        print_diff(stream, &old, &new);
+    }
+
+    Ok(())
+}
+
+/// How much slower a run has to be for the diff to point it out, both
+/// relatively and absolutely so noise in short runs isn't reported
+const SLOWER_PERCENT: f64 = 10.0;
+const SLOWER_SECONDS: f64 = 0.05;
+
+/// Lines of unchanged output shown around each change
+const CONTEXT_LINES: usize = 2;
+
+#[derive(Clone, Copy, PartialEq)]
+enum Edit {
+    Same,
+    Removed,
+    Added,
+}
+
+/// Prints the changed lines of one stream with some context, like `diff -u`
+pub fn print_diff(stream: &str, old: &str, new: &str) {
+    let old = old.lines().collect::<Vec<_>>();
+    let new = new.lines().collect::<Vec<_>>();
+    let edits = diff_lines(&old, &new);
+    let removed = edits
+        .iter()
+        .filter(|(edit, _)| *edit == Edit::Removed)
+        .count();
+    let added = edits
+        .iter()
+        .filter(|(edit, _)| *edit == Edit::Added)
+        .count();
+    if removed == 0 && added == 0 {
+        println!("{}:    identical", stream);
+        return;
+    }
+    println!(
+        "{}",
+        format!("{}:    {} lines removed, {} added", stream, removed, added).bold()
+    );
+
+    // Shows the lines within CONTEXT_LINES of a change, marking gaps
+    let near_change = |index: usize| {
+        let start = index.saturating_sub(CONTEXT_LINES);
+        let end = (index + CONTEXT_LINES + 1).min(edits.len());
+        edits[start..end]
+            .iter()
+            .any(|(edit, _)| *edit != Edit::Same)
+    };
+    let mut gap = false;
+    for (index, (edit, line)) in edits.iter().enumerate() {
+        if !near_change(index) {
+            gap = true;
+            continue;
+        }
+        if gap {
+            println!("{}", "  ...".grey());
+            gap = false;
+        }
+        match edit {
+            Edit::Same => println!("  {}", line),
+            Edit::Removed => println!("{}", format!("- {}", line).red()),
+            Edit::Added => println!("{}", format!("+ {}", line).green()),
+        }
+    }
+}
+
+/// The edits turning `old` into `new`, from their longest common
+/// subsequence of lines. The unchanged start and end are skipped first,
+/// which keeps the table small when little changed.
+fn diff_lines<'a>(old: &[&'a str], new: &[&'a str]) -> Vec<(Edit, &'a str)> {
+    let prefix = old
+        .iter()
+        .zip(new)
+        .take_while(|(old, new)| old == new)
+        .count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(old, new)| old == new)
+        .count();
+    let old_middle = &old[prefix..old.len() - suffix];
+    let new_middle = &new[prefix..new.len() - suffix];
+
+    // lengths[i][j] is the length of the common subsequence of
+    // old_middle[i..] and new_middle[j..]
+    let mut lengths = vec![vec![0usize; new_middle.len() + 1]; old_middle.len() + 1];
+    for i in (0..old_middle.len()).rev() {
+        for j in (0..new_middle.len()).rev() {
+            lengths[i][j] = match old_middle[i] == new_middle[j] {
+                true => lengths[i + 1][j + 1] + 1,
+                false => lengths[i + 1][j].max(lengths[i][j + 1]),
+            };
+        }
+    }
+
+    let mut edits = old[..prefix]
+        .iter()
+        .map(|line| (Edit::Same, *line))
+        .collect::<Vec<_>>();
+    let (mut i, mut j) = (0, 0);
+    while i < old_middle.len() || j < new_middle.len() {
+        if i < old_middle.len() && j < new_middle.len() && old_middle[i] == new_middle[j] {
+            edits.push((Edit::Same, old_middle[i]));
+            i += 1;
+            j += 1;
+        } else if i < old_middle.len()
+            && (j == new_middle.len() || lengths[i + 1][j] >= lengths[i][j + 1])
+        {
+            edits.push((Edit::Removed, old_middle[i]));
+            i += 1;
+        } else {
+            edits.push((Edit::Added, new_middle[j]));
+            j += 1;
+        }
+    }
+    edits.extend(
+        old[old.len() - suffix..]
+            .iter()
+            .map(|line| (Edit::Same, *line)),
+    );
+
+    edits
+}
+
+fn summary(run: &Run) -> String {
+    let status = match run.exit_code {
+        Some(code) => format!("exit {}", code),
+        None => "killed".to_string(),
+    };
+
+    format!(
+        "{:>3}  {}  {:>8.3}s  {:<7}  {}",
+        run.id,
+        format_timestamp(run.timestamp),
+        run.seconds,
+        status,
+        run.command
+    )
+}
+
+/// A captured run by its id, or the latest one
+pub fn find(id: Option<&str>) -> Result<Run> {
+    let runs = load_all();
+    match id {
+        Some(id) => {
+            let id = id
+                .parse::<usize>()
+                .with_context(|| format!("'{}' isn't a run id, see 'runs list'", id))?;
+            runs.into_iter()
+                .find(|run| run.id == id)
+                .with_context(|| format!("No captured run {}, see 'runs list'", id))
+        }
+        None => runs
+            .into_iter()
+            .next_back()
+            .context("No captured runs, run with --capture to capture one"),
+    }
+}
+
+/// The directory holding a captured run's record and output
+pub fn run_dir(id: usize) -> PathBuf {
+    Path::new(STATE_DIR).join(RUNS_DIR).join(id.to_string())
+}
+
+/// The captured runs, oldest first
+fn load_all() -> Vec<Run> {
+    let Ok(entries) = fs::read_dir(Path::new(STATE_DIR).join(RUNS_DIR)) else {
+        return Vec::new();
+    };
+
+    let mut runs = entries
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| fs::read_to_string(entry.path().join("run.json")).ok())
+        .filter_map(|text| serde_json::from_str::<Run>(&text).ok())
+        .collect::<Vec<_>>();
+    runs.sort_by_key(|run| run.id);
+    runs
+}
+
+fn next_id() -> usize {
+    load_all().last().map_or(1, |run| run.id + 1)
+}
+
+fn save(run: &Run, stdout: &str, stderr: &str) -> Result<()> {
+    let dir = run_dir(run.id);
+    fs::create_dir_all(&dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+    fs::write(dir.join("stdout"), stdout).context("Failed to capture stdout")?;
+    fs::write(dir.join("stderr"), stderr).context("Failed to capture stderr")?;
+    let text = serde_json::to_string(run).context("Failed to serialize run")?;
+    fs::write(dir.join("run.json"), text).context("Failed to write run")?;
+
+    let runs = load_all();
+    for old in &runs[..runs.len().saturating_sub(MAX_RUNS)] {
+        fs::remove_dir_all(run_dir(old.id)).context("Failed to remove an old run")?;
+    }
+
+    Ok(())
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|now| now.as_secs())
+        .unwrap_or(0)
+}
@@ -0,0 +1,157 @@
+use anyhow::{Context, Result};
+use colorize::AnsiColor;
+use std::{fs, path::Path};
+
+use crate::{
+    cmake,
+    guards::{wrap_header, GuardStyle},
+    layout::{self, Placement},
+};
+
+pub fn handle_add_class(name: String, dir: Option<String>, header_only: bool) -> Result<()> {
+    if cmake::is_c_project()? {
+        anyhow::bail!("Classes require a C++ project, use 'add header' instead");
+    }
+
+    let placement = resolve_placement(dir.as_deref())?;
+    let stem = file_stem(&name);
+    let header = Path::new(&layout::include_dir()?)
+        .join(&placement.header_dir)
+        .join(format!("{}.hpp", stem));
+    let source = Path::new(&layout::src_dir()?)
+        .join(&placement.source_dir)
+        .join(format!("{}.cpp", stem));
+
+    let declaration = if header_only {
+        format!("class {name} {{\n public:\n  {name}() = default;\n}};\n")
+    } else {
+        format!("class {name} {{\n public:\n  {name}();\n}};\n")
+    };
+    write_new_file(&header, &header_file(&header, &placement, &declaration)?)?;
+
+    if !header_only {
+        let include = placement
+            .header_dir
+            .join(format!("{}.hpp", stem))
+            .display()
+            .to_string();
+        let definition = format!("{name}::{name}() = default;\n");
+        let contents = format!(
+            "#include <{}>\n\n{}",
+            include,
+            in_namespace(&placement.namespace, &definition)
+        );
+        write_new_file(&source, &contents)?;
+        register_source(&source)?;
+    }
+
+    eprintln!("{}", format!("Added class '{}'", name).green());
+
+    Ok(())
+}
+
+pub fn handle_add_header(name: String, dir: Option<String>) -> Result<()> {
+    let c_project = cmake::is_c_project()?;
+    let placement = resolve_placement(dir.as_deref())?;
+    let extension = if c_project { "h" } else { "hpp" };
+    let header = Path::new(&layout::include_dir()?)
+        .join(&placement.header_dir)
+        .join(format!("{}.{}", file_stem(&name), extension));
+
+    let contents = if c_project {
+        header_file(&header, &Placement::default(), "")?
+    } else {
+        header_file(&header, &placement, "")?
+    };
+    write_new_file(&header, &contents)?;
+
+    eprintln!("{}", format!("Added header '{}'", header.display()).green());
+
+    Ok(())
+}
+
+fn resolve_placement(dir: Option<&str>) -> Result<Placement> {
+    let project_name = cmake::project_name()?;
+    let layout = layout::detect_layout(&project_name)?;
+
+    layout::placement(&project_name, layout, dir)
+}
+
+/// `FooBar` becomes `foo_bar`
+pub fn file_stem(name: &str) -> String {
+    let mut stem = String::new();
+    let mut previous: Option<char> = None;
+
+    for c in name.chars() {
+        if c.is_ascii_uppercase()
+            && previous.is_some_and(|p| p.is_ascii_lowercase() || p.is_ascii_digit())
+        {
+            stem.push('_');
+        }
+        stem.push(c.to_ascii_lowercase());
+        previous = Some(c);
+    }
+
+    stem.replace('-', "_")
+}
+
+fn header_file(header: &Path, placement: &Placement, body: &str) -> Result<String> {
+    let style = GuardStyle::for_project()?;
+    let project_name = cmake::project_name()?;
+
+    wrap_header(
+        &style,
+        &project_name,
+        header,
+        &in_namespace(&placement.namespace, body),
+    )
+}
+
+fn in_namespace(namespace: &[String], body: &str) -> String {
+    if namespace.is_empty() {
+        return body.to_string();
+    }
+
+    let namespace = namespace.join("::");
+    let body = if body.is_empty() {
+        String::new()
+    } else {
+        format!("{}\n", body)
+    };
+
+    format!(
+        "namespace {} {{\n\n{}}}  // namespace {}\n",
+        namespace, body, namespace
+    )
+}
+
+fn write_new_file(path: &Path, contents: &str) -> Result<()> {
+    if path.exists() {
+        anyhow::bail!("'{}' already exists", path.display());
+    }
+
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)
+            .with_context(|| format!("Failed to create '{}'", parent.display()))?;
+    }
+
+    fs::write(path, contents).with_context(|| format!("Failed to create '{}'", path.display()))
+}
+
+fn register_source(source: &Path) -> Result<()> {
+    let source = source.display().to_string();
+
+    if !cmake::add_source_file(&source)? {
+        eprintln!(
+            "{}",
+            format!(
+                "No SOURCE_FILES list found in {}, add '{}' to your target manually",
+                cmake::CMAKE_LISTS,
+                source
+            )
+            .yellow()
+        );
+    }
+
+    Ok(())
+}
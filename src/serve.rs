@@ -0,0 +1,330 @@
+use anyhow::{Context, Result};
+use colorize::AnsiColor;
+use std::{
+    fs,
+    io::{BufRead, BufReader, Write},
+    net::{TcpListener, TcpStream},
+    path::{Component, Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    thread,
+    time::{Duration, SystemTime},
+};
+
+use crate::verify::coverage_report_dir;
+
+/// How often the served directories are checked for regenerated files
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// The path pages poll to learn that the files changed
+const VERSION_PATH: &str = "/__cxx_version";
+
+/// Added to every HTML page, reloads it once the served files change
+const RELOAD_SCRIPT: &str = "<script>
+(() => {
+  let version = null;
+  setInterval(async () => {
+    try {
+      const current = await (await fetch('/__cxx_version')).text();
+      if (version !== null && current !== version) location.reload();
+      version = current;
+    } catch (_) {}
+  }, 1000);
+})();
+</script>";
+
+/// A generated site and the URL prefix it's served under
+struct Site {
+    prefix: &'static str,
+    title: &'static str,
+    dir: PathBuf,
+    hint: &'static str,
+}
+
+/// Serves the Doxygen documentation and the coverage report on localhost.
+/// Open pages reload when the files are generated again.
+pub fn handle_serve(
+    port: u16,
+    docs: Option<String>,
+    coverage: Option<String>,
+    build_dir: String,
+) -> Result<()> {
+    let sites = Arc::new(vec![
+        Site {
+            prefix: "/docs/",
+            title: "Documentation",
+            dir: docs.map(PathBuf::from).unwrap_or_else(doxygen_html_dir),
+            hint: "run doxygen",
+        },
+        Site {
+            prefix: "/coverage/",
+            title: "Coverage",
+            dir: coverage
+                .map(PathBuf::from)
+                .unwrap_or_else(|| coverage_report_dir(&build_dir)),
+            hint: "run 'cxx verify' with the coverage step",
+        },
+    ]);
+    if !sites.iter().any(|site| site.dir.is_dir()) {
+        eprintln!(
+            "{}",
+            "Nothing generated yet, pages appear once docs or coverage are built".yellow()
+        );
+    }
+
+    let listener = TcpListener::bind(("127.0.0.1", port))
+        .with_context(|| format!("Failed to listen on port {}, is it in use?", port))?;
+
+    let version = Arc::new(AtomicU64::new(fingerprint(&sites)));
+    {
+        let sites = Arc::clone(&sites);
+        let version = Arc::clone(&version);
+        thread::spawn(move || loop {
+            thread::sleep(POLL_INTERVAL);
+            version.store(fingerprint(&sites), Ordering::Relaxed);
+        });
+    }
+
+    eprintln!(
+        "{}",
+        format!(
+            "Serving on http://localhost:{}/, press Ctrl+C to stop",
+            port
+        )
+        .green()
+    );
+    for site in sites.iter() {
+        let state = match site.dir.is_dir() {
+            true => format!("http://localhost:{}{}", port, site.prefix),
+            false => format!("not generated, {}", site.hint),
+        };
+        eprintln!("  {:14} {} ({})", site.title, site.dir.display(), state);
+    }
+
+    for stream in listener.incoming().filter_map(|stream| stream.ok()) {
+        let sites = Arc::clone(&sites);
+        let version = Arc::clone(&version);
+        thread::spawn(move || {
+            let _ = respond(stream, &sites, &version);
+        });
+    }
+
+    Ok(())
+}
+
+/// Doxygen writes HTML to `HTML_OUTPUT` below `OUTPUT_DIRECTORY`, as set in
+/// the project's Doxyfile
+fn doxygen_html_dir() -> PathBuf {
+    let doxyfile = fs::read_to_string("Doxyfile").unwrap_or_default();
+    let value = |key: &str| {
+        doxyfile
+            .lines()
+            .filter_map(|line| line.split_once('='))
+            .filter(|(name, _)| name.trim() == key)
+            .map(|(_, value)| value.trim().trim_matches('"').to_string())
+            .next_back()
+            .filter(|value| !value.is_empty())
+    };
+
+    let output = value("OUTPUT_DIRECTORY").unwrap_or_default();
+    let html = value("HTML_OUTPUT").unwrap_or_else(|| "html".to_string());
+    Path::new(&output).join(html)
+}
+
+/// Changes whenever a file in one of the sites is added, removed or
+/// rewritten
+fn fingerprint(sites: &[Site]) -> u64 {
+    fn visit(dir: &Path, hash: &mut u64) {
+        let Ok(entries) = fs::read_dir(dir) else {
+            return;
+        };
+        for entry in entries.filter_map(|entry| entry.ok()) {
+            let Ok(metadata) = entry.metadata() else {
+                continue;
+            };
+            if metadata.is_dir() {
+                visit(&entry.path(), hash);
+                continue;
+            }
+            let modified = metadata
+                .modified()
+                .ok()
+                .and_then(|time| time.duration_since(SystemTime::UNIX_EPOCH).ok())
+                .map(|time| time.as_nanos() as u64)
+                .unwrap_or(0);
+            // Order independent, directories list in any order
+            *hash = hash.wrapping_add(modified ^ metadata.len().rotate_left(32));
+        }
+    }
+
+    let mut hash = 0;
+    for site in sites {
+        visit(&site.dir, &mut hash);
+    }
+    hash
+}
+
+fn respond(mut stream: TcpStream, sites: &[Site], version: &AtomicU64) -> Result<()> {
+    let mut request = String::new();
+    BufReader::new(&stream).read_line(&mut request)?;
+    let mut parts = request.split_whitespace();
+    let (method, target) = (parts.next().unwrap_or(""), parts.next().unwrap_or("/"));
+    if method != "GET" && method != "HEAD" {
+        return send(&mut stream, 405, "text/plain", b"Method not allowed", false);
+    }
+    let head = method == "HEAD";
+    let path = decode(target.split(['?', '#']).next().unwrap_or("/"));
+
+    if path == VERSION_PATH {
+        let body = version.load(Ordering::Relaxed).to_string();
+        return send(&mut stream, 200, "text/plain", body.as_bytes(), head);
+    }
+    if path == "/" {
+        return send(&mut stream, 200, "text/html", index(sites).as_bytes(), head);
+    }
+
+    let Some((site, relative)) = sites
+        .iter()
+        .find_map(|site| Some((site, path.strip_prefix(site.prefix)?)))
+    else {
+        return send(&mut stream, 404, "text/plain", b"Not found", head);
+    };
+
+    // Only plain names, nothing may escape the site's directory
+    let relative = Path::new(relative);
+    if relative
+        .components()
+        .any(|component| !matches!(component, Component::Normal(_)))
+    {
+        return send(&mut stream, 403, "text/plain", b"Forbidden", head);
+    }
+    let mut file = site.dir.join(relative);
+    if file.is_dir() {
+        file.push("index.html");
+    }
+
+    match fs::read(&file) {
+        Ok(mut body) => {
+            let content_type = content_type(&file);
+            if content_type == "text/html" {
+                inject_reload(&mut body);
+            }
+            send(&mut stream, 200, content_type, &body, head)
+        }
+        Err(_) => send(&mut stream, 404, "text/plain", b"Not found", head),
+    }
+}
+
+/// Links the sites, or says how to generate the missing ones
+fn index(sites: &[Site]) -> String {
+    let items = sites
+        .iter()
+        .map(|site| match site.dir.is_dir() {
+            true => format!("<li><a href=\"{}\">{}</a></li>", site.prefix, site.title),
+            false => format!(
+                "<li>{}: not generated yet, {}</li>",
+                site.title,
+                html_escape(site.hint)
+            ),
+        })
+        .collect::<String>();
+
+    format!(
+        "<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\"><title>cxx serve</title></head>\
+         <body><ul>{}</ul>{}</body></html>\n",
+        items, RELOAD_SCRIPT
+    )
+}
+
+fn inject_reload(body: &mut Vec<u8>) {
+    let position = body
+        .windows(7)
+        .rposition(|window| window.eq_ignore_ascii_case(b"</body>"))
+        .unwrap_or(body.len());
+    body.splice(position..position, RELOAD_SCRIPT.bytes());
+}
+
+fn send(
+    stream: &mut TcpStream,
+    status: u16,
+    content_type: &str,
+    body: &[u8],
+    head: bool,
+) -> Result<()> {
+    let reason = match status {
+        200 => "OK",
+        403 => "Forbidden",
+        404 => "Not Found",
+        _ => "Method Not Allowed",
+    };
+    write!(
+        stream,
+        "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nCache-Control: no-store\r\nConnection: close\r\n\r\n",
+        status,
+        reason,
+        content_type,
+        body.len()
+    )?;
+    if !head {
+        stream.write_all(body)?;
+    }
+    stream.flush()?;
+
+    Ok(())
+}
+
+fn content_type(path: &Path) -> &'static str {
+    match path
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .unwrap_or_default()
+    {
+        "html" | "htm" => "text/html",
+        "css" => "text/css",
+        "js" => "text/javascript",
+        "json" => "application/json",
+        "svg" => "image/svg+xml",
+        "png" => "image/png",
+        "gif" => "image/gif",
+        "jpg" | "jpeg" => "image/jpeg",
+        "ico" => "image/x-icon",
+        "woff" => "font/woff",
+        "woff2" => "font/woff2",
+        "txt" | "md5" | "map" => "text/plain",
+        _ => "application/octet-stream",
+    }
+}
+
+/// Undoes percent-encoding, e.g. `%20` for spaces in file names
+fn decode(path: &str) -> String {
+    let bytes = path.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut index = 0;
+    while index < bytes.len() {
+        let escaped = (bytes[index] == b'%')
+            .then(|| path.get(index + 1..index + 3))
+            .flatten()
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match escaped {
+            Some(byte) => {
+                decoded.push(byte);
+                index += 3;
+            }
+            None => {
+                decoded.push(bytes[index]);
+                index += 1;
+            }
+        }
+    }
+
+    String::from_utf8_lossy(&decoded).to_string()
+}
+
+fn html_escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('\'', "&#39;")
+}
@@ -0,0 +1,115 @@
+use anyhow::{Context, Result};
+use colorize::AnsiColor;
+use std::{
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use crate::executor::executor;
+
+/// Suffix of the Sigstore bundle written next to each signed artifact, e.g.
+/// `app-1.2.0-linux.tar.gz.sigstore.json`
+const BUNDLE_SUFFIX: &str = ".sigstore.json";
+
+/// Fails early when artifacts are to be signed without cosign, before
+/// anything is built
+pub fn require_cosign() -> Result<()> {
+    match has_tool("cosign") {
+        true => Ok(()),
+        false => anyhow::bail!("Signing artifacts requires cosign, see https://docs.sigstore.dev"),
+    }
+}
+
+/// The Sigstore bundle of an artifact
+pub fn bundle_path(artifact: &Path) -> PathBuf {
+    let mut path = artifact.as_os_str().to_owned();
+    path.push(BUNDLE_SUFFIX);
+
+    PathBuf::from(path)
+}
+
+pub fn is_bundle(path: &Path) -> bool {
+    path.to_string_lossy().ends_with(BUNDLE_SUFFIX)
+}
+
+/// Signs an artifact keylessly with cosign: the signer logs in with an
+/// OpenID Connect provider, or CI provides its workload identity, Fulcio
+/// certifies that identity for a short-lived key, and the signature is
+/// recorded in the Rekor transparency log. The certificate, signature and
+/// log entry go into one bundle next to the artifact, which is what
+/// `verify-artifact` checks.
+pub fn sign(artifact: &Path) -> Result<PathBuf> {
+    let bundle = bundle_path(artifact);
+    executor()
+        .execute(
+            Command::new("cosign")
+                .args(["sign-blob", "--yes", "--bundle"])
+                .arg(&bundle)
+                .arg(artifact),
+        )
+        .with_context(|| format!("Failed to sign {}", artifact.display()))?;
+
+    Ok(bundle)
+}
+
+/// Checks that an artifact was signed by `identity`, an email address or,
+/// for CI, the URI of the workflow, as certified by the OpenID Connect
+/// `issuer`, and that the signature is in the transparency log. The bundle
+/// is looked for next to the artifact unless given.
+pub fn handle_verify_artifact(
+    artifact: String,
+    bundle: Option<String>,
+    identity: String,
+    issuer: String,
+) -> Result<()> {
+    require_cosign()?;
+    let artifact = PathBuf::from(artifact);
+    let bundle = bundle
+        .map(PathBuf::from)
+        .unwrap_or_else(|| bundle_path(&artifact));
+    if !artifact.is_file() {
+        anyhow::bail!("'{}' doesn't exist", artifact.display());
+    }
+    if !bundle.is_file() && !executor().dry_run() {
+        anyhow::bail!(
+            "'{}' has no signature bundle, expected '{}'",
+            artifact.display(),
+            bundle.display()
+        );
+    }
+
+    executor()
+        .execute(
+            Command::new("cosign")
+                .arg("verify-blob")
+                .arg("--bundle")
+                .arg(&bundle)
+                .args(["--certificate-identity", &identity])
+                .args(["--certificate-oidc-issuer", &issuer])
+                .arg(&artifact),
+        )
+        .with_context(|| {
+            format!(
+                "'{}' isn't signed by {} through {}",
+                artifact.display(),
+                identity,
+                issuer
+            )
+        })?;
+    if executor().dry_run() {
+        return Ok(());
+    }
+
+    eprintln!(
+        "{}",
+        format!("Verified {}, signed by {}", artifact.display(), identity).green()
+    );
+
+    Ok(())
+}
+
+fn has_tool(tool: &str) -> bool {
+    executor()
+        .capture(Command::new(tool).arg("version"))
+        .is_ok()
+}
@@ -0,0 +1,109 @@
+use anyhow::{Context, Result};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+const SOURCE_EXTENSIONS: [&str; 5] = ["c", "cc", "cpp", "cxx", "c++"];
+const HEADER_EXTENSIONS: [&str; 7] = ["h", "hh", "hpp", "hxx", "h++", "ipp", "inl"];
+
+/// Lists the C/C++ sources and headers of the project in the current
+/// directory, skipping hidden directories and configured build trees
+pub fn project_sources() -> Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+
+    collect(Path::new("."), &mut files).context("Failed to list project sources")?;
+    files.sort();
+
+    Ok(files)
+}
+
+/// Lists the C/C++ sources and headers below another directory, e.g. a
+/// dependency fetched into the build tree
+pub fn sources_in(dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+
+    collect(dir, &mut files)
+        .with_context(|| format!("Failed to list sources in '{}'", dir.display()))?;
+    files.sort();
+
+    Ok(files)
+}
+
+pub fn is_header(path: &Path) -> bool {
+    has_extension(path, &HEADER_EXTENSIONS)
+}
+
+pub fn is_source(path: &Path) -> bool {
+    has_extension(path, &SOURCE_EXTENSIONS)
+}
+
+fn has_extension(path: &Path, extensions: &[&str]) -> bool {
+    path.extension()
+        .map(|extension| extension.to_string_lossy().to_ascii_lowercase())
+        .is_some_and(|extension| extensions.contains(&extension.as_str()))
+}
+
+fn collect(dir: &Path, files: &mut Vec<PathBuf>) -> Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        let hidden = path
+            .file_name()
+            .is_some_and(|name| name.to_string_lossy().starts_with('.'));
+
+        if path.is_dir() {
+            if !hidden && !path.join("CMakeCache.txt").exists() {
+                collect(&path, files)?;
+            }
+        } else if is_source(&path) || is_header(&path) {
+            files.push(path.strip_prefix(".").unwrap_or(&path).to_path_buf());
+        }
+    }
+
+    Ok(())
+}
+
+/// Replaces `old` with `new` where it appears as a whole identifier
+pub fn replace_word(text: &str, old: &str, new: &str) -> String {
+    replace_matching(text, old, new, true)
+}
+
+/// Replaces identifiers starting with `old` by ones starting with `new`,
+/// e.g. include guards and macros derived from the project name
+pub fn replace_identifier_prefix(text: &str, old: &str, new: &str) -> String {
+    replace_matching(text, old, new, false)
+}
+
+/// Whether `word` appears in `text` as a whole identifier
+pub fn contains_word(text: &str, word: &str) -> bool {
+    text.match_indices(word).any(|(index, _)| {
+        let before = text[..index].chars().next_back();
+        let after = text[index + word.len()..].chars().next();
+
+        !before.is_some_and(is_identifier) && !after.is_some_and(is_identifier)
+    })
+}
+
+fn is_identifier(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_'
+}
+
+fn replace_matching(text: &str, old: &str, new: &str, whole_word: bool) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut offset = 0;
+
+    while let Some(index) = text[offset..].find(old).map(|index| offset + index) {
+        let before = text[..index].chars().next_back();
+        let after = text[index + old.len()..].chars().next();
+
+        let starts_word = !before.is_some_and(is_identifier);
+        let ends_word = !whole_word || !after.is_some_and(is_identifier);
+
+        result.push_str(&text[offset..index]);
+        result.push_str(if starts_word && ends_word { new } else { old });
+        offset = index + old.len();
+    }
+    result.push_str(&text[offset..]);
+
+    result
+}
@@ -0,0 +1,268 @@
+use anyhow::{Context, Result};
+use colorize::AnsiColor;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    fs,
+    io::Write,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use crate::{build_info::format_timestamp, executor::executor, manifest::STATE_DIR};
+
+/// Timings of every configure, build and test, one JSON object per line
+pub const STATS_FILE: &str = "stats.jsonl";
+
+/// Number of slowest files kept per build
+const SLOWEST_FILES: usize = 10;
+
+/// Width of the bars in the build time chart
+const CHART_WIDTH: usize = 40;
+
+#[derive(Serialize, Deserialize)]
+struct Record {
+    /// Seconds since the Unix epoch when the phase finished
+    timestamp: u64,
+    /// `configure`, `build` or `test`
+    phase: String,
+    seconds: f64,
+    /// Time spent compiling and linking, known for Ninja builds
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    compile_seconds: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    link_seconds: Option<f64>,
+    /// The sources that took longest to compile in this build
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    slowest: Vec<(String, f64)>,
+}
+
+fn stats_path(root_dir: &Path) -> PathBuf {
+    root_dir.join(STATE_DIR).join(STATS_FILE)
+}
+
+/// Where a build's entries start in the Ninja log, so only the steps of
+/// that build are attributed to it
+pub fn ninja_log_offset(build_dir: &str) -> u64 {
+    fs::metadata(Path::new(build_dir).join(".ninja_log"))
+        .map(|metadata| metadata.len())
+        .unwrap_or(0)
+}
+
+pub fn record_configure(root_dir: &Path, duration: Duration) -> Result<()> {
+    append(root_dir, new_record("configure", duration))
+}
+
+pub fn record_test(duration: Duration) -> Result<()> {
+    append(Path::new("."), new_record("test", duration))
+}
+
+/// Records a build, splitting it into compile and link time with the
+/// entries Ninja logged since `log_offset`
+pub fn record_build(duration: Duration, build_dir: &str, log_offset: u64) -> Result<()> {
+    let mut record = new_record("build", duration);
+
+    let mut compile = 0.0;
+    let mut link = 0.0;
+    let mut files = Vec::new();
+    for (output, seconds) in ninja_steps(build_dir, log_offset) {
+        match object_source(&output) {
+            Some(source) => {
+                compile += seconds;
+                files.push((source, seconds));
+            }
+            None => link += seconds,
+        }
+    }
+
+    if !files.is_empty() || link > 0.0 {
+        files.sort_by(|a, b| b.1.total_cmp(&a.1));
+        files.truncate(SLOWEST_FILES);
+
+        record.compile_seconds = Some(compile);
+        record.link_seconds = Some(link);
+        record.slowest = files;
+    }
+
+    append(Path::new("."), record)
+}
+
+/// How long each source took to compile in the steps Ninja logged since
+/// `log_offset`, empty with other generators
+pub fn compile_times(build_dir: &str, log_offset: u64) -> Vec<(String, f64)> {
+    ninja_steps(build_dir, log_offset)
+        .into_iter()
+        .filter_map(|(output, seconds)| Some((object_source(&output)?, seconds)))
+        .collect()
+}
+
+/// The outputs of the steps Ninja logged since `log_offset`, with how many
+/// seconds each took
+fn ninja_steps(build_dir: &str, log_offset: u64) -> Vec<(String, f64)> {
+    let log = fs::read(Path::new(build_dir).join(".ninja_log")).unwrap_or_default();
+    let Some(entries) = log.get(log_offset as usize..) else {
+        return Vec::new();
+    };
+
+    String::from_utf8_lossy(entries)
+        .lines()
+        .filter_map(|line| {
+            let fields = line.split('\t').collect::<Vec<_>>();
+            let start = fields.first()?.parse::<u64>().ok()?;
+            let end = fields.get(1)?.parse::<u64>().ok()?;
+            let output = fields.get(3)?;
+            Some((
+                output.to_string(),
+                end.saturating_sub(start) as f64 / 1000.0,
+            ))
+        })
+        .collect()
+}
+
+/// `CMakeFiles/app.dir/src/foo.cpp.o` was compiled from `src/foo.cpp`
+pub fn object_source(output: &str) -> Option<String> {
+    let object = output
+        .strip_suffix(".o")
+        .or_else(|| output.strip_suffix(".obj"))?;
+
+    Some(
+        object
+            .split_once(".dir/")
+            .map(|(_, source)| source)
+            .unwrap_or(object)
+            .to_string(),
+    )
+}
+
+fn new_record(phase: &str, duration: Duration) -> Record {
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|now| now.as_secs())
+        .unwrap_or(0);
+
+    Record {
+        timestamp,
+        phase: phase.to_string(),
+        seconds: duration.as_secs_f64(),
+        compile_seconds: None,
+        link_seconds: None,
+        slowest: Vec::new(),
+    }
+}
+
+fn append(root_dir: &Path, record: Record) -> Result<()> {
+    if executor().dry_run() {
+        return Ok(());
+    }
+
+    fs::create_dir_all(root_dir.join(STATE_DIR)).context("Failed to create state directory")?;
+    let line = serde_json::to_string(&record).context("Failed to serialize statistics")?;
+
+    fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(stats_path(root_dir))
+        .and_then(|mut file| writeln!(file, "{}", line))
+        .context("Failed to record statistics")
+}
+
+fn load() -> Result<Vec<Record>> {
+    let Ok(text) = fs::read_to_string(stats_path(Path::new("."))) else {
+        return Ok(Vec::new());
+    };
+
+    // Skip lines that don't parse, e.g. one cut short by an interrupted write
+    Ok(text
+        .lines()
+        .filter_map(|line| serde_json::from_str(line).ok())
+        .collect())
+}
+
+/// Charts the duration of the last builds and lists the slowest files
+pub fn handle_build_stats(last: usize) -> Result<()> {
+    let records = load()?;
+
+    let builds = records
+        .iter()
+        .filter(|record| record.phase == "build")
+        .collect::<Vec<_>>();
+    if builds.is_empty() {
+        anyhow::bail!("No builds recorded yet, run 'cxx build' first");
+    }
+    let builds = &builds[builds.len().saturating_sub(last)..];
+
+    let total = builds.iter().map(|build| build.seconds).sum::<f64>();
+    let fastest = builds
+        .iter()
+        .map(|build| build.seconds)
+        .fold(f64::MAX, f64::min);
+    let slowest = builds.iter().map(|build| build.seconds).fold(0.0, f64::max);
+
+    eprintln!(
+        "{}",
+        format!(
+            "Last {} builds: average {:.1}s, fastest {:.1}s, slowest {:.1}s",
+            builds.len(),
+            total / builds.len() as f64,
+            fastest,
+            slowest
+        )
+        .bold()
+    );
+    for build in builds {
+        let width = if slowest > 0.0 {
+            ((build.seconds / slowest) * CHART_WIDTH as f64).round() as usize
+        } else {
+            0
+        };
+        let split = match (build.compile_seconds, build.link_seconds) {
+            (Some(compile), Some(link)) => {
+                format!("  compile {:.1}s, link {:.1}s", compile, link)
+            }
+            _ => String::new(),
+        };
+
+        println!(
+            "{}  {:>7.1}s  {}{}",
+            format_timestamp(build.timestamp),
+            build.seconds,
+            "#".repeat(width.max(1)).cyan(),
+            split.grey()
+        );
+    }
+
+    for phase in ["configure", "test"] {
+        let runs = records
+            .iter()
+            .filter(|record| record.phase == phase)
+            .map(|record| record.seconds)
+            .collect::<Vec<_>>();
+        if !runs.is_empty() {
+            println!(
+                "{}: average {:.1}s over {} runs",
+                phase,
+                runs.iter().sum::<f64>() / runs.len() as f64,
+                runs.len()
+            );
+        }
+    }
+
+    let mut files: BTreeMap<&str, f64> = BTreeMap::new();
+    for build in builds {
+        for (file, seconds) in &build.slowest {
+            let slowest = files.entry(file).or_default();
+            *slowest = slowest.max(*seconds);
+        }
+    }
+    if !files.is_empty() {
+        let mut files = files.into_iter().collect::<Vec<_>>();
+        files.sort_by(|a, b| b.1.total_cmp(&a.1));
+
+        println!("\nSlowest files:");
+        for (file, seconds) in files.into_iter().take(SLOWEST_FILES) {
+            println!("  {:>7.1}s  {}", seconds, file);
+        }
+    }
+
+    Ok(())
+}
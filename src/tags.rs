@@ -0,0 +1,167 @@
+use anyhow::{Context, Result};
+use colorize::AnsiColor;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    process::Command,
+    time::SystemTime,
+};
+
+use crate::{executor::executor, manifest::STATE_DIR, sources};
+
+/// The files the last tags were generated from, relative to the state directory
+const FILE_LIST: &str = "tags_files";
+
+/// The files the formats write into the project root, GNU global's included
+pub const TAG_FILES: [&str; 5] = ["tags", "TAGS", "GTAGS", "GRTAGS", "GPATH"];
+
+#[derive(Clone, Copy)]
+enum Format {
+    Ctags,
+    Etags,
+    Global,
+}
+
+impl Format {
+    fn parse(format: &str) -> Result<Format> {
+        match format.to_ascii_lowercase().as_str() {
+            "ctags" => Ok(Format::Ctags),
+            "etags" => Ok(Format::Etags),
+            "global" | "gtags" => Ok(Format::Global),
+            _ => anyhow::bail!("Valid tag formats are 'ctags', 'etags' and 'global'"),
+        }
+    }
+
+    /// The file the format writes into the project root
+    fn output(self) -> &'static str {
+        match self {
+            Format::Ctags => "tags",
+            Format::Etags => "TAGS",
+            Format::Global => "GTAGS",
+        }
+    }
+
+    /// GNU global updates its database incrementally on its own, the
+    /// ctags formats are rewritten
+    fn command(self, list: &Path) -> Command {
+        let mut command = match self {
+            Format::Ctags => Command::new("ctags"),
+            Format::Etags => Command::new("ctags"),
+            Format::Global => Command::new("gtags"),
+        };
+        match self {
+            Format::Ctags => command
+                .args([
+                    "--languages=C,C++",
+                    "--c++-kinds=+p",
+                    "--fields=+iaS",
+                    "--extras=+q",
+                    "-L",
+                ])
+                .arg(list)
+                .args(["-f", "tags"]),
+            Format::Etags => command
+                .args(["-e", "--languages=C,C++", "-L"])
+                .arg(list)
+                .args(["-f", "TAGS"]),
+            Format::Global => command.args(["-i", "-f"]).arg(list),
+        };
+
+        command
+    }
+}
+
+/// Generates a tags file or GNU global database for the project sources and
+/// the headers of dependencies fetched into the build directory
+pub fn handle_tags(format: String, build_dir: String) -> Result<()> {
+    let format = Format::parse(&format)?;
+    let files = tagged_files(&build_dir)?;
+    generate(format, &files)?;
+
+    eprintln!(
+        "{}",
+        format!("Tagged {} files into {}", files.len(), format.output()).green()
+    );
+
+    Ok(())
+}
+
+/// Regenerates the tags after a build when the project has them and a file
+/// was added, removed or changed since they were written
+pub fn refresh_tags(build_dir: &str) -> Result<()> {
+    let existing = [Format::Global, Format::Ctags, Format::Etags]
+        .into_iter()
+        .filter(|format| Path::new(format.output()).exists())
+        .collect::<Vec<_>>();
+    if existing.is_empty() {
+        return Ok(());
+    }
+
+    let files = tagged_files(build_dir)?;
+    let previous = fs::read_to_string(list_path()).unwrap_or_default();
+    let listed = files == previous.lines().map(PathBuf::from).collect::<Vec<_>>();
+    for format in existing {
+        let written = modified(Path::new(format.output()));
+        let changed = files.iter().any(|file| modified(file) > written);
+        if !listed || changed {
+            generate(format, &files)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Project sources and headers, then those of fetched dependencies
+fn tagged_files(build_dir: &str) -> Result<Vec<PathBuf>> {
+    let mut files = sources::project_sources()?;
+
+    let deps_dir = Path::new(build_dir).join("_deps");
+    if let Ok(entries) = fs::read_dir(&deps_dir) {
+        let mut sources_dirs = entries
+            .flatten()
+            .map(|entry| entry.path())
+            .filter(|path| {
+                path.is_dir()
+                    && path
+                        .file_name()
+                        .is_some_and(|name| name.to_string_lossy().ends_with("-src"))
+            })
+            .collect::<Vec<_>>();
+        sources_dirs.sort();
+        for dir in sources_dirs {
+            files.extend(
+                sources::sources_in(&dir)?
+                    .into_iter()
+                    .filter(|file| sources::is_header(file)),
+            );
+        }
+    }
+
+    Ok(files)
+}
+
+fn generate(format: Format, files: &[PathBuf]) -> Result<()> {
+    let list = list_path();
+    if !executor().dry_run() {
+        fs::create_dir_all(STATE_DIR).context("Failed to create state directory")?;
+        let text = files
+            .iter()
+            .map(|file| format!("{}\n", file.display()))
+            .collect::<String>();
+        fs::write(&list, text).context("Failed to write the list of tagged files")?;
+    }
+
+    executor()
+        .execute(&mut format.command(&list))
+        .with_context(|| format!("Failed to generate {}", format.output()))
+}
+
+fn list_path() -> PathBuf {
+    Path::new(STATE_DIR).join(FILE_LIST)
+}
+
+fn modified(path: &Path) -> SystemTime {
+    fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .unwrap_or(SystemTime::UNIX_EPOCH)
+}
@@ -0,0 +1,257 @@
+use anyhow::{Context, Result};
+use colorize::AnsiColor;
+use std::fmt::Write;
+
+use crate::{
+    cmake, glob,
+    manifest::{Manifest, TargetConfig, MANIFEST_FILE},
+    validate_project_name,
+};
+
+/// Fragment the manifest targets are generated into
+pub const TARGETS_FRAGMENT: &str = "targets.cmake";
+
+/// Generates the `[target.<name>]` tables of the manifest into a CMake
+/// fragment included by CMakeLists.txt. Source globs are expanded here, so
+/// re-run it after adding or removing files.
+pub fn handle_sync() -> Result<()> {
+    let manifest = Manifest::load()?;
+    if manifest.target.is_empty() {
+        anyhow::bail!(
+            "{} declares no targets, add a [target.<name>] table first",
+            MANIFEST_FILE
+        );
+    }
+
+    let project_name = cmake::project_name()?;
+    let c_project = cmake::is_c_project()?;
+
+    let mut fragment = format!(
+        "# Generated by cxx sync from {}, do not edit.\n",
+        MANIFEST_FILE
+    );
+    for (name, target) in &manifest.target {
+        validate_project_name(name).with_context(|| format!("Invalid target name '{}'", name))?;
+        fragment.push('\n');
+        fragment.push_str(&target_commands(
+            name,
+            target,
+            name == &project_name,
+            c_project,
+        )?);
+    }
+
+    cmake::write_fragment(TARGETS_FRAGMENT, &fragment)?;
+
+    eprintln!(
+        "{}",
+        format!(
+            "Synced {} targets into {}/{}, re-run init to pick them up",
+            manifest.target.len(),
+            cmake::FRAGMENT_DIR,
+            TARGETS_FRAGMENT
+        )
+        .green()
+    );
+
+    Ok(())
+}
+
+/// The commands declaring one target. Options of libraries are public so
+/// the targets linking them inherit their include directories and
+/// definitions.
+fn target_commands(
+    name: &str,
+    target: &TargetConfig,
+    is_project_target: bool,
+    c_project: bool,
+) -> Result<String> {
+    check_kind(name, &target.kind)?;
+    let (command, kind, scope) = match target.kind.as_str() {
+        "executable" => ("add_executable", "", "PRIVATE"),
+        "static" => ("add_library", " STATIC", "PUBLIC"),
+        "shared" => ("add_library", " SHARED", "PUBLIC"),
+        _ => ("add_library", " INTERFACE", "INTERFACE"),
+    };
+
+    let mut sources = Vec::new();
+    for pattern in &target.sources {
+        let matches = glob::expand(pattern)?;
+        if matches.is_empty() {
+            anyhow::bail!("Sources '{}' of target '{}' match no files", pattern, name);
+        }
+        sources.extend(
+            matches
+                .iter()
+                .map(|file| project_path(&file.display().to_string())),
+        );
+    }
+
+    let mut commands = String::new();
+    if is_project_target {
+        if !sources.is_empty() {
+            writeln!(
+                commands,
+                "target_sources({} PRIVATE\n  {})",
+                name,
+                sources.join("\n  ")
+            )?;
+        }
+    } else if target.kind == "interface" {
+        writeln!(commands, "{}({}{})", command, name, kind)?;
+    } else {
+        if sources.is_empty() {
+            anyhow::bail!("Target '{}' has no sources", name);
+        }
+        writeln!(
+            commands,
+            "{}({}{}\n  {})",
+            command,
+            name,
+            kind,
+            sources.join("\n  ")
+        )?;
+    }
+
+    if !target.include_dirs.is_empty() {
+        // Libraries pass their include directories on, also when installed
+        // by `add package`, which only allows paths into the source tree
+        // while building
+        let dirs = target
+            .include_dirs
+            .iter()
+            .map(|dir| match target.kind.as_str() {
+                "executable" => project_path(dir),
+                _ => format!(
+                    "\"$<BUILD_INTERFACE:{}>\"",
+                    project_path(dir).trim_matches('"')
+                ),
+            })
+            .collect::<Vec<_>>();
+        writeln!(
+            commands,
+            "target_include_directories({} {}\n  {})",
+            name,
+            scope,
+            dirs.join("\n  ")
+        )?;
+    }
+    if !target.definitions.is_empty() {
+        let definitions = target
+            .definitions
+            .iter()
+            .map(|definition| quote(definition))
+            .collect::<Vec<_>>();
+        writeln!(
+            commands,
+            "target_compile_definitions({} {}\n  {})",
+            name,
+            scope,
+            definitions.join("\n  ")
+        )?;
+    }
+    if !target.link.is_empty() {
+        writeln!(
+            commands,
+            "target_link_libraries({} {}\n  {})",
+            name,
+            scope,
+            target.link.join("\n  ")
+        )?;
+    }
+    if let Some(standard) = &target.standard {
+        let (language, version, extensions) = parse_standard(standard, c_project)
+            .with_context(|| format!("Invalid standard for target '{}'", name))?;
+        if target.kind == "interface" {
+            let feature = if language == "C" {
+                "c_std_"
+            } else {
+                "cxx_std_"
+            };
+            writeln!(
+                commands,
+                "target_compile_features({} INTERFACE {}{})",
+                name, feature, version
+            )?;
+        } else {
+            writeln!(
+                commands,
+                "set_target_properties({} PROPERTIES\n  {}_STANDARD {}\n  {}_STANDARD_REQUIRED ON\n  {}_EXTENSIONS {})",
+                name,
+                language,
+                version,
+                language,
+                language,
+                if extensions { "ON" } else { "OFF" }
+            )?;
+        }
+    }
+
+    Ok(commands)
+}
+
+pub fn check_kind(name: &str, kind: &str) -> Result<()> {
+    if !["executable", "static", "shared", "interface"].contains(&kind) {
+        anyhow::bail!(
+            "Invalid kind '{}' for target '{}', valid kinds are 'executable', 'static', 'shared' and 'interface'",
+            kind,
+            name
+        );
+    }
+
+    Ok(())
+}
+
+/// Reads standards such as `c++20`, `gnu++17`, `c11` or a bare `20`, which
+/// is taken to be the project's language. Returns the CMake language, the
+/// version and whether GNU extensions are enabled.
+fn parse_standard(standard: &str, c_project: bool) -> Result<(&'static str, String, bool)> {
+    let lower = standard.to_ascii_lowercase();
+    let (language, version, extensions) = if let Some(version) = lower.strip_prefix("gnu++") {
+        ("CXX", version, true)
+    } else if let Some(version) = lower
+        .strip_prefix("c++")
+        .or_else(|| lower.strip_prefix("cxx"))
+    {
+        ("CXX", version, false)
+    } else if let Some(version) = lower.strip_prefix("gnu") {
+        ("C", version, true)
+    } else if let Some(version) = lower.strip_prefix('c') {
+        ("C", version, false)
+    } else {
+        (if c_project { "C" } else { "CXX" }, lower.as_str(), false)
+    };
+
+    if version.is_empty() || !version.chars().all(|c| c.is_ascii_digit()) {
+        anyhow::bail!(
+            "'{}' is not a language standard, e.g. 'c++20' or 'c11'",
+            standard
+        );
+    }
+
+    Ok((language, version.to_string(), extensions))
+}
+
+/// The compiler flag selecting a standard, e.g. `-std=gnu++17`
+pub fn standard_flag(standard: &str, c_project: bool) -> Result<String> {
+    let (language, version, extensions) = parse_standard(standard, c_project)?;
+    let prefix = match (language, extensions) {
+        ("C", false) => "c",
+        ("C", true) => "gnu",
+        (_, false) => "c++",
+        (_, true) => "gnu++",
+    };
+
+    Ok(format!("-std={}{}", prefix, version))
+}
+
+fn project_path(path: &str) -> String {
+    quote(&format!(
+        "${{CMAKE_CURRENT_SOURCE_DIR}}/{}",
+        path.trim_start_matches("./")
+    ))
+}
+
+fn quote(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
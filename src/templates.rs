@@ -0,0 +1,396 @@
+use anyhow::{Context, Result};
+use colorize::AnsiColor;
+use serde::Deserialize;
+use std::{
+    fs,
+    io::{BufRead, IsTerminal, Write},
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use crate::{
+    cmake,
+    config::GlobalConfig,
+    executor::{self, executor},
+    layout,
+};
+
+/// The manifest of a template directory, which isn't copied into projects
+const TEMPLATE_MANIFEST: &str = "template.toml";
+
+/// Sample projects `new --template` starts from instead of hello world
+#[derive(Clone)]
+pub enum Template {
+    /// A coroutine generator and a sender pipeline running on a thread pool
+    Async,
+    /// A directory whose files are copied over the scaffolded project
+    Custom(PathBuf),
+}
+
+pub fn parse_template(template: &str) -> Result<Template> {
+    match template.to_ascii_lowercase().as_str() {
+        "async" => Ok(Template::Async),
+        _ if Path::new(template).is_dir() => fs::canonicalize(template)
+            .map(Template::Custom)
+            .with_context(|| format!("Failed to read template '{}'", template)),
+        _ => anyhow::bail!("Valid templates are 'async' and template directories"),
+    }
+}
+
+/// `template.toml` of a template directory
+#[derive(Deserialize, Default)]
+struct TemplateManifest {
+    /// Runs in the generated project before it's configured, e.g. to fetch
+    /// assets or rename files
+    post_generate: Option<Hook>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Hook {
+    /// A script in the template directory, run with the shell
+    Script(String),
+    /// Command lines, each run with the shell
+    Commands(Vec<String>),
+}
+
+impl TemplateManifest {
+    fn load(dir: &Path) -> Result<Self> {
+        let path = dir.join(TEMPLATE_MANIFEST);
+        if !path.is_file() {
+            return Ok(Self::default());
+        }
+        let text = fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+
+        toml::from_str(&text).with_context(|| format!("Failed to parse {}", path.display()))
+    }
+
+    /// The hook's script, which has to be inside the template so a template
+    /// can't point at files elsewhere on the machine
+    fn script(&self, dir: &Path) -> Result<Option<PathBuf>> {
+        let Some(Hook::Script(script)) = &self.post_generate else {
+            return Ok(None);
+        };
+        let path = fs::canonicalize(dir.join(script)).with_context(|| {
+            format!(
+                "The post-generate script '{}' of template '{}' doesn't exist",
+                script,
+                dir.display()
+            )
+        })?;
+        if !path.starts_with(dir) || !path.is_file() {
+            anyhow::bail!(
+                "The post-generate script '{}' must be a file in template '{}'",
+                script,
+                dir.display()
+            );
+        }
+
+        Ok(Some(path))
+    }
+}
+
+/// Fails before anything is created when a template's hook can't run: its
+/// script is missing, or there's no terminal to confirm it at and it isn't
+/// trusted
+pub fn check_hooks(template: &Template, trusted: bool) -> Result<()> {
+    let Template::Custom(dir) = template else {
+        return Ok(());
+    };
+    let manifest = TemplateManifest::load(dir)?;
+    manifest.script(dir)?;
+    if manifest.post_generate.is_some()
+        && !trusted
+        && !executor().dry_run()
+        && !std::io::stdin().is_terminal()
+    {
+        anyhow::bail!(
+            "Template '{}' has a post-generate hook, which can't be confirmed without a terminal, pass --trust-template to run it",
+            dir.display()
+        );
+    }
+
+    Ok(())
+}
+
+/// stdexec, the reference implementation of senders and receivers, has no
+/// releases yet. Its headers are marked as system headers so the template's
+/// warnings, errors with -Werror, don't apply to them. stdexec needs MSVC's
+/// conforming preprocessor, and GCC 10 needs coroutines turned on.
+const ASYNC_FRAGMENT: &str = "# Generated by cxx new --template async
+include(FetchContent)
+set(STDEXEC_BUILD_EXAMPLES OFF CACHE BOOL \"\" FORCE)
+set(STDEXEC_BUILD_TESTS OFF CACHE BOOL \"\" FORCE)
+FetchContent_Declare(stdexec
+  GIT_REPOSITORY https://github.com/NVIDIA/stdexec.git
+  GIT_TAG main
+  GIT_SHALLOW TRUE)
+FetchContent_MakeAvailable(stdexec)
+
+get_target_property(stdexec_include_dirs stdexec INTERFACE_INCLUDE_DIRECTORIES)
+set_target_properties(stdexec PROPERTIES
+  INTERFACE_SYSTEM_INCLUDE_DIRECTORIES \"${stdexec_include_dirs}\")
+target_link_libraries(${PROJECT_NAME} PRIVATE STDEXEC::stdexec)
+
+if(MSVC)
+  target_compile_options(${PROJECT_NAME} PRIVATE /Zc:__cplusplus /Zc:preprocessor)
+elseif(CMAKE_CXX_COMPILER_ID STREQUAL \"GNU\" AND CMAKE_CXX_COMPILER_VERSION VERSION_LESS 11)
+  target_compile_options(${PROJECT_NAME} PRIVATE -fcoroutines)
+endif()
+";
+
+const ASYNC_MAIN: &str = "// Copyright (c) 2024 Author. All Rights Reserved.
+#include <coroutine>
+#include <exception>
+#include <iostream>
+#include <utility>
+
+#include <exec/static_thread_pool.hpp>
+#include <stdexec/execution.hpp>
+
+// A lazy sequence of values a coroutine produces with co_yield
+template <typename T>
+class Generator {
+ public:
+  struct promise_type {
+    T current{};
+
+    Generator get_return_object() {
+      return Generator{std::coroutine_handle<promise_type>::from_promise(*this)};
+    }
+    std::suspend_always initial_suspend() noexcept { return {}; }
+    std::suspend_always final_suspend() noexcept { return {}; }
+    std::suspend_always yield_value(T value) {
+      current = std::move(value);
+      return {};
+    }
+    void return_void() {}
+    void unhandled_exception() { throw; }
+  };
+
+  explicit Generator(std::coroutine_handle<promise_type> handle) : handle_(handle) {}
+  Generator(Generator&& other) noexcept : handle_(std::exchange(other.handle_, {})) {}
+  Generator(const Generator&) = delete;
+  Generator& operator=(const Generator&) = delete;
+  Generator& operator=(Generator&&) = delete;
+  ~Generator() {
+    if (handle_) {
+      handle_.destroy();
+    }
+  }
+
+  // Runs the coroutine to its next co_yield, false once it's finished
+  bool next() {
+    handle_.resume();
+    return !handle_.done();
+  }
+  const T& value() const { return handle_.promise().current; }
+
+ private:
+  std::coroutine_handle<promise_type> handle_;
+};
+
+Generator<long> fibonacci(int count) {
+  long current = 0;
+  long next = 1;
+  for (int i = 0; i < count; ++i) {
+    co_yield current;
+    current = std::exchange(next, current + next);
+  }
+}
+
+int main() {
+  auto numbers = fibonacci(10);
+  while (numbers.next()) {
+    std::cout << numbers.value() << ' ';
+  }
+  std::cout << '\\n';
+
+  // Senders describe work and a scheduler decides where it runs, here the
+  // two halves run on the pool's threads before their results are combined
+  exec::static_thread_pool pool(2);
+  auto scheduler = pool.get_scheduler();
+  auto work = stdexec::when_all(stdexec::schedule(scheduler) | stdexec::then([] { return 6; }),
+                                stdexec::schedule(scheduler) | stdexec::then([] { return 7; })) |
+              stdexec::then([](int left, int right) { return left * right; });
+  auto [answer] = stdexec::sync_wait(std::move(work)).value();
+  std::cout << \"The answer is \" << answer << '\\n';
+
+  return 0;
+}
+";
+
+/// Replaces the main source of a freshly scaffolded C++ CMake project with
+/// the template's and adds the CMake setup it needs
+pub fn write_template(template: &Template, project_dir: &str, src_dir: &str) -> Result<()> {
+    match template {
+        Template::Async => {
+            fs::write(
+                Path::new(project_dir).join(src_dir).join("main.cpp"),
+                ASYNC_MAIN,
+            )
+            .context("Failed to create main source file")?;
+            cmake::write_fragment_in(Path::new(project_dir), "async.cmake", ASYNC_FRAGMENT)
+        }
+        // Copied by `generate` once the whole project is scaffolded, so its
+        // files replace any the project starts with
+        Template::Custom(_) => Ok(()),
+    }
+}
+
+/// Copies a template directory over a scaffolded project and runs its
+/// post-generate hook there, once it's confirmed. `{{name}}` and
+/// `{{identifier}}` in file names, file contents and hook commands become
+/// the project's name and the name as a C/C++ identifier.
+pub fn generate(template: &Template, name: &str, project_dir: &str, trusted: bool) -> Result<()> {
+    let Template::Custom(dir) = template else {
+        return Ok(());
+    };
+    let manifest = TemplateManifest::load(dir)?;
+    let script = manifest.script(dir)?;
+    let substitute = |text: &str| {
+        text.replace("{{name}}", name)
+            .replace("{{identifier}}", &layout::identifier(name))
+    };
+
+    let mut skipped = vec![dir.join(TEMPLATE_MANIFEST)];
+    skipped.extend(script.clone());
+    copy_template(dir, dir, Path::new(project_dir), &skipped, &substitute)?;
+
+    let commands = match (&manifest.post_generate, script) {
+        (Some(Hook::Commands(lines)), _) => lines
+            .iter()
+            .map(|line| executor().shell(&substitute(line)))
+            .collect(),
+        (_, Some(script)) => {
+            let shell = GlobalConfig::load()?.shell;
+            let mut command = Command::new(&shell);
+            if executor::is_cmd(&shell) {
+                command.arg("/C");
+            }
+            command.arg(script);
+            vec![command]
+        }
+        _ => return Ok(()),
+    };
+    if !trusted && !executor().dry_run() && !confirm(&manifest, dir, project_dir, &substitute)? {
+        eprintln!(
+            "{}",
+            format!(
+                "Skipped the post-generate hook of template '{}'",
+                dir.display()
+            )
+            .yellow()
+        );
+        return Ok(());
+    }
+
+    for mut command in commands {
+        executor()
+            .execute(
+                command
+                    .current_dir(project_dir)
+                    .env("CPP_TOOLS_PROJECT_NAME", name)
+                    .env("CPP_TOOLS_TEMPLATE_DIR", dir),
+            )
+            .with_context(|| {
+                format!(
+                    "The post-generate hook of template '{}' failed",
+                    dir.display()
+                )
+            })?;
+    }
+
+    Ok(())
+}
+
+/// Shows what the hook runs, the script's contents or its command lines,
+/// and asks whether to run it
+fn confirm(
+    manifest: &TemplateManifest,
+    dir: &Path,
+    project_dir: &str,
+    substitute: &dyn Fn(&str) -> String,
+) -> Result<bool> {
+    eprintln!(
+        "{}",
+        format!(
+            "Template '{}' runs this in '{}' after generating it:",
+            dir.display(),
+            project_dir
+        )
+        .yellow()
+    );
+    match &manifest.post_generate {
+        Some(Hook::Script(script)) => {
+            let path = dir.join(script);
+            let contents = fs::read_to_string(&path)
+                .with_context(|| format!("Failed to read {}", path.display()))?;
+            eprintln!("  {}", script);
+            for line in contents.lines() {
+                eprintln!("  | {}", line);
+            }
+        }
+        Some(Hook::Commands(lines)) => {
+            for line in lines {
+                eprintln!("  {}", substitute(line));
+            }
+        }
+        None => {}
+    }
+    eprint!("Run it? [y/N] ");
+    std::io::stderr().flush()?;
+
+    let mut answer = String::new();
+    std::io::stdin()
+        .lock()
+        .read_line(&mut answer)
+        .context("Failed to read the answer")?;
+
+    Ok(matches!(
+        answer.trim().to_ascii_lowercase().as_str(),
+        "y" | "yes"
+    ))
+}
+
+fn copy_template(
+    root: &Path,
+    from: &Path,
+    to: &Path,
+    skipped: &[PathBuf],
+    substitute: &dyn Fn(&str) -> String,
+) -> Result<()> {
+    for entry in fs::read_dir(from).with_context(|| format!("Failed to read {}", from.display()))? {
+        let entry = entry?;
+        let path = entry.path();
+        if entry.file_name() == ".git" || skipped.contains(&path) {
+            continue;
+        }
+        let target = to.join(substitute(&entry.file_name().to_string_lossy()));
+        if path.is_dir() {
+            copy_template(root, &path, &target, skipped, substitute)?;
+            continue;
+        }
+
+        let relative = path.strip_prefix(root).unwrap_or(&path);
+        if executor().dry_run() {
+            eprintln!("Would copy {} to {}", relative.display(), target.display());
+            continue;
+        }
+
+        fs::create_dir_all(to).with_context(|| format!("Failed to create {}", to.display()))?;
+        let contents =
+            fs::read(&path).with_context(|| format!("Failed to read {}", relative.display()))?;
+        // Binary files such as images are copied as they are
+        let contents = match String::from_utf8(contents) {
+            Ok(text) => substitute(&text).into_bytes(),
+            Err(error) => error.into_bytes(),
+        };
+        fs::write(&target, contents)
+            .and_then(|_| fs::set_permissions(&target, entry.metadata()?.permissions()))
+            .with_context(|| format!("Failed to create {}", target.display()))?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,190 @@
+use anyhow::{Context, Result};
+use colorize::AnsiColor;
+use std::{fs, path::Path};
+
+use crate::{
+    cmake::{self, CMAKE_LISTS, FRAGMENT_DIR},
+    executor::executor,
+    glob,
+};
+
+/// Directory holding the unit tests
+const TESTS_DIR: &str = "tests";
+
+/// Fragment building the unit tests and registering them with CTest
+const TESTS_FRAGMENT: &str = "tests.cmake";
+
+/// Catch2 builds every file in `tests/` into one executable and registers
+/// each test case with CTest. The project's warnings-as-errors are left out
+/// of Catch2's own sources. An executable can't be linked against, so its
+/// sources apart from main are compiled into the tests.
+const CATCH2_FRAGMENT: &str = "# Generated by cxx test, do not edit.
+enable_testing()
+
+include(FetchContent)
+FetchContent_Declare(Catch2
+  GIT_REPOSITORY https://github.com/catchorg/Catch2.git
+  GIT_TAG v3.7.1)
+get_directory_property(TESTS_COMPILE_OPTIONS COMPILE_OPTIONS)
+set_directory_properties(PROPERTIES COMPILE_OPTIONS \"\")
+FetchContent_MakeAvailable(Catch2)
+set_directory_properties(PROPERTIES COMPILE_OPTIONS \"${TESTS_COMPILE_OPTIONS}\")
+include(Catch)
+
+file(GLOB TEST_SOURCES CONFIGURE_DEPENDS ${CMAKE_CURRENT_SOURCE_DIR}/tests/*.cpp)
+
+get_target_property(PROJECT_TARGET_TYPE ${PROJECT_NAME} TYPE)
+set(TEST_PROJECT_SOURCES ${SOURCE_FILES})
+list(FILTER TEST_PROJECT_SOURCES EXCLUDE REGEX \"(^|/)main\\\\.(c|cpp)$\")
+add_executable(tests ${TEST_SOURCES})
+set_target_properties(tests PROPERTIES
+  RUNTIME_OUTPUT_DIRECTORY ${CMAKE_RUNTIME_OUTPUT_DIRECTORY}/tests)
+target_link_libraries(tests PRIVATE Catch2::Catch2WithMain)
+if(PROJECT_TARGET_TYPE STREQUAL \"EXECUTABLE\")
+  target_sources(tests PRIVATE ${TEST_PROJECT_SOURCES})
+else()
+  target_link_libraries(tests PRIVATE ${PROJECT_NAME})
+endif()
+catch_discover_tests(tests WORKING_DIRECTORY ${CMAKE_CURRENT_SOURCE_DIR})
+";
+
+/// Unity tests have a main of their own, so every file in `tests/` becomes
+/// an executable registered as one CTest test
+const UNITY_FRAGMENT: &str = "# Generated by cxx test, do not edit.
+enable_testing()
+
+include(FetchContent)
+FetchContent_Declare(Unity
+  GIT_REPOSITORY https://github.com/ThrowTheSwitch/Unity.git
+  GIT_TAG v2.6.0)
+get_directory_property(TESTS_COMPILE_OPTIONS COMPILE_OPTIONS)
+set_directory_properties(PROPERTIES COMPILE_OPTIONS \"\")
+FetchContent_MakeAvailable(Unity)
+set_directory_properties(PROPERTIES COMPILE_OPTIONS \"${TESTS_COMPILE_OPTIONS}\")
+
+file(GLOB TEST_SOURCES CONFIGURE_DEPENDS ${CMAKE_CURRENT_SOURCE_DIR}/tests/*.c)
+
+get_target_property(PROJECT_TARGET_TYPE ${PROJECT_NAME} TYPE)
+set(TEST_PROJECT_SOURCES ${SOURCE_FILES})
+list(FILTER TEST_PROJECT_SOURCES EXCLUDE REGEX \"(^|/)main\\\\.(c|cpp)$\")
+foreach(test_source ${TEST_SOURCES})
+  get_filename_component(test ${test_source} NAME_WE)
+  add_executable(test_${test} ${test_source})
+  set_target_properties(test_${test} PROPERTIES
+    OUTPUT_NAME ${test}
+    RUNTIME_OUTPUT_DIRECTORY ${CMAKE_RUNTIME_OUTPUT_DIRECTORY}/tests)
+  target_link_libraries(test_${test} PRIVATE unity)
+  if(PROJECT_TARGET_TYPE STREQUAL \"EXECUTABLE\")
+    target_sources(test_${test} PRIVATE ${TEST_PROJECT_SOURCES})
+  else()
+    target_link_libraries(test_${test} PRIVATE ${PROJECT_NAME})
+  endif()
+  add_test(NAME ${test} COMMAND test_${test} WORKING_DIRECTORY ${CMAKE_CURRENT_SOURCE_DIR})
+endforeach()
+";
+
+/// Sets up unit tests in a project generated by cxx that registers none:
+/// Catch2 for C++ or Unity for C, fetched with FetchContent, and a first
+/// test in `tests/` to start from. Projects with tests of their own are
+/// left alone.
+pub fn scaffold_tests() -> Result<()> {
+    if cmake::is_foreign_project() || has_tests()? {
+        return Ok(());
+    }
+
+    let name = cmake::project_name()?;
+    let (path, source, fragment, framework) = match cmake::is_c_project()? {
+        true => (
+            Path::new(TESTS_DIR).join(format!("test_{}.c", name)),
+            unity_test(&name),
+            UNITY_FRAGMENT,
+            "Unity",
+        ),
+        false => (
+            Path::new(TESTS_DIR).join(format!("test_{}.cpp", name)),
+            catch2_test(&name),
+            CATCH2_FRAGMENT,
+            "Catch2",
+        ),
+    };
+    if executor().dry_run() {
+        eprintln!(
+            "Would set up {} tests in {}/ with {} and {}/{}",
+            framework,
+            TESTS_DIR,
+            path.display(),
+            FRAGMENT_DIR,
+            TESTS_FRAGMENT
+        );
+        return Ok(());
+    }
+    if !path.exists() {
+        fs::create_dir_all(TESTS_DIR).context("Failed to create tests directory")?;
+        fs::write(&path, source).with_context(|| format!("Failed to write {}", path.display()))?;
+    }
+    cmake::write_fragment(TESTS_FRAGMENT, fragment)?;
+
+    eprintln!(
+        "{}",
+        format!(
+            "Set up {} tests in {}/, add test files there",
+            framework, TESTS_DIR
+        )
+        .green()
+    );
+
+    Ok(())
+}
+
+/// Whether the project's CMake files already register tests with CTest
+fn has_tests() -> Result<bool> {
+    let mut files = vec![Path::new(CMAKE_LISTS).to_path_buf()];
+    if Path::new(FRAGMENT_DIR).is_dir() {
+        files.extend(glob::expand(&format!("{}/*.cmake", FRAGMENT_DIR))?);
+    }
+
+    Ok(files.iter().any(|file| {
+        fs::read_to_string(file).is_ok_and(|text| {
+            text.lines()
+                .map(|line| line.split('#').next().unwrap_or_default().trim())
+                .any(|line| {
+                    line.starts_with("enable_testing(")
+                        || line.starts_with("include(CTest)")
+                        || line.starts_with("add_test(")
+                })
+        })
+    }))
+}
+
+fn catch2_test(name: &str) -> String {
+    format!(
+        "#include <catch2/catch_test_macros.hpp>
+
+TEST_CASE(\"{name} adds up\", \"[{name}]\") {{
+  REQUIRE(1 + 1 == 2);
+}}
+"
+    )
+}
+
+fn unity_test(name: &str) -> String {
+    let name = name.replace(|c: char| !c.is_ascii_alphanumeric(), "_");
+    format!(
+        "#include \"unity.h\"
+
+void setUp(void) {{}}
+
+void tearDown(void) {{}}
+
+static void test_{name}_adds_up(void) {{
+  TEST_ASSERT_EQUAL_INT(2, 1 + 1);
+}}
+
+int main(void) {{
+  UNITY_BEGIN();
+  RUN_TEST(test_{name}_adds_up);
+  return UNITY_END();
+}}
+"
+    )
+}
@@ -0,0 +1,249 @@
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use crate::{cmake, executor::executor, fileapi, hash::Fnv, manifest::STATE_DIR};
+
+/// Hashes of the inputs of the tests that passed last, per build directory
+const CACHE_FILE: &str = "test_cache.json";
+
+/// Where ctest records the last run, relative to the build directory
+const LAST_TEST_LOG: &str = "Testing/Temporary/LastTest.log";
+const LAST_FAILED_LOG: &str = "Testing/Temporary/LastTestsFailed.log";
+
+type Cache = BTreeMap<String, BTreeMap<String, String>>;
+
+#[derive(Deserialize)]
+struct TestList {
+    #[serde(default)]
+    tests: Vec<TestInfo>,
+}
+
+#[derive(Deserialize)]
+struct TestInfo {
+    name: String,
+    #[serde(default)]
+    command: Vec<String>,
+    #[serde(default)]
+    properties: Vec<Property>,
+}
+
+#[derive(Deserialize)]
+struct Property {
+    name: String,
+    value: serde_json::Value,
+}
+
+/// Which tests need to run because their inputs changed since they last
+/// passed, or they never did
+pub struct Plan {
+    build_dir: String,
+    hashes: BTreeMap<String, String>,
+    pub run: Vec<String>,
+    pub skipped: usize,
+}
+
+/// Hashes every test's command, properties, executable, the sources of its
+/// target and, with Ninja, the project headers they include, and compares
+/// them with the hashes recorded when it last passed
+pub fn plan(build_dir: &str, config: Option<&str>) -> Result<Plan> {
+    let mut command = Command::new("ctest");
+    command.args(["--test-dir", build_dir, "--show-only=json-v1"]);
+    if let Some(config) = config {
+        command.args(["-C", config]);
+    }
+    let output = executor()
+        .capture(&mut command)
+        .context("Failed to list tests")?;
+    if !output.status.success() {
+        anyhow::bail!(
+            "Failed to list tests: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    let list: TestList = serde_json::from_slice(&output.stdout).context("Failed to parse tests")?;
+
+    let root = fs::canonicalize(".").context("Failed to resolve project directory")?;
+    let executables = fileapi::executables(Path::new(build_dir))?;
+    let headers = included_headers(build_dir, &root);
+    let cached = load()?.remove(build_dir).unwrap_or_default();
+
+    let mut plan = Plan {
+        build_dir: build_dir.to_string(),
+        hashes: BTreeMap::new(),
+        run: Vec::new(),
+        skipped: 0,
+    };
+    for test in list.tests {
+        let mut inputs = BTreeSet::new();
+        if let Some(program) = test.command.first() {
+            let program = PathBuf::from(program);
+            let target = executables.iter().find(|executable| {
+                same_file(&Path::new(build_dir).join(&executable.path), &program)
+            });
+            if let Some(target) = target {
+                inputs.extend(target.sources.iter().cloned());
+                if let Some(headers) = headers.get(&target.name) {
+                    inputs.extend(headers.iter().cloned());
+                }
+            }
+            inputs.insert(program);
+        }
+
+        let mut hasher = Fnv::new();
+        hasher.write(test.name.as_bytes());
+        for argument in &test.command {
+            hasher.write(argument.as_bytes());
+        }
+        for property in &test.properties {
+            hasher.write(property.name.as_bytes());
+            hasher.write(property.value.to_string().as_bytes());
+        }
+        for input in &inputs {
+            hasher.write(input.to_string_lossy().as_bytes());
+            hasher.write_file(input);
+        }
+        let hash = hasher.finish();
+
+        if cached.get(&test.name) == Some(&hash) {
+            plan.skipped += 1;
+        } else {
+            plan.run.push(test.name.clone());
+        }
+        plan.hashes.insert(test.name, hash);
+    }
+
+    Ok(plan)
+}
+
+/// A `ctest -R` expression matching exactly the named tests
+pub fn name_filter(names: &[String]) -> String {
+    let names = names
+        .iter()
+        .map(|name| {
+            name.chars()
+                .flat_map(|c| {
+                    let escape = "\\^$.|?*+()[]{}".contains(c);
+                    escape.then_some('\\').into_iter().chain([c])
+                })
+                .collect::<String>()
+        })
+        .collect::<Vec<_>>();
+
+    format!("^({})$", names.join("|"))
+}
+
+impl Plan {
+    /// A `ctest -R` expression matching exactly the tests to run
+    pub fn filter(&self) -> String {
+        name_filter(&self.run)
+    }
+
+    /// Forgets the outcome of the previous run, so `record` only sees this one
+    pub fn clear_results(&self) {
+        for log in [LAST_TEST_LOG, LAST_FAILED_LOG] {
+            let _ = fs::remove_file(Path::new(&self.build_dir).join(log));
+        }
+    }
+
+    /// Remembers the hashes of the tests that ran and passed, and forgets
+    /// the ones that failed
+    pub fn record(self) -> Result<()> {
+        let build_dir = Path::new(&self.build_dir);
+        if executor().dry_run() || !build_dir.join(LAST_TEST_LOG).exists() {
+            return Ok(());
+        }
+
+        let failed = fs::read_to_string(build_dir.join(LAST_FAILED_LOG))
+            .unwrap_or_default()
+            .lines()
+            .filter_map(|line| line.split_once(':').map(|(_, name)| name.to_string()))
+            .collect::<BTreeSet<_>>();
+
+        let mut cache = load()?;
+        let entries = cache.entry(self.build_dir.clone()).or_default();
+        entries.retain(|name, _| self.hashes.contains_key(name));
+        for name in &self.run {
+            if failed.contains(name) {
+                entries.remove(name);
+            } else if let Some(hash) = self.hashes.get(name) {
+                entries.insert(name.clone(), hash.clone());
+            }
+        }
+
+        fs::create_dir_all(STATE_DIR).context("Failed to create state directory")?;
+        let text = serde_json::to_string(&cache).context("Failed to serialize test cache")?;
+        fs::write(cache_path(), text).context("Failed to write test cache")
+    }
+}
+
+fn cache_path() -> PathBuf {
+    Path::new(STATE_DIR).join(CACHE_FILE)
+}
+
+/// A cache that doesn't parse, e.g. from an older version, is started over
+fn load() -> Result<Cache> {
+    Ok(fs::read_to_string(cache_path())
+        .ok()
+        .and_then(|text| serde_json::from_str(&text).ok())
+        .unwrap_or_default())
+}
+
+fn same_file(a: &Path, b: &Path) -> bool {
+    match (fs::canonicalize(a), fs::canonicalize(b)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => a == b,
+    }
+}
+
+/// The headers inside the project each target's objects include, from the
+/// dependency log of Ninja builds. Other generators only track the sources.
+fn included_headers(build_dir: &str, root: &Path) -> BTreeMap<String, BTreeSet<PathBuf>> {
+    let mut headers: BTreeMap<String, BTreeSet<PathBuf>> = BTreeMap::new();
+    let ninja = cmake::cache_value(Path::new(build_dir), "CMAKE_GENERATOR")
+        .is_some_and(|generator| generator.starts_with("Ninja"));
+    if !ninja {
+        return headers;
+    }
+    let Ok(output) =
+        executor().capture(Command::new("ninja").args(["-C", build_dir, "-t", "deps"]))
+    else {
+        return headers;
+    };
+
+    // Objects are listed unindented, e.g. `CMakeFiles/app.dir/src/main.cpp.o: #deps 2, ...`,
+    // followed by their dependencies relative to the build directory
+    let mut target = None;
+    for line in String::from_utf8_lossy(&output.stdout).lines() {
+        if !line.starts_with(char::is_whitespace) {
+            target = line
+                .split_once("CMakeFiles/")
+                .and_then(|(_, rest)| rest.split_once(".dir/"))
+                .map(|(name, _)| name.to_string());
+            continue;
+        }
+        let (Some(target), dependency) = (&target, line.trim()) else {
+            continue;
+        };
+        if dependency.is_empty() {
+            continue;
+        }
+
+        let path = Path::new(build_dir).join(dependency);
+        if let Ok(path) = fs::canonicalize(path) {
+            if let Ok(relative) = path.strip_prefix(root) {
+                headers
+                    .entry(target.clone())
+                    .or_default()
+                    .insert(relative.to_path_buf());
+            }
+        }
+    }
+
+    headers
+}
@@ -0,0 +1,537 @@
+use anyhow::{Context, Result};
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use crate::{
+    cmake,
+    executor::{executor, shell_quote},
+    manifest::STATE_DIR,
+    upgrade::is_newer,
+};
+
+/// Directory in the state directory holding generated toolchain files
+const TOOLCHAINS_DIR: &str = "toolchains";
+
+/// ABIs the Android NDK builds for
+const ANDROID_ABIS: [&str; 4] = ["arm64-v8a", "armeabi-v7a", "x86", "x86_64"];
+
+/// API level Android builds target unless `--api` says otherwise
+pub const DEFAULT_ANDROID_API: u32 = 24;
+
+/// Minimum iOS version of iOS builds
+const IOS_DEPLOYMENT_TARGET: &str = "15.0";
+
+/// Architectures of a universal macOS binary
+const UNIVERSAL_ARCHS: [&str; 2] = ["arm64", "x86_64"];
+
+/// Where `run --target android` deploys executables on the device
+const ANDROID_DEVICE_DIR: &str = "/data/local/tmp";
+
+/// Puts the outputs of builds for a platform with several ABIs in a
+/// directory per ABI, once all targets are defined
+const PLATFORM_FRAGMENT: &str = "# Generated by cxx, do not edit.
+function(cxx_platform_output_dirs)
+  if(NOT CPP_TOOLS_PLATFORM_DIR)
+    return()
+  endif()
+  get_property(targets DIRECTORY ${CMAKE_CURRENT_SOURCE_DIR} PROPERTY BUILDSYSTEM_TARGETS)
+  foreach(target ${targets})
+    get_target_property(type ${target} TYPE)
+    if(type MATCHES \"^(EXECUTABLE|SHARED_LIBRARY|MODULE_LIBRARY)$\")
+      get_target_property(output_dir ${target} RUNTIME_OUTPUT_DIRECTORY)
+      if(NOT output_dir)
+        set(output_dir ${CMAKE_RUNTIME_OUTPUT_DIRECTORY})
+      endif()
+      if(NOT output_dir)
+        set(output_dir ${CMAKE_CURRENT_BINARY_DIR})
+      endif()
+      set_target_properties(${target} PROPERTIES
+        RUNTIME_OUTPUT_DIRECTORY ${output_dir}/${CPP_TOOLS_PLATFORM_DIR}
+        LIBRARY_OUTPUT_DIRECTORY ${output_dir}/${CPP_TOOLS_PLATFORM_DIR})
+    endif()
+  endforeach()
+endfunction()
+cmake_language(DEFER CALL cxx_platform_output_dirs)
+";
+
+/// Platforms cxx cross compiles for
+#[derive(Clone, Copy, PartialEq)]
+pub enum Platform {
+    /// Windows with mingw-w64
+    MinGw,
+    /// Windows with clang-cl and the MSVC libraries downloaded by xwin
+    ClangCl,
+    /// Android with the NDK, for one ABI and minimum API level
+    Android { abi: &'static str, api: u32 },
+    /// iOS devices, or the simulator, with Xcode
+    Ios { simulator: bool },
+}
+
+/// Reads `--target` along with the options that only apply to some targets
+pub fn parse_platform(target: &str, abi: Option<&str>, api: Option<u32>) -> Result<Platform> {
+    let platform = match target.to_ascii_lowercase().as_str() {
+        "windows" | "windows-gnu" | "mingw" => Platform::MinGw,
+        "windows-msvc" | "clang-cl" => Platform::ClangCl,
+        "ios" => Platform::Ios { simulator: false },
+        "ios-simulator" => Platform::Ios { simulator: true },
+        "android" => {
+            let abi = abi.unwrap_or(ANDROID_ABIS[0]);
+            let abi = ANDROID_ABIS
+                .into_iter()
+                .find(|valid| *valid == abi)
+                .ok_or_else(|| {
+                    anyhow::anyhow!(
+                        "Valid Android ABIs are 'arm64-v8a', 'armeabi-v7a', 'x86' and 'x86_64'"
+                    )
+                })?;
+
+            return Ok(Platform::Android {
+                abi,
+                api: api.unwrap_or(DEFAULT_ANDROID_API),
+            });
+        }
+        _ => {
+            anyhow::bail!(
+                "Valid targets are 'windows', 'windows-msvc', 'android', 'ios' and 'ios-simulator'"
+            );
+        }
+    };
+
+    if abi.is_some() || api.is_some() {
+        anyhow::bail!("--abi and --api only apply to --target android");
+    }
+
+    Ok(platform)
+}
+
+impl Platform {
+    fn name(self) -> &'static str {
+        match self {
+            Platform::MinGw => "windows",
+            Platform::ClangCl => "windows-msvc",
+            Platform::Android { .. } => "android",
+            Platform::Ios { simulator: false } => "ios",
+            Platform::Ios { simulator: true } => "ios-simulator",
+        }
+    }
+
+    /// Whether the platform's compiler takes MSVC-style flags
+    pub fn msvc_flags(self) -> bool {
+        self == Platform::ClangCl
+    }
+
+    pub fn executable_suffix(self) -> &'static str {
+        match self {
+            Platform::MinGw | Platform::ClangCl => ".exe",
+            Platform::Android { .. } | Platform::Ios { .. } => "",
+        }
+    }
+
+    /// The directory below the runtime directory the platform's outputs go
+    /// to, for platforms built once per ABI
+    pub fn output_dir(self) -> Option<&'static str> {
+        match self {
+            Platform::Android { abi, .. } => Some(abi),
+            _ => None,
+        }
+    }
+
+    /// The cache variables that configure a build directory for the
+    /// platform, writing its toolchain file when cxx generates one
+    pub fn cache_variables(self, root_dir: &Path) -> Result<Vec<(String, String)>> {
+        let mut variables = Vec::new();
+
+        match self {
+            Platform::MinGw | Platform::ClangCl => {
+                let toolchain = write_toolchain_file(root_dir, self)?;
+                variables.push((
+                    "CMAKE_TOOLCHAIN_FILE".to_string(),
+                    toolchain.display().to_string(),
+                ));
+            }
+            Platform::Android { abi, api } => {
+                let toolchain = find_ndk()?.join("build/cmake/android.toolchain.cmake");
+                if !toolchain.exists() {
+                    anyhow::bail!(
+                        "The NDK has no CMake toolchain file at '{}'",
+                        toolchain.display()
+                    );
+                }
+
+                cmake::write_fragment_in(root_dir, "platform.cmake", PLATFORM_FRAGMENT)?;
+                variables.push((
+                    "CMAKE_TOOLCHAIN_FILE".to_string(),
+                    toolchain.display().to_string(),
+                ));
+                variables.push(("ANDROID_ABI".to_string(), abi.to_string()));
+                variables.push(("ANDROID_PLATFORM".to_string(), format!("android-{}", api)));
+            }
+            Platform::Ios { simulator } => {
+                if !cfg!(target_os = "macos") {
+                    anyhow::bail!("Building for iOS requires macOS with Xcode");
+                }
+
+                let (sysroot, archs) = if simulator {
+                    ("iphonesimulator", UNIVERSAL_ARCHS.join(";"))
+                } else {
+                    ("iphoneos", "arm64".to_string())
+                };
+                variables.push(("CMAKE_SYSTEM_NAME".to_string(), "iOS".to_string()));
+                variables.push(("CMAKE_OSX_SYSROOT".to_string(), sysroot.to_string()));
+                variables.push(("CMAKE_OSX_ARCHITECTURES".to_string(), archs));
+                variables.push((
+                    "CMAKE_OSX_DEPLOYMENT_TARGET".to_string(),
+                    IOS_DEPLOYMENT_TARGET.to_string(),
+                ));
+            }
+        }
+        variables.push((
+            "CPP_TOOLS_PLATFORM_DIR".to_string(),
+            self.output_dir().unwrap_or_default().to_string(),
+        ));
+
+        Ok(variables)
+    }
+
+    /// The command executables for the platform run under on this machine,
+    /// if they don't run on a device
+    pub fn wrapper(self) -> Result<Option<String>> {
+        match self {
+            Platform::MinGw | Platform::ClangCl => {
+                if !has_tool("wine") {
+                    anyhow::bail!("Running Windows executables requires Wine");
+                }
+
+                Ok(Some("wine".to_string()))
+            }
+            Platform::Android { .. } => Ok(None),
+            Platform::Ios { .. } => {
+                anyhow::bail!("iOS builds can't run here, use the library from an Xcode project")
+            }
+        }
+    }
+
+    /// The commands copying an executable to a connected device with adb
+    /// and running it there with the given environment and arguments. adb
+    /// runs the latter with the device's shell.
+    pub fn device_commands(
+        self,
+        exec_path: &Path,
+        env: &BTreeMap<String, String>,
+        args: &[String],
+    ) -> Result<(Command, Command)> {
+        if !has_tool("adb") {
+            anyhow::bail!("Running on an Android device requires adb from the Android SDK");
+        }
+
+        let name = exec_path
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let device_path = format!("{}/{}", ANDROID_DEVICE_DIR, name);
+
+        let mut push = Command::new("adb");
+        push.arg("push").arg(exec_path).arg(&device_path);
+
+        let env = env
+            .iter()
+            .map(|(key, value)| format!("{}={} ", key, shell_quote(value)))
+            .collect::<String>();
+        let args = args
+            .iter()
+            .map(|arg| format!(" {}", shell_quote(arg)))
+            .collect::<String>();
+        let mut run = Command::new("adb");
+        run.arg("shell").arg(format!(
+            "cd {} && chmod +x {} && {}{}{}",
+            ANDROID_DEVICE_DIR,
+            shell_quote(&device_path),
+            env,
+            shell_quote(&device_path),
+            args
+        ));
+
+        Ok((push, run))
+    }
+
+    fn toolchain(self) -> Result<String> {
+        match self {
+            Platform::MinGw => {
+                if !has_tool("x86_64-w64-mingw32-g++") {
+                    anyhow::bail!(
+                        "Cross compiling for Windows requires mingw-w64 (x86_64-w64-mingw32-g++)"
+                    );
+                }
+
+                Ok(MINGW_TOOLCHAIN.to_string())
+            }
+            Platform::ClangCl => {
+                let xwin = xwin_dir()?;
+                if !xwin.join("crt").is_dir() || !xwin.join("sdk").is_dir() {
+                    anyhow::bail!(
+                        "No MSVC libraries found in '{}', download them with 'xwin --accept-license splat --output {}' or set XWIN_DIR",
+                        xwin.display(),
+                        xwin.display()
+                    );
+                }
+
+                Ok(format!(
+                    "set(XWIN_DIR \"{}\")\n{}",
+                    xwin.display(),
+                    CLANG_CL_TOOLCHAIN
+                ))
+            }
+            Platform::Android { .. } | Platform::Ios { .. } => {
+                unreachable!("only Windows targets use a generated toolchain file")
+            }
+        }
+    }
+}
+
+/// Links the C and C++ runtimes statically, so executables run under Wine
+/// and on machines without mingw-w64 installed
+const MINGW_TOOLCHAIN: &str = "set(CMAKE_SYSTEM_NAME Windows)
+set(CMAKE_SYSTEM_PROCESSOR x86_64)
+
+set(CMAKE_C_COMPILER x86_64-w64-mingw32-gcc)
+set(CMAKE_CXX_COMPILER x86_64-w64-mingw32-g++)
+set(CMAKE_RC_COMPILER x86_64-w64-mingw32-windres)
+set(CMAKE_EXE_LINKER_FLAGS_INIT \"-static\")
+
+set(CMAKE_FIND_ROOT_PATH /usr/x86_64-w64-mingw32)
+set(CMAKE_FIND_ROOT_PATH_MODE_PROGRAM NEVER)
+set(CMAKE_FIND_ROOT_PATH_MODE_LIBRARY ONLY)
+set(CMAKE_FIND_ROOT_PATH_MODE_INCLUDE ONLY)
+";
+
+/// Follows the layout of `xwin splat`
+const CLANG_CL_TOOLCHAIN: &str = "set(CMAKE_SYSTEM_NAME Windows)
+set(CMAKE_SYSTEM_PROCESSOR AMD64)
+
+set(CMAKE_C_COMPILER clang-cl)
+set(CMAKE_CXX_COMPILER clang-cl)
+set(CMAKE_LINKER lld-link)
+set(CMAKE_RC_COMPILER llvm-rc)
+set(CMAKE_MT llvm-mt)
+
+set(XWIN_FLAGS \"--target=x86_64-pc-windows-msvc\")
+foreach(dir crt/include sdk/include/ucrt sdk/include/um sdk/include/shared)
+  string(APPEND XWIN_FLAGS \" /imsvc \\\"${XWIN_DIR}/${dir}\\\"\")
+endforeach()
+set(CMAKE_C_FLAGS_INIT \"${XWIN_FLAGS}\")
+set(CMAKE_CXX_FLAGS_INIT \"${XWIN_FLAGS}\")
+
+foreach(dir crt/lib/x86_64 sdk/lib/um/x86_64 sdk/lib/ucrt/x86_64)
+  string(APPEND XWIN_LINK_FLAGS \" /libpath:\\\"${XWIN_DIR}/${dir}\\\"\")
+endforeach()
+set(CMAKE_EXE_LINKER_FLAGS_INIT \"${XWIN_LINK_FLAGS}\")
+set(CMAKE_SHARED_LINKER_FLAGS_INIT \"${XWIN_LINK_FLAGS}\")
+set(CMAKE_MODULE_LINKER_FLAGS_INIT \"${XWIN_LINK_FLAGS}\")
+";
+
+/// `$XWIN_DIR`, or `~/.xwin` where xwin's documentation splats to
+fn xwin_dir() -> Result<PathBuf> {
+    std::env::var_os("XWIN_DIR")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| Path::new(&home).join(".xwin")))
+        .context("Neither XWIN_DIR nor HOME is set")
+}
+
+/// The NDK named by `ANDROID_NDK_HOME` or `ANDROID_NDK_ROOT`, or else the
+/// newest one installed in the Android SDK
+fn find_ndk() -> Result<PathBuf> {
+    for variable in ["ANDROID_NDK_HOME", "ANDROID_NDK_ROOT"] {
+        if let Some(ndk) = std::env::var_os(variable).filter(|ndk| !ndk.is_empty()) {
+            return Ok(PathBuf::from(ndk));
+        }
+    }
+
+    let home = std::env::var_os("HOME").map(PathBuf::from);
+    let sdks = ["ANDROID_HOME", "ANDROID_SDK_ROOT"]
+        .into_iter()
+        .filter_map(|variable| std::env::var_os(variable).map(PathBuf::from))
+        .chain(
+            home.iter()
+                .flat_map(|home| [home.join("Android/Sdk"), home.join("Library/Android/sdk")]),
+        );
+
+    for sdk in sdks {
+        let Ok(entries) = fs::read_dir(sdk.join("ndk")) else {
+            continue;
+        };
+        let newest = entries
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.file_name().to_string_lossy().to_string())
+            .reduce(|newest, version| {
+                if is_newer(&version, &newest) {
+                    version
+                } else {
+                    newest
+                }
+            });
+        if let Some(version) = newest {
+            return Ok(sdk.join("ndk").join(version));
+        }
+    }
+
+    anyhow::bail!("No Android NDK found, set ANDROID_NDK_HOME or install one with the SDK manager")
+}
+
+fn has_tool(tool: &str) -> bool {
+    executor()
+        .capture(Command::new(tool).arg("--version"))
+        .is_ok()
+}
+
+/// Writes the toolchain file of a platform into the state directory,
+/// returning its absolute path for `CMAKE_TOOLCHAIN_FILE`
+fn write_toolchain_file(root_dir: &Path, platform: Platform) -> Result<PathBuf> {
+    let dir = root_dir.join(STATE_DIR).join(TOOLCHAINS_DIR);
+    fs::create_dir_all(&dir).context("Failed to create toolchain directory")?;
+
+    let path = dir.join(format!("{}.cmake", platform.name()));
+    let contents = format!(
+        "# Generated by cxx for --target {}, do not edit.\n{}",
+        platform.name(),
+        platform.toolchain()?
+    );
+    fs::write(&path, contents).with_context(|| format!("Failed to write {}", path.display()))?;
+
+    fs::canonicalize(&path).context("Failed to resolve toolchain file")
+}
+
+/// Whether a build directory was configured for the platform
+pub fn is_configured_for(build_dir: &Path, platform: Platform) -> bool {
+    let toolchain = cmake::cache_value(build_dir, "CMAKE_TOOLCHAIN_FILE").unwrap_or_default();
+
+    match platform {
+        Platform::Android { abi, api } => {
+            toolchain.ends_with("android.toolchain.cmake")
+                && cmake::cache_value(build_dir, "ANDROID_ABI").as_deref() == Some(abi)
+                && cmake::cache_value(build_dir, "ANDROID_PLATFORM")
+                    == Some(format!("android-{}", api))
+        }
+        Platform::Ios { simulator } => {
+            let sysroot = if simulator {
+                "iphonesimulator"
+            } else {
+                "iphoneos"
+            };
+            cmake::cache_value(build_dir, "CMAKE_SYSTEM_NAME").as_deref() == Some("iOS")
+                && cmake::cache_value(build_dir, "CMAKE_OSX_SYSROOT").as_deref() == Some(sysroot)
+        }
+        _ => Path::new(&toolchain)
+            .file_name()
+            .is_some_and(|name| name.to_string_lossy() == format!("{}.cmake", platform.name())),
+    }
+}
+
+/// Architectures Visual Studio generators build for, passed as `-A`
+const VISUAL_STUDIO_ARCHS: [&str; 4] = ["x64", "arm64", "Win32", "ARM64EC"];
+
+/// Whether the generator is one of the Visual Studio ones, e.g.
+/// `Visual Studio 17 2022`
+pub fn is_visual_studio(generator: &str) -> bool {
+    generator.starts_with("Visual Studio")
+}
+
+/// Checks an `--arch` value, returning the spelling CMake expects
+pub fn visual_studio_arch(arch: &str) -> Result<&'static str> {
+    VISUAL_STUDIO_ARCHS
+        .into_iter()
+        .find(|valid| valid.eq_ignore_ascii_case(arch))
+        .ok_or_else(|| {
+            anyhow::anyhow!("Valid architectures are 'x64', 'arm64', 'Win32' and 'ARM64EC'")
+        })
+}
+
+/// Whether the build directory uses a compiler with MSVC-style flags, i.e.
+/// cl or clang-cl. Before the first configure only the generator is known.
+pub fn uses_msvc_flags(build_dir: &Path, generator: Option<&str>) -> bool {
+    let cached_generator = cmake::cache_value(build_dir, "CMAKE_GENERATOR");
+    if generator
+        .or(cached_generator.as_deref())
+        .is_some_and(is_visual_studio)
+    {
+        return true;
+    }
+
+    ["CMAKE_CXX_COMPILER", "CMAKE_C_COMPILER"]
+        .into_iter()
+        .any(|key| {
+            cmake::cache_value(build_dir, key).is_some_and(|compiler| {
+                Path::new(&compiler).file_stem().is_some_and(|stem| {
+                    stem.eq_ignore_ascii_case("cl") || stem.eq_ignore_ascii_case("clang-cl")
+                })
+            })
+        })
+}
+
+/// The configuration to build with multi-config generators such as Visual
+/// Studio and Ninja Multi-Config, `None` with single-config ones. It is the
+/// build type of the profile the directory was configured with.
+pub fn build_config(build_dir: &Path) -> Option<String> {
+    let types = cmake::cache_value(build_dir, "CMAKE_CONFIGURATION_TYPES")
+        .filter(|types| !types.is_empty())?;
+
+    Some(
+        cmake::cache_value(build_dir, "CMAKE_BUILD_TYPE")
+            .filter(|build_type| !build_type.is_empty())
+            .unwrap_or_else(|| types.split(';').next().unwrap_or("Debug").to_string()),
+    )
+}
+
+/// Where an executable ends up below the runtime directory. Multi-config
+/// generators put each configuration in its own subdirectory.
+pub fn executable(build_dir: &str, exec_name: &str) -> String {
+    match build_config(Path::new(build_dir)) {
+        Some(config) => format!("{}/{}", config, exec_name),
+        None => exec_name.to_string(),
+    }
+}
+
+/// The cache variable making macOS builds universal binaries
+pub fn universal_cache_variable() -> Result<(String, String)> {
+    if !cfg!(target_os = "macos") {
+        anyhow::bail!("Universal binaries can only be built on macOS");
+    }
+
+    Ok((
+        "CMAKE_OSX_ARCHITECTURES".to_string(),
+        UNIVERSAL_ARCHS.join(";"),
+    ))
+}
+
+/// Checks with lipo that a binary contains every architecture of a universal
+/// binary
+pub fn verify_universal(binary: &Path) -> Result<()> {
+    let output = executor()
+        .capture(Command::new("lipo").arg("-archs").arg(binary))
+        .context("Failed to run lipo")?;
+    if !output.status.success() {
+        anyhow::bail!(
+            "lipo failed to read '{}': {}",
+            binary.display(),
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+
+    let archs = String::from_utf8_lossy(&output.stdout);
+    let missing = UNIVERSAL_ARCHS
+        .into_iter()
+        .filter(|arch| !archs.split_whitespace().any(|found| found == *arch))
+        .collect::<Vec<_>>();
+    if !missing.is_empty() {
+        anyhow::bail!(
+            "'{}' is not a universal binary, it lacks {}",
+            binary.display(),
+            missing.join(", ")
+        );
+    }
+
+    Ok(())
+}
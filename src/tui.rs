@@ -0,0 +1,444 @@
+use anyhow::{Context, Result};
+use ratatui::{
+    crossterm::event::{self, Event, KeyCode, KeyEventKind},
+    layout::{Constraint, Layout, Rect},
+    style::{Color, Modifier, Style},
+    text::{Line, Span},
+    widgets::{Block, List, ListItem, ListState, Paragraph},
+    DefaultTerminal, Frame,
+};
+use std::{
+    io::{BufRead, BufReader, Read},
+    process::{Command, Stdio},
+    sync::mpsc::{self, Receiver, Sender},
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    manifest::Manifest,
+    open,
+    output::{parse_diagnostic, parse_test_result, strip_ansi, Diagnostic, TestResult},
+    watch::{self, Snapshot},
+};
+
+/// How long to wait for a key before updating the screen
+const TICK: Duration = Duration::from_millis(100);
+
+/// How often watch mode scans the project for changes
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Lines of build output kept for scrolling back
+const OUTPUT_LINES: usize = 5000;
+
+#[derive(Clone, Copy, PartialEq)]
+enum Job {
+    Build,
+    Test,
+}
+
+impl Job {
+    fn name(self) -> &'static str {
+        match self {
+            Job::Build => "build",
+            Job::Test => "test",
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Pane {
+    Output,
+    Diagnostics,
+    Tests,
+}
+
+/// Output of the running cxx command, sent from the threads reading it
+enum Message {
+    Line(String),
+    Done(bool),
+}
+
+struct App {
+    build_dir: String,
+    runtime_dir: String,
+    focus: Pane,
+    output: Vec<String>,
+    /// Lines scrolled up from the end of the output
+    scroll: usize,
+    diagnostics: Vec<Diagnostic>,
+    diagnostics_state: ListState,
+    tests: Vec<TestResult>,
+    tests_state: ListState,
+    running: Option<(Job, Instant, Receiver<Message>)>,
+    /// The outcome of the last command, e.g. `test passed in 3.2s`
+    last: Option<(Job, bool, Duration)>,
+    /// The files watch mode compares against, `None` when not watching
+    watching: Option<(Snapshot, Instant)>,
+    include: Vec<String>,
+    exclude: Vec<String>,
+    quit: bool,
+}
+
+/// Opens a dashboard with panes for the build output, the compiler
+/// diagnostics and the test results, which rebuilds and tests on changes in
+/// watch mode. Diagnostics open in `$EDITOR` at their line.
+pub fn handle_tui(build_dir: String, runtime_dir: String) -> Result<()> {
+    let manifest = Manifest::load()?;
+    let mut app = App {
+        build_dir,
+        runtime_dir,
+        focus: Pane::Diagnostics,
+        output: Vec::new(),
+        scroll: 0,
+        diagnostics: Vec::new(),
+        diagnostics_state: ListState::default(),
+        tests: Vec::new(),
+        tests_state: ListState::default(),
+        running: None,
+        last: None,
+        watching: None,
+        include: manifest.watch.include.clone(),
+        exclude: manifest.watch.exclude.clone(),
+        quit: false,
+    };
+
+    let mut terminal = ratatui::init();
+    let result = app.run(&mut terminal);
+    ratatui::restore();
+
+    result
+}
+
+impl App {
+    fn run(&mut self, terminal: &mut DefaultTerminal) -> Result<()> {
+        self.start(Job::Build)?;
+
+        while !self.quit {
+            terminal
+                .draw(|frame| self.draw(frame))
+                .context("Failed to draw the dashboard")?;
+
+            self.receive();
+            self.poll_changes()?;
+
+            if !event::poll(TICK).context("Failed to read terminal events")? {
+                continue;
+            }
+            if let Event::Key(key) = event::read().context("Failed to read terminal events")? {
+                if key.kind != KeyEventKind::Press {
+                    continue;
+                }
+                match key.code {
+                    KeyCode::Char('q') | KeyCode::Esc => self.quit = true,
+                    KeyCode::Char('b') => self.start(Job::Build)?,
+                    KeyCode::Char('t') => self.start(Job::Test)?,
+                    KeyCode::Char('w') => self.toggle_watch(),
+                    KeyCode::Tab => {
+                        self.focus = match self.focus {
+                            Pane::Output => Pane::Diagnostics,
+                            Pane::Diagnostics => Pane::Tests,
+                            Pane::Tests => Pane::Output,
+                        }
+                    }
+                    KeyCode::Up | KeyCode::Char('k') => self.move_selection(-1),
+                    KeyCode::Down | KeyCode::Char('j') => self.move_selection(1),
+                    KeyCode::Enter => {
+                        if let Some(diagnostic) = self
+                            .diagnostics_state
+                            .selected()
+                            .and_then(|index| self.diagnostics.get(index))
+                        {
+                            // The editor needs the terminal for itself
+                            ratatui::restore();
+                            let opened = open::open_at(&diagnostic.file, diagnostic.line);
+                            *terminal = ratatui::init();
+                            opened?;
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Runs `cxx build` or `cxx test` in the background, unless a command
+    /// is running already
+    fn start(&mut self, job: Job) -> Result<()> {
+        if self.running.is_some() {
+            return Ok(());
+        }
+
+        let executable = std::env::current_exe().context("Failed to locate the cxx executable")?;
+        let mut child = Command::new(executable)
+            .args([
+                job.name(),
+                "--build-dir",
+                &self.build_dir,
+                "--runtime-dir",
+                &self.runtime_dir,
+            ])
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .with_context(|| format!("Failed to run cxx {}", job.name()))?;
+
+        let (sender, receiver) = mpsc::channel();
+        let readers = [
+            child.stdout.take().map(forward(sender.clone())),
+            child.stderr.take().map(forward(sender.clone())),
+        ];
+        thread::spawn(move || {
+            for reader in readers.into_iter().flatten() {
+                let _ = reader.join();
+            }
+            let passed = child.wait().is_ok_and(|status| status.success());
+            let _ = sender.send(Message::Done(passed));
+        });
+
+        self.output.clear();
+        self.scroll = 0;
+        self.diagnostics.clear();
+        self.diagnostics_state.select(None);
+        if job == Job::Test {
+            self.tests.clear();
+            self.tests_state.select(None);
+        }
+        self.running = Some((job, Instant::now(), receiver));
+
+        Ok(())
+    }
+
+    /// Takes the output the running command printed since the last frame
+    fn receive(&mut self) {
+        let Some((job, started, receiver)) = &self.running else {
+            return;
+        };
+        let (job, started) = (*job, *started);
+
+        let mut finished = None;
+        while let Ok(message) = receiver.try_recv() {
+            match message {
+                Message::Line(line) => {
+                    if let Some(diagnostic) = parse_diagnostic(&line) {
+                        self.diagnostics.push(diagnostic);
+                        if self.diagnostics_state.selected().is_none() {
+                            self.diagnostics_state.select(Some(0));
+                        }
+                    }
+                    if let Some(test) = parse_test_result(&line) {
+                        self.tests.push(test);
+                    }
+                    self.output.push(line);
+                }
+                Message::Done(passed) => finished = Some(passed),
+            }
+        }
+        if self.output.len() > OUTPUT_LINES {
+            self.output.drain(..self.output.len() - OUTPUT_LINES);
+        }
+
+        if let Some(passed) = finished {
+            self.running = None;
+            self.last = Some((job, passed, started.elapsed()));
+        }
+    }
+
+    fn toggle_watch(&mut self) {
+        self.watching = match self.watching {
+            Some(_) => None,
+            None => Some((watch::scan(&self.include, &self.exclude), Instant::now())),
+        };
+    }
+
+    /// In watch mode, builds and tests again once a watched file changed
+    /// and the previous run finished
+    fn poll_changes(&mut self) -> Result<()> {
+        let Some((snapshot, last_poll)) = &mut self.watching else {
+            return Ok(());
+        };
+        if self.running.is_some() || last_poll.elapsed() < POLL_INTERVAL {
+            return Ok(());
+        }
+
+        *last_poll = Instant::now();
+        let current = watch::scan(&self.include, &self.exclude);
+        if current != *snapshot {
+            *snapshot = current;
+            self.start(Job::Test)?;
+        }
+
+        Ok(())
+    }
+
+    fn move_selection(&mut self, delta: isize) {
+        let (state, len) = match self.focus {
+            Pane::Output => {
+                self.scroll = if delta < 0 {
+                    (self.scroll + 1).min(self.output.len())
+                } else {
+                    self.scroll.saturating_sub(1)
+                };
+                return;
+            }
+            Pane::Diagnostics => (&mut self.diagnostics_state, self.diagnostics.len()),
+            Pane::Tests => (&mut self.tests_state, self.tests.len()),
+        };
+        if len == 0 {
+            return;
+        }
+
+        let selected = state.selected().unwrap_or(0) as isize + delta;
+        state.select(Some(selected.clamp(0, len as isize - 1) as usize));
+    }
+
+    fn draw(&mut self, frame: &mut Frame) {
+        let [main, status] =
+            Layout::vertical([Constraint::Min(0), Constraint::Length(1)]).areas(frame.area());
+        let [output, side] =
+            Layout::horizontal([Constraint::Percentage(55), Constraint::Percentage(45)])
+                .areas(main);
+        let [diagnostics, tests] =
+            Layout::vertical([Constraint::Percentage(50), Constraint::Percentage(50)]).areas(side);
+
+        self.draw_output(frame, output);
+        self.draw_diagnostics(frame, diagnostics);
+        self.draw_tests(frame, tests);
+        self.draw_status(frame, status);
+    }
+
+    fn block(&self, pane: Pane, title: String) -> Block<'static> {
+        let style = if self.focus == pane {
+            Style::new().fg(Color::Cyan)
+        } else {
+            Style::new()
+        };
+        Block::bordered().border_style(style).title(title)
+    }
+
+    fn draw_output(&self, frame: &mut Frame, area: Rect) {
+        let height = area.height.saturating_sub(2) as usize;
+        let end = self.output.len().saturating_sub(self.scroll);
+        let lines = self.output[end.saturating_sub(height)..end]
+            .iter()
+            .map(|line| Line::raw(line.clone()))
+            .collect::<Vec<_>>();
+
+        let title = match &self.running {
+            Some((job, _, _)) => format!(" Output: {} running ", job.name()),
+            None => " Output ".to_string(),
+        };
+        frame.render_widget(
+            Paragraph::new(lines).block(self.block(Pane::Output, title)),
+            area,
+        );
+    }
+
+    fn draw_diagnostics(&mut self, frame: &mut Frame, area: Rect) {
+        let items = self
+            .diagnostics
+            .iter()
+            .map(|diagnostic| {
+                let (label, color) = match diagnostic.error {
+                    true => ("error", Color::Red),
+                    false => ("warning", Color::Yellow),
+                };
+                ListItem::new(Line::from(vec![
+                    Span::styled(format!("{:8}", label), Style::new().fg(color)),
+                    Span::raw(format!(
+                        "{}:{} {}",
+                        diagnostic.file.display(),
+                        diagnostic.line,
+                        diagnostic.message
+                    )),
+                ]))
+            })
+            .collect::<Vec<_>>();
+
+        let title = format!(" Diagnostics ({}), Enter opens ", self.diagnostics.len());
+        let list = List::new(items)
+            .block(self.block(Pane::Diagnostics, title))
+            .highlight_style(Style::new().add_modifier(Modifier::REVERSED));
+        frame.render_stateful_widget(list, area, &mut self.diagnostics_state);
+    }
+
+    fn draw_tests(&mut self, frame: &mut Frame, area: Rect) {
+        let failed = self.tests.iter().filter(|test| !test.passed).count();
+        let items = self
+            .tests
+            .iter()
+            .map(|test| {
+                let (label, color) = match (test.passed, &test.signal) {
+                    (true, _) => ("passed", Color::Green),
+                    (false, Some(_)) => ("CRASHED", Color::Red),
+                    (false, None) => ("FAILED", Color::Red),
+                };
+                ListItem::new(Line::from(vec![
+                    Span::styled(format!("{:8}", label), Style::new().fg(color)),
+                    Span::raw(match test.seconds {
+                        Some(seconds) => format!("{} {:.2}s", test.name, seconds),
+                        None => test.name.clone(),
+                    }),
+                ]))
+            })
+            .collect::<Vec<_>>();
+
+        let title = format!(" Tests ({}, {} failed) ", self.tests.len(), failed);
+        let list = List::new(items)
+            .block(self.block(Pane::Tests, title))
+            .highlight_style(Style::new().add_modifier(Modifier::REVERSED));
+        frame.render_stateful_widget(list, area, &mut self.tests_state);
+    }
+
+    fn draw_status(&self, frame: &mut Frame, area: Rect) {
+        let mut spans = Vec::new();
+        match (&self.running, &self.last) {
+            (Some((job, started, _)), _) => spans.push(Span::styled(
+                format!(
+                    " {} running {:.0}s ",
+                    job.name(),
+                    started.elapsed().as_secs_f64()
+                ),
+                Style::new().fg(Color::Cyan),
+            )),
+            (None, Some((job, passed, duration))) => spans.push(Span::styled(
+                format!(
+                    " {} {} in {:.1}s ",
+                    job.name(),
+                    if *passed { "passed" } else { "failed" },
+                    duration.as_secs_f64()
+                ),
+                Style::new().fg(if *passed { Color::Green } else { Color::Red }),
+            )),
+            (None, None) => {}
+        }
+        spans.push(match self.watching {
+            Some(_) => Span::styled(" watching ", Style::new().fg(Color::Green)),
+            None => Span::raw(" not watching "),
+        });
+        spans.push(Span::styled(
+            " b build  t test  w watch  Tab pane  ↑↓ select  Enter open  q quit",
+            Style::new().fg(Color::DarkGray),
+        ));
+
+        frame.render_widget(Paragraph::new(Line::from(spans)), area);
+    }
+}
+
+/// Sends every line read to the dashboard, without terminal colors
+fn forward<R: Read + Send + 'static>(
+    sender: Sender<Message>,
+) -> impl FnOnce(R) -> thread::JoinHandle<()> {
+    move |reader| {
+        thread::spawn(move || {
+            for line in BufReader::new(reader).lines().map_while(|line| line.ok()) {
+                if sender.send(Message::Line(strip_ansi(&line))).is_err() {
+                    break;
+                }
+            }
+        })
+    }
+}
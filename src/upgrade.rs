@@ -0,0 +1,243 @@
+use anyhow::{Context, Result};
+use colorize::AnsiColor;
+use serde::Deserialize;
+use std::{fs, path::Path, process::Command};
+
+use crate::executor::executor;
+
+const LATEST_RELEASE: &str = "https://api.github.com/repos/dbolivar25/cpp_tools/releases/latest";
+
+#[derive(Deserialize)]
+struct Release {
+    tag_name: String,
+    assets: Vec<Asset>,
+}
+
+#[derive(Deserialize)]
+struct Asset {
+    name: String,
+    browser_download_url: String,
+}
+
+/// Replaces the running executable with the latest release built for this
+/// platform, after checking it against the published SHA-256 checksum. With
+/// `check` only reports, failing when a newer version exists so CI notices.
+pub fn handle_upgrade(check: bool) -> Result<()> {
+    let current = env!("CARGO_PKG_VERSION");
+    let release = latest_release()?;
+    let latest = release.tag_name.trim_start_matches('v');
+
+    if !is_newer(latest, current) {
+        eprintln!(
+            "{}",
+            format!("cxx {} is the latest version", current).green()
+        );
+        return Ok(());
+    }
+
+    if check {
+        anyhow::bail!(
+            "cxx {} is available, this is {}, run 'cxx upgrade' to update",
+            latest,
+            current
+        );
+    }
+
+    let binary_name = binary_name();
+    let binary = find_asset(&release, &binary_name)?;
+    let checksum = find_asset(&release, &format!("{}.sha256", binary_name))?;
+
+    let exe = std::env::current_exe().context("Failed to locate the running executable")?;
+    let download = exe.with_extension("download");
+
+    executor()
+        .execute(
+            Command::new("curl")
+                .args(["-fsSL", "-o"])
+                .arg(&download)
+                .arg(&binary.browser_download_url),
+        )
+        .context("Failed to download release")?;
+    if executor().dry_run() {
+        return Ok(());
+    }
+
+    let result = verify_checksum(&download, &checksum.browser_download_url)
+        .and_then(|()| replace_executable(&download, &exe));
+    if result.is_err() {
+        let _ = fs::remove_file(&download);
+    }
+    result?;
+
+    eprintln!(
+        "{}",
+        format!("Upgraded cxx from {} to {}", current, latest).green()
+    );
+
+    Ok(())
+}
+
+fn latest_release() -> Result<Release> {
+    let output = executor()
+        .capture(Command::new("curl").args([
+            "-fsSL",
+            "-H",
+            "Accept: application/vnd.github+json",
+            LATEST_RELEASE,
+        ]))
+        .context("Failed to run curl")?;
+    if !output.status.success() {
+        anyhow::bail!(
+            "Failed to fetch the latest release: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+
+    serde_json::from_slice(&output.stdout).context("Failed to parse release information")
+}
+
+/// Release binaries are named after the platform, e.g. `cxx-x86_64-linux`
+fn binary_name() -> String {
+    let extension = if cfg!(windows) { ".exe" } else { "" };
+
+    format!(
+        "cxx-{}-{}{}",
+        std::env::consts::ARCH,
+        std::env::consts::OS,
+        extension
+    )
+}
+
+fn find_asset<'a>(release: &'a Release, name: &str) -> Result<&'a Asset> {
+    release
+        .assets
+        .iter()
+        .find(|asset| asset.name == name)
+        .with_context(|| format!("Release {} has no '{}'", release.tag_name, name))
+}
+
+/// Compares dotted versions numerically, so 0.10.0 is newer than 0.9.1. A
+/// prerelease is older than the release it leads up to, so 1.0.0-rc1 is
+/// older than 1.0.0 and 1.0.0-rc2 older than 1.0.0-rc10.
+pub fn is_newer(candidate: &str, current: &str) -> bool {
+    let parse = |version: &str| {
+        let version = version.split('+').next().unwrap_or(version);
+        let (core, prerelease) = match version.split_once('-') {
+            Some((core, prerelease)) => (core, Some(prerelease)),
+            None => (version, None),
+        };
+        let core = core
+            .split('.')
+            .map(|part| part.parse::<u64>().unwrap_or(0))
+            .collect::<Vec<_>>();
+        // `rc10` is the label `rc` and the number 10
+        let prerelease = prerelease.map(|prerelease| {
+            prerelease
+                .split('.')
+                .map(|part| {
+                    let label = part.trim_end_matches(|c: char| c.is_ascii_digit());
+                    (
+                        label.to_string(),
+                        part[label.len()..].parse::<u64>().unwrap_or(0),
+                    )
+                })
+                .collect::<Vec<_>>()
+        });
+
+        (core, prerelease.is_none(), prerelease)
+    };
+
+    parse(candidate) > parse(current)
+}
+
+fn verify_checksum(file: &Path, checksum_url: &str) -> Result<()> {
+    let output = executor()
+        .capture(Command::new("curl").args(["-fsSL", checksum_url]))
+        .context("Failed to run curl")?;
+    if !output.status.success() {
+        anyhow::bail!("Failed to download checksum");
+    }
+    let expected = String::from_utf8_lossy(&output.stdout)
+        .split_whitespace()
+        .next()
+        .map(str::to_ascii_lowercase)
+        .context("Checksum file is empty")?;
+
+    let actual = sha256(file)?;
+    if actual != expected {
+        anyhow::bail!(
+            "Checksum mismatch for the downloaded binary, expected {} but got {}",
+            expected,
+            actual
+        );
+    }
+
+    Ok(())
+}
+
+/// The SHA-256 of a file, by whichever of sha256sum and shasum is installed
+pub fn sha256(file: &Path) -> Result<String> {
+    let mut sha256sum = Command::new("sha256sum");
+    sha256sum.arg(file);
+    let mut shasum = Command::new("shasum");
+    shasum.args(["-a", "256"]).arg(file);
+
+    for mut command in [sha256sum, shasum] {
+        if let Ok(output) = executor().capture(&mut command) {
+            if output.status.success() {
+                if let Some(hash) = String::from_utf8_lossy(&output.stdout)
+                    .split_whitespace()
+                    .next()
+                {
+                    return Ok(hash.to_ascii_lowercase());
+                }
+            }
+        }
+    }
+
+    anyhow::bail!("Checksums require sha256sum or shasum")
+}
+
+/// Swaps in the new binary. The running executable can be renamed but not
+/// overwritten on Windows, so it is moved aside first.
+fn replace_executable(download: &Path, exe: &Path) -> Result<()> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(download, fs::Permissions::from_mode(0o755))
+            .context("Failed to make the download executable")?;
+    }
+
+    let old = exe.with_extension("old");
+    if cfg!(windows) {
+        fs::rename(exe, &old).context("Failed to move the running executable aside")?;
+    }
+
+    fs::rename(download, exe).context("Failed to replace the executable")?;
+    let _ = fs::remove_file(old);
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compares_versions_numerically() {
+        assert!(is_newer("0.10.0", "0.9.1"));
+        assert!(is_newer("1.0.1", "1.0.0"));
+        assert!(!is_newer("1.0.0", "1.0.0"));
+        assert!(!is_newer("0.9.1", "0.10.0"));
+    }
+
+    #[test]
+    fn prereleases_are_older_than_their_release() {
+        assert!(is_newer("1.0.0", "1.0.0-rc1"));
+        assert!(!is_newer("1.0.0-rc1", "1.0.0"));
+        assert!(is_newer("1.0.0-rc1", "0.9.0"));
+        assert!(is_newer("1.0.0-rc10", "1.0.0-rc2"));
+        assert!(is_newer("1.0.0-rc1", "1.0.0-beta.2"));
+        assert!(!is_newer("1.0.0+build.5", "1.0.0"));
+    }
+}
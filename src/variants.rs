@@ -0,0 +1,302 @@
+use anyhow::{Context, Result};
+use colorize::AnsiColor;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use crate::{
+    cmake::{self, CMAKE_LISTS, FRAGMENT_DIR},
+    executor::executor,
+    format_size, glob, handle_init_project,
+    hash::Fnv,
+    manifest::{MANIFEST_FILE, STATE_DIR},
+    ConfigureArgs,
+};
+
+/// The variants of every build directory, keyed by the build directory
+const VARIANTS_FILE: &str = "build_variants.json";
+
+/// The built-in profiles, which a variant is configured with when the
+/// build directory it branches off uses one
+const BUILT_IN_PROFILES: [&str; 4] = ["debug", "release", "relwithdebinfo", "minsizerel"];
+
+type State = BTreeMap<String, Variants>;
+
+/// The directories a build directory has been split into, one per
+/// configuration the branches built in it have
+#[derive(Serialize, Deserialize, Default)]
+struct Variants {
+    /// The branch and directory of the last build
+    branch: String,
+    active: String,
+    variants: Vec<Variant>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Variant {
+    dir: String,
+    /// Hash of the CMake files and the manifest the directory is configured from
+    hash: String,
+    branch: String,
+}
+
+/// The directory to build in for the checked out branch. After switching
+/// to a branch whose CMake files or manifest differ, the build continues in
+/// a variant of `build_dir` configured for them, `<BUILD_DIR>-<HASH>`,
+/// instead of reconfiguring in place, so switching back finds its build
+/// intact. Editing the configuration without switching branches keeps
+/// reconfiguring in place as usual.
+pub fn select(build_dir: String) -> Result<String> {
+    if !is_configured(&build_dir) {
+        return Ok(build_dir);
+    }
+    let Some(branch) = current_branch() else {
+        return Ok(build_dir);
+    };
+    let hash = working_hash()?;
+
+    let mut state = load();
+    let entry = state.entry(build_dir.clone()).or_default();
+    let known = entry
+        .variants
+        .iter()
+        .position(|variant| variant.hash == hash);
+    let dir = match known {
+        Some(index) => {
+            entry.variants[index].branch = branch.clone();
+            entry.variants[index].dir.clone()
+        }
+        None if entry.variants.is_empty() || entry.branch == branch => {
+            let active = match entry.active.is_empty() {
+                true => build_dir.clone(),
+                false => entry.active.clone(),
+            };
+            entry.variants.retain(|variant| variant.dir != active);
+            entry.variants.push(Variant {
+                dir: active.clone(),
+                hash: hash.clone(),
+                branch: branch.clone(),
+            });
+            active
+        }
+        None => {
+            let dir = format!("{}-{}", build_dir, &hash[..8]);
+            entry.variants.push(Variant {
+                dir: dir.clone(),
+                hash: hash.clone(),
+                branch: branch.clone(),
+            });
+            dir
+        }
+    };
+
+    if entry.active != dir && !entry.active.is_empty() {
+        eprintln!("{}", format!("Building '{}' in '{}'", branch, dir).yellow());
+    }
+    entry.branch = branch;
+    entry.active = dir.clone();
+    if !is_configured(&dir) {
+        handle_init_project(".", &dir, false, &configure_like(&build_dir))?;
+    }
+    if !executor().dry_run() {
+        save(&state)?;
+    }
+
+    Ok(dir)
+}
+
+/// The variant of `build_dir` configured for the working tree, or
+/// `build_dir` itself. Unlike [`select`], nothing is configured, for the
+/// commands that only read or clean a build.
+pub fn active(build_dir: String) -> Result<String> {
+    let state = load();
+    let Some(entry) = state.get(&build_dir) else {
+        return Ok(build_dir);
+    };
+    let hash = working_hash()?;
+
+    Ok(entry
+        .variants
+        .iter()
+        .find(|variant| variant.hash == hash)
+        .map_or(build_dir, |variant| variant.dir.clone()))
+}
+
+/// Deletes the variants of `build_dir` whose configuration no local branch
+/// has anymore, e.g. of merged and deleted branches. The variant of the
+/// working tree and `build_dir` itself are kept. Returns the space freed.
+pub fn prune(build_dir: &str) -> Result<u64> {
+    let mut state = load();
+    let Some(entry) = state.get_mut(build_dir) else {
+        return Ok(0);
+    };
+
+    let mut live = branch_hashes();
+    live.insert(working_hash()?);
+    let (stale, kept) = std::mem::take(&mut entry.variants)
+        .into_iter()
+        .partition::<Vec<_>, _>(|variant| {
+            variant.dir != build_dir && variant.dir != entry.active && !live.contains(&variant.hash)
+        });
+    entry.variants = kept;
+
+    let mut freed = 0;
+    for variant in &stale {
+        let size = dir_size(Path::new(&variant.dir));
+        freed += size;
+        if executor().dry_run() {
+            eprintln!(
+                "Would remove stale variant {} of '{}', {}",
+                variant.dir,
+                variant.branch,
+                format_size(size)
+            );
+            continue;
+        }
+        if Path::new(&variant.dir).exists() {
+            fs::remove_dir_all(&variant.dir)
+                .with_context(|| format!("Failed to remove '{}'", variant.dir))?;
+        }
+        eprintln!(
+            "Removed stale variant {} of '{}', {}",
+            variant.dir,
+            variant.branch,
+            format_size(size)
+        );
+    }
+    if !executor().dry_run() {
+        save(&state)?;
+    }
+
+    Ok(freed)
+}
+
+/// Configures a new variant with the generator and profile of the build
+/// directory it branches off
+pub fn configure_like(build_dir: &str) -> ConfigureArgs {
+    let build_dir = Path::new(build_dir);
+    let profile = cmake::cache_value(build_dir, "CMAKE_BUILD_TYPE")
+        .map(|build_type| build_type.to_ascii_lowercase())
+        .filter(|profile| BUILT_IN_PROFILES.contains(&profile.as_str()));
+
+    ConfigureArgs {
+        generator: cmake::cache_value(build_dir, "CMAKE_GENERATOR"),
+        profile,
+        ..Default::default()
+    }
+}
+
+fn is_configured(build_dir: &str) -> bool {
+    Path::new(build_dir).join("CMakeCache.txt").exists()
+}
+
+fn current_branch() -> Option<String> {
+    git(&["rev-parse", "--abbrev-ref", "HEAD"])
+}
+
+/// The hash of the configuration in the working tree
+fn working_hash() -> Result<String> {
+    let mut fragments = Vec::new();
+    if Path::new(FRAGMENT_DIR).is_dir() {
+        fragments = glob::expand(&format!("{}/*.cmake", FRAGMENT_DIR))?
+            .into_iter()
+            .map(|path| path.display().to_string())
+            .collect();
+    }
+
+    Ok(config_hash(fragments, |path| fs::read(path).ok()))
+}
+
+/// The hashes of the configuration committed on every local branch
+fn branch_hashes() -> BTreeSet<String> {
+    let branches =
+        git(&["for-each-ref", "--format=%(refname:short)", "refs/heads"]).unwrap_or_default();
+
+    branches
+        .lines()
+        .map(|branch| {
+            let fragments = git(&[
+                "ls-tree",
+                "--name-only",
+                branch,
+                &format!("{}/", FRAGMENT_DIR),
+            ])
+            .unwrap_or_default()
+            .lines()
+            .filter(|path| path.ends_with(".cmake"))
+            .map(String::from)
+            .collect();
+            config_hash(fragments, |path| {
+                let output = executor()
+                    .capture(Command::new("git").args(["show", &format!("{}:{}", branch, path)]))
+                    .ok()?;
+                output.status.success().then_some(output.stdout)
+            })
+        })
+        .collect()
+}
+
+/// Hashes the files CMake is configured from: CMakeLists.txt, the
+/// fragments in `cmake/` and the manifest
+fn config_hash(mut fragments: Vec<String>, read: impl Fn(&str) -> Option<Vec<u8>>) -> String {
+    fragments.sort();
+
+    let mut hasher = Fnv::new();
+    for path in [CMAKE_LISTS.to_string(), MANIFEST_FILE.to_string()]
+        .into_iter()
+        .chain(fragments)
+    {
+        hasher.write(path.as_bytes());
+        match read(&path) {
+            Some(contents) => hasher.write(&contents),
+            None => hasher.write(&[0xff]),
+        }
+    }
+
+    hasher.finish()
+}
+
+fn git(args: &[&str]) -> Option<String> {
+    let output = executor().capture(Command::new("git").args(args)).ok()?;
+
+    output
+        .status
+        .success()
+        .then(|| String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+fn dir_size(dir: &Path) -> u64 {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return 0;
+    };
+
+    entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| match entry.file_type() {
+            Ok(file_type) if file_type.is_dir() => dir_size(&entry.path()),
+            _ => entry.metadata().map(|metadata| metadata.len()).unwrap_or(0),
+        })
+        .sum()
+}
+
+fn state_path() -> PathBuf {
+    Path::new(STATE_DIR).join(VARIANTS_FILE)
+}
+
+/// A state that doesn't parse, e.g. from an older version, is started over
+fn load() -> State {
+    fs::read_to_string(state_path())
+        .ok()
+        .and_then(|text| serde_json::from_str(&text).ok())
+        .unwrap_or_default()
+}
+
+fn save(state: &State) -> Result<()> {
+    fs::create_dir_all(STATE_DIR).context("Failed to create state directory")?;
+    let text = serde_json::to_string(state).context("Failed to serialize build variants")?;
+    fs::write(state_path(), text).context("Failed to write build variants")
+}
@@ -0,0 +1,224 @@
+use anyhow::{Context, Result};
+use colorize::AnsiColor;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    process::Command,
+    time::Instant,
+};
+
+use crate::{
+    cmake,
+    executor::executor,
+    handle_build_project, handle_init_project, handle_test_project, lint,
+    manifest::{Manifest, MANIFEST_FILE},
+    sources, ConfigureArgs, FailureArgs,
+};
+
+enum Step {
+    Format,
+    Build(String),
+    Lint,
+    Test,
+    Coverage(f64),
+}
+
+impl Step {
+    fn label(&self) -> String {
+        match self {
+            Step::Format => "format".to_string(),
+            Step::Build(profile) => format!("build ({})", profile),
+            Step::Lint => "lint".to_string(),
+            Step::Test => "test".to_string(),
+            Step::Coverage(minimum) => format!("coverage (>= {}%)", minimum),
+        }
+    }
+}
+
+/// Where a profile is built. The first profile uses the build directory
+/// itself, so its results are reused by the other commands.
+fn profile_build_dir(build_dir: &str, profiles: &[String], profile: &str) -> String {
+    if profiles.first().is_some_and(|first| first == profile) {
+        build_dir.to_string()
+    } else {
+        format!("{}-{}", build_dir, profile)
+    }
+}
+
+/// Runs the manifest's `[verify]` steps as one pipeline and prints how long
+/// each took and whether it passed. Stops at the first failure unless
+/// `keep_going` is set.
+pub fn handle_verify(
+    build_dir: String,
+    runtime_dir: String,
+    jobs: Option<usize>,
+    keep_going: bool,
+) -> Result<()> {
+    let manifest = Manifest::load()?;
+    let config = &manifest.verify;
+    if config.profiles.is_empty() {
+        anyhow::bail!("[verify] in {} lists no profiles", MANIFEST_FILE);
+    }
+
+    let mut steps = Vec::new();
+    for step in &config.steps {
+        match step.as_str() {
+            "format" => steps.push(Step::Format),
+            "build" => steps.extend(config.profiles.iter().cloned().map(Step::Build)),
+            "lint" => steps.push(Step::Lint),
+            "test" => steps.push(Step::Test),
+            "coverage" => steps.push(Step::Coverage(config.coverage)),
+            _ => anyhow::bail!(
+                "Invalid verify step '{}', valid steps are 'format', 'build', 'lint', 'test' and 'coverage'",
+                step
+            ),
+        }
+    }
+    if steps.is_empty() {
+        anyhow::bail!("[verify] in {} lists no steps", MANIFEST_FILE);
+    }
+
+    let main_dir = build_dir.clone();
+    let main_profile = &config.profiles[0];
+    let mut results: Vec<(String, Option<(f64, bool)>)> = Vec::new();
+    let mut stopped = false;
+    for step in &steps {
+        if stopped {
+            results.push((step.label(), None));
+            continue;
+        }
+
+        eprintln!("{}", format!("==> {}", step.label()).green());
+        let start = Instant::now();
+        let result = match step {
+            Step::Format => check_format(),
+            Step::Build(profile) => {
+                let dir = profile_build_dir(&build_dir, &config.profiles, profile);
+                configure(&dir, profile, true).and_then(|_| {
+                    handle_build_project(dir, &runtime_dir, jobs, &FailureArgs::default())
+                })
+            }
+            Step::Lint => configure(&main_dir, main_profile, false)
+                .and_then(|_| lint::handle_lint(main_dir.clone(), jobs, false, false)),
+            Step::Test => configure(&main_dir, main_profile, false).and_then(|_| {
+                handle_test_project(main_dir.clone(), &runtime_dir, jobs, true, None, None)
+            }),
+            Step::Coverage(minimum) => coverage(&build_dir, *minimum, jobs),
+        };
+        let passed = match result {
+            Ok(()) => true,
+            Err(error) => {
+                eprintln!("{} {:#}", "error:".red(), error);
+                false
+            }
+        };
+        results.push((step.label(), Some((start.elapsed().as_secs_f64(), passed))));
+        stopped = !passed && !keep_going;
+    }
+
+    let width = results
+        .iter()
+        .map(|(label, _)| label.len())
+        .max()
+        .unwrap_or(0);
+    println!();
+    for (label, result) in &results {
+        let (time, status) = match result {
+            Some((seconds, true)) => (format!("{:.1}s", seconds), "ok".green()),
+            Some((seconds, false)) => (format!("{:.1}s", seconds), "FAILED".red()),
+            None => ("-".to_string(), "skipped".yellow()),
+        };
+        println!("{:width$}  {:>7}  {}", label, time, status, width = width);
+    }
+
+    let failed = results
+        .iter()
+        .filter(|(_, result)| matches!(result, Some((_, false))))
+        .count();
+    if failed > 0 {
+        anyhow::bail!(
+            "Verification failed, {} of {} steps failed",
+            failed,
+            steps.len()
+        );
+    }
+    eprintln!("{}", "All checks passed".green());
+
+    Ok(())
+}
+
+/// Configures a build directory with a profile, unless it is configured
+/// already and `always` isn't set
+fn configure(build_dir: &str, profile: &str, always: bool) -> Result<()> {
+    if !always && cmake::cache_value(Path::new(build_dir), "CMAKE_HOME_DIRECTORY").is_some() {
+        return Ok(());
+    }
+
+    let configure = ConfigureArgs {
+        profile: Some(profile.to_string()),
+        ..Default::default()
+    };
+    handle_init_project(".", build_dir, false, &configure)
+}
+
+fn check_format() -> Result<()> {
+    let files = sources::project_sources()?
+        .into_iter()
+        .filter(|file| sources::is_source(file) || sources::is_header(file))
+        .collect::<Vec<_>>();
+    if files.is_empty() {
+        return Ok(());
+    }
+
+    executor()
+        .execute(
+            Command::new("clang-format")
+                .args(["--dry-run", "-Werror", "-style=file"])
+                .args(&files),
+        )
+        .context("Sources aren't formatted, run 'cxx format'")
+}
+
+/// Where the coverage step writes its HTML report
+pub fn coverage_report_dir(build_dir: &str) -> PathBuf {
+    Path::new(&format!("{}-coverage", build_dir)).join("coverage")
+}
+
+/// Builds and tests with gcov instrumentation in its own directory, then
+/// checks the line coverage of the project's sources with gcovr and writes
+/// an HTML report of it
+fn coverage(build_dir: &str, minimum: f64, jobs: Option<usize>) -> Result<()> {
+    let dir = format!("{}-coverage", build_dir);
+    executor()
+        .execute(&mut cmake::configure_command(
+            &dir,
+            &[
+                ("CMAKE_BUILD_TYPE", "Debug"),
+                ("CMAKE_C_FLAGS", "--coverage"),
+                ("CMAKE_CXX_FLAGS", "--coverage"),
+                ("CMAKE_EXE_LINKER_FLAGS", "--coverage"),
+                ("CMAKE_SHARED_LINKER_FLAGS", "--coverage"),
+            ],
+        ))
+        .context("Failed to configure the coverage build")?;
+
+    executor()
+        .execute(&mut cmake::build_command(&dir, None, jobs))
+        .context("Failed to build with coverage")?;
+    executor()
+        .execute(Command::new("ctest").args(["--test-dir", &dir, "--output-on-failure"]))
+        .context("Tests failed in the coverage build")?;
+
+    let report = coverage_report_dir(build_dir);
+    fs::create_dir_all(&report)
+        .with_context(|| format!("Failed to create {}", report.display()))?;
+    executor()
+        .execute(
+            Command::new("gcovr")
+                .args(["--root", ".", "--exclude", &format!("{}/", dir)])
+                .args(["--print-summary", "--html-details"])
+                .arg(report.join("index.html"))
+                .args(["--fail-under-line", &minimum.to_string(), &dir]),
+        )
+        .with_context(|| format!("Line coverage is below {}%", minimum))
+}
@@ -0,0 +1,178 @@
+use anyhow::{Context, Result};
+use colorize::AnsiColor;
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::Path,
+    process::Command,
+    thread,
+    time::{Duration, SystemTime},
+};
+
+use crate::{
+    build_info::format_timestamp, compdb::split_command, executor::executor, glob,
+    manifest::Manifest,
+};
+
+/// How often the project is scanned for changes
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Editors save in several steps, so a run waits until nothing changed for
+/// this long
+const SETTLE_TIME: Duration = Duration::from_millis(200);
+
+/// Clears the terminal and moves the cursor home
+const CLEAR_SCREEN: &str = "\x1b[2J\x1b[H";
+
+pub type Snapshot = BTreeMap<String, (SystemTime, u64)>;
+
+/// Runs a cxx command, e.g. `test --filter Parser`, and again whenever a
+/// file matching the include globs and none of the exclude globs changes.
+/// Globs given on the command line replace the manifest's `[watch]` ones.
+pub fn handle_watch(
+    command: String,
+    include: Vec<String>,
+    exclude: Vec<String>,
+    no_clear: bool,
+) -> Result<()> {
+    let arguments = split_command(&command);
+    match arguments.first().map(String::as_str) {
+        None => anyhow::bail!("No command to watch, e.g. cxx watch \"test\""),
+        Some("watch") => anyhow::bail!("'watch' can't watch itself"),
+        Some(_) => {}
+    }
+
+    let manifest = Manifest::load()?;
+    let config = &manifest.watch;
+    let include = if include.is_empty() {
+        config.include.clone()
+    } else {
+        include
+    };
+    let exclude = if exclude.is_empty() {
+        config.exclude.clone()
+    } else {
+        exclude
+    };
+    let clear = config.clear && !no_clear;
+
+    let executable = std::env::current_exe().context("Failed to locate the cxx executable")?;
+    let mut snapshot = scan(&include, &exclude);
+    let mut changed = Vec::new();
+    loop {
+        if clear {
+            eprint!("{}", CLEAR_SCREEN);
+        }
+        let now = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map(|duration| duration.as_secs())
+            .unwrap_or(0);
+        let reason = match changed.len() {
+            0 => String::new(),
+            1 => format!(", {} changed", changed[0]),
+            count => format!(", {} files changed", count),
+        };
+        eprintln!(
+            "{}",
+            format!("[{}] cxx {}{}", format_timestamp(now), command, reason).grey()
+        );
+
+        let mut child = Command::new(&executable);
+        child.args(&arguments);
+        if executor().dry_run() {
+            child.env("CPP_TOOLS_DRY_RUN", "true");
+        }
+        match child.status() {
+            Ok(status) if status.success() => eprintln!("{}", "Succeeded".green()),
+            Ok(status) => eprintln!("{}", format!("Failed ({})", status).red()),
+            Err(error) => eprintln!("{}", format!("Failed to run cxx: {}", error).red()),
+        }
+        eprintln!("{}", "Watching for changes...".grey());
+
+        changed = loop {
+            thread::sleep(POLL_INTERVAL);
+            let current = scan(&include, &exclude);
+            if current != snapshot {
+                // Wait for the rest of the save before running
+                let mut settled = current;
+                loop {
+                    thread::sleep(SETTLE_TIME);
+                    let next = scan(&include, &exclude);
+                    if next == settled {
+                        break;
+                    }
+                    settled = next;
+                }
+                let changed = differences(&snapshot, &settled);
+                snapshot = settled;
+                break changed;
+            }
+        };
+    }
+}
+
+/// The modification time and size of every watched file
+pub fn scan(include: &[String], exclude: &[String]) -> Snapshot {
+    let mut snapshot = Snapshot::new();
+    collect(Path::new("."), include, exclude, &mut snapshot);
+    snapshot
+}
+
+/// Walks the project like `sources` does, skipping hidden directories and
+/// configured build trees, which change on every build
+fn collect(dir: &Path, include: &[String], exclude: &[String], snapshot: &mut Snapshot) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+
+    for entry in entries.filter_map(|entry| entry.ok()) {
+        let path = entry.path();
+        let hidden = entry.file_name().to_string_lossy().starts_with('.');
+        let relative = path
+            .strip_prefix(".")
+            .unwrap_or(&path)
+            .to_string_lossy()
+            .to_string();
+
+        if path.is_dir() {
+            let excluded = exclude
+                .iter()
+                .any(|pattern| glob::matches(pattern, &relative));
+            if !hidden && !excluded && !path.join("CMakeCache.txt").exists() {
+                collect(&path, include, exclude, snapshot);
+            }
+            continue;
+        }
+
+        let watched = include
+            .iter()
+            .any(|pattern| glob::matches(pattern, &relative))
+            && !exclude
+                .iter()
+                .any(|pattern| glob::matches(pattern, &relative));
+        if !watched {
+            continue;
+        }
+        if let Ok(metadata) = entry.metadata() {
+            let modified = metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+            snapshot.insert(relative, (modified, metadata.len()));
+        }
+    }
+}
+
+/// Files added, removed or modified between two snapshots
+fn differences(before: &Snapshot, after: &Snapshot) -> Vec<String> {
+    let mut changed = after
+        .iter()
+        .filter(|(file, state)| before.get(*file) != Some(state))
+        .map(|(file, _)| file.clone())
+        .collect::<Vec<_>>();
+    changed.extend(
+        before
+            .keys()
+            .filter(|file| !after.contains_key(*file))
+            .cloned(),
+    );
+    changed.sort();
+    changed
+}
@@ -0,0 +1,602 @@
+use anyhow::{Context, Result};
+use colorize::AnsiColor;
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+    process::{Command, Output},
+    sync::mpsc,
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    build_info,
+    cmake::{self, CMAKE_LISTS},
+    config::{toml_string, write_value},
+    executor::executor,
+    hash::Fnv,
+    manifest::{Manifest, MANIFEST_FILE, STATE_DIR},
+    tags::TAG_FILES,
+};
+
+/// The fragment wiring a member to the siblings it depends on
+const MEMBERS_FRAGMENT: &str = "workspace.cmake";
+
+/// Hashes of the members each task last succeeded for, relative to the
+/// state directory of the workspace root
+const CACHE_FILE: &str = "workspace_cache.json";
+
+/// A project of the workspace
+pub struct Member {
+    pub name: String,
+    /// Relative to the workspace root
+    pub dir: PathBuf,
+    /// Names of the members it depends on
+    pub depends: Vec<String>,
+}
+
+/// What the workspace runs in every member
+#[derive(Clone, Copy)]
+pub enum Task {
+    Build,
+    Test,
+}
+
+impl Task {
+    fn name(self) -> &'static str {
+        match self {
+            Task::Build => "build",
+            Task::Test => "test",
+        }
+    }
+}
+
+enum Outcome {
+    Passed(Duration),
+    Failed(Duration),
+    /// Not run, e.g. because nothing changed
+    Skipped(&'static str),
+    /// Not run because a dependency failed
+    Blocked(String),
+}
+
+/// The workspace's members in dependency order, each after the members it
+/// depends on. Fails on unknown dependencies and cycles.
+pub fn members() -> Result<Vec<Member>> {
+    members_in(Path::new("."))
+}
+
+/// The members of the workspace rooted at `root_dir`, with directories
+/// relative to it
+fn members_in(root_dir: &Path) -> Result<Vec<Member>> {
+    let manifest = Manifest::load_from(root_dir)?;
+    if manifest.workspace.members.is_empty() {
+        anyhow::bail!(
+            "No workspace members, list them in [workspace] members of {}",
+            MANIFEST_FILE
+        );
+    }
+
+    let mut members = Vec::new();
+    for dir in &manifest.workspace.members {
+        let dir = Path::new(dir);
+        let dir = dir.strip_prefix(".").unwrap_or(dir).to_path_buf();
+        let member_dir = root_dir.join(&dir);
+        if !member_dir.join(MANIFEST_FILE).exists() && !member_dir.join(CMAKE_LISTS).exists() {
+            anyhow::bail!("Workspace member '{}' isn't a project", dir.display());
+        }
+        let member_manifest = Manifest::load_from(&member_dir)
+            .with_context(|| format!("Failed to load workspace member '{}'", dir.display()))?;
+        let name = member_manifest.package.name.clone().unwrap_or_else(|| {
+            dir.file_name()
+                .map(|name| name.to_string_lossy().to_string())
+                .unwrap_or_else(|| dir.display().to_string())
+        });
+        if members.iter().any(|member: &Member| member.name == name) {
+            anyhow::bail!("Two workspace members are named '{}'", name);
+        }
+        members.push(Member {
+            name,
+            dir,
+            depends: member_manifest.workspace.depends,
+        });
+    }
+
+    for member in &members {
+        if let Some(unknown) = member
+            .depends
+            .iter()
+            .find(|name| !members.iter().any(|other| other.name == **name))
+        {
+            anyhow::bail!(
+                "Workspace member '{}' depends on '{}', which isn't a member",
+                member.name,
+                unknown
+            );
+        }
+    }
+
+    let mut ordered: Vec<Member> = Vec::new();
+    while !members.is_empty() {
+        let ready = members.iter().position(|member| {
+            member
+                .depends
+                .iter()
+                .all(|name| ordered.iter().any(|done| done.name == *name))
+        });
+        match ready {
+            Some(index) => ordered.push(members.remove(index)),
+            None => {
+                let names = members
+                    .iter()
+                    .map(|member| member.name.as_str())
+                    .collect::<Vec<_>>();
+                anyhow::bail!(
+                    "Workspace members depend on each other in a cycle: {}",
+                    names.join(", ")
+                );
+            }
+        }
+    }
+
+    Ok(ordered)
+}
+
+/// Makes the workspace member in the current directory depend on a sibling
+/// library: records it in the member's manifest, so the workspace builds
+/// the sibling first, and generates a CMake fragment that builds the sibling
+/// along and links it with its headers on the include path
+pub fn handle_add_member(name: String) -> Result<()> {
+    let current = fs::canonicalize(".").context("Failed to resolve project directory")?;
+    let Some((root, members)) = current.ancestors().skip(1).find_map(|dir| {
+        let manifest = Manifest::load_from(dir).ok()?;
+        (!manifest.workspace.members.is_empty()).then(|| (dir.to_path_buf(), members_in(dir)))
+    }) else {
+        anyhow::bail!(
+            "Not inside a workspace member, no parent directory lists [workspace] members"
+        );
+    };
+    let members = members?;
+    let relative = current.strip_prefix(&root).unwrap_or(&current);
+    let Some(member) = members.iter().find(|member| member.dir == relative) else {
+        anyhow::bail!(
+            "'{}' isn't a member of the workspace in '{}'",
+            relative.display(),
+            root.display()
+        );
+    };
+    let Some(dependency) = members.iter().find(|other| other.name == name) else {
+        let names = members
+            .iter()
+            .map(|member| member.name.as_str())
+            .collect::<Vec<_>>();
+        anyhow::bail!(
+            "No workspace member named '{}', the members are {}",
+            name,
+            names.join(", ")
+        );
+    };
+    if dependency.name == member.name {
+        anyhow::bail!("A member can't depend on itself");
+    }
+    if depends_on(&members, dependency, &member.name) {
+        anyhow::bail!(
+            "'{}' already depends on '{}', depending back would be a cycle",
+            dependency.name,
+            member.name
+        );
+    }
+
+    let mut depends = member.depends.clone();
+    if !depends.contains(&name) {
+        depends.push(name.clone());
+    }
+    let mut fragment = String::from(
+        "# Builds and links the workspace members this project depends on,\n\
+         # generated by `cxx add --member` from [workspace] depends\n",
+    );
+    for dependency in &depends {
+        let dependency = members
+            .iter()
+            .find(|other| other.name == *dependency)
+            .context("Dependency vanished from the workspace")?;
+        fragment.push_str(&member_wiring(&root, member, dependency)?);
+    }
+
+    let literal = format!(
+        "[{}]",
+        depends
+            .iter()
+            .map(|name| toml_string(name))
+            .collect::<Vec<_>>()
+            .join(", ")
+    );
+    let text = fs::read_to_string(MANIFEST_FILE).unwrap_or_default();
+    fs::write(
+        MANIFEST_FILE,
+        write_value(&text, "workspace.depends", &literal),
+    )
+    .with_context(|| format!("Failed to update {}", MANIFEST_FILE))?;
+    cmake::write_fragment(MEMBERS_FRAGMENT, &fragment)?;
+
+    eprintln!(
+        "{}",
+        format!(
+            "{} now depends on {}, the workspace builds {} first",
+            member.name, dependency.name, dependency.name
+        )
+        .green()
+    );
+
+    Ok(())
+}
+
+/// Whether a member depends on another, directly or through other members
+fn depends_on(members: &[Member], member: &Member, name: &str) -> bool {
+    member.depends.iter().any(|dependency| {
+        dependency == name
+            || members
+                .iter()
+                .find(|other| other.name == *dependency)
+                .is_some_and(|other| depends_on(members, other, name))
+    })
+}
+
+/// The CMake building a sibling library into the member's build tree, once
+/// even when several members depend on it, and linking it
+fn member_wiring(root: &Path, member: &Member, dependency: &Member) -> Result<String> {
+    let dependency_dir = root.join(&dependency.dir);
+    let target = cmake::project_name_in(&dependency_dir)?;
+    let lists = fs::read_to_string(dependency_dir.join(CMAKE_LISTS)).unwrap_or_default();
+    let library = lists.lines().any(|line| {
+        line.trim()
+            .strip_prefix("add_library(")
+            .is_some_and(|rest| rest.split([' ', ')']).next() == Some(target.as_str()))
+    });
+    if !library {
+        anyhow::bail!(
+            "'{}' builds an executable, only libraries can be depended on",
+            dependency.name
+        );
+    }
+    // Generated projects put their headers on the include path of the
+    // directory rather than the target, so linking doesn't carry them over
+    let include_dir =
+        cmake::include_dir_in(&dependency_dir).unwrap_or_else(|| "include".to_string());
+
+    let up = "../".repeat(member.dir.components().count());
+    let source_dir = format!(
+        "${{CMAKE_CURRENT_SOURCE_DIR}}/{}{}",
+        up,
+        dependency.dir.display()
+    );
+
+    Ok(format!(
+        "\nif(NOT TARGET {target})\n  \
+         add_subdirectory({source_dir} ${{CMAKE_BINARY_DIR}}/workspace/{name} EXCLUDE_FROM_ALL)\n\
+         endif()\n\
+         target_link_libraries(${{PROJECT_NAME}} PRIVATE {target})\n\
+         target_include_directories(${{PROJECT_NAME}} PRIVATE {source_dir}/{include_dir})\n",
+        name = dependency.name,
+    ))
+}
+
+/// Runs a task in every member of the workspace, in dependency order with up
+/// to `jobs` independent members at once. Members whose files and
+/// dependencies are unchanged since the task last succeeded are skipped
+/// unless `no_cache` is set. `affected_since` only runs the members changed
+/// since a git ref and the members depending on them.
+pub fn handle_workspace(
+    task: Task,
+    jobs: Option<usize>,
+    affected_since: Option<String>,
+    no_cache: bool,
+) -> Result<()> {
+    let members = members()?;
+    let executable = std::env::current_exe().context("Failed to locate the cxx executable")?;
+
+    if executor().dry_run() {
+        for member in &members {
+            executor().execute(
+                Command::new("cxx")
+                    .current_dir(&member.dir)
+                    .arg(task.name()),
+            )?;
+        }
+        return Ok(());
+    }
+
+    let affected = match &affected_since {
+        Some(since) => Some(affected(&members, since)?),
+        None => None,
+    };
+    let mut keys: Vec<String> = Vec::new();
+    for member in &members {
+        let mut hasher = Fnv::new();
+        hasher.write(task.name().as_bytes());
+        hash_dir(&member.dir, &member.dir, &outputs(&member.dir), &mut hasher);
+        for name in &member.depends {
+            let index = members.iter().position(|other| other.name == *name);
+            if let Some(key) = index.and_then(|index| keys.get(index)) {
+                hasher.write(key.as_bytes());
+            }
+        }
+        keys.push(hasher.finish());
+    }
+
+    let mut cache = if no_cache { Cache::new() } else { load() };
+    let cache_key = |member: &Member| format!("{}:{}", task.name(), member.name);
+
+    let jobs = jobs
+        .or_else(|| thread::available_parallelism().ok().map(|jobs| jobs.get()))
+        .unwrap_or(1)
+        .max(1);
+    let mut outcomes: Vec<Option<Outcome>> = members.iter().map(|_| None).collect();
+    let mut running = 0;
+    let (sender, receiver) = mpsc::channel();
+    loop {
+        for (index, member) in members.iter().enumerate() {
+            if outcomes[index].is_some() || running >= jobs {
+                continue;
+            }
+            let dependencies = member
+                .depends
+                .iter()
+                .filter_map(|name| members.iter().position(|other| other.name == *name))
+                .collect::<Vec<_>>();
+            if let Some(failed) = dependencies.iter().find(|dependency| {
+                matches!(
+                    outcomes[**dependency],
+                    Some(Outcome::Failed(_) | Outcome::Blocked(_))
+                )
+            }) {
+                outcomes[index] = Some(Outcome::Blocked(members[*failed].name.clone()));
+                continue;
+            }
+            let ready = dependencies.iter().all(|dependency| {
+                matches!(
+                    outcomes[*dependency],
+                    Some(Outcome::Passed(_) | Outcome::Skipped(_))
+                )
+            });
+            if !ready {
+                continue;
+            }
+
+            if affected
+                .as_ref()
+                .is_some_and(|affected| !affected.contains(&member.name))
+            {
+                outcomes[index] = Some(Outcome::Skipped("not affected"));
+                continue;
+            }
+            if cache.get(&cache_key(member)) == Some(&keys[index]) {
+                outcomes[index] = Some(Outcome::Skipped("unchanged"));
+                continue;
+            }
+
+            eprintln!(
+                "{}",
+                format!("Running {} in {}", task.name(), member.name).bold()
+            );
+            let sender = sender.clone();
+            let mut command = Command::new(&executable);
+            command.arg(task.name()).current_dir(&member.dir);
+            thread::spawn(move || {
+                let start = Instant::now();
+                let output = command.output();
+                let _ = sender.send((index, output, start.elapsed()));
+            });
+            running += 1;
+        }
+
+        if running == 0 {
+            break;
+        }
+        let (index, output, elapsed) = receiver.recv().context("Workspace task vanished")?;
+        running -= 1;
+        let member = &members[index];
+        let outcome = finish(member, task, output, elapsed);
+        if matches!(outcome, Outcome::Passed(_)) {
+            cache.insert(cache_key(member), keys[index].clone());
+            save(&cache)?;
+        }
+        outcomes[index] = Some(outcome);
+    }
+
+    print_summary(&members, &outcomes, task);
+    let failed = outcomes
+        .iter()
+        .filter(|outcome| matches!(outcome, Some(Outcome::Failed(_) | Outcome::Blocked(_))))
+        .count();
+    if failed > 0 {
+        anyhow::bail!(
+            "{} failed for {} of {} workspace members",
+            task.name(),
+            failed,
+            members.len()
+        );
+    }
+
+    Ok(())
+}
+
+/// Reports a finished member, showing the output of failures
+fn finish(
+    member: &Member,
+    task: Task,
+    output: std::io::Result<Output>,
+    elapsed: Duration,
+) -> Outcome {
+    match output {
+        Ok(output) if output.status.success() => {
+            eprintln!(
+                "{}",
+                format!(
+                    "Finished {} in {} ({:.1}s)",
+                    task.name(),
+                    member.name,
+                    elapsed.as_secs_f64()
+                )
+                .green()
+            );
+            Outcome::Passed(elapsed)
+        }
+        Ok(output) => {
+            eprintln!(
+                "{}",
+                format!("{} failed in {}", task.name(), member.name).red()
+            );
+            eprint!("{}", String::from_utf8_lossy(&output.stdout));
+            eprint!("{}", String::from_utf8_lossy(&output.stderr));
+            Outcome::Failed(elapsed)
+        }
+        Err(error) => {
+            eprintln!(
+                "{}",
+                format!(
+                    "Failed to run {} in {}: {}",
+                    task.name(),
+                    member.name,
+                    error
+                )
+                .red()
+            );
+            Outcome::Failed(elapsed)
+        }
+    }
+}
+
+fn print_summary(members: &[Member], outcomes: &[Option<Outcome>], task: Task) {
+    let width = members
+        .iter()
+        .map(|member| member.name.len())
+        .max()
+        .unwrap_or(0);
+
+    eprintln!();
+    for (member, outcome) in members.iter().zip(outcomes) {
+        let result = match outcome {
+            Some(Outcome::Passed(elapsed)) => {
+                format!("{} ({:.1}s)", task.name(), elapsed.as_secs_f64()).green()
+            }
+            Some(Outcome::Failed(elapsed)) => {
+                format!("failed ({:.1}s)", elapsed.as_secs_f64()).red()
+            }
+            Some(Outcome::Skipped(reason)) => format!("skipped, {}", reason).grey(),
+            Some(Outcome::Blocked(dependency)) => {
+                format!("not run, {} failed", dependency).yellow()
+            }
+            None => "not run".grey(),
+        };
+        eprintln!("{:<width$}  {}", member.name, result);
+    }
+}
+
+/// The members with files changed since a git ref, committed or not, and
+/// the members depending on them
+fn affected(members: &[Member], since: &str) -> Result<Vec<String>> {
+    let mut changed = Vec::new();
+    for args in [
+        vec!["diff", "--name-only", "--relative", since],
+        vec!["ls-files", "--others", "--exclude-standard"],
+    ] {
+        let output = executor()
+            .capture(Command::new("git").args(&args))
+            .context("Failed to run git")?;
+        if !output.status.success() {
+            anyhow::bail!(
+                "Failed to list the files changed since '{}': {}",
+                since,
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+        changed.extend(
+            String::from_utf8_lossy(&output.stdout)
+                .lines()
+                .map(PathBuf::from),
+        );
+    }
+
+    // Members come in dependency order, so their dependencies are decided first
+    let mut affected: Vec<String> = Vec::new();
+    for member in members {
+        let own = changed.iter().any(|file| file.starts_with(&member.dir));
+        let dependency = member.depends.iter().any(|name| affected.contains(name));
+        if own || dependency {
+            affected.push(member.name.clone());
+        }
+    }
+
+    Ok(affected)
+}
+
+/// What a member's tasks write besides its build directory: the executable
+/// directory, the generated headers and tags, which would change its hash
+/// on every run
+fn outputs(dir: &Path) -> Vec<PathBuf> {
+    let manifest = Manifest::load_from(dir).unwrap_or_default();
+    let runtime_dir = manifest.dirs.runtime.as_deref().unwrap_or("bin");
+
+    let mut outputs = vec![dir.join(runtime_dir)];
+    outputs.extend(TAG_FILES.map(|file| dir.join(file)));
+    if let Ok(Some(header)) = build_info::header_path(&manifest, dir) {
+        outputs.push(dir.join(header));
+    }
+    if !manifest.assets.embed.is_empty() {
+        outputs.push(dir.join(&manifest.assets.embed_header));
+    }
+
+    outputs
+}
+
+/// Hashes the paths and contents of a member's files, skipping hidden
+/// directories, configured build trees and the member's outputs
+fn hash_dir(root: &Path, dir: &Path, outputs: &[PathBuf], hasher: &mut Fnv) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    let mut paths = entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| {
+            !path
+                .file_name()
+                .is_some_and(|name| name.to_string_lossy().starts_with('.'))
+                && !outputs.contains(path)
+        })
+        .collect::<Vec<_>>();
+    paths.sort();
+
+    for path in paths {
+        if path.is_dir() {
+            if !path.join("CMakeCache.txt").exists() {
+                hash_dir(root, &path, outputs, hasher);
+            }
+        } else {
+            let relative = path.strip_prefix(root).unwrap_or(&path);
+            hasher.write(relative.to_string_lossy().as_bytes());
+            hasher.write_file(&path);
+        }
+    }
+}
+
+type Cache = BTreeMap<String, String>;
+
+fn cache_path() -> PathBuf {
+    Path::new(STATE_DIR).join(CACHE_FILE)
+}
+
+fn load() -> Cache {
+    fs::read_to_string(cache_path())
+        .ok()
+        .and_then(|text| serde_json::from_str(&text).ok())
+        .unwrap_or_default()
+}
+
+fn save(cache: &Cache) -> Result<()> {
+    fs::create_dir_all(STATE_DIR).context("Failed to create state directory")?;
+    let text = serde_json::to_string(cache).context("Failed to serialize workspace cache")?;
+    fs::write(cache_path(), text).context("Failed to write workspace cache")
+}
@@ -0,0 +1,77 @@
+use colorize::AnsiColor;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use crate::{cmake, config::GlobalConfig, hash::Fnv};
+
+/// The build directory of the project at the canonical `root` under the
+/// configured `build_root`, named after the project's directory and keyed by
+/// its path, so every worktree of a repository builds in a directory of its
+/// own
+pub fn build_dir(root: &Path) -> Option<String> {
+    let build_root = GlobalConfig::load().ok()?.build_root?;
+    let name = root.file_name()?.to_string_lossy().to_string();
+
+    let mut hasher = Fnv::new();
+    hasher.write(root.as_os_str().as_encoded_bytes());
+    let key = &hasher.finish()[..8];
+
+    Some(
+        Path::new(&build_root)
+            .join(format!("{}-{}", name, key))
+            .display()
+            .to_string(),
+    )
+}
+
+/// Whether a build directory is configured for the sources of another
+/// directory than the project at the canonical `root`, e.g. a recorded build directory committed in one worktree and
+/// checked out in another, whose cache CMake would refuse or, worse, clobber
+pub fn is_foreign(root: &Path, build_dir: &str) -> bool {
+    let build_dir = root.join(build_dir);
+    let Some(home) = cmake::cache_value(&build_dir, "CMAKE_HOME_DIRECTORY") else {
+        return false;
+    };
+    let foreign = fs::canonicalize(&home).map_or(true, |home| home != root);
+    if foreign {
+        eprintln!(
+            "{}",
+            format!(
+                "Ignoring build directory '{}', it's configured for '{}'",
+                build_dir.display(),
+                home
+            )
+            .yellow()
+        );
+    }
+
+    foreign
+}
+
+/// Points ccache at the project root when it's one of several worktrees of
+/// a repository, so objects compiled in one worktree are hits in the others
+/// despite their different paths
+pub fn share_compiler_cache() {
+    if std::env::var_os("CCACHE_BASEDIR").is_some() {
+        return;
+    }
+    let Some(root) = project_root() else {
+        return;
+    };
+    let git = root.join(".git");
+    // A linked worktree has a `.git` file, the main one a `worktrees` list
+    let has_worktrees = git.is_file()
+        || fs::read_dir(git.join("worktrees")).is_ok_and(|mut entries| entries.next().is_some());
+    if has_worktrees {
+        std::env::set_var("CCACHE_BASEDIR", root);
+    }
+}
+
+fn project_root() -> Option<PathBuf> {
+    let current = std::env::current_dir().ok()?;
+    let root = cmake::project_root_of(&current)?;
+
+    fs::canonicalize(root).ok()
+}
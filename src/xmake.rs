@@ -0,0 +1,197 @@
+use anyhow::Result;
+use std::{fmt::Write, path::Path, process::Command};
+
+use crate::backend::{BuildBackend, Project, Target, GENERATED_MARKER};
+
+/// The xmake project file
+pub const XMAKE_FILE: &str = "xmake.lua";
+
+/// Generates an `xmake.lua` with one target per project target. Projects
+/// scaffolded with `new --build-system xmake` are built with xmake as well.
+pub struct Xmake;
+
+impl BuildBackend for Xmake {
+    fn name(&self) -> &'static str {
+        "xmake"
+    }
+
+    fn generate(&self, project: &Project) -> Result<Vec<(String, String)>> {
+        let header = format!("-- {} export xmake, do not edit.\n", GENERATED_MARKER);
+
+        Ok(vec![(
+            XMAKE_FILE.to_string(),
+            header + &project_file(project)?,
+        )])
+    }
+
+    fn configure_command(
+        &self,
+        root_dir: &str,
+        build_dir: &str,
+        profile: Option<&str>,
+    ) -> Result<Command> {
+        let mut command = Command::new("xmake");
+        command
+            .args(["config", "-y", "-P", root_dir, "-o"])
+            .arg(Path::new(root_dir).join(build_dir));
+        if let Some(profile) = profile {
+            command.args(["-m", mode(profile)?]);
+        }
+
+        Ok(command)
+    }
+
+    fn build_command(&self, jobs: Option<usize>) -> Result<Command> {
+        let mut command = Command::new("xmake");
+        command.args(["build", "-y", "-P", "."]);
+        if let Some(jobs) = jobs {
+            command.arg("-j").arg(jobs.to_string());
+        }
+
+        Ok(command)
+    }
+
+    fn clean_command(&self, target: Option<&str>) -> Result<Command> {
+        let mut command = Command::new("xmake");
+        command.args(["clean", "-y", "-P", "."]).args(target);
+
+        Ok(command)
+    }
+
+    fn test_command(&self) -> Result<Command> {
+        let mut command = Command::new("xmake");
+        command.args(["test", "-P", "."]);
+
+        Ok(command)
+    }
+
+    fn run_command(&self, target: &str, working_dir: &str, args: &[String]) -> Result<Command> {
+        let mut command = Command::new("xmake");
+        command
+            .args(["run", "-P", ".", "-w", working_dir, target])
+            .args(args);
+
+        Ok(command)
+    }
+}
+
+/// The xmake mode matching a built-in profile. Manifest profiles set CMake
+/// cache variables, which xmake has no equivalent for.
+fn mode(profile: &str) -> Result<&'static str> {
+    match profile.to_ascii_lowercase().as_str() {
+        "debug" => Ok("debug"),
+        "release" => Ok("release"),
+        "relwithdebinfo" => Ok("releasedbg"),
+        "minsizerel" => Ok("minsizerel"),
+        _ => anyhow::bail!(
+            "xmake projects support the built-in profiles only, valid profiles are 'debug', 'release', 'relwithdebinfo' and 'minsizerel'"
+        ),
+    }
+}
+
+/// The project file without its header comment
+pub fn project_file(project: &Project) -> Result<String> {
+    let mut lua = String::new();
+    writeln!(lua, "set_project({})", quote(&project.name))?;
+    writeln!(lua, "set_version({})", quote(&project.version))?;
+    writeln!(
+        lua,
+        "set_languages({})",
+        quote(language(&project.standard_flag))
+    )?;
+    writeln!(
+        lua,
+        "add_rules(\"mode.debug\", \"mode.release\", \"mode.releasedbg\", \"mode.minsizerel\")"
+    )?;
+    writeln!(
+        lua,
+        "add_rules(\"plugin.compile_commands.autoupdate\", {{outputdir = {}}})",
+        quote(&project.build_dir)
+    )?;
+    writeln!(lua, "set_warnings(\"allextra\", \"pedantic\", \"error\")")?;
+
+    for target in &project.targets {
+        lua.push('\n');
+        lua.push_str(&target_block(project, target)?);
+    }
+
+    Ok(lua)
+}
+
+/// Libraries export their include directories and definitions, like the
+/// public options of the CMake targets
+fn target_block(project: &Project, target: &Target) -> Result<String> {
+    let kind = match target.kind.as_str() {
+        "executable" => "binary",
+        "interface" => "headeronly",
+        kind => kind,
+    };
+    let public = if target.is_library() {
+        ", {public = true}"
+    } else {
+        ""
+    };
+    let (deps, system_libraries): (Vec<_>, Vec<_>) = target
+        .link
+        .iter()
+        .partition(|name| project.has_target(name));
+
+    let mut block = String::new();
+    writeln!(block, "target({})", quote(&target.name))?;
+    writeln!(block, "    set_kind({})", quote(kind))?;
+    if let Some(standard_flag) = &target.standard_flag {
+        writeln!(
+            block,
+            "    set_languages({})",
+            quote(language(standard_flag))
+        )?;
+    }
+    if !target.sources.is_empty() {
+        writeln!(block, "    add_files({})", list(&target.sources))?;
+    }
+    if !target.include_dirs.is_empty() {
+        writeln!(
+            block,
+            "    add_includedirs({}{})",
+            list(&target.include_dirs),
+            public
+        )?;
+    }
+    if !target.definitions.is_empty() {
+        writeln!(
+            block,
+            "    add_defines({}{})",
+            list(&target.definitions),
+            public
+        )?;
+    }
+    if !deps.is_empty() {
+        writeln!(block, "    add_deps({})", list(&deps))?;
+    }
+    if !system_libraries.is_empty() {
+        writeln!(block, "    add_syslinks({})", list(&system_libraries))?;
+    }
+    if !target.is_library() {
+        writeln!(block, "    set_targetdir({})", quote(&project.runtime_dir))?;
+    }
+    writeln!(block, "target_end()")?;
+
+    Ok(block)
+}
+
+/// `-std=c++23` becomes `c++23`, the spelling `set_languages` takes
+fn language(standard_flag: &str) -> &str {
+    standard_flag.trim_start_matches("-std=")
+}
+
+fn list<S: AsRef<str>>(values: &[S]) -> String {
+    values
+        .iter()
+        .map(|value| quote(value.as_ref()))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn quote(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
@@ -0,0 +1,166 @@
+//! End-to-end tests that scaffold, configure, build and run real projects.
+//! They need cmake and a C/C++ toolchain, so they only run when asked for:
+//!
+//! ```bash
+//! cargo test -- --ignored
+//! ```
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    process::{Command, Output},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+/// A scratch directory removed when the test finishes
+struct TempDir(PathBuf);
+
+impl TempDir {
+    fn new() -> TempDir {
+        static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+        let dir = std::env::temp_dir().join(format!(
+            "cxx-test-{}-{}",
+            std::process::id(),
+            COUNTER.fetch_add(1, Ordering::SeqCst)
+        ));
+        fs::create_dir_all(&dir).expect("Failed to create temporary directory");
+
+        TempDir(dir)
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+fn cxx(dir: &Path, args: &[&str]) -> Output {
+    let output = Command::new(env!("CARGO_BIN_EXE_cxx"))
+        .args(args)
+        .current_dir(dir)
+        .env("XDG_CONFIG_HOME", dir)
+        .output()
+        .expect("Failed to run cxx");
+
+    assert!(
+        output.status.success(),
+        "cxx {} failed:\n{}",
+        args.join(" "),
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    output
+}
+
+/// Creates, builds and runs a project, checking the generated files and the
+/// output of the executable
+fn check_template(file_ext: &str, layout: &str) {
+    let temp = TempDir::new();
+    let name = "demo";
+    let project = temp.0.join(name);
+
+    cxx(&temp.0, &["new", name, "-f", file_ext, "-l", layout]);
+
+    for file in [
+        "CMakeLists.txt".to_string(),
+        ".gitignore".to_string(),
+        format!("src/main.{}", file_ext),
+        "build/CMakeCache.txt".to_string(),
+    ] {
+        assert!(project.join(&file).exists(), "missing {}", file);
+    }
+    if layout == "canonical" {
+        let header = if file_ext == "c" {
+            "demo.h"
+        } else {
+            "demo.hpp"
+        };
+        assert!(project.join("include/demo").join(header).exists());
+    }
+
+    cxx(&project, &["build"]);
+    assert!(project.join("bin").join(name).exists());
+
+    let run = cxx(&project, &["run"]);
+    assert!(String::from_utf8_lossy(&run.stdout).contains("Hello, world!"));
+
+    cxx(&project, &["test"]);
+}
+
+#[test]
+#[ignore = "requires cmake and a C/C++ toolchain"]
+fn cpp_flat() {
+    check_template("cpp", "flat");
+}
+
+#[test]
+#[ignore = "requires cmake and a C/C++ toolchain"]
+fn cpp_canonical() {
+    check_template("cpp", "canonical");
+}
+
+#[test]
+#[ignore = "requires cmake and a C/C++ toolchain"]
+fn c_flat() {
+    check_template("c", "flat");
+}
+
+#[test]
+#[ignore = "requires cmake and a C/C++ toolchain"]
+fn c_canonical() {
+    check_template("c", "canonical");
+}
+
+#[test]
+#[ignore = "requires cmake and a C/C++ toolchain"]
+fn init_new_in_existing_directory() {
+    let temp = TempDir::new();
+    let project = temp.0.join("existing");
+    fs::create_dir_all(&project).unwrap();
+    fs::write(project.join(".gitignore"), "notes.txt\n").unwrap();
+
+    cxx(&project, &["init", "--new"]);
+
+    let gitignore = fs::read_to_string(project.join(".gitignore")).unwrap();
+    assert!(gitignore.starts_with("notes.txt\n"));
+    assert!(project.join("CMakeLists.txt").exists());
+
+    cxx(&project, &["build"]);
+    assert!(project.join("bin/existing").exists());
+}
+
+#[test]
+#[ignore = "requires cmake and a C/C++ toolchain"]
+fn added_class_and_example_build() {
+    let temp = TempDir::new();
+    let project = temp.0.join("app");
+
+    cxx(&temp.0, &["new", "app", "-l", "canonical"]);
+    cxx(&project, &["add", "class", "FooBar"]);
+    cxx(&project, &["add", "example", "hello"]);
+
+    assert!(project.join("include/app/foo_bar.hpp").exists());
+    assert!(project.join("src/foo_bar.cpp").exists());
+
+    cxx(&project, &["init"]);
+    cxx(&project, &["build", "--examples"]);
+    assert!(project.join("bin/examples/hello").exists());
+
+    let run = cxx(&project, &["run", "--example", "hello"]);
+    assert!(String::from_utf8_lossy(&run.stdout).contains("Hello from an example!"));
+}
+
+#[test]
+fn dry_run_prints_commands() {
+    let temp = TempDir::new();
+    fs::write(
+        temp.0.join("CMakeLists.txt"),
+        "cmake_minimum_required(VERSION 3.24)\nproject(app CXX)\n",
+    )
+    .unwrap();
+
+    let output = cxx(&temp.0, &["--dry-run", "format"]);
+    assert!(String::from_utf8_lossy(&output.stdout).contains("clang-format -i"));
+}